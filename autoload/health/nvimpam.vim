@@ -42,11 +42,65 @@ function! s:checkPamcards() abort
     endif
 endfunction
 
+function! s:checkAttached() abort
+    let l:reports = luaeval('require("nvimpam").health_reports()')
+    if empty(l:reports)
+        call health#report_info('No buffer attached')
+        return
+    endif
+    for l:entry in l:reports
+        let l:name = 'Buffer ' . l:entry.buf . ' (' . bufname(l:entry.buf) . ')'
+        let l:r = l:entry.report
+        if type(l:r) != v:t_dict
+            call health#report_error(l:name . ': ' . string(l:r),
+              \ ['Try `:NvimPamRestart`.'])
+            continue
+        endif
+        call health#report_ok(l:name . ' attached to nvimpam ' . l:r.version
+              \ . (l:r.attached ? '' : ' in preview mode'))
+        if l:r.api_level isnot v:null
+            call health#report_info('Neovim API level: ' . l:r.api_level)
+        endif
+        if type(get(l:entry, 'hello', v:null)) == v:t_dict
+            call health#report_info('Nvimpam API level: '
+                  \ . l:entry.hello.api_level . ', Lua API level: '
+                  \ . l:entry.lua_api_level)
+        else
+            call health#report_warn('No handshake with the binary',
+              \ ['The binary is older than the Lua runtime files, update it.'])
+        endif
+        call health#report_info('Card definitions: ' . l:r.builtin_cards
+              \ . ' builtin, ' . l:r.custom_cards . ' custom')
+        call health#report_info('Lines: ' . l:r.stats.lines . ', folds: '
+              \ . l:r.stats.folds . ', highlights: ' . l:r.stats.highlights
+              \ . (l:r.highlights ? '' : ' (disabled)'))
+        if l:r.degraded isnot v:null
+            call health#report_warn('Buffer degraded to mode ' . l:r.degraded,
+              \ ['The buffer is larger than g:nvimpam_max_size.'])
+        endif
+        if has_key(l:r.stats, 'last_parse_us')
+            call health#report_info('Last parse: '
+                  \ . l:r.stats.last_parse_us . ' us')
+        endif
+        if has_key(l:r.stats, 'last_update_us')
+            call health#report_info('Last update: '
+                  \ . l:r.stats.last_update_us . ' us')
+        endif
+        call health#report_info('Include index: ' . l:r.index
+              \ . (l:r.index ==# 'ready' ? ', ' . l:r.index_files . ' files' : ''))
+        for l:err in l:r.index_errors
+            call health#report_warn('Include index: ' . l:err)
+        endfor
+    endfor
+endfunction
+
 function! health#nvimpam#check() abort
     call health#report_start("Buffer updates")
     call s:checkBufferUpdatesFeature()
     call health#report_start("Nvimpam binary")
     call s:checkBinary()
+    call health#report_start("Attached buffers")
+    call s:checkAttached()
     call health#report_start("Menu availability")
     call s:checkImpromptu()
     call s:checkPamcards()
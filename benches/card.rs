@@ -51,6 +51,20 @@ fn bench_parse_str(c: &mut Criterion) {
   });
 }
 
+// Parse a keyword line of each builtin keyword, so the keywords at the end of
+// the list are looked up as often as the ones at the start
+fn bench_parse_samples(c: &mut Criterion) {
+  c.bench_function("card_parse_samples", |b| {
+    let samples: Vec<Vec<u8>> =
+      Keyword::BUILTIN.iter().filter_map(|k| k.sample()).collect();
+
+    b.iter(|| {
+      let _parsed: Vec<Option<Keyword>> =
+        samples.iter().map(|s| Keyword::parse(s)).collect();
+    });
+  });
+}
+
 const GES: [&str; 9] = [
   "        PART 1234",
   "        OGRP 'hausbau'",
@@ -72,9 +86,9 @@ fn bench_skip_ges(c: &mut Criterion) {
     b.iter(|| {
       let mut li: LinesIter<_> = lines.iter();
       let mut tmp = li.next().unwrap();
-      let mut _a = li.skip_ges(g, &tmp);
+      let mut _a = li.skip_ges(g, tmp);
       tmp = li.next().unwrap();
-      _a = li.skip_ges(g, &tmp);
+      _a = li.skip_ges(g, tmp);
     });
   });
 }
@@ -82,6 +96,9 @@ fn bench_skip_ges(c: &mut Criterion) {
 criterion_group!(
   name = card;
   config = Criterion::default();
-  targets = bench_parse2bufdata, bench_parse_str, bench_skip_ges
+  targets = bench_parse2bufdata,
+            bench_parse_str,
+            bench_parse_samples,
+            bench_skip_ges
 );
 criterion_main!(card);
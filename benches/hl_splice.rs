@@ -36,11 +36,10 @@ macro_rules! hl_bench {
           .filter(|((l, _, _), _)| {
             LineNr::from_usize($start) <= *l && *l < LineNr::from_usize($end)
           })
-          .map(|((l, s, e), h)| ((*l, *s, *e), *h))
           .collect();
 
         b.iter(move || {
-          let newhls: Highlights = Highlights(v.clone());
+          let newhls: Highlights = Highlights::from(v.clone());
           let range = bufdata.highlights.splice(
             newhls,
             $sstart.into(),
@@ -59,6 +58,52 @@ macro_rules! hl_bench {
   };
 }
 
+// Benchmark the full update path, i.e. what happens on a
+// `nvim_buf_lines_event`: Replace the lines `$first..$last` by the same lines
+// with `$added` more lines inserted, then undo that to keep the buffer the
+// same for the next iteration.
+macro_rules! update_bench {
+  ($fn: ident; lines: ($first: expr, $last: expr); added: $added: expr) => {
+    fn $fn(c: &mut Criterion) {
+      c.bench_function(stringify!($fn), move |b| {
+        let buf = Buffer::new(Value::from(0_usize));
+        let origlines = fs::read("files/example.pc").expect("1");
+        let mut bufdata = BufData::new(&buf);
+        bufdata.parse_slice(&origlines).expect("2");
+
+        let lines: Vec<String> = String::from_utf8_lossy(&origlines)
+          .lines()
+          .skip($first)
+          .take($last - $first)
+          .map(String::from)
+          .collect();
+        let mut newlines = lines.clone();
+        newlines.extend(std::iter::repeat(lines[0].clone()).take($added));
+
+        b.iter(|| {
+          let first = LineNr::from_usize($first);
+          let _ = black_box(
+            bufdata
+              .update(first, LineNr::from_usize($last), newlines.clone())
+              .expect("3"),
+          );
+          if $added > 0 {
+            let _ = black_box(
+              bufdata
+                .update(
+                  first,
+                  LineNr::from_usize($last + $added),
+                  lines.clone(),
+                )
+                .expect("4"),
+            );
+          }
+        })
+      });
+    }
+  };
+}
+
 fn bench_bufdata_create(c: &mut Criterion) {
   c.bench_function("bench_bufdata_create", move |b| {
     let origlines = fs::read("files/example.pc").expect("1");
@@ -101,6 +146,8 @@ hl_bench!(
   spliceto: (20500, 20501, 0)
   );
 
+// Moves all highlights after line 28. This takes about 72µs, while the same
+// on a single Vec of highlights took about 250µs.
 hl_bench!(
   bench_bufdata_add_line_start;
   lines: (28, 30);
@@ -137,6 +184,36 @@ hl_bench!(
   spliceto: (0, 0, 7)
   );
 
+update_bench!(
+  bench_bufdata_update_change_start;
+  lines: (28, 29);
+  added: 0
+  );
+
+update_bench!(
+  bench_bufdata_update_change_end;
+  lines: (20500, 20501);
+  added: 0
+  );
+
+update_bench!(
+  bench_bufdata_update_add_start;
+  lines: (28, 29);
+  added: 1
+  );
+
+update_bench!(
+  bench_bufdata_update_add_end;
+  lines: (20500, 20501);
+  added: 1
+  );
+
+update_bench!(
+  bench_bufdata_update_paste_middle;
+  lines: (10000, 10007);
+  added: 7
+  );
+
 criterion_group!(
   name = hl_splice;
   config = Criterion::default().sample_size(10).without_plots();
@@ -150,5 +227,10 @@ criterion_group!(
             bench_bufdata_paste_after,
             bench_bufdata_paste_before,
             bench_bufdata_readonly,
+            bench_bufdata_update_change_start,
+            bench_bufdata_update_change_end,
+            bench_bufdata_update_add_start,
+            bench_bufdata_update_add_end,
+            bench_bufdata_update_paste_middle,
 );
 criterion_main!(hl_splice);
@@ -0,0 +1,123 @@
+extern crate nvimpam_lib;
+
+#[macro_use]
+extern crate criterion;
+extern crate neovim_lib;
+
+use std::fs;
+
+use criterion::{black_box, Criterion};
+
+use neovim_lib::{neovim_api::Buffer, Value};
+
+use nvimpam_lib::{bufdata::BufData, linenr::LineNr};
+
+const NEWLINE: &str =
+  "NODE  /        1              0.             0.5              0.";
+
+/// The lines of the file `origlines`
+fn split(origlines: &[u8]) -> Vec<String> {
+  String::from_utf8_lossy(origlines)
+    .lines()
+    .map(String::from)
+    .collect()
+}
+
+// Open a new line in the middle of the deck, type a node into it, and delete
+// it again. Each keystroke is an update of the line.
+fn bench_update_type_line(c: &mut Criterion) {
+  c.bench_function("update_type_line", move |b| {
+    let origlines = fs::read("files/example.pc").expect("1");
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_slice(&origlines).expect("2");
+    let lines = split(&origlines);
+    let middle = LineNr::from_usize(lines.len() / 2);
+    let next = middle + 1;
+
+    b.iter(|| {
+      black_box(
+        bufdata
+          .update(middle, middle, vec![String::new()])
+          .expect("4"),
+      );
+      for end in (8..=NEWLINE.len()).step_by(8) {
+        let typed = vec![NEWLINE[..end].to_string()];
+        black_box(bufdata.update(middle, next, typed).expect("4"));
+      }
+      black_box(bufdata.update(middle, next, vec![]).expect("4"));
+    })
+  });
+}
+
+// Insert a single line in the middle of the deck, and delete it again
+fn bench_update_insert_line(c: &mut Criterion) {
+  c.bench_function("update_insert_line", move |b| {
+    let origlines = fs::read("files/example.pc").expect("1");
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_slice(&origlines).expect("2");
+    let lines = split(&origlines);
+    let middle = LineNr::from_usize(lines.len() / 2);
+
+    b.iter(|| {
+      let newline = vec![NEWLINE.to_string()];
+      black_box(bufdata.update(middle, middle, newline).expect("4"));
+      black_box(bufdata.update(middle, middle + 1, vec![]).expect("4"));
+    })
+  });
+}
+
+// Paste the first 10000 lines of the deck into its middle, and delete them
+// again
+fn bench_update_paste_10k(c: &mut Criterion) {
+  c.bench_function("update_paste_10k", move |b| {
+    let origlines = fs::read("files/example.pc").expect("1");
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_slice(&origlines).expect("2");
+    let lines = split(&origlines);
+    let middle = LineNr::from_usize(lines.len() / 2);
+    let pasted: Vec<String> = lines.iter().take(10_000).cloned().collect();
+    let end = middle + pasted.len() as isize;
+
+    b.iter(|| {
+      black_box(bufdata.update(middle, middle, pasted.clone()).expect("4"));
+      black_box(bufdata.update(middle, end, vec![]).expect("4"));
+    })
+  });
+}
+
+// Delete the first card of more than one line, a MATER near the start of the
+// deck, and undo that
+fn bench_update_delete_card(c: &mut Criterion) {
+  c.bench_function("update_delete_card", move |b| {
+    let origlines = fs::read("files/example.pc").expect("1");
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_slice(&origlines).expect("2");
+    let lines = split(&origlines);
+    let [first, last] = bufdata
+      .cards()
+      .map(|c| c.range)
+      .find(|r| r[1] > r[0])
+      .expect("3");
+    let end = last + 1;
+    let card = lines[usize::from(first)..usize::from(end)].to_vec();
+
+    b.iter(|| {
+      black_box(bufdata.update(first, end, vec![]).expect("4"));
+      black_box(bufdata.update(first, first, card.clone()).expect("4"));
+    })
+  });
+}
+
+criterion_group!(
+  name = update;
+  config = Criterion::default().sample_size(10).without_plots();
+  targets = bench_update_type_line,
+            bench_update_insert_line,
+            bench_update_paste_10k,
+            bench_update_delete_card,
+);
+criterion_main!(update);
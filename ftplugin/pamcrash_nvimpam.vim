@@ -8,19 +8,128 @@ command -buffer NvimPamAttach call luaeval(
       \ 'require("nvimpam").attach(_A.f)',
       \ { 'f': expand('%:p') }
       \ )
+command -buffer NvimPamPreview call luaeval(
+      \ 'require("nvimpam").preview(_A.f)',
+      \ { 'f': expand('%:p') }
+      \ )
+command -buffer NvimPamRestart call luaeval('require("nvimpam").restart()')
 command -buffer NvimPamUpdateFolds call luaeval('require("nvimpam").refresh_folds()')
 command -buffer NvimPamHighlightScreen call luaeval(
       \ 'require("nvimpam").highlight_region(_A.b, _A.f, _A.l)',
       \ { 'b': bufnr('%'), 'f': line('w0')-1, 'l': line('w$')-1 }
       \ )
+command -buffer -nargs=? NvimPamConvertCard call luaeval(
+      \ 'require("nvimpam").convert_card(_A)', <q-args>
+      \ )
+command -buffer -range=% -nargs=+ NvimPamRenumber call luaeval(
+      \ 'require("nvimpam").renumber(_A[1], _A[2], _A[3], _A[4], _A[5])',
+      \ [<f-args>, 1, 1][0:2] + [<line1> - 1, <line2>]
+      \ )
+command -buffer -nargs=+ NvimPamRenameId call luaeval(
+      \ 'require("nvimpam").rename_id(_A[1], _A[2], _A[3])', [<f-args>]
+      \ )
+command -buffer -nargs=+ NvimPamOffsetInclude call luaeval(
+      \ 'require("nvimpam").offset_include(_A[1], _A[2:])', [<f-args>]
+      \ )
+command -buffer -range=% NvimPamAlign call luaeval(
+      \ 'require("nvimpam").align_cells(_A[1], _A[2])',
+      \ [<line1> - 1, <line2>]
+      \ )
+command -buffer -nargs=? NvimPamRenameFold call luaeval(
+      \ 'require("nvimpam").rename_fold(_A)', <q-args>
+      \ )
+command -buffer -nargs=? -complete=file NvimPamExportFolds call luaeval(
+      \ 'require("nvimpam").export_folds(_A)', <q-args>
+      \ )
+command -buffer -nargs=? -complete=file NvimPamImportFolds call luaeval(
+      \ 'require("nvimpam").import_folds(_A)', <q-args>
+      \ )
+command -buffer -nargs=? -complete=file NvimPamSaveState call luaeval(
+      \ 'require("nvimpam").save_state(_A)', <q-args>
+      \ )
+command -buffer -nargs=? -complete=file NvimPamLoadState call luaeval(
+      \ 'require("nvimpam").load_state(_A)', <q-args>
+      \ )
+command -buffer NvimPamCellHint call luaeval('require("nvimpam").warn_cell_shift()')
+command -buffer NvimPamCardInfo call luaeval('require("nvimpam").show_card_info()')
+command -buffer NvimPamGesContents call luaeval('require("nvimpam").show_ges_contents()')
+command -buffer -nargs=1 NvimPamSelectCell call luaeval(
+      \ 'require("nvimpam").select_cell(_A)', <q-args>
+      \ )
+command -buffer NvimPamCellNumber call luaeval('require("nvimpam").show_cell_number()')
+command -buffer NvimPamMetadata call luaeval('require("nvimpam").show_metadata()')
+command -buffer NvimPamStats call luaeval('require("nvimpam").show_stats()')
+command -buffer NvimPamKeywords call luaeval('require("nvimpam").show_keyword_summary()')
+command -buffer -nargs=? NvimPamTimings call luaeval(
+      \ 'require("nvimpam").show_event_timings(_A)', <q-args>
+      \ )
+command -buffer NvimPamLintSections call luaeval('require("nvimpam").lint_sections()')
+command -buffer -bang NvimPamDuplicates call luaeval(
+      \ 'require("nvimpam").show_duplicates(_A)', <q-bang> != '!'
+      \ )
+command -buffer -range -nargs=1 -complete=file NvimPamExtractInclude call luaeval(
+      \ 'require("nvimpam").extract_include(_A[1], _A[2], _A[3])',
+      \ [<q-args>, <line1> - 1, <line2>]
+      \ )
+command -buffer -range NvimPamToggleComment call luaeval(
+      \ 'require("nvimpam").toggle_comment(_A[1], _A[2])',
+      \ [<line1> - 1, <line2>]
+      \ )
+command -buffer NvimPamIndexIncludes call luaeval('require("nvimpam").index_includes()')
+command -buffer -nargs=+ NvimPamDefinition call luaeval(
+      \ 'require("nvimpam").goto_definition(_A[1], _A[2])', [<f-args>]
+      \ )
+command -buffer -nargs=1 -complete=file NvimPamDiffDeck call luaeval(
+      \ 'require("nvimpam").show_diff_deck(_A)', <q-args>
+      \ )
+command -buffer NvimPamQuickfix call luaeval('require("nvimpam").set_quickfix()')
+command -buffer NvimPamCancel call luaeval('require("nvimpam").cancel_requests()')
 command -buffer NvimPamMenu call luaeval('require("nvimpam.cardmenu").cardmenu()')
 
+if !get(g:, 'nvimpam_no_textobjects', 0)
+  xnoremap <buffer> <silent> aC :<C-u>lua require("nvimpam").select_card(false)<CR>
+  xnoremap <buffer> <silent> iC :<C-u>lua require("nvimpam").select_card(true)<CR>
+  onoremap <buffer> <silent> aC :<C-u>lua require("nvimpam").select_card(false)<CR>
+  onoremap <buffer> <silent> iC :<C-u>lua require("nvimpam").select_card(true)<CR>
+endif
+
 augroup nvimpam_leave
   " clear all previous autocommands
   autocmd!
   autocmd VimLeavePre * call luaeval('require("nvimpam").detach_all()')
 augroup end
 
+augroup nvimpam_session
+  autocmd!
+  autocmd SessionLoadPost * call luaeval('require("nvimpam").load_states()')
+augroup end
+
+if get(g:, 'nvimpam_highlight_viewport', 0)
+  augroup nvimpam_viewport
+    autocmd! * <buffer>
+    autocmd CursorMoved,CursorMovedI,BufWinEnter <buffer>
+          \ call luaeval('require("nvimpam").highlight_viewport()')
+    if exists('##WinScrolled')
+      autocmd WinScrolled <buffer>
+            \ call luaeval('require("nvimpam").highlight_viewport()')
+    endif
+  augroup end
+endif
+
+if exists('##CmdlineEnter')
+  augroup nvimpam_inccommand
+    autocmd! * <buffer>
+    autocmd CmdlineEnter <buffer> call luaeval('require("nvimpam").inccommand(true)')
+    autocmd CmdlineLeave <buffer> call luaeval('require("nvimpam").inccommand(false)')
+  augroup end
+endif
+
+augroup nvimpam_align
+  autocmd! * <buffer>
+  autocmd BufWritePre <buffer> call luaeval('require("nvimpam").align_on_save()')
+  autocmd BufWritePost <buffer> call luaeval('require("nvimpam").written()')
+augroup end
+
 function! Nvimpam_foldtext()
   return luaeval('require("nvimpam").foldtext()')
 endfunction
@@ -28,18 +137,36 @@ endfunction
 let s:save_foldtext = &foldtext
 setlocal foldtext=Nvimpam_foldtext()
 
+" The fold level of the line a:lnum for 'foldexpr', see g:nvimpam_foldexpr
+function! Nvimpam_foldlevel(lnum)
+  let l:jobid = get(b:, 'nvimpam_jobid', 0)
+  return l:jobid > 0 ? rpcrequest(l:jobid, 'FoldLevel', a:lnum - 1) : 0
+endfunction
+
+let s:save_foldmethod = &foldmethod
+let s:save_foldexpr = &foldexpr
+if get(g:, 'nvimpam_foldexpr', 0)
+  setlocal foldmethod=expr foldexpr=Nvimpam_foldlevel(v:lnum)
+endif
+
 if &background == "dark"
   highlight default PamCellEven ctermbg=229 guibg=#ffffcf
   highlight default PamCellOdd ctermbg=254 guibg=#e4e4e4
   highlight default PamErrorCellEven ctermfg=15 ctermbg=124 guifg=#ffffff guibg=#af0000
   highlight default PamErrorCellOdd ctermfg=15 ctermbg=9 guifg=#ffffff guibg=#ff0000
   highlight default PamKeyword cterm=bold ctermfg=94 gui=bold guifg=#875f00
+  highlight default PamTitle cterm=italic ctermfg=24 gui=italic guifg=#005f87
+  highlight default link PamDuplicate WarningMsg
+  highlight default link PamIgnored WarningMsg
 else
   highlight default PamCellEven ctermbg=229 guibg=#ffffcf
   highlight default PamCellOdd ctermbg=254 guibg=#e4e4e4
   highlight default PamErrorCellEven ctermfg=15 ctermbg=124 guifg=#ffffff guibg=#af0000
   highlight default PamErrorCellOdd ctermfg=15 ctermbg=9 guifg=#ffffff guibg=#ff0000
   highlight default PamKeyword cterm=bold ctermfg=94 gui=bold guifg=#875f00
+  highlight default PamTitle cterm=italic ctermfg=24 gui=italic guifg=#005f87
+  highlight default link PamDuplicate WarningMsg
+  highlight default link PamIgnored WarningMsg
 endif
 
 if !exists('b:undo_ftplugin')
@@ -47,7 +174,46 @@ if !exists('b:undo_ftplugin')
 endif
 
 let b:undo_ftplugin .= '|setlocal foldtext='.s:save_foldtext
+      \ . '|setlocal foldmethod='.s:save_foldmethod
+      \ . '|setlocal foldexpr='.escape(s:save_foldexpr, ' \|')
+      \ . '|silent! autocmd! nvimpam_viewport * <buffer>'
+      \ . '|silent! autocmd! nvimpam_align * <buffer>'
+      \ . '|silent! autocmd! nvimpam_inccommand * <buffer>'
       \ . '|delcommand NvimPamAttach'
+      \ . '|delcommand NvimPamPreview'
+      \ . '|delcommand NvimPamRestart'
       \ . '|delcommand NvimPamUpdateFolds'
       \ . '|delcommand NvimPamHighlightScreen'
+      \ . '|delcommand NvimPamConvertCard'
+      \ . '|delcommand NvimPamRenumber'
+      \ . '|delcommand NvimPamRenameId'
+      \ . '|delcommand NvimPamOffsetInclude'
+      \ . '|delcommand NvimPamAlign'
+      \ . '|delcommand NvimPamRenameFold'
+      \ . '|delcommand NvimPamExportFolds'
+      \ . '|delcommand NvimPamImportFolds'
+      \ . '|delcommand NvimPamSaveState'
+      \ . '|delcommand NvimPamLoadState'
+      \ . '|delcommand NvimPamCellHint'
+      \ . '|delcommand NvimPamCardInfo'
+      \ . '|delcommand NvimPamGesContents'
+      \ . '|delcommand NvimPamSelectCell'
+      \ . '|delcommand NvimPamCellNumber'
+      \ . '|delcommand NvimPamMetadata'
+      \ . '|delcommand NvimPamStats'
+      \ . '|delcommand NvimPamKeywords'
+      \ . '|delcommand NvimPamTimings'
+      \ . '|delcommand NvimPamLintSections'
+      \ . '|delcommand NvimPamDuplicates'
+      \ . '|delcommand NvimPamExtractInclude'
+      \ . '|delcommand NvimPamToggleComment'
+      \ . '|delcommand NvimPamIndexIncludes'
+      \ . '|delcommand NvimPamDefinition'
+      \ . '|delcommand NvimPamDiffDeck'
+      \ . '|delcommand NvimPamQuickfix'
+      \ . '|delcommand NvimPamCancel'
       \ . '|delcommand NvimPamMenu'
+      \ . '|silent! xunmap <buffer> aC'
+      \ . '|silent! xunmap <buffer> iC'
+      \ . '|silent! ounmap <buffer> aC'
+      \ . '|silent! ounmap <buffer> iC'
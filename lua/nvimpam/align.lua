@@ -0,0 +1,78 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local set_lines = vim.api.nvim_buf_set_lines
+local get_var = vim.api.nvim_get_var
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local show = require('nvimpam.diagnostics').show
+
+-- Move the numbers crossing the borders of their cells into the cell holding
+-- most of them, for the cards in the lines `firstline` to `lastline`
+-- (zero-indexed, end-exclusive). If `modified` is true, only the cards
+-- changed since the buffer was last written are re-aligned. Lines that can't
+-- be re-aligned are reported as messages. If `quiet` is true, errors are not
+-- reported either.
+local function align_cells(firstline, lastline, modified, buf, quiet)
+  buf = buf or curbuf()
+  firstline = firstline or 0
+  lastline = lastline or call("line", { "$" })
+
+  if not jobids[buf] then
+    if not quiet then
+      nvimpam_err("align_cells failed: No jobid entry for buffer "
+                  ..tostring(buf).."!")
+    end
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "AlignCells", firstline,
+                                   lastline, modified and true or false })
+
+  if type(res) == "string" then
+    if not quiet then
+      nvimpam_err(res)
+    end
+    return false
+  end
+
+  for _, e in ipairs(res.edits) do
+    set_lines(buf, e[1], e[1] + 1, true, { e[2] })
+  end
+
+  show(res.diagnostics)
+
+  return true
+end
+
+-- Re-align the cells before writing the buffer, according to
+-- g:nvimpam_align_on_save: "all" re-aligns the whole deck, "modified" only
+-- the cards changed since the buffer was last written. Never fails, so the
+-- buffer is written in any case.
+local function align_on_save(buf)
+  local has_align, align = pcall(get_var, "nvimpam_align_on_save")
+
+  if not has_align or (align ~= "all" and align ~= "modified") then
+    return true
+  end
+
+  return align_cells(nil, nil, align == "modified", buf or curbuf(), true)
+end
+
+-- Tell the nvimpam instance attached to `buf` that the buffer was written,
+-- so it forgets about the changes made so far
+local function written(buf)
+  buf = buf or curbuf()
+
+  if jobids[buf] then
+    call("rpcnotify", { jobids[buf], "Written" })
+  end
+
+  return true
+end
+
+return {
+  align_cells = align_cells,
+  align_on_save = align_on_save,
+  written = written,
+}
@@ -0,0 +1,297 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local out_write = vim.api.nvim_out_write
+local command = vim.api.nvim_command
+local get_var = vim.api.nvim_get_var
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Ask nvimpam about the card containing line `line` (zero-indexed), which
+-- defaults to the cursor line. Returns a table with the `keyword` of the
+-- card, its `first` and `last` line, the cells of its `lines` and the
+-- `diagnostics` for the card. Each entry of `lines` has the `line` number and
+-- a list of `cells` with the keys `kind`, `start`, `end`, `value` and, if a
+-- `$#` comment names it, `name`. By the nature of msgpack, blank values are
+-- `vim.NIL`.
+local function card_info(line, buf)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+
+  if not jobids[buf] then
+    nvimpam_err("card_info failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CardInfo", line })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Echo the cell values and diagnostics of the card under the cursor
+local function show_card_info()
+  local info = card_info()
+
+  if not info then
+    return false
+  end
+
+  out_write(string.format("%s in lines %d-%d\n", info.keyword,
+                          info.first + 1, info.last + 1))
+
+  for _, l in ipairs(info.lines) do
+    local cells = {}
+    for _, c in ipairs(l.cells) do
+      if c.kind ~= "keyword" and c.kind ~= "blank" then
+        local value = c.value
+        if value == nil or value == vim.NIL then
+          value = ""
+        end
+        table.insert(cells, (c.name or c.kind).."="..tostring(value))
+      end
+    end
+    out_write(string.format("%5d: %s\n", l.line + 1, table.concat(cells, " ")))
+  end
+
+  for _, d in ipairs(info.diagnostics) do
+    out_write(string.format("%5d: %s: %s\n", d.line + 1, d.severity,
+                            d.message))
+  end
+
+  return true
+end
+
+-- Ask nvimpam about the GES containing line `line` (zero-indexed), which
+-- defaults to the cursor line. Returns a table with its `first` and `last`
+-- line, if it's `complete`, i.e. the include files are indexed if there are
+-- any, and its `selectors`. Each of those has the `line`, the `selector`, if
+-- it `deselects` the entities, and its `items` as written. Selectors of IDs
+-- also have the `entity`, the number of defined IDs `matched`, and the
+-- single IDs that are `missing`.
+local function ges_contents(line, buf)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+
+  if not jobids[buf] then
+    nvimpam_err("ges_contents failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "GesContents", line })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Echo the selectors of the GES under the cursor, and how many entities each
+-- of them matches
+local function show_ges_contents()
+  local ges = ges_contents()
+
+  if not ges then
+    return false
+  end
+
+  out_write(string.format("GES in lines %d-%d\n", ges.first + 1,
+                          ges.last + 1))
+
+  for _, s in ipairs(ges.selectors) do
+    local matched = ""
+    if s.entity then
+      matched = string.format(": %d %s(s) defined", s.matched, s.entity)
+      if #s.missing > 0 then
+        matched = matched..", missing "..table.concat(s.missing, " ")
+      end
+    end
+    out_write(string.format("%5d: %s %s%s\n", s.line + 1, s.selector,
+                            table.concat(s.items, " "), matched))
+  end
+
+  if not ges.complete then
+    out_write("The include files are not indexed, see :NvimPamIndexIncludes\n")
+  end
+
+  return true
+end
+
+-- Ask nvimpam for the columns of the cell `field` of line `line`
+-- (zero-indexed, defaults to the cursor line). The `field` is the name of the
+-- cell in a `$#` comment line, or its index starting at 1. Returns a table
+-- with the `first` and `last` line of the run of identical cards around the
+-- line, the `start` and (exclusive) `end` column of the cell, its `kind` and
+-- its `name` if known.
+local function cell_range(field, line, buf)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+
+  if not field or field == "" then
+    nvimpam_err("cell_range failed: No field given!")
+    return nil
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("cell_range failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CellRange", line, field })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Select the cell `field` of all the cards around the cursor line in visual
+-- block mode, see cell_range
+local function select_cell(field)
+  local range = cell_range(field)
+
+  if not range then
+    return false
+  end
+
+  command(string.format('exe "normal! %dG%d|\\<C-v>%dG%d|"', range.first + 1,
+                        range.start + 1, range.last + 1, range["end"]))
+  return true
+end
+
+-- Ask nvimpam for the lines of "a card" around line `line` (zero-indexed,
+-- defaults to the cursor line), i.e. the card along with the comments above
+-- its keyword line. If `inner` is true, the keyword line and the comments
+-- following it are left out. Returns a table of the `first` and `last` line.
+local function card_object(inner, line, buf)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+
+  if not jobids[buf] then
+    nvimpam_err("card_object failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CardObject", line,
+                                   inner and true or false })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return { first = res[1], last = res[2] }
+end
+
+-- Select the card around the cursor line in linewise visual mode, see
+-- card_object. Used for the text objects `aC` and `iC`.
+local function select_card(inner)
+  local range = card_object(inner)
+
+  if not range then
+    return false
+  end
+
+  command(string.format("normal! %dGV%dG", range.first + 1, range.last + 1))
+  return true
+end
+
+-- Ask nvimpam for the number in the cell at column `col` of line `line`
+-- (both zero-indexed, default to the cursor position). Packed numbers like
+-- `1.5-3` are read like the solver does. Returns a table with the `value`,
+-- its `normalized` and `engineering` notations, and the `name` of the cell if
+-- a `$#` comment names it. If g:nvimpam_units has a unit for the cell, the
+-- value in that unit is given as `scaled`, along with the name of the `unit`.
+local function cell_number(line, col, buf)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+  col = col or call("col", { "." }) - 1
+
+  if not jobids[buf] then
+    nvimpam_err("cell_number failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local has_units, units = pcall(get_var, "nvimpam_units")
+  if not has_units then
+    units = {}
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CellNumber", line, col,
+                                   units })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Show the number in the cell under the cursor in a floating window, or echo
+-- it if those are not available, see cell_number
+local function show_cell_number()
+  local number = cell_number()
+
+  if not number then
+    return false
+  end
+
+  local lines = {
+    string.format("%s = %s", number.name or "value", number.normalized),
+    string.format("engineering: %s", number.engineering),
+  }
+  if number.scaled then
+    table.insert(lines, string.format("in %s: %s", number.unit, number.scaled))
+  end
+
+  if not vim.api.nvim_open_win then
+    out_write(table.concat(lines, "\n").."\n")
+    return true
+  end
+
+  local width = 0
+  for _, l in ipairs(lines) do
+    width = math.max(width, #l)
+  end
+
+  local popup = vim.api.nvim_create_buf(false, true)
+  vim.api.nvim_buf_set_lines(popup, 0, -1, true, lines)
+  vim.api.nvim_buf_set_keymap(popup, "n", "q", "<Cmd>close<CR>",
+                              { noremap = true, silent = true })
+  vim.api.nvim_open_win(popup, true, {
+    relative = "cursor",
+    width = width,
+    height = #lines,
+    row = 1,
+    col = 0,
+  })
+
+  return true
+end
+
+return {
+  card_info = card_info,
+  show_card_info = show_card_info,
+  ges_contents = ges_contents,
+  show_ges_contents = show_ges_contents,
+  cell_range = cell_range,
+  select_cell = select_cell,
+  card_object = card_object,
+  select_card = select_card,
+  cell_number = cell_number,
+  show_cell_number = show_cell_number,
+}
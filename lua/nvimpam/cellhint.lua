@@ -0,0 +1,52 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Ask nvimpam if inserting a character at column `col` of line `line` (both
+-- zero-indexed) would move cells out of their columns. Returns a table with
+-- the boolean `shifts`, and if known the `cell` containing the column as a
+-- `{start, end}` pair as well as a blank column `pad` that can be removed to
+-- keep the following cells aligned. Defaults to the cursor position.
+local function cell_hint(line, col, buf)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+  col = col or call("col", { "." }) - 1
+
+  if not jobids[buf] then
+    nvimpam_err("cell_hint failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CellHint", line, col })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Echo a warning if inserting at the cursor position would shift cells
+local function warn_cell_shift()
+  local hint = cell_hint()
+
+  if hint and hint.shifts then
+    local msg = "Inserting here shifts the following cells"
+    if hint.pad then
+      msg = msg.." (pad by removing column "..tostring(hint.pad + 1)..")"
+    end
+    out_write(msg.."\n")
+  end
+
+  return hint ~= nil
+end
+
+return {
+  cell_hint = cell_hint,
+  warn_cell_shift = warn_cell_shift,
+}
@@ -0,0 +1,34 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local set_lines = vim.api.nvim_buf_set_lines
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Comment out the cards in the lines `firstline` to `lastline` (zero-indexed,
+-- end-exclusive), or uncomment them if all of those lines are comments. The
+-- range is extended to whole cards.
+local function toggle_comment(firstline, lastline, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("toggle_comment failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "ToggleComment", firstline,
+                                   lastline })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  set_lines(buf, res.first, res.last, true, res.lines)
+  return true
+end
+
+return {
+  toggle_comment = toggle_comment,
+}
@@ -0,0 +1,75 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local set_lines = vim.api.nvim_buf_set_lines
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local show = require('nvimpam.diagnostics').show
+
+-- Let the user pick one of the card types in `targets`, returns nil if
+-- none was picked
+local function pick_target(targets)
+  local items = { "Convert the card to:" }
+  for i, t in ipairs(targets) do
+    items[#items + 1] = tostring(i)..". "..t
+  end
+
+  local choice = call("inputlist", { items })
+  return targets[choice]
+end
+
+-- Convert the card containing line `line` (zero-indexed) to the card type
+-- `target`, e.g. "membr". If `target` is nil or empty, the card types the
+-- card can be converted to are offered to pick from. Cells that could not
+-- be converted or need to be filled in are reported as messages.
+local function convert_card(target, buf, line)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+
+  if not jobids[buf] then
+    nvimpam_err("convert_card failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  if not target or target == "" then
+    local res = call("rpcrequest", { jobids[buf], "ConvertCard", line, "" })
+
+    if type(res) == "string" then
+      nvimpam_err(res)
+      return false
+    elseif #res.targets == 0 then
+      nvimpam_err("The card can not be converted to another card type!")
+      return false
+    end
+
+    target = pick_target(res.targets)
+    if not target then
+      return false
+    end
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "ConvertCard", line, target })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  for _, e in ipairs(res.edits) do
+    set_lines(buf, e[1], e[1] + 1, true, { e[2] })
+  end
+
+  if res.added then
+    local after = res.added[1] + 1
+    set_lines(buf, after, after, true, res.added[2])
+  end
+
+  show(res.diagnostics)
+
+  return true
+end
+
+return {
+  convert_card = convert_card,
+}
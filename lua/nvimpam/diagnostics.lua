@@ -0,0 +1,26 @@
+local out_write = vim.api.nvim_out_write
+
+-- Format a diagnostic received from nvimpam. Line and column numbers are
+-- zero-indexed on the rust side.
+local function format(d)
+  local msg = tostring(d.line + 1)..":"..tostring(d.start + 1).." "
+              ..d.severity..": "..d.message
+
+  if d.target then
+    msg = msg.." (see line "..tostring(d.target + 1)..")"
+  end
+
+  return msg
+end
+
+-- Echo a list of diagnostics
+local function show(diagnostics)
+  for _, d in ipairs(diagnostics) do
+    out_write(format(d).."\n")
+  end
+end
+
+return {
+  format = format,
+  show = show,
+}
@@ -0,0 +1,85 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local background_request = require('nvimpam.request').background_request
+
+-- Compare the cards of the buffer `buf` with the ones of the file `path`.
+-- Returns a list of the cards that differ, each with the `change` ("added",
+-- "removed" or "changed"), the `keyword`, the `id` if the card has one, and
+-- its keyword `line` in the buffer and/or `refline` in the file
+-- (zero-indexed).
+local function diff_deck(path, buf)
+  buf = buf or curbuf()
+
+  if not path or path == "" then
+    nvimpam_err("diff_deck failed: No file name given!")
+    return nil
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("diff_deck failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  path = call("fnamemodify", { path, ":p" })
+  local res = call("rpcrequest", { jobids[buf], "DiffDeck", path })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Put the cards of the buffer `buf` that differ from the ones of the file
+-- `path` into the quickfix list. Removed cards point into the file, all
+-- others into the buffer.
+local function set_diff_qflist(path, buf, diffs)
+  local items = {}
+  for _, d in ipairs(diffs) do
+    local text = d.change.." "..d.keyword
+    if d.id then
+      text = text.." "..tostring(d.id)
+    end
+
+    if d.change == "removed" then
+      table.insert(items, { filename = path, lnum = d.refline + 1,
+                            text = text })
+    else
+      table.insert(items, { bufnr = buf, lnum = d.line + 1, text = text })
+    end
+  end
+
+  call("setqflist", { {}, " ", { title = "NvimPamDiffDeck "..path,
+                                 items = items } })
+  out_write(string.format("%d cards differ from %s\n", #diffs, path))
+end
+
+-- Compare the current buffer with the file `path` in the background, and put
+-- the cards that differ into the quickfix list once that's done. Can be
+-- cancelled by `:NvimPamCancel`.
+local function show_diff_deck(path)
+  local buf = curbuf()
+
+  if not path or path == "" then
+    nvimpam_err("show_diff_deck failed: No file name given!")
+    return false
+  end
+
+  path = call("fnamemodify", { path, ":p" })
+  local id = background_request("DiffDeck", { path }, function(diffs)
+    set_diff_qflist(path, buf, diffs)
+  end, buf)
+
+  return id ~= nil
+end
+
+return {
+  diff_deck = diff_deck,
+  show_diff_deck = show_diff_deck,
+}
@@ -0,0 +1,68 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local create_namespace = vim.api.nvim_create_namespace
+local clear_namespace = vim.api.nvim_buf_clear_namespace
+local add_highlight = vim.api.nvim_buf_add_highlight
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local show = require('nvimpam.diagnostics').show
+
+-- The duplicates get their own namespace, so they're independent of the
+-- highlights sent by nvimpam
+local namespace = create_namespace("nvimpam_duplicates")
+
+-- Highlight the later definitions of duplicate IDs, replacing the previous
+-- highlights. Called by nvimpam after each update while the check is enabled.
+local function update_duplicates(buf, duplicates)
+  clear_namespace(buf, namespace, 0, -1)
+
+  for _, d in ipairs(duplicates) do
+    add_highlight(buf, namespace, "PamDuplicate", d.line, d.start, d["end"])
+  end
+end
+
+-- Enable the check for IDs that are defined more than once, e.g. two nodes
+-- with the same ID, and highlight the duplicates found. Pass `false` as
+-- `enable` to disable the check and remove the highlights. Returns the list of
+-- duplicates, or nil on error.
+local function check_duplicates(enable, buf)
+  buf = buf or curbuf()
+  if enable == nil then
+    enable = true
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("check_duplicates failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CheckDuplicates", enable })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  update_duplicates(buf, res)
+  return res
+end
+
+-- Like `check_duplicates` for the current buffer, but echo the duplicates
+local function show_duplicates(enable)
+  local duplicates = check_duplicates(enable)
+
+  if not duplicates then
+    return false
+  end
+
+  show(duplicates)
+  return #duplicates == 0
+end
+
+return {
+  update_duplicates = update_duplicates,
+  check_duplicates = check_duplicates,
+  show_duplicates = show_duplicates,
+}
@@ -4,6 +4,7 @@ local input = vim.api.nvim_input
 local command = vim.api.nvim_command
 local get_vvar = vim.api.nvim_get_vvar
 local eval = vim.api.nvim_eval
+local get_var = vim.api.nvim_get_var
 
 local nvimpam_err = require('nvimpam.job').nvimpam_err
 local jobids = require('nvimpam.job').jobids
@@ -46,9 +47,93 @@ local function update_folds(texts)
     table.insert(foldtexts, v)
     cmd = cmd.."|"..v[1]..","..v[2].."fo" 
   end
+  -- The GES folds inside of cards, if they're long enough
+  local ok, minlines = pcall(get_var, "nvimpam_ges_folds")
+  if ok and minlines > 0 then
+    for _, v in ipairs(texts[3] or {}) do
+      if v[2] - v[1] + 1 >= minlines then
+        table.insert(foldtexts, v)
+        cmd = cmd.."|"..v[1]..","..v[2].."fo"
+      end
+    end
+  end
+
+  -- With 'foldexpr', see g:nvimpam_foldexpr, neovim asks nvimpam for the
+  -- folds itself. They're recomputed after returning, since nvimpam is
+  -- waiting for this call to finish.
+  if eval("&foldmethod") == "expr" then
+    command("call timer_start(0, {-> execute('normal! zx')})")
+    return
+  end
   command(cmd)
 end
 
+-- Update the foldtexts after the lines `firstline..lastline` (zero-indexed,
+-- end-exclusive) were replaced, adding `added` lines. Neovim moves and resizes
+-- the folds along with the lines, so the foldtexts are moved the same way.
+-- The `texts` of the folds that changed replace the ones of the same range.
+local function update_foldtexts(firstline, lastline, added, texts)
+  local new = {}
+
+  for _, v in ipairs(texts) do
+    new[v[1]..":"..v[2]] = v
+  end
+
+  local updated = {}
+  for _, v in ipairs(foldtexts) do
+    local start, ende = v[1], v[2]
+    if start - 1 >= lastline then
+      start, ende = start + added, ende + added
+    elseif ende - 1 >= lastline then
+      ende = ende + added
+    end
+
+    local key = start..":"..ende
+    if not new[key] then
+      table.insert(updated, { start, ende, v[3] })
+    end
+  end
+
+  for _, v in ipairs(texts) do
+    table.insert(updated, v)
+  end
+
+  foldtexts = updated
+end
+
+-- Holds the folds sent by add_folds until commit_folds applies them, of the
+-- same form as the argument of update_folds
+local pending = { {}, {}, {} }
+
+-- Add a chunk of folds sent by nvimpam. `level` is 1 or 2 for the level 1 or
+-- 2 folds, and 3 for the GES folds
+local function add_folds(level, folds)
+  for _, v in ipairs(folds) do
+    table.insert(pending[level], v)
+  end
+end
+
+-- Apply all folds added by add_folds
+local function commit_folds()
+  local texts = pending
+  pending = { {}, {}, {} }
+  update_folds(texts)
+end
+
+-- Remove the folds in all windows showing the buffer `buf`, e.g. when
+-- nvimpam quits
+local function clear_folds(buf)
+  local win = call("win_getid", {})
+
+  for _, w in ipairs(call("win_findbuf", { buf })) do
+    call("win_gotoid", { w })
+    command('exe "norm! zE"')
+  end
+
+  call("win_gotoid", { win })
+  foldtexts = {}
+end
+
 local function refresh_folds(buf)
   buf = buf or curbuf()
 
@@ -62,9 +147,69 @@ local function refresh_folds(buf)
   return true
 end
 
+-- Set the foldtext of the fold starting at line `start` (zero-indexed) to
+-- `label`. Defaults to the closed fold under the cursor, or the fold starting
+-- at the cursor line. An empty label restores the generated foldtext.
+local function rename_fold(label, buf, start)
+  buf = buf or curbuf()
+
+  if not start then
+    start = call("foldclosed", { "." })
+    if start == -1 then
+      start = call("line", { "." })
+    end
+    start = start - 1
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("rename_fold failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "RenameFold", start, label })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  update_folds(res)
+  return true
+end
+
+-- Get the folds intersecting the lines `firstline..lastline` (zero-indexed,
+-- end-exclusive) without creating them. They're returned as a list of the
+-- level 1, level 2 and GES folds, each of them a list of values of the form
+-- {start, end, text}, so the folds of a window can be created on their own.
+-- Returns nil on failure.
+local function folds_for_range(firstline, lastline, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("folds_for_range failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "FoldsForRange", firstline,
+                                   lastline })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
 return {
   update_folds = update_folds,
+  add_folds = add_folds,
+  commit_folds = commit_folds,
   refresh_folds = refresh_folds,
+  clear_folds = clear_folds,
+  rename_fold = rename_fold,
+  folds_for_range = folds_for_range,
   foldtext = foldtext,
+  update_foldtexts = update_foldtexts,
   printfolds = printfolds,
 }
@@ -0,0 +1,132 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local update_folds = require('nvimpam.fold').update_folds
+
+-- The sidecar file to use if none is given
+local function default_path(buf)
+  return call("fnamemodify", { call("bufname", { buf }), ":p" })..".folds"
+end
+
+-- The session file to use if none is given
+local function default_state_path(buf)
+  return call("fnamemodify", { call("bufname", { buf }), ":p" })..".nvimpam"
+end
+
+-- Write the folds of the buffer, including custom folds and foldtexts, to the
+-- file `path`
+local function export_folds(path, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("export_folds failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  if not path or path == "" then
+    path = default_path(buf)
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "ExportFolds", path })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Read the folds from the file `path` and apply them to the buffer. Fails if
+-- the file was exported from a different revision of the buffer.
+local function import_folds(path, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("import_folds failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  if not path or path == "" then
+    path = default_path(buf)
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "ImportFolds", path })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  update_folds(res)
+  return true
+end
+
+-- Write the state of the buffer, i.e. its folds and the index of its include
+-- files, to the file `path`, so it can be restored along with a session
+local function save_state(path, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("save_state failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  if not path or path == "" then
+    path = default_state_path(buf)
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "SaveState", path })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Read the state of the buffer from the file `path` and apply its folds. Fails
+-- if the state was saved for a different revision of the buffer.
+local function load_state(path, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("load_state failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  if not path or path == "" then
+    path = default_state_path(buf)
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "LoadState", path })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  update_folds(res)
+  return true
+end
+
+-- Load the states of all attached buffers from their default session files,
+-- if they exist. Called when a session was restored.
+local function load_states()
+  for buf, _ in pairs(jobids) do
+    if call("filereadable", { default_state_path(buf) }) == 1 then
+      load_state(nil, buf)
+    end
+  end
+end
+
+return {
+  export_folds = export_folds,
+  import_folds = import_folds,
+  save_state = save_state,
+  load_state = load_state,
+  load_states = load_states,
+}
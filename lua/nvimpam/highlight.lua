@@ -1,5 +1,10 @@
 local curbuf = vim.api.nvim_get_current_buf
 local call = vim.api.nvim_call_function
+local get_var = vim.api.nvim_get_var
+local get_option = vim.api.nvim_get_option
+local get_vvar = vim.api.nvim_get_vvar
+local create_namespace = vim.api.nvim_create_namespace
+local buf_line_count = vim.api.nvim_buf_line_count
 
 local nvimpam_err = require('nvimpam.job').nvimpam_err
 local jobids = require('nvimpam.job').jobids
@@ -17,6 +22,241 @@ local function highlight_region(buf, firstline, lastline)
   return true
 end
 
+-- The visible lines last sent by highlight_viewport, by buffer
+local viewports = {}
+
+-- Ask nvimpam to highlight the lines visible in the current window right
+-- away, and the rest of the buffer when idle, starting with the lines the
+-- window is scrolling towards. Meant to be called from an autocommand, so
+-- nothing is sent if the visible lines did not change or nvimpam is not
+-- attached to the buffer.
+local function highlight_viewport()
+  local buf = curbuf()
+
+  if not jobids[buf] then
+    return false
+  end
+
+  local top = call("line", { "w0" }) - 1
+  local bot = call("line", { "w$" }) - 1
+  local last = viewports[buf]
+
+  if last and last[1] == top and last[2] == bot then
+    return true
+  end
+
+  viewports[buf] = { top, bot }
+  -- Like neovim's win_viewport event, the bottom line is exclusive
+  call("rpcnotify", { jobids[buf], "Viewport", top, bot + 1 })
+  return true
+end
+
+-- The buffers whose nvimpam was told that an 'inccommand' preview is shown
+local previewing = {}
+
+-- Tell nvimpam that an 'inccommand' preview might be shown while the command
+-- line is open, or that the command line was left. Meant to be called from
+-- the CmdlineEnter and CmdlineLeave autocommands. Nvimpam highlights the
+-- changes of the preview, but forgets about them when it's left, since
+-- neovim undoes them.
+local function inccommand(active)
+  local buf = curbuf()
+
+  if not jobids[buf] then
+    return false
+  end
+
+  if active then
+    if get_option("inccommand") == "" or get_vvar("event").cmdtype ~= ":" then
+      return true
+    end
+  elseif not previewing[buf] then
+    return true
+  end
+
+  previewing[buf] = active or nil
+  call("rpcnotify", { jobids[buf], "Inccommand", active })
+  return true
+end
+
+-- Send the highlight configuration to nvimpam. The namespace is named by
+-- g:nvimpam_highlight_namespace, g:nvimpam_highlight_groups maps highlight
+-- kinds to group names, g:nvimpam_highlight_overrides maps keywords of cards
+-- to group names, g:nvimpam_highlight_columns is the column the
+-- highlights end at, and g:nvimpam_column_encoding the unit of the columns
+-- returned by highlights_for_range. Nothing is sent if none of them is set.
+local function set_highlight_config(buf)
+  buf = buf or curbuf()
+
+  local has_ns, ns = pcall(get_var, "nvimpam_highlight_namespace")
+  local has_groups, groups = pcall(get_var, "nvimpam_highlight_groups")
+  local has_columns, columns = pcall(get_var, "nvimpam_highlight_columns")
+  local has_encoding, encoding = pcall(get_var, "nvimpam_column_encoding")
+  local has_overrides, overrides = pcall(get_var,
+                                         "nvimpam_highlight_overrides")
+
+  if not has_ns and not has_groups and not has_columns and not has_encoding
+     and not has_overrides then
+    return true
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("set_highlight_config failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local nsid = has_ns and create_namespace(ns) or vim.NIL
+  local res = call("rpcrequest", { jobids[buf], "HighlightConfig", nsid,
+                                   has_groups and groups or {},
+                                   has_columns and columns or vim.NIL,
+                                   has_encoding and encoding or vim.NIL,
+                                   has_overrides and overrides or {} })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Get the highlights of the lines `firstline..lastline` (zero-indexed,
+-- end-exclusive) as a list of values of the form {line, start, end, group},
+-- with the columns counted as set by g:nvimpam_column_encoding. Returns nil
+-- on failure.
+local function highlights_for_range(firstline, lastline, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("highlights_for_range failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "HighlightsForRange",
+                                   firstline, lastline })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Get the highlights of the lines `firstline..lastline` (zero-indexed,
+-- end-exclusive) as LSP semantic tokens, i.e. a table with the `data` of the
+-- delta-encoded tokens and their `legend` holding the `tokenTypes` and
+-- `tokenModifiers`, with the columns counted as set by
+-- g:nvimpam_column_encoding. Returns nil on failure.
+local function semantic_tokens(firstline, lastline, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("semantic_tokens failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "SemanticTokens",
+                                   firstline, lastline })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Replace the highlights other plugins added to the lines
+-- `firstline..lastline` (zero-indexed, end-exclusive, -1 for the end of the
+-- buffer) of `buf` by `spans`, a list of values of the form
+-- {line, start, end, group}. Nvimpam adds them to its namespace, and keeps
+-- them along with its own highlights when the buffer changes.
+local function external_highlights(firstline, lastline, spans, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("external_highlights failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  if lastline < 0 then
+    lastline = buf_line_count(buf)
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "ExternalHighlights",
+                                   firstline, lastline, spans })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Enable or disable the highlights of the buffer `buf`. While disabled,
+-- nvimpam only keeps the folds up to date, which is faster for big files.
+local function enable_highlights(enable, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("enable_highlights failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "EnableHighlights", enable })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Disable the highlights of `buf` if g:nvimpam_highlight is set to 0
+local function set_highlighting(buf)
+  local has_hl, hl = pcall(get_var, "nvimpam_highlight")
+
+  if not has_hl or (hl ~= 0 and hl ~= false) then
+    return true
+  end
+
+  return enable_highlights(false, buf)
+end
+
+-- Send g:nvimpam_highlight_age to the nvimpam instance attached to `buf`, if
+-- it's set. The highlights of lines not viewed for that many milliseconds are
+-- cleared, and sent again when the lines are viewed.
+local function set_highlight_age(buf)
+  local has_age, age = pcall(get_var, "nvimpam_highlight_age")
+
+  if not has_age then
+    return true
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "HighlightAge", age })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
 return {
   highlight_region = highlight_region,
+  highlight_viewport = highlight_viewport,
+  inccommand = inccommand,
+  set_highlight_config = set_highlight_config,
+  highlights_for_range = highlights_for_range,
+  semantic_tokens = semantic_tokens,
+  external_highlights = external_highlights,
+  enable_highlights = enable_highlights,
+  set_highlighting = set_highlighting,
+  set_highlight_age = set_highlight_age,
 }
@@ -0,0 +1,103 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local set_lines = vim.api.nvim_buf_set_lines
+local buf_get_name = vim.api.nvim_buf_get_name
+local command = vim.api.nvim_command
+local set_cursor = vim.api.nvim_win_set_cursor
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Move the cards in the lines `firstline` to `lastline` (zero-indexed,
+-- end-exclusive) to the new file `path`, and replace them by an INCLU line.
+-- The range needs to consist of whole cards.
+local function extract_include(path, firstline, lastline, buf)
+  buf = buf or curbuf()
+
+  if not path or path == "" then
+    nvimpam_err("extract_include failed: No file name given!")
+    return false
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("extract_include failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "ExtractInclude", firstline,
+                                   lastline, path })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  set_lines(buf, firstline, lastline, true, { res.line })
+  return true
+end
+
+-- Index the entities defined in the files included by the buffer `buf` in
+-- the background, so `goto_definition` finds them
+local function index_includes(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("index_includes failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "IndexIncludes",
+                                   buf_get_name(buf) })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Called by nvimpam when the include file `path` of the buffer `buf` changed
+-- on disk. The include files are indexed again, since their definitions or
+-- includes might have changed.
+local function include_changed(buf, path)
+  out_write("Include file "..path.." changed, updating the index\n")
+  return index_includes(buf)
+end
+
+-- Jump to the definition of the ID `id` of `entity`, e.g. "node", in the
+-- buffer `buf` or one of the include files indexed by `index_includes`
+local function goto_definition(entity, id, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("goto_definition failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "FindDefinition", entity,
+                                   tonumber(id) })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  if res.file ~= "" then
+    command("edit "..call("fnameescape", { res.file }))
+  end
+  set_cursor(0, { res.line + 1, 0 })
+
+  return true
+end
+
+return {
+  extract_include = extract_include,
+  index_includes = index_includes,
+  include_changed = include_changed,
+  goto_definition = goto_definition,
+}
@@ -2,21 +2,119 @@ local utils = require('nvimpam.utils')
 local highlight = require('nvimpam.highlight')
 local fold = require('nvimpam.fold')
 local job = require('nvimpam.job')
+local convert = require('nvimpam.convert')
+local lint = require('nvimpam.lint')
+local renumber = require('nvimpam.renumber')
+local cellhint = require('nvimpam.cellhint')
+local metadata = require('nvimpam.metadata')
+local stats = require('nvimpam.stats')
+local foldstate = require('nvimpam.foldstate')
+local duplicates = require('nvimpam.duplicates')
+local include = require('nvimpam.include')
+local comment = require('nvimpam.comment')
+local cardinfo = require('nvimpam.cardinfo')
+local diff = require('nvimpam.diff')
+local quickfix = require('nvimpam.quickfix')
+local request = require('nvimpam.request')
+local align = require('nvimpam.align')
 
 return {
   -- job
   attach = job.attach,
+  preview = job.preview,
   detach = job.detach,
+  restart = job.restart,
   detach_all = job.detach_all,
   on_stderr = job.on_stderr,
   on_exit = job.on_exit,
   nvimpam_err = job.nvimpam_err,
+  on_panic = job.on_panic,
+  handshake = job.handshake,
+  capabilities = job.capabilities,
   -- fold
   update_folds = fold.update_folds,
+  add_folds = fold.add_folds,
+  commit_folds = fold.commit_folds,
   refresh_folds = fold.refresh_folds,
+  clear_folds = fold.clear_folds,
+  rename_fold = fold.rename_fold,
+  folds_for_range = fold.folds_for_range,
   foldtext = fold.foldtext,
+  update_foldtexts = fold.update_foldtexts,
+  -- foldstate
+  export_folds = foldstate.export_folds,
+  import_folds = foldstate.import_folds,
+  save_state = foldstate.save_state,
+  load_state = foldstate.load_state,
+  load_states = foldstate.load_states,
   -- utils
   locate_binary = utils.locate_binary,
   -- highlight
   highlight_region = highlight.highlight_region,
+  highlight_viewport = highlight.highlight_viewport,
+  inccommand = highlight.inccommand,
+  set_highlight_config = highlight.set_highlight_config,
+  highlights_for_range = highlight.highlights_for_range,
+  semantic_tokens = highlight.semantic_tokens,
+  external_highlights = highlight.external_highlights,
+  enable_highlights = highlight.enable_highlights,
+  -- convert
+  convert_card = convert.convert_card,
+  -- renumber
+  renumber = renumber.renumber,
+  rename_id = renumber.rename_id,
+  offset_include = renumber.offset_include,
+  -- cellhint
+  cell_hint = cellhint.cell_hint,
+  warn_cell_shift = cellhint.warn_cell_shift,
+  -- cardinfo
+  card_info = cardinfo.card_info,
+  show_card_info = cardinfo.show_card_info,
+  ges_contents = cardinfo.ges_contents,
+  show_ges_contents = cardinfo.show_ges_contents,
+  cell_range = cardinfo.cell_range,
+  select_cell = cardinfo.select_cell,
+  card_object = cardinfo.card_object,
+  select_card = cardinfo.select_card,
+  cell_number = cardinfo.cell_number,
+  show_cell_number = cardinfo.show_cell_number,
+  -- metadata
+  deck_metadata = metadata.deck_metadata,
+  show_metadata = metadata.show_metadata,
+  -- stats
+  buffer_stats = stats.buffer_stats,
+  show_stats = stats.show_stats,
+  keyword_summary = stats.keyword_summary,
+  show_keyword_summary = stats.show_keyword_summary,
+  event_timings = stats.event_timings,
+  show_event_timings = stats.show_event_timings,
+  health_reports = stats.health_reports,
+  -- lint
+  lint_sections = lint.lint_sections,
+  -- duplicates
+  check_duplicates = duplicates.check_duplicates,
+  show_duplicates = duplicates.show_duplicates,
+  update_duplicates = duplicates.update_duplicates,
+  -- include
+  extract_include = include.extract_include,
+  index_includes = include.index_includes,
+  include_changed = include.include_changed,
+  goto_definition = include.goto_definition,
+  -- comment
+  toggle_comment = comment.toggle_comment,
+  -- diff
+  diff_deck = diff.diff_deck,
+  show_diff_deck = diff.show_diff_deck,
+  -- quickfix
+  quickfix_entries = quickfix.quickfix_entries,
+  set_quickfix = quickfix.set_quickfix,
+  -- request
+  background_request = request.background_request,
+  cancel_request = request.cancel_request,
+  cancel_requests = request.cancel_requests,
+  finish_request = request.finish_request,
+  -- align
+  align_cells = align.align_cells,
+  align_on_save = align.align_on_save,
+  written = align.written,
 }
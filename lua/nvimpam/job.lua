@@ -3,6 +3,7 @@ local call = vim.api.nvim_call_function
 local curbuf = vim.api.nvim_get_current_buf
 local get_vvar = vim.api.nvim_get_vvar
 local input = vim.api.nvim_input
+local get_var = vim.api.nvim_get_var
 
 local locate_binary = require('nvimpam.utils').locate_binary
 
@@ -12,6 +13,14 @@ local binary = nil
 -- Holds buffer -> jobid associations
 local jobids = {}
 
+-- The API level of these Lua files, see LUA_API_LEVEL in src/handshake.rs
+local lua_api_level = 1
+-- The lowest API level of the binary handling all the requests sent from here
+local required_api_level = 7
+
+-- Holds buffer -> the hello sent by nvimpam on connecting, see handshake
+local hellos = {}
+
 -- Holds nvimpam stderr output
 local stderr = {}
 -- Saves the value of NVIMPAM_STDERR
@@ -33,7 +42,7 @@ local function on_stderr(id, data, event)
 end
 
 local function nvimpam_err(msg, id)
-  command("echoerr \'"..msg.."\'")
+  command("echoerr \'"..msg:gsub("'", "''").."\'")
 
   id = id or "NONE" 
   on_stderr(id, {msg})
@@ -50,10 +59,166 @@ local function on_exit(id, exitcode)
   
   if bufname then
     jobids[bufname] = nil
+    hellos[bufname] = nil
+    pcall(vim.api.nvim_buf_del_var, bufname, "nvimpam_jobid")
+  end
+end
+
+-- Called by nvimpam right before it exits after a panic. The message
+-- contains the location of the panic, and was written to the crash log `log`
+-- as well.
+local function on_panic(message, log)
+  nvimpam_err("Nvimpam crashed: "..message.." (see "..log..")")
+end
+
+-- Called by nvimpam right after starting with its `hello`, a table of its
+-- `version`, its `api_level`, the `lua_api_level` it needs, and the names of
+-- the `requests` it handles and the `callbacks` it calls. A binary with a
+-- lower API level than needed here is reported. Returns the API level of the
+-- Lua files and the callbacks they provide, which nvimpam checks in turn.
+local function handshake(hello)
+  hellos[curbuf()] = hello
+
+  if hello.api_level < required_api_level then
+    pcall(nvimpam_err, "The nvimpam binary has API level "
+                       ..tostring(hello.api_level)..", but the Lua runtime "
+                       .."files need "..tostring(required_api_level)
+                       ..", please update it!")
+  end
+
+  local nvimpam = require('nvimpam')
+  local callbacks = {}
+  for _, name in ipairs(hello.callbacks or {}) do
+    if type(nvimpam[name]) == "function" then
+      table.insert(callbacks, name)
+    end
+  end
+
+  return { lua_api_level = lua_api_level, callbacks = callbacks }
+end
+
+-- What the nvimpam instance attached to `buf` (defaults to the current
+-- buffer) can do, i.e. the `hello` it sent on connecting, see handshake.
+-- Returns nil for a binary without the handshake.
+local function capabilities(buf)
+  return hellos[buf or curbuf()]
+end
+
+-- Send g:nvimpam_coalesce_ms to the nvimpam instance attached to `buf`, if
+-- it's set
+local function set_coalesce_window(buf)
+  local has_ms, ms = pcall(get_var, "nvimpam_coalesce_ms")
+
+  if not has_ms then
+    return true
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "CoalesceWindow", ms })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Send g:nvimpam_fold_chunk to the nvimpam instance attached to `buf`, if
+-- it's set
+local function set_fold_chunk(buf)
+  local has_size, size = pcall(get_var, "nvimpam_fold_chunk")
+
+  if not has_size then
+    return true
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "FoldChunkSize", size })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  return true
+end
+
+-- Read the foldtext templates from the JSON file g:nvimpam_foldtext_file and
+-- send them to the nvimpam instance attached to `buf`, if it's set. In
+-- preview mode, the folds have already been sent, so they're updated with
+-- the new foldtexts.
+local function set_foldtexts(buf, preview)
+  local has_file, file = pcall(get_var, "nvimpam_foldtext_file")
+
+  if not has_file then
+    return true
+  end
+
+  if call("filereadable", { file }) == 0 then
+    nvimpam_err("Could not read foldtext file '"..file.."'!")
+    return false
+  end
+
+  local ok, texts = pcall(call, "json_decode", { call("readfile", { file }) })
+
+  if not ok or type(texts) ~= "table" then
+    nvimpam_err("Could not parse foldtext file '"..file.."'!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "FoldTexts", texts })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  if preview then
+    require('nvimpam.fold').update_folds(res)
+  end
+
+  return true
+end
+
+-- Send the rules for grouping the folds from g:nvimpam_fold_groups to the
+-- nvimpam instance attached to `buf`, if it's set. In preview mode, the
+-- folds are updated with the new level 2 folds.
+local function set_fold_groups(buf, preview)
+  local has_groups, groups = pcall(get_var, "nvimpam_fold_groups")
+
+  if not has_groups then
+    return true
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "FoldGroups", groups })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  if preview then
+    require('nvimpam.fold').update_folds(res)
   end
+
+  return true
+end
+
+-- Enable the check for duplicate IDs in `buf` if g:nvimpam_check_duplicates
+-- is set to a nonzero value
+local function check_duplicates(buf)
+  local has_check, check = pcall(get_var, "nvimpam_check_duplicates")
+
+  if not has_check or check == 0 then
+    return true
+  end
+
+  return require('nvimpam.duplicates').check_duplicates(true, buf) ~= nil
 end
 
-local function attach(filename)
+-- Start nvimpam for the current buffer. If `preview` is true, it only sends
+-- the folds and highlights of the file `filename` once, without attaching to
+-- the buffer.
+local function attach(filename, preview)
   local buf = curbuf()
 
   if jobids[buf] then
@@ -72,11 +237,50 @@ local function attach(filename)
     return false
   end
 
-  local binlist
-  if filename == nil or filename == "" then
-    binlist = { binary }
-  else
-    binlist = { binary, filename }
+  local binlist = { binary }
+  local has_defs, carddefs = pcall(get_var, "nvimpam_carddefs")
+  if has_defs and carddefs ~= "" then
+    table.insert(binlist, "--carddefs")
+    table.insert(binlist, call("expand", { carddefs }))
+  end
+  local has_tabs, tabs = pcall(get_var, "nvimpam_tabs")
+  if has_tabs and tabs ~= "" then
+    table.insert(binlist, "--tabs")
+    table.insert(binlist, tabs)
+  end
+  local has_size, size = pcall(get_var, "nvimpam_max_size")
+  if has_size then
+    table.insert(binlist, "--max-size")
+    table.insert(binlist, tostring(size))
+    local has_mode, mode = pcall(get_var, "nvimpam_degraded_mode")
+    if has_mode then
+      table.insert(binlist, "--degraded")
+      table.insert(binlist, mode)
+    end
+  end
+  local has_banners, banners = pcall(get_var, "nvimpam_banner_patterns")
+  if has_banners then
+    if #banners == 0 then
+      table.insert(binlist, "--no-banners")
+    end
+    for _, pattern in ipairs(banners) do
+      table.insert(binlist, "--banner")
+      table.insert(binlist, pattern)
+    end
+  end
+  local has_strict, strict = pcall(get_var, "nvimpam_strict")
+  if has_strict and strict ~= 0 then
+    table.insert(binlist, "--strict")
+  end
+  if preview then
+    if filename == nil or filename == "" then
+      nvimpam_err("Preview failed: Buffer "..tostring(buf).." has no file!")
+      return false
+    end
+    table.insert(binlist, "--preview")
+  end
+  if filename ~= nil and filename ~= "" then
+    table.insert(binlist, filename)
   end
 
   if not callbacks_defined["onexit"] then
@@ -126,10 +330,24 @@ local function attach(filename)
     return false
   else
     jobids[buf] = jobid
-    return true
+    -- For 'foldexpr', see g:nvimpam_foldexpr
+    vim.api.nvim_buf_set_var(buf, "nvimpam_jobid", jobid)
+    return require('nvimpam.highlight').set_highlight_config(buf)
+           and require('nvimpam.highlight').set_highlighting(buf)
+           and require('nvimpam.highlight').set_highlight_age(buf)
+           and set_coalesce_window(buf)
+           and set_fold_chunk(buf)
+           and set_foldtexts(buf, preview)
+           and set_fold_groups(buf, preview)
+           and check_duplicates(buf)
   end
 end
 
+-- Start nvimpam for the current buffer in preview mode, see attach
+local function preview(filename)
+  return attach(filename, true)
+end
+
 local function detach(buf)
   buf = buf or curbuf()
   local jobid = jobids[buf]
@@ -143,6 +361,42 @@ local function detach(buf)
   end
 end
 
+-- Restart nvimpam for the current buffer, e.g. after upgrading the binary.
+-- The old process sends back its state along with the buffer's changedtick,
+-- which are handed over to the new one by a notification, so the custom folds
+-- are kept. The buffer's contents are requested again since they might
+-- differ from the file on disk.
+local function restart()
+  local buf = curbuf()
+  local jobid = jobids[buf]
+
+  if not jobid then
+    nvimpam_err("Restart failed: No jobid entry for buffer "..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobid, "Restart" })
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  elseif type(res) ~= "table" or type(res.state) ~= "string"
+         or type(res.changedtick) ~= "number" then
+    nvimpam_err("Restart failed: Invalid reply "..tostring(res))
+    return false
+  end
+
+  jobids[buf] = nil
+  hellos[buf] = nil
+
+  local attached = attach(nil)
+  if jobids[buf] then
+    call("rpcnotify", { jobids[buf], "RestoreState", res.state,
+                        res.changedtick })
+  end
+
+  return attached
+end
+
 local function detach_all()
   for buf, jobid in pairs(jobids) do
     detach(buf)
@@ -189,11 +443,17 @@ end
 
 return {
   attach = attach,
+  preview = preview,
   detach = detach,
+  restart = restart,
   detach_all = detach_all,
   on_stderr = on_stderr,
   on_exit = on_exit,
+  on_panic = on_panic,
+  handshake = handshake,
   printstderr = printstderr,
   jobids = jobids,
+  capabilities = capabilities,
+  lua_api_level = lua_api_level,
   nvimpam_err = nvimpam_err,
 }
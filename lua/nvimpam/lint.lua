@@ -0,0 +1,34 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local get_var = vim.api.nvim_get_var
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local show = require('nvimpam.diagnostics').show
+
+-- Check the order of the sections in the buffer. The rules are taken from
+-- g:nvimpam_section_rules if it exists, otherwise nvimpam uses its defaults.
+local function lint_sections(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("lint_sections failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local ok, rules = pcall(get_var, "nvimpam_section_rules")
+  if not ok then
+    rules = {}
+  end
+
+  local diagnostics = call("rpcrequest",
+                           { jobids[buf], "LintSections", rules })
+  show(diagnostics)
+
+  return #diagnostics == 0
+end
+
+return {
+  lint_sections = lint_sections,
+}
@@ -0,0 +1,48 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Ask nvimpam for the metadata declared at the top of the buffer. Returns a
+-- table with the keys `version`, `analysis`, `solver`, `unit` and `title`,
+-- each present only if the corresponding line was found.
+local function deck_metadata(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("deck_metadata failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "Metadata" })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Echo the metadata of the current buffer
+local function show_metadata()
+  local meta = deck_metadata()
+
+  if not meta then
+    return false
+  end
+
+  for _, key in ipairs({ "version", "analysis", "solver", "unit", "title" }) do
+    out_write(key..": "..(meta[key] or "-").."\n")
+  end
+
+  return true
+end
+
+return {
+  deck_metadata = deck_metadata,
+  show_metadata = show_metadata,
+}
@@ -0,0 +1,42 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local buf_get_name = vim.api.nvim_buf_get_name
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Get all diagnostics of the buffer `buf` as entries of the quickfix list,
+-- i.e. tables with the keys `filename`, `lnum`, `col`, `end_col`, `text` and
+-- `type`, ready to be passed to `setqflist()`
+local function quickfix_entries(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("quickfix_entries failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  return call("rpcrequest", { jobids[buf], "Quickfix", buf_get_name(buf) })
+end
+
+-- Replace the quickfix list by the diagnostics of the current buffer
+local function set_quickfix()
+  local entries = quickfix_entries()
+
+  if not entries then
+    return false
+  end
+
+  call("setqflist", { {}, " ", { title = "nvimpam diagnostics",
+                                 items = entries } })
+  out_write(string.format("%d diagnostics\n", #entries))
+
+  return #entries == 0
+end
+
+return {
+  quickfix_entries = quickfix_entries,
+  set_quickfix = set_quickfix,
+}
@@ -0,0 +1,135 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local set_lines = vim.api.nvim_buf_set_lines
+local buf_get_name = vim.api.nvim_buf_get_name
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local show = require('nvimpam.diagnostics').show
+
+-- Renumber the entities of type `entity`, e.g. "node", defined in the lines
+-- `firstline` to `lastline` (zero-indexed, end-exclusive), starting with the
+-- ID `start` and increasing by `step`. References to them are updated as
+-- well. IDs that clash with existing ones are reported as messages.
+local function renumber(entity, start, step, firstline, lastline, buf)
+  buf = buf or curbuf()
+  start = tonumber(start) or 1
+  step = tonumber(step) or 1
+  firstline = firstline or 0
+  lastline = lastline or call("line", { "$" })
+
+  if not jobids[buf] then
+    nvimpam_err("renumber failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "Renumber", entity, start,
+                                   step, firstline, lastline })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  for _, e in ipairs(res.edits) do
+    set_lines(buf, e[1], e[1] + 1, true, { e[2] })
+  end
+
+  show(res.diagnostics)
+
+  return true
+end
+
+-- Rename the entity of type `entity`, e.g. "node", with the ID `old` to
+-- `new`. References to it are updated as well. If `new` is already defined,
+-- nothing is changed and a message is shown instead.
+local function rename_id(entity, old, new, buf)
+  buf = buf or curbuf()
+  old = tonumber(old)
+  new = tonumber(new)
+
+  if not old or not new then
+    nvimpam_err("rename_id failed: IDs need to be numbers!")
+    return false
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("rename_id failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "RenameId", entity, old, new })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  for _, e in ipairs(res.edits) do
+    set_lines(buf, e[1], e[1] + 1, true, { e[2] })
+  end
+
+  show(res.diagnostics)
+
+  return true
+end
+
+-- Add `offset` to the IDs of the `entities`, e.g. { "node" }, defined in
+-- the include file of the INCLU line `line` (zero-indexed), or of all
+-- entities if `entities` is empty. References to them are updated in the
+-- buffer and its indexed include files, which are loaded into hidden buffers
+-- to change them.
+local function offset_include(offset, entities, buf, line)
+  buf = buf or curbuf()
+  line = line or call("line", { "." }) - 1
+  offset = tonumber(offset)
+
+  if not offset then
+    nvimpam_err("offset_include failed: The offset needs to be a number!")
+    return false
+  end
+
+  if not jobids[buf] then
+    nvimpam_err("offset_include failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return false
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "OffsetInclude",
+                                   buf_get_name(buf), line, offset,
+                                   entities or {} })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return false
+  end
+
+  for _, f in ipairs(res.files) do
+    local b = buf
+
+    if f.file ~= "" then
+      b = call("bufadd", { f.file })
+      call("bufload", { b })
+      if #f.diagnostics > 0 then
+        out_write(f.file..":\n")
+      end
+    end
+
+    for _, e in ipairs(f.edits) do
+      set_lines(b, e[1], e[1] + 1, true, { e[2] })
+    end
+
+    show(f.diagnostics)
+  end
+
+  return true
+end
+
+return {
+  renumber = renumber,
+  rename_id = rename_id,
+  offset_include = offset_include,
+}
@@ -0,0 +1,92 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Holds id -> { buf, callback } of the requests running in the background
+local running = {}
+
+-- The ID of the next background request, unique among all buffers
+local next_id = 1
+
+-- Send the request `name` with the arguments `args` to the nvimpam process of
+-- the buffer `buf`, which answers it in the background. Only "DiffDeck",
+-- "Quickfix" and "KeywordSummary" are supported. Once it's done, `callback`
+-- is called with the answer. Returns the ID of the request, which can be
+-- passed to `cancel_request`.
+local function background_request(name, args, callback, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("background_request failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local id = next_id
+  next_id = next_id + 1
+  running[id] = { buf = buf, callback = callback }
+
+  call("rpcnotify", { jobids[buf], "Background", id, name, args or {} })
+
+  return id
+end
+
+-- Cancel the background request `id`. Its callback is not called anymore.
+local function cancel_request(id)
+  local request = running[id]
+
+  if not request then
+    return false
+  end
+
+  running[id] = nil
+
+  if jobids[request.buf] then
+    call("rpcnotify", { jobids[request.buf], "Cancel", id })
+  end
+
+  return true
+end
+
+-- Cancel all background requests of the buffer `buf`
+local function cancel_requests(buf)
+  buf = buf or curbuf()
+  local cancelled = 0
+
+  for id, request in pairs(running) do
+    if request.buf == buf and cancel_request(id) then
+      cancelled = cancelled + 1
+    end
+  end
+
+  out_write(string.format("Cancelled %d requests\n", cancelled))
+
+  return cancelled
+end
+
+-- Called by nvimpam with the answer `result` to the background request `id`
+local function finish_request(id, result)
+  local request = running[id]
+
+  if not request then
+    return
+  end
+
+  running[id] = nil
+
+  if type(result) == "string" then
+    nvimpam_err(result)
+  else
+    request.callback(result)
+  end
+end
+
+return {
+  background_request = background_request,
+  cancel_request = cancel_request,
+  cancel_requests = cancel_requests,
+  finish_request = finish_request,
+}
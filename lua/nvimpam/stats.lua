@@ -0,0 +1,193 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local out_write = vim.api.nvim_out_write
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+local capabilities = require('nvimpam.job').capabilities
+local lua_api_level = require('nvimpam.job').lua_api_level
+
+-- The keys of the table returned by `buffer_stats`, in the order they're shown
+local keys = {
+  "lines", "keywords", "line_bytes", "heap_bytes", "folds", "folds_level2",
+  "folds_ges", "custom_folds", "highlights", "last_parse_us", "last_update_us"
+}
+
+-- Ask nvimpam for statistics about the data it holds for the buffer, e.g. the
+-- number of lines and folds, and how long the last update took.
+local function buffer_stats(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("buffer_stats failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "Stats" })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Echo the statistics of the current buffer
+local function show_stats()
+  local stats = buffer_stats()
+
+  if not stats then
+    return false
+  end
+
+  for _, key in ipairs(keys) do
+    if stats[key] then
+      out_write(key..": "..tostring(stats[key]).."\n")
+    end
+  end
+
+  return true
+end
+
+-- Ask nvimpam for the number of cards and lines of each card type in the
+-- buffer. Returns a list of tables with the keys `keyword`, `cards` and
+-- `lines`, sorted by the number of lines.
+local function keyword_summary(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("keyword_summary failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "KeywordSummary" })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Show the keyword summary of the current buffer in a floating window, which
+-- is closed by pressing `q`. Without floating windows, it is echoed instead.
+local function show_keyword_summary()
+  local summary = keyword_summary()
+
+  if not summary then
+    return false
+  end
+
+  local lines = {}
+  local width = 0
+  for _, c in ipairs(summary) do
+    local line = string.format(" %-12s %8d cards %9d lines ", c.keyword,
+                               c.cards, c.lines)
+    table.insert(lines, line)
+    width = math.max(width, #line)
+  end
+
+  if #lines == 0 then
+    out_write("No cards in the buffer\n")
+    return true
+  end
+
+  if not vim.api.nvim_open_win then
+    out_write(table.concat(lines, "\n").."\n")
+    return true
+  end
+
+  local columns = vim.api.nvim_get_option("columns")
+  local rows = vim.api.nvim_get_option("lines")
+  local height = math.min(#lines, rows - 4)
+
+  local buf = vim.api.nvim_create_buf(false, true)
+  vim.api.nvim_buf_set_lines(buf, 0, -1, true, lines)
+  vim.api.nvim_buf_set_keymap(buf, "n", "q", "<Cmd>close<CR>",
+                              { noremap = true, silent = true })
+  vim.api.nvim_open_win(buf, true, {
+    relative = "editor",
+    width = width,
+    height = height,
+    row = math.floor((rows - height) / 2),
+    col = math.floor((columns - width) / 2),
+  })
+
+  return true
+end
+
+-- Ask nvimpam for the timings of the last `count` events it handled, oldest
+-- first. Returns a list of tables with the keys `event`, `duration_us`,
+-- `lines` and `folds`.
+local function event_timings(count, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("event_timings failed: No jobid entry for buffer "
+                ..tostring(buf).."!")
+    return nil
+  end
+
+  local res = call("rpcrequest", { jobids[buf], "EventTimings", count })
+
+  if type(res) == "string" then
+    nvimpam_err(res)
+    return nil
+  end
+
+  return res
+end
+
+-- Echo the timings of the last `count` events of the current buffer, 20 if
+-- count is not given
+local function show_event_timings(count)
+  count = tonumber(count) or 20
+  local timings = event_timings(count)
+
+  if not timings then
+    return false
+  end
+
+  for _, t in ipairs(timings) do
+    out_write(string.format("%-18s %10d us %8d lines %6d folds\n", t.event,
+                            t.duration_us, t.lines, t.folds))
+  end
+
+  return true
+end
+
+-- Ask nvimpam for the reports shown by `:checkhealth nvimpam` of all attached
+-- buffers. Returns a list of tables with the keys `buf` and `report`, the
+-- latter being the table sent by nvimpam, or an error message, along with the
+-- `hello` nvimpam sent on connecting (nil for binaries without the handshake)
+-- and the `lua_api_level` of the Lua files.
+local function health_reports()
+  local reports = {}
+
+  for buf, jobid in pairs(jobids) do
+    local ok, res = pcall(call, "rpcrequest", { jobid, "Health" })
+    if not ok then
+      res = tostring(res)
+    end
+    table.insert(reports, { buf = buf, report = res,
+                            hello = capabilities(buf),
+                            lua_api_level = lua_api_level })
+  end
+
+  table.sort(reports, function(a, b) return a.buf < b.buf end)
+  return reports
+end
+
+return {
+  buffer_stats = buffer_stats,
+  show_stats = show_stats,
+  keyword_summary = keyword_summary,
+  show_keyword_summary = show_keyword_summary,
+  event_timings = event_timings,
+  show_event_timings = show_event_timings,
+  health_reports = health_reports,
+}
@@ -0,0 +1,327 @@
+//! Typed parsing of the arguments of the rpc requests and notifications that
+//! neovim sends. The arguments of an event are parsed as a tuple of types
+//! implementing [`FromValue`](crate::args::FromValue), which checks their
+//! number and types. Errors are reported as an
+//! [`ArgError`](crate::args::ArgError) that can be sent back to neovim.
+use std::fmt;
+
+use failure::Fail;
+use neovim_lib::{neovim_api::Buffer, Value};
+
+/// The ways the arguments of an event can be invalid. Indices start at 0.
+#[derive(Debug, PartialEq)]
+pub enum ArgError {
+  /// The number of arguments is wrong
+  Count { expected: usize, got: usize },
+  /// An argument does not have the expected type
+  Type {
+    index: usize,
+    expected: String,
+    got: String,
+  },
+  /// An argument has the right type, but an invalid value
+  Invalid { index: usize, message: String },
+}
+
+impl ArgError {
+  /// The name of the kind of the error, e.g. `"count"`
+  pub fn kind(&self) -> &'static str {
+    match self {
+      ArgError::Count { .. } => "count",
+      ArgError::Type { .. } => "type",
+      ArgError::Invalid { .. } => "invalid",
+    }
+  }
+
+  /// Pack up the error for a reply to the request `name`. Like the errors of
+  /// the neovim api, this is an array of the kind and the message, so neovim
+  /// shows the message to the user.
+  pub fn to_value(&self, name: &str) -> Value {
+    Value::from(vec![
+      Value::from(self.kind()),
+      Value::from(format!("Invalid arguments for {}: {}", name, self)),
+    ])
+  }
+}
+
+impl fmt::Display for ArgError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ArgError::Count { expected, got } => {
+        write!(f, "expected {} arguments, got {}", expected, got)
+      }
+      ArgError::Type {
+        index,
+        expected,
+        got,
+      } => write!(f, "argument {} should be {}, got {}", index, expected, got),
+      ArgError::Invalid { index, message } => {
+        write!(f, "argument {}: {}", index, message)
+      }
+    }
+  }
+}
+
+impl Fail for ArgError {}
+
+/// A type that can be parsed from a single argument
+pub trait FromValue: Sized {
+  /// The name of the type for error messages, e.g. `"an integer"`
+  fn name() -> String;
+
+  /// Parse `value`. On failure, the offending value is returned formatted
+  /// for an error message.
+  fn from_value(value: Value) -> Result<Self, String>;
+}
+
+impl FromValue for i64 {
+  fn name() -> String {
+    "an integer".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    value.as_i64().ok_or_else(|| value.to_string())
+  }
+}
+
+impl FromValue for u64 {
+  fn name() -> String {
+    "a nonnegative integer".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    value.as_u64().ok_or_else(|| value.to_string())
+  }
+}
+
+impl FromValue for bool {
+  fn name() -> String {
+    "a boolean".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    value.as_bool().ok_or_else(|| value.to_string())
+  }
+}
+
+impl FromValue for String {
+  fn name() -> String {
+    "a string".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    match value {
+      Value::String(s) => {
+        if s.is_str() {
+          Ok(s.into_str().unwrap_or_default())
+        } else {
+          Err(Value::String(s).to_string())
+        }
+      }
+      v => Err(v.to_string()),
+    }
+  }
+}
+
+/// Any value, e.g. the arguments of a request that are parsed later
+impl FromValue for Value {
+  fn name() -> String {
+    "any value".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    Ok(value)
+  }
+}
+
+/// This cannot fail, but if the value was not obtained from the rpc api, this
+/// will probably not be a valid buffer to send commands to.
+impl FromValue for Buffer {
+  fn name() -> String {
+    "a buffer".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    Ok(Buffer::new(value))
+  }
+}
+
+/// `nil` gives `None`
+impl<T: FromValue> FromValue for Option<T> {
+  fn name() -> String {
+    T::name()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    if value.is_nil() {
+      Ok(None)
+    } else {
+      T::from_value(value).map(Some)
+    }
+  }
+}
+
+/// The plural of a type name like `"a string"` or `"an array of strings"`
+fn plural(name: &str) -> String {
+  let name = name.trim_start_matches("an ").trim_start_matches("a ");
+
+  match name.find(" of ") {
+    Some(i) => format!("{}s{}", &name[..i], &name[i..]),
+    None => format!("{}s", name),
+  }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+  fn name() -> String {
+    format!("an array of {}", plural(&T::name()))
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    match value {
+      Value::Array(v) => v.into_iter().map(T::from_value).collect(),
+      v => Err(v.to_string()),
+    }
+  }
+}
+
+/// A map with string keys and values, e.g. a lua table like
+/// `{ card = "{keyword}" }`
+#[derive(Debug, Default, PartialEq)]
+pub struct StringMap(pub Vec<(String, String)>);
+
+impl FromValue for StringMap {
+  fn name() -> String {
+    "a map of strings".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    match value {
+      Value::Map(m) => m
+        .into_iter()
+        .map(|(k, v)| Ok((String::from_value(k)?, String::from_value(v)?)))
+        .collect::<Result<_, _>>()
+        .map(StringMap),
+      // An empty lua table is sent as an array
+      Value::Array(ref v) if v.is_empty() => Ok(StringMap::default()),
+      v => Err(v.to_string()),
+    }
+  }
+}
+
+/// Parse the argument `value` at position `index`
+pub fn parse_arg<T: FromValue>(
+  value: Value,
+  index: usize,
+) -> Result<T, ArgError> {
+  T::from_value(value).map_err(|got| ArgError::Type {
+    index,
+    expected: T::name(),
+    got,
+  })
+}
+
+/// A type that can be parsed from all arguments of an event
+pub trait FromValues: Sized {
+  /// Parse `args`, which needs to have the right number of elements
+  fn from_values(args: Vec<Value>) -> Result<Self, ArgError>;
+}
+
+impl FromValues for () {
+  fn from_values(args: Vec<Value>) -> Result<Self, ArgError> {
+    if args.is_empty() {
+      Ok(())
+    } else {
+      Err(ArgError::Count {
+        expected: 0,
+        got: args.len(),
+      })
+    }
+  }
+}
+
+macro_rules! tuple_from_values {
+  ($len:expr; $($t:ident $i:tt),+) => {
+    impl<$($t: FromValue),+> FromValues for ($($t,)+) {
+      fn from_values(args: Vec<Value>) -> Result<Self, ArgError> {
+        if args.len() != $len {
+          return Err(ArgError::Count {
+            expected: $len,
+            got: args.len(),
+          });
+        }
+
+        let mut args = args.into_iter();
+        Ok(($(parse_arg::<$t>(args.next().unwrap_or(Value::Nil), $i)?,)+))
+      }
+    }
+  };
+}
+
+tuple_from_values!(1; A 0);
+tuple_from_values!(2; A 0, B 1);
+tuple_from_values!(3; A 0, B 1, C 2);
+tuple_from_values!(4; A 0, B 1, C 2, D 3);
+tuple_from_values!(5; A 0, B 1, C 2, D 3, E 4);
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::Value;
+
+  use crate::args::{ArgError, FromValues, StringMap};
+
+  #[test]
+  fn parse_tuples() {
+    let args = vec![Value::from(3), Value::from("a"), Value::Nil];
+    let (i, s, o): (i64, String, Option<bool>) =
+      FromValues::from_values(args).unwrap();
+    assert_eq!((i, s.as_str(), o), (3, "a", None));
+
+    let args = vec![Value::from(vec![(Value::from("card"), Value::from("x"))])];
+    let (m,): (StringMap,) = FromValues::from_values(args).unwrap();
+    assert_eq!(m.0, vec![("card".to_string(), "x".to_string())]);
+
+    let args = vec![Value::from(vec![Value::from("a"), Value::from(1)])];
+    let res: Result<(Vec<String>,), _> = FromValues::from_values(args);
+    assert_eq!(
+      res.unwrap_err(),
+      ArgError::Type {
+        index: 0,
+        expected: "an array of strings".to_string(),
+        got: "1".to_string(),
+      }
+    );
+  }
+
+  #[test]
+  fn report_errors() {
+    let args = vec![Value::from(3)];
+    let res: Result<(i64, i64), _> = FromValues::from_values(args);
+    let err = res.unwrap_err();
+    assert_eq!(
+      err,
+      ArgError::Count {
+        expected: 2,
+        got: 1
+      }
+    );
+    assert_eq!(
+      err.to_value("CellHint"),
+      Value::from(vec![
+        Value::from("count"),
+        Value::from(
+          "Invalid arguments for CellHint: expected 2 arguments, got 1"
+        )
+      ])
+    );
+
+    let args = vec![Value::from(3), Value::from(true)];
+    let res: Result<(i64, String), _> = FromValues::from_values(args);
+    assert_eq!(
+      res.unwrap_err().to_string(),
+      "argument 1 should be a string, got true"
+    );
+
+    let res: Result<(), _> = FromValues::from_values(vec![Value::Nil]);
+    assert!(res.is_err());
+  }
+}
@@ -0,0 +1,263 @@
+//! Answering long-running requests in a background thread, so neovim does
+//! not block while e.g. the deck is compared to another file. Neovim sends
+//! them as a [`Background`](crate::event::Event::Background) notification
+//! along with an ID, and may cancel them by a
+//! [`Cancel`](crate::event::Event::Cancel) notification with that ID.
+//!
+//! The [`Requests`](crate::background::Requests) thread gets a
+//! [`Snapshot`](crate::bufdata::Snapshot) of the buffer taken when the request
+//! arrived, and sends the answer back as a
+//! [`Finished`](crate::event::Event::Finished) event. The main loop passes it
+//! on to neovim, unless the request got cancelled.
+use std::{
+  collections::HashMap,
+  fs,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+  },
+  thread,
+};
+
+use failure::{Error, ResultExt};
+use log::warn;
+use neovim_lib::{neovim_api::Buffer, Value};
+
+use crate::{
+  bufdata::{BufData, Snapshot},
+  diff,
+  event::Event,
+  index::Index,
+};
+
+/// A request to answer
+struct Job {
+  id: u64,
+  request: Event,
+  buf: Buffer,
+  snapshot: Snapshot,
+  index: Option<Arc<Index>>,
+  cancel: Arc<AtomicBool>,
+}
+
+/// A handle to the thread answering the requests, along with the cancel flags
+/// of the requests not finished yet. The thread exits when this is dropped.
+pub struct Requests {
+  jobs: mpsc::Sender<Job>,
+  running: HashMap<u64, Arc<AtomicBool>>,
+}
+
+impl Requests {
+  /// Spawn the thread. The answers are sent to `to_main`, in the order the
+  /// requests were submitted.
+  pub fn spawn(to_main: mpsc::Sender<Event>) -> Self {
+    let (jobs, from_main) = mpsc::channel::<Job>();
+
+    let _ = thread::spawn(move || {
+      for job in from_main {
+        let result = if job.cancel.load(Ordering::Relaxed) {
+          None
+        } else {
+          let bufdata = BufData::from_snapshot(&job.buf, job.snapshot);
+          let value = answer(&job.request, &bufdata, job.index.as_deref());
+
+          if job.cancel.load(Ordering::Relaxed) {
+            None
+          } else {
+            Some(value)
+          }
+        };
+
+        let finished = Event::Finished { id: job.id, result };
+        if to_main.send(finished).is_err() {
+          warn!("Event loop gone, stopping the background requests");
+          break;
+        }
+      }
+    });
+
+    Requests {
+      jobs,
+      running: HashMap::new(),
+    }
+  }
+
+  /// Answer `request` for the current state of `bufdata`, looking up
+  /// entities in `index`. The answer is sent with the given `id`.
+  pub fn submit(
+    &mut self,
+    id: u64,
+    request: Event,
+    bufdata: &BufData,
+    index: Option<Arc<Index>>,
+  ) -> Result<(), Error> {
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    self
+      .jobs
+      .send(Job {
+        id,
+        request,
+        buf: bufdata.buf.clone(),
+        snapshot: bufdata.snapshot(),
+        index,
+        cancel: Arc::clone(&cancel),
+      })
+      .map_err(|_| failure::err_msg("The background thread stopped!"))?;
+
+    if self.running.insert(id, cancel).is_some() {
+      warn!("Background request {} was submitted twice", id);
+    }
+
+    Ok(())
+  }
+
+  /// Cancel the request `id`. Returns false if it is not running.
+  pub fn cancel(&mut self, id: u64) -> bool {
+    match self.running.remove(&id) {
+      Some(cancel) => {
+        cancel.store(true, Ordering::Relaxed);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Forget about the request `id` after its answer arrived. Returns false if
+  /// it got cancelled in the meantime, so the answer is not needed anymore.
+  pub fn finish(&mut self, id: u64) -> bool {
+    self.running.remove(&id).is_some()
+  }
+}
+
+/// The answer to `request` for the buffer `bufdata`, looking up entities in
+/// the `index` of the include files. Only defined for the requests that
+/// [`run in the background`](crate::event::Event::runs_in_background), the main
+/// loop uses it to answer them directly as well.
+pub fn answer(
+  request: &Event,
+  bufdata: &BufData,
+  index: Option<&Index>,
+) -> Value {
+  match *request {
+    Event::KeywordSummary => Value::from(
+      bufdata
+        .keyword_summary()
+        .iter()
+        .map(Value::from)
+        .collect::<Vec<_>>(),
+    ),
+    Event::DiffDeck { ref path } => match diff_deck(bufdata, path) {
+      Ok(v) => v,
+      Err(e) => Value::from(format!("Could not diff the deck: {}", e)),
+    },
+    Event::Quickfix { ref path } => Value::from(
+      bufdata
+        .diagnostics(index)
+        .iter()
+        .map(|d| d.to_quickfix(path))
+        .collect::<Vec<_>>(),
+    ),
+    ref e => {
+      Value::from(format!("Cannot answer {} in the background", e.name()))
+    }
+  }
+}
+
+/// Compare the cards of the buffer to the ones of the file `path`, see
+/// [`DiffDeck`](crate::event::Event::DiffDeck)
+fn diff_deck(bufdata: &BufData, path: &str) -> Result<Value, Error> {
+  let content = fs::read(path)
+    .with_context(|e| format!("Could not read '{}': {}", path, e))?;
+  let mut reference = BufData::new(bufdata.buf);
+  reference.parse_slice(&content)?;
+
+  let diffs = diff::diff_cards(&bufdata.deck_cards(), &reference.deck_cards());
+
+  Ok(Value::from(
+    diffs.iter().map(Value::from).collect::<Vec<_>>(),
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc;
+
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::{
+    background::{answer, Requests},
+    bufdata::BufData,
+    event::Event,
+  };
+
+  const LINES: [&str; 4] = [
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        2              0.             0.5              0.",
+    "SHELL /     3129       1       1    2967    2971    2970",
+    "SHELL /     3130       1       1    2967    2971    2970",
+  ];
+
+  #[test]
+  fn answer_from_snapshot() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let copy = BufData::from_snapshot(&buf, bufdata.snapshot());
+    assert_eq!(copy.folds_to_vec(), bufdata.folds_to_vec());
+    assert_eq!(copy.deck_cards(), bufdata.deck_cards());
+
+    let quickfix = Event::Quickfix {
+      path: "deck.pc".to_string(),
+    };
+    assert_eq!(
+      answer(&quickfix, &copy, None),
+      answer(&quickfix, &bufdata, None)
+    );
+    assert_eq!(
+      answer(&Event::Stats, &bufdata, None),
+      Value::from("Cannot answer Stats in the background")
+    );
+  }
+
+  #[test]
+  fn background_requests() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut requests = Requests::spawn(tx);
+
+    requests
+      .submit(7, Event::KeywordSummary, &bufdata, None)
+      .unwrap();
+
+    match rx.recv().unwrap() {
+      Event::Finished { id, result } => {
+        assert_eq!(id, 7);
+        assert_eq!(
+          result,
+          Some(answer(&Event::KeywordSummary, &bufdata, None))
+        );
+      }
+      e => panic!("Not a Finished event: {:?}", e),
+    }
+    assert!(requests.finish(7));
+    assert!(!requests.cancel(7));
+
+    requests
+      .submit(8, Event::KeywordSummary, &bufdata, None)
+      .unwrap();
+    assert!(requests.cancel(8));
+
+    // The answer might have been computed before the cancellation, but it's
+    // not needed anymore
+    match rx.recv().unwrap() {
+      Event::Finished { id, .. } => assert_eq!(id, 8),
+      e => panic!("Not a Finished event: {:?}", e),
+    }
+    assert!(!requests.finish(8));
+  }
+}
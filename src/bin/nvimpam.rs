@@ -22,13 +22,81 @@
 //! which is quite a bit faster (mostly probably because the file is cached by
 //! your OS since it was loaded by neovim just before).
 //!
+//! When nvimpam gets restarted, the old process hands over its state to the
+//! Lua side, which passes it on to the new one by a `RestoreState`
+//! notification, see [`Restart`](nvimpam_lib::event::Event::Restart).
+//!
+//! For viewing huge decks, pass `--preview` before the filename. Nvimpam then
+//! sends the folds and highlights of the file once, but does not attach to
+//! the buffer, so it does not get any updates.
+//!
+//! To keep huge decks responsive, pass `--max-size <bytes>` before the
+//! filename. A buffer larger than that is degraded to the mode given by
+//! `--degraded <mode>`, which is one of `folds` (the default, no
+//! highlights), `level2` (only the level 2 folds, no highlights) or
+//! `viewport` (only the viewed lines are highlighted), see
+//! [`Degraded`](nvimpam_lib::bufdata::degraded::Degraded).
+//!
+//! Comment banners are folded if one of their lines matches a pattern given
+//! by `--banner <pattern>`, which can be passed several times. Without it,
+//! the banners of ERFH5 exports are folded, and `--no-banners` disables
+//! folding them, see [`banner`](nvimpam_lib::bufdata::banner).
+//!
+//! For testing, pass `--strict` before the filename. The data of the buffer
+//! is then checked for consistency after each update, and an inconsistency
+//! is reported as an error message, see
+//! [`set_strict`](nvimpam_lib::bufdata::BufData::set_strict).
+//!
+//! Additional card definitions can be loaded from a TOML file by passing
+//! `--carddefs <file>` before the filename, see
+//! [`card::custom`](nvimpam_lib::card::custom).
+//!
+//! What to do about tabs in the lines of the cards is set by `--tabs
+//! <policy>`, which is one of `expand` (expand them for the column math),
+//! `flag` (the default, report them as warnings) or `replace` (report them
+//! along with the spaces to replace them by), see
+//! [`TabPolicy`](nvimpam_lib::card::tabs::TabPolicy).
+//!
+//! To print a report about a deck without starting neovim, run
+//!
+//! ```text
+//! nvimpam stats [--json] [--carddefs <file>] [--tabs <policy>] <file>
+//! ```
+//!
+//! It lists the cards of each keyword, the tree of included files, the ranges
+//! of the IDs and the warnings about the deck, see
+//! [`report`](nvimpam_lib::report). With `--json`, it's printed as a JSON
+//! object instead of a table.
+//!
+//! To check the card definitions for consistency, run
+//!
+//! ```text
+//! nvimpam selfcheck [--carddefs <file>]
+//! ```
+//!
+//! It prints the problems found with the builtin cards and the ones defined
+//! in `<file>`, and exits with a non-zero status if there are any, see
+//! [`selfcheck`](nvimpam_lib::card::selfcheck).
+//!
 //! If you want logging, set the following environment variables:
 //!
 //! * `NVIMPAM_LOG_FILE` is the path to the log file (no logging if this is
 //!   empty)
 //! * `NVIMPAM_LOG_LEVEL` can be one of `error`, `warn`, `info`, `debug` and
 //!   `trace`, in ascending order of verbosity. The default is `warn`.
-use std::{env::args_os, sync::mpsc};
+//!
+//! If nvimpam panics, the message is written to a crash log in the temporary
+//! directory, and reported to neovim before exiting.
+use std::{
+  env::{self, args_os},
+  ffi::OsString,
+  fs,
+  panic::{self, AssertUnwindSafe},
+  path::{Path, PathBuf},
+  process,
+  sync::{mpsc, Arc, Mutex},
+  thread,
+};
 
 use failure::{Error, ResultExt};
 use log::error;
@@ -37,10 +105,220 @@ use neovim_lib::{
 };
 use simplelog::{Config, Level, LevelFilter, WriteLogger};
 
-use nvimpam_lib::{event::Event, handler::NeovimHandler};
+use nvimpam_lib::{
+  bufdata::{banner::Pattern, degraded::Degraded},
+  card::{
+    custom,
+    selfcheck::Selfcheck,
+    tabs::{self, TabPolicy},
+  },
+  event::{Event, Startup},
+  handler::NeovimHandler,
+  handshake,
+  report::Report,
+};
+
+const USAGE: &str = "Usage: nvimpam [--carddefs <file>] [--tabs <policy>] \
+                     [--preview] [--max-size <bytes>] [--degraded <mode>] \
+                     [--banner <pattern>]... [--no-banners] [--strict] \
+                     [<file>]
+       nvimpam stats [--json] [--carddefs <file>] [--tabs <policy>] <file>
+       nvimpam selfcheck [--carddefs <file>]";
+
+/// The arguments for connecting to neovim by stdin/stdout, see the
+/// [`event_loop`](nvimpam_lib::event::Event::event_loop)
+#[derive(Debug, PartialEq)]
+struct AttachArgs {
+  carddefs: Option<OsString>,
+  tab_policy: Option<TabPolicy>,
+  preview: bool,
+  max_size: Option<(usize, Degraded)>,
+  banners: Option<Vec<Pattern>>,
+  strict: bool,
+  file: Option<OsString>,
+}
+
+/// What nvimpam was asked to do by its arguments
+#[derive(Debug, PartialEq)]
+enum Command {
+  Attach(AttachArgs),
+  /// Print the [`Report`](nvimpam_lib::report::Report) about the deck `file`
+  Stats {
+    carddefs: Option<OsString>,
+    tab_policy: Option<TabPolicy>,
+    json: bool,
+    file: OsString,
+  },
+  /// Check the builtin card definitions and the ones in `carddefs`, see
+  /// [`Selfcheck`](nvimpam_lib::card::selfcheck::Selfcheck)
+  Selfcheck { carddefs: Option<OsString> },
+}
+
+/// Parse the arguments given to nvimpam, without the name of the binary
+fn parse_args<I>(args: I) -> Result<Command, String>
+where
+  I: IntoIterator<Item = OsString>,
+{
+  let mut args = args.into_iter().peekable();
+  let stats = args.peek().map_or(false, |a| a == "stats");
+  let selfcheck = args.peek().map_or(false, |a| a == "selfcheck");
+  if stats || selfcheck {
+    let _ = args.next();
+  }
+  let attach = !stats && !selfcheck;
+
+  let mut carddefs = None;
+  let mut tab_policy = None;
+  let mut preview = false;
+  let mut max_size = None;
+  let mut degraded = None;
+  let mut banners: Option<Vec<Pattern>> = None;
+  let mut strict = false;
+  let mut json = false;
+
+  loop {
+    match args.peek().and_then(|a| a.to_str()) {
+      Some("--carddefs") => carddefs = args.nth(1),
+      Some("--tabs") if !selfcheck => {
+        let policy = args.nth(1).unwrap_or_default();
+        match policy.to_str().and_then(TabPolicy::from_name) {
+          Some(p) => tab_policy = Some(p),
+          None => {
+            return Err(format!(
+              "Invalid tab policy '{}'",
+              policy.to_string_lossy()
+            ))
+          }
+        }
+      }
+      Some("--preview") if attach => {
+        preview = true;
+        let _ = args.next();
+      }
+      Some("--max-size") if attach => {
+        let size = args.nth(1).unwrap_or_default();
+        match size.to_str().and_then(|s| s.parse::<usize>().ok()) {
+          Some(s) => max_size = Some(s),
+          None => {
+            return Err(format!("Invalid size '{}'", size.to_string_lossy()))
+          }
+        }
+      }
+      Some("--degraded") if attach => {
+        let mode = args.nth(1).unwrap_or_default();
+        match mode.to_str().and_then(Degraded::from_name) {
+          Some(m) => degraded = Some(m),
+          None => {
+            return Err(format!("Invalid mode '{}'", mode.to_string_lossy()))
+          }
+        }
+      }
+      Some("--banner") if attach => {
+        let pattern = args.nth(1).unwrap_or_default();
+        match Pattern::parse(&pattern.to_string_lossy()) {
+          Ok(p) => banners.get_or_insert_with(Vec::new).push(p),
+          Err(e) => return Err(e.to_string()),
+        }
+      }
+      Some("--no-banners") if attach => {
+        banners = Some(vec![]);
+        let _ = args.next();
+      }
+      Some("--strict") if attach => {
+        strict = true;
+        let _ = args.next();
+      }
+      Some("--json") if stats => {
+        json = true;
+        let _ = args.next();
+      }
+      Some(a) if a.starts_with("--") => {
+        return Err(format!("Unknown option '{}'", a));
+      }
+      _ => break,
+    }
+  }
+  let file = args.next();
+
+  if let Some(a) = args.next() {
+    return Err(format!("Unexpected argument '{}'", a.to_string_lossy()));
+  }
+
+  if degraded.is_some() && max_size.is_none() {
+    return Err("--degraded needs --max-size".to_string());
+  }
+
+  if selfcheck {
+    return match file {
+      Some(a) => Err(format!("Unexpected argument '{}'", a.to_string_lossy())),
+      None => Ok(Command::Selfcheck { carddefs }),
+    };
+  }
+
+  match (stats, file) {
+    (false, file) => Ok(Command::Attach(AttachArgs {
+      carddefs,
+      tab_policy,
+      preview,
+      max_size: max_size.map(|s| (s, degraded.unwrap_or(Degraded::Folds))),
+      banners,
+      strict,
+      file,
+    })),
+    (true, Some(file)) => Ok(Command::Stats {
+      carddefs,
+      tab_policy,
+      json,
+      file,
+    }),
+    (true, None) => Err("No file given".to_string()),
+  }
+}
 
 fn main() {
-  use std::process;
+  let command = match parse_args(args_os().skip(1)) {
+    Ok(c) => c,
+    Err(e) => {
+      eprintln!("Nvimpam: {}\n{}", e, USAGE);
+      process::exit(2);
+    }
+  };
+
+  let args = match command {
+    Command::Attach(args) => args,
+    Command::Stats {
+      carddefs,
+      tab_policy,
+      json,
+      file,
+    } => {
+      if let Some(policy) = tab_policy {
+        tabs::set_policy(policy);
+      }
+      if let Err(e) = print_stats(carddefs, json, &file) {
+        eprintln!("Nvimpam: {}", e);
+        for cause in e.iter_chain().skip(1) {
+          eprintln!("Caused by: {}", cause)
+        }
+        process::exit(1);
+      }
+      return;
+    }
+    Command::Selfcheck { carddefs } => {
+      if let Some(path) = carddefs {
+        if let Err(e) = custom::load_file(&path.to_string_lossy()) {
+          eprintln!("Nvimpam: {}", e);
+          for cause in e.iter_chain().skip(1) {
+            eprintln!("Caused by: {}", cause)
+          }
+          process::exit(1);
+        }
+      }
+      let check = Selfcheck::run();
+      print!("{}", check);
+      process::exit(if check.problems.is_empty() { 0 } else { 1 });
+    }
+  };
 
   match init_logging() {
     Err(e) => {
@@ -54,7 +332,7 @@ fn main() {
     Ok(()) => {}
   }
 
-  match start_program() {
+  match start_program(args) {
     Ok(_) => process::exit(0),
     Err(e) => {
       error!("Nvimpam encountered an error: {}", e);
@@ -67,6 +345,27 @@ fn main() {
   };
 }
 
+/// Print the report about the deck `file` to stdout, as JSON if `json` is
+/// set. The card definitions in `carddefs` are loaded before.
+fn print_stats(
+  carddefs: Option<OsString>,
+  json: bool,
+  file: &OsString,
+) -> Result<(), Error> {
+  if let Some(path) = carddefs {
+    let _ = custom::load_file(&path.to_string_lossy())?;
+  }
+
+  let report = Report::read(Path::new(file))?;
+  if json {
+    println!("{}", report.to_json());
+  } else {
+    print!("{}", report);
+  }
+
+  Ok(())
+}
+
 fn send_err(nvim: &mut Neovim, err: &Error) {
   let luafn = "require('nvimpam').nvimpam_err(...)";
   let luaargs = Value::from(format!("Nvimpam ecountered an error: {:?}!", err));
@@ -79,11 +378,78 @@ fn send_err(nvim: &mut Neovim, err: &Error) {
   }
 }
 
+/// The file the message of a panic is written to
+fn crash_log() -> PathBuf {
+  env::temp_dir().join(format!("nvimpam-crash-{}.log", process::id()))
+}
+
+/// Report the panic with the message `message` to neovim
+fn send_panic(nvim: &mut Neovim, message: &str) {
+  let luafn = "require('nvimpam').on_panic(...)";
+  let luaargs = vec![
+    Value::from(message),
+    Value::from(crash_log().to_string_lossy().into_owned()),
+  ];
+
+  if let Err(e) = nvim.execute_lua(luafn, luaargs) {
+    error!(
+      "Could not send panic to neovim: '{:?}'.\n Panic was: '{}'",
+      e, message
+    );
+  }
+}
+
+/// Log panics and write them to the [`crash_log`](crash_log), and let the
+/// event loop know by sending a
+/// [`Panicked`](nvimpam_lib::event::Event::Panicked) event to `to_main`. The
+/// message of the first panic is put into the returned `Mutex`, so it can be
+/// sent to neovim once the event loop stopped.
+fn set_panic_hook(to_main: mpsc::Sender<Event>) -> Arc<Mutex<Option<String>>> {
+  let report = Arc::new(Mutex::new(None));
+  let hook_report = Arc::clone(&report);
+  let to_main = Mutex::new(to_main);
+
+  panic::set_hook(Box::new(move |info| {
+    let payload = info.payload();
+    let msg = match payload.downcast_ref::<&str>() {
+      Some(s) => *s,
+      None => payload
+        .downcast_ref::<String>()
+        .map_or("Box<Any>", |s| &s[..]),
+    };
+    let location = info.location().map_or_else(String::new, |l| {
+      format!(" at {}:{}:{}", l.file(), l.line(), l.column())
+    });
+    let message = format!(
+      "Thread '{}' panicked{}: {}",
+      thread::current().name().unwrap_or("<unnamed>"),
+      location,
+      msg
+    );
+
+    error!("{}", message);
+    let log = format!("nvimpam {}\n{}\n", env!("CARGO_PKG_VERSION"), message);
+    if let Err(e) = fs::write(crash_log(), log) {
+      error!("Could not write the crash log: {}", e);
+    }
+
+    if let Ok(mut report) = hook_report.lock() {
+      if report.is_none() {
+        *report = Some(message);
+      }
+    }
+
+    // A panic of the main thread ends the event loop anyways
+    if let Ok(to_main) = to_main.lock() {
+      let _ = to_main.send(Event::Panicked);
+    }
+  }));
+
+  report
+}
+
 fn init_logging() -> Result<(), Error> {
-  use std::{
-    env::{self, VarError},
-    fs::File,
-  };
+  use std::{env::VarError, fs::File};
 
   let filepath = match env::var_os("NVIMPAM_LOG_FILE") {
     Some(s) => s,
@@ -127,7 +493,9 @@ fn init_logging() -> Result<(), Error> {
   Ok(())
 }
 
-fn send_client_info(nvim: &mut Neovim) -> Result<(), Error> {
+/// Send the client info to neovim, and return the names of the requests
+/// nvimpam handles
+fn send_client_info(nvim: &mut Neovim) -> Result<Vec<String>, Error> {
   const VERSION_MAJOR: &str = env!("CARGO_PKG_VERSION_MAJOR");
   const VERSION_MINOR: &str = env!("CARGO_PKG_VERSION_MINOR");
   const VERSION_PATCH: &str = env!("CARGO_PKG_VERSION_PATCH");
@@ -154,6 +522,342 @@ fn send_client_info(nvim: &mut Neovim) -> Result<(), Error> {
       ]
       .into(),
     ),
+    (
+      "Restart".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ConvertCard".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "Renumber".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(5_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "RenameId".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "OffsetInclude".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(4_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "AlignCells".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CellHint".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "HighlightConfig".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(5_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "HighlightAge".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CoalesceWindow".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "FoldChunkSize".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CheckDuplicates".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "EnableHighlights".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "FoldTexts".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "FoldGroups".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "Stats".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "Health".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "KeywordSummary".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "Metadata".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "LintSections".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ExportFolds".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ImportFolds".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "SaveState".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "LoadState".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "RenameFold".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ExtractInclude".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ToggleComment".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "IndexIncludes".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "FindDefinition".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "EventTimings".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CardInfo".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "GesContents".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "FoldLevel".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "FoldsForRange".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "HighlightsForRange".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "SemanticTokens".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ExternalHighlights".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CellRange".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CardObject".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "CellNumber".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "DiffDeck".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "Quickfix".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
     (
       "HighlightRegion".into(),
       vec![
@@ -162,6 +866,62 @@ fn send_client_info(nvim: &mut Neovim) -> Result<(), Error> {
       ]
       .into(),
     ),
+    (
+      "HighlightViewport".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
+    (
+      "Viewport".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
+    (
+      "Background".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
+    (
+      "Cancel".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
+    (
+      "Written".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
+    (
+      "Inccommand".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
+    (
+      "RestoreState".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(true)]),
+      ]
+      .into(),
+    ),
   ];
 
   let attribs: Vec<(Value, Value)> = vec![
@@ -180,28 +940,81 @@ fn send_client_info(nvim: &mut Neovim) -> Result<(), Error> {
     ),
   ];
 
+  let requests = methods
+    .iter()
+    .filter_map(|(name, _)| name.as_str().map(str::to_string))
+    .collect();
+
   let typ = "remote";
   nvim.set_client_info(NAME, version, typ, methods, attribs)?;
 
-  Ok(())
+  Ok(requests)
 }
 
-fn start_program() -> Result<(), Error> {
+fn start_program(args: AttachArgs) -> Result<(), Error> {
+  let AttachArgs {
+    carddefs,
+    tab_policy,
+    preview,
+    max_size,
+    banners,
+    strict,
+    file,
+  } = args;
+
   let (handler_to_main, main_from_handler) = mpsc::channel();
   let (main_to_handler, handler_from_main) = mpsc::channel();
   let mut session = Session::new_parent()?;
+  let to_main = handler_to_main.clone();
 
   session.start_event_loop_handler(NeovimHandler {
     to_main: handler_to_main,
     from_main: handler_from_main,
   });
   let mut nvim = Neovim::new(session);
+  let panicked = set_panic_hook(to_main.clone());
+
+  let requests = send_client_info(&mut nvim)?;
+  // Out of sync Lua files are reported, but the program keeps going as long
+  // as it can
+  if let Err(e) = handshake::handshake(&mut nvim, &requests) {
+    send_err(&mut nvim, &e);
+  }
 
-  send_client_info(&mut nvim)?;
+  // Without the definitions, we can still work with the builtin cards
+  if let Some(path) = carddefs {
+    if let Err(e) = custom::load_file(&path.to_string_lossy()) {
+      send_err(&mut nvim, &e);
+    }
+  }
+  if let Some(policy) = tab_policy {
+    tabs::set_policy(policy);
+  }
+
+  let res = panic::catch_unwind(AssertUnwindSafe(|| {
+    Event::event_loop(
+      &main_from_handler,
+      &main_to_handler,
+      &to_main,
+      &mut nvim,
+      Startup {
+        file,
+        preview,
+        max_size,
+        banners,
+        strict,
+      },
+    )
+  }));
 
-  let file = args_os().nth(1);
+  let panic = panicked.lock().ok().and_then(|mut p| p.take());
+  if let Some(message) = panic {
+    send_panic(&mut nvim, &message);
+    return Err(failure::err_msg(message));
+  }
 
-  Event::event_loop(&main_from_handler, &main_to_handler, &mut nvim, file)
+  res
+    .unwrap_or_else(|_| Err(failure::err_msg("Nvimpam panicked!")))
     .map_err(|e| {
       send_err(&mut nvim, &e);
       e
@@ -0,0 +1,430 @@
+//! Folding the comment banners written by pre- and postprocessors, e.g.
+//!
+//! ```text
+//! $----------------------------------------
+//! $---- Part: Door inner ----
+//! $----------------------------------------
+//! ```
+//!
+//! A block of consecutive comment lines is folded if one of its lines matches
+//! a [`Pattern`](crate::bufdata::banner::Pattern), and the title captured by
+//! the pattern is the foldtext. Since the comments are not kept in the
+//! [`Lines`](crate::lines::Lines), all comment blocks are tracked here, along
+//! with the titles of their lines, so the blocks can be updated when lines
+//! are changed next to them.
+use std::collections::BTreeMap;
+
+use failure::{self, Error};
+use neovim_lib::Value;
+
+use crate::{bufdata::folds, linenr::LineNr};
+
+/// The patterns used if none are given when starting nvimpam, matching the
+/// banners of ERFH5 exports
+pub const DEFAULT_PATTERNS: &[&str] = &["$--* {title} --*"];
+
+/// A part of a [`Pattern`](crate::bufdata::banner::Pattern)
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  /// Literal bytes
+  Literal(Vec<u8>),
+  /// Any bytes, written as `*`
+  Any,
+  /// Any bytes captured as the title, written as `{title}`
+  Title,
+}
+
+/// A pattern for a line of a banner. `*` matches any characters and
+/// `{title}` matches the title, everything else is matched literally. The
+/// whole line needs to match, without trailing whitespace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern(Vec<Token>);
+
+impl Pattern {
+  /// Parse the pattern `s`, which needs to contain `{title}` exactly once
+  pub fn parse(s: &str) -> Result<Self, Error> {
+    if s.matches("{title}").count() != 1 {
+      return Err(failure::err_msg(format!(
+        "Banner pattern '{}' needs to contain {{title}} exactly once!",
+        s
+      )));
+    }
+
+    let mut tokens = vec![];
+    for (i, part) in s.split("{title}").enumerate() {
+      if i > 0 {
+        tokens.push(Token::Title);
+      }
+      for (j, lit) in part.split('*').enumerate() {
+        if j > 0 && tokens.last() != Some(&Token::Any) {
+          tokens.push(Token::Any);
+        }
+        if !lit.is_empty() {
+          tokens.push(Token::Literal(lit.as_bytes().to_vec()));
+        }
+      }
+    }
+
+    Ok(Pattern(tokens))
+  }
+
+  /// The title of `line` if it matches the pattern. The title is trimmed,
+  /// and an empty one does not count.
+  pub fn title(&self, line: &[u8]) -> Option<String> {
+    let end = line
+      .iter()
+      .rposition(|b| !b.is_ascii_whitespace())
+      .map_or(0, |i| i + 1);
+    let mut title = None;
+
+    if !matches(&self.0, &line[..end], &mut title) {
+      return None;
+    }
+
+    title
+      .map(|t| String::from_utf8_lossy(t).trim().to_string())
+      .filter(|t| !t.is_empty())
+  }
+}
+
+/// If `tokens` match all of `line`. The bytes matched by a
+/// [`Title`](crate::bufdata::banner::Token::Title) are put into `title`.
+/// Wildcards match as few bytes as possible.
+fn matches<'a>(
+  tokens: &[Token],
+  line: &'a [u8],
+  title: &mut Option<&'a [u8]>,
+) -> bool {
+  match tokens.split_first() {
+    None => line.is_empty(),
+    Some((Token::Literal(lit), rest)) => {
+      line.starts_with(lit) && matches(rest, &line[lit.len()..], title)
+    }
+    Some((token, rest)) => (0..=line.len()).any(|i| {
+      if matches(rest, &line[i..], title) {
+        if *token == Token::Title {
+          *title = Some(&line[..i]);
+        }
+        true
+      } else {
+        false
+      }
+    }),
+  }
+}
+
+/// If `line` is a comment
+fn is_comment(line: &[u8]) -> bool {
+  let first = line.first();
+  first == Some(&b'$') || first == Some(&b'#')
+}
+
+/// A comment block, end-inclusive, with the lines matching a pattern and
+/// their titles
+type Block = ([LineNr; 2], Vec<(LineNr, String)>);
+
+/// Comment blocks, see [`Block`](crate::bufdata::banner::Block)
+pub type Blocks = BTreeMap<[LineNr; 2], Vec<(LineNr, String)>>;
+
+/// The comment blocks of a buffer. The blocks don't touch.
+#[derive(Debug, Default)]
+pub struct Banners {
+  patterns: Vec<Pattern>,
+  blocks: Blocks,
+}
+
+impl Banners {
+  /// Banners matching the
+  /// [`DEFAULT_PATTERNS`](crate::bufdata::banner::DEFAULT_PATTERNS)
+  pub fn new() -> Self {
+    Banners {
+      patterns: DEFAULT_PATTERNS
+        .iter()
+        .filter_map(|p| Pattern::parse(p).ok())
+        .collect(),
+      blocks: BTreeMap::new(),
+    }
+  }
+
+  /// Replace the patterns. Without any, the comments are not looked at. Only
+  /// takes effect for the lines parsed afterwards.
+  pub fn set_patterns(&mut self, patterns: Vec<Pattern>) {
+    self.patterns = patterns;
+    if self.patterns.is_empty() {
+      self.blocks.clear();
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.blocks.clear();
+  }
+
+  /// The comment blocks of `lines`, which start at line `first`
+  fn scan<B, I>(&self, first: LineNr, lines: I) -> Vec<Block>
+  where
+    B: AsRef<[u8]>,
+    I: IntoIterator<Item = B>,
+  {
+    let mut blocks: Vec<Block> = vec![];
+
+    if self.patterns.is_empty() {
+      return blocks;
+    }
+
+    for (i, line) in lines.into_iter().enumerate() {
+      let line = line.as_ref();
+      if !is_comment(line) {
+        continue;
+      }
+
+      let nr = first + LineNr::from_usize(i);
+      let title = self.patterns.iter().find_map(|p| p.title(line));
+
+      match blocks.last_mut() {
+        Some((range, titles)) if range[1] + 1 == nr => {
+          range[1] = nr;
+          titles.extend(title.map(|t| (nr, t)));
+        }
+        _ => {
+          blocks.push(([nr, nr], title.map(|t| (nr, t)).into_iter().collect()))
+        }
+      }
+    }
+
+    blocks
+  }
+
+  /// Find the comment blocks of all `lines` of the buffer
+  pub fn parse<B, I>(&mut self, lines: I)
+  where
+    B: AsRef<[u8]>,
+    I: IntoIterator<Item = B>,
+  {
+    self.blocks = self.blocks(lines);
+  }
+
+  /// The comment blocks of all `lines` of the buffer, without keeping them.
+  /// They can be put in place by
+  /// [`set_blocks`](crate::bufdata::banner::Banners::set_blocks) later.
+  pub fn blocks<B, I>(&self, lines: I) -> Blocks
+  where
+    B: AsRef<[u8]>,
+    I: IntoIterator<Item = B>,
+  {
+    self
+      .scan(LineNr::from_usize(0), lines)
+      .into_iter()
+      .collect()
+  }
+
+  /// Replace the comment blocks by `blocks`
+  pub fn set_blocks(&mut self, blocks: Blocks) {
+    self.blocks = blocks;
+  }
+
+  /// Update the blocks after the lines `firstline..lastline` were replaced by
+  /// `linedata`, adding `added` lines. The parts of the blocks outside of
+  /// the replaced lines are kept, and joined with the comments of
+  /// `linedata` next to them.
+  pub fn splice(
+    &mut self,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+    linedata: &[String],
+  ) {
+    if self.patterns.is_empty() {
+      return;
+    }
+
+    let old = std::mem::take(&mut self.blocks);
+    let mut pieces = vec![];
+
+    for (range, titles) in old {
+      if range[1] + 1 < firstline {
+        let _ = self.blocks.insert(range, titles);
+      } else if lastline <= range[0] {
+        let titles = titles.into_iter().map(|(l, t)| (l + added, t)).collect();
+        pieces.push(([range[0] + added, range[1] + added], titles));
+      } else {
+        if range[0] < firstline {
+          let titles = titles
+            .iter()
+            .filter(|(l, _)| *l < firstline)
+            .cloned()
+            .collect();
+          pieces.push(([range[0], firstline.prev()], titles));
+        }
+        if lastline <= range[1] {
+          let titles = titles
+            .iter()
+            .filter(|(l, _)| lastline <= *l)
+            .map(|(l, t)| (*l + added, t.clone()))
+            .collect();
+          pieces.push(([lastline + added, range[1] + added], titles));
+        }
+      }
+    }
+
+    pieces.extend(self.scan(firstline, linedata));
+    pieces.sort_by_key(|(r, _)| r[0]);
+
+    // Blocks only touch around the changed lines
+    let mut joined: Vec<Block> = vec![];
+    for (range, titles) in pieces {
+      match joined.last_mut() {
+        Some((r, t)) if r[1] + 1 == range[0] => {
+          r[1] = range[1];
+          t.extend(titles);
+        }
+        _ => joined.push((range, titles)),
+      }
+    }
+
+    self.blocks.extend(joined);
+  }
+
+  /// The folds of the banners, i.e. the blocks of at least 2 lines with a
+  /// title. The title of the first matching line is the foldtext.
+  pub fn folds(&self) -> impl Iterator<Item = (&[LineNr; 2], &str)> {
+    self
+      .blocks
+      .iter()
+      .filter(|(r, _)| r[0] < r[1])
+      .filter_map(|(r, t)| t.first().map(|(_, t)| (r, t.as_str())))
+  }
+
+  /// If there's a banner fold with the range `range`
+  pub fn is_fold(&self, range: &[LineNr; 2]) -> bool {
+    range[0] < range[1] && self.blocks.get(range).map_or(0, Vec::len) > 0
+  }
+
+  /// Pack up the folds intersecting the lines `first..=last` like the other
+  /// folds. A text in `custom` overrides the title.
+  pub fn fold_calls(
+    &self,
+    first: LineNr,
+    last: LineNr,
+    custom: &BTreeMap<[LineNr; 2], String>,
+  ) -> Vec<Value> {
+    folds::intersecting(&self.blocks, first, last)
+      .filter(|(r, _)| r[0] < r[1])
+      .filter_map(|(r, t)| match custom.get(r) {
+        Some(c) => Some(folds::fold_call(r, c)),
+        None => t
+          .first()
+          .map(|(_, t)| folds::fold_call(r, &format!(" {} ", t))),
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::banner::{Banners, Pattern},
+    linenr::LineNr,
+  };
+
+  const LINES: [&str; 8] = [
+    "$----------------------------------------",
+    "$---- Part: Door inner ----",
+    "$----------------------------------------",
+    "NODE  /        1              0.             0.5",
+    "$---- Part: Door outer ----   ",
+    "$ some comment",
+    "NODE  /        2              0.             0.5",
+    "$---- Part: Roof ----",
+  ];
+
+  fn folds(banners: &Banners) -> Vec<(usize, usize, String)> {
+    banners
+      .folds()
+      .map(|(r, t)| (r[0].into(), r[1].into(), t.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn banner_patterns() {
+    let pattern = Pattern::parse("$--* {title} --*").unwrap();
+    assert_eq!(
+      pattern.title(b"$---- Part: Door inner ----  "),
+      Some("Part: Door inner".to_string())
+    );
+    assert_eq!(pattern.title(b"$-- x --"), Some("x".to_string()));
+    assert_eq!(pattern.title(b"$----------------"), None);
+    assert_eq!(pattern.title(b"$----  ----"), None);
+    assert_eq!(pattern.title(b"# ---- Part ----"), None);
+
+    let pattern = Pattern::parse("#*PART {title}").unwrap();
+    assert_eq!(pattern.title(b"#  PART 12 "), Some("12".to_string()));
+
+    assert!(Pattern::parse("$---- Part ----").is_err());
+    assert!(Pattern::parse("{title} {title}").is_err());
+  }
+
+  #[test]
+  fn banner_folds() {
+    let mut banners = Banners::new();
+    banners.parse(LINES.iter());
+
+    assert_eq!(
+      folds(&banners),
+      vec![
+        (0, 2, "Part: Door inner".to_string()),
+        (4, 5, "Part: Door outer".to_string()),
+      ]
+    );
+
+    banners.set_patterns(vec![]);
+    banners.parse(LINES.iter());
+    assert!(folds(&banners).is_empty());
+  }
+
+  #[test]
+  fn banner_splices() {
+    let mut banners = Banners::new();
+    banners.parse(LINES.iter());
+
+    // Deleting the node joins the blocks
+    banners.splice(LineNr::from_usize(3), LineNr::from_usize(4), -1, &[]);
+    assert_eq!(
+      folds(&banners),
+      vec![(0, 4, "Part: Door inner".to_string())]
+    );
+
+    // Inserting a comment after the last line makes a banner of it
+    banners.splice(
+      LineNr::from_usize(7),
+      LineNr::from_usize(7),
+      1,
+      &["$".to_string()],
+    );
+    assert_eq!(folds(&banners)[1], (6, 7, "Part: Roof".to_string()));
+
+    // Changing the title inside of a block
+    banners.splice(
+      LineNr::from_usize(1),
+      LineNr::from_usize(2),
+      0,
+      &["$---- Part: Hood ----".to_string()],
+    );
+    assert_eq!(folds(&banners)[0], (0, 4, "Part: Hood".to_string()));
+
+    // Splitting a block
+    banners.splice(
+      LineNr::from_usize(2),
+      LineNr::from_usize(2),
+      1,
+      &["NODE  /        3".to_string()],
+    );
+    assert_eq!(
+      folds(&banners),
+      vec![
+        (0, 1, "Part: Hood".to_string()),
+        (3, 5, "Part: Door outer".to_string()),
+        (7, 8, "Part: Roof".to_string()),
+      ]
+    );
+  }
+}
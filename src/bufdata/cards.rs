@@ -0,0 +1,109 @@
+//! A lazy iterator over the cards of a buffer, see
+//! [`BufData::cards`](crate::bufdata::BufData::cards). The cards are found by
+//! walking the level 1 folds, and splitting them at the keyword lines inside,
+//! since a fold can hold several cards, e.g. a block of `SHELL`s.
+use std::iter::Peekable;
+
+use crate::{
+  card::{keyword::Keyword, line},
+  linenr::LineNr,
+  lines::{self, LineRef},
+};
+
+/// A card of a buffer
+#[derive(Debug, PartialEq, Clone)]
+pub struct CardRef {
+  pub keyword: Keyword,
+  /// The end-inclusive range of the card, from its keyword line to the line
+  /// before the next card in the same level 1 fold, comments included
+  pub range: [LineNr; 2],
+  /// The title of the first `NAME` line of the card, if it has one
+  pub title: Option<String>,
+}
+
+/// The iterator returned by [`BufData::cards`](crate::bufdata::BufData::cards)
+pub struct Cards<'a, I>
+where
+  I: Iterator<Item = [LineNr; 2]>,
+{
+  lines: Peekable<lines::Iter<'a>>,
+  folds: I,
+  fold: Option<[LineNr; 2]>,
+}
+
+impl<'a, I> Cards<'a, I>
+where
+  I: Iterator<Item = [LineNr; 2]>,
+{
+  /// The cards in `folds`, which need to be ordered and must not overlap,
+  /// made up of `lines`
+  pub(super) fn new(lines: lines::Iter<'a>, folds: I) -> Self {
+    Cards {
+      lines: lines.peekable(),
+      folds,
+      fold: None,
+    }
+  }
+
+  /// The next keyword line inside of the current fold. Lines before it are
+  /// skipped.
+  fn next_keyword_line(&mut self) -> Option<LineRef<'a>> {
+    let fold = self.fold?;
+
+    while let Some(l) = self.lines.peek() {
+      if l.number > fold[1] {
+        break;
+      }
+      let l = self.lines.next()?;
+      if l.number >= fold[0] && l.keyword.is_some() {
+        return Some(l);
+      }
+    }
+
+    None
+  }
+}
+
+impl<'a, I> Iterator for Cards<'a, I>
+where
+  I: Iterator<Item = [LineNr; 2]>,
+{
+  type Item = CardRef;
+
+  fn next(&mut self) -> Option<CardRef> {
+    let start = loop {
+      if self.fold.is_none() {
+        self.fold = Some(self.folds.next()?);
+      }
+
+      match self.next_keyword_line() {
+        Some(l) => break l,
+        None => self.fold = None,
+      }
+    };
+    let fold = self.fold?;
+
+    let mut end = fold[1];
+    let mut title = None;
+
+    while let Some(l) = self.lines.peek() {
+      if l.number > fold[1] {
+        break;
+      }
+      if l.keyword.is_some() {
+        end = l.number.prev();
+        break;
+      }
+      if title.is_none() && l.text.as_ref().starts_with(b"NAME") {
+        title = Some(line::name_title(l.text.as_ref()));
+      }
+      let _ = self.lines.next();
+    }
+
+    Some(CardRef {
+      keyword: start.keyword?,
+      range: [start.number, end],
+      title,
+    })
+  }
+}
@@ -0,0 +1,93 @@
+//! Commenting out whole cards, and uncommenting them again. A card is
+//! commented by prefixing all of its lines with `$`, including the comments
+//! inside of it, so uncommenting is done by removing the first character of
+//! each line.
+use failure::Error;
+use neovim_lib::neovim_api::Buffer;
+
+use crate::{bufdata::BufData, linenr::LineNr};
+
+/// Comment out `lines`
+pub fn comment(lines: &[String]) -> Vec<String> {
+  lines.iter().map(|l| format!("${}", l)).collect()
+}
+
+/// Uncomment the cards in the lines `first..=last` of `block`, which consists
+/// of comments only. The cards are found by parsing the uncommented block, so
+/// comments before or after them are left alone. If `first` or `last` is not
+/// inside of a commented card, the range ends at that line.
+///
+/// Returns the range of lines in `block` that got uncommented (end-inclusive),
+/// and their new text.
+pub fn uncomment(
+  buf: &Buffer,
+  block: &[String],
+  first: usize,
+  last: usize,
+) -> Result<([usize; 2], Vec<String>), Error> {
+  let uncommented: Vec<&str> = block.iter().map(|l| &l[1..]).collect();
+  let mut bufdata = BufData::new(buf);
+  bufdata.parse_strs(&uncommented)?;
+
+  let start = bufdata
+    .card_range(LineNr::from_usize(first))
+    .map_or(first, |r| r[0].into());
+  let end = bufdata
+    .card_range(LineNr::from_usize(last))
+    .map_or(last, |r| r[1].into());
+
+  let lines = uncommented[start..=end]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+  Ok(([start, end], lines))
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::bufdata::comment::{comment, uncomment};
+
+  const LINES: [&str; 7] = [
+    "$ Some deck",
+    "NODE  /        1              0.             0.5              0.",
+    "$#         IDNOD               X               Y               Z",
+    "NODE  /        2              0.             0.5              0.",
+    "SHELL /     3129       1       1    2967    2971    2970",
+    "$ End of the deck",
+    "ENDDATA",
+  ];
+
+  fn strings(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|l| l.to_string()).collect()
+  }
+
+  #[test]
+  fn toggle_comments() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let block = comment(&strings(&LINES[..6]));
+
+    assert_eq!(
+      block[2],
+      "$$#         IDNOD               X               Y               Z"
+    );
+
+    let (range, lines) = uncomment(&buf, &block, 3, 3).unwrap();
+    assert_eq!(range, [3, 3]);
+    assert_eq!(lines, strings(&LINES[3..4]));
+
+    let (range, lines) = uncomment(&buf, &block, 1, 4).unwrap();
+    assert_eq!(range, [1, 4]);
+    assert_eq!(lines, strings(&LINES[1..5]));
+
+    let (range, lines) = uncomment(&buf, &block, 2, 2).unwrap();
+    assert_eq!(range, [1, 2]);
+    assert_eq!(lines, strings(&LINES[1..3]));
+
+    let (range, lines) = uncomment(&buf, &block, 0, 0).unwrap();
+    assert_eq!(range, [0, 0]);
+    assert_eq!(lines, strings(&LINES[..1]));
+  }
+}
@@ -0,0 +1,49 @@
+//! The degraded modes of a buffer too large to be handled as a whole. Above a
+//! size set when starting nvimpam, some of the features are dropped to keep
+//! huge decks responsive, see
+//! [`set_max_size`](crate::bufdata::BufData::set_max_size).
+use std::fmt;
+
+/// The features kept in a degraded buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Degraded {
+  /// Only the folds are sent, no highlights are generated
+  Folds,
+  /// Only the level 2 folds are sent, no highlights are generated
+  Level2,
+  /// Only the lines viewed are highlighted, nothing is highlighted when idle
+  Viewport,
+}
+
+impl Degraded {
+  /// The mode with the name `name`, e.g. `"folds"`
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "folds" => Some(Degraded::Folds),
+      "level2" => Some(Degraded::Level2),
+      "viewport" => Some(Degraded::Viewport),
+      _ => None,
+    }
+  }
+
+  /// The name of the mode, the inverse of
+  /// [`from_name`](crate::bufdata::degraded::Degraded::from_name)
+  pub fn name(self) -> &'static str {
+    match self {
+      Degraded::Folds => "folds",
+      Degraded::Level2 => "level2",
+      Degraded::Viewport => "viewport",
+    }
+  }
+
+  /// If the highlights are generated in this mode
+  pub fn highlights(self) -> bool {
+    self == Degraded::Viewport
+  }
+}
+
+impl fmt::Display for Degraded {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
@@ -0,0 +1,114 @@
+//! User defined rules to group the level 1 folds of several keywords into a
+//! single level 2 fold, e.g. all surface elements. A rule is given by its
+//! name and the keywords of the group, like `["Surface elements", "SHELL",
+//! "MEMBR"]`. Keywords not in any rule are grouped with the adjacent folds of
+//! the same keyword.
+use crate::card::keyword::Keyword;
+
+/// A named group of keywords
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldGroup {
+  pub name: String,
+  pub keywords: Vec<Keyword>,
+}
+
+impl FoldGroup {
+  /// Parse a group from its configuration, e.g. `["Surface elements",
+  /// "SHELL", "MEMBR"]`. The keywords are matched case insensitively.
+  /// Returns `None` if the name is empty, or there are no or unknown
+  /// keywords.
+  pub fn from_strs<S: AsRef<str>>(strs: &[S]) -> Option<Self> {
+    let (name, keywords) = strs.split_first()?;
+
+    if name.as_ref().is_empty() || keywords.is_empty() {
+      return None;
+    }
+
+    let keywords = keywords
+      .iter()
+      .map(|k| Keyword::from_name(k.as_ref()))
+      .collect::<Option<Vec<_>>>()?;
+
+    Some(FoldGroup {
+      name: name.as_ref().to_string(),
+      keywords,
+    })
+  }
+}
+
+/// What makes adjacent level 1 folds fall into the same level 2 fold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupKey {
+  /// The folds have the keyword, which is not in a rule
+  Keyword(Keyword),
+  /// The keywords of the folds are in the rule with the index
+  Rule(usize),
+}
+
+/// The user provided rules for grouping the folds. If a keyword is listed by
+/// several rules, the first one counts.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FoldGroups {
+  pub groups: Vec<FoldGroup>,
+}
+
+impl FoldGroups {
+  /// The [`GroupKey`](crate::bufdata::foldgroup::GroupKey) of a fold of
+  /// keyword `kw`
+  pub fn key(&self, kw: Keyword) -> GroupKey {
+    self
+      .groups
+      .iter()
+      .position(|g| g.keywords.contains(&kw))
+      .map_or(GroupKey::Keyword(kw), GroupKey::Rule)
+  }
+
+  /// The name of the group `kw` belongs to, if it's in one
+  pub fn name(&self, kw: Keyword) -> Option<&str> {
+    match self.key(kw) {
+      GroupKey::Rule(i) => Some(&self.groups[i].name),
+      GroupKey::Keyword(_) => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::foldgroup::{FoldGroup, FoldGroups, GroupKey},
+    card::keyword::Keyword::*,
+  };
+
+  #[test]
+  fn parse_groups() {
+    let group = FoldGroup::from_strs(&["Surface elements", "SHELL", "membr"]);
+    assert_eq!(
+      group,
+      Some(FoldGroup {
+        name: "Surface elements".to_string(),
+        keywords: vec![Shell, Membr],
+      })
+    );
+
+    assert_eq!(FoldGroup::from_strs(&["Nothing"]), None);
+    assert_eq!(FoldGroup::from_strs(&["", "SHELL"]), None);
+    assert_eq!(FoldGroup::from_strs(&["Unknown", "SHELL", "NOCARD"]), None);
+  }
+
+  #[test]
+  fn group_keys() {
+    let groups = FoldGroups {
+      groups: vec![
+        FoldGroup::from_strs(&["Surface elements", "SHELL", "MEMBR"]).unwrap(),
+        FoldGroup::from_strs(&["Shells", "SHELL", "TSHEL"]).unwrap(),
+      ],
+    };
+
+    assert_eq!(groups.key(Membr), GroupKey::Rule(0));
+    assert_eq!(groups.key(Shell), GroupKey::Rule(0));
+    assert_eq!(groups.key(Tshel), GroupKey::Rule(1));
+    assert_eq!(groups.key(Node), GroupKey::Keyword(Node));
+    assert_eq!(groups.name(Tshel), Some("Shells"));
+    assert_eq!(groups.name(Node), None);
+  }
+}
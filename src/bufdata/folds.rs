@@ -1,28 +1,126 @@
 //! Holds the `Folds` datastructure for the fold data associated with a buffer
-use std::collections::{btree_map::Entry, BTreeMap};
+use std::{
+  cmp,
+  collections::{btree_map::Entry, BTreeMap},
+};
 
 use failure::Error;
 use itertools::Itertools;
+use log::warn;
 use neovim_lib::Value;
 
-use crate::{card::keyword::Keyword, linenr::LineNr};
+use crate::{
+  bufdata::foldgroup::{FoldGroups, GroupKey},
+  card::{keyword::Keyword, Card},
+  linenr::LineNr,
+};
 
 /// Folds are saved as the **end-inclusive** interval [start, end] of line
 /// numbers, the corresponding [`Keyword`](::card::keyword::Keyword) and a
 /// `String` for usage in nvims foldtext.
 ///
+/// The folds never overlap, a fold overlapping others when inserted is
+/// repaired, see [`checked_insert`](::bufdata::folds::Folds::checked_insert).
+/// The repairs are recorded until they're taken by
+/// [`take_repairs`](::bufdata::folds::Folds::take_repairs). The last field
+/// counts the folds of [container](crate::card::Card::closed_by) cards, so
+/// updates don't need to look for them.
+///
 /// TODO(KillTheMule): Check out other data structures for this, especially wrt
 /// usage in [`splice`](::bufdata::folds::Folds::splice)
-#[derive(Default, Debug)]
-pub(super) struct Folds(BTreeMap<[LineNr; 2], (Keyword, String)>);
+#[derive(Default, Debug, Clone)]
+pub(super) struct Folds(
+  BTreeMap<[LineNr; 2], (Keyword, String)>,
+  Vec<String>,
+  usize,
+);
+
+/// If `kw` is a [container](crate::card::Card::closed_by) card
+fn is_container(kw: Keyword) -> bool {
+  <&Card>::from(&kw).closed_by().is_some()
+}
+
+/// If `kw` is the keyword of a card closing a
+/// [container](crate::card::Card::closed_by) card
+fn is_closing(kw: Keyword) -> bool {
+  Keyword::BUILTIN
+    .iter()
+    .any(|c| <&Card>::from(c).closed_by() == Some(kw))
+}
 
 impl Folds {
   pub(super) fn new() -> Self {
-    Self(BTreeMap::new())
+    Self(BTreeMap::new(), vec![], 0)
   }
 
   pub(super) fn clear(&mut self) {
-    self.0.clear()
+    self.0.clear();
+    self.1.clear();
+    self.2 = 0;
+  }
+
+  /// Insert the fold `range` unless it's already there. Returns if it was
+  /// inserted. All insertions go through here to keep count of the container
+  /// folds.
+  fn put(&mut self, range: [LineNr; 2], value: (Keyword, String)) -> bool {
+    match self.0.entry(range) {
+      Entry::Occupied(_) => false,
+      Entry::Vacant(entry) => {
+        if is_container(value.0) {
+          self.2 += 1;
+        }
+        let _ = entry.insert(value);
+        true
+      }
+    }
+  }
+
+  /// Remove the fold `range`. Like
+  /// [`put`](::bufdata::folds::Folds::put), this keeps count of the container
+  /// folds.
+  fn take(&mut self, range: &[LineNr; 2]) -> Option<(Keyword, String)> {
+    let value = self.0.remove(range)?;
+    if is_container(value.0) {
+      self.2 -= 1;
+    }
+    Some(value)
+  }
+
+  /// Move the folds starting at or after `from` by `added` lines. A moved
+  /// fold is dropped if there's one with its new range already. All moved
+  /// folds are taken out and inserted again, so this takes time linear in
+  /// their number.
+  fn shift_from(&mut self, from: &[LineNr; 2], added: isize) {
+    if added == 0 {
+      return;
+    }
+
+    let to_move = self.0.split_off(from);
+    self.2 -= to_move.values().filter(|(kw, _)| is_container(*kw)).count();
+    for (r, v) in to_move {
+      let _ = self.put([r[0] + added, r[1] + added], v);
+    }
+  }
+
+  /// If there are folds of [container](crate::card::Card::closed_by) cards
+  pub(super) fn has_containers(&self) -> bool {
+    self.2 > 0
+  }
+
+  /// If one of the folds intersecting `firstline..lastline` is a
+  /// [container](crate::card::Card::closed_by) card or a card closing one.
+  /// Replacing those changes the level 2 folds after them, see
+  /// [`splice_level2`](crate::bufdata::folds::Folds::splice_level2).
+  pub(super) fn nests(&self, firstline: LineNr, lastline: LineNr) -> bool {
+    self
+      .intersecting(firstline, lastline)
+      .any(|(_, &(kw, _))| is_container(kw) || is_closing(kw))
+  }
+
+  /// Take the messages describing the overlapping folds repaired since the
+  /// last call
+  pub(super) fn take_repairs(&mut self) -> Vec<String> {
+    std::mem::take(&mut self.1)
   }
 
   pub(super) fn iter(
@@ -35,6 +133,10 @@ impl Folds {
     self.0.len()
   }
 
+  pub(super) fn get(&self, range: &[LineNr; 2]) -> Option<&(Keyword, String)> {
+    self.0.get(range)
+  }
+
   /// Insert a fold `([start, end], (Keyword, String))`.  Returns an error if
   /// that fold is already in the list.
   fn insert(
@@ -43,22 +145,26 @@ impl Folds {
     end: LineNr,
     kw: Keyword,
   ) -> Result<(), Error> {
-    match self.0.entry([start, end]) {
-      Entry::Occupied(_) => {
-        return Err(failure::err_msg("Fold already in foldlist!"));
-      }
-      Entry::Vacant(entry) => {
-        // TODO: Maybe use a &'static str without #lines for cards with ownfold
-        // = true?
-        entry.insert((kw, format!(" {} lines: {:?} ", end - start + 1, kw)));
-      }
+    if self.0.contains_key(&[start, end]) {
+      return Err(failure::err_msg("Fold already in foldlist!"));
     }
+
+    // TODO: Maybe use a &'static str without #lines for cards with ownfold
+    // = true?
+    let text = match kw {
+      Keyword::Encrypted => {
+        format!(" {} lines: encrypted block ", end - start + 1)
+      }
+      _ => format!(" {} lines: {:?} ", end - start + 1, kw),
+    };
+    let _ = self.put([start, end], (kw, text));
     Ok(())
   }
 
   /// Insert fold `([start, end], (Keyword, String))`. If `end < start`, we
   /// return an Error.  Otherwise, we call the internal insert function that
-  /// returns an error if the fold is already in the list.
+  /// returns an error if the fold is already in the list. Folds it overlaps
+  /// are [`repair`](::bufdata::folds::Folds::repair)ed first.
   pub(super) fn checked_insert(
     &mut self,
     start: LineNr,
@@ -66,12 +172,97 @@ impl Folds {
     kw: Keyword,
   ) -> Result<(), Error> {
     if start <= end {
+      let [start, end] = self.repair(start, end, kw);
       self.insert(start, end, kw)
     } else {
       Err(failure::err_msg("Need start <= end to insert a fold!"))
     }
   }
 
+  /// Make room for the fold `[start, end]` of `kw` among the folds it
+  /// overlaps: Those of the same keyword are merged into it, the others are
+  /// cut down to their lines outside of it. Returns the range of the fold
+  /// after merging. Each repair is logged and recorded for
+  /// [`take_repairs`](::bufdata::folds::Folds::take_repairs).
+  fn repair(
+    &mut self,
+    mut start: LineNr,
+    mut end: LineNr,
+    kw: Keyword,
+  ) -> [LineNr; 2] {
+    loop {
+      let overlapped: Vec<_> = self
+        .0
+        .range(..[end + 1, LineNr::from_usize(0)])
+        .rev()
+        .take_while(|(r, _)| start <= r[1])
+        .filter(|(r, _)| **r != [start, end])
+        .map(|(r, (k, _))| (*r, *k))
+        .collect();
+
+      if overlapped.is_empty() {
+        return [start, end];
+      }
+
+      for (r, k) in overlapped {
+        let _ = self.take(&r);
+        let fold = [start, end];
+
+        let action = if k == kw {
+          start = cmp::min(start, r[0]);
+          end = cmp::max(end, r[1]);
+          "merged them"
+        } else {
+          if r[0] < start {
+            let _ = self.insert(r[0], start.prev(), k);
+          }
+          if end < r[1] {
+            let _ = self.insert(end + 1, r[1], k);
+          }
+          "cut the latter"
+        };
+
+        let msg = format!(
+          "Fold {}-{} of {:?} overlaps fold {}-{} of {:?}, {}",
+          fold[0], fold[1], kw, r[0], r[1], k, action
+        );
+        warn!("{}", msg);
+        self.1.push(msg);
+      }
+    }
+  }
+
+  /// Repair the folds overlapping others from the last one starting before
+  /// `first` up to the first one starting after `last`. The fold starting
+  /// later is kept, see [`repair`](::bufdata::folds::Folds::repair).
+  fn repair_overlapping(&mut self, first: LineNr, last: LineNr) {
+    while let Some(r) = self.overlapping(first, last) {
+      if let Some((kw, _)) = self.take(&r) {
+        let [start, end] = self.repair(r[0], r[1], kw);
+        let _ = self.insert(start, end, kw);
+      }
+    }
+  }
+
+  /// Insert the fold of a single card with the title `title`, see
+  /// [`take_title`](crate::linesiter::LinesIter::take_title). The title is
+  /// part of its foldtext, e.g. ` PartShell 37: bumper_outer (52 lines) `.
+  pub(super) fn checked_insert_titled(
+    &mut self,
+    start: LineNr,
+    end: LineNr,
+    kw: Keyword,
+    title: &str,
+  ) -> Result<(), Error> {
+    self.checked_insert(start, end, kw)?;
+
+    if let Some(v) = self.0.get_mut(&[start, end]) {
+      v.1 = format!(" {:?} {} ({} lines) ", kw, title, end - start + 1);
+    }
+
+    Ok(())
+  }
+
   // Copy the elements of a FoldList into a Vec, containing
   // the tuples (start, end, Keyword). Only needed for tests.
   #[cfg(test)]
@@ -83,20 +274,195 @@ impl Folds {
       .collect()
   }
 
-  /// Recreate level 2 folds from level 1 folds. If there's no or one
-  /// level 1 fold, `Ok(())` is returned.
-  pub(super) fn recreate_level2(&mut self, folds: &Self) -> Result<(), Error> {
+  /// Recreate level 2 folds from level 1 folds, grouped according to
+  /// `groups`. If there's no or one level 1 fold, `Ok(())` is returned.
+  pub(super) fn recreate_level2(
+    &mut self,
+    folds: &Self,
+    groups: &FoldGroups,
+  ) -> Result<(), Error> {
     self.0.clear();
+    self.2 = 0;
 
     if folds.len() < 2 {
       return Ok(());
     }
 
-    let grouped = folds.iter().group_by(|(_, &(kw, _))| kw);
+    self.insert_level2(folds.iter(), groups)
+  }
+
+  /// Update the level 2 folds after the level 1 folds `folds` got
+  /// [`splice`](crate::bufdata::folds::Folds::splice)d with the same
+  /// `firstline`, `lastline` and `added`. Only the groups of level 1 folds
+  /// that intersect the changed lines are recreated, along with the groups
+  /// right before and after them, which the changed folds might have joined.
+  /// The level 2 folds after those are moved by `added` lines, which takes
+  /// time linear in their number, see
+  /// [`shift_from`](crate::bufdata::folds::Folds::shift_from).
+  ///
+  /// If `nesting` is true, i.e. a
+  /// [container](crate::card::Card::closed_by) card or a card closing one
+  /// was added or removed, see [`nests`](crate::bufdata::folds::Folds::nests),
+  /// all level 2 folds are recreated, since that might change which cards are
+  /// nested up to the end of the buffer.
+  pub(super) fn splice_level2(
+    &mut self,
+    folds: &Self,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+    nesting: bool,
+    groups: &FoldGroups,
+  ) -> Result<(), Error> {
+    if nesting && (folds.has_containers() || self.has_containers()) {
+      return self.recreate_level2(folds, groups);
+    }
+
+    let newlast = lastline.saturating_add(added);
+
+    // The last fold before the changed lines. Level 1 folds before
+    // `firstline` keep their start when splicing.
+    let before = folds
+      .0
+      .range(..[firstline, LineNr::from_usize(0)])
+      .next_back()
+      .map(|(r, _)| r[0]);
+    // The start of its group
+    let lo = before.map_or(LineNr::from_usize(0), |start| {
+      self.containing(start).map_or(start, |l2| l2[0])
+    });
+
+    // The end of the group of the first fold after the changed lines (in the
+    // old line numbers), or `None` if all groups until the end are affected.
+    // Only the folds from the one before the changed lines on are looked at.
+    let hi = folds
+      .0
+      .range([before.unwrap_or(lo), LineNr::from_usize(0)]..)
+      .find(|(r, _)| newlast <= r[1])
+      .map(|(r, _)| {
+        let end = r[1].saturating_add(-added);
+        self.containing(end).map_or(end, |l2| l2[1])
+      });
+
+    // Level 2 folds don't overlap, so the first one ending after `lo` starts
+    // at the latest with the one containing it
+    let from = self.first_before(lo).map_or(lo, |r| r[0]);
+    let affected: Vec<_> = self
+      .0
+      .range([from, LineNr::from_usize(0)]..)
+      .map(|(r, _)| *r)
+      .filter(|r| lo <= r[1])
+      .take_while(|r| hi.map_or(true, |h| r[0] <= h))
+      .collect();
+    for r in affected {
+      let _ = self.take(&r);
+    }
+
+    // Those start after `hi` now
+    self.shift_from(&[lo, LineNr::from_usize(0)], added);
+
+    let newhi = hi.map(|h| h.saturating_add(added));
+    self.insert_level2(
+      folds
+        .0
+        .range([lo, LineNr::from_usize(0)]..)
+        .take_while(|(r, _)| newhi.map_or(true, |h| r[1] <= h)),
+      groups,
+    )
+  }
+
+  /// The fold containing `line`. For level 2 folds, or level 1 folds that
+  /// don't overlap.
+  pub(super) fn containing(&self, line: LineNr) -> Option<[LineNr; 2]> {
+    self.first_before(line).filter(|r| line <= r[1])
+  }
+
+  /// The last fold starting before or at `line`. If several folds start on
+  /// the same line, the one ending last is returned.
+  pub(super) fn first_before(&self, line: LineNr) -> Option<[LineNr; 2]> {
+    self
+      .0
+      .range(..=[line, LineNr::MAX])
+      .next_back()
+      .map(|(r, _)| *r)
+  }
+
+  /// The first fold starting after or at `line`. If several folds start on
+  /// the same line, the one ending first is returned, so all folds from
+  /// there on can be split off the map.
+  pub(super) fn first_after(&self, line: LineNr) -> Option<[LineNr; 2]> {
+    self
+      .0
+      .range([line, LineNr::from_usize(0)]..)
+      .next()
+      .map(|(r, _)| *r)
+  }
+
+  /// Insert level 2 folds for the level 1 folds in `folds`. A
+  /// [container](crate::card::Card::closed_by) card starts a level 2 fold
+  /// holding the folds up to the card closing it. The other folds are grouped
+  /// by [`insert_groups`](crate::bufdata::folds::Folds::insert_groups).
+  fn insert_level2<'a, I>(
+    &mut self,
+    folds: I,
+    groups: &FoldGroups,
+  ) -> Result<(), Error>
+  where
+    I: Iterator<Item = (&'a [LineNr; 2], &'a (Keyword, String))>,
+  {
+    let folds: Vec<_> = folds.collect();
+    let mut ungrouped = 0;
+    let mut i = 0;
+
+    while i < folds.len() {
+      let kw = (folds[i].1).0;
+      let closing = <&Card>::from(&kw).closed_by().and_then(|closing| {
+        folds[i + 1..]
+          .iter()
+          .position(|(_, &(k, _))| k == closing)
+          .map(|p| i + 1 + p)
+      });
+
+      let j = match closing {
+        Some(j) => j,
+        None => {
+          i += 1;
+          continue;
+        }
+      };
+
+      self.insert_groups(folds[ungrouped..i].iter().cloned(), groups)?;
+
+      let range = [folds[i].0[0], folds[j].0[1]];
+      let text = format!(" {:?} with {} cards ", kw, j - i - 1);
+      if !self.put(range, (kw, text)) {
+        return Err(failure::err_msg("Fold already in foldlist_level2!"));
+      }
+
+      ungrouped = j + 1;
+      i = j + 1;
+    }
+
+    self.insert_groups(folds[ungrouped..].iter().cloned(), groups)
+  }
+
+  /// Insert level 2 folds for the groups of adjacent level 1 folds in
+  /// `folds` with the same [`GroupKey`](crate::bufdata::foldgroup::GroupKey).
+  /// The level 2 fold gets the keyword of its first level 1 fold.
+  fn insert_groups<'a, I>(
+    &mut self,
+    folds: I,
+    groups: &FoldGroups,
+  ) -> Result<(), Error>
+  where
+    I: Iterator<Item = (&'a [LineNr; 2], &'a (Keyword, String))>,
+  {
+    let grouped = folds.group_by(|(_, &(kw, _))| groups.key(kw));
 
-    for (kw, group) in &grouped {
+    for (key, group) in &grouped {
       let mut group = group.enumerate();
       let firstfold = group.next().expect("Empty group from group_by!").1;
+      let kw = (firstfold.1).0;
       let (nr, lastfold) = match group.last() {
         None => continue, // only 1 fold in group
         Some((i, e)) => (i, e),
@@ -108,14 +474,20 @@ impl Folds {
       // TODO(KillTheMule): This is sort of redundant wrt checked_insert, but we
       // want our own foldtext here.
       if firstline < lastline {
-        match self.0.entry([firstline, lastline]) {
-          Entry::Occupied(_) => {
-            return Err(failure::err_msg("Fold already in foldlist_level2!"));
+        if self.0.contains_key(&[firstline, lastline]) {
+          return Err(failure::err_msg("Fold already in foldlist_level2!"));
+        }
+
+        let text = match key {
+          GroupKey::Rule(i) => {
+            format!(" {} {} ", nr + 1, groups.groups[i].name)
           }
-          Entry::Vacant(entry) => {
-            entry.insert((kw, format!(" {} {:?}s ", nr + 1, kw)));
+          GroupKey::Keyword(Keyword::Encrypted) => {
+            format!(" {} encrypted blocks ", nr + 1)
           }
-        }
+          GroupKey::Keyword(_) => format!(" {} {:?}s ", nr + 1, kw),
+        };
+        let _ = self.put([firstline, lastline], (kw, text));
       }
     }
     Ok(())
@@ -133,6 +505,13 @@ impl Folds {
   /// Note: The major pain point here is fusing folds at the boundary. This will
   /// stay somewhat complicated no matter what, but the code might be
   /// complicated by our use of a `HashMap`.
+  ///
+  /// Returns the ranges of the folds around the changed lines whose foldtext
+  /// changed, e.g. because lines were added to them. Neovim moves and resizes
+  /// its folds along with the lines, so only those texts need to be sent
+  /// again. Folds overlapping others afterwards, e.g. because the new folds
+  /// did not cover whole cards, are repaired, see
+  /// [`repair`](::bufdata::folds::Folds::repair).
   /// TODO(KillTheMule): This needs to return a result, propagated from insert
   pub(super) fn splice(
     &mut self,
@@ -140,7 +519,11 @@ impl Folds {
     firstline: LineNr,
     lastline: LineNr,
     added: isize,
-  ) {
+  ) -> Vec<[LineNr; 2]> {
+    let old: Vec<_> = self
+      .intersecting(firstline, lastline)
+      .map(|(r, (_, t))| (*r, t.clone()))
+      .collect();
     let mut to_delete = vec![];
     let mut to_split = vec![];
     let mut last_before = None;
@@ -173,11 +556,11 @@ impl Folds {
     }
 
     for k in to_delete {
-      self.0.remove(&k);
+      let _ = self.take(&k);
     }
 
     for (k, v) in to_split {
-      self.0.remove(&k);
+      let _ = self.take(&k);
 
       if k[0] < firstline {
         let _ = self.checked_insert(k[0], firstline.prev(), v);
@@ -198,7 +581,7 @@ impl Folds {
     let _ = last_before.map(|(k1, v1)| {
       first_new.map(|(_, v2)| {
         if v1 == v2 {
-          self.0.remove(&k1);
+          let _ = self.take(&k1);
           merge_to_first = last_before;
         }
       })
@@ -216,41 +599,32 @@ impl Folds {
     let _ = first_after.map(|(k1, v1)| {
       last_new.map(|(_, v2)| {
         if v1 == v2 {
-          self.0.remove(&k1);
+          let _ = self.take(&k1);
           merge_to_last = first_after;
         }
       })
     });
 
-    let first_fold_to_move =
-      match self.0.range([lastline, 0_usize.into()]..).next() {
-        Some((i, k)) => Some((*i, k.0)),
-        None => None,
-      };
-
-    if let Some((f, _)) = first_fold_to_move {
-      let to_move = self.0.split_off(&f);
-
-      for (k, v) in &to_move {
-        let _ = self.insert(k[0] + added, k[1] + added, v.0);
-      }
+    if let Some(f) = self.first_after(lastline) {
+      // Moving keeps the size, so the foldtexts stay valid
+      self.shift_from(&f, added);
     }
 
     let mut last_added = None;
-    for (k, v) in &newfolds.0 {
+    for (k, v) in newfolds.0 {
       if let Some((k1, _)) = merge_to_first {
         let _ = self.insert(k1[0], k[1], v.0);
         last_added = Some([k1[0], k[1]]);
         merge_to_first = None;
       } else {
-        let _ = self.insert(k[0], k[1], v.0);
-        last_added = Some([k[0], k[1]]);
+        let _ = self.put(k, v);
+        last_added = Some(k);
       }
     }
 
     if let Some(i) = last_added {
       if let Some((k2, v2)) = merge_to_last {
-        self.0.remove(&i);
+        let _ = self.take(&i);
         let _ = self.insert(i[0], k2[1] + added, v2);
       }
     } else {
@@ -259,30 +633,217 @@ impl Folds {
       if let Some((k1, v1)) = last_before {
         if let Some((k2, v2)) = first_after {
           if v1 == v2 {
-            self.0.remove(&k1);
-            self.0.remove(&k2);
+            let _ = self.take(&k1);
+            let _ = self.take(&k2);
             let _ = self.insert(k1[0], k2[1] + added, v1);
           }
         }
       }
     }
+
+    let newlast = lastline.saturating_add(added);
+    self.repair_overlapping(firstline, newlast);
+
+    self
+      .intersecting(firstline, newlast)
+      .filter(|(r, (_, t))| {
+        !old
+          .iter()
+          .any(|(o, ot)| o[0] == r[0] && o[1] - o[0] == r[1] - r[0] && ot == t)
+      })
+      .map(|(r, _)| *r)
+      .collect()
+  }
+
+  /// The folds containing any of the lines `firstline..lastline`, or the line
+  /// `firstline` if the range is empty. Since level 1 folds don't overlap,
+  /// their ends are ordered as well.
+  fn intersecting(
+    &self,
+    firstline: LineNr,
+    lastline: LineNr,
+  ) -> impl Iterator<Item = (&[LineNr; 2], &(Keyword, String))> {
+    let end = std::cmp::max(firstline + 1, lastline);
+    let mut folds: Vec<_> = self
+      .0
+      .range(..[end, LineNr::from_usize(0)])
+      .rev()
+      .take_while(move |(r, _)| firstline <= r[1])
+      .collect();
+    folds.reverse();
+    folds.into_iter()
+  }
+
+  /// The first fold that starts before the end of the previous one. Only the
+  /// folds from the last one starting before `first` up to the first one
+  /// starting after `last` are checked.
+  pub(super) fn overlapping(
+    &self,
+    first: LineNr,
+    last: LineNr,
+  ) -> Option<[LineNr; 2]> {
+    let from = self
+      .0
+      .range(..[first, LineNr::from_usize(0)])
+      .next_back()
+      .map_or([LineNr::from_usize(0); 2], |(r, _)| *r);
+    let mut end = None;
+
+    for r in self.0.range(from..).map(|(r, _)| r) {
+      if end.map_or(false, |e| r[0] <= e) {
+        return Some(*r);
+      }
+      if last < r[0] {
+        break;
+      }
+      end = Some(r[1]);
+    }
+
+    None
+  }
+
+  /// The number of folds inside of `range`
+  pub(super) fn count_within(&self, range: &[LineNr; 2]) -> usize {
+    self
+      .0
+      .range([range[0], range[0]]..=[range[1], range[1]])
+      .filter(|(r, _)| r[1] <= range[1])
+      .count()
+  }
+
+  /// Pack up the folds intersecting the lines `first..=last` into `Value`s
+  /// suitable to send to neovim. A text in `custom` overrides the foldtext of
+  /// the fold with the same range, otherwise a text returned by `relabel`
+  /// replaces the generated one.
+  pub(super) fn fold_calls<F>(
+    &self,
+    first: LineNr,
+    last: LineNr,
+    custom: &BTreeMap<[LineNr; 2], String>,
+    relabel: F,
+  ) -> Vec<Value>
+  where
+    F: Fn(&[LineNr; 2], Keyword) -> Option<String>,
+  {
+    intersecting(&self.0, first, last)
+      .map(|(range, (kw, text))| label(range, *kw, text, custom, &relabel))
+      .collect()
+  }
+
+  /// The `foldexpr` values of the lines `0..len`, with each fold being a
+  /// fold of level 1. Only meaningful if the folds don't overlap.
+  pub(super) fn expr_levels(&self, len: usize) -> Vec<ExprLevel> {
+    let mut levels = vec![ExprLevel::Outside; len];
+
+    for range in self.0.keys() {
+      let start = usize::from(range[0]);
+      let end = cmp::min(usize::from(range[1]) + 1, len);
+
+      if start >= end {
+        continue;
+      }
+      for level in &mut levels[start..end] {
+        *level = ExprLevel::Inside;
+      }
+      levels[start] = ExprLevel::Start;
+    }
+
+    levels
+  }
+
+  /// Like [`fold_calls`](crate::bufdata::folds::Folds::fold_calls), but only
+  /// for the folds with the given `ranges`. Ranges that are no folds are
+  /// skipped.
+  pub(super) fn fold_calls_of<F>(
+    &self,
+    ranges: &[[LineNr; 2]],
+    custom: &BTreeMap<[LineNr; 2], String>,
+    relabel: F,
+  ) -> Vec<Value>
+  where
+    F: Fn(&[LineNr; 2], Keyword) -> Option<String>,
+  {
+    ranges
+      .iter()
+      .filter_map(|r| self.0.get_key_value(r))
+      .map(|(range, (kw, text))| label(range, *kw, text, custom, &relabel))
+      .collect()
   }
+}
+
+/// The entries of `map` whose ranges intersect the lines `first..=last`. The
+/// ranges must not overlap, so only the one starting last before `first` can
+/// reach into the lines.
+pub(super) fn intersecting<V>(
+  map: &BTreeMap<[LineNr; 2], V>,
+  first: LineNr,
+  last: LineNr,
+) -> impl Iterator<Item = (&[LineNr; 2], &V)> {
+  let before = map
+    .range(..[first, first])
+    .next_back()
+    .filter(|(r, _)| first <= r[1]);
 
-  pub(super) fn fold_calls(&self) -> Value {
-    let mut luaargs = vec![];
+  before.into_iter().chain(
+    map
+      .range([first, first]..)
+      .take_while(move |(r, _)| r[0] <= last),
+  )
+}
 
-    for (range, (_, text)) in self.iter() {
-      luaargs.push(Value::from(vec![
-        Value::from(range[0] + 1),
-        Value::from(range[1] + 1),
-        Value::from(text.to_string()),
-      ]));
+/// The value of `foldexpr` for a line, see
+/// [`fold_level`](crate::bufdata::BufData::fold_level)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExprLevel {
+  /// The line is not part of a fold
+  Outside,
+  /// A fold starts at the line, ending the one before
+  Start,
+  /// The line is part of a fold, but does not start it
+  Inside,
+}
+
+impl From<ExprLevel> for Value {
+  fn from(l: ExprLevel) -> Self {
+    match l {
+      ExprLevel::Outside => Value::from(0),
+      ExprLevel::Start => Value::from(">1"),
+      ExprLevel::Inside => Value::from(1),
     }
+  }
+}
 
-    Value::from(luaargs)
+/// The call for the fold over `range` with the generated foldtext `text`, see
+/// [`fold_calls`](crate::bufdata::folds::Folds::fold_calls)
+fn label<F>(
+  range: &[LineNr; 2],
+  kw: Keyword,
+  text: &str,
+  custom: &BTreeMap<[LineNr; 2], String>,
+  relabel: &F,
+) -> Value
+where
+  F: Fn(&[LineNr; 2], Keyword) -> Option<String>,
+{
+  match custom.get(range) {
+    Some(t) => fold_call(range, t),
+    None => match relabel(range, kw) {
+      Some(t) => fold_call(range, &t),
+      None => fold_call(range, text),
+    },
   }
 }
 
+/// The `Value` to send to neovim to create a fold over `range` with the
+/// foldtext `text`. Neovim's line numbers are 1-indexed.
+pub(super) fn fold_call(range: &[LineNr; 2], text: &str) -> Value {
+  Value::from(vec![
+    Value::from(range[0] + 1),
+    Value::from(range[1] + 1),
+    Value::from(text.to_string()),
+  ])
+}
+
 #[cfg(test)]
 macro_rules! splicetest {
   (
@@ -491,6 +1052,30 @@ mod tests {
     vec![(0, 12, Rbody0)]
   );
 
+  #[test]
+  fn splice_resized_folds() {
+    use crate::{bufdata::folds::Folds, linenr::LineNr};
+
+    let l = LineNr::from_usize;
+    let mut folds = Folds::new();
+    folds.insert(l(0), l(9), Node).unwrap();
+    folds.insert(l(10), l(14), Shell).unwrap();
+    folds.insert(l(15), l(19), Node).unwrap();
+
+    // 2 lines added to the nodes at the start
+    let mut newfolds = Folds::new();
+    newfolds.insert(l(5), l(11), Node).unwrap();
+    let resized = folds.splice(newfolds, l(5), l(10), 2);
+    assert_eq!(resized, vec![[l(0), l(11)]]);
+    assert_eq!(folds.get(&[l(0), l(11)]).unwrap().1, " 12 lines: Node ");
+
+    // The shells are changed, but keep their lines
+    let mut newfolds = Folds::new();
+    newfolds.insert(l(12), l(16), Shell).unwrap();
+    let resized = folds.splice(newfolds, l(12), l(17), 0);
+    assert!(resized.is_empty());
+  }
+
   splicetest!(splice_folds_trivial;
     existing: [0, 4, Node], [10, 14, PartSolid];
     new: [7, 13, Shell];
@@ -548,4 +1133,114 @@ mod tests {
     expected: [0, 2, Node], [3, 5, Shell], [6, 13, Node]
   );
 
+  #[test]
+  fn count_container_folds() {
+    use crate::{bufdata::folds::Folds, linenr::LineNr};
+
+    let l = LineNr::from_usize;
+    let mut folds = Folds::new();
+    folds.insert(l(0), l(1), Lcase).unwrap();
+    folds.insert(l(2), l(4), Node).unwrap();
+    folds.insert(l(5), l(5), EndLcase).unwrap();
+    assert!(folds.has_containers());
+
+    // Moving the load case keeps it
+    let mut newfolds = Folds::new();
+    newfolds.insert(l(0), l(0), Node).unwrap();
+    let _ = folds.splice(newfolds, l(0), l(0), 1);
+    assert_eq!(folds.to_vec()[1], (1, 2, Lcase));
+    assert!(folds.has_containers());
+
+    // Replacing it by nodes removes it
+    let mut newfolds = Folds::new();
+    newfolds.insert(l(1), l(2), Node).unwrap();
+    let _ = folds.splice(newfolds, l(1), l(3), 0);
+    assert!(!folds.has_containers());
+  }
+
+  #[test]
+  fn overlapping_folds() {
+    use crate::{bufdata::folds::Folds, linenr::LineNr};
+
+    let line = LineNr::from_usize;
+    let mut folds = Folds::new();
+    for (s, e) in &[(0, 3), (4, 9), (10, 12), (20, 24)] {
+      folds.checked_insert(line(*s), line(*e), Node).unwrap();
+    }
+    assert_eq!(folds.overlapping(line(0), line(30)), None);
+
+    folds.insert(line(6), line(7), Shell).unwrap();
+    assert_eq!(
+      folds.overlapping(line(5), line(6)),
+      Some([line(6), line(7)])
+    );
+    assert_eq!(folds.overlapping(line(0), line(2)), None);
+    assert_eq!(folds.overlapping(line(20), line(22)), None);
+  }
+
+  #[test]
+  fn repair_overlapping_folds() {
+    use crate::{bufdata::folds::Folds, linenr::LineNr};
+
+    let line = LineNr::from_usize;
+    let mut folds = Folds::new();
+    for (s, e) in &[(0, 3), (4, 9), (10, 12)] {
+      folds.checked_insert(line(*s), line(*e), Node).unwrap();
+    }
+    assert!(folds.take_repairs().is_empty());
+
+    // Folds of the same keyword are merged
+    folds.checked_insert(line(2), line(5), Node).unwrap();
+    assert_eq!(folds.to_vec(), vec![(0, 9, Node), (10, 12, Node)]);
+    assert_eq!(folds.take_repairs().len(), 2);
+    assert!(folds.take_repairs().is_empty());
+
+    // Folds of other keywords are cut
+    folds.checked_insert(line(5), line(10), Shell).unwrap();
+    assert_eq!(
+      folds.to_vec(),
+      vec![(0, 4, Node), (5, 10, Shell), (11, 12, Node)]
+    );
+    assert_eq!(
+      folds.take_repairs()[0],
+      "Fold 5-10 of Shell overlaps fold 10-12 of Node, cut the latter"
+    );
+    assert_eq!(folds.overlapping(line(0), LineNr::MAX), None);
+  }
+
+  #[test]
+  fn first_before_after() {
+    use crate::{bufdata::folds::Folds, linenr::LineNr};
+
+    let line = LineNr::from_usize;
+    let mut folds = Folds::new();
+    assert_eq!(folds.first_before(line(0)), None);
+    assert_eq!(folds.first_after(line(0)), None);
+
+    for (s, e) in &[(2, 3), (4, 9), (12, 14)] {
+      folds.checked_insert(line(*s), line(*e), Node).unwrap();
+    }
+
+    assert_eq!(folds.first_before(line(1)), None);
+    assert_eq!(folds.first_before(line(2)), Some([line(2), line(3)]));
+    assert_eq!(folds.first_before(line(3)), Some([line(2), line(3)]));
+    assert_eq!(folds.first_before(line(4)), Some([line(4), line(9)]));
+    assert_eq!(folds.first_before(line(11)), Some([line(4), line(9)]));
+    assert_eq!(folds.first_before(LineNr::MAX), Some([line(12), line(14)]));
+
+    assert_eq!(folds.first_after(line(0)), Some([line(2), line(3)]));
+    assert_eq!(folds.first_after(line(3)), Some([line(4), line(9)]));
+    assert_eq!(folds.first_after(line(4)), Some([line(4), line(9)]));
+    assert_eq!(folds.first_after(line(10)), Some([line(12), line(14)]));
+    assert_eq!(folds.first_after(line(13)), None);
+
+    folds.insert(line(4), line(6), Shell).unwrap();
+    folds.insert(line(7), line(12), Shell).unwrap();
+
+    assert_eq!(folds.first_before(line(4)), Some([line(4), line(9)]));
+    assert_eq!(folds.first_after(line(4)), Some([line(4), line(6)]));
+    assert_eq!(folds.first_after(line(5)), Some([line(7), line(12)]));
+    assert_eq!(folds.first_before(line(8)), Some([line(7), line(12)]));
+    assert_eq!(folds.first_before(line(12)), Some([line(12), line(14)]));
+  }
 }
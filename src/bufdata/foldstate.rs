@@ -0,0 +1,234 @@
+//! The fold state of a buffer as it is exchanged via a sidecar file. This
+//! allows sharing the structural view of a deck, including custom folds and
+//! fold texts, with somebody working on the same revision of it.
+//!
+//! The format is line-based. After a header line, there's the hash of the
+//! buffer contents the state belongs to, followed by one line per fold
+//! containing the (1-indexed, end-inclusive) start and end line and the
+//! foldtext:
+//!
+//! ```text
+//! nvimpam foldstate 1
+//! hash 8c1ef6a4b8942589
+//! 1 4  4 lines: Node
+//! 6 13 Boundary Conditions
+//! ```
+use std::fmt;
+
+use failure::{self, Error};
+
+use crate::{linenr::LineNr, lines::LineRef};
+
+/// The first line of a fold state file
+const HEADER: &str = "nvimpam foldstate 1";
+
+pub(super) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The folds of a buffer, along with the hash of the lines of the buffer they
+/// belong to.
+#[derive(Debug, PartialEq)]
+pub struct FoldState {
+  pub hash: u64,
+  pub folds: Vec<([LineNr; 2], String)>,
+}
+
+impl FoldState {
+  /// Parse the contents of a fold state file
+  pub fn parse(s: &str) -> Result<Self, Error> {
+    let mut lines = s.lines();
+
+    if lines.next() != Some(HEADER) {
+      return Err(failure::err_msg("Not a nvimpam fold state file!"));
+    }
+
+    let hash = lines
+      .next()
+      .filter(|l| l.starts_with("hash "))
+      .and_then(|l| u64::from_str_radix(&l[5..], 16).ok())
+      .ok_or_else(|| failure::err_msg("Missing hash in fold state file!"))?;
+
+    let folds = lines
+      .filter(|l| !l.is_empty())
+      .map(parse_fold)
+      .collect::<Result<_, _>>()?;
+
+    Ok(FoldState { hash, folds })
+  }
+}
+
+impl fmt::Display for FoldState {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}", HEADER)?;
+    writeln!(f, "hash {:016x}", self.hash)?;
+
+    for (range, text) in &self.folds {
+      write_fold(f, *range, text)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Parse the `line` of a fold, i.e. its (1-indexed, end-inclusive) start and
+/// end line followed by the foldtext
+pub(crate) fn parse_fold(line: &str) -> Result<([LineNr; 2], String), Error> {
+  let mut parts = line.splitn(3, ' ');
+  let start = parts.next().and_then(|p| p.parse::<usize>().ok());
+  let end = parts.next().and_then(|p| p.parse::<usize>().ok());
+
+  match (start, end, parts.next()) {
+    (Some(s), Some(e), Some(text)) if 0 < s && s <= e => Ok((
+      [LineNr::from_usize(s - 1), LineNr::from_usize(e - 1)],
+      text.to_string(),
+    )),
+    _ => Err(failure::err_msg(format!(
+      "Invalid fold '{}' in fold state file!",
+      line
+    ))),
+  }
+}
+
+/// Write the line of the fold `range` with the foldtext `text`, see
+/// [`parse_fold`](crate::bufdata::foldstate::parse_fold)
+pub(crate) fn write_fold(
+  f: &mut fmt::Formatter,
+  range: [LineNr; 2],
+  text: &str,
+) -> fmt::Result {
+  writeln!(
+    f,
+    "{} {} {}",
+    range[0] + 1,
+    range[1] + 1,
+    text.replace('\n', " ")
+  )
+}
+
+/// Compute the hash identifying a revision of a buffer. Since comments aren't
+/// part of the lines, they're accounted for via the line numbers. We're not
+/// using `std`s `DefaultHasher` since the hash needs to be stable across
+/// machines.
+pub fn hash_lines<'a, I>(lines: I) -> u64
+where
+  I: Iterator<Item = LineRef<'a>>,
+{
+  let mut hash = FNV_OFFSET;
+
+  for line in lines {
+    fnv_feed(&mut hash, &(usize::from(line.number) as u64).to_le_bytes());
+    fnv_feed(&mut hash, line.text.as_ref());
+  }
+
+  hash
+}
+
+/// Feed `bytes` into the FNV-1a `hash`, which should start out as
+/// `FNV_OFFSET`
+pub(super) fn fnv_feed(hash: &mut u64, bytes: &[u8]) {
+  for b in bytes {
+    *hash ^= u64::from(*b);
+    *hash = hash.wrapping_mul(FNV_PRIME);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::{
+    bufdata::{foldstate::FoldState, BufData},
+    linenr::LineNr,
+  };
+
+  const STATE: &str = "nvimpam foldstate 1\nhash 00000000000012ab\n1 4  4 \
+                       lines: Node \n6 13 Boundary Conditions\n";
+
+  #[test]
+  fn foldstate_roundtrip() {
+    let state = FoldState::parse(STATE).unwrap();
+
+    assert_eq!(state.hash, 0x12ab);
+    assert_eq!(
+      state.folds,
+      vec![
+        (
+          [LineNr::from_usize(0), LineNr::from_usize(3)],
+          " 4 lines: Node ".to_string()
+        ),
+        (
+          [LineNr::from_usize(5), LineNr::from_usize(12)],
+          "Boundary Conditions".to_string()
+        ),
+      ]
+    );
+    assert_eq!(state.to_string(), STATE);
+  }
+
+  #[test]
+  fn foldstate_invalid() {
+    assert!(FoldState::parse("hash 12ab\n").is_err());
+    assert!(FoldState::parse("nvimpam foldstate 1\n1 4 Node\n").is_err());
+    assert!(FoldState::parse("nvimpam foldstate 1\nhash 1\n4 1 x\n").is_err());
+  }
+
+  const LINES: [&str; 6] = [
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        2              0.             0.5              0.",
+    "#Comment",
+    "SHELL /     3129       1       1    2967    2971    2970",
+    "SHELL /     3130       1       1    2967    2971    2970",
+    "NODE  /        3              0.             0.5              0.",
+  ];
+
+  #[test]
+  fn foldstate_transfer() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let mut state = bufdata.fold_state();
+    state.folds[0].1 = "Upper nodes".to_string();
+    let mixed = [LineNr::from_usize(3), LineNr::from_usize(5)];
+    state.folds.insert(2, (mixed, "Mixed".to_string()));
+    let exported = state.to_string();
+
+    let mut other = BufData::new(&buf);
+    other.parse_strs(&LINES).unwrap();
+    other
+      .set_fold_state(FoldState::parse(&exported).unwrap())
+      .unwrap();
+
+    let imported = other.fold_state();
+    assert_eq!(imported.folds.len(), 4);
+    assert_eq!(imported.folds[0].1, "Upper nodes");
+    assert_eq!(imported.to_string(), exported);
+
+    let mut changed = BufData::new(&buf);
+    changed.parse_strs(&LINES[1..]).unwrap();
+    assert!(changed
+      .set_fold_state(FoldState::parse(&exported).unwrap())
+      .is_err());
+  }
+
+  #[test]
+  fn rename_fold() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    bufdata
+      .rename_fold(LineNr::from_usize(3), "Shells".to_string())
+      .unwrap();
+    assert_eq!(bufdata.fold_state().folds[1].1, "Shells");
+
+    bufdata
+      .rename_fold(LineNr::from_usize(3), String::new())
+      .unwrap();
+    assert_eq!(bufdata.fold_state().folds[1].1, " 2 lines: Shell ");
+
+    assert!(bufdata
+      .rename_fold(LineNr::from_usize(4), "Shells".to_string())
+      .is_err());
+  }
+}
@@ -0,0 +1,153 @@
+//! Templates for the foldtexts, so they can be translated or adjusted to a
+//! team's conventions. A template is a string with placeholders in braces,
+//! e.g. `"{keyword}: {lines} Zeilen"`. The placeholders available depend on
+//! the [`FoldKind`](crate::bufdata::foldtext::FoldKind).
+use std::collections::HashMap;
+
+use crate::{card::keyword::Keyword, linenr::LineNr};
+
+/// The different kinds of folds that get a generated foldtext
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum FoldKind {
+  /// A level 1 fold of cards. Placeholders: `{lines}`, `{keyword}`
+  Card,
+  /// A level 1 fold of an encrypted block. Placeholders: `{lines}`
+  Encrypted,
+  /// A level 2 fold grouping level 1 folds. Placeholders: `{count}`,
+  /// `{keyword}`, which is the name of the group for the groups of
+  /// [`FoldGroups`](crate::bufdata::foldgroup::FoldGroups)
+  Group,
+  /// A level 2 fold grouping encrypted blocks. Placeholders: `{count}`
+  EncryptedGroup,
+  /// A fold of a GES region. Placeholders: `{entries}`
+  Ges,
+}
+
+impl FoldKind {
+  /// Parse the name of a fold kind, e.g. `"card"`
+  pub fn from_name(name: &str) -> Option<Self> {
+    use self::FoldKind::*;
+
+    match name {
+      "card" => Some(Card),
+      "encrypted" => Some(Encrypted),
+      "group" => Some(Group),
+      "encrypted_group" => Some(EncryptedGroup),
+      "ges" => Some(Ges),
+      _ => None,
+    }
+  }
+}
+
+/// The user provided templates for the foldtexts. Fold kinds without a
+/// template keep the generated foldtext.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FoldTexts {
+  pub templates: HashMap<FoldKind, String>,
+}
+
+/// Replace the placeholders `{name}` in `template` by their values. Unknown
+/// placeholders are left as they are.
+fn render(template: &str, values: &[(&str, String)]) -> String {
+  let mut text = template.to_string();
+
+  for (name, value) in values {
+    text = text.replace(&format!("{{{}}}", name), value);
+  }
+
+  text
+}
+
+impl FoldTexts {
+  /// The foldtext of a level 1 fold over the lines in `range`, if there's a
+  /// template for it
+  pub fn card(&self, range: &[LineNr; 2], kw: Keyword) -> Option<String> {
+    let lines = ("lines", (range[1] - range[0] + 1).to_string());
+
+    match kw {
+      Keyword::Encrypted => self
+        .templates
+        .get(&FoldKind::Encrypted)
+        .map(|t| render(t, &[lines])),
+      _ => self
+        .templates
+        .get(&FoldKind::Card)
+        .map(|t| render(t, &[lines, ("keyword", format!("{:?}", kw))])),
+    }
+  }
+
+  /// The foldtext of a level 2 fold grouping `count` level 1 folds, if
+  /// there's a template for it. `name` is the name of the group if the
+  /// folds were grouped by a rule, otherwise the keyword is used.
+  pub fn group(
+    &self,
+    count: usize,
+    kw: Keyword,
+    name: Option<&str>,
+  ) -> Option<String> {
+    let count = ("count", count.to_string());
+    let name = name.map_or_else(|| format!("{:?}", kw), str::to_string);
+
+    match kw {
+      Keyword::Encrypted => self
+        .templates
+        .get(&FoldKind::EncryptedGroup)
+        .map(|t| render(t, &[count])),
+      _ => self
+        .templates
+        .get(&FoldKind::Group)
+        .map(|t| render(t, &[count, ("keyword", name)])),
+    }
+  }
+
+  /// The foldtext of a fold over a GES region with `entries` entries, if
+  /// there's a template for it
+  pub fn ges(&self, entries: usize) -> Option<String> {
+    self
+      .templates
+      .get(&FoldKind::Ges)
+      .map(|t| render(t, &[("entries", entries.to_string())]))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::foldtext::{FoldKind, FoldTexts},
+    card::keyword::Keyword::*,
+    linenr::LineNr,
+  };
+
+  #[test]
+  fn render_templates() {
+    let mut texts = FoldTexts::default();
+    let range = [LineNr::from_usize(3), LineNr::from_usize(39)];
+
+    assert_eq!(texts.card(&range, Node), None);
+
+    let _ = texts
+      .templates
+      .insert(FoldKind::Card, " {keyword} ({lines} Zeilen) ".to_string());
+    let _ = texts
+      .templates
+      .insert(FoldKind::Group, " {count}x {keyword} {unknown}".to_string());
+    let _ = texts
+      .templates
+      .insert(FoldKind::Ges, " GES: {entries} ".to_string());
+
+    assert_eq!(
+      texts.card(&range, Node),
+      Some(" Node (37 Zeilen) ".to_string())
+    );
+    assert_eq!(texts.card(&range, Encrypted), None);
+    assert_eq!(
+      texts.group(4, Shell, None),
+      Some(" 4x Shell {unknown}".to_string())
+    );
+    assert_eq!(
+      texts.group(2, Shell, Some("Surface elements")),
+      Some(" 2x Surface elements {unknown}".to_string())
+    );
+    assert_eq!(texts.ges(12), Some(" GES: 12 ".to_string()));
+  }
+}
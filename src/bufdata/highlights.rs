@@ -1,11 +1,24 @@
 //! The highlight module
-use std::{self, cmp, convert::From, ops::Range};
+use std::{
+  self,
+  cmp,
+  borrow::Cow,
+  collections::HashMap,
+  convert::From,
+  ops::Range,
+  sync::{Mutex, PoisonError},
+};
 
 use neovim_lib::{neovim_api::Buffer, Value};
 
 use crate::{
   bufdata::highlights::HighlightGroup as Hl,
-  card::{cell::Cell, line::Line as CardLine},
+  card::{
+    cell::{Cell, FixedStr},
+    keyword::Keyword,
+    line::Line as CardLine,
+    tabs, value,
+  },
   linenr::LineNr,
 };
 
@@ -17,6 +30,13 @@ pub enum HighlightGroup {
   ErrorCellEven,
   ErrorCellOdd,
   Keyword,
+  Title,
+  /// Content the solver ignores, e.g. after the last cell of a line
+  Ignored,
+  /// A highlight in the given group pushed by another tool, e.g. a parser of
+  /// the solver log, see
+  /// [`external`](crate::bufdata::highlights::HighlightGroup::external)
+  External(&'static str),
 }
 
 impl From<HighlightGroup> for &'static str {
@@ -29,7 +49,191 @@ impl From<HighlightGroup> for &'static str {
       ErrorCellEven => "PamErrorCellEven",
       ErrorCellOdd => "PamErrorCellOdd",
       Keyword => "PamKeyword",
+      Title => "PamTitle",
+      Ignored => "PamIgnored",
+      External(g) => g,
+    }
+  }
+}
+
+/// The types of the LSP semantic tokens sent by
+/// [`semantic_tokens`](crate::bufdata::BufData::semantic_tokens), in the
+/// order of their indices
+pub const TOKEN_TYPES: &[&str] = &["keyword", "string", "number", "comment"];
+
+/// The modifiers of the LSP semantic tokens, in the order of their bits. The
+/// cells of a line alternate between tokens with and without `odd`.
+pub const TOKEN_MODIFIERS: &[&str] = &["odd", "invalid"];
+
+/// The group names of the
+/// [`External`](crate::bufdata::highlights::HighlightGroup::External)
+/// highlights. Each name is leaked once, so there are only as many as there
+/// are different groups.
+static EXTERNAL_GROUPS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+impl HighlightGroup {
+  /// Parse the name of a highlight kind as used in the configuration, e.g.
+  /// `"celleven"` or `"Keyword"`.
+  pub fn from_name(name: &str) -> Option<Self> {
+    use self::HighlightGroup::*;
+
+    match name.to_lowercase().as_str() {
+      "celleven" => Some(CellEven),
+      "cellodd" => Some(CellOdd),
+      "errorcelleven" => Some(ErrorCellEven),
+      "errorcellodd" => Some(ErrorCellOdd),
+      "keyword" => Some(Keyword),
+      "title" => Some(Title),
+      "ignored" => Some(Ignored),
+      _ => None,
+    }
+  }
+
+  /// The index of the LSP semantic token type of the highlight in
+  /// [`TOKEN_TYPES`](crate::bufdata::highlights::TOKEN_TYPES), and the bits of
+  /// its modifiers in
+  /// [`TOKEN_MODIFIERS`](crate::bufdata::highlights::TOKEN_MODIFIERS).
+  /// External highlights have no token.
+  pub fn semantic_token(self) -> Option<(u64, u64)> {
+    use self::HighlightGroup::*;
+
+    match self {
+      Keyword => Some((0, 0)),
+      Title => Some((1, 0)),
+      CellEven => Some((2, 0)),
+      CellOdd => Some((2, 0b01)),
+      ErrorCellEven => Some((2, 0b10)),
+      ErrorCellOdd => Some((2, 0b11)),
+      Ignored => Some((3, 0)),
+      External(_) => None,
+    }
+  }
+
+  /// The [`External`](crate::bufdata::highlights::HighlightGroup::External)
+  /// highlight in the group `group`
+  pub fn external(group: &str) -> Self {
+    let mut groups = EXTERNAL_GROUPS
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+
+    match groups.iter().find(|&&g| g == group) {
+      Some(&g) => HighlightGroup::External(g),
+      None => {
+        let g: &'static str = Box::leak(group.into());
+        groups.push(g);
+        HighlightGroup::External(g)
+      }
+    }
+  }
+}
+
+/// The unit of the columns of the highlights returned by
+/// [`positions`](crate::bufdata::highlights::Highlights::positions). The
+/// highlights are kept as byte offsets, as neovim's highlights are, but e.g.
+/// LSP positions count UTF-16 code units.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+  Bytes,
+  /// The characters of the UTF-8 text
+  Chars,
+  /// The UTF-16 code units, i.e. characters outside of the basic plane count
+  /// twice
+  Utf16,
+}
+
+impl Encoding {
+  /// Parse the name of an encoding as used in the configuration, i.e.
+  /// `"bytes"`, `"chars"` or `"utf-16"`.
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name.to_lowercase().as_str() {
+      "bytes" => Some(Encoding::Bytes),
+      "chars" => Some(Encoding::Chars),
+      "utf-16" | "utf16" => Some(Encoding::Utf16),
+      _ => None,
+    }
+  }
+
+  /// Convert the byte offset `col` of `text` to this encoding. A column in
+  /// the middle of a character counts that character. Invalid UTF-8 is
+  /// counted bytewise, and so are the columns after the end of `text`.
+  pub fn column(self, text: &[u8], col: u8) -> u64 {
+    if self == Encoding::Bytes {
+      return u64::from(col);
     }
+
+    let head = &text[..cmp::min(usize::from(col), text.len())];
+    let past = usize::from(col) - head.len();
+    let units: usize = head
+      .iter()
+      .map(|&b| match b {
+        _ if is_continuation(b) => 0,
+        0xF0..=0xF7 if self == Encoding::Utf16 => 2,
+        _ => 1,
+      })
+      .sum();
+
+    (units + past) as u64
+  }
+}
+
+/// If `b` continues a multibyte character in UTF-8
+fn is_continuation(b: u8) -> bool {
+  b & 0xC0 == 0x80
+}
+
+/// The default of [`HlConfig::columns`](HlConfig::columns), right after the
+/// 80 columns of the cards
+pub const DEFAULT_COLUMNS: u8 = 81;
+
+/// The namespace the highlights are added to, and the highlight groups used
+/// for each [`HighlightGroup`](crate::bufdata::highlights::HighlightGroup).
+#[derive(Debug, PartialEq, Clone)]
+pub struct HlConfig {
+  /// The id of the namespace, as returned by `nvim_create_namespace`
+  pub namespace: i64,
+  /// Group names that replace the default ones, e.g. `PamCellEven`
+  pub groups: HashMap<Hl, String>,
+  /// Group names for the keywords of single cards, e.g. `NODE`, replacing
+  /// the one of [`Keyword`](crate::bufdata::highlights::HighlightGroup)
+  pub keywords: Vec<(Keyword, String)>,
+  /// The column the highlights of a line end at. The rest of the line is
+  /// not looked at, so very long lines don't take long to highlight.
+  pub columns: u8,
+  /// The unit of the columns returned by
+  /// [`positions`](crate::bufdata::highlights::Highlights::positions). The
+  /// highlights sent to neovim always use bytes.
+  pub encoding: Encoding,
+}
+
+impl Default for HlConfig {
+  fn default() -> Self {
+    HlConfig {
+      namespace: 5,
+      groups: HashMap::new(),
+      keywords: vec![],
+      columns: DEFAULT_COLUMNS,
+      encoding: Encoding::Bytes,
+    }
+  }
+}
+
+impl HlConfig {
+  /// The name of the highlight group to use for `hl`
+  pub fn group(&self, hl: Hl) -> &str {
+    match self.groups.get(&hl) {
+      Some(g) => g,
+      None => hl.into(),
+    }
+  }
+
+  /// The name of the highlight group to use for the keyword of the cards
+  /// `kw`, if it's configured
+  pub fn keyword_group(&self, kw: Keyword) -> Option<&str> {
+    self
+      .keywords
+      .iter()
+      .find(|(k, _)| *k == kw)
+      .map(|(_, g)| g.as_str())
   }
 }
 
@@ -55,18 +259,35 @@ impl<'a> Iterator for HlIter<'a> {
 
     let cell = match self.cells.next() {
       Some(c) => c,
-      None => return None,
+      None => {
+        // Content after the last cell is ignored by the solver
+        let ignored = value::ignored_range(
+          &self.text[..usize::from(self.linelen)],
+          self.until,
+        );
+        self.until = self.linelen;
+        return ignored.map(|(s, e)| ((self.num, s, e), Hl::Ignored));
+      }
     };
 
     let celllen = cell.len();
-    let range = self.until..cmp::min(self.linelen, self.until + celllen);
+    let end = self.until.saturating_add(celllen);
+    let range = self.until..cmp::min(self.linelen, end);
     let odd = self.odd;
 
-    self.until += celllen;
+    self.until = end;
     self.odd = !odd;
 
     if let Cell::Kw(_) = cell {
       Some(((self.num, range.start, range.end), Hl::Keyword))
+    } else if matches!(cell, Cell::Blank(_))
+      && self
+        .text
+        .get(range.start as usize..range.end as usize)
+        .map_or(false, |s| s.iter().any(|b| !b.is_ascii_whitespace()))
+    {
+      // Content in blank cells is ignored by the solver
+      Some(((self.num, range.start, range.end), Hl::Ignored))
     } else {
       match self
         .text
@@ -93,12 +314,236 @@ impl<'a> Iterator for HlIter<'a> {
   }
 }
 
-/// The struct to hold the highlights for a buffer. The internal `Vec` needs to
-/// stay ordered on the first tuple.
-///
-/// TODO(KillTheMule): Don't expose the internal `Vec`
+/// The call to clear the namespace `namespace` of the buffer `buf`
+fn clear_call(buf: &Buffer, namespace: i64) -> Value {
+  clear_lines_call(buf, namespace, Value::from(0), Value::from(-1))
+}
+
+/// The call to clear the namespace `namespace` of the buffer `buf` in the
+/// lines `firstline..lastline`
+fn clear_lines_call(
+  buf: &Buffer,
+  namespace: i64,
+  firstline: Value,
+  lastline: Value,
+) -> Value {
+  vec![
+    Value::from("nvim_buf_clear_highlight".to_string()),
+    vec![
+      buf.get_value().clone(),
+      Value::from(namespace),
+      firstline,
+      lastline,
+    ]
+    .into(),
+  ]
+  .into()
+}
+
+/// The maximum number of highlights of a chunk
+const CHUNK_HLS: usize = 4096;
+
+/// A highlight as stored in a [`HlChunk`](crate::bufdata::highlights::HlChunk),
+/// with its line relative to the first line of the chunk
+type StoredHl = ((u32, u8, u8), Hl);
+
+/// Consecutive highlights, never empty. `first` is the line of the first one.
+#[derive(Debug, Clone)]
+struct HlChunk {
+  first: LineNr,
+  hls: Vec<StoredHl>,
+}
+
+impl HlChunk {
+  fn iter(&self) -> impl Iterator<Item = ((LineNr, u8, u8), Hl)> + '_ {
+    let first = self.first;
+    self.hls.iter().map(move |&((o, s, e), hl)| {
+      ((first + LineNr::from_usize(o as usize), s, e), hl)
+    })
+  }
+}
+
+/// Append `hl` to the last of the `chunks`, or to a new one if it has `size`
+/// highlights already. A highlight before the first line of the last chunk
+/// starts a new one, so it can't wrap around, and
+/// [`check_consistency`](crate::bufdata::BufData::check_consistency) still
+/// notices the disorder.
+fn push_hl(
+  chunks: &mut Vec<HlChunk>,
+  ((l, s, e), hl): ((LineNr, u8, u8), Hl),
+  size: usize,
+) {
+  match chunks.last_mut() {
+    Some(chunk) if chunk.hls.len() < size && chunk.first <= l => {
+      chunk.hls.push(((chunk.first.lines_to(l) as u32, s, e), hl))
+    }
+    _ => chunks.push(HlChunk {
+      first: l,
+      hls: vec![((0, s, e), hl)],
+    }),
+  }
+}
+
+/// The highlights of a buffer, stored in chunks of at most `CHUNK_HLS`
+/// highlights, with their lines relative to the first line of their chunk,
+/// just like [`Lines`](crate::lines::Lines). Highlights are accessed by
+/// their index.
+#[derive(Debug, Default, Clone)]
+struct HlChunks {
+  chunks: Vec<HlChunk>,
+  /// The index of the first highlight of each chunk
+  starts: Vec<usize>,
+  len: usize,
+}
+
+impl HlChunks {
+  fn clear(&mut self) {
+    self.chunks.clear();
+    self.starts.clear();
+    self.len = 0;
+  }
+
+  /// Append a highlight, which needs to be after all others
+  fn push(&mut self, hl: ((LineNr, u8, u8), Hl)) {
+    let chunks = self.chunks.len();
+    push_hl(&mut self.chunks, hl, CHUNK_HLS);
+    if self.chunks.len() > chunks {
+      self.starts.push(self.len);
+    }
+    self.len += 1;
+  }
+
+  /// Recompute the index of the first highlight of the chunks from the one
+  /// with index `from` on. The ones before need to be unchanged.
+  fn reindex_from(&mut self, from: usize) {
+    self.starts.truncate(from);
+    let mut start = self
+      .starts
+      .last()
+      .map_or(0, |&s| s + self.chunks[from - 1].hls.len());
+    for chunk in &self.chunks[from..] {
+      self.starts.push(start);
+      start += chunk.hls.len();
+    }
+    self.len = start;
+  }
+
+  /// The index of the chunk containing the highlight with index `idx`. It's
+  /// the last chunk if `idx` is after the last highlight.
+  fn chunk_of(&self, idx: usize) -> usize {
+    match self.starts.binary_search(&idx) {
+      Ok(c) => c,
+      Err(c) => c.saturating_sub(1),
+    }
+  }
+
+  /// The highlights with the indices in `range`
+  fn range(
+    &self,
+    range: Range<usize>,
+  ) -> impl Iterator<Item = ((LineNr, u8, u8), Hl)> + '_ {
+    let end = cmp::min(range.end, self.len);
+    let start = cmp::min(range.start, end);
+    let first = self.chunk_of(start);
+    let skip = start - self.starts.get(first).map_or(0, |&s| s);
+
+    self.chunks[first..]
+      .iter()
+      .flat_map(HlChunk::iter)
+      .skip(skip)
+      .take(end - start)
+  }
+
+  /// The index of the first highlight with a line number of at least `line`,
+  /// or the number of highlights if there's no such highlight. Both the
+  /// chunks and the highlights in them are ordered, so these are binary
+  /// searches.
+  fn first_index_from(&self, line: LineNr) -> usize {
+    let c = self.chunks.partition_point(|c| c.first < line);
+    if c == 0 {
+      return 0;
+    }
+
+    let chunk = &self.chunks[c - 1];
+    let offset = chunk.first.lines_to(line) as u32;
+    self.starts[c - 1] + chunk.hls.partition_point(|((o, _, _), _)| *o < offset)
+  }
+
+  /// Replace the highlights with the indices in `range` by `new`, and move
+  /// the ones after them by `added` lines. Only the chunks containing the
+  /// replaced highlights are rebuilt, along with the next one if they'd end
+  /// up small. The chunks after them are moved by changing their first line,
+  /// and their indices are recomputed. See
+  /// [`Lines::update`](crate::lines::Lines::update), which does the same for
+  /// the lines.
+  fn splice(
+    &mut self,
+    range: Range<usize>,
+    new: impl Iterator<Item = ((LineNr, u8, u8), Hl)>,
+    added: isize,
+  ) {
+    let mut touched = if self.chunks.is_empty() {
+      0..0
+    } else {
+      let last = if range.end > range.start {
+        range.end - 1
+      } else {
+        range.start
+      };
+      self.chunk_of(range.start)..self.chunk_of(last) + 1
+    };
+
+    let first_idx = self.starts.get(touched.start).map_or(0, |&s| s);
+    let new: Vec<_> = new.collect();
+    let kept = self.starts.get(touched.end).map_or(self.len, |&s| s)
+      - first_idx
+      - range.len();
+    if kept + new.len() < CHUNK_HLS / 2 && touched.end < self.chunks.len() {
+      touched.end += 1;
+    }
+
+    let mut before = vec![];
+    let mut after = vec![];
+    let drained: Vec<_> = self.chunks.drain(touched.clone()).collect();
+    for (idx, ((l, s, e), hl)) in
+      (first_idx..).zip(drained.iter().flat_map(HlChunk::iter))
+    {
+      if idx < range.start {
+        before.push(((l, s, e), hl));
+      } else if idx >= range.end {
+        after.push(((l + added, s, e), hl));
+      }
+    }
+
+    // Spread the highlights evenly, so the chunks don't get split into tiny
+    // ones by repeated insertions
+    let count = before.len() + new.len() + after.len();
+    let size = match count.div_ceil(CHUNK_HLS) {
+      0 => CHUNK_HLS,
+      nchunks => count.div_ceil(nchunks),
+    };
+    let mut rebuilt = vec![];
+    for hl in before.into_iter().chain(new).chain(after) {
+      push_hl(&mut rebuilt, hl, size);
+    }
+
+    let moved = touched.start + rebuilt.len();
+    let _ = self.chunks.splice(touched.start..touched.start, rebuilt);
+    if added != 0 {
+      for chunk in &mut self.chunks[moved..] {
+        chunk.first += added;
+      }
+    }
+    self.reindex_from(touched.start);
+  }
+}
+
+/// The struct to hold the highlights for a buffer. They need to stay ordered
+/// on their position. The
+/// [`HlConfig`](crate::bufdata::highlights::HlConfig) is used when sending the
+/// highlights to neovim, and is kept when clearing or splicing.
 #[derive(Default, Debug)]
-pub struct Highlights(pub Vec<((LineNr, u8, u8), Hl)>);
+pub struct Highlights(HlChunks, HlConfig);
 
 impl Highlights {
   pub(super) fn clear(&mut self) {
@@ -106,18 +551,90 @@ impl Highlights {
   }
 
   pub fn new() -> Self {
-    Self(Vec::new())
+    Self(HlChunks::default(), HlConfig::default())
+  }
+
+  /// The number of highlights
+  pub fn len(&self) -> usize {
+    self.0.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.len == 0
+  }
+
+  /// Replace the [`HlConfig`](crate::bufdata::highlights::HlConfig). If the
+  /// namespace changes, this returns the calls to clear the old one, since
+  /// the highlights in there can't be updated anymore.
+  pub(super) fn set_config(
+    &mut self,
+    buf: &Buffer,
+    config: HlConfig,
+  ) -> Option<Vec<Value>> {
+    let old = std::mem::replace(&mut self.1, config);
+
+    if old.namespace == self.1.namespace {
+      return None;
+    }
+
+    Some(vec![clear_call(buf, old.namespace)])
+  }
+
+  /// The column the highlights of a line end at, see
+  /// [`HlConfig::columns`](crate::bufdata::highlights::HlConfig::columns)
+  pub fn columns(&self) -> u8 {
+    self.1.columns
   }
 
-  pub fn iter(&self) -> impl Iterator<Item = &((LineNr, u8, u8), Hl)> {
-    self.0.iter()
+  /// Set the column the highlights of a line end at, e.g. for highlights
+  /// parsed apart from the buffer's
+  pub(super) fn set_columns(&mut self, columns: u8) {
+    self.1.columns = columns;
+  }
+
+  /// Use the [`HlConfig`](crate::bufdata::highlights::HlConfig) of `other`,
+  /// e.g. to send highlights that were parsed apart from the buffer's
+  pub(super) fn with_config_of(mut self, other: &Self) -> Self {
+    self.1 = other.1.clone();
+    self
+  }
+
+  /// The calls to remove all highlights of the buffer in neovim
+  pub(super) fn clear_calls(&self, buf: &Buffer) -> Vec<Value> {
+    vec![clear_call(buf, self.1.namespace)]
+  }
+
+  /// The calls to remove the highlights of the lines in `regions` in neovim.
+  /// The regions are end-exclusive.
+  pub(super) fn clear_regions_calls(
+    &self,
+    buf: &Buffer,
+    regions: &[[LineNr; 2]],
+  ) -> Vec<Value> {
+    let ns = self.1.namespace;
+
+    regions
+      .iter()
+      .map(|[f, l]| clear_lines_call(buf, ns, Value::from(*f), Value::from(*l)))
+      .collect()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = ((LineNr, u8, u8), Hl)> + '_ {
+    self.0.range(0..self.0.len)
   }
 
   /// Remove all the highlights with linenumbers in `firstline..lastline`, and
-  /// paste in the ones given in `newhls`. Keeps the `Vec` ordered. Returns the
-  /// range of indices with new highlight entries (note that all the elements
-  /// above that range have been modified, as their line numbers had to be
-  /// shifted).
+  /// paste in the ones given in `newhls`. Keeps the highlights ordered.
+  /// Returns the range of indices with new highlight entries (note that all
+  /// the elements above that range have been modified, as their line numbers
+  /// had to be shifted).
+  ///
+  /// Finding the range to replace is logarithmic. The highlights are stored
+  /// in chunks, and only the ones containing the replaced highlights are
+  /// rebuilt. The line numbers after them are shifted by moving the first
+  /// line of each later chunk, so that part is linear in the number of
+  /// chunks, i.e. the highlights after the replaced ones divided by
+  /// `CHUNK_HLS`, see `benches/hl_splice.rs`.
   pub fn splice(
     &mut self,
     newhls: Self,
@@ -125,34 +642,74 @@ impl Highlights {
     lastline: LineNr,
     added: isize,
   ) -> Range<usize> {
-    let start = self
-      .0
-      .binary_search_by_key(&(firstline, 0), |&((l, s, _), _)| (l, s))
-      // error contains index where ele could be inserted preserving Order
-      .unwrap_or_else(|e| e);
-    let end = self.0[start..]
+    let range = self.linerange(firstline, lastline);
+    let num_new = newhls.len();
+    self.0.splice(range.clone(), newhls.iter(), added);
+
+    range.start..(range.start + num_new)
+  }
+
+  /// Keep only the highlights for which `f` returns true
+  pub(super) fn retain<F>(&mut self, mut f: F)
+  where
+    F: FnMut(&((LineNr, u8, u8), Hl)) -> bool,
+  {
+    let mut kept = HlChunks::default();
+    for hl in self.iter().filter(|hl| f(hl)) {
+      kept.push(hl);
+    }
+    self.0 = kept;
+  }
+
+  /// The
+  /// [`External`](crate::bufdata::highlights::HighlightGroup::External)
+  /// highlights
+  pub(super) fn external(&self) -> Vec<((LineNr, u8, u8), Hl)> {
+    self
       .iter()
-      .enumerate()
-      .find(|(_, ((l, _, _), _))| *l >= lastline)
-      .map_or_else(|| self.0.len(), |(i, ((_, _, _), _))| i + start);
-
-    let num_new = newhls.0.len();
-    let _ = self.0.splice(
-      start..end,
-      newhls.0.into_iter().map(|((l, s, e), h)| ((l, s, e), h)),
+      .filter(|(_, hl)| matches!(hl, Hl::External(_)))
+      .collect()
+  }
+
+  /// Replace the
+  /// [`External`](crate::bufdata::highlights::HighlightGroup::External)
+  /// highlights of the lines `firstline..lastline` by the ones in `spans`,
+  /// ignoring those outside of these lines. Keeps the highlights ordered. Returns
+  /// the range of indices of the highlights of the lines.
+  ///
+  /// Like the highlights nvimpam generates, they're moved by the
+  /// [`splice`](crate::bufdata::highlights::Highlights::splice) of an update,
+  /// and dropped when their line changes. Clearing drops them as well.
+  pub(super) fn set_external(
+    &mut self,
+    spans: Vec<((LineNr, u8, u8), Hl)>,
+    firstline: LineNr,
+    lastline: LineNr,
+  ) -> Range<usize> {
+    let range = self.linerange(firstline, lastline);
+
+    let mut region: Vec<_> = self
+      .0
+      .range(range.clone())
+      .filter(|(_, hl)| !matches!(hl, Hl::External(_)))
+      .collect();
+    region.extend(
+      spans
+        .into_iter()
+        .filter(|((l, _, _), _)| firstline <= *l && *l < lastline),
     );
+    region.sort_by(|a, b| a.0.cmp(&b.0));
 
-    if added != 0 {
-      for t in self.0[start + num_new..].iter_mut() {
-        ((*t).0).0 += added;
-      }
-    }
+    let len = region.len();
+    self.0.splice(range.clone(), region.into_iter(), 0);
 
-    start..(start + num_new)
+    range.start..range.start + len
   }
 
-  /// Add the highlights of a line by pushing them to the end of the `Vec`. Be
-  /// sure that the order of the `Vec` is not destroyed by this!
+  /// Add the highlights of a line by pushing them after all others. Be sure
+  /// that the order of the highlights is not destroyed by this! If the tabs
+  /// of the line are [`expanded`](crate::card::tabs::expanded), the cells are
+  /// found in the expanded line, and their columns converted back to bytes.
   #[inline]
   pub fn add_line_highlights(
     &mut self,
@@ -160,20 +717,48 @@ impl Highlights {
     text: &[u8],
     cardline: &CardLine,
   ) {
-    // We only highlight until column 81
     #![allow(clippy::cast_possible_truncation)]
-    let linelen = cmp::min(text.len(), 81) as u8;
+    let expanded = tabs::expanded(text);
+    let tabbed = matches!(expanded, Cow::Owned(_));
+    let linelen = cmp::min(expanded.len(), usize::from(self.1.columns)) as u8;
     let cells = cardline.cells().unwrap_or(&[]).iter();
     let it = HlIter {
       num,
       cardline,
-      text,
+      text: &expanded,
       linelen,
       until: 0,
       odd: false,
       cells,
     };
-    self.0.extend(it);
+
+    if tabbed {
+      it.map(|((l, s, e), hl)| {
+        ((l, tabs::byte_column(text, s), tabs::byte_column(text, e)), hl)
+      })
+      .filter(|((_, s, e), _)| s < e)
+      .for_each(|hl| self.0.push(hl));
+    } else {
+      it.for_each(|hl| self.0.push(hl));
+    }
+  }
+
+  /// Add the highlights of a `NAME` line, the fixed string `NAME` as a
+  /// keyword and the rest of the line as a title. Like
+  /// [`add_line_highlights`](Highlights::add_line_highlights), this pushes
+  /// them after all others.
+  #[inline]
+  pub fn add_title_highlights(&mut self, num: LineNr, text: &[u8]) {
+    #![allow(clippy::cast_possible_truncation)]
+    let linelen = cmp::min(text.len(), usize::from(self.1.columns)) as u8;
+    let namelen = FixedStr::Name.len();
+
+    let kwend = cmp::min(linelen, namelen);
+
+    self.0.push(((num, 0, kwend), Hl::Keyword));
+    if linelen > namelen {
+      self.0.push(((num, namelen, linelen), Hl::Title));
+    }
   }
 
   /// Return an iterator over the highlights of the lines with linenumber in the
@@ -183,16 +768,8 @@ impl Highlights {
     firstline: LineNr,
     lastline: LineNr,
   ) -> Range<usize> {
-    let start = self
-      .0
-      .binary_search_by_key(&(firstline, 0), |&((l, s, _), _)| (l, s))
-      // error contains index where ele could be inserted preserving Order
-      .unwrap_or_else(|e| e);
-    let end = self.0[start..]
-      .iter()
-      .enumerate()
-      .find(|(_, ((l, _, _), _))| *l >= lastline)
-      .map_or_else(|| self.0.len(), |(i, ((_, _, _), _))| i + start);
+    let start = self.0.first_index_from(firstline);
+    let end = cmp::max(start, self.0.first_index_from(lastline));
 
     start..end
   }
@@ -200,17 +777,22 @@ impl Highlights {
   /// Construct the necessary calls to neovim to highlight the region given by
   /// `firstline..lastline`. Here, `indexrange` gives the index of the
   /// highlights to send. All existing highlights in this linerange are cleare
-  /// beforehand.
+  /// beforehand. If groups are configured for single keywords, the keyword of
+  /// a line is looked up by `keyword`.
   ///
   /// TODO(KillTheMule): efficient? maybe not send strings for the hl groups,
   /// but small ints, and have a mapping ready?
-  pub(super) fn highlight_region_calls(
+  pub(super) fn highlight_region_calls<F>(
     &self,
     buf: &Buffer,
     indexrange: Range<usize>,
     firstline: LineNr,
     lastline: LineNr,
-  ) -> Option<Vec<Value>> {
+    keyword: F,
+  ) -> Option<Vec<Value>>
+  where
+    F: Fn(LineNr) -> Option<Keyword>,
+  {
     if indexrange.start == indexrange.end && firstline == lastline {
       return None;
     }
@@ -222,7 +804,7 @@ impl Highlights {
         Value::from("nvim_buf_clear_highlight".to_string()),
         vec![
           buf.get_value().clone(),
-          Value::from(5),
+          Value::from(self.1.namespace),
           Value::from(firstline),
           Value::from(lastline),
         ]
@@ -231,17 +813,23 @@ impl Highlights {
       .into(),
     );
 
-    calls.extend(self.0[indexrange].iter().map(|((l, s, e), t)| {
-      let st: &'static str = (*t).into();
+    calls.extend(self.0.range(indexrange).map(|((l, s, e), t)| {
+      let group = match t {
+        Hl::Keyword if !self.1.keywords.is_empty() => keyword(l)
+          .and_then(|kw| self.1.keyword_group(kw))
+          .unwrap_or_else(|| self.1.group(t)),
+        _ => self.1.group(t),
+      };
+
       vec![
         Value::from("nvim_buf_add_highlight".to_string()),
         vec![
           buf.get_value().clone(),
-          Value::from(5),
-          Value::from(st.to_string()),
-          Value::from(*l),
-          Value::from(u64::from(*s)),
-          Value::from(u64::from(*e)),
+          Value::from(self.1.namespace),
+          Value::from(group),
+          Value::from(l),
+          Value::from(u64::from(s)),
+          Value::from(u64::from(e)),
         ]
         .into(),
       ]
@@ -251,12 +839,125 @@ impl Highlights {
     Some(calls)
   }
 
+  /// Call `f` with the line, start and end column, and group of the
+  /// highlights with the indices in `indexrange`, with the columns in the
+  /// configured [`Encoding`](crate::bufdata::highlights::Encoding). The text
+  /// of a line is only looked up by `text` if the columns need converting,
+  /// and they're only converted if it holds multibyte characters.
+  fn for_each_encoded<'b, F, G>(
+    &self,
+    indexrange: Range<usize>,
+    text: F,
+    mut f: G,
+  ) where
+    F: Fn(LineNr) -> Option<&'b [u8]>,
+    G: FnMut(LineNr, u64, u64, Hl),
+  {
+    let encoding = self.1.encoding;
+    // The line looked up last, and its text if it needs converting
+    let mut current: Option<(LineNr, Option<&'b [u8]>)> = None;
+
+    for ((l, s, e), t) in self.0.range(indexrange) {
+      let multibyte = match current {
+        _ if encoding == Encoding::Bytes => None,
+        Some((c, m)) if c == l => m,
+        _ => {
+          let m = text(l).filter(|t| !t.is_ascii());
+          current = Some((l, m));
+          m
+        }
+      };
+      let (start, end) = match multibyte {
+        Some(t) => (encoding.column(t, s), encoding.column(t, e)),
+        None => (u64::from(s), u64::from(e)),
+      };
+
+      f(l, start, end, t);
+    }
+  }
+
+  /// The highlights with the indices in `indexrange` as lists
+  /// `[line, start, end, group]`, with the columns in the configured
+  /// [`Encoding`](crate::bufdata::highlights::Encoding), see
+  /// `for_each_encoded`.
+  pub(super) fn positions<'b, F>(
+    &self,
+    indexrange: Range<usize>,
+    text: F,
+  ) -> Vec<Value>
+  where
+    F: Fn(LineNr) -> Option<&'b [u8]>,
+  {
+    let mut positions = Vec::with_capacity(indexrange.len());
+
+    self.for_each_encoded(indexrange, text, |l, start, end, t| {
+      positions.push(Value::from(vec![
+        Value::from(l),
+        Value::from(start),
+        Value::from(end),
+        Value::from(self.1.group(t)),
+      ]))
+    });
+
+    positions
+  }
+
+  /// The highlights with the indices in `indexrange` as LSP semantic tokens,
+  /// with the columns in the configured
+  /// [`Encoding`](crate::bufdata::highlights::Encoding), see
+  /// `for_each_encoded`. Each token is given by 5 numbers: The line relative
+  /// to the line of the token before, the start column relative to the start
+  /// of the token before if it's on the same line, the length, the index of
+  /// its type in [`TOKEN_TYPES`](crate::bufdata::highlights::TOKEN_TYPES)
+  /// and the bits of its modifiers in
+  /// [`TOKEN_MODIFIERS`](crate::bufdata::highlights::TOKEN_MODIFIERS). The
+  /// first token is relative to the start of the buffer. External highlights
+  /// and empty ones are left out.
+  pub(super) fn semantic_tokens<'b, F>(
+    &self,
+    indexrange: Range<usize>,
+    text: F,
+  ) -> Vec<u64>
+  where
+    F: Fn(LineNr) -> Option<&'b [u8]>,
+  {
+    let mut data = Vec::with_capacity(5 * indexrange.len());
+    let mut prev = (0, 0);
+
+    self.for_each_encoded(indexrange, text, |l, start, end, t| {
+      let (typ, modifiers) = match t.semantic_token() {
+        Some(tok) if start < end => tok,
+        _ => return,
+      };
+      let line = usize::from(l) as u64;
+      let (dline, dstart) = match line - prev.0 {
+        0 => (0, start - prev.1),
+        d => (d, start),
+      };
+
+      data.extend_from_slice(&[dline, dstart, end - start, typ, modifiers]);
+      prev = (line, start);
+    });
+
+    data
+  }
+
   #[cfg(test)]
   pub fn add_highlight(&mut self, line: LineNr, start: u8, end: u8, hl: Hl) {
     self.0.push(((line, start, end), hl))
   }
 }
 
+impl From<Vec<((LineNr, u8, u8), Hl)>> for Highlights {
+  fn from(v: Vec<((LineNr, u8, u8), Hl)>) -> Self {
+    let mut highlights = Self::new();
+    for hl in v {
+      highlights.0.push(hl);
+    }
+    highlights
+  }
+}
+
 #[cfg(test)]
 macro_rules! splicetest {
   (
@@ -279,7 +980,7 @@ macro_rules! splicetest {
       h.splice(h1, LineNr::from_usize($first), LineNr::from_usize($last), $added);
       let v = vec![$( (LineNr::from_usize($lll), $($g),+ ),)+];
 
-      let w:Vec<_> = h.0.iter().map(|((l, s, e), h)| (*l, *s, *e, *h)).collect();
+      let w:Vec<_> = h.iter().map(|((l, s, e), h)| (l, s, e, h)).collect();
       assert_eq!(v, w);
     }
   };
@@ -504,8 +1205,276 @@ mod tests {
     ];
 
     // this is not a trivial test, it ascertains the iteration order
-    let w: Vec<_> = h.0.iter().map(|((l, s, e), h)| (*l, *s, *e, *h)).collect();
+    let w: Vec<_> = h.iter().map(|((l, s, e), h)| (l, s, e, h)).collect();
+    assert_eq!(v, w);
+  }
+
+  #[test]
+  pub fn hl_ignored() {
+    use crate::card::{cell::Cell, keyword, line::Line};
+
+    let line = Line::Cells(&[
+      Cell::Kw(keyword::Keyword::Node),
+      Cell::Integer(8),
+      Cell::Blank(8),
+      Cell::Float(8),
+    ]);
+    let mut h = Highlights::new();
+
+    h.add_line_highlights(0.into(), b"NODE  /        1              0.", &line);
+    h.add_line_highlights(
+      1.into(),
+      b"NODE  /        1   x         0.  ab c ",
+      &line,
+    );
+
+    let v = vec![
+      (0.into(), 0, 8, Keyword),
+      (0.into(), 8, 16, CellEven),
+      (0.into(), 16, 24, CellOdd),
+      (0.into(), 24, 32, CellEven),
+      (1.into(), 0, 8, Keyword),
+      (1.into(), 8, 16, CellEven),
+      (1.into(), 16, 24, Ignored),
+      (1.into(), 24, 32, CellEven),
+      (1.into(), 33, 37, Ignored),
+    ];
+    let w: Vec<_> = h.iter().map(|((l, s, e), h)| (l, s, e, h)).collect();
+    assert_eq!(v, w);
+  }
+
+  #[test]
+  pub fn hl_long_lines() {
+    use crate::card::{cell::Cell, keyword, line::Line};
+
+    let line = Line::Cells(&[
+      Cell::Kw(keyword::Keyword::Node),
+      Cell::Integer(8),
+      Cell::Str(72),
+      Cell::Float(200),
+    ]);
+    let mut text = b"NODE  /        1".to_vec();
+    text.extend(vec![b'x'; 500_000]);
+    let mut h = Highlights::new();
+
+    h.add_line_highlights(0.into(), &text, &line);
+    h.add_title_highlights(1.into(), &text);
+    h.set_columns(255);
+    h.add_line_highlights(2.into(), &text, &line);
+
+    let v = vec![
+      (0.into(), 0, 8, Keyword),
+      (0.into(), 8, 16, CellEven),
+      (0.into(), 16, 81, CellOdd),
+      (1.into(), 0, 4, Keyword),
+      (1.into(), 4, 81, Title),
+      (2.into(), 0, 8, Keyword),
+      (2.into(), 8, 16, CellEven),
+      (2.into(), 16, 88, CellOdd),
+      (2.into(), 88, 255, ErrorCellEven),
+    ];
+    let w: Vec<_> = h.iter().map(|((l, s, e), h)| (l, s, e, h)).collect();
     assert_eq!(v, w);
   }
 
+  #[test]
+  pub fn hl_linerange() {
+    let mut h = Highlights::new();
+
+    h.add_highlight(0.into(), 0, 8, Keyword);
+    h.add_highlight(2.into(), 0, 4, Keyword);
+    h.add_highlight(2.into(), 5, 12, CellOdd);
+    h.add_highlight(2.into(), 13, 20, CellEven);
+    h.add_highlight(5.into(), 0, 8, Keyword);
+
+    assert_eq!(h.linerange(0.into(), 1.into()), 0..1);
+    assert_eq!(h.linerange(1.into(), 2.into()), 1..1);
+    assert_eq!(h.linerange(1.into(), 3.into()), 1..4);
+    assert_eq!(h.linerange(2.into(), 6.into()), 1..5);
+    assert_eq!(h.linerange(3.into(), 5.into()), 4..4);
+    assert_eq!(h.linerange(6.into(), 8.into()), 5..5);
+    assert_eq!(h.linerange(3.into(), 1.into()), 4..4);
+  }
+
+  #[test]
+  pub fn hl_splice_chunks() {
+    use crate::{bufdata::highlights::CHUNK_HLS, linenr::LineNr};
+
+    let line = LineNr::from_usize;
+    let hls = |lines: std::ops::Range<usize>| -> Vec<_> {
+      lines
+        .flat_map(|l| {
+          vec![((line(l), 0, 8), Keyword), ((line(l), 8, 16), CellOdd)]
+        })
+        .collect()
+    };
+
+    // Several chunks, compared to splicing a plain Vec
+    let mut h = Highlights::from(hls(0..3 * CHUNK_HLS));
+    let mut v = hls(0..3 * CHUNK_HLS);
+    assert!(h.0.chunks.len() > 1);
+
+    // The lines `first..last` are replaced by `new` lines
+    let splices = [
+      (10, 11, 3),
+      (0, 0, 5),
+      (CHUNK_HLS / 2 - 2, CHUNK_HLS / 2 + 1, 3),
+      (100, 2 * CHUNK_HLS, 1),
+      (4000, 4000, 1000),
+      (3 * CHUNK_HLS, 3 * CHUNK_HLS + 10, 0),
+      (2, 3000, 2998),
+    ];
+
+    for &(first, last, new) in splices.iter() {
+      let added = new as isize - (last - first) as isize;
+      let start = v.partition_point(|((l, _, _), _)| *l < line(first));
+      let end = v.partition_point(|((l, _, _), _)| *l < line(last));
+      let _ = v.splice(start..end, hls(first..first + new));
+      for hl in &mut v[start + 2 * new..] {
+        (hl.0).0 += added;
+      }
+
+      let range = h.splice(
+        Highlights::from(hls(first..first + new)),
+        line(first),
+        line(last),
+        added,
+      );
+
+      assert_eq!(range, start..start + 2 * new);
+      assert_eq!(h.len(), v.len());
+      assert_eq!(h.iter().collect::<Vec<_>>(), v);
+      assert!(h.0.chunks.iter().all(|c| c.hls.len() <= CHUNK_HLS));
+      for l in (0..v.len() / 2).step_by(97) {
+        let start = v.partition_point(|((ll, _, _), _)| *ll < line(l));
+        let end = v.partition_point(|((ll, _, _), _)| *ll < line(l + 3));
+        assert_eq!(h.linerange(line(l), line(l + 3)), start..end);
+      }
+    }
+  }
+
+  #[test]
+  pub fn hl_config() {
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    use crate::{
+      bufdata::highlights::HlConfig,
+      card::keyword::Keyword::{Node, Shell},
+    };
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut h = Highlights::new();
+    h.add_highlight(0.into(), 0, 8, Keyword);
+    h.add_highlight(0.into(), 8, 16, CellOdd);
+
+    let mut config = HlConfig::default();
+    assert!(h.set_config(&buf, config.clone()).is_none());
+
+    config.namespace = 12;
+    let _ = config.groups.insert(Keyword, "Statement".to_string());
+    let clear = h.set_config(&buf, config).unwrap();
+    assert_eq!(
+      clear[0],
+      Value::from(vec![
+        Value::from("nvim_buf_clear_highlight"),
+        Value::from(vec![
+          Value::from(0_usize),
+          Value::from(5),
+          Value::from(0),
+          Value::from(-1)
+        ])
+      ])
+    );
+
+    let calls = h
+      .highlight_region_calls(&buf, 0..2, 0.into(), 1.into(), |_| None)
+      .unwrap();
+    let args: Vec<_> = calls[1..]
+      .iter()
+      .map(|c| c.as_array().unwrap()[1].as_array().unwrap().to_vec())
+      .collect();
+    assert_eq!(args[0][1], Value::from(12));
+    assert_eq!(args[0][2], Value::from("Statement"));
+    assert_eq!(args[1][2], Value::from("PamCellOdd"));
+
+    h.1.keywords.push((Node, "MyNodeHl".to_string()));
+    let group = |kw| {
+      let calls = h
+        .highlight_region_calls(&buf, 0..2, 0.into(), 1.into(), |_| kw)
+        .unwrap();
+      calls[1].as_array().unwrap()[1].as_array().unwrap()[2].clone()
+    };
+    assert_eq!(group(Some(Node)), Value::from("MyNodeHl"));
+    assert_eq!(group(Some(Shell)), Value::from("Statement"));
+    assert_eq!(group(None), Value::from("Statement"));
+  }
+
+  #[test]
+  pub fn hl_encoding() {
+    use neovim_lib::Value;
+
+    use crate::{bufdata::highlights::Encoding, linenr::LineNr};
+
+    let text = "ab\u{e4}c\u{1f600}d".as_bytes();
+    assert_eq!(Encoding::Bytes.column(text, 5), 5);
+    assert_eq!(Encoding::Chars.column(text, 5), 4);
+    assert_eq!(Encoding::Chars.column(text, 9), 5);
+    assert_eq!(Encoding::Utf16.column(text, 9), 6);
+    assert_eq!(Encoding::Utf16.column(text, 12), 9);
+    assert_eq!(Encoding::from_name("UTF-16"), Some(Encoding::Utf16));
+    assert_eq!(Encoding::from_name("latin1"), None);
+
+    let mut h = Highlights::new();
+    h.add_highlight(0.into(), 0, 4, Keyword);
+    h.add_highlight(0.into(), 4, 10, CellOdd);
+    h.add_highlight(1.into(), 0, 4, Keyword);
+    let lines = ["ab\u{e4}c\u{1f600}d", "abcd"];
+    let text = |l: LineNr| lines.get(usize::from(l)).map(|t| t.as_bytes());
+
+    let ends = |h: &Highlights| -> Vec<Value> {
+      h.positions(0..3, text)
+        .into_iter()
+        .map(|v| v.as_array().unwrap()[2].clone())
+        .collect()
+    };
+    assert_eq!(
+      ends(&h),
+      vec![Value::from(4), Value::from(10), Value::from(4)]
+    );
+
+    h.1.encoding = Encoding::Utf16;
+    assert_eq!(
+      ends(&h),
+      vec![Value::from(3), Value::from(7), Value::from(4)]
+    );
+  }
+
+  #[test]
+  pub fn semantic_tokens() {
+    use crate::{
+      bufdata::highlights::{Encoding, HighlightGroup},
+      linenr::LineNr,
+    };
+
+    let mut h = Highlights::new();
+    h.add_highlight(0.into(), 0, 4, Keyword);
+    h.add_highlight(0.into(), 4, 10, CellOdd);
+    h.add_highlight(2.into(), 0, 8, Keyword);
+    h.add_highlight(2.into(), 8, 16, HighlightGroup::external("Search"));
+    h.add_highlight(2.into(), 16, 24, ErrorCellOdd);
+    let lines = ["ab\u{e4}c\u{1f600}d", "", "abcd"];
+    let text = |l: LineNr| lines.get(usize::from(l)).map(|t| t.as_bytes());
+
+    assert_eq!(
+      h.semantic_tokens(0..5, text),
+      vec![0, 0, 4, 0, 0, 0, 4, 6, 2, 1, 2, 0, 8, 0, 0, 0, 16, 8, 2, 3]
+    );
+    assert_eq!(h.semantic_tokens(2..5, text)[..2], [2, 0]);
+
+    h.1.encoding = Encoding::Utf16;
+    assert_eq!(
+      h.semantic_tokens(0..2, text),
+      vec![0, 0, 3, 0, 0, 0, 3, 4, 2, 1]
+    );
+  }
 }
@@ -0,0 +1,128 @@
+//! The metadata of a deck, as declared by the control lines at its top, e.g.
+//!
+//! ```text
+//! INPUTVERSION 2018
+//! ANALYSIS EXPLICIT
+//! SOLVER CRASH
+//! UNIT MM KG MS KELVIN
+//! TITLE / Example
+//! ```
+//!
+//! The input version determines the layout of some cards, see
+//! [`card::version`](crate::card::version).
+use neovim_lib::Value;
+
+use crate::{card::version::InputVersion, lines::LineRef};
+
+/// The header lines of a deck. Entries are `None` if the corresponding line
+/// is missing or could not be parsed.
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct DeckMetadata {
+  pub version: Option<InputVersion>,
+  pub analysis: Option<String>,
+  pub solver: Option<String>,
+  pub unit: Option<String>,
+  pub title: Option<String>,
+}
+
+/// The rest of `text` if it starts with `prefix`, trimmed and without a
+/// leading `/`
+fn header_value(text: &[u8], prefix: &[u8]) -> Option<String> {
+  if !text.starts_with(prefix) {
+    return None;
+  }
+
+  let rest = String::from_utf8_lossy(&text[prefix.len()..]);
+  let rest = rest.trim();
+  let rest = rest.trim_start_matches('/').trim_start();
+
+  Some(rest.to_string())
+}
+
+impl DeckMetadata {
+  /// Parse the metadata from the lines of a buffer. Only the lines before
+  /// the first keyword are considered, which is where the control cards need
+  /// to be. If a line occurs several times, the first occurence counts.
+  pub fn parse<'a, I>(lines: I) -> Self
+  where
+    I: Iterator<Item = LineRef<'a>>,
+  {
+    let mut meta = DeckMetadata::default();
+
+    for line in lines.take_while(|l| l.keyword.is_none()) {
+      let text = line.text.as_ref();
+
+      if meta.version.is_none() {
+        meta.version = header_value(text, b"INPUTVERSION")
+          .and_then(|v| InputVersion::parse(&v));
+      }
+
+      for (prefix, entry) in &mut [
+        (&b"ANALYSIS"[..], &mut meta.analysis),
+        (&b"SOLVER"[..], &mut meta.solver),
+        (&b"UNIT"[..], &mut meta.unit),
+        (&b"TITLE"[..], &mut meta.title),
+      ] {
+        if entry.is_none() {
+          **entry = header_value(text, prefix);
+        }
+      }
+    }
+
+    meta
+  }
+}
+
+impl<'a> From<&'a DeckMetadata> for Value {
+  fn from(m: &'a DeckMetadata) -> Self {
+    let mut map = vec![];
+
+    if let Some(version) = m.version {
+      map.push((Value::from("version"), Value::from(version.to_string())));
+    }
+
+    for (key, entry) in &[
+      ("analysis", &m.analysis),
+      ("solver", &m.solver),
+      ("unit", &m.unit),
+      ("title", &m.title),
+    ] {
+      if let Some(e) = entry {
+        map.push((Value::from(*key), Value::from(e.as_str())));
+      }
+    }
+
+    Value::from(map)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::metadata::DeckMetadata, card::version::InputVersion, lines::Lines,
+  };
+
+  const HEADER: &str = "INPUTVERSION 2008.0\n$ Comment\nANALYSIS EXPLICIT\n\
+                        UNIT MM KG MS KELVIN\nTITLE / Example deck\n\
+                        NODE  /        1              0.             0.5\n\
+                        INPUTVERSION 2018\n";
+
+  #[test]
+  fn parse_metadata() {
+    let mut lines = Lines::new();
+    lines.parse_slice(HEADER.as_ref());
+    let meta = DeckMetadata::parse(lines.iter());
+
+    assert_eq!(meta.version, Some(InputVersion::new(2008, 0)));
+    assert_eq!(meta.analysis.as_ref().map(String::as_str), Some("EXPLICIT"));
+    assert_eq!(meta.solver, None);
+    assert_eq!(
+      meta.unit.as_ref().map(String::as_str),
+      Some("MM KG MS KELVIN")
+    );
+    assert_eq!(
+      meta.title.as_ref().map(String::as_str),
+      Some("Example deck")
+    );
+  }
+}
@@ -1,25 +1,77 @@
 //! This module provides the [`BufData`](crate::bufdata::BufData) struct to
 //! manage the lines, folds and highlights in a buffer.
 
+pub mod banner;
+pub mod cards;
+pub mod comment;
+pub mod degraded;
+pub mod foldgroup;
 pub mod folds;
+pub mod foldstate;
+pub mod foldtext;
 pub mod highlights;
+pub mod metadata;
+pub mod modified;
+pub mod prescan;
+pub mod refresh;
+#[cfg(test)]
+mod snapshot;
+pub mod stats;
+pub mod viewed;
 
-use std::ops::Range;
+use std::{
+  cmp::Reverse,
+  collections::{BTreeMap, HashMap},
+  fs,
+  ops::Range,
+  path::Path,
+  ptr,
+  sync::atomic::{AtomicBool, Ordering},
+  time::Instant,
+};
 
-use failure::Error;
+use failure::{Error, ResultExt};
+use itertools::Itertools;
+use log::{info, warn};
 
 use neovim_lib::{neovim_api::Buffer, Value};
 
 use crate::{
-  bufdata::{folds::Folds, highlights::Highlights},
+  bufdata::{
+    banner::{Banners, Pattern},
+    cards::Cards,
+    degraded::Degraded,
+    foldgroup::FoldGroups,
+    folds::{ExprLevel, Folds},
+    foldstate::FoldState,
+    foldtext::FoldTexts,
+    highlights::{HighlightGroup as Hl, Highlights, HlConfig},
+    metadata::DeckMetadata,
+    modified::ModifiedLines,
+    refresh::{Refresh, RegionLine},
+    stats::{KeywordCount, Stats, Timings},
+  },
+  card::{
+    align::{self, Alignment},
+    ges::{GesItem, GesLine, GesType},
+    keyword::Keyword,
+    line::name_continues,
+    number::{self, Units},
+    section::{Section, SectionRule},
+    tabs,
+    value::{self, CellData},
+    version::{self, InputVersion},
+    xref::{self, Entity, XrefIndex},
+  },
+  carddata::{auxiliaries, CONVERSIONS},
+  diagnostics::{Diagnostic, Severity},
+  diff::DeckCard,
+  index::{self, Index},
   linenr::LineNr,
-  lines::{Lines, ParsedLine},
+  lines::{LineRef, Lines, ParsedLine},
   linesiter::LinesIter,
 };
 
-#[cfg(test)]
-use crate::card::keyword::Keyword;
-
 macro_rules! unwrap_or_ok {
   ($option:expr) => {
     match $option {
@@ -35,6 +87,71 @@ macro_rules! unwrap_or_ok {
   };
 }
 
+/// The folds and highlights parsed from some lines of the buffer, see
+/// [`parse_region`](crate::bufdata::BufData::parse_region)
+#[derive(Default)]
+pub struct ParsedRegion {
+  highlights: Highlights,
+  folds: Folds,
+  folds_ges: BTreeMap<[LineNr; 2], (usize, String)>,
+}
+
+/// An update of the lines of the buffer whose folds and highlights have not
+/// been updated yet, see
+/// [`prepare_update`](crate::bufdata::BufData::prepare_update)
+#[derive(Debug)]
+pub struct PendingUpdate {
+  firstline: LineNr,
+  lastline: LineNr,
+  added: isize,
+  /// The lines of the cards around the changed lines before the update,
+  /// end-exclusive
+  window: [LineNr; 2],
+  /// The indices of the lines to parse after the update
+  parse_range: Range<usize>,
+  /// If the update changed the input version of the deck
+  version_changed: bool,
+  start: Instant,
+}
+
+impl PendingUpdate {
+  /// The number of lines added by the update
+  pub fn added(&self) -> isize {
+    self.added
+  }
+
+  /// The first and last line changed by the update, in the line numbers after
+  /// the update, end-exclusive
+  pub fn changed_lines(&self) -> [LineNr; 2] {
+    [self.firstline, self.lastline + self.added]
+  }
+
+  /// If the update changed the input version of the deck. In that case, the
+  /// caller needs to [`regenerate`](crate::bufdata::BufData::regenerate) the
+  /// buffer after finishing it, since other cards might have a different
+  /// layout now.
+  pub fn version_changed(&self) -> bool {
+    self.version_changed
+  }
+
+  /// If the cards parsed for this update contain all the ones parsed for the
+  /// `previous` update, which was prepared right before. In that case, the
+  /// results of the previous parse do not matter, since they're replaced
+  /// when finishing this update.
+  pub fn covers(&self, previous: &PendingUpdate) -> bool {
+    self.window[0] <= previous.window[0]
+      && previous.window[1] + previous.added <= self.window[1]
+  }
+}
+
+/// A copy of the lines, the level 1 folds and the metadata of the buffer, to
+/// answer requests in another thread, see [`background`](crate::background)
+pub struct Snapshot {
+  lines: Vec<ParsedLine<'static>>,
+  folds: Folds,
+  metadata: DeckMetadata,
+}
+
 /// The datastructure to hold all the information of a buffer.
 pub struct BufData<'a> {
   /// The buffer the plugin is attached to
@@ -45,8 +162,58 @@ pub struct BufData<'a> {
   folds: Folds,
   /// The level 2 folds.
   folds_level2: Folds,
+  /// The level 1 folds whose foldtext changed in the last update
+  resized_folds: Vec<[LineNr; 2]>,
+  /// The `foldexpr` values of all lines, see
+  /// [`fold_level`](crate::bufdata::BufData::fold_level). Computed on demand,
+  /// and dropped when the folds change.
+  expr_levels: Option<Vec<ExprLevel>>,
+  /// The folds of the GES regions inside of cards, along with the number of
+  /// entries and their foldtexts
+  folds_ges: BTreeMap<[LineNr; 2], (usize, String)>,
+  /// The comment blocks of the buffer, to fold the banners among them
+  banners: Banners,
+  /// Foldtexts overriding the generated ones, keyed by the fold's range.
+  /// Ranges without a generated fold are additional custom folds.
+  custom_folds: BTreeMap<[LineNr; 2], String>,
   /// The highlights of the buffer
   pub highlights: Highlights,
+  /// The metadata declared at the top of the buffer
+  metadata: DeckMetadata,
+  /// The templates replacing the generated foldtexts
+  foldtexts: FoldTexts,
+  /// The rules grouping the level 1 folds of several keywords
+  fold_groups: FoldGroups,
+  /// If the highlights are generated and sent to neovim
+  highlight: bool,
+  /// If the buffer is checked for duplicate IDs after parsing
+  check_duplicates: bool,
+  /// The IDs defined more than once, see
+  /// [`duplicates`](crate::card::xref::duplicates)
+  duplicates: Vec<Diagnostic>,
+  /// The IDs on the keyword lines, kept up to date once built by
+  /// [`index_xrefs`](crate::bufdata::BufData::index_xrefs)
+  xrefs: Option<XrefIndex>,
+  /// How long the last parse and update took
+  timings: Timings,
+  /// The number of updates prepared, but not finished yet. The line numbers
+  /// of the folds are only up to date if there are none.
+  pending: usize,
+  /// The lines changed since the buffer was last written
+  modified: ModifiedLines,
+  /// The size of the buffer in bytes above which it's degraded, and the mode
+  /// it's degraded to
+  max_size: Option<(usize, Degraded)>,
+  /// The mode the buffer is degraded to, if it's too large
+  degraded: Option<Degraded>,
+  /// If the data is checked for consistency after each update, see
+  /// [`check_consistency`](crate::bufdata::BufData::check_consistency)
+  strict: bool,
+  /// The inconsistency found by the last update, not reported yet
+  inconsistency: Option<String>,
+  /// If the last parse of the whole buffer was skipped since it contains no
+  /// cards, not reported yet
+  no_cards: bool,
 }
 
 impl<'a> BufData<'a> {
@@ -57,79 +224,510 @@ impl<'a> BufData<'a> {
       lines: Lines::new(),
       folds: Folds::new(),
       folds_level2: Folds::new(),
+      resized_folds: vec![],
+      expr_levels: None,
+      folds_ges: BTreeMap::new(),
+      banners: Banners::new(),
+      custom_folds: BTreeMap::new(),
       highlights: Highlights::new(),
+      metadata: DeckMetadata::default(),
+      foldtexts: FoldTexts::default(),
+      fold_groups: FoldGroups::default(),
+      highlight: true,
+      check_duplicates: false,
+      duplicates: vec![],
+      xrefs: None,
+      timings: Timings::default(),
+      pending: 0,
+      modified: ModifiedLines::default(),
+      max_size: None,
+      degraded: None,
+      strict: false,
+      inconsistency: None,
+      no_cards: false,
+    }
+  }
+
+  /// Copy the data needed to answer requests in another thread, see
+  /// [`from_snapshot`](crate::bufdata::BufData::from_snapshot)
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      lines: self.lines.iter().map(|l| l.to_owned_line()).collect(),
+      folds: self.folds.clone(),
+      metadata: self.metadata.clone(),
     }
   }
 
+  /// Recreate the `BufData` of the buffer `buf` from a `snapshot`. It has no
+  /// highlights, and only the level 1 folds.
+  pub fn from_snapshot(buf: &'a Buffer, snapshot: Snapshot) -> Self {
+    let mut bufdata = BufData::new(buf);
+    bufdata.lines = Lines::from(snapshot.lines);
+    bufdata.folds = snapshot.folds;
+    bufdata.metadata = snapshot.metadata;
+
+    bufdata
+  }
+
   pub fn clear(&mut self) {
     self.lines.clear();
     self.folds.clear();
     self.folds_level2.clear();
+    self.resized_folds.clear();
+    self.expr_levels = None;
+    self.folds_ges.clear();
+    self.banners.clear();
+    self.custom_folds.clear();
     self.highlights.clear();
+    self.metadata = DeckMetadata::default();
+    self.duplicates.clear();
+    if self.xrefs.is_some() {
+      self.xrefs = Some(XrefIndex::default());
+    }
+    self.pending = 0;
+    self.modified.clear();
   }
 
   /// Extend the lines of the buffer by splitting the slice on newlines. Parse
-  /// for new keywords, and update the folds/highlights appropriately.
+  /// for new keywords, and update the folds/highlights appropriately. If
+  /// the lines contain no cards, they are only stored, see
+  /// [`prescan`](crate::bufdata::prescan).
   ///
   /// Assumes the `BufData` was empty before.
   pub fn parse_slice<'c: 'a>(&mut self, v: &'c [u8]) -> Result<(), Error> {
+    self.check_size(v.len());
+    let skip = prescan::no_cards(v.split(|&b| b == b'\n'));
+    if !skip {
+      self.banners.parse(v.split(|&b| b == b'\n'));
+    }
     self.lines.parse_slice(v);
-    self.regenerate()?;
 
+    if skip {
+      self.skip_parse();
+    } else {
+      self.regenerate()?;
+    }
     Ok(())
   }
 
   /// Extend the lines of the buffer by the `String`s in the `Vec`. Parse
-  /// for new keywords, and update the folds/highlights appropriately.
+  /// for new keywords, and update the folds/highlights appropriately, like
+  /// [`parse_slice`](crate::bufdata::BufData::parse_slice).
   ///
   /// Assumes the `BufData` was empty before.
   pub fn parse_vec(&mut self, v: Vec<String>) -> Result<(), Error> {
+    self.check_size(v.iter().map(|l| l.len() + 1).sum());
+    let skip = prescan::no_cards(&v);
+    if !skip {
+      self.banners.parse(&v);
+    }
     self.lines.parse_vec(v);
-    self.regenerate()?;
 
+    if skip {
+      self.skip_parse();
+    } else {
+      self.regenerate()?;
+    }
     Ok(())
   }
 
   /// Extend the lines of the buffer by the `&str`s in the `slice`. Parse
-  /// for new keywords, and update the folds/highlights appropriately.
+  /// for new keywords, and update the folds/highlights appropriately, like
+  /// [`parse_slice`](crate::bufdata::BufData::parse_slice).
   ///
   /// Assumes the `BufData` was empty before.
   pub fn parse_strs<'c: 'a>(&mut self, v: &'c [&'a str]) -> Result<(), Error> {
+    self.check_size(v.iter().map(|l| l.len() + 1).sum());
+    let skip = prescan::no_cards(v);
+    if !skip {
+      self.banners.parse(v);
+    }
     self.lines.parse_strs(v);
-    self.regenerate()?;
+
+    if skip {
+      self.skip_parse();
+    } else {
+      self.regenerate()?;
+    }
+    Ok(())
+  }
+
+  /// Clear the folds and highlights instead of parsing the lines, since the
+  /// [`prescan`](crate::bufdata::prescan) found no cards. The buffer is
+  /// marked to report that, see
+  /// [`take_no_cards`](crate::bufdata::BufData::take_no_cards).
+  fn skip_parse(&mut self) {
+    info!("No cards found, skipping the parser");
+    self.folds.clear();
+    self.folds_level2.clear();
+    self.resized_folds.clear();
+    self.expr_levels = None;
+    self.folds_ges.clear();
+    self.banners.clear();
+    let external = self.highlights.external();
+    self.highlights.clear();
+    if !external.is_empty() {
+      let first = LineNr::from_usize(0);
+      let end = self.end();
+      let _ = self.highlights.set_external(external, first, end);
+    }
+    self.metadata = DeckMetadata::default();
+    if self.xrefs.is_some() {
+      self.xrefs = Some(XrefIndex::new(&self.keyword_lines()));
+    }
+    self.duplicates.clear();
+    self.no_cards = true;
+  }
+
+  /// If the last parse of the whole buffer found no cards and was skipped,
+  /// see [`prescan`](crate::bufdata::prescan). Only returns true once per
+  /// parse, so it's reported a single time.
+  pub fn take_no_cards(&mut self) -> bool {
+    std::mem::take(&mut self.no_cards)
+  }
+
+  /// Set the size of the buffer in bytes above which it's degraded to `mode`
+  /// when it's parsed as a whole. Once degraded, the buffer stays that way.
+  pub fn set_max_size(&mut self, size: usize, mode: Degraded) {
+    self.max_size = Some((size, mode));
+  }
+
+  /// The mode the buffer is degraded to, if it exceeded the size set by
+  /// [`set_max_size`](crate::bufdata::BufData::set_max_size)
+  pub fn degraded(&self) -> Option<Degraded> {
+    self.degraded
+  }
+
+  /// Check the data of the buffer for consistency after each update. An
+  /// inconsistency is recorded to be reported to the user, and the buffer is
+  /// parsed anew.
+  pub fn set_strict(&mut self, strict: bool) {
+    self.strict = strict;
+  }
+
+  /// Take the inconsistency found by the last update, if any. Only set in
+  /// strict mode, see [`set_strict`](crate::bufdata::BufData::set_strict).
+  pub fn take_inconsistency(&mut self) -> Option<String> {
+    self.inconsistency.take()
+  }
+
+  /// Check the internal invariants of the data: The number of lines matches
+  /// the lines stored, their numbers are strictly increasing, the folds don't
+  /// overlap and lie within the buffer, level 1 folds start at a keyword, the
+  /// index of the IDs matches the lines, and the highlights are sorted by
+  /// line.
+  pub fn check_consistency(&self) -> Result<(), Error> {
+    let count = self.lines.iter().count();
+    if count != self.lines.len() {
+      return Err(failure::err_msg(format!(
+        "{} lines stored, but the length is {}",
+        count,
+        self.lines.len()
+      )));
+    }
+
+    let mut prev: Option<LineNr> = None;
+    for line in self.lines.iter() {
+      if let Some(p) = prev {
+        if line.number <= p {
+          return Err(failure::err_msg(format!(
+            "Line {} follows line {}",
+            line.number, p
+          )));
+        }
+      }
+      prev = Some(line.number);
+    }
+
+    let end = self.end();
+    for (level, folds) in [(1, &self.folds), (2, &self.folds_level2)].iter() {
+      if let Some(r) = folds.overlapping(LineNr::from_usize(0), LineNr::MAX) {
+        return Err(failure::err_msg(format!(
+          "Level {} fold {}-{} overlaps another one",
+          level, r[0], r[1]
+        )));
+      }
+      let outside = folds.iter().find(|(r, _)| r[1] < r[0] || end <= r[1]);
+      if let Some((r, _)) = outside {
+        return Err(failure::err_msg(format!(
+          "Level {} fold {}-{} is not within the {} lines of the buffer",
+          level, r[0], r[1], end
+        )));
+      }
+    }
+
+    for (r, _) in self.folds.iter() {
+      if line_keyword(&self.lines, r[0]).is_none() {
+        return Err(failure::err_msg(format!(
+          "Fold {}-{} does not start at a keyword",
+          r[0], r[1]
+        )));
+      }
+    }
+
+    if let Some(ref xrefs) = self.xrefs {
+      if *xrefs != XrefIndex::new(&self.keyword_lines()) {
+        return Err(failure::err_msg(
+          "The index of the IDs does not match the keyword lines",
+        ));
+      }
+    }
+
+    if let Some((prev, next)) = self
+      .highlights
+      .iter()
+      .tuple_windows()
+      .find(|(p, n)| n.0 < p.0)
+    {
+      return Err(failure::err_msg(format!(
+        "Highlight in line {} follows one in line {}",
+        (next.0).0,
+        (prev.0).0
+      )));
+    }
 
     Ok(())
   }
 
+  /// Record the inconsistency `error` found after replacing the lines
+  /// `firstline..lastline` by `added` more lines, if in strict mode
+  fn record_inconsistency(
+    &mut self,
+    error: &str,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+  ) {
+    if self.strict {
+      self.inconsistency = Some(format!(
+        "Inconsistent data after updating lines {}-{} ({:+} lines): {}. The \
+         buffer was parsed anew.",
+        firstline + 1,
+        lastline,
+        added,
+        error
+      ));
+    }
+  }
+
+  /// Degrade the buffer if `size` exceeds the maximum size. Called before
+  /// parsing the lines, so the highlights are not generated in the first
+  /// place if the mode drops them.
+  fn check_size(&mut self, size: usize) {
+    if let Some((max, mode)) = self.max_size {
+      if size > max && self.degraded.is_none() {
+        warn!("Buffer has {} bytes, degrading to mode '{}'", size, mode);
+        self.degraded = Some(mode);
+        self.highlight &= mode.highlights();
+      }
+    }
+  }
+
+  /// Set the patterns of the comment banners to fold, see
+  /// [`banner`](crate::bufdata::banner). They're used for the lines parsed
+  /// afterwards.
+  pub fn set_banner_patterns(&mut self, patterns: Vec<Pattern>) {
+    self.banners.set_patterns(patterns);
+  }
+
   /// After adding lines and the keywords of a `BufData` structure, this
   /// computes the folds and highlights. Everything's cleared beforehand, so it
   /// should only be used after the initalization. Use
-  /// [`update`](crate::bufdata::BufData::update) otherwise.
+  /// [`update`](crate::bufdata::BufData::update) otherwise. The highlights
+  /// other tools pushed are kept, see
+  /// [`set_external_highlights`](crate::bufdata::BufData::set_external_highlights).
   pub fn regenerate(&mut self) -> Result<(), Error> {
+    let start = Instant::now();
     self.folds.clear();
     self.folds_level2.clear();
+    self.resized_folds.clear();
+    self.expr_levels = None;
+    self.folds_ges.clear();
+    // The lines did not change, so the highlights of other tools still fit
+    let external = self.highlights.external();
     self.highlights.clear();
+    self.metadata = DeckMetadata::parse(self.lines.iter());
 
     self.parse_lines()?;
-    self.folds_level2.recreate_level2(&self.folds)?;
+    if !external.is_empty() {
+      let first = LineNr::from_usize(0);
+      let end = self.end();
+      let _ = self.highlights.set_external(external, first, end);
+    }
+    self
+      .folds_level2
+      .recreate_level2(&self.folds, &self.fold_groups)?;
+    if self.xrefs.is_some() {
+      self.xrefs = Some(XrefIndex::new(&self.keyword_lines()));
+    }
+    self.find_duplicates();
+    self.timings.parse = Some(start.elapsed());
 
     Ok(())
   }
 
+  /// Replace all lines of the buffer by the `String`s in the `Vec`, e.g.
+  /// because neovim sent the whole buffer again after `:e!`. Only the lines
+  /// between the card regions that did not change are parsed anew, see
+  /// [`refresh`](crate::bufdata::refresh). The whole buffer is parsed if
+  /// there are pending updates, or if the input version changed.
+  pub fn refresh(
+    &mut self,
+    mut linedata: Vec<String>,
+  ) -> Result<Refresh, Error> {
+    // Neovim sends the whole buffer after reading the file again
+    self.modified.clear();
+
+    if self.lines.is_empty() || self.pending > 0 {
+      self.lines.clear();
+      self.pending = 0;
+      self.parse_vec(linedata)?;
+      return Ok(Refresh::Parsed);
+    }
+
+    // The comments are not compared, so their banners are looked for anew
+    let banners = self.banners.blocks(&linedata);
+    let changed = {
+      let old: Vec<RegionLine> = self
+        .lines
+        .iter()
+        .map(|l| (l.number, l.text.as_ref(), l.keyword.is_some()))
+        .collect();
+      let new: Vec<RegionLine> = linedata
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !s.starts_with('$') && !s.starts_with('#'))
+        .map(|(i, s)| {
+          let text = s.as_bytes();
+          let text = text.strip_suffix(b"\r").unwrap_or(text);
+          (LineNr::from_usize(i), text, Keyword::parse(text).is_some())
+        })
+        .collect();
+
+      refresh::changed_lines(&old, &new)
+    };
+
+    let [firstline, lastline, newlast] = match changed {
+      Some(c) => c,
+      None => {
+        self.banners.set_blocks(banners);
+        return Ok(Refresh::Unchanged);
+      }
+    };
+
+    linedata.truncate(newlast.into());
+    let _ = linedata.drain(..usize::from(firstline));
+
+    let version = self.metadata.version;
+    let (range, added) = self.update(firstline, lastline, linedata)?;
+    self.banners.set_blocks(banners);
+    if self.metadata.version != version {
+      self.regenerate()?;
+      return Ok(Refresh::Parsed);
+    }
+
+    Ok(Refresh::Updated {
+      range,
+      firstline,
+      lastline,
+      added,
+    })
+  }
+
   /// Update the `BufData` structure from the lines of a `Vec<String>`. Tries to
   /// be as efficient as possible. Returns the range of indices with new
   /// highlights. This is usefull to call
   /// [`highlight_region_calls`](crate::bufdata::BufData::
   /// highlight_region_calls) afterwards.
+  ///
+  /// The [`metadata`](crate::bufdata::BufData::metadata) is updated as well.
+  /// If the input version changed, the caller needs to
+  /// [`regenerate`](crate::bufdata::BufData::regenerate) the buffer, since
+  /// other cards might have a different layout now.
+  ///
+  /// This is [`prepare_update`](crate::bufdata::BufData::prepare_update)
+  /// and [`finish_update`](crate::bufdata::BufData::finish_update) in one go,
+  /// parsing the changed cards in between.
   pub fn update(
     &mut self,
     firstline: LineNr,
     lastline: LineNr,
     linedata: Vec<String>,
   ) -> Result<(Range<usize>, isize), Error> {
-    let added: isize = linedata.len() as isize - (lastline - firstline);
+    let pending = self.prepare_update(firstline, lastline, linedata);
+
+    let li = LinesIter::new(self.lines.range(pending.parse_range.clone()))
+      .with_version(self.metadata.version);
+    let mut parsed = ParsedRegion::default();
+    parsed.highlights.set_columns(self.highlights.columns());
+    BufData::parse_from_iter(
+      &mut parsed.highlights,
+      &mut parsed.folds,
+      &mut parsed.folds_ges,
+      li.with_highlights(self.highlight),
+      None,
+    )?;
+
+    self.finish_update(pending, parsed)
+  }
+
+  /// The start of the card containing the keyword line `line`, given by its
+  /// index and number. It's the line itself unless it's inside of a card that
+  /// does not start there, e.g. in an opaque block of lines.
+  fn card_start(&self, mut line: (usize, LineNr)) -> (usize, LineNr) {
+    while let Some(r) = self.inner_fold(line) {
+      line = self.lines.first_before(r[0]);
+    }
+
+    line
+  }
+
+  /// The end of the card before the keyword line `line`, given by its index
+  /// and number, i.e. the first line after it. Like
+  /// [`card_start`](crate::bufdata::BufData::card_start), this skips to the
+  /// first keyword line after the card containing `line`.
+  fn card_end(&self, mut line: (usize, LineNr)) -> (usize, LineNr) {
+    while let Some(r) = self.inner_fold(line) {
+      line = self.lines.first_after(r[1] + 1);
+    }
+
+    line
+  }
+
+  /// The level 1 fold containing the keyword line `line` if the line does not
+  /// start a card, i.e. if the fold does not start there and it's either a
+  /// fold of a single card or the line has a different keyword.
+  fn inner_fold(&self, line: (usize, LineNr)) -> Option<[LineNr; 2]> {
+    let r = self.folds.containing(line.1).filter(|r| r[0] < line.1)?;
+    let kw = self.folds.get(&r)?.0;
+    let ownfold = version::card_for(kw, self.metadata.version).ownfold;
+
+    if !ownfold && self.lines.get(line.0).and_then(|l| l.keyword) == Some(kw) {
+      None
+    } else {
+      Some(r)
+    }
+  }
+
+  /// Update the lines and the metadata of the buffer, but not its folds and
+  /// highlights. Their update needs to be finished by
+  /// [`finish_update`](crate::bufdata::BufData::finish_update) after parsing
+  /// the [`pending_lines`](crate::bufdata::BufData::pending_lines).
+  ///
+  /// Several updates can be pending, as long as they're finished in the order
+  /// they were prepared in.
+  pub fn prepare_update(
+    &mut self,
+    firstline: LineNr,
+    lastline: LineNr,
+    linedata: Vec<String>,
+  ) -> PendingUpdate {
+    let start = Instant::now();
+    // The new end of the changed lines minus the old one
+    let added = (firstline + LineNr::from_usize(linedata.len())) - lastline;
+    self.modified.update(firstline, lastline, added);
     let mut first_pre = self.lines.first_before(firstline);
-    let last_pre = self.lines.first_after(lastline);
+    let mut last_pre = self.lines.first_after(lastline);
 
     let adjust_first = self
       .lines
@@ -142,23 +740,220 @@ impl<'a> BufData<'a> {
       // last line's data, but we want the virtual one after that
       first_pre.0 += 1;
       first_pre.1 += 1;
+    } else if self.pending == 0 {
+      first_pre = self.card_start(first_pre);
+    }
+
+    if self.pending == 0 {
+      last_pre = self.card_end(last_pre);
     }
+    self.pending += 1;
 
+    let version = self.metadata.version;
+    self.banners.splice(firstline, lastline, added, &linedata);
     let added_nocom = self.lines.update(linedata, firstline, lastline, added);
+    self.metadata = DeckMetadata::parse(self.lines.iter());
+
+    if let Some(ref mut xrefs) = self.xrefs {
+      let newlast = lastline.saturating_add(added);
+      let start = self.lines.search(firstline).unwrap_or_else(|e| e);
+      let end = self.lines.search(newlast).unwrap_or_else(|e| e);
+      let kwlines: Vec<_> = self
+        .lines
+        .range(start..end)
+        .filter_map(|l| l.keyword.map(|k| (l.number, l.text.as_ref(), k)))
+        .collect();
+      xrefs.splice(firstline, lastline, added, &kwlines);
+    }
 
     let first_post = first_pre.0;
     // TODO(KillTheMule): Check this!
-    let last_post = ((last_pre.0 as isize) + added_nocom) as usize;
+    let last_post = (LineNr::from_usize(last_pre.0) + added_nocom).into();
+
+    PendingUpdate {
+      firstline,
+      lastline,
+      added,
+      window: [first_pre.1, last_pre.1],
+      parse_range: first_post..last_post,
+      version_changed: self.metadata.version != version,
+      start,
+    }
+  }
+
+  /// Copies of the lines that need to be parsed to finish the `pending`
+  /// update, e.g. to parse them in another thread by
+  /// [`parse_region`](crate::bufdata::BufData::parse_region)
+  pub fn pending_lines(
+    &self,
+    pending: &PendingUpdate,
+  ) -> Vec<ParsedLine<'static>> {
+    self
+      .lines
+      .range(pending.parse_range.clone())
+      .map(|l| l.to_owned_line())
+      .collect()
+  }
+
+  /// Parse the folds of `lines` for the input version `version`, and their
+  /// highlights up to the column `columns` if `highlight` is true. Returns
+  /// `None` if `cancel` got set in the meantime.
+  pub fn parse_region(
+    lines: &[ParsedLine],
+    version: Option<InputVersion>,
+    highlight: bool,
+    columns: u8,
+    cancel: &AtomicBool,
+  ) -> Result<Option<ParsedRegion>, Error> {
+    let mut parsed = ParsedRegion::default();
+    parsed.highlights.set_columns(columns);
+    let li = LinesIter::new(lines.iter().map(ParsedLine::as_line_ref))
+      .with_version(version)
+      .with_highlights(highlight);
+
+    BufData::parse_from_iter(
+      &mut parsed.highlights,
+      &mut parsed.folds,
+      &mut parsed.folds_ges,
+      li,
+      Some(cancel),
+    )?;
+
+    if cancel.load(Ordering::Relaxed) {
+      Ok(None)
+    } else {
+      Ok(Some(parsed))
+    }
+  }
+
+  /// Splice the folds and highlights `parsed` from the lines of the `pending`
+  /// update into the ones of the buffer. Returns the range of indices with
+  /// new highlights, and the number of lines added by the update.
+  ///
+  /// If the parse got cancelled, because a later update replaces its results
+  /// anyways, pass `ParsedRegion::default()`.
+  pub fn finish_update(
+    &mut self,
+    pending: PendingUpdate,
+    parsed: ParsedRegion,
+  ) -> Result<(Range<usize>, isize), Error> {
+    let PendingUpdate {
+      firstline,
+      lastline,
+      added,
+      window: [first, last],
+      start,
+      ..
+    } = pending;
+
+    self.pending = self.pending.saturating_sub(1);
+
+    let nfolds = parsed.folds.len();
+    let nesting = self.folds.nests(first, last)
+      || parsed.folds.nests(first, last.saturating_add(added));
+    // Repairs of earlier parses have been logged already
+    let _ = self.folds.take_repairs();
+    self.resized_folds = self.folds.splice(parsed.folds, first, last, added);
+    self.expr_levels = None;
+    self.splice_ges_folds(parsed.folds_ges, first, last, added);
+    self.folds_level2.splice_level2(
+      &self.folds,
+      first,
+      last,
+      added,
+      nesting,
+      &self.fold_groups,
+    )?;
+    self.splice_custom_folds(firstline, lastline, added);
+
+    // Only the highlights of the changed lines are replaced, the window
+    // starts before them if they're inside of a card
+    let newlast = lastline.saturating_add(added);
+    let mut highlights = parsed.highlights;
+    highlights.retain(|((l, _, _), _)| firstline <= *l && *l < newlast);
+    let mut range =
+      self.highlights.splice(highlights, firstline, lastline, added);
+
+    // The window did not cover whole cards, e.g. because the folds were
+    // outdated while preparing the update. The overlapping folds have been
+    // repaired, but the buffer is parsed anew to get them right.
+    let repairs = self.folds.take_repairs();
+    if let Some(r) = repairs.first() {
+      warn!(
+        "{} overlapping folds repaired after an update, reparsing",
+        repairs.len()
+      );
+      self.record_inconsistency(r, firstline, lastline, added);
+      self.regenerate()?;
+      let _ = self.folds.take_repairs();
+      range = self.highlights.linerange(firstline, newlast);
+    } else if self.strict {
+      if let Err(e) = self.check_consistency() {
+        warn!("{} after an update, reparsing", e);
+        self.record_inconsistency(&e.to_string(), firstline, lastline, added);
+        self.regenerate()?;
+        range = self.highlights.linerange(firstline, newlast);
+      }
+    }
+
+    self.find_duplicates();
+    self.timings.update = Some(start.elapsed());
+    self.timings.update_folds = nfolds;
+
+    Ok((range, added))
+  }
+
+  /// Highlight the cards changed by replacing the lines `firstline..lastline`
+  /// by `linedata`, but keep the data of the buffer as it is. Used for the
+  /// changes of an `'inccommand'` preview, which neovim undoes right away.
+  ///
+  /// Returns the calls to send the highlights, and the lines whose highlights
+  /// need to be sent again by
+  /// [`highlight_region_calls`](crate::bufdata::BufData::highlight_region_calls)
+  /// after the change was undone.
+  pub fn preview_calls(
+    &self,
+    firstline: LineNr,
+    lastline: LineNr,
+    linedata: Vec<String>,
+  ) -> Result<(Option<Vec<Value>>, [LineNr; 2]), Error> {
+    let added = (firstline + LineNr::from_usize(linedata.len())) - lastline;
+    let first = self.card_start(self.lines.first_before(firstline));
+    let last = self.card_end(self.lines.first_after(lastline));
+    let touched = [first.1, last.1.max(last.1.saturating_add(added))];
 
-    let mut newhls = Highlights::new();
-    let mut newfolds = Folds::new();
+    if !self.highlight {
+      return Ok((None, touched));
+    }
+
+    let lines = self
+      .lines
+      .replaced(first.0..last.0, linedata, firstline, lastline);
+    let parsed = BufData::parse_region(
+      &lines,
+      self.metadata.version,
+      true,
+      self.highlights.columns(),
+      &AtomicBool::new(false),
+    )?
+    .unwrap_or_default();
+    let len = parsed.highlights.len();
 
-    let li = LinesIter::new(self.lines[first_post..last_post].iter());
+    let calls = parsed
+      .highlights
+      .with_config_of(&self.highlights)
+      .highlight_region_calls(
+        &self.buf,
+        0..len,
+        first.1,
+        last.1.saturating_add(added),
+        |line| {
+          let idx = lines.binary_search_by_key(&line, |l| l.number).ok()?;
+          lines[idx].keyword
+        },
+      );
 
-    BufData::parse_from_iter(&mut newhls, &mut newfolds, li)?;
-    self.folds.splice(newfolds, first_pre.1, last_pre.1, added);
-    self.folds_level2.recreate_level2(&self.folds)?;
-    Ok((self.highlights.splice(newhls, firstline, lastline, added), added))
+    Ok((calls, touched))
   }
 
   /// After initializing the lines and keywords of a `BufData` structure, this
@@ -167,9 +962,19 @@ impl<'a> BufData<'a> {
   ///
   /// TODO(KillTheMule): Can we merge this with update?
   pub fn parse_lines(&mut self) -> Result<(), Error> {
-    let li = self.lines.iter();
+    let li = self
+      .lines
+      .iter()
+      .with_version(self.metadata.version)
+      .with_highlights(self.highlight);
 
-    BufData::parse_from_iter(&mut self.highlights, &mut self.folds, li)
+    BufData::parse_from_iter(
+      &mut self.highlights,
+      &mut self.folds,
+      &mut self.folds_ges,
+      li,
+      None,
+    )
   }
 
   /// Iterate over a [`LinesIter`](::linesiter::LinesIter) and add
@@ -177,10 +982,12 @@ impl<'a> BufData<'a> {
   fn parse_from_iter<'b, I>(
     highlights: &mut Highlights,
     folds: &mut Folds,
+    folds_ges: &mut BTreeMap<[LineNr; 2], (usize, String)>,
     mut li: LinesIter<'b, I>,
+    cancel: Option<&AtomicBool>,
   ) -> Result<(), Error>
   where
-    I: Iterator<Item = &'b ParsedLine<'b>>,
+    I: Iterator<Item = LineRef<'b>>,
   {
     let mut foldstart;
     let mut foldend;
@@ -190,23 +997,85 @@ impl<'a> BufData<'a> {
     let mut nextline = unwrap_or_ok!(li.skip_to_next_keyword());
 
     loop {
+      if cancel.map_or(false, |c| c.load(Ordering::Relaxed)) {
+        return Ok(());
+      }
+
       foldkw = nextline.keyword;
       foldstart = nextline.number;
       skipped = li.skip_fold(&nextline, highlights);
 
       // The latter only happens when a file ends after the only line of a card
-      foldend = skipped.skip_end;
+      foldend = skipped.skip_end();
+
+      match li.take_title() {
+        Some(title) => {
+          folds.checked_insert_titled(foldstart, foldend, foldkw, &title)?
+        }
+        None => folds.checked_insert(foldstart, foldend, foldkw)?,
+      }
 
-      folds.checked_insert(foldstart, foldend, foldkw)?;
+      for (range, entries) in li.take_ges_regions() {
+        let text = format!(" GES ({} entries) ", entries);
+        let _ = folds_ges.insert(range, (entries, text));
+      }
 
-      if let Some(Some(kl)) =
-        skipped.nextline.map(ParsedLine::try_into_keywordline)
-      {
-        nextline = kl;
-      } else {
-        nextline = unwrap_or_ok!(li.skip_to_next_keyword());
+      nextline = match skipped.next_keywordline() {
+        Some(kl) => kl,
+        None => unwrap_or_ok!(li.skip_to_next_keyword()),
+      };
+    }
+  }
+
+  /// The metadata declared by the control lines at the top of the buffer
+  pub fn metadata(&self) -> &DeckMetadata {
+    &self.metadata
+  }
+
+  /// The sizes of the datastructures holding the buffer's data
+  pub fn stats(&self) -> Stats {
+    Stats {
+      lines: self.lines.len(),
+      keywords: self.lines.iter().filter(|l| l.keyword.is_some()).count(),
+      line_bytes: self.lines.text_bytes(),
+      heap_bytes: self.lines.heap_bytes(),
+      folds: self.folds.len(),
+      folds_level2: self.folds_level2.len(),
+      folds_ges: self.folds_ges.len(),
+      custom_folds: self.custom_folds.len(),
+      highlights: self.highlights.len(),
+    }
+  }
+
+  /// The number of cards of each type in the buffer and the lines they span.
+  /// See [`cards`](crate::bufdata::BufData::cards). Sorted by the number of
+  /// lines, descending.
+  pub fn keyword_summary(&self) -> Vec<KeywordCount> {
+    let mut counts: Vec<KeywordCount> = vec![];
+
+    for card in self.cards() {
+      let lines = usize::from(card.range[1]) - usize::from(card.range[0]) + 1;
+
+      match counts.iter_mut().find(|c| c.keyword == card.keyword) {
+        Some(c) => {
+          c.cards += 1;
+          c.lines += lines;
+        }
+        None => counts.push(KeywordCount {
+          keyword: card.keyword,
+          cards: 1,
+          lines,
+        }),
       }
     }
+
+    counts.sort_by_key(|c| Reverse(c.lines));
+    counts
+  }
+
+  /// How long the last parse and update of the buffer took
+  pub fn timings(&self) -> Timings {
+    self.timings
   }
 
   pub fn hl_linerange(&self, first: LineNr, last: LineNr) -> Range<usize> {
@@ -225,33 +1094,2908 @@ impl<'a> BufData<'a> {
   /// `firstline..lastline`. Here, `indexrange` gives the index of the
   /// highlights to send. All existing highlights in this linerange are cleare
   /// beforehand.
+  ///
+  /// Returns `None` if highlighting is disabled, see
+  /// [`set_highlights_enabled`](crate::bufdata::BufData::set_highlights_enabled).
   pub fn highlight_region_calls(
     &mut self,
     indexrange: Range<usize>,
     firstline: LineNr,
     lastline: LineNr,
   ) -> Option<Vec<Value>> {
+    if !self.highlight {
+      return None;
+    }
+
+    let lines = &self.lines;
+    self.highlights.highlight_region_calls(
+      &self.buf,
+      indexrange,
+      firstline,
+      lastline,
+      |line| line_keyword(lines, line),
+    )
+  }
+
+  /// Construct the calls to neovim to clear all highlights of the buffer and
+  /// send them anew. In the [`Viewport`](crate::bufdata::degraded::Degraded)
+  /// mode, only the viewed lines are highlighted, so there are none.
+  pub fn highlight_all_calls(&mut self) -> Option<Vec<Value>> {
+    if self.degraded == Some(Degraded::Viewport) {
+      return None;
+    }
+
+    let end = self.end();
+    let len = self.highlights.len();
+
+    self.highlight_region_calls(0..len, LineNr::from_usize(0), end)
+  }
+
+  /// Replace the highlights other tools pushed to the lines
+  /// `firstline..lastline` by `spans`, see
+  /// [`External`](crate::bufdata::highlights::HighlightGroup::External).
+  /// They're sent in nvimpam's namespace, so they aren't cleared when
+  /// nvimpam highlights the lines again. Returns the calls to send the
+  /// highlights of the lines.
+  ///
+  /// Returns `None` if highlighting is disabled, the spans are dropped then.
+  pub fn set_external_highlights(
+    &mut self,
+    spans: Vec<((LineNr, u8, u8), Hl)>,
+    firstline: LineNr,
+    lastline: LineNr,
+  ) -> Option<Vec<Value>> {
+    if !self.highlight {
+      return None;
+    }
+
+    let range = self.highlights.set_external(spans, firstline, lastline);
+    self.highlight_region_calls(range, firstline, lastline)
+  }
+
+  /// The number of the line after the last line of the buffer
+  pub fn end(&self) -> LineNr {
     self
-      .highlights
-      .highlight_region_calls(&self.buf, indexrange, firstline, lastline)
+      .lines
+      .last()
+      .map_or(LineNr::from_usize(0), |l| l.number + 1)
   }
 
-  /// Pack up all existing level 1 and level 2 folds (in that order) into a
-  /// `Value` suitable to send to neovim.
+  /// Pack up all existing level 1 and level 2 folds, as well as the GES folds
+  /// (in that order) into a `Value` suitable to send to neovim. Custom folds
+  /// are sent along with the level 2 folds.
+  ///
+  /// The foldtexts are taken from the templates set by
+  /// [`set_foldtexts`](crate::bufdata::BufData::set_foldtexts) if available.
   pub fn fold_calls(&self) -> Value {
-    Value::from(vec![
-      self.folds.fold_calls(),
-      self.folds_level2.fold_calls(),
-    ])
+    Value::from(
+      self
+        .fold_levels()
+        .into_iter()
+        .map(Value::from)
+        .collect::<Vec<_>>(),
+    )
   }
 
-  #[cfg(test)]
-  pub fn folds_to_vec(&self) -> Vec<(usize, usize, Keyword)> {
-    self.folds.to_vec()
+  /// Pack up the level 1 folds whose foldtext changed in the last
+  /// [`finish_update`](crate::bufdata::BufData::finish_update), e.g. because
+  /// lines were added to them, like
+  /// [`fold_calls`](crate::bufdata::BufData::fold_calls) does.
+  pub fn resized_fold_calls(&self) -> Vec<Value> {
+    if self.degraded == Some(Degraded::Level2) {
+      return vec![];
+    }
+
+    let texts = &self.foldtexts;
+
+    self.folds.fold_calls_of(
+      &self.resized_folds,
+      &self.custom_folds,
+      |r, kw| texts.card(r, kw).or_else(|| self.table_foldtext(r, kw)),
+    )
   }
 
-  #[cfg(test)]
-  pub fn folds_level2_to_vec(&self) -> Vec<(usize, usize, Keyword)> {
-    self.folds_level2.to_vec()
+  /// The value of `foldexpr` for the line `line`, for neovim to compute the
+  /// folds itself instead of getting them sent. Only the level 1 folds are
+  /// covered, each one starting with `>1`. The values of all lines are
+  /// computed on the first call after the folds changed.
+  pub fn fold_level(&mut self, line: LineNr) -> ExprLevel {
+    let end = usize::from(self.end());
+    let folds = &self.folds;
+    let levels = self
+      .expr_levels
+      .get_or_insert_with(|| folds.expr_levels(end));
+
+    levels
+      .get(usize::from(line))
+      .cloned()
+      .unwrap_or(ExprLevel::Outside)
+  }
+
+  /// The level 1, level 2 and GES folds as sent by
+  /// [`fold_calls`](crate::bufdata::BufData::fold_calls), for sending them
+  /// piecewise
+  pub fn fold_levels(&self) -> Vec<Vec<Value>> {
+    self.folds_in_range(LineNr::from_usize(0), LineNr::MAX)
+  }
+
+  /// The level 1, level 2 and GES folds intersecting the lines
+  /// `first..=last`, packed up like
+  /// [`fold_levels`](crate::bufdata::BufData::fold_levels). This way, the
+  /// folds of the lines shown in a window can be created on their own. In the
+  /// [`Level2`](crate::bufdata::degraded::Degraded) mode, only the level 2
+  /// folds are packed up.
+  pub fn folds_in_range(&self, first: LineNr, last: LineNr) -> Vec<Vec<Value>> {
+    let texts = &self.foldtexts;
+    let mut level2 =
+      self
+        .folds_level2
+        .fold_calls(first, last, &self.custom_folds, |r, kw| {
+          let name = self.fold_groups.name(kw);
+          texts.group(self.folds.count_within(r), kw, name)
+        });
+    level2.extend(
+      self
+        .custom_folds
+        .iter()
+        .filter(|(r, _)| r[0] <= last && first <= r[1])
+        .filter(|(r, _)| self.generated_fold(r).is_none())
+        .filter(|(r, _)| !self.banners.is_fold(r))
+        .map(|(r, t)| folds::fold_call(r, t)),
+    );
+    level2.extend(self.banners.fold_calls(first, last, &self.custom_folds));
+
+    if self.degraded == Some(Degraded::Level2) {
+      return vec![vec![], level2, vec![]];
+    }
+
+    vec![
+      self
+        .folds
+        .fold_calls(first, last, &self.custom_folds, |r, kw| {
+          texts.card(r, kw).or_else(|| self.table_foldtext(r, kw))
+        }),
+      level2,
+      folds::intersecting(&self.folds_ges, first, last)
+        .map(|(r, (entries, t))| match texts.ges(*entries) {
+          Some(t) => folds::fold_call(r, &t),
+          None => folds::fold_call(r, t),
+        })
+        .collect(),
+    ]
+  }
+
+  /// The highlights of the lines `first..last` as lists
+  /// `[line, start, end, group]`, with the columns in the
+  /// [`Encoding`](crate::bufdata::highlights::Encoding) set by
+  /// [`set_highlight_config`](crate::bufdata::BufData::set_highlight_config),
+  /// e.g. to pass them on to an LSP client.
+  pub fn highlights_in_range(&self, first: LineNr, last: LineNr) -> Vec<Value> {
+    let range = self.highlights.linerange(first, last);
+
+    self.highlights.positions(range, |line| {
+      let idx = self.lines.search(line).ok()?;
+      Some(self.lines.get(idx)?.text.as_ref())
+    })
+  }
+
+  /// The highlights of the lines `first..last` as LSP semantic tokens, i.e.
+  /// the flat list of the delta-encoded numbers of the tokens, with the
+  /// columns in the [`Encoding`](crate::bufdata::highlights::Encoding) set
+  /// by [`set_highlight_config`](crate::bufdata::BufData::set_highlight_config).
+  /// See [`TOKEN_TYPES`](crate::bufdata::highlights::TOKEN_TYPES) for the
+  /// legend.
+  pub fn semantic_tokens(&self, first: LineNr, last: LineNr) -> Vec<u64> {
+    let range = self.highlights.linerange(first, last);
+
+    self.highlights.semantic_tokens(range, |line| {
+      let idx = self.lines.search(line).ok()?;
+      Some(self.lines.get(idx)?.text.as_ref())
+    })
+  }
+
+  /// The foldtext of a level 1 fold over a function table, see
+  /// [`table_foldtext`](crate::carddata::auxiliaries::table_foldtext)
+  fn table_foldtext(&self, range: &[LineNr; 2], kw: Keyword) -> Option<String> {
+    let (idx, _) = self.lines.first_after(range[0]);
+    let text = self.lines.get(idx)?.text.as_ref();
+
+    auxiliaries::table_foldtext(kw, text, range[1] - range[0] + 1)
+  }
+
+  /// The generated level 1 or level 2 fold with the given range
+  fn generated_fold(&self, range: &[LineNr; 2]) -> Option<&(Keyword, String)> {
+    self
+      .folds
+      .get(range)
+      .or_else(|| self.folds_level2.get(range))
+  }
+
+  /// Drop the custom folds touching the changed lines `firstline..lastline`,
+  /// and move the ones after that by `added` lines.
+  fn splice_custom_folds(
+    &mut self,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+  ) {
+    let old = std::mem::take(&mut self.custom_folds);
+
+    for (range, text) in old {
+      if range[1] < firstline {
+        let _ = self.custom_folds.insert(range, text);
+      } else if lastline <= range[0] {
+        let _ = self
+          .custom_folds
+          .insert([range[0] + added, range[1] + added], text);
+      }
+    }
+  }
+
+  /// Replace the GES folds in the lines `firstline..lastline` by `newges`,
+  /// and move the ones after that by `added` lines. Since GES regions are
+  /// always inside a card, there's nothing to merge.
+  fn splice_ges_folds(
+    &mut self,
+    newges: BTreeMap<[LineNr; 2], (usize, String)>,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+  ) {
+    let old = std::mem::replace(&mut self.folds_ges, newges);
+
+    for (range, text) in old {
+      if range[0] < firstline {
+        let _ = self.folds_ges.insert(range, text);
+      } else if lastline <= range[0] {
+        let _ = self
+          .folds_ges
+          .insert([range[0] + added, range[1] + added], text);
+      }
+    }
+  }
+
+  /// The current fold state of the buffer, i.e. all generated and custom
+  /// folds along with their foldtexts, and the hash of the buffer's lines.
+  pub fn fold_state(&self) -> FoldState {
+    let mut folds: BTreeMap<[LineNr; 2], String> = self
+      .folds
+      .iter()
+      .chain(self.folds_level2.iter())
+      .map(|(r, (_, t))| (*r, t.to_string()))
+      .collect();
+
+    for (r, t) in &self.custom_folds {
+      let _ = folds.insert(*r, t.to_string());
+    }
+
+    FoldState {
+      hash: foldstate::hash_lines(self.lines.iter()),
+      folds: folds.into_iter().collect(),
+    }
+  }
+
+  /// Restore the custom folds and foldtexts from a
+  /// [`FoldState`](crate::bufdata::foldstate::FoldState). Returns an error if
+  /// the state belongs to a different revision of the buffer. All previous
+  /// custom folds are discarded.
+  pub fn set_fold_state(&mut self, state: FoldState) -> Result<(), Error> {
+    if state.hash != foldstate::hash_lines(self.lines.iter()) {
+      return Err(failure::err_msg(
+        "Fold state belongs to a different revision of the buffer!",
+      ));
+    }
+
+    self.custom_folds.clear();
+
+    for (range, text) in state.folds {
+      if self.generated_fold(&range).map(|(_, t)| t) != Some(&text) {
+        let _ = self.custom_folds.insert(range, text);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Set the foldtext of the fold starting at line `start` to `label`. If
+  /// there are several such folds, the innermost one is renamed. An empty
+  /// `label` restores the generated foldtext, or removes the fold if it's a
+  /// custom one.
+  pub fn rename_fold(
+    &mut self,
+    start: LineNr,
+    label: String,
+  ) -> Result<(), Error> {
+    let range = self
+      .folds
+      .iter()
+      .chain(self.folds_level2.iter())
+      .map(|(r, _)| r)
+      .chain(self.custom_folds.keys())
+      .find(|r| r[0] == start)
+      .cloned()
+      .ok_or_else(|| {
+        failure::err_msg(format!("No fold starting at line {}!", start + 1))
+      })?;
+
+    if label.is_empty() {
+      let _ = self.custom_folds.remove(&range);
+    } else {
+      let _ = self.custom_folds.insert(range, label);
+    }
+
+    Ok(())
+  }
+
+  /// Convert the card containing the line `line` to the card type named by
+  /// `target`, e.g. `"membr"`. Returns a map containing the `edits` as an
+  /// array of `[linenr, text]` pairs, and the `diagnostics` for all cells that
+  /// could not be converted or need to be filled in by hand. Only the first
+  /// lines of the card get converted. If the new card has more lines, e.g.
+  /// when converting a `TETR4` to a `TETR10`, they are given as `added`, an
+  /// array of the line to add them after and the lines.
+  ///
+  /// If `target` is empty, the map contains the names of the card types the
+  /// card can be converted to as `targets` instead.
+  pub fn convert_card(&self, line: LineNr, target: &str) -> Value {
+    let mut edits = vec![];
+    let mut added = vec![];
+    let mut diagnostics = vec![];
+
+    let (idx, number) = self.first_before(line);
+    let from = self.lines.get(idx).and_then(|l| l.keyword);
+
+    if target.trim().is_empty() {
+      let targets: Vec<Value> = CONVERSIONS
+        .iter()
+        .filter(|c| Some(c.from) == from)
+        .map(|c| Value::from(format!("{:?}", c.to).to_lowercase()))
+        .collect();
+      return Value::from(vec![(Value::from("targets"), Value::from(targets))]);
+    }
+
+    let to = Keyword::parse(format!("{:<6}/ ", target.to_uppercase()).as_ref())
+      .filter(|&k| k != Keyword::Unknown);
+
+    let conversion = match (from, to) {
+      (Some(f), Some(t)) => {
+        CONVERSIONS.iter().find(|c| c.from == f && c.to == t)
+      }
+      _ => None,
+    };
+
+    match conversion {
+      None => diagnostics.push(Diagnostic::new(
+        number,
+        0,
+        8,
+        Severity::Error,
+        format!("No conversion from {:?} to '{}' known", from, target),
+      )),
+      Some(conv) => {
+        let end = self
+          .lines
+          .range(idx + 1..self.lines.len())
+          .position(|l| l.keyword.is_some())
+          .map_or(self.lines.len(), |p| idx + 1 + p);
+        // Lines after the card that are not part of its fold stay as they are
+        let last = self.card_range(number).map(|r| r[1]);
+        let cardlines: Vec<(LineNr, &[u8])> = self
+          .lines
+          .range(idx..end)
+          .take_while(|l| last.map_or(true, |last| l.number <= last))
+          .filter(|l| !matches!(l.text.as_ref().first(), Some(b'$' | b'#')))
+          .map(|l| (l.number, l.text.as_ref()))
+          .collect();
+
+        let (e, a, d) = conv.apply(&cardlines);
+        edits = e;
+        added = a;
+        diagnostics = d;
+      }
+    }
+
+    let after = edits.last().map(|e| e.0);
+    let mut value = edits_value(edits, &diagnostics);
+    if let (Some(after), Value::Map(ref mut m)) = (after, &mut value) {
+      if !added.is_empty() {
+        let added = added.into_iter().map(Value::from).collect::<Vec<_>>();
+        m.push((
+          Value::from("added"),
+          Value::from(vec![Value::from(after), Value::from(added)]),
+        ));
+      }
+    }
+
+    value
+  }
+
+  /// Renumber the entities named by `entity`, e.g. `"node"`, that are defined
+  /// in the lines `first..last`, starting with the ID `start` and increasing
+  /// by `step`. All references to them are updated as well. Returns the same
+  /// kind of map as [`convert_card`](crate::bufdata::BufData::convert_card).
+  pub fn renumber(
+    &self,
+    entity: &str,
+    start: i64,
+    step: i64,
+    first: LineNr,
+    last: LineNr,
+  ) -> Value {
+    let entity = match Entity::from_name(entity) {
+      Some(e) => e,
+      None => {
+        let d = Diagnostic::new(
+          first,
+          0,
+          8,
+          Severity::Error,
+          format!("Unknown entity '{}'", entity),
+        );
+        return edits_value(vec![], &[d]);
+      }
+    };
+
+    let (edits, diagnostics) =
+      xref::renumber(&self.keyword_lines(), entity, start, step, first, last);
+
+    edits_value(edits, &diagnostics)
+  }
+
+  /// Rename the entity named by `entity`, e.g. `"node"`, with the ID `old`
+  /// to `new`, and update all references to it. Refused if `new` is already
+  /// defined in the buffer or in the `index` of its include files. Returns the
+  /// same kind of map as
+  /// [`convert_card`](crate::bufdata::BufData::convert_card), or an error
+  /// message if `old` is not defined in the buffer.
+  pub fn rename_id(
+    &self,
+    entity: &str,
+    old: i64,
+    new: i64,
+    index: Option<&Index>,
+  ) -> Value {
+    let entity = match Entity::from_name(entity) {
+      Some(e) => e,
+      None => return Value::from(format!("Unknown entity '{}'", entity)),
+    };
+
+    let renamed = xref::rename(&self.keyword_lines(), entity, old, new, |id| {
+      index.map_or(false, |i| i.find(entity, id).is_some())
+    });
+
+    match renamed {
+      Ok((edits, diagnostics)) => edits_value(edits, &diagnostics),
+      Err(e) => Value::from(format!("Could not rename: {}", e)),
+    }
+  }
+
+  /// Add `offset` to the IDs of the `entities`, e.g. `"node"`, defined in the
+  /// include file of the `INCLU` line `line`, or all entities if `entities`
+  /// is empty. The include file is resolved relative to the directory `dir`
+  /// of the buffer. References to the IDs are updated in the buffer, the
+  /// include file and the other files of the `index`, see
+  /// [`xref::offset`](crate::card::xref::offset).
+  ///
+  /// Returns a map with the list `files` of the files to change. Each is a
+  /// map of the `file`, which is empty for the buffer itself, and its `edits`
+  /// and `diagnostics` like for
+  /// [`convert_card`](crate::bufdata::BufData::convert_card).
+  pub fn offset_include(
+    &self,
+    line: LineNr,
+    dir: &Path,
+    offset: i64,
+    entities: &[String],
+    index: Option<&Index>,
+  ) -> Result<Value, Error> {
+    let entities = if entities.is_empty() {
+      vec![Entity::Node, Entity::Element, Entity::Part]
+    } else {
+      entities
+        .iter()
+        .map(|e| {
+          let unknown = format!("Unknown entity '{}'!", e);
+          Entity::from_name(e).ok_or_else(|| failure::err_msg(unknown))
+        })
+        .collect::<Result<_, _>>()?
+    };
+
+    let include = self
+      .lines
+      .search(line)
+      .ok()
+      .and_then(|i| self.lines.get(i))
+      .and_then(|l| index::include_path(l.text.as_ref()))
+      .ok_or_else(|| {
+        failure::err_msg(format!("Line {} is not an INCLU line!", line + 1))
+      })?;
+    let path = dir.join(include);
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.into());
+
+    let mut paths = vec![path.clone()];
+    paths.extend(
+      index
+        .map_or(&[][..], Index::files)
+        .iter()
+        .filter(|f| canonical(f) != canonical(&path))
+        .cloned(),
+    );
+    let contents = paths
+      .iter()
+      .map(|p| {
+        fs::read(p)
+          .with_context(|e| format!("Could not read '{}': {}", p.display(), e))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    let parsed: Vec<Lines> = contents
+      .iter()
+      .map(|c| {
+        let mut lines = Lines::new();
+        lines.parse_slice(c);
+        lines
+      })
+      .collect();
+
+    let mut files: Vec<_> = parsed
+      .iter()
+      .map(|lines| {
+        lines
+          .iter()
+          .filter_map(|l| l.keyword.map(|k| (l.number, l.text.as_ref(), k)))
+          .collect()
+      })
+      .collect();
+    files.insert(1, self.keyword_lines());
+
+    let mut names: Vec<String> = paths
+      .iter()
+      .map(|p| p.to_string_lossy().into_owned())
+      .collect();
+    names.insert(1, String::new());
+
+    let changed: Vec<Value> = xref::offset(&files, &entities, offset)
+      .into_iter()
+      .zip(names)
+      .filter(|((e, d), _)| !e.is_empty() || !d.is_empty())
+      .map(|((edits, diagnostics), name)| {
+        let mut value = edits_value(edits, &diagnostics);
+        if let Value::Map(ref mut m) = value {
+          m.insert(0, (Value::from("file"), Value::from(name)));
+        }
+        value
+      })
+      .collect();
+
+    Ok(Value::from(vec![(
+      Value::from("files"),
+      Value::from(changed),
+    )]))
+  }
+
+  /// Move the numbers crossing the borders of their cells into the cell
+  /// holding most of them, see [`align`](crate::card::align). Only the cards
+  /// overlapping the lines `first..last` are checked, and if `modified_only`
+  /// is set, only the ones changed since the buffer was last
+  /// [`written`](crate::bufdata::BufData::written). Returns the same kind of
+  /// map as [`convert_card`](crate::bufdata::BufData::convert_card), with a
+  /// warning for each line that can't be re-aligned.
+  pub fn align_cells(
+    &self,
+    first: LineNr,
+    last: LineNr,
+    modified_only: bool,
+  ) -> Value {
+    let mut edits = vec![];
+    let mut diagnostics = vec![];
+
+    for (kw, lines) in self.card_lines() {
+      let cardfirst = lines[0].0;
+      let cardlast = lines[lines.len() - 1].0;
+
+      if cardlast < first
+        || cardfirst >= last
+        || (modified_only && !self.modified.touches(cardfirst, cardlast))
+      {
+        continue;
+      }
+
+      let card = version::card_for(kw, self.metadata.version);
+
+      for lv in value::card_values(card, &lines) {
+        let text = match lines.iter().find(|l| l.0 == lv.line) {
+          Some(l) => l.1,
+          None => continue,
+        };
+
+        match align::check(&lv.cells, text) {
+          Alignment::Aligned => {}
+          Alignment::Realigned(t) => edits.push((lv.line, t)),
+          Alignment::Ambiguous(start, end) => diagnostics.push(Diagnostic::new(
+            lv.line,
+            start,
+            end,
+            Severity::Warning,
+            "Content crosses the border of its cell".to_string(),
+          )),
+        }
+      }
+    }
+
+    edits_value(edits, &diagnostics)
+  }
+
+  /// Forget the changes made to the buffer after it was written, see
+  /// [`align_cells`](crate::bufdata::BufData::align_cells)
+  pub fn written(&mut self) {
+    self.modified.clear();
+  }
+
+  /// The number, text and keyword of all keyword lines of the buffer
+  fn keyword_lines(&self) -> Vec<(LineNr, &[u8], Keyword)> {
+    self
+      .lines
+      .iter()
+      .filter_map(|l| l.keyword.map(|k| (l.number, l.text.as_ref(), k)))
+      .collect()
+  }
+
+  /// Recompute the duplicate IDs of all entities if enabled by
+  /// [`set_check_duplicates`](crate::bufdata::BufData::set_check_duplicates)
+  fn find_duplicates(&mut self) {
+    if !self.check_duplicates {
+      return;
+    }
+
+    self.duplicates = self.duplicate_ids();
+  }
+
+  /// The diagnostics for the IDs of all entities defined more than once,
+  /// ordered by line
+  fn duplicate_ids(&self) -> Vec<Diagnostic> {
+    let mut duplicates: Vec<Diagnostic> = self.with_xrefs(|xrefs| {
+      [Entity::Node, Entity::Element, Entity::Part]
+        .iter()
+        .flat_map(|e| xrefs.duplicates(*e))
+        .collect()
+    });
+    duplicates.sort_by_key(|d| d.line);
+
+    duplicates
+  }
+
+  /// Enable or disable the check for IDs defined more than once. The check
+  /// runs after each parse or update of the buffer while enabled.
+  pub fn set_check_duplicates(&mut self, enable: bool) {
+    self.check_duplicates = enable;
+
+    if enable {
+      self.index_xrefs();
+      self.find_duplicates();
+    } else {
+      self.duplicates.clear();
+    }
+  }
+
+  /// If the buffer is checked for duplicate IDs
+  pub fn checks_duplicates(&self) -> bool {
+    self.check_duplicates
+  }
+
+  /// The diagnostics for the IDs defined more than once, ordered by line.
+  /// Empty if the check is disabled.
+  pub fn duplicates(&self) -> &[Diagnostic] {
+    &self.duplicates
+  }
+
+  /// The line defining the ID `id` of `entity`. If it's defined more than
+  /// once, the first definition is returned.
+  pub fn find_definition(&self, entity: Entity, id: i64) -> Option<LineNr> {
+    self.definitions(entity).get(&id).cloned()
+  }
+
+  /// The IDs of `entity` defined in the buffer, along with the line of their
+  /// first definition
+  pub fn definitions(&self, entity: Entity) -> HashMap<i64, LineNr> {
+    self.with_xrefs(|xrefs| xrefs.definitions(entity))
+  }
+
+  /// Call `f` with the index of the IDs on the keyword lines. If it's not
+  /// kept by [`index_xrefs`](crate::bufdata::BufData::index_xrefs), it's
+  /// built for the call.
+  fn with_xrefs<F, T>(&self, f: F) -> T
+  where
+    F: FnOnce(&XrefIndex) -> T,
+  {
+    match self.xrefs {
+      Some(ref xrefs) => f(xrefs),
+      None => f(&XrefIndex::new(&self.keyword_lines())),
+    }
+  }
+
+  /// Build the index of the IDs on the keyword lines, if there's none yet.
+  /// It's updated with the changed lines only afterwards, which saves looking
+  /// for the IDs on all cards after each change, e.g. to find the duplicates.
+  pub fn index_xrefs(&mut self) {
+    if self.xrefs.is_none() {
+      self.xrefs = Some(XrefIndex::new(&self.keyword_lines()));
+    }
+  }
+
+  /// The cards of the buffer in the order they occur, found lazily from the
+  /// level 1 folds. Lines outside of the folds are skipped.
+  pub fn cards(&self) -> Cards<'_, impl Iterator<Item = [LineNr; 2]> + '_> {
+    Cards::new(
+      self.lines.range(0..self.lines.len()),
+      self.folds.iter().map(|(r, _)| *r),
+    )
+  }
+
+  /// The file names of the `INCLU` lines of the buffer, see
+  /// [`index`](crate::index)
+  pub fn includes(&self) -> Vec<String> {
+    self
+      .lines
+      .iter()
+      .filter_map(|l| index::include_path(l.text.as_ref()))
+      .collect()
+  }
+
+  /// The numbered lines of the cards of the buffer in the order they occur,
+  /// each starting with its keyword line. Lines outside of the folds are
+  /// skipped.
+  fn card_lines(&self) -> Vec<(Keyword, Vec<(LineNr, &[u8])>)> {
+    self
+      .cards()
+      .map(|card| {
+        let first = match self.lines.search(card.range[0]) {
+          Ok(i) | Err(i) => i,
+        };
+        let lines = self
+          .lines
+          .range(first..self.lines.len())
+          .take_while(|l| l.number <= card.range[1])
+          .map(|l| (l.number, l.text.as_ref()))
+          .collect();
+
+        (card.keyword, lines)
+      })
+      .collect()
+  }
+
+  /// The cards of the buffer in the order they occur, see
+  /// [`diff`](crate::diff). Lines outside of the folds are skipped.
+  pub fn deck_cards(&self) -> Vec<DeckCard> {
+    self
+      .card_lines()
+      .into_iter()
+      .map(|(kw, lines)| {
+        let card = version::card_for(kw, self.metadata.version);
+
+        DeckCard {
+          keyword: kw,
+          id: value::card_id(card, lines[0].1),
+          line: lines[0].0,
+          lines: lines
+            .iter()
+            .map(|(_, t)| String::from_utf8_lossy(t).trim_end().to_string())
+            .collect(),
+        }
+      })
+      .collect()
+  }
+
+  /// All diagnostics for the buffer, ordered by line: Cells with invalid
+  /// contents, content the solver ignores because it's written after the last
+  /// cell of a line, references to undefined nodes, elements and parts, and
+  /// IDs defined more than once. References are also looked up in the `index`
+  /// of the include files. They're not checked if the buffer has includes
+  /// that are not indexed, since most of them would be defined there.
+  pub fn diagnostics(&self, index: Option<&Index>) -> Vec<Diagnostic> {
+    let version = self.metadata.version;
+    let mut diagnostics: Vec<Diagnostic> = self
+      .card_lines()
+      .iter()
+      .flat_map(|(kw, lines)| {
+        let card = version::card_for(*kw, version);
+        let values = value::card_values(card, lines);
+        let mut diagnostics = value::invalid_cells(&values);
+        diagnostics.extend(value::overflows(&values, lines));
+        for d in &mut diagnostics {
+          if let Some((_, text)) = lines.iter().find(|(n, _)| *n == d.line) {
+            tabs::to_bytes(d, text);
+          }
+        }
+        diagnostics.extend(
+          lines.iter().flat_map(|(n, text)| tabs::diagnostics(*n, text)),
+        );
+        diagnostics
+      })
+      .collect();
+
+    self.with_xrefs(|xrefs| {
+      if index.is_some() || self.includes().is_empty() {
+        for e in &[Entity::Node, Entity::Element, Entity::Part] {
+          diagnostics.extend(xrefs.undefined(*e, |id| {
+            index.map_or(false, |i| i.find(*e, id).is_some())
+          }));
+        }
+      }
+
+      for e in &[Entity::Node, Entity::Element, Entity::Part] {
+        diagnostics.extend(xrefs.duplicates(*e));
+      }
+    });
+    diagnostics.sort_by_key(|d| d.line);
+
+    diagnostics
+  }
+
+  /// Check if inserting a character at the column `col` of the line `line`
+  /// would move the contents of fixed-format cells out of their columns.
+  /// Returns a map with the key `shifts`. If the column belongs to a cell
+  /// of known width, its range is given as `cell`. If the insertion shifts,
+  /// and the cell has a blank at or after `col`, its column is given as `pad`.
+  /// Removing that blank after inserting keeps the following cells in place.
+  pub fn cell_hint(&self, line: LineNr, col: u8) -> Value {
+    let (idx, _) = self.first_before(line);
+    let cells = self.lines.get(idx).and_then(|kwline| {
+      let card = version::card_for(kwline.keyword?, self.metadata.version);
+      let offset = self
+        .lines
+        .range(idx..self.lines.len())
+        .position(|l| l.number == line)?;
+
+      // A continued NAME moves all following lines of the card
+      if self
+        .lines
+        .range(idx..idx + offset)
+        .any(|l| name_continues(l.text.as_ref()))
+      {
+        return None;
+      }
+
+      let text = self.lines.get(idx + offset)?.text.as_ref();
+      card
+        .cell_boundaries(kwline.text.as_ref(), offset)
+        .map(|r| (r, text))
+    });
+
+    let mut shifts = false;
+    let mut map = vec![];
+
+    if let Some((ranges, text)) = cells {
+      let blank = |c: u8| text.get(c as usize).unwrap_or(&b' ') == &b' ';
+      shifts = text.iter().skip(col as usize).any(|b| *b != b' ');
+
+      if let Some(&(s, e)) = ranges.iter().find(|r| r.0 <= col && col < r.1) {
+        map.push((
+          Value::from("cell"),
+          Value::from(vec![
+            Value::from(u64::from(s)),
+            Value::from(u64::from(e)),
+          ]),
+        ));
+
+        if let Some(p) = (col..e).find(|c| blank(*c)).filter(|_| shifts) {
+          map.push((Value::from("pad"), Value::from(u64::from(p))));
+        }
+      }
+    }
+
+    map.insert(0, (Value::from("shifts"), Value::from(shifts)));
+    Value::from(map)
+  }
+
+  /// Set the namespace and the highlight groups used to highlight the buffer.
+  /// If the namespace changed, returns the calls to clear the old one. If the
+  /// column the highlights end at changed, the buffer is parsed anew, and the
+  /// calls to send all highlights are returned as well.
+  pub fn set_highlight_config(
+    &mut self,
+    config: HlConfig,
+  ) -> Result<Option<Vec<Value>>, Error> {
+    let columns_changed = config.columns != self.highlights.columns();
+    let calls = self.highlights.set_config(self.buf, config);
+
+    if !self.highlight {
+      return Ok(None);
+    }
+    if !columns_changed {
+      return Ok(calls);
+    }
+
+    self.regenerate()?;
+    let mut calls = calls.unwrap_or_default();
+    calls.extend(self.highlight_all_calls().unwrap_or_default());
+
+    Ok(Some(calls))
+  }
+
+  /// Enable or disable generating the highlights. The folds are not affected.
+  /// Disabling drops the highlights, and returns the calls to remove them in
+  /// neovim. Enabling reparses the buffer, the highlights can be sent via
+  /// [`highlight_all_calls`](crate::bufdata::BufData::highlight_all_calls)
+  /// afterwards. The setting is kept when clearing the buffer.
+  pub fn set_highlights_enabled(
+    &mut self,
+    enable: bool,
+  ) -> Result<Option<Vec<Value>>, Error> {
+    if enable == self.highlight {
+      return Ok(None);
+    }
+
+    self.highlight = enable;
+    if enable {
+      self.regenerate()?;
+      Ok(self.highlight_all_calls())
+    } else {
+      self.highlights.clear();
+      Ok(Some(self.clear_calls()))
+    }
+  }
+
+  /// If the highlights are generated, see
+  /// [`set_highlights_enabled`](crate::bufdata::BufData::set_highlights_enabled)
+  pub fn highlights_enabled(&self) -> bool {
+    self.highlight
+  }
+
+  /// The calls to remove the highlights of the buffer in neovim, e.g. when
+  /// quitting
+  pub fn clear_calls(&self) -> Vec<Value> {
+    self.highlights.clear_calls(self.buf)
+  }
+
+  /// The calls to remove the highlights of the lines in `regions` in neovim,
+  /// e.g. because they have not been viewed for a while, see
+  /// [`ViewedRegions`](crate::bufdata::viewed::ViewedRegions). The highlights
+  /// are kept, so the regions can be highlighted again later. Returns `None`
+  /// if there are no regions or highlighting is disabled.
+  pub fn clear_regions_calls(
+    &self,
+    regions: &[[LineNr; 2]],
+  ) -> Option<Vec<Value>> {
+    if !self.highlight || regions.is_empty() {
+      return None;
+    }
+
+    Some(self.highlights.clear_regions_calls(self.buf, regions))
+  }
+
+  /// Set the templates for the foldtexts. They're kept when clearing the
+  /// buffer, and only take effect when the folds are sent to neovim.
+  pub fn set_foldtexts(&mut self, texts: FoldTexts) {
+    self.foldtexts = texts;
+  }
+
+  /// Set the rules grouping the level 1 folds, and recreate the level 2 folds
+  /// accordingly. The rules are kept when clearing the buffer.
+  pub fn set_fold_groups(&mut self, groups: FoldGroups) -> Result<(), Error> {
+    self.fold_groups = groups;
+    self
+      .folds_level2
+      .recreate_level2(&self.folds, &self.fold_groups)
+  }
+
+  /// Check the order of the sections of the buffer against the given rules.
+  pub fn lint_sections(&self, rules: &[SectionRule]) -> Vec<Diagnostic> {
+    let mut sections: Vec<(Section, LineNr)> = vec![];
+
+    for line in self.lines.iter() {
+      if let Some(s) = Section::parse(line.text.as_ref(), line.keyword) {
+        if sections.last().map(|(t, _)| *t) != Some(s) {
+          sections.push((s, line.number));
+        }
+      }
+    }
+
+    rules.iter().flat_map(|r| r.check(&sections)).collect()
+  }
+
+  /// Check that the lines `first..last` consist of whole cards, i.e. that a
+  /// card starts at both ends of the range if they're inside of a level 1
+  /// fold. Returns the number of cards, or an error if there are none.
+  pub fn whole_cards(
+    &self,
+    first: LineNr,
+    last: LineNr,
+  ) -> Result<usize, Error> {
+    for bound in &[first, last] {
+      let fold = self
+        .folds
+        .iter()
+        .map(|(r, _)| r)
+        .find(|r| r[0] < *bound && *bound <= r[1]);
+      // Comments are not part of the lines, so check the next line after them
+      let next = match self.lines.search(*bound) {
+        Ok(i) | Err(i) => i,
+      };
+      let starts_card = match self.lines.get(next) {
+        Some(l) => l.keyword.is_some(),
+        None => true,
+      };
+
+      if let (Some(r), false) = (fold, starts_card) {
+        return Err(failure::err_msg(format!(
+          "The range cuts through a card in lines {}-{}!",
+          r[0] + 1,
+          r[1] + 1
+        )));
+      }
+    }
+
+    let count = self
+      .cards()
+      .skip_while(|c| c.range[0] < first)
+      .take_while(|c| c.range[0] < last)
+      .count();
+
+    if count == 0 {
+      return Err(failure::err_msg("No cards in the range!"));
+    }
+
+    Ok(count)
+  }
+
+  /// Describe the card in the lines `range` (end-inclusive), see
+  /// [`card_range`](crate::bufdata::BufData::card_range). The `lines` are
+  /// the text of the buffer starting at the line `start`, which may be before
+  /// the card to include the `$#` header of its keyword line. Returns a map
+  /// with the `keyword` of the card, its `first` and `last` line, the `lines`
+  /// with their cell values, see [`card::value`](crate::card::value), and the
+  /// `diagnostics` of the card: Cells with invalid contents and duplicate IDs.
+  pub fn card_info(
+    &self,
+    range: [LineNr; 2],
+    start: LineNr,
+    lines: &[String],
+  ) -> Result<Value, Error> {
+    let [first, last] = range;
+    let keyword = self
+      .lines
+      .search(first)
+      .ok()
+      .and_then(|i| self.lines.get(i)?.keyword)
+      .ok_or_else(|| {
+        failure::err_msg(format!("No card starts in line {}!", first + 1))
+      })?;
+    let card = version::card_for(keyword, self.metadata.version);
+
+    let numbered: Vec<(LineNr, &[u8])> = lines
+      .iter()
+      .enumerate()
+      .map(|(i, l)| (start + LineNr::from_usize(i), l.as_bytes()))
+      .filter(|(n, t)| *n >= first || t.starts_with(b"$#"))
+      .take_while(|(n, _)| *n <= last)
+      .collect();
+    let values = value::card_values(card, &numbered);
+
+    let mut diagnostics = value::invalid_cells(&values);
+    diagnostics.extend(
+      self
+        .duplicates
+        .iter()
+        .filter(|d| first <= d.line && d.line <= last)
+        .cloned(),
+    );
+
+    let name = format!("{:?}", keyword);
+
+    Ok(Value::from(vec![
+      (Value::from("keyword"), Value::from(name)),
+      (Value::from("first"), Value::from(first)),
+      (Value::from("last"), Value::from(last)),
+      (
+        Value::from("lines"),
+        Value::from(values.iter().map(Value::from).collect::<Vec<_>>()),
+      ),
+      (
+        Value::from("diagnostics"),
+        Value::from(diagnostics.iter().map(Value::from).collect::<Vec<_>>()),
+      ),
+    ]))
+  }
+
+  /// List the selectors of the GES containing `line`. The `lines` of the
+  /// buffer start at the line `start` and need to cover the GES. Returns a
+  /// map with the `first` and `last` line of the GES, and its `selectors`.
+  /// Each of those has the `line`, the `selector` like `PART>NOD`, if it
+  /// `deselects` the entities, and its `items`. If its items are IDs, the
+  /// `entity` is given, along with how many of the IDs are defined as
+  /// `matched`, and the single IDs that are `missing`. IDs are looked up in
+  /// the buffer and the `index` of its include files. If there are include
+  /// files, but no index, the result is not `complete`.
+  pub fn ges_contents(
+    &self,
+    start: LineNr,
+    lines: &[String],
+    line: LineNr,
+    index: Option<&Index>,
+  ) -> Result<Value, Error> {
+    let is_ges = |i: usize| GesType::GesNode.contains(lines[i].as_bytes());
+    let number = |i: usize| start + LineNr::from_usize(i);
+    let not_ges =
+      || failure::err_msg(format!("Line {} is not part of a GES!", line + 1));
+
+    let mut at = usize::from(line)
+      .checked_sub(usize::from(start))
+      .filter(|i| *i < lines.len())
+      .ok_or_else(not_ges)?;
+    if at > 0 && GesType::GesNode.ended_by(lines[at].as_bytes()) {
+      at -= 1;
+    }
+    if !is_ges(at) {
+      return Err(not_ges());
+    }
+
+    let mut first = at;
+    while first > 0 && is_ges(first - 1) {
+      first -= 1;
+    }
+    let mut last = at;
+    while last + 1 < lines.len() && is_ges(last + 1) {
+      last += 1;
+    }
+
+    let mut definitions = HashMap::new();
+    let mut selectors = vec![];
+
+    for (i, text) in lines.iter().enumerate().take(last + 1).skip(first) {
+      let ges = match GesLine::parse(text.as_bytes()) {
+        Some(l) => l,
+        None => continue,
+      };
+      let mut selector = vec![
+        (Value::from("line"), Value::from(number(i))),
+        (Value::from("selector"), Value::from(ges.selector.as_str())),
+        (Value::from("deselects"), Value::from(ges.deselects())),
+        (
+          Value::from("items"),
+          Value::from(
+            ges
+              .items
+              .iter()
+              .map(|i| Value::from(i.to_string()))
+              .collect::<Vec<_>>(),
+          ),
+        ),
+      ];
+
+      if let Some(entity) = ges.entity() {
+        let defined = definitions
+          .entry(entity)
+          .or_insert_with(|| self.definitions(entity));
+        let included = |id: i64| {
+          !defined.contains_key(&id)
+            && index.map_or(false, |i| i.find(entity, id).is_some())
+        };
+
+        let mut matched = 0;
+        let mut missing = vec![];
+        for item in &ges.items {
+          match *item {
+            GesItem::Id(id) if defined.contains_key(&id) || included(id) => {
+              matched += 1
+            }
+            GesItem::Id(id) => missing.push(Value::from(id)),
+            GesItem::Range(from, to) => {
+              let within = |id: &i64| from <= *id && *id <= to;
+              matched += defined.keys().filter(|id| within(id)).count();
+              matched += index.map_or(0, |i| {
+                i.ids(entity)
+                  .filter(|id| within(id) && !defined.contains_key(id))
+                  .count()
+              });
+            }
+            GesItem::Name(_) => {}
+          }
+        }
+
+        selector.extend(vec![
+          (Value::from("entity"), Value::from(format!("{:?}", entity))),
+          (Value::from("matched"), Value::from(matched)),
+          (Value::from("missing"), Value::from(missing)),
+        ]);
+      }
+
+      selectors.push(Value::from(selector));
+    }
+
+    let complete = index.is_some() || self.includes().is_empty();
+
+    Ok(Value::from(vec![
+      (Value::from("first"), Value::from(number(first))),
+      (Value::from("last"), Value::from(number(last))),
+      (Value::from("complete"), Value::from(complete)),
+      (Value::from("selectors"), Value::from(selectors)),
+    ]))
+  }
+
+  /// The range of the card containing `line`, from its keyword line to the
+  /// line before the next card in the same level 1 fold. Comments inside of
+  /// the card are included. Returns `None` if `line` is not part of a card.
+  pub fn card_range(&self, line: LineNr) -> Option<[LineNr; 2]> {
+    let fold = self
+      .folds
+      .iter()
+      .map(|(r, _)| r)
+      .find(|r| r[0] <= line && line <= r[1])?;
+    let next = match self.lines.search(line) {
+      Ok(i) => i + 1,
+      Err(i) => i,
+    };
+
+    let start = self
+      .lines
+      .range(0..next)
+      .rev()
+      .find(|l| l.keyword.is_some())
+      .map(|l| l.number)?;
+    let end = self
+      .lines
+      .range(next..self.lines.len())
+      .take_while(|l| l.number <= fold[1])
+      .find(|l| l.keyword.is_some())
+      .map_or(fold[1], |l| l.number.prev());
+
+    Some([start, end])
+  }
+
+  /// The lines of "a card" around `line` (`inner` false) or of the "inner
+  /// card" (`inner` true), for the text objects selecting cards. A comment
+  /// belongs to the card whose keyword line follows it, so a card starts with
+  /// the comments directly above its keyword line and ends with its last line
+  /// that isn't a comment. The inner card leaves out the keyword line and the
+  /// comments following it, for a card of only one line it's just the
+  /// keyword line. Returns `None` if `line` is neither part of a card nor a
+  /// comment above one.
+  pub fn card_object(&self, line: LineNr, inner: bool) -> Option<[LineNr; 2]> {
+    let [start, end] = match self.lines.search(line) {
+      Ok(_) => self.card_range(line)?,
+      Err(i) => match self.lines.get(i) {
+        Some(l) if l.keyword.is_some() => self
+          .card_range(l.number)
+          .or_else(|| self.card_range(line))?,
+        _ => self.card_range(line)?,
+      },
+    };
+
+    // `start` is a keyword line, and `end` is not before it
+    let idx = self.lines.search(start).ok()?;
+    let last = match self.lines.search(end) {
+      Ok(i) => i,
+      Err(i) => i - 1,
+    };
+    let end = self.lines.get(last)?.number;
+
+    if inner {
+      let first = self.lines.get(idx + 1).filter(|l| l.number <= end);
+      Some([first.map_or(start, |l| l.number), end])
+    } else {
+      let first = match idx.checked_sub(1).and_then(|i| self.lines.get(i)) {
+        Some(l) => l.number.saturating_add(1),
+        None => LineNr::from_usize(0),
+      };
+      Some([first, end])
+    }
+  }
+
+  /// The lines of the run of cards around `line` that share the layout of
+  /// its line, e.g. a block of `SHELL`s. Only cards of a single line form a
+  /// run, for other cards this is just `line`. Returns `None` if `line` is
+  /// not part of a card.
+  pub fn cell_run(&self, line: LineNr) -> Option<[LineNr; 2]> {
+    let fold = self.folds.containing(line)?;
+    let idx = self.lines.search(line).ok()?;
+
+    // The cells of the index `i` of the lines, if it's a card of one line
+    let cells = |i: usize| {
+      let l = self.lines.get(i)?;
+      let next = self.lines.get(i + 1);
+      if next.map_or(false, |n| n.keyword.is_none() && n.number <= fold[1]) {
+        return None;
+      }
+      let card = version::card_for(l.keyword?, self.metadata.version);
+      card.layout(l.text.as_ref()).first()?.cells()
+    };
+    let own = match cells(idx) {
+      Some(c) => c,
+      None => return Some([line, line]),
+    };
+    let same = |i: usize| {
+      cells(i).map_or(false, |c| ptr::eq(c, own))
+        && self
+          .lines
+          .get(i)
+          .map_or(false, |l| fold[0] <= l.number && l.number <= fold[1])
+    };
+
+    let mut first = idx;
+    while first > 0 && same(first - 1) {
+      first -= 1;
+    }
+    let mut last = idx;
+    while same(last + 1) {
+      last += 1;
+    }
+
+    Some([self.lines.get(first)?.number, self.lines.get(last)?.number])
+  }
+
+  /// The column range of the cell `field` of the line `line`, along with the
+  /// lines of its [`cell_run`](crate::bufdata::BufData::cell_run) `run`.
+  /// `lines` is the text of the buffer starting at the line `start`, which
+  /// may be before the run to include the `$#` header naming the cells. The
+  /// `field` is either the name of a cell in the header, or its index
+  /// starting at 1.
+  ///
+  /// Returns a map with the `first` and `last` line of the run, the `start`
+  /// and (exclusive) `end` column of the cell, its `kind` and `name`, if the
+  /// header names it.
+  pub fn cell_range(
+    &self,
+    run: [LineNr; 2],
+    start: LineNr,
+    lines: &[String],
+    line: LineNr,
+    field: &str,
+  ) -> Result<Value, Error> {
+    let cells = self.line_cells(run, start, lines, line)?;
+    let cell = match field.parse::<usize>() {
+      Ok(i) if i > 0 => cells.get(i - 1),
+      _ => cells.iter().find(|c| {
+        c.name
+          .as_ref()
+          .map_or(false, |n| n.eq_ignore_ascii_case(field.trim()))
+      }),
+    }
+    .ok_or_else(|| {
+      failure::err_msg(format!("No cell '{}' in line {}!", field, line + 1))
+    })?;
+
+    let mut map = vec![
+      (Value::from("first"), Value::from(run[0])),
+      (Value::from("last"), Value::from(run[1])),
+      (Value::from("start"), Value::from(u64::from(cell.start))),
+      (Value::from("end"), Value::from(u64::from(cell.end))),
+      (Value::from("kind"), Value::from(cell.kind)),
+    ];
+    if let Some(ref name) = cell.name {
+      map.push((Value::from("name"), Value::from(name.as_str())));
+    }
+
+    Ok(Value::from(map))
+  }
+
+  /// The number in the cell at the column `column` of the line `line`, with
+  /// `run`, `start` and `lines` as for
+  /// [`cell_range`](crate::bufdata::BufData::cell_range). The content of the
+  /// cell is read like the solver does, see
+  /// [`card::number`](crate::card::number).
+  ///
+  /// Returns a map with the `value`, its `normalized` and `engineering`
+  /// notations, and the `name` of the cell if the header names it. If `units`
+  /// has a unit for the cell, the value multiplied by its factor is added as
+  /// `scaled`, in engineering notation, along with the name of the `unit`.
+  pub fn cell_number(
+    &self,
+    run: [LineNr; 2],
+    start: LineNr,
+    lines: &[String],
+    line: LineNr,
+    column: usize,
+    units: &Units,
+  ) -> Result<Value, Error> {
+    let cells = self.line_cells(run, start, lines, line)?;
+    let cell = cells
+      .iter()
+      .find(|c| c.start as usize <= column && column < c.end as usize)
+      .ok_or_else(|| {
+        failure::err_msg(format!(
+          "No cell in line {}, column {}!",
+          line + 1,
+          column + 1
+        ))
+      })?;
+    if cell.kind != "float" && cell.kind != "integer" {
+      return Err(failure::err_msg(format!(
+        "The cell holds a {}, not a number!",
+        cell.kind
+      )));
+    }
+
+    let text = lines
+      .get((line - start).max(0) as usize)
+      .map(|l| l.as_bytes())
+      .unwrap_or(&[]);
+    let end = (cell.end as usize).min(text.len());
+    let raw =
+      String::from_utf8_lossy(text.get(cell.start as usize..end).unwrap_or(&[]));
+    let f = number::parse(&raw).ok_or_else(|| {
+      failure::err_msg(format!("'{}' is not a number!", raw.trim()))
+    })?;
+
+    let mut map = vec![
+      (Value::from("value"), Value::from(f)),
+      (Value::from("normalized"), Value::from(number::normalized(f))),
+      (Value::from("engineering"), Value::from(number::engineering(f))),
+    ];
+    if let Some(ref name) = cell.name {
+      map.push((Value::from("name"), Value::from(name.as_str())));
+    }
+    if let Some(unit) = units.get(cell.name.as_deref()) {
+      map.push((
+        Value::from("scaled"),
+        Value::from(number::engineering(f * unit.factor)),
+      ));
+      map.push((Value::from("unit"), Value::from(unit.name.as_str())));
+    }
+
+    Ok(Value::from(map))
+  }
+
+  /// The cells of the line `line`, named by the header of its run `run`, see
+  /// [`cell_range`](crate::bufdata::BufData::cell_range)
+  fn line_cells(
+    &self,
+    run: [LineNr; 2],
+    start: LineNr,
+    lines: &[String],
+    line: LineNr,
+  ) -> Result<Vec<CellData>, Error> {
+    let range = self.card_range(line).ok_or_else(|| {
+      failure::err_msg(format!("Line {} is not part of a card!", line + 1))
+    })?;
+    let keyword = self
+      .lines
+      .search(range[0])
+      .ok()
+      .and_then(|i| self.lines.get(i)?.keyword)
+      .ok_or_else(|| {
+        failure::err_msg(format!("No card starts in line {}!", range[0] + 1))
+      })?;
+    let card = version::card_for(keyword, self.metadata.version);
+
+    let numbered: Vec<(LineNr, &[u8])> = lines
+      .iter()
+      .enumerate()
+      .map(|(i, l)| (start + LineNr::from_usize(i), l.as_bytes()))
+      .collect();
+    // The cells of a run are named by the last header before the line, the
+    // ones of other cards only by the header right before their line
+    let cardlines: Vec<(LineNr, &[u8])> = if run == [line, line] {
+      numbered
+        .into_iter()
+        .filter(|(n, t)| *n >= range[0] || t.starts_with(b"$#"))
+        .take_while(|(n, _)| *n <= range[1])
+        .collect()
+    } else {
+      let header = numbered
+        .iter()
+        .rev()
+        .find(|(n, t)| *n < line && t.starts_with(b"$#"));
+      header
+        .into_iter()
+        .chain(numbered.iter().filter(|(n, _)| *n == line))
+        .cloned()
+        .collect()
+    };
+
+    let values = value::card_values(card, &cardlines);
+
+    Ok(
+      values
+        .into_iter()
+        .find(|l| l.line == line)
+        .map(|l| l.cells)
+        .unwrap_or_default(),
+    )
+  }
+
+  #[cfg(test)]
+  pub fn folds_to_vec(&self) -> Vec<(usize, usize, Keyword)> {
+    self.folds.to_vec()
+  }
+
+  #[cfg(test)]
+  pub fn folds_level2_to_vec(&self) -> Vec<(usize, usize, Keyword)> {
+    self.folds_level2.to_vec()
+  }
+}
+
+/// Pack up the changed lines and diagnostics of an operation into a map with
+/// the keys `edits` and `diagnostics`, suitable to send to neovim.
+fn edits_value(
+  edits: Vec<(LineNr, String)>,
+  diagnostics: &[Diagnostic],
+) -> Value {
+  Value::from(vec![
+    (
+      Value::from("edits"),
+      Value::from(
+        edits
+          .into_iter()
+          .map(|(l, t)| Value::from(vec![Value::from(l), Value::from(t)]))
+          .collect::<Vec<_>>(),
+      ),
+    ),
+    (
+      Value::from("diagnostics"),
+      Value::from(diagnostics.iter().map(Value::from).collect::<Vec<_>>()),
+    ),
+  ])
+}
+
+/// The keyword of the line `line` of `lines`, if it starts a card
+fn line_keyword(lines: &Lines, line: LineNr) -> Option<Keyword> {
+  let idx = lines.search(line).ok()?;
+  lines.get(idx)?.keyword
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::{
+    bufdata::{
+      degraded::Degraded,
+      foldgroup::{FoldGroup, FoldGroups},
+      folds::{ExprLevel, ExprLevel::*, Folds},
+      foldtext::{FoldKind, FoldTexts},
+      highlights::{HighlightGroup as Hl, HlConfig},
+      refresh::Refresh,
+      BufData,
+    },
+    card::{
+      keyword::Keyword::*,
+      number::{Unit, Units},
+      version::InputVersion,
+      xref::Entity,
+    },
+    linenr::LineNr,
+  };
+
+  const LINES: [&str; 4] = [
+    "NODE  /        1              0.             0.5",
+    "#Comment",
+    "NODE  /        2              0.          0.5  ",
+    "        3              1.",
+  ];
+
+  fn hint(bufdata: &BufData, line: usize, col: u8) -> Vec<(String, Value)> {
+    match bufdata.cell_hint(LineNr::from_usize(line), col) {
+      Value::Map(m) => m
+        .into_iter()
+        .map(|(k, v)| (k.as_str().unwrap().to_string(), v))
+        .collect(),
+      v => panic!("Not a map: {:?}", v),
+    }
+  }
+
+  #[test]
+  fn cell_hints() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    // Inside the first coordinate, blanks to pad with
+    let h = hint(&bufdata, 0, 20);
+    assert_eq!(h[0], ("shifts".to_string(), Value::from(true)));
+    assert_eq!(
+      h[1],
+      (
+        "cell".to_string(),
+        Value::from(vec![Value::from(16_u64), Value::from(32_u64)])
+      )
+    );
+    assert_eq!(h[2], ("pad".to_string(), Value::from(20_u64)));
+
+    // No blank left in the cell to pad with
+    let h = hint(&bufdata, 0, 46);
+    assert_eq!(h.len(), 2);
+
+    // Only trailing blanks follow
+    let h = hint(&bufdata, 2, 46);
+    assert_eq!(h[0], ("shifts".to_string(), Value::from(false)));
+
+    // Comments and lines not belonging to the card
+    assert_eq!(hint(&bufdata, 1, 10).len(), 1);
+    assert_eq!(hint(&bufdata, 3, 10).len(), 1);
+  }
+
+  const NSMAS: [&str; 4] = [
+    "INPUTVERSION 2008",
+    "NSMAS /        1              0.              0.              0.",
+    "NAME Nonstructural mass",
+    "        END",
+  ];
+
+  #[test]
+  fn foldtext_templates() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let mut texts = FoldTexts::default();
+    let _ = texts
+      .templates
+      .insert(FoldKind::Card, " {keyword}: {lines} Zeilen ".to_string());
+    bufdata.set_foldtexts(texts);
+
+    let fold = Value::from(vec![
+      Value::from(1_u64),
+      Value::from(3_u64),
+      Value::from(" Node: 3 Zeilen "),
+    ]);
+    assert_eq!(bufdata.fold_calls()[0], Value::from(vec![fold]));
+
+    // The stored foldtexts are unchanged
+    assert_eq!(bufdata.fold_state().folds[0].1, " 3 lines: Node ");
+  }
+
+  #[test]
+  fn titled_folds() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "NODE  /        1              0.             0.5              0.",
+      "PART  /       37   SHELL       2       0       0       0",
+      "NAME bumper_outer ->1",
+      "NAME front",
+      "END_PART",
+      "PART  /       38   SHELL       2       0       0       0",
+      "NAME",
+      "END_PART",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+
+    let texts = |b: &BufData| -> Vec<String> {
+      b.fold_state().folds.into_iter().map(|f| f.1).collect()
+    };
+    assert_eq!(
+      texts(&bufdata),
+      vec![
+        " 1 lines: Node ",
+        " PartShell 37: bumper_outer front (4 lines) ",
+        " 2 PartShells ",
+        " 3 lines: PartShell ",
+      ]
+    );
+
+    // Moving the fold keeps its title
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(0),
+        LineNr::from_usize(0),
+        vec![lines[0].to_string()],
+      )
+      .unwrap();
+    assert_eq!(
+      texts(&bufdata)[1],
+      " PartShell 37: bumper_outer front (4 lines) "
+    );
+  }
+
+  #[test]
+  fn update_inside_of_opaque_block() {
+    let node =
+      "NODE  /        1              0.             0.5              0.";
+    let lines = [
+      "LOOKU /        1",
+      "NAME table",
+      node,
+      "        FUNCTION",
+      node,
+      "        END",
+      "SHELL /     3129       1       1    2967    2971    2970",
+    ];
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+    let expected = vec![(0, 5, Looku), (6, 6, Shell)];
+    assert_eq!(bufdata.folds_to_vec(), expected);
+
+    // The NODE lines are keyword lines, but no cards of their own
+    for line in &[4, 2] {
+      let _ = bufdata
+        .update(
+          LineNr::from_usize(*line),
+          LineNr::from_usize(line + 1),
+          vec![node.replace("0.5", "1.5")],
+        )
+        .unwrap();
+      assert_eq!(bufdata.folds_to_vec(), expected);
+    }
+  }
+
+  #[test]
+  fn duplicates_after_update() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    bufdata.set_check_duplicates(true);
+    assert!(bufdata.duplicates().is_empty());
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(2),
+        LineNr::from_usize(3),
+        vec![LINES[0].to_string()],
+      )
+      .unwrap();
+
+    let duplicates = bufdata.duplicates();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].line, LineNr::from_usize(2));
+    assert_eq!(duplicates[0].target, Some(LineNr::from_usize(0)));
+
+    bufdata.set_check_duplicates(false);
+    assert!(bufdata.duplicates().is_empty());
+  }
+
+  #[test]
+  fn fold_levels_for_foldexpr() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let levels = |bufdata: &mut BufData| -> Vec<ExprLevel> {
+      (0..5)
+        .map(|l| bufdata.fold_level(LineNr::from_usize(l)))
+        .collect()
+    };
+    assert_eq!(
+      levels(&mut bufdata),
+      vec![Start, Inside, Inside, Outside, Outside]
+    );
+
+    // The cached levels are dropped by an update
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(1),
+        LineNr::from_usize(1),
+        vec!["SHELL /     3129       1       1    2967    2971".to_string()],
+      )
+      .unwrap();
+    assert_eq!(
+      levels(&mut bufdata),
+      vec![Start, Start, Outside, Start, Outside]
+    );
+  }
+
+  #[test]
+  fn folds_for_a_range() {
+    let node =
+      "NODE  /        1              0.             0.5              0.";
+    let shell = "SHELL /     3129       1       1    2967    2971    2970";
+    let lines = [node, node, "#Comment", shell, shell, node, node];
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+
+    // The starts of the level 1 folds intersecting the lines
+    let starts = |first: usize, last: usize| -> Vec<Value> {
+      bufdata.folds_in_range(
+        LineNr::from_usize(first),
+        LineNr::from_usize(last),
+      )[0]
+        .iter()
+        .map(|f| f.as_array().unwrap()[0].clone())
+        .collect()
+    };
+
+    assert_eq!(starts(0, 0), vec![Value::from(1)]);
+    assert_eq!(starts(1, 2), vec![Value::from(1)]);
+    assert_eq!(starts(2, 2), vec![]);
+    assert_eq!(starts(2, 3), vec![Value::from(4)]);
+    assert_eq!(starts(4, 5), vec![Value::from(4), Value::from(6)]);
+    assert_eq!(starts(7, 9), vec![]);
+    assert_eq!(
+      bufdata.folds_in_range(LineNr::from_usize(0), LineNr::from_usize(6)),
+      bufdata.fold_levels()
+    );
+  }
+
+  #[test]
+  fn refresh_changed_cards() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+    let mut deck = vec![
+      "NODE  /        1              0.             0.5",
+      "NODE  /        2              0.             0.5",
+      "#Comment",
+      "SHELL /     3129       1    1000    1001    1003    1002",
+      "BEAM  /        1       1       1       2       3",
+    ];
+
+    assert_eq!(bufdata.refresh(lines(&deck)).unwrap(), Refresh::Parsed);
+    assert_eq!(bufdata.refresh(lines(&deck)).unwrap(), Refresh::Unchanged);
+
+    deck[3] = "SHELL /     3129       1    1000    1001    1003    1004";
+    deck.insert(
+      4,
+      "SHELL /     3130       1    1000    1001    1003    1002",
+    );
+    match bufdata.refresh(lines(&deck)).unwrap() {
+      Refresh::Updated {
+        firstline,
+        lastline,
+        added,
+        ..
+      } => {
+        assert_eq!(firstline, LineNr::from_usize(3));
+        assert_eq!(lastline, LineNr::from_usize(4));
+        assert_eq!(added, 1);
+      }
+      r => panic!("Not updated: {:?}", r),
+    }
+
+    let mut fresh = BufData::new(&buf);
+    fresh.parse_strs(&deck).unwrap();
+    assert_eq!(bufdata.folds_to_vec(), fresh.folds_to_vec());
+    assert!(bufdata.highlights.iter().eq(fresh.highlights.iter()));
+  }
+
+  #[test]
+  fn disabled_highlights() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let folds = bufdata.folds_to_vec();
+    let highlights = bufdata.highlights.len();
+    assert!(highlights > 0);
+
+    let calls = bufdata.set_highlights_enabled(false).unwrap();
+    assert_eq!(calls, Some(bufdata.clear_calls()));
+    assert!(bufdata.highlights.is_empty());
+    assert_eq!(bufdata.highlight_all_calls(), None);
+
+    let (range, _) = bufdata
+      .update(
+        LineNr::from_usize(2),
+        LineNr::from_usize(3),
+        vec![LINES[2].to_string()],
+      )
+      .unwrap();
+    assert_eq!(range, 0..0);
+    assert!(bufdata.highlights.is_empty());
+    assert_eq!(bufdata.folds_to_vec(), folds);
+
+    let calls = bufdata.set_highlights_enabled(true).unwrap();
+    assert_eq!(calls.map(|c| c.len()), Some(highlights + 1));
+    assert_eq!(bufdata.highlights.len(), highlights);
+    assert_eq!(bufdata.set_highlights_enabled(true).unwrap(), None);
+  }
+
+  #[test]
+  fn external_highlights() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let highlights = bufdata.highlights.len();
+    let line = LineNr::from_usize;
+    let external = |bufdata: &BufData| -> Vec<_> {
+      bufdata
+        .highlights
+        .iter()
+        .filter_map(|((l, s, e), hl)| match hl {
+          Hl::External(g) => Some((l, s, e, g)),
+          _ => None,
+        })
+        .collect()
+    };
+
+    // Spans outside of the lines are ignored
+    let spans = vec![
+      ((line(3), 2, 6), Hl::external("LogWarn")),
+      ((line(1), 0, 8), Hl::external("LogError")),
+      ((line(9), 0, 8), Hl::external("LogError")),
+    ];
+    let calls = bufdata.set_external_highlights(spans, line(0), line(4));
+    assert_eq!(calls.map(|c| c.len()), Some(highlights + 3));
+    assert_eq!(
+      external(&bufdata),
+      vec![(line(1), 0, 8, "LogError"), (line(3), 2, 6, "LogWarn")]
+    );
+    assert!(bufdata.check_consistency().is_ok());
+
+    // Moved by an update, and dropped when their line changes
+    let _ = bufdata
+      .update(line(1), line(1), vec!["#Another comment".to_string()])
+      .unwrap();
+    assert_eq!(
+      external(&bufdata),
+      vec![(line(2), 0, 8, "LogError"), (line(4), 2, 6, "LogWarn")]
+    );
+    let _ = bufdata
+      .update(line(4), line(5), vec![LINES[3].to_string()])
+      .unwrap();
+    assert_eq!(external(&bufdata), vec![(line(2), 0, 8, "LogError")]);
+
+    // Replacing them keeps the ones of nvimpam
+    let _ = bufdata.set_external_highlights(vec![], line(0), line(5));
+    assert!(external(&bufdata).is_empty());
+    assert_eq!(bufdata.highlights.len(), highlights);
+  }
+
+  #[test]
+  fn banner_folds() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+    let mut deck = vec![
+      "$---- Part: Door ----",
+      "$",
+      "NODE  /        1              0.             0.5",
+      "NODE  /        2              0.             0.5",
+    ];
+    bufdata.parse_vec(lines(&deck)).unwrap();
+
+    let banner = |first: u64, last: u64, text: &str| {
+      Value::from(vec![
+        Value::from(first),
+        Value::from(last),
+        Value::from(text),
+      ])
+    };
+    assert_eq!(bufdata.fold_levels()[1], vec![banner(1, 2, " Part: Door ")]);
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(1),
+        LineNr::from_usize(1),
+        vec!["$".to_string()],
+      )
+      .unwrap();
+    assert_eq!(bufdata.fold_levels()[1], vec![banner(1, 3, " Part: Door ")]);
+
+    // Only a comment changed, but the banner is found anyways
+    deck[0] = "$---- Part: Roof ----";
+    deck.insert(1, "$");
+    assert_eq!(bufdata.refresh(lines(&deck)).unwrap(), Refresh::Unchanged);
+    assert_eq!(bufdata.fold_levels()[1], vec![banner(1, 3, " Part: Roof ")]);
+
+    bufdata.set_banner_patterns(vec![]);
+    assert!(bufdata.fold_levels()[1].is_empty());
+  }
+
+  #[test]
+  fn degraded_buffers() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.set_max_size(1000, Degraded::Folds);
+    bufdata.parse_strs(&LINES).unwrap();
+    assert_eq!(bufdata.degraded(), None);
+    assert!(bufdata.highlights_enabled());
+
+    let mut bufdata = BufData::new(&buf);
+    bufdata.set_max_size(100, Degraded::Folds);
+    bufdata.parse_strs(&LINES).unwrap();
+    assert_eq!(bufdata.degraded(), Some(Degraded::Folds));
+    assert!(!bufdata.highlights_enabled());
+    assert!(bufdata.highlights.is_empty());
+    assert_eq!(bufdata.fold_levels()[0].len(), 1);
+
+    let mut bufdata = BufData::new(&buf);
+    bufdata.set_max_size(100, Degraded::Level2);
+    bufdata.parse_strs(&LINES).unwrap();
+    assert!(bufdata.highlights.is_empty());
+    assert_eq!(bufdata.folds_to_vec().len(), 1);
+    assert!(bufdata.fold_levels()[0].is_empty());
+
+    let mut bufdata = BufData::new(&buf);
+    bufdata.set_max_size(100, Degraded::Viewport);
+    bufdata.parse_strs(&LINES).unwrap();
+    assert!(!bufdata.highlights.is_empty());
+    assert_eq!(bufdata.highlight_all_calls(), None);
+    assert!(bufdata
+      .highlight_region_calls(
+        bufdata.hl_linerange(LineNr::from_usize(0), LineNr::from_usize(2)),
+        LineNr::from_usize(0),
+        LineNr::from_usize(2),
+      )
+      .is_some());
+  }
+
+  #[test]
+  fn keyword_highlight_overrides() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let mut config = HlConfig::default();
+    config.keywords.push((Node, "MyNodeHl".to_string()));
+    let _ = bufdata.set_highlight_config(config).unwrap();
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let calls = bufdata.highlight_all_calls().unwrap();
+    let groups: Vec<_> = calls[1..]
+      .iter()
+      .map(|c| c.as_array().unwrap()[1].as_array().unwrap()[2].clone())
+      .collect();
+    assert_eq!(groups[0], Value::from("MyNodeHl"));
+    assert_eq!(groups[1], Value::from("PamCellEven"));
+  }
+
+  #[test]
+  fn xref_index_updates() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    bufdata.set_check_duplicates(true);
+    assert!(bufdata.duplicates().is_empty());
+
+    let node = |id: usize| {
+      format!("NODE  / {:>8}              0.             0.5", id)
+    };
+    let edits = vec![
+      (0, 0, vec![node(2)]),
+      (2, 2, vec!["#Comment".to_string(), node(7)]),
+      (1, 3, vec![]),
+      (4, 5, vec![node(1), node(2), "        3".to_string()]),
+    ];
+    let mut duplicates = vec![];
+    for (first, last, linedata) in edits {
+      let _ = bufdata
+        .update(LineNr::from_usize(first), LineNr::from_usize(last), linedata)
+        .unwrap();
+      bufdata.check_consistency().unwrap();
+      duplicates.push(bufdata.duplicates().len());
+    }
+    assert_eq!(duplicates, vec![1, 1, 1, 2]);
+    assert_eq!(
+      bufdata.find_definition(Entity::Node, 7),
+      Some(LineNr::from_usize(1))
+    );
+  }
+
+  #[test]
+  fn strict_mode() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.set_strict(true);
+    bufdata.parse_strs(&LINES).unwrap();
+    assert!(bufdata.check_consistency().is_ok());
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(2),
+        LineNr::from_usize(3),
+        vec!["NODE  /        3              0.          0.5  ".to_string()],
+      )
+      .unwrap();
+    assert_eq!(bufdata.take_inconsistency(), None);
+
+    bufdata
+      .folds_level2
+      .checked_insert(
+        LineNr::from_usize(10),
+        LineNr::from_usize(12),
+        Node,
+      )
+      .unwrap();
+    assert!(bufdata.check_consistency().is_err());
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(0),
+        LineNr::from_usize(1),
+        vec!["NODE  /        4              0.          0.5  ".to_string()],
+      )
+      .unwrap();
+    let msg = bufdata.take_inconsistency().unwrap();
+    assert!(msg.contains("updating lines 1-1"));
+    assert!(msg.contains("Level 2 fold 10-12"));
+    assert!(bufdata.check_consistency().is_ok());
+    assert_eq!(bufdata.take_inconsistency(), None);
+  }
+
+  #[test]
+  fn versioned_layout() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&NSMAS).unwrap();
+
+    assert_eq!(bufdata.metadata().version, Some(InputVersion::new(2008, 0)));
+    // The old layout has no cell for the 4th float
+    assert_eq!(hint(&bufdata, 1, 70).len(), 1);
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(0),
+        LineNr::from_usize(1),
+        vec!["INPUTVERSION 2018".to_string()],
+      )
+      .unwrap();
+    assert_eq!(bufdata.metadata().version, Some(InputVersion::new(2018, 0)));
+
+    bufdata.regenerate().unwrap();
+    assert_eq!(hint(&bufdata, 1, 70).len(), 2);
+  }
+
+  #[test]
+  fn whole_cards() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let line = LineNr::from_usize;
+
+    assert_eq!(bufdata.whole_cards(line(0), line(4)).unwrap(), 2);
+    assert_eq!(bufdata.whole_cards(line(2), line(4)).unwrap(), 1);
+    assert_eq!(bufdata.whole_cards(line(0), line(2)).unwrap(), 1);
+    assert_eq!(bufdata.whole_cards(line(0), line(1)).unwrap(), 1);
+    assert!(bufdata.whole_cards(line(1), line(2)).is_err());
+
+    let lines = [
+      "PART  /        1   SHELL       2       0       0       0",
+      "NAME Part 1",
+      "        0.",
+      "END_PART",
+    ];
+    bufdata.clear();
+    bufdata.parse_strs(&lines).unwrap();
+    assert!(bufdata.whole_cards(line(0), line(4)).is_ok());
+    assert!(bufdata.whole_cards(line(0), line(2)).is_err());
+  }
+
+  #[test]
+  fn card_ranges() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let line = LineNr::from_usize;
+
+    assert_eq!(bufdata.card_range(line(0)), Some([line(0), line(1)]));
+    assert_eq!(bufdata.card_range(line(1)), Some([line(0), line(1)]));
+    assert_eq!(bufdata.card_range(line(2)), Some([line(2), line(2)]));
+    assert_eq!(bufdata.card_range(line(3)), None);
+
+    let lines = [
+      "PART  /        1   SHELL       2       0       0       0",
+      "NAME Part 1",
+      "$ Comment",
+      "        0.",
+      "END_PART",
+    ];
+    bufdata.clear();
+    bufdata.parse_strs(&lines).unwrap();
+    assert_eq!(bufdata.card_range(line(3)), Some([line(0), line(4)]));
+  }
+
+  #[test]
+  fn card_objects() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "$ Header",
+      "PART  /        1   SHELL       2       0       0       0",
+      "$ Name",
+      "NAME Part 1",
+      "        0.",
+      "END_PART",
+      "$ Nodes",
+      "$ of the part",
+      "NODE  /        1              0.             0.5",
+      "NODE  /        2              0.          0.5  ",
+      "$ Trailing",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+    let line = LineNr::from_usize;
+    let object = |l, inner| bufdata.card_object(line(l), inner);
+
+    for &l in &[0, 1, 2, 5] {
+      assert_eq!(object(l, false), Some([line(0), line(5)]));
+      assert_eq!(object(l, true), Some([line(3), line(5)]));
+    }
+    for &l in &[6, 7, 8] {
+      assert_eq!(object(l, false), Some([line(6), line(8)]));
+      assert_eq!(object(l, true), Some([line(8), line(8)]));
+    }
+    assert_eq!(object(9, false), Some([line(9), line(9)]));
+    assert_eq!(object(10, false), None);
+  }
+
+  #[test]
+  fn card_iterator() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "$ Header",
+      "PART  /        1   SHELL       2       0       0       0",
+      "NAME Part 1 ->1",
+      "NAME continued",
+      "$ Comment",
+      "        0.",
+      "END_PART",
+      "NODE  /        1              0.             0.5",
+      "$ Comment",
+      "NODE  /        2              0.          0.5  ",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+    let line = LineNr::from_usize;
+
+    let cards: Vec<_> = bufdata
+      .cards()
+      .map(|c| (c.keyword, c.range, c.title))
+      .collect();
+    assert_eq!(
+      cards,
+      vec![
+        (PartShell, [line(1), line(6)], Some("Part 1".to_string())),
+        (Node, [line(7), line(8)], None),
+        (Node, [line(9), line(9)], None),
+      ]
+    );
+    assert_eq!(bufdata.cards().nth(2).map(|c| c.range[0]), Some(line(9)));
+  }
+
+  #[test]
+  fn convert_card_keeps_following_lines() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "TETR4 /      100       1      11      12      13      14",
+      "",
+      "$ comment",
+      "        stray text",
+      "NODE  /        1              0.             0.5              0.",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+
+    let value = bufdata.convert_card(LineNr::from_usize(0), "tetr10");
+    let map = value.as_map().unwrap();
+    let get = |key: &str| {
+      map
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v.clone())
+        .unwrap()
+    };
+
+    let edits = get("edits");
+    let edits = edits.as_array().unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].as_array().unwrap()[0], Value::from(0_u64));
+
+    let added = get("added");
+    let added = added.as_array().unwrap();
+    assert_eq!(added[0], Value::from(0_u64));
+    assert_eq!(added[1].as_array().unwrap().len(), 2);
+  }
+
+  #[test]
+  fn skip_files_without_cards() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = ["$ comment", "# comment", "$ NODE  /        1"];
+    bufdata.parse_strs(&lines).unwrap();
+
+    assert!(bufdata.folds_to_vec().is_empty());
+    assert!(bufdata.highlights.iter().next().is_none());
+    assert!(bufdata.take_no_cards());
+    assert!(!bufdata.take_no_cards());
+
+    // Updates are still parsed
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(3),
+        LineNr::from_usize(3),
+        vec![
+          "NODE  /        1              0.             0.5              0."
+            .to_string(),
+        ],
+      )
+      .unwrap();
+    assert_eq!(bufdata.folds_to_vec(), vec![(3, 3, Node)]);
+    assert!(!bufdata.take_no_cards());
+
+    let mut bufdata = BufData::new(&buf);
+    bufdata
+      .parse_vec(vec![lines[0].to_string(), lines[1].to_string()])
+      .unwrap();
+    assert!(bufdata.take_no_cards());
+
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_slice(b"$ comment\nNODE  /        1").unwrap();
+    assert_eq!(bufdata.folds_to_vec(), vec![(1, 1, Node)]);
+    assert!(!bufdata.take_no_cards());
+  }
+
+  #[test]
+  fn definitions_and_includes() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "NODE  /        1              0.             0.5              0.",
+      "INCLU / shells.inc",
+      "NODE  /        2              0.             0.5              0.",
+      "NODE  /        1              0.             0.5              0.",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+
+    assert_eq!(
+      bufdata.find_definition(Entity::Node, 1),
+      Some(LineNr::from_usize(0))
+    );
+    assert_eq!(
+      bufdata.find_definition(Entity::Node, 2),
+      Some(LineNr::from_usize(2))
+    );
+    assert_eq!(bufdata.find_definition(Entity::Element, 1), None);
+    assert_eq!(bufdata.includes(), vec!["shells.inc".to_string()]);
+  }
+
+  #[test]
+  fn all_diagnostics() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "NODE  /        1              0.             0.5              x.",
+      "NODE  /        1              0.             0.5              0.",
+      "SHELL /     3129       1       1       2       1       1",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+
+    let messages: Vec<_> = bufdata
+      .diagnostics(None)
+      .into_iter()
+      .map(|d| (usize::from(d.line), d.message))
+      .collect();
+    assert_eq!(
+      messages,
+      vec![
+        (0, "Invalid float cell 'x.'".to_string()),
+        (1, "Node ID 1 is defined more than once".to_string()),
+        (2, "Node 2 is not defined".to_string()),
+        (2, "Part 1 is not defined".to_string()),
+      ]
+    );
+
+    // The references might be defined in the include files
+    let lines = [lines[2], "INCLU / nodes.inc"];
+    bufdata.clear();
+    bufdata.parse_strs(&lines).unwrap();
+    assert!(bufdata.diagnostics(None).is_empty());
+  }
+
+  /// The line numbers of the edits returned by
+  /// [`align_cells`](crate::bufdata::BufData::align_cells)
+  fn aligned(bufdata: &BufData, modified_only: bool) -> Vec<u64> {
+    let v = bufdata.align_cells(
+      LineNr::from_usize(0),
+      LineNr::from_usize(2),
+      modified_only,
+    );
+    let edits = &v.as_map().unwrap()[0].1;
+
+    edits
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|e| e.as_array().unwrap()[0].as_u64().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn align_modified_cards() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let shifted =
+      "NODE  /        2              0.               0.5            0.";
+    let lines = [
+      "NODE  /        1              0.               0.5            0.",
+      "SHELL /     3129       1       1    2967    2971    2970",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+
+    assert_eq!(aligned(&bufdata, false), vec![0]);
+    assert!(aligned(&bufdata, true).is_empty());
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(1),
+        LineNr::from_usize(1),
+        vec![shifted.to_string()],
+      )
+      .unwrap();
+    assert_eq!(aligned(&bufdata, false), vec![0, 1]);
+    assert_eq!(aligned(&bufdata, true), vec![1]);
+
+    bufdata.written();
+    assert!(aligned(&bufdata, true).is_empty());
+  }
+
+  #[test]
+  fn cell_ranges() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "$#          IDEL   IPART    NOD1    NOD2    NOD3    NOD4           THICK",
+      "SHELL /     3129       1       1    2967    2971    2970              1.",
+      "SHELL /     3130       1       1    2967    2971    2970              1.",
+      "$ A comment inside of the block",
+      "SHELL /     3131       1       1    2967    2971    2970              1.",
+      "NODE  /        1              0.             0.5              0.",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let line = LineNr::from_usize;
+
+    let run = bufdata.cell_run(line(2)).unwrap();
+    assert_eq!(run, [line(1), line(4)]);
+    assert_eq!(bufdata.cell_run(line(5)), Some([line(5), line(5)]));
+    assert_eq!(bufdata.cell_run(line(3)), None);
+
+    let range = bufdata
+      .cell_range(run, line(0), &lines, line(4), "thick")
+      .unwrap();
+    assert_eq!(
+      range,
+      Value::from(vec![
+        (Value::from("first"), Value::from(1)),
+        (Value::from("last"), Value::from(4)),
+        (Value::from("start"), Value::from(64)),
+        (Value::from("end"), Value::from(72)),
+        (Value::from("kind"), Value::from("float")),
+        (Value::from("name"), Value::from("THICK")),
+      ])
+    );
+
+    // Without a header, cells can be given by their index
+    let range = bufdata
+      .cell_range(run, line(1), &lines[1..], line(2), "3")
+      .unwrap();
+    assert_eq!(range.as_map().unwrap()[2].1, Value::from(16));
+    assert!(bufdata
+      .cell_range(run, line(1), &lines[1..], line(2), "thick")
+      .is_err());
+  }
+
+  #[test]
+  fn preview_keeps_data() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let highlights: Vec<_> = bufdata.highlights.iter().collect();
+    let line = LineNr::from_usize;
+
+    let (calls, touched) = bufdata
+      .preview_calls(
+        line(0),
+        line(1),
+        vec![
+          "NODE  /        1              0.             0.7".to_string(),
+          "NODE  /        9              0.             0.7".to_string(),
+        ],
+      )
+      .unwrap();
+    let calls = calls.unwrap();
+
+    // The second NODE line moved down by one
+    assert_eq!(touched, [line(0), line(3)]);
+    let clear = bufdata.clear_regions_calls(&[touched]).unwrap();
+    assert_eq!(calls[0], clear[0]);
+    assert!(calls.len() > 1);
+
+    assert_eq!(bufdata.highlights.iter().collect::<Vec<_>>(), highlights);
+    assert_eq!(bufdata.lines.len(), 3);
+    assert_eq!(
+      bufdata.lines.get(0).map(|l| l.text.as_ref()),
+      Some(LINES[0].as_bytes())
+    );
+  }
+
+  #[test]
+  fn cell_numbers() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "$#          IDEL   IPART    NOD1    NOD2    NOD3    NOD4           THICK",
+      "SHELL /     3129       1       1    2967    2971    2970           1.5-3",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let line = LineNr::from_usize;
+    let run = [line(1), line(1)];
+    let units = Units(vec![(
+      "thick".to_string(),
+      Unit {
+        factor: 1000.,
+        name: "mm".to_string(),
+      },
+    )]);
+
+    let number = bufdata
+      .cell_number(run, line(0), &lines, line(1), 70, &units)
+      .unwrap();
+    assert_eq!(
+      number,
+      Value::from(vec![
+        (Value::from("value"), Value::from(0.0015)),
+        (Value::from("normalized"), Value::from("1.5E-03")),
+        (Value::from("engineering"), Value::from("1.5E-03")),
+        (Value::from("name"), Value::from("THICK")),
+        (Value::from("scaled"), Value::from("1.5E+00")),
+        (Value::from("unit"), Value::from("mm")),
+      ])
+    );
+
+    let number = bufdata
+      .cell_number(run, line(0), &lines, line(1), 10, &Units::default())
+      .unwrap();
+    assert_eq!(number.as_map().unwrap()[2].1, Value::from("3.129E+03"));
+    assert_eq!(number.as_map().unwrap().len(), 4);
+
+    // The keyword, and after the last cell
+    assert!(bufdata
+      .cell_number(run, line(0), &lines, line(1), 2, &units)
+      .is_err());
+    assert!(bufdata
+      .cell_number(run, line(0), &lines, line(1), 90, &units)
+      .is_err());
+  }
+
+  #[test]
+  fn card_infos() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "$#         IDNOD               X               Y               Z",
+      "NODE  /        1              0.             0.5              0.",
+      "NODE  /        1              0.             0.5              0.",
+    ];
+    bufdata.set_check_duplicates(true);
+    bufdata.parse_strs(&lines).unwrap();
+    let text: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let line = LineNr::from_usize;
+
+    let info = match bufdata.card_info([line(1), line(1)], line(0), &text) {
+      Ok(Value::Map(m)) => m,
+      v => panic!("Not a map: {:?}", v),
+    };
+    assert_eq!(info[0], (Value::from("keyword"), Value::from("Node")));
+    let cells = &info[3].1.as_array().unwrap()[0].as_map().unwrap()[1].1;
+    let id = cells.as_array().unwrap()[1].as_map().unwrap();
+    assert_eq!(id[3], (Value::from("value"), Value::from(1)));
+    assert_eq!(id[4], (Value::from("name"), Value::from("IDNOD")));
+    assert_eq!(info[4].1.as_array().unwrap().len(), 0);
+
+    // Not a header, and a duplicate
+    let info = match bufdata.card_info([line(2), line(2)], line(1), &text[1..])
+    {
+      Ok(Value::Map(m)) => m,
+      v => panic!("Not a map: {:?}", v),
+    };
+    assert_eq!(info[3].1.as_array().unwrap().len(), 1);
+    assert_eq!(info[4].1.as_array().unwrap().len(), 1);
+
+    let err = bufdata.card_info([line(0), line(0)], line(0), &text);
+    assert!(err.is_err());
+  }
+
+  #[test]
+  fn ges_contents() {
+    use crate::index::{Index, Location};
+    use std::path::PathBuf;
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let lines = [
+      "NODE  /        1              0.             0.5              0.",
+      "NODE  /        2              0.             0.5              0.",
+      "NODE  /        5              0.             0.5              0.",
+      "BOUNC /        0  111111       0       0",
+      "NAME Fixed End",
+      "        NOD        1:4        5        7",
+      "        DELPART        3",
+      "        OGRP 'hausbau'",
+      "        END",
+    ];
+    bufdata.parse_strs(&lines).unwrap();
+    let text: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let line = LineNr::from_usize;
+
+    let start = line(3);
+    let contents = |line: LineNr, index: Option<&Index>| {
+      let info = bufdata.ges_contents(start, &text[3..], line, index);
+      info.unwrap().as_map().unwrap().to_vec()
+    };
+    let selector = |m: &[(Value, Value)], i: usize, key: &str| {
+      let selectors = m[3].1.as_array().unwrap();
+      let selector = selectors[i].as_map().unwrap();
+      selector
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v.clone())
+    };
+
+    let info = contents(line(6), None);
+    assert_eq!(info[0], (Value::from("first"), Value::from(5)));
+    assert_eq!(info[1], (Value::from("last"), Value::from(7)));
+    assert_eq!(info[2], (Value::from("complete"), Value::from(true)));
+    assert_eq!(info[3].1.as_array().unwrap().len(), 3);
+    assert_eq!(selector(&info, 0, "matched"), Some(Value::from(3)));
+    assert_eq!(
+      selector(&info, 0, "missing"),
+      Some(Value::from(vec![Value::from(7)]))
+    );
+    assert_eq!(selector(&info, 1, "entity"), Some(Value::from("Part")));
+    assert_eq!(selector(&info, 1, "deselects"), Some(Value::from(true)));
+    assert_eq!(selector(&info, 2, "entity"), None);
+    assert_eq!(
+      selector(&info, 2, "items"),
+      Some(Value::from(vec![Value::from("'hausbau'")]))
+    );
+
+    // The IDs of the include files count as well
+    let location = Location {
+      file: PathBuf::from("nodes.inc"),
+      line: line(0),
+    };
+    let index = Index::from_parts(
+      vec![
+        (Entity::Node, 3, location.clone()),
+        (Entity::Node, 7, location),
+      ],
+      vec![],
+      vec![],
+    );
+    let info = contents(line(8), Some(&index));
+    assert_eq!(info[0], (Value::from("first"), Value::from(5)));
+    assert_eq!(selector(&info, 0, "matched"), Some(Value::from(5)));
+    assert_eq!(selector(&info, 0, "missing"), Some(Value::Array(vec![])));
+
+    assert!(bufdata
+      .ges_contents(start, &text[3..], line(4), None)
+      .is_err());
+    assert!(bufdata
+      .ges_contents(start, &text[3..], line(9), None)
+      .is_err());
+  }
+
+  #[test]
+  fn offset_include() {
+    use crate::index::Index;
+    use std::{env, fs, process};
+
+    let dir = env::temp_dir().join(format!("nvimpam-offset-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+      dir.join("nodes.inc"),
+      "NODE  /        1              0.             0.5              0.\n\
+       $ comment\n\
+       NODE  /        2              0.             0.5              0.\n",
+    )
+    .unwrap();
+    fs::write(
+      dir.join("shells.inc"),
+      "SHELL /     3130       1       2       3       4       5\n",
+    )
+    .unwrap();
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata
+      .parse_strs(&[
+        "INCLU / nodes.inc",
+        "SHELL /     3129       1       1       2       2       1",
+      ])
+      .unwrap();
+    let index =
+      Index::build(&dir, &["nodes.inc".to_string(), "shells.inc".to_string()]);
+
+    let line = LineNr::from_usize;
+    let offset = |entities: &[&str]| {
+      let entities: Vec<String> =
+        entities.iter().map(|e| e.to_string()).collect();
+      bufdata.offset_include(line(0), &dir, 100, &entities, Some(&index))
+    };
+    let res = offset(&["node"]);
+    let err = offset(&["nose"]).unwrap_err();
+    let not_inclu =
+      bufdata.offset_include(line(1), &dir, 100, &[], Some(&index));
+    let _ = fs::remove_dir_all(&dir);
+
+    let files = res.unwrap().as_map().unwrap()[0].1.clone();
+    let files = files.as_array().unwrap();
+    assert_eq!(files.len(), 3);
+
+    let file = |i: usize| files[i].as_map().unwrap().to_vec();
+    let path = |f: &str| Value::from(dir.join(f).to_string_lossy().as_ref());
+    assert_eq!(file(0)[0].1, path("nodes.inc"));
+    let edits = file(0)[1].1.clone();
+    assert_eq!(edits.as_array().unwrap().len(), 2);
+    assert_eq!(
+      edits.as_array().unwrap()[1],
+      Value::from(vec![
+        Value::from(2),
+        Value::from(
+          "NODE  /      102              0.             0.5              0."
+        ),
+      ])
+    );
+
+    assert_eq!(file(1)[0].1, Value::from(""));
+    assert_eq!(
+      file(1)[1].1,
+      Value::from(vec![Value::from(vec![
+        Value::from(1),
+        Value::from("SHELL /     3129       1     101     102     102     101"),
+      ])])
+    );
+    assert_eq!(file(2)[0].1, path("shells.inc"));
+
+    assert_eq!(err.to_string(), "Unknown entity 'nose'!");
+    assert_eq!(
+      not_inclu.unwrap_err().to_string(),
+      "Line 2 is not an INCLU line!"
+    );
+  }
+
+  #[test]
+  fn grouped_level2_folds() {
+    let node =
+      "NODE  /        1              0.             0.5              0.";
+    let shell = "SHELL /     3129       1       1    2967    2971    2970";
+    let membr = "MEMBR /     3130       1       1    2967    2971    2970";
+    let lines = [node, node, shell, membr, membr, node, shell];
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+    assert!(bufdata.folds_level2_to_vec().is_empty());
+
+    let group = FoldGroup::from_strs(&["Surface elements", "SHELL", "MEMBR"]);
+    bufdata
+      .set_fold_groups(FoldGroups {
+        groups: vec![group.unwrap()],
+      })
+      .unwrap();
+    assert_eq!(bufdata.folds_level2_to_vec(), vec![(2, 4, Shell)]);
+    assert_eq!(
+      bufdata
+        .folds_level2
+        .get(&[LineNr::from_usize(2), LineNr::from_usize(4)]),
+      Some(&(Shell, " 2 Surface elements ".to_string()))
+    );
+
+    // Replacing the NODE between the groups joins them
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(5),
+        LineNr::from_usize(6),
+        vec![membr.to_string()],
+      )
+      .unwrap();
+    assert_eq!(bufdata.folds_level2_to_vec(), vec![(2, 6, Shell)]);
+  }
+
+  #[test]
+  fn level2_folds_after_update() {
+    let node =
+      "NODE  /        1              0.             0.5              0.";
+    let shell = "SHELL /     3129       1       1    2967    2971    2970";
+    let part = "PART  /        1   SHELL       2       0       0       0";
+    let text: Vec<String> = [
+      part,
+      "NAME Part 1",
+      "END_PART",
+      part,
+      "NAME Part 2",
+      "END_PART",
+      node,
+      node,
+      shell,
+      shell,
+      part,
+      "NAME Part 3",
+      "END_PART",
+      part,
+      "NAME Part 4",
+      "END_PART",
+      node,
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect();
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_vec(text).unwrap();
+    assert_eq!(bufdata.folds_level2_to_vec().len(), 2);
+
+    let updates: Vec<(usize, usize, Vec<&str>)> = vec![
+      // Change the line right after the first group
+      (6, 7, vec![node]),
+      // And the one right before the second group
+      (9, 10, vec![shell]),
+      // Add a line between the groups, moving the second one
+      (8, 9, vec![shell, shell]),
+      // Change a line inside of the first group
+      (1, 2, vec!["NAME Part 0"]),
+      // Add a line at the start
+      (0, 0, vec![node]),
+      // Remove the cards between the groups, they're joined now
+      (7, 11, vec![]),
+      // Split the group again
+      (4, 4, vec![shell]),
+      // Add lines at the end
+      (18, 18, vec![part, "NAME Part 5", "END_PART"]),
+      // Delete everything but the first and last line
+      (1, 20, vec![]),
+    ];
+
+    for (first, last, new) in updates {
+      let _ = bufdata
+        .update(
+          LineNr::from_usize(first),
+          LineNr::from_usize(last),
+          new.iter().map(|l| l.to_string()).collect(),
+        )
+        .unwrap();
+
+      let mut expected = Folds::new();
+      expected
+        .recreate_level2(&bufdata.folds, &bufdata.fold_groups)
+        .unwrap();
+      assert_eq!(expected.to_vec(), bufdata.folds_level2_to_vec());
+    }
+  }
+
+  #[test]
+  fn container_folds_after_update() {
+    let frame = "FRAME /        1       0       1";
+    let lines = ["LCASE /        1      1.", "NAME Loads", frame, frame, frame];
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+    assert_eq!(bufdata.folds_level2_to_vec(), vec![(2, 4, Frame)]);
+
+    // Closing the load case far from its start
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(5),
+        LineNr::from_usize(5),
+        vec!["END_LCASE".to_string()],
+      )
+      .unwrap();
+    assert_eq!(bufdata.folds_level2_to_vec(), vec![(0, 5, Lcase)]);
+
+    let _ = bufdata
+      .update(LineNr::from_usize(5), LineNr::from_usize(6), vec![])
+      .unwrap();
+    assert_eq!(bufdata.folds_level2_to_vec(), vec![(2, 4, Frame)]);
+
+    let node = "NODE  /        1              0.             0.5";
+    let updates: Vec<(usize, usize, Vec<&str>)> = vec![
+      // Close it again
+      (5, 5, vec!["END_LCASE", node, node]),
+      // Change the cards inside of the load case
+      (3, 4, vec![node]),
+      (2, 2, vec![frame, frame]),
+      // And the ones after it
+      (7, 8, vec![frame]),
+      (8, 8, vec![node]),
+      // Start another one after it
+      (9, 9, vec!["LCASE /        2      1."]),
+      // Remove the first one
+      (0, 2, vec![]),
+    ];
+
+    for (first, last, new) in updates {
+      let _ = bufdata
+        .update(
+          LineNr::from_usize(first),
+          LineNr::from_usize(last),
+          new.iter().map(|l| l.to_string()).collect(),
+        )
+        .unwrap();
+
+      let mut expected = Folds::new();
+      expected
+        .recreate_level2(&bufdata.folds, &bufdata.fold_groups)
+        .unwrap();
+      assert_eq!(expected.to_vec(), bufdata.folds_level2_to_vec());
+    }
   }
 }
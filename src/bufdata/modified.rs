@@ -0,0 +1,94 @@
+//! Tracking the lines changed since the buffer was last written, so only the
+//! cards the user edited are re-aligned when saving, see
+//! [`align_cells`](crate::bufdata::BufData::align_cells).
+use std::cmp;
+
+use crate::linenr::LineNr;
+
+/// The changed regions of the buffer. They're end-exclusive, don't overlap or
+/// touch, and are ordered by their first line.
+#[derive(Debug, Default)]
+pub struct ModifiedLines(Vec<[LineNr; 2]>);
+
+impl ModifiedLines {
+  /// Forget all changes, e.g. after the buffer was written
+  pub fn clear(&mut self) {
+    self.0.clear();
+  }
+
+  /// The changed regions
+  pub fn regions(&self) -> &[[LineNr; 2]] {
+    &self.0
+  }
+
+  /// If any of the lines `first..=last` changed
+  pub fn touches(&self, first: LineNr, last: LineNr) -> bool {
+    self.0.iter().any(|r| r[0] <= last && first < r[1])
+  }
+
+  /// Record an update of the buffer that replaced the lines
+  /// `firstline..lastline`, adding `added` lines. Regions after the update
+  /// are moved, the ones touching it are merged with it. If lines were only
+  /// deleted, the line after them counts as changed.
+  pub fn update(&mut self, firstline: LineNr, lastline: LineNr, added: isize) {
+    let newlast = cmp::max(lastline.saturating_add(added), firstline + 1);
+    let mut merged = [firstline, newlast];
+    let mut regions = Vec::with_capacity(self.0.len() + 1);
+
+    for &[first, last] in &self.0 {
+      if last < firstline {
+        regions.push([first, last]);
+      } else if first > lastline {
+        regions.push([first.saturating_add(added), last.saturating_add(added)]);
+      } else {
+        merged[0] = cmp::min(merged[0], first);
+        if last > lastline {
+          merged[1] = cmp::max(merged[1], last.saturating_add(added));
+        }
+      }
+    }
+
+    regions.push(merged);
+    regions.sort_by_key(|r| r[0]);
+    self.0 = regions;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{bufdata::modified::ModifiedLines, linenr::LineNr};
+
+  fn regions(m: &ModifiedLines) -> Vec<[usize; 2]> {
+    m.regions()
+      .iter()
+      .map(|r| [r[0].into(), r[1].into()])
+      .collect()
+  }
+
+  fn update(m: &mut ModifiedLines, first: usize, last: usize, added: isize) {
+    m.update(LineNr::from_usize(first), LineNr::from_usize(last), added);
+  }
+
+  #[test]
+  fn modified_regions() {
+    let mut m = ModifiedLines::default();
+
+    update(&mut m, 10, 11, 0);
+    update(&mut m, 20, 20, 2);
+    assert_eq!(regions(&m), vec![[10, 11], [20, 22]]);
+
+    // Deleting lines before moves the later regions
+    update(&mut m, 2, 4, -2);
+    assert_eq!(regions(&m), vec![[2, 3], [8, 9], [18, 20]]);
+
+    // Touching regions are merged
+    update(&mut m, 9, 18, 0);
+    assert_eq!(regions(&m), vec![[2, 3], [8, 20]]);
+
+    assert!(m.touches(LineNr::from_usize(0), LineNr::from_usize(2)));
+    assert!(!m.touches(LineNr::from_usize(3), LineNr::from_usize(7)));
+
+    m.clear();
+    assert!(regions(&m).is_empty());
+  }
+}
@@ -0,0 +1,66 @@
+//! A quick look at a buffer before parsing it as a whole. If none of its lines
+//! starts a card, it's no deck at all, e.g. a file of comments only or some
+//! log file. The parser is skipped for those, so a huge file like that is not
+//! walked line by line to find no folds and highlights. Looking for a keyword
+//! line is a lot cheaper than parsing, and stops at the first one, so decks
+//! are hardly slowed down. See
+//! [`BufData::parse_slice`](crate::bufdata::BufData::parse_slice).
+use crate::card::keyword::Keyword;
+
+/// If none of `lines` starts a card. Stops at the first keyword line. Buffers
+/// of blank lines only are no reason to complain, so this is false for them.
+pub fn no_cards<B, I>(lines: I) -> bool
+where
+  B: AsRef<[u8]>,
+  I: IntoIterator<Item = B>,
+{
+  let mut blank = true;
+
+  for line in lines {
+    let line = line.as_ref();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    if Keyword::parse(line).is_some() {
+      return false;
+    }
+    blank &= line.iter().all(u8::is_ascii_whitespace);
+  }
+
+  !blank
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::bufdata::prescan::no_cards;
+
+  const NODE: &str = "NODE  /        1              0.             0.5";
+
+  #[test]
+  fn find_no_cards() {
+    assert!(no_cards(&["$ comment", "# comment", "garbage"]));
+    assert!(!no_cards(&[
+      "$ comment",
+      "NODE  /        1              0.             0.5              0.\r",
+    ]));
+
+    // Nothing to complain about
+    assert!(!no_cards(Vec::<&str>::new()));
+    assert!(!no_cards(&["", "  "]));
+    assert!(!no_cards(b"".split(|&b| b == b'\n')));
+
+    // A deck with a long header
+    let mut lines = vec!["$ comment"; 50_000];
+    lines.push(NODE);
+    assert!(!no_cards(&lines));
+    assert!(!no_cards(lines.join("\n").split('\n')));
+
+    // Cards anywhere are found
+    let _ = lines.pop();
+    lines[12_345] = NODE;
+    assert!(!no_cards(&lines));
+    assert!(!no_cards(lines.join("\n").split('\n')));
+
+    lines[12_345] = "$ comment";
+    assert!(no_cards(&lines));
+  }
+}
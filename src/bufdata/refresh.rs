@@ -0,0 +1,195 @@
+//! Finding the lines that changed when neovim sends the whole buffer again,
+//! e.g. after `:e!`. The lines are split into regions, each starting at a
+//! keyword line, and the regions at the start and the end of the buffer whose
+//! contents hash the same as before are kept. Only the lines in between need
+//! to be parsed anew, which is usually a tiny part of the buffer.
+use std::ops::Range;
+
+use crate::{
+  bufdata::foldstate::{fnv_feed, FNV_OFFSET},
+  linenr::LineNr,
+};
+
+/// A line as far as the regions are concerned: Its number, its text, and if
+/// it starts with a keyword. Comments are not part of the lines, see
+/// [`Lines`](crate::lines::Lines).
+pub type RegionLine<'a> = (LineNr, &'a [u8], bool);
+
+/// What happened when refreshing the buffer, see
+/// [`BufData::refresh`](crate::bufdata::BufData::refresh)
+#[derive(Debug, PartialEq)]
+pub enum Refresh {
+  /// The contents did not change, nothing was parsed
+  Unchanged,
+  /// The lines `firstline..lastline` were replaced, adding `added` lines. The
+  /// highlights of the indices `range` are new.
+  Updated {
+    range: Range<usize>,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+  },
+  /// The whole buffer was parsed anew
+  Parsed,
+}
+
+/// The first line of a region, and the hash of its contents
+#[derive(Debug, PartialEq)]
+struct Region {
+  start: LineNr,
+  hash: u64,
+}
+
+/// Split `lines` into regions starting at keyword lines, the first one
+/// starting at the first line. `end` is the number of the line after the last
+/// one. The hash of a region covers the texts and the numbers of its lines
+/// relative to its start, as well as the number of lines up to the next
+/// region, so comments inside of it count as well.
+fn regions(lines: &[RegionLine], end: LineNr) -> Vec<Region> {
+  let mut starts: Vec<usize> = lines
+    .iter()
+    .enumerate()
+    .filter(|(i, l)| *i == 0 || l.2)
+    .map(|(i, _)| i)
+    .collect();
+  starts.push(lines.len());
+
+  starts
+    .windows(2)
+    .map(|w| {
+      let start = lines[w[0]].0;
+      let next = lines.get(w[1]).map_or(end, |l| l.0);
+      let offset = |l: LineNr| (usize::from(l) - usize::from(start)) as u64;
+
+      let mut hash = FNV_OFFSET;
+      for &(number, text, _) in &lines[w[0]..w[1]] {
+        fnv_feed(&mut hash, &offset(number).to_le_bytes());
+        fnv_feed(&mut hash, text);
+      }
+      fnv_feed(&mut hash, &offset(next).to_le_bytes());
+
+      Region { start, hash }
+    })
+    .collect()
+}
+
+/// The number of the line after the last one of `lines`
+fn end(lines: &[RegionLine]) -> LineNr {
+  lines.last().map_or(LineNr::from_usize(0), |l| l.0 + 1)
+}
+
+/// Compare the regions of the `old` and the `new` lines. Returns `None` if
+/// they're the same. Otherwise, returns the first changed line, and the
+/// line after the last changed one in the `old` and the `new` lines.
+pub fn changed_lines(
+  old: &[RegionLine],
+  new: &[RegionLine],
+) -> Option<[LineNr; 3]> {
+  let (old_end, new_end) = (end(old), end(new));
+  let old_regions = regions(old, old_end);
+  let new_regions = regions(new, new_end);
+
+  let prefix = old_regions
+    .iter()
+    .zip(new_regions.iter())
+    .take_while(|(o, n)| o == n)
+    .count();
+
+  if prefix == old_regions.len() && prefix == new_regions.len() {
+    return None;
+  }
+
+  let max_suffix = old_regions.len().min(new_regions.len()) - prefix;
+  let suffix = old_regions
+    .iter()
+    .rev()
+    .zip(new_regions.iter().rev())
+    .take(max_suffix)
+    .take_while(|(o, n)| o.hash == n.hash)
+    .count();
+
+  let first = match prefix {
+    0 => LineNr::from_usize(0),
+    _ => old_regions.get(prefix).map_or(old_end, |r| r.start),
+  };
+  let last = |regions: &[Region], end: LineNr| match suffix {
+    0 => end,
+    _ => regions[regions.len() - suffix].start,
+  };
+
+  Some([
+    first,
+    last(&old_regions, old_end),
+    last(&new_regions, new_end),
+  ])
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::refresh::{changed_lines, RegionLine},
+    linenr::LineNr,
+  };
+
+  fn lines<'a>(v: &[(usize, &'a str, bool)]) -> Vec<RegionLine<'a>> {
+    v.iter()
+      .map(|&(n, t, k)| (LineNr::from_usize(n), t.as_bytes(), k))
+      .collect()
+  }
+
+  fn changed(
+    old: &[(usize, &str, bool)],
+    new: &[(usize, &str, bool)],
+  ) -> Option<[usize; 3]> {
+    changed_lines(&lines(old), &lines(new))
+      .map(|r| [r[0].into(), r[1].into(), r[2].into()])
+  }
+
+  const OLD: &[(usize, &str, bool)] = &[
+    (0, "NODE  /", true),
+    (1, "NODE  /", true),
+    (2, "SHELL /", true),
+    (3, "  data", false),
+    (5, "SHELL /", true),
+    (6, "BEAM  /", true),
+  ];
+
+  #[test]
+  fn refresh_unchanged() {
+    assert_eq!(changed(OLD, OLD), None);
+  }
+
+  #[test]
+  fn refresh_changed_line() {
+    let mut new = OLD.to_vec();
+    new[3] = (3, "  changed", false);
+    assert_eq!(changed(OLD, &new), Some([2, 5, 5]));
+  }
+
+  #[test]
+  fn refresh_added_comment() {
+    // A comment line was added before line 3
+    let new = &[
+      (0, "NODE  /", true),
+      (1, "NODE  /", true),
+      (2, "SHELL /", true),
+      (4, "  data", false),
+      (6, "SHELL /", true),
+      (7, "BEAM  /", true),
+    ];
+    assert_eq!(changed(OLD, new), Some([2, 5, 6]));
+  }
+
+  #[test]
+  fn refresh_deleted_cards() {
+    let new = &[(0, "NODE  /", true), (1, "BEAM  /", true)];
+    assert_eq!(changed(OLD, new), Some([1, 6, 1]));
+  }
+
+  #[test]
+  fn refresh_changed_first_line() {
+    let mut new = OLD.to_vec();
+    new[0] = (0, "NODE  / 1", true);
+    assert_eq!(changed(OLD, &new), Some([0, 1, 1]));
+  }
+}
@@ -0,0 +1,190 @@
+//! Snapshots of the folds and highlights computed for the example decks in
+//! `files/`. The test `example_decks` compares them to the ones saved in
+//! `files/snapshots/`, so changes to the results of the parser don't go
+//! unnoticed when refactoring it.
+//!
+//! If the results change on purpose, run the tests with the environment
+//! variable `NVIMPAM_UPDATE_SNAPSHOTS` set to write the snapshots anew, and
+//! review their diff before committing them.
+use std::{env, fmt::Write, fs, path::Path};
+
+use crate::bufdata::{highlights::HighlightGroup as Hl, BufData};
+
+/// The environment variable to set to update the snapshots
+pub const UPDATE_VAR: &str = "NVIMPAM_UPDATE_SNAPSHOTS";
+
+/// The name of a highlight group in a snapshot
+fn group_name(hl: Hl) -> &'static str {
+  match hl {
+    Hl::CellEven => "even",
+    Hl::CellOdd => "odd",
+    Hl::ErrorCellEven => "err_even",
+    Hl::ErrorCellOdd => "err_odd",
+    Hl::Keyword => "kw",
+    Hl::Title => "title",
+    Hl::Ignored => "ignored",
+    Hl::External(g) => g,
+  }
+}
+
+/// Render the folds and highlights of `bufdata` as text. Each fold is a line
+/// of its range, keyword and quoted text. The highlights are given by line, as the
+/// groups along with their column ranges. Consecutive lines with the same
+/// highlights are given as a range of lines, so the blocks of cards of the
+/// same kind don't take up a line each. All line numbers are zero-indexed.
+pub fn render(bufdata: &BufData) -> String {
+  let mut out = String::new();
+
+  for (title, folds) in &[
+    ("folds", &bufdata.folds),
+    ("level 2 folds", &bufdata.folds_level2),
+  ] {
+    let _ = writeln!(out, "{}", title);
+    for (r, (kw, text)) in folds.iter() {
+      let _ = writeln!(out, "{}-{} {:?}: {:?}", r[0], r[1], kw, text);
+    }
+    out.push('\n');
+  }
+
+  out.push_str("highlights\n");
+  let mut lines: Vec<(usize, String)> = vec![];
+  for ((line, start, end), hl) in bufdata.highlights.iter() {
+    let line = usize::from(line);
+    let span = format!("{} {}-{}", group_name(hl), start, end);
+
+    match lines.last_mut() {
+      Some((l, spans)) if *l == line => {
+        spans.push_str(", ");
+        spans.push_str(&span);
+      }
+      _ => lines.push((line, span)),
+    }
+  }
+
+  let mut i = 0;
+  while i < lines.len() {
+    let (first, ref spans) = lines[i];
+    let mut last = first;
+    while i + 1 < lines.len()
+      && lines[i + 1].0 == last + 1
+      && lines[i + 1].1 == *spans
+    {
+      i += 1;
+      last += 1;
+    }
+
+    if first == last {
+      let _ = writeln!(out, "{}: {}", first, spans);
+    } else {
+      let _ = writeln!(out, "{}-{}: {}", first, last, spans);
+    }
+    i += 1;
+  }
+
+  out
+}
+
+/// Compare `actual` to the snapshot saved at `path`, or save it there if the
+/// environment variable [`UPDATE_VAR`](self::UPDATE_VAR) is set. Returns a
+/// message describing the first difference, if any.
+pub fn check(path: &Path, actual: &str) -> Result<(), String> {
+  if env::var_os(UPDATE_VAR).is_some() {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    return fs::write(path, actual).map_err(|e| e.to_string());
+  }
+
+  let expected = fs::read_to_string(path).map_err(|e| {
+    format!(
+      "Could not read the snapshot '{}': {}. Set {} to write it.",
+      path.display(),
+      e,
+      UPDATE_VAR
+    )
+  })?;
+
+  let mut expected_lines = expected.lines();
+  let mut actual_lines = actual.lines();
+  for number in 1.. {
+    match (expected_lines.next(), actual_lines.next()) {
+      (None, None) => break,
+      (e, a) if e == a => {}
+      (e, a) => {
+        return Err(format!(
+          "The snapshot '{}' differs in line {}:\n  expected: {}\n  actual:   \
+           {}\nSet {} to update it if that's intended.",
+          path.display(),
+          number,
+          e.unwrap_or("<end of file>"),
+          a.unwrap_or("<end of file>"),
+          UPDATE_VAR
+        ))
+      }
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, path::Path};
+
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::bufdata::{
+    snapshot::{check, render},
+    BufData,
+  };
+
+  #[test]
+  fn render_snapshot() {
+    let lines = [
+      "NODE  /        1              0.             0.5              0.",
+      "NODE  /        2              0.             0.5              0.",
+      "$ comment",
+      "NODE  /        3              0.             0.5              0.",
+    ];
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+
+    let snapshot = render(&bufdata);
+    let mut parts = snapshot.split("\n\n");
+
+    assert!(parts.next().unwrap().starts_with("folds\n0-3 Node: \""));
+    assert_eq!(parts.next(), Some("level 2 folds"));
+    let highlights: Vec<_> = parts.next().unwrap().lines().collect();
+    assert_eq!(highlights.len(), 3);
+    assert!(highlights[1].starts_with("0-1: kw 0-8, even 8-16, "));
+    assert!(highlights[2].starts_with("3: kw 0-8, "));
+  }
+
+  #[test]
+  fn example_decks() {
+    let mut decks: Vec<_> = fs::read_dir("files")
+      .unwrap()
+      .map(|e| e.unwrap().path())
+      .filter(|p| p.extension().map_or(false, |e| e == "pc"))
+      .collect();
+    decks.sort();
+    assert!(!decks.is_empty());
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let errors: Vec<String> = decks
+      .iter()
+      .filter_map(|deck| {
+        let content = fs::read(deck).unwrap();
+        let mut bufdata = BufData::new(&buf);
+        bufdata.parse_slice(&content).unwrap();
+
+        let name = deck.file_name().unwrap().to_string_lossy();
+        let path = Path::new("files/snapshots").join(format!("{}.snap", name));
+        check(&path, &render(&bufdata)).err()
+      })
+      .collect();
+
+    assert!(errors.is_empty(), "{}", errors.join("\n"));
+  }
+}
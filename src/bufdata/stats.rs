@@ -0,0 +1,267 @@
+//! Statistics about the data nvimpam holds for a buffer, so users with huge
+//! decks can see what it costs them.
+use std::{collections::VecDeque, fmt, time::Duration};
+
+use neovim_lib::Value;
+
+use crate::card::keyword::Keyword;
+
+/// The durations of the last full parse and the last update of a buffer, and
+/// the number of folds the last update recreated
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+  pub parse: Option<Duration>,
+  pub update: Option<Duration>,
+  pub update_folds: usize,
+}
+
+/// The sizes of the datastructures of a
+/// [`BufData`](crate::bufdata::BufData). `line_bytes` is the length of the
+/// text of all lines, while `heap_bytes` is the memory allocated by the lines
+/// themselves, i.e. not counting the lines of a file read from disk, which are
+/// borrowed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+  pub lines: usize,
+  pub keywords: usize,
+  pub line_bytes: usize,
+  pub heap_bytes: usize,
+  pub folds: usize,
+  pub folds_level2: usize,
+  pub folds_ges: usize,
+  pub custom_folds: usize,
+  pub highlights: usize,
+}
+
+/// Convert a duration to microseconds for sending to neovim
+fn micros(d: Duration) -> u64 {
+  d.as_secs() * 1_000_000 + u64::from(d.subsec_micros())
+}
+
+impl Stats {
+  /// Pack up the stats and the timings into a `Value` suitable to send to
+  /// neovim. Durations are given in microseconds, and left out if there was
+  /// no parse or update yet.
+  pub fn to_value(&self, timings: Timings) -> Value {
+    let mut map = vec![
+      (Value::from("lines"), Value::from(self.lines)),
+      (Value::from("keywords"), Value::from(self.keywords)),
+      (Value::from("line_bytes"), Value::from(self.line_bytes)),
+      (Value::from("heap_bytes"), Value::from(self.heap_bytes)),
+      (Value::from("folds"), Value::from(self.folds)),
+      (Value::from("folds_level2"), Value::from(self.folds_level2)),
+      (Value::from("folds_ges"), Value::from(self.folds_ges)),
+      (Value::from("custom_folds"), Value::from(self.custom_folds)),
+      (Value::from("highlights"), Value::from(self.highlights)),
+    ];
+
+    if let Some(d) = timings.parse {
+      map.push((Value::from("last_parse_us"), Value::from(micros(d))));
+    }
+    if let Some(d) = timings.update {
+      map.push((Value::from("last_update_us"), Value::from(micros(d))));
+    }
+
+    Value::from(map)
+  }
+}
+
+/// The number of cards of one type in a buffer, and the number of lines they
+/// span, including comments within the cards
+#[derive(Debug, PartialEq)]
+pub struct KeywordCount {
+  pub keyword: Keyword,
+  pub cards: usize,
+  pub lines: usize,
+}
+
+impl<'a> From<&'a KeywordCount> for Value {
+  fn from(c: &'a KeywordCount) -> Self {
+    let keyword = format!("{:?}", c.keyword);
+
+    Value::from(vec![
+      (Value::from("keyword"), Value::from(keyword)),
+      (Value::from("cards"), Value::from(c.cards)),
+      (Value::from("lines"), Value::from(c.lines)),
+    ])
+  }
+}
+
+/// The number of [`EventTiming`](crate::bufdata::stats::EventTiming)s kept by
+/// a [`TimingLog`](crate::bufdata::stats::TimingLog)
+pub const TIMING_RECORDS: usize = 100;
+
+/// How long the [`event_loop`](crate::event::Event::event_loop) took to
+/// handle an event, how many lines of the buffer the event touched and how many
+/// folds it recreated
+#[derive(Debug, PartialEq, Clone)]
+pub struct EventTiming {
+  pub event: &'static str,
+  pub duration: Duration,
+  pub lines: usize,
+  pub folds: usize,
+}
+
+impl<'a> From<&'a EventTiming> for Value {
+  fn from(t: &'a EventTiming) -> Self {
+    Value::from(vec![
+      (Value::from("event"), Value::from(t.event)),
+      (Value::from("duration_us"), Value::from(micros(t.duration))),
+      (Value::from("lines"), Value::from(t.lines)),
+      (Value::from("folds"), Value::from(t.folds)),
+    ])
+  }
+}
+
+impl fmt::Display for EventTiming {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "event={} duration_us={} lines={} folds={}",
+      self.event,
+      micros(self.duration),
+      self.lines,
+      self.folds
+    )
+  }
+}
+
+/// The timings of the last
+/// [`TIMING_RECORDS`](crate::bufdata::stats::TIMING_RECORDS) events, oldest
+/// first
+#[derive(Debug, Default)]
+pub struct TimingLog {
+  records: VecDeque<EventTiming>,
+}
+
+impl TimingLog {
+  /// Add the timing of an event, dropping the oldest one if the log is full
+  pub fn push(&mut self, timing: EventTiming) {
+    if self.records.len() == TIMING_RECORDS {
+      let _ = self.records.pop_front();
+    }
+    self.records.push_back(timing);
+  }
+
+  /// The timings of the last `count` events, oldest first
+  pub fn last(&self, count: usize) -> impl Iterator<Item = &EventTiming> {
+    let skip = self.records.len().saturating_sub(count);
+    self.records.iter().skip(skip)
+  }
+
+  /// The timings of the last `count` events as an array of maps, see
+  /// [`last`](crate::bufdata::stats::TimingLog::last)
+  pub fn to_value(&self, count: usize) -> Value {
+    Value::from(self.last(count).map(Value::from).collect::<Vec<_>>())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use std::time::Duration;
+
+  use crate::{
+    bufdata::{
+      stats::{EventTiming, KeywordCount, TimingLog, TIMING_RECORDS},
+      BufData,
+    },
+    card::keyword::Keyword,
+    linenr::LineNr,
+  };
+
+  const LINES: [&str; 5] = [
+    "NODE  /        1              0.             0.5              0.",
+    "#Comment",
+    "NODE  /        2              0.             0.5              0.",
+    "SHELL /     3129       1       1    2967    2971    2970",
+    "SHELL /     3130       1       1    2967    2971    2970",
+  ];
+
+  #[test]
+  fn buffer_stats() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let stats = bufdata.stats();
+    assert_eq!(stats.lines, 4);
+    assert_eq!(stats.keywords, 4);
+    assert_eq!(stats.line_bytes, 64 + 64 + 56 + 56);
+    assert_eq!(stats.folds, 2);
+    assert_eq!(stats.folds_ges, 0);
+    assert!(bufdata.timings().parse.is_some());
+    assert!(bufdata.timings().update.is_none());
+
+    let heap = stats.heap_bytes;
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(3),
+        LineNr::from_usize(4),
+        vec![LINES[3].to_string()],
+      )
+      .unwrap();
+
+    let stats = bufdata.stats();
+    assert_eq!(stats.lines, 4);
+    assert!(stats.heap_bytes >= heap + 56);
+    assert!(bufdata.timings().update.is_some());
+
+    match stats.to_value(bufdata.timings()) {
+      Value::Map(m) => assert_eq!(m.len(), 11),
+      v => panic!("Not a map: {:?}", v),
+    }
+  }
+
+  #[test]
+  fn keyword_summary() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    assert_eq!(
+      bufdata.keyword_summary(),
+      vec![
+        KeywordCount {
+          keyword: Keyword::Node,
+          cards: 2,
+          lines: 3,
+        },
+        KeywordCount {
+          keyword: Keyword::Shell,
+          cards: 2,
+          lines: 2,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn timing_log() {
+    let timing = |lines| EventTiming {
+      event: "LinesEvent",
+      duration: Duration::from_micros(1500),
+      lines,
+      folds: 1,
+    };
+    let mut log = TimingLog::default();
+
+    for i in 0..TIMING_RECORDS + 5 {
+      log.push(timing(i));
+    }
+
+    let last: Vec<_> = log.last(2).map(|t| t.lines).collect();
+    assert_eq!(last, vec![TIMING_RECORDS + 3, TIMING_RECORDS + 4]);
+    assert_eq!(log.last(1000).count(), TIMING_RECORDS);
+    assert_eq!(
+      timing(3).to_string(),
+      "event=LinesEvent duration_us=1500 lines=3 folds=1"
+    );
+
+    match log.to_value(3) {
+      Value::Array(a) => assert_eq!(a.len(), 3),
+      v => panic!("Not an array: {:?}", v),
+    }
+  }
+}
@@ -0,0 +1,193 @@
+//! Tracking of the regions of the buffer that have been highlighted because
+//! they were viewed. For huge buffers, keeping the highlights of the whole
+//! buffer in neovim costs a lot of memory, so the highlights of regions that
+//! have not been viewed for a while can be cleared. They are sent again when
+//! the region is viewed the next time.
+use std::{
+  cmp,
+  time::{Duration, Instant},
+};
+
+use crate::linenr::LineNr;
+
+/// The regions highlighted in neovim, along with the time they were last
+/// viewed. The regions are end-exclusive, don't overlap, and are ordered by
+/// their first line. Without a maximum age, nothing is tracked.
+#[derive(Debug, Default)]
+pub struct ViewedRegions {
+  max_age: Option<Duration>,
+  regions: Vec<([LineNr; 2], Instant)>,
+}
+
+impl ViewedRegions {
+  /// Set the time after which the highlights of a region not viewed in the
+  /// meantime are cleared. `None` stops tracking the regions.
+  pub fn set_max_age(&mut self, max_age: Option<Duration>) {
+    self.max_age = max_age;
+    if max_age.is_none() {
+      self.regions.clear();
+    }
+  }
+
+  /// If the regions are tracked, i.e. a maximum age is set
+  pub fn enabled(&self) -> bool {
+    self.max_age.is_some()
+  }
+
+  /// Forget all regions, e.g. because the highlights have been cleared
+  pub fn clear(&mut self) {
+    self.regions.clear();
+  }
+
+  /// The regions that are currently tracked
+  pub fn regions(&self) -> impl Iterator<Item = [LineNr; 2]> + '_ {
+    self.regions.iter().map(|(r, _)| *r)
+  }
+
+  /// Record that the lines `firstline..lastline` have been highlighted at
+  /// `now`. Parts of other regions overlapping them are replaced.
+  pub fn view(&mut self, firstline: LineNr, lastline: LineNr, now: Instant) {
+    if !self.enabled() || lastline <= firstline {
+      return;
+    }
+
+    let mut regions = Vec::with_capacity(self.regions.len() + 2);
+
+    for &([first, last], time) in &self.regions {
+      if last <= firstline || first >= lastline {
+        regions.push(([first, last], time));
+        continue;
+      }
+      if first < firstline {
+        regions.push(([first, firstline], time));
+      }
+      if last > lastline {
+        regions.push(([lastline, last], time));
+      }
+    }
+
+    regions.push(([firstline, lastline], now));
+    regions.sort_by_key(|(r, _)| r[0]);
+    self.regions = regions;
+  }
+
+  /// Move the regions after an update of the buffer that replaced the lines
+  /// `firstline..lastline`, adding `added` lines. Regions inside of deleted
+  /// lines shrink accordingly, and are dropped if empty.
+  pub fn shift(&mut self, firstline: LineNr, lastline: LineNr, added: isize) {
+    if added == 0 {
+      return;
+    }
+
+    let newlast = lastline.saturating_add(added);
+    let shift = |l: LineNr| {
+      if l >= lastline {
+        l.saturating_add(added)
+      } else if l > firstline {
+        cmp::min(l, newlast)
+      } else {
+        l
+      }
+    };
+
+    for (region, _) in &mut self.regions {
+      *region = [shift(region[0]), shift(region[1])];
+    }
+    self.regions.retain(|(r, _)| r[0] < r[1]);
+  }
+
+  /// Remove the regions not viewed within the maximum age before `now`, and
+  /// return them
+  pub fn expire(&mut self, now: Instant) -> Vec<[LineNr; 2]> {
+    let max_age = match self.max_age {
+      Some(a) => a,
+      None => return vec![],
+    };
+
+    let mut expired = vec![];
+    self.regions.retain(|&(r, time)| {
+      let keep = now.duration_since(time) < max_age;
+      if !keep {
+        expired.push(r);
+      }
+      keep
+    });
+
+    expired
+  }
+
+  /// The time after `now` when the next region expires, or `None` if there
+  /// are no regions
+  pub fn next_expiry(&self, now: Instant) -> Option<Duration> {
+    let max_age = self.max_age?;
+    let oldest = self.regions.iter().map(|(_, t)| *t).min()?;
+
+    Some((oldest + max_age).saturating_duration_since(now))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, Instant};
+
+  use crate::{bufdata::viewed::ViewedRegions, linenr::LineNr};
+
+  fn regions(v: &ViewedRegions) -> Vec<[usize; 2]> {
+    v.regions()
+      .map(|[f, l]| [usize::from(f), usize::from(l)])
+      .collect()
+  }
+
+  fn view(v: &mut ViewedRegions, first: usize, last: usize, now: Instant) {
+    v.view(LineNr::from_usize(first), LineNr::from_usize(last), now)
+  }
+
+  #[test]
+  fn viewed_regions_expire() {
+    let start = Instant::now();
+    let mut v = ViewedRegions::default();
+
+    view(&mut v, 0, 10, start);
+    assert!(regions(&v).is_empty());
+
+    v.set_max_age(Some(Duration::from_millis(100)));
+    view(&mut v, 0, 10, start);
+    view(&mut v, 30, 40, start + Duration::from_millis(50));
+    view(&mut v, 5, 35, start + Duration::from_millis(60));
+    assert_eq!(regions(&v), vec![[0, 5], [5, 35], [35, 40]]);
+    assert_eq!(
+      v.next_expiry(start + Duration::from_millis(30)),
+      Some(Duration::from_millis(70))
+    );
+
+    let expired = v.expire(start + Duration::from_millis(120));
+    assert_eq!(
+      expired,
+      vec![[LineNr::from_usize(0), LineNr::from_usize(5)]]
+    );
+    assert_eq!(regions(&v), vec![[5, 35], [35, 40]]);
+
+    let expired = v.expire(start + Duration::from_millis(160));
+    assert_eq!(expired.len(), 2);
+    assert!(v.next_expiry(start).is_none());
+  }
+
+  #[test]
+  fn viewed_regions_shift() {
+    let now = Instant::now();
+    let mut v = ViewedRegions::default();
+    v.set_max_age(Some(Duration::from_millis(100)));
+
+    view(&mut v, 0, 10, now);
+    view(&mut v, 12, 14, now);
+    view(&mut v, 20, 30, now);
+
+    // Delete the lines 8..16
+    v.shift(LineNr::from_usize(8), LineNr::from_usize(16), -8);
+    assert_eq!(regions(&v), vec![[0, 8], [12, 22]]);
+
+    // Insert 5 lines before line 3
+    v.shift(LineNr::from_usize(3), LineNr::from_usize(3), 5);
+    assert_eq!(regions(&v), vec![[0, 13], [17, 27]]);
+  }
+}
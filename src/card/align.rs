@@ -0,0 +1,202 @@
+//! Checking that the contents of a line are inside of their cells. The solver
+//! reads the cells by their columns, so a number crossing the border of its
+//! cell, e.g. after typing in the middle of a line, is read as two broken
+//! numbers. Such lines are re-aligned by moving the content into the cell
+//! holding most of it, right-aligned like the preprocessors write them.
+//!
+//! Only lines made of numeric cells are checked, since strings may contain
+//! blanks, so their contents can't be told apart.
+use crate::card::value::CellData;
+
+/// The result of checking the alignment of a line
+#[derive(Debug, PartialEq)]
+pub enum Alignment {
+  /// All contents are inside of their cells, or the line was not checked
+  Aligned,
+  /// Some contents crossed the border of their cell. This is the line with
+  /// those contents moved into a cell.
+  Realigned(String),
+  /// The content in the columns `start..end` crosses the border of its cell,
+  /// but can't be moved into a cell unambiguously
+  Ambiguous(u8, u8),
+}
+
+/// The column ranges of the words of `text`, i.e. the runs of non-blanks
+fn words(text: &[u8]) -> Vec<(usize, usize)> {
+  let mut words = vec![];
+  let mut start = None;
+
+  for (i, b) in text.iter().chain(Some(&b' ')).enumerate() {
+    match (b.is_ascii_whitespace(), start) {
+      (false, None) => start = Some(i),
+      (true, Some(s)) => {
+        words.push((s, i));
+        start = None;
+      }
+      _ => {}
+    }
+  }
+
+  words
+}
+
+/// Check the alignment of the line `text` with the cells `cells`, see
+/// [`line_values`](crate::card::value::line_values)
+pub fn check(cells: &[CellData], text: &[u8]) -> Alignment {
+  use self::Alignment::*;
+
+  let numeric = |c: &CellData| matches!(c.kind, "integer" | "float" | "blank");
+  if cells.is_empty() || !cells.iter().skip(1).all(numeric) {
+    return Aligned;
+  }
+
+  let cell_of = |col: usize| {
+    cells
+      .iter()
+      .position(|c| c.start as usize <= col && col < c.end as usize)
+  };
+  #[allow(clippy::cast_possible_truncation)]
+  let ambiguous = |(s, e): (usize, usize)| Ambiguous(s as u8, e.min(255) as u8);
+
+  // The words inside of a cell, and the ones crossing a border along with
+  // the cells they touch
+  let mut placed = vec![];
+  let mut crossing = vec![];
+  for word in words(text) {
+    match (cell_of(word.0), cell_of(word.1 - 1)) {
+      (Some(f), Some(l)) if f == l => placed.push((word, f)),
+      // The keyword cell is never touched
+      (Some(0), Some(_)) => return ambiguous(word),
+      (Some(f), Some(l)) => crossing.push((word, f, l)),
+      // Content after the last cell is ignored by the solver, see
+      // `overflows`
+      (None, _) => {}
+      (Some(_), None) => return ambiguous(word),
+    }
+  }
+
+  if crossing.is_empty() {
+    return Aligned;
+  }
+
+  let mut touched = vec![false; cells.len()];
+  for &(word, first, last) in &crossing {
+    // The free cell holding most of the word, the last one on a tie
+    let target = (first..=last)
+      .rev()
+      .filter(|&i| cells[i].kind != "blank")
+      .filter(|&i| placed.iter().all(|p| p.1 != i))
+      .filter(|&i| word.1 - word.0 <= (cells[i].end - cells[i].start) as usize)
+      .max_by_key(|&i| {
+        let c = &cells[i];
+        word.1.min(c.end as usize) - word.0.max(c.start as usize)
+      });
+
+    match target {
+      Some(t) => {
+        placed.push((word, t));
+        for t in &mut touched[first..=last] {
+          *t = true;
+        }
+      }
+      None => return ambiguous(word),
+    }
+  }
+
+  let mut newline = text.to_vec();
+  for (cell, _) in cells.iter().zip(&touched).filter(|(_, t)| **t) {
+    if newline.len() < cell.end as usize {
+      newline.resize(cell.end as usize, b' ');
+    }
+    for b in &mut newline[cell.start as usize..cell.end as usize] {
+      *b = b' ';
+    }
+  }
+
+  let mut filled = vec![false; cells.len()];
+  for &(word, target) in placed.iter().filter(|p| touched[p.1]) {
+    if filled[target] {
+      return ambiguous(word);
+    }
+    filled[target] = true;
+
+    let end = cells[target].end as usize;
+    let start = end - (word.1 - word.0);
+    newline[start..end].copy_from_slice(&text[word.0..word.1]);
+  }
+
+  Realigned(String::from_utf8_lossy(&newline).trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    card::{
+      align::{check, Alignment},
+      value::card_values,
+    },
+    carddata::{NODE, SHELL},
+    linenr::LineNr,
+  };
+
+  fn align(text: &str) -> Alignment {
+    let card = if text.starts_with("NODE") {
+      &NODE
+    } else {
+      &SHELL
+    };
+    let values = card_values(card, &[(LineNr::from_usize(0), text.as_bytes())]);
+
+    check(&values[0].cells, text.as_bytes())
+  }
+
+  #[test]
+  fn aligned_lines() {
+    assert_eq!(
+      align("NODE  /        1              0.             0.5              0."),
+      Alignment::Aligned
+    );
+    // Left-aligned numbers are fine, they're inside of their cells
+    assert_eq!(
+      align("NODE  / 1       0.              0.5             0."),
+      Alignment::Aligned
+    );
+  }
+
+  #[test]
+  fn realign_crossing_numbers() {
+    // The 0.5 was shifted right by 2 columns
+    assert_eq!(
+      align("NODE  /        1              0.               0.5            0."),
+      Alignment::Realigned(
+        "NODE  /        1              0.             0.5              0."
+          .to_string()
+      )
+    );
+    assert_eq!(
+      align("SHELL /     3129       1     2967   2971    2970"),
+      Alignment::Realigned(
+        "SHELL /     3129       1    2967    2971    2970".to_string()
+      )
+    );
+  }
+
+  #[test]
+  fn ambiguous_alignment() {
+    // Both cells touched by the 2967 are taken
+    assert_eq!(
+      align("SHELL /     3129       1       1 2     2967    5    2970"),
+      Alignment::Ambiguous(39, 43)
+    );
+    // Too long for any cell
+    assert_eq!(
+      align("SHELL /     3129       1       1   296729712        2970"),
+      Alignment::Ambiguous(35, 44)
+    );
+    // Crossing from the keyword into the first cell
+    assert_eq!(
+      align("SHELL /3129       1       1    2967    2971    2970"),
+      Alignment::Ambiguous(6, 11)
+    );
+  }
+}
@@ -14,6 +14,7 @@ pub enum FixedStr {
   Weight,
   Rmat,
   EndPart, // 'END_PART'
+  End,     // 'END'
   Comment, // '#'
 }
 
@@ -25,6 +26,7 @@ impl FixedStr {
       Weight => 6,
       Rmat => 4,
       EndPart => 8, // 'END_PART'
+      End => 3,     // 'END'
       Comment => 1, // '#'
     }
   }
@@ -38,6 +40,7 @@ impl From<FixedStr> for &'static str {
       Weight => "WEIGHT",
       Rmat => "RMAT",
       EndPart => "END_PART",
+      End => "END",
       Comment => "#",
     }
   }
@@ -138,6 +141,20 @@ impl Cell {
   }
 }
 
+/// Compute the (end-exclusive) column ranges of the given cells
+pub(crate) fn cell_ranges(cells: &[Cell]) -> Vec<(u8, u8)> {
+  let mut start = 0;
+
+  cells
+    .iter()
+    .map(|c| {
+      let r = (start, start + c.len());
+      start += c.len();
+      r
+    })
+    .collect()
+}
+
 #[cfg(test)]
 mod tests {
   use super::Cell;
@@ -188,3 +205,4 @@ mod tests {
   }
 
 }
+
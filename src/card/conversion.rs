@@ -0,0 +1,322 @@
+//! Declarative conversions between related cards, e.g. from a `SHELL` to a
+//! `MEMBR`. The tables themselves can be found in
+//! [`carddata::conversion`](crate::carddata::conversion).
+use std::cmp;
+
+use crate::{
+  card::{cell::Cell, keyword::Keyword, line::Line, parse::CellLine, Card},
+  diagnostics::{Diagnostic, Severity},
+  linenr::LineNr,
+};
+
+/// Where the contents of a cell of the target card come from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CellSource {
+  /// The cell with the given index on the same line of the source card
+  Source(u8),
+  /// The cell with the given index on the given line of the source card,
+  /// e.g. a node on the keyword line of a `TETR4` that's on the second line
+  /// of a `TETR10`
+  Cell(u8, u8),
+  /// A fixed text, e.g. the keyword of the target card
+  Text(&'static [u8]),
+  /// Leave the cell blank
+  Empty,
+  /// Leave the cell blank, but it needs to be filled in by hand. The text
+  /// names the cell when reporting it, e.g. `"node 5"`.
+  Manual(&'static str),
+}
+
+/// A conversion from cards of type `from` to cards of type `to`. There's one
+/// entry in `lines` for each of the first lines of the target card, holding
+/// the [`CellSource`](crate::card::conversion::CellSource) for each
+/// [`Cell`](crate::card::cell::Cell) of that line.
+///
+/// The lines that are always present in both cards are converted, the lines
+/// after those are left as they are. If the target card has more lines, they
+/// are added after the converted ones.
+#[derive(Debug)]
+pub struct Conversion {
+  pub from: Keyword,
+  pub to: Keyword,
+  pub lines: &'static [&'static [CellSource]],
+}
+
+impl Conversion {
+  /// If the cell with index `cell` on the line `line` of the source card
+  /// has a place in the target card
+  fn maps(&self, line: usize, cell: u8) -> bool {
+    self.lines.iter().enumerate().any(|(i, sources)| {
+      sources.iter().any(|s| match *s {
+        CellSource::Source(k) => i == line && k == cell,
+        CellSource::Cell(l, k) => usize::from(l) == line && k == cell,
+        _ => false,
+      })
+    })
+  }
+
+  /// Convert the given lines of a card of type `from`. Only the lines the
+  /// source card always has are looked at, following lines like comments
+  /// are left alone. Returns the changed lines, the lines of the target card
+  /// to add after the last of them, and a
+  /// [`Diagnostic`](crate::diagnostics::Diagnostic) for each nonblank cell of
+  /// the source card that has no place in the target card. The cells that
+  /// need to be filled in by hand are listed in a diagnostic as well.
+  pub fn apply(
+    &self,
+    lines: &[(LineNr, &[u8])],
+  ) -> (Vec<(LineNr, String)>, Vec<String>, Vec<Diagnostic>) {
+    let source: &Card = (&self.from).into();
+    let target: &Card = (&self.to).into();
+
+    let mut edits = vec![];
+    let mut added = vec![];
+    let mut diagnostics = vec![];
+    let mut manual = vec![];
+
+    let srclayout = source.layout(lines.first().map_or(&[], |l| l.1));
+    let tgtlines = target.layout(&[]);
+    let fixed = srclayout
+      .iter()
+      .take_while(|l| matches!(l, Line::Cells(_) | Line::Provides(..)))
+      .count();
+    let srclines: Vec<CellLine> = lines
+      .iter()
+      .take(cmp::min(fixed, self.lines.len()))
+      .enumerate()
+      .map(|(i, (_, text))| {
+        let cells = srclayout.get(i).and_then(|l| l.cells()).unwrap_or(&[]);
+        CellLine::from_cells(cells, text)
+      })
+      .collect();
+    let last = lines
+      .get(srclines.len().saturating_sub(1))
+      .map_or(LineNr::from_usize(0), |l| l.0);
+
+    for (i, cellsources) in self.lines.iter().enumerate() {
+      let tgtcells = tgtlines.get(i).and_then(|l| l.cells()).unwrap_or(&[]);
+      let number = match lines.get(i) {
+        Some(l) if i < srclines.len() => l.0,
+        _ => last + 1 + (i - srclines.len()) as isize,
+      };
+      let raw = |line: usize, k: u8| {
+        srclines
+          .get(line)
+          .and_then(|l| l.raw(k as usize).ok())
+          .unwrap_or(&[])
+      };
+      let mut newline = Vec::with_capacity(80);
+
+      for (cell, cellsource) in tgtcells.iter().zip(cellsources.iter()) {
+        let width = cell.len() as usize;
+        let content: &[u8] = match *cellsource {
+          CellSource::Source(k) => raw(i, k),
+          CellSource::Cell(l, k) => raw(usize::from(l), k),
+          CellSource::Text(t) => t,
+          CellSource::Empty => &[],
+          CellSource::Manual(name) => {
+            manual.push(name);
+            &[]
+          }
+        };
+
+        let len = cmp::min(width, content.len());
+        if content[len..].iter().any(|b| *b != b' ') {
+          // Safe, lines aren't longer than 80 chars
+          #[allow(clippy::cast_possible_truncation)]
+          let start = newline.len() as u8;
+          diagnostics.push(Diagnostic::new(
+            number,
+            start,
+            start + cell.len(),
+            Severity::Warning,
+            format!("Content truncated converting {:?}", self.from),
+          ));
+        }
+        newline.extend_from_slice(&content[..len]);
+        newline.extend((len..width).map(|_| b' '));
+      }
+
+      while newline.last() == Some(&b' ') {
+        let _ = newline.pop();
+      }
+
+      let newline = String::from_utf8_lossy(&newline).into_owned();
+      if i < srclines.len() {
+        edits.push((number, newline));
+      } else {
+        added.push(newline);
+      }
+    }
+
+    for (i, (srcline, (number, _))) in srclines.iter().zip(lines).enumerate() {
+      let srccells = srclayout.get(i).and_then(|l| l.cells()).unwrap_or(&[]);
+
+      for (k, r) in srcline.ranges().iter().enumerate() {
+        if k == 0 && srccells.first().and_then(Cell::keyword).is_some() {
+          continue;
+        }
+
+        let content = srcline.raw(k).unwrap_or(&[]);
+
+        if !self.maps(i, k as u8) && content.iter().any(|b| *b != b' ') {
+          diagnostics.push(Diagnostic::new(
+            *number,
+            r.0,
+            r.1,
+            Severity::Warning,
+            format!(
+              "Cell '{}' of {:?} can not be converted to {:?}",
+              String::from_utf8_lossy(content).trim(),
+              self.from,
+              self.to
+            ),
+          ));
+        }
+      }
+    }
+
+    if let Some((first, _)) = edits.first() {
+      if !manual.is_empty() {
+        diagnostics.push(Diagnostic::new(
+          *first,
+          0,
+          8,
+          Severity::Info,
+          format!(
+            "Fill in the cells of {:?} left blank: {}",
+            self.to,
+            manual.join(", ")
+          ),
+        ));
+      }
+    }
+
+    (edits, added, diagnostics)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    card::keyword::Keyword::*, carddata::CONVERSIONS, linenr::LineNr,
+  };
+
+  const SHELL: &str =
+    "SHELL /     3129       1       1    2967    2971    2970             0.1";
+  const MEMBR: &str =
+    "MEMBR /     3129       1       1    2967    2971    2970             0.1";
+  const MEMBR2: &str =
+    "MEMBR /     3129       1       1    2967    2971    2970             0.1     0.2";
+
+  #[test]
+  fn shell_to_membr() {
+    let conv = CONVERSIONS
+      .iter()
+      .find(|c| c.from == Shell && c.to == Membr)
+      .unwrap();
+    let (edits, added, diags) =
+      conv.apply(&[(LineNr::from_usize(3), SHELL.as_ref())]);
+
+    assert_eq!(edits, vec![(LineNr::from_usize(3), MEMBR.to_string())]);
+    assert!(added.is_empty());
+    assert!(diags.is_empty());
+  }
+
+  #[test]
+  fn membr_to_shell_reports_unmapped() {
+    let conv = CONVERSIONS
+      .iter()
+      .find(|c| c.from == Membr && c.to == Shell)
+      .unwrap();
+    let (edits, _, diags) =
+      conv.apply(&[(LineNr::from_usize(0), MEMBR2.as_ref())]);
+
+    assert_eq!(edits, vec![(LineNr::from_usize(0), SHELL.to_string())]);
+    assert_eq!(diags.len(), 1);
+    assert_eq!((diags[0].start, diags[0].end), (72, 80));
+  }
+
+  const MTOCO: [&str; 2] = [
+    "MTOCO /        1      12111111       0       0       0     0.5",
+    "NAME MTOCO / ->1",
+  ];
+
+  #[test]
+  fn mtoco_to_otmco() {
+    let conv = CONVERSIONS
+      .iter()
+      .find(|c| c.from == Mtoco && c.to == Otmco)
+      .unwrap();
+    let lines = [
+      (LineNr::from_usize(0), MTOCO[0].as_ref()),
+      (LineNr::from_usize(1), MTOCO[1].as_ref()),
+    ];
+    let (edits, _, diags) = conv.apply(&lines);
+
+    assert_eq!(
+      edits[0].1,
+      format!("OTMCO /        1      12111111{}0.5", " ".repeat(37))
+    );
+    assert_eq!(edits[1].1, MTOCO[1]);
+    // IFRA1, ITMTO and ISENS have no counterpart
+    assert_eq!(diags.len(), 3);
+  }
+
+  #[test]
+  fn tetr4_to_tetr10() {
+    let conv = CONVERSIONS
+      .iter()
+      .find(|c| c.from == Tetr4 && c.to == Tetr10)
+      .unwrap();
+    let tetr4 = "TETR4 /      100       1      11      12      13      14";
+    const TETR10: &str = "TETR10/      100       1";
+    let (edits, added, diags) =
+      conv.apply(&[(LineNr::from_usize(5), tetr4.as_ref())]);
+
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0], (LineNr::from_usize(5), TETR10.to_string()));
+    assert_eq!(
+      added,
+      vec![
+        format!("{}      11      12      13      14", " ".repeat(16)),
+        String::new(),
+      ]
+    );
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(5));
+    assert_eq!(
+      diags[0].message,
+      "Fill in the cells of Tetr10 left blank: node 5, node 6, node 7, node \
+       8, node 9, node 10"
+    );
+  }
+
+  #[test]
+  fn keep_lines_after_the_card() {
+    let conv = CONVERSIONS
+      .iter()
+      .find(|c| c.from == Solid && c.to == Hexa20)
+      .unwrap();
+    let solid = [
+      "SOLID /      100       1",
+      "                       1       2       3       4       5       6",
+      "",
+      "        stray text",
+      "$ comment",
+    ];
+    let lines: Vec<_> = solid
+      .iter()
+      .enumerate()
+      .map(|(i, l)| (LineNr::from_usize(i), l.as_ref()))
+      .collect();
+    let (edits, added, diags) = conv.apply(&lines);
+
+    assert_eq!(
+      edits.iter().map(|e| e.0).collect::<Vec<_>>(),
+      vec![LineNr::from_usize(0), LineNr::from_usize(1)]
+    );
+    assert!(!added.is_empty());
+    assert!(diags.iter().all(|d| d.line <= LineNr::from_usize(1)));
+  }
+}
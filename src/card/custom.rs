@@ -0,0 +1,429 @@
+//! Card definitions loaded at startup from a TOML file given by `--carddefs`,
+//! so site specific cards get folded and highlighted without recompiling
+//! nvimpam. A definition looks like this:
+//!
+//! ```text
+//! [[card]]
+//! keyword = "MYLOAD/ "
+//! section = "constraint"
+//! ownfold = true
+//! lines = [
+//!   "Kw Integer(8) Float(8) Binary(6)",
+//!   "Fixed(Name) Str(76)",
+//!   "Ges(GesNode)",
+//! ]
+//! ```
+//!
+//! Each line is either `Ges(...)`, `Opaque(...)` with the start of the line
+//! ending the block, or the cells of a
+//! [`Cells`](crate::card::line::Line::Cells) line written like in
+//! [`carddata`](crate::carddata). The first line needs to start with `Kw`.
+//! Conditional lines are not supported.
+//!
+//! If `keyword` is the one of a builtin card, the definition replaces it.
+//! Otherwise, it gets a [`Custom`](crate::card::keyword::Keyword::Custom)
+//! keyword, belonging to `section` (default `auxiliaries`). Builtin keywords
+//! depending on more than their first 8 characters, like `PART` or `RBODY`,
+//! can't be replaced. `ownfold` defaults to `true`.
+use std::{
+  fs,
+  sync::{Arc, RwLock},
+};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use failure::{self, Error, ResultExt};
+use lazy_static::lazy_static;
+
+use crate::{
+  card::{
+    cell::{Cell, FixedStr},
+    ges::GesType,
+    keyword::Keyword,
+    line::Line,
+    registry::{self, registry},
+    section::Section,
+    Card,
+  },
+  toml::{self, Table, TomlValue},
+};
+
+/// A card defined by the user. `hash` is the big endian number formed by the
+/// 8 characters of the keyword, see
+/// [`Keyword::parse`](crate::card::keyword::Keyword::parse).
+#[derive(Debug)]
+pub struct CustomCard {
+  pub keyword: Keyword,
+  pub hash: u64,
+  pub section: Option<Section>,
+  pub card: &'static Card,
+}
+
+lazy_static! {
+  /// The registered cards. This is only ever set once, see
+  /// [`register`](crate::card::custom::register).
+  static ref CUSTOM_CARDS: RwLock<Option<Arc<Vec<CustomCard>>>> =
+    RwLock::new(None);
+}
+
+/// The registered custom cards
+pub fn custom_cards() -> Arc<Vec<CustomCard>> {
+  match *CUSTOM_CARDS.read().unwrap() {
+    Some(ref cards) => Arc::clone(cards),
+    None => Arc::new(vec![]),
+  }
+}
+
+/// Register `cards` for the rest of the runtime of the program. This can only
+/// be done once.
+pub fn register(cards: Vec<CustomCard>) -> Result<(), Error> {
+  {
+    let mut current = CUSTOM_CARDS.write().unwrap();
+    if current.is_some() {
+      return Err(failure::err_msg(
+        "Card definitions can only be loaded once!",
+      ));
+    }
+    *current = Some(Arc::new(cards));
+  }
+
+  registry::rebuild();
+  Ok(())
+}
+
+/// The new keyword of the custom card whose first 8 characters form `hash`,
+/// see [`Registry::keyword`](crate::card::registry::Registry::keyword)
+#[inline]
+pub fn keyword(hash: u64) -> Option<Keyword> {
+  registry().keyword(hash)
+}
+
+/// The custom card for `kw`, if there is one
+#[inline]
+pub fn card(kw: Keyword) -> Option<&'static Card> {
+  registry().custom(kw).map(|c| c.card)
+}
+
+/// The section of the custom card for `kw`, if it was given one
+pub fn section(kw: Keyword) -> Option<Section> {
+  registry().custom(kw).and_then(|c| c.section)
+}
+
+/// The argument of `s` if it's of the form `name(arg)`
+fn argument<'a>(s: &'a str, name: &str) -> Option<&'a str> {
+  if s.starts_with(name) && s[name.len()..].starts_with('(') && s.ends_with(')')
+  {
+    Some(&s[name.len() + 1..s.len() - 1])
+  } else {
+    None
+  }
+}
+
+fn parse_cell(s: &str, kw: Keyword) -> Result<Cell, Error> {
+  let width = |arg: &str| {
+    arg
+      .parse::<u8>()
+      .map_err(|_| failure::err_msg(format!("Invalid width in '{}'", s)))
+  };
+
+  if s == "Kw" {
+    return Ok(Cell::Kw(kw));
+  } else if s == "Cont" {
+    return Ok(Cell::Cont);
+  } else if let Some(arg) = argument(s, "Fixed") {
+    let fixed = match arg {
+      "Name" => FixedStr::Name,
+      "Weight" => FixedStr::Weight,
+      "Rmat" => FixedStr::Rmat,
+      "EndPart" => FixedStr::EndPart,
+      "End" => FixedStr::End,
+      "Comment" => FixedStr::Comment,
+      _ => {
+        return Err(failure::err_msg(format!("Unknown fixed string '{}'", arg)))
+      }
+    };
+    return Ok(Cell::Fixed(fixed));
+  }
+
+  let unknown = || failure::err_msg(format!("Unknown cell '{}'", s));
+  let (name, arg) = match s.find('(') {
+    Some(i) if s.ends_with(')') => (&s[..i], &s[i + 1..s.len() - 1]),
+    _ => return Err(unknown()),
+  };
+
+  let cell: fn(u8) -> Cell = match name {
+    "Integer" => Cell::Integer,
+    "Float" => Cell::Float,
+    "Blank" => Cell::Blank,
+    "Str" => Cell::Str,
+    "Binary" => Cell::Binary,
+    "IntegerorBlank" => Cell::IntegerorBlank,
+    _ => return Err(unknown()),
+  };
+
+  Ok(cell(width(arg)?))
+}
+
+fn parse_line(s: &str, kw: Keyword, first: bool) -> Result<Line, Error> {
+  let s = s.trim();
+
+  if let Some(arg) = argument(s, "Ges") {
+    let ges = match arg {
+      "GesNode" => GesType::GesNode,
+      "GesEle" => GesType::GesEle,
+      "GesEdge" => GesType::GesEdge,
+      "GesFace" => GesType::GesFace,
+      "GesPart" => GesType::GesPart,
+      _ => return Err(failure::err_msg(format!("Unknown GES type '{}'", arg))),
+    };
+
+    if first {
+      return Err(failure::err_msg("The first line needs to start with Kw"));
+    }
+    return Ok(Line::Ges(ges));
+  }
+
+  if let Some(arg) = argument(s, "Opaque") {
+    if first {
+      return Err(failure::err_msg("The first line needs to start with Kw"));
+    }
+    let end: &'static [u8] = Box::leak(arg.as_bytes().into());
+    return Ok(Line::Opaque(end));
+  }
+
+  let cells = s
+    .split_whitespace()
+    .map(|c| parse_cell(c, kw))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  match cells.iter().position(|c| c.keyword().is_some()) {
+    Some(0) if first => {}
+    None if !first => {}
+    _ => {
+      return Err(failure::err_msg(
+        "Kw needs to be the first cell of the first line",
+      ))
+    }
+  }
+
+  Ok(Line::Cells(Box::leak(cells.into_boxed_slice())))
+}
+
+/// Parse a `[[card]]` table. New keywords get the keyword
+/// `Keyword::Custom(index)`.
+fn parse_card(table: &Table, index: usize) -> Result<CustomCard, Error> {
+  let mut text = None;
+  let mut section = None;
+  let mut ownfold = true;
+  let mut lines = None;
+
+  for (key, value) in table {
+    match (key.as_str(), value) {
+      ("keyword", TomlValue::Str(s)) => text = Some(s.as_str()),
+      ("section", TomlValue::Str(s)) => {
+        section = Some(Section::from_name(s).ok_or_else(|| {
+          failure::err_msg(format!("Unknown section '{}'", s))
+        })?)
+      }
+      ("ownfold", TomlValue::Bool(b)) => ownfold = *b,
+      ("lines", TomlValue::Array(a)) => lines = Some(a),
+      _ => {
+        return Err(failure::err_msg(format!(
+          "Invalid entry '{}' = {:?}",
+          key, value
+        )))
+      }
+    }
+  }
+
+  let text = text.ok_or_else(|| failure::err_msg("Missing keyword"))?;
+  if text.len() > 8 || !text.is_ascii() {
+    return Err(failure::err_msg(format!(
+      "Keyword '{}' needs to be at most 8 ascii characters",
+      text
+    )));
+  }
+  let padded = format!("{:<8}", text);
+  let hash = padded.as_bytes().read_u64::<BigEndian>()?;
+
+  let keyword = match Keyword::parse(padded.as_bytes()) {
+    Some(k) if k != Keyword::Unknown => k,
+    _ if index < 256 => Keyword::Custom(index as u8),
+    _ => return Err(failure::err_msg("Too many custom keywords")),
+  };
+
+  let lines = lines.ok_or_else(|| failure::err_msg("Missing lines"))?;
+  if lines.is_empty() {
+    return Err(failure::err_msg("Missing lines"));
+  }
+
+  let lines = lines
+    .iter()
+    .enumerate()
+    .map(|(i, l)| match l {
+      TomlValue::Str(s) => parse_line(s, keyword, i == 0)
+        .with_context(|_| format!("Invalid line {}", i + 1))
+        .map_err(Error::from),
+      v => Err(failure::err_msg(format!(
+        "Line {} is not a string: {:?}",
+        i + 1,
+        v
+      ))),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let card = Box::leak(Box::new(Card {
+    lines: Box::leak(lines.into_boxed_slice()),
+    ownfold,
+  }));
+
+  Ok(CustomCard {
+    keyword,
+    hash,
+    section,
+    card,
+  })
+}
+
+/// Parse the card definitions in `text`
+pub fn parse(text: &str) -> Result<Vec<CustomCard>, Error> {
+  let mut cards: Vec<CustomCard> = vec![];
+  let mut custom = 0;
+
+  for (i, (name, table)) in toml::parse(text)?.iter().enumerate() {
+    if name != "card" {
+      return Err(failure::err_msg(format!("Unknown table '[[{}]]'", name)));
+    }
+
+    let card = parse_card(table, custom)
+      .with_context(|_| format!("Invalid card definition {}", i + 1))?;
+
+    if cards.iter().any(|c| c.hash == card.hash) {
+      return Err(failure::err_msg(format!(
+        "Card definition {} duplicates a keyword",
+        i + 1
+      )));
+    }
+    if let Keyword::Custom(_) = card.keyword {
+      custom += 1;
+    }
+
+    cards.push(card);
+  }
+
+  Ok(cards)
+}
+
+/// Load and register the card definitions from the file at `path`. Returns the
+/// number of cards defined.
+pub fn load_file(path: &str) -> Result<usize, Error> {
+  let text = fs::read_to_string(path)
+    .with_context(|_| format!("Could not read card definitions '{}'", path))?;
+  let cards = parse(&text)?;
+  let len = cards.len();
+
+  register(cards)?;
+
+  Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::card::{
+    cell::{Cell::*, FixedStr},
+    custom::{parse, register},
+    ges::GesType::*,
+    keyword::Keyword::{self, *},
+    line::Line::*,
+    section::Section,
+  };
+
+  const DEFS: &str = r#"
+[[card]]
+keyword = "HFLUX /"
+lines = ["Kw Integer(8)", "Fixed(Name) Str(76)", "Ges(GesNode)"]
+
+[[card]]
+keyword = "MYLOAD/ "
+section = "constraint"
+ownfold = false
+lines = [
+  "Kw Integer(8) Float(8) Binary(6)",
+  "Fixed(Name) Str(76)",
+  "Opaque(END_MYLOAD)",
+]
+"#;
+
+  #[test]
+  fn parse_carddefs() {
+    let cards = parse(DEFS).unwrap();
+
+    assert_eq!(cards.len(), 2);
+    assert_eq!(cards[0].keyword, Hflux);
+    assert_eq!(cards[0].card.lines[2], Ges(GesNode));
+    assert_eq!(cards[1].keyword, Custom(0));
+    assert_eq!(cards[1].section, Some(Section::Constraint));
+    assert!(!cards[1].card.ownfold);
+    assert_eq!(
+      cards[1].card.lines[0],
+      Cells(&[Kw(Custom(0)), Integer(8), Float(8), Binary(6)])
+    );
+    assert_eq!(
+      cards[1].card.lines[1],
+      Cells(&[Fixed(FixedStr::Name), Str(76)])
+    );
+    assert_eq!(cards[1].card.lines[2], Opaque(b"END_MYLOAD"));
+  }
+
+  #[test]
+  fn report_invalid_carddefs() {
+    for defs in &[
+      "[[card]]\nlines = [\"Kw\"]",
+      "[[card]]\nkeyword = \"TOOLONGKW\"\nlines = [\"Kw\"]",
+      "[[card]]\nkeyword = \"A\"\nlines = [\"Integer(8)\"]",
+      "[[card]]\nkeyword = \"A\"\nlines = [\"Kw\", \"Kw Float(8)\"]",
+      "[[card]]\nkeyword = \"A\"\nlines = [\"Kw Float(x)\"]",
+      "[[card]]\nkeyword = \"A\"\nlines = [\"Kw\"]\ncolor = \"red\"",
+      "[[card]]\nkeyword = \"A\"\nlines = [\"Kw\"]\n[[card]]\nkeyword = \"A\"\n\
+       lines = [\"Kw\"]",
+      "[[cards]]\nkeyword = \"A\"\nlines = [\"Kw\"]",
+    ] {
+      assert!(parse(defs).is_err(), "Parsed {:?}", defs);
+    }
+  }
+
+  #[test]
+  fn fold_custom_cards() {
+    use crate::bufdata::BufData;
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    // This is the only test registering cards, since that can only be done
+    // once
+    let defs = "[[card]]\nkeyword = \"MYCARD/\"\n\
+                lines = [\"Kw Integer(8)\", \"Fixed(Name) Str(76)\", \
+                \"Ges(GesNode)\"]";
+    register(parse(defs).unwrap()).unwrap();
+    assert!(register(vec![]).is_err());
+
+    let kw = Keyword::parse(b"MYCARD/        1").unwrap();
+    assert_eq!(Section::from(kw), Section::Auxiliaries);
+
+    let lines = [
+      "NODE  /        1              0.             0.5              0.",
+      "MYCARD/        1",
+      "NAME My card",
+      "        NOD 1",
+      "        END",
+      "MYCARD/        2",
+      "NAME My card",
+    ];
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+
+    assert_eq!(
+      vec![(0, 0, Node), (1, 4, kw), (5, 6, kw)],
+      bufdata.folds_to_vec()
+    );
+  }
+}
@@ -1,121 +1,60 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]
 //! The General Entity Selection scheme of Pamcrash.
+use std::fmt;
 
-/// An enum to denote the type of a GES. Not yet used.
+use crate::card::xref::Entity;
+
+/// An enum to denote the type of a GES, i.e. the entities it selects. Each
+/// type allows its own set of selections, see
+/// [`contains`](crate::card::ges::GesType::contains).
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum GesType {
+  /// Nodes. Since every entity has nodes, all selections are allowed.
   GesNode,
+  /// Elements, selected directly or by their parts or groups
   GesEle,
+  /// Edges, which can also be given by elements or nodes
   GesEdge,
+  /// Faces, i.e. segments, which can also be given by elements or nodes
   GesFace,
+  /// Parts, selected directly or by groups
+  GesPart,
+}
+
+/// The kind of entity a line of a GES selects
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Selection {
+  Node,
+  Element,
+  Edge,
+  Segment,
+  Part,
+  /// Groups and submodels, which might contain any entity
+  Group,
 }
 
 impl GesType {
   /// Checks if a given line fits the basic format of a line in a GES: 8 blanks
-  /// followed by one of several keywords, followed by a Blank. Checks nothing
+  /// followed by one of several keywords, followed by a Blank. The keyword
+  /// needs to select entities the GES type allows, e.g. a `NOD` line is not
+  /// part of a [`GesEle`](crate::card::ges::GesType::GesEle). Checks nothing
   /// else.
   pub fn contains(self, b: &[u8]) -> bool {
-    use byteorder::{BigEndian, ReadBytesExt};
-
-    let len = b.len();
-
-    // b"        " as a u64 in BigEndian is 2314885530818453536
-    if len < 12
-      || (&b[0..8]).read_u64::<BigEndian>().ok() != Some(2314885530818453536)
-    {
-      false
-    } else {
-      let num = match (&b[8..12]).read_u32::<BigEndian>() {
-        Ok(n) => n,
-        Err(_) => return false,
-      };
+    use self::{GesType::*, Selection::*};
 
-      match num {
-        // b"ELE " | b"GRP " | b"NOD " | b"SEG " | b"EDG " | b"MOD "
-        1162626336 | 1196576800 | 1313817632 | 1162102560 | 1297040416 => true,
-        // b"OGRP"
-        1330074192 => len >= 13 && b[12] == b' ',
-        // b"DELN"
-        1145392206 => {
-          len >= 15
-            // b"OD " as u24 in BigEndian is 5194784
-            && (&b[12..15]).read_u24::<BigEndian>().ok() == Some(5194784)
-        }
-        // b"DELE"
-        1145392197 => {
-          if len < 15 {
-            false
-          } else {
-            match (&b[12..15]).read_u24::<BigEndian>().ok() {
-              // b"LE " as u24 in BigEndian is 4998432
-              Some(4998432) => true,
-              // b"LE>" as u24 in BigEndian is 4998462
-              Some(4998462) => {
-                len >= 19
-                  // b"NOD " as u32 in BigEndian is 1313817632
-                  && (&b[15..19]).read_u32::<BigEndian>().ok()
-                    == Some(1313817632)
-              }
-              _ => false,
-            }
-          }
-        }
-        // b"DELG"
-        1145392199 => {
-          if len < 15 {
-            false
-          } else {
-            match (&b[12..15]).read_u24::<BigEndian>().ok() {
-              // b"RP " as u24 in BigEndian is 5394464
-              Some(5394464) => true,
-              // b"RP>" as u24 in BigEndian is 5394494
-              Some(5394494) => {
-                len >= 19
-                  // b"NOD " as u32 in BigEndian is 1313817632
-                  && (&b[15..19]).read_u32::<BigEndian>().ok()
-                    == Some(1313817632)
-              }
-              _ => false,
-            }
-          }
-        }
-        // b"END_"
-        1162757215 => {
-          len >= 15
-            // b"MOD" as u24 in BigEndian is 5066564
-            && (&b[12..15]).read_u24::<BigEndian>().ok() == Some(5066564)
-        }
-        // b"ELE>" | b"GRP>"
-        1162626366 | 1196576830 => {
-          // b"NOD " as u32 in BigEndian is 1313817632
-          len >= 16
-            && (&b[12..16]).read_u32::<BigEndian>().ok() == Some(1313817632)
-        }
-        // b"DELP"
-        1145392208 => {
-          // b"ART " as u32 in BigEndian is 1095914528
-          // b"ART>NOD " as u64 in BigEndian is 4706917187134112800
-          len >= 16
-            && (&b[12..16]).read_u32::<BigEndian>().ok() == Some(1095914528)
-            || len >= 20
-              && (&b[12..20]).read_u64::<BigEndian>().ok()
-                == Some(4706917187134112800)
-        }
-        // b"PART"
-        1346458196 => {
-          len >= 13 && b[12] == b' '
-            || len >= 17
-              && b[16] == b' '
-              // b">NOD" as u32 in BigEndian is 1045319492
-              && (&b[12..16]).read_u32::<BigEndian>().ok() == Some(1045319492)
-        }
-        _ => false,
-      }
+    match selection(b) {
+      None => false,
+      Some(Group) | Some(Part) => true,
+      Some(Node) => self == GesNode || self == GesEdge || self == GesFace,
+      Some(Element) => self != GesPart,
+      Some(Edge) => self == GesNode || self == GesEdge,
+      Some(Segment) => self == GesNode || self == GesFace,
     }
   }
 
   /// Check if a given line ends a GES. That is, it consists of 8 blanks
   /// followed by "END". Anything more or less makes the check return `false`.
+  /// This is the same for all GES types.
   pub fn ended_by(self, b: &[u8]) -> bool {
     let len = b.len();
 
@@ -123,9 +62,233 @@ impl GesType {
   }
 }
 
+/// An item selected by a line of a GES
+#[derive(Debug, PartialEq, Clone)]
+pub enum GesItem {
+  /// A single ID
+  Id(i64),
+  /// The IDs from the first to the last one, e.g. `1:9`
+  Range(i64, i64),
+  /// The name of a group or submodel, without the quotes
+  Name(String),
+}
+
+impl GesItem {
+  fn parse(s: &str) -> Self {
+    use self::GesItem::*;
+
+    let mut bounds = s.splitn(2, ':').map(str::parse::<i64>);
+
+    match (bounds.next(), bounds.next()) {
+      (Some(Ok(id)), None) => Id(id),
+      (Some(Ok(from)), Some(Ok(to))) => Range(from, to),
+      _ => Name(s.trim_matches('\'').to_string()),
+    }
+  }
+}
+
+impl fmt::Display for GesItem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    use self::GesItem::*;
+
+    match self {
+      Id(id) => write!(f, "{}", id),
+      Range(from, to) => write!(f, "{}:{}", from, to),
+      Name(name) => write!(f, "'{}'", name),
+    }
+  }
+}
+
+/// A line of a GES, i.e. its selector like `PART>NOD` and the items it
+/// selects
+#[derive(Debug, PartialEq, Clone)]
+pub struct GesLine {
+  pub selector: String,
+  pub items: Vec<GesItem>,
+}
+
+impl GesLine {
+  /// Parse the line `b` of a GES. Returns `None` if it does not fit the
+  /// format of a GES line, see
+  /// [`contains`](crate::card::ges::GesType::contains). Names of groups are
+  /// enclosed in single quotes and may contain blanks, other items are
+  /// separated by blanks.
+  pub fn parse(b: &[u8]) -> Option<Self> {
+    let _ = selection(b)?;
+
+    let text = String::from_utf8_lossy(&b[8..]);
+    let (selector, rest) = text.split_at(text.find(' ').unwrap_or(text.len()));
+    let mut rest = rest.trim_start();
+    let mut items = vec![];
+
+    while !rest.is_empty() {
+      let end = if rest.starts_with('\'') {
+        rest[1..].find('\'').map_or(rest.len(), |i| i + 2)
+      } else {
+        rest.find(char::is_whitespace).unwrap_or(rest.len())
+      };
+      items.push(GesItem::parse(&rest[..end]));
+      rest = rest[end..].trim_start();
+    }
+
+    Some(GesLine {
+      selector: selector.to_string(),
+      items,
+    })
+  }
+
+  /// If the line removes entities from the selection, e.g. `DELPART`
+  pub fn deselects(&self) -> bool {
+    self.selector.starts_with("DEL")
+  }
+
+  /// The entity the IDs of the line belong to, e.g. `Part` for `PART>NOD`.
+  /// Returns `None` if it's not one that can be referenced by its ID, e.g.
+  /// for groups.
+  pub fn entity(&self) -> Option<Entity> {
+    let selector = self.selector.trim_start_matches("DEL");
+
+    match selector.split('>').next() {
+      Some("NOD") => Some(Entity::Node),
+      Some("ELE") => Some(Entity::Element),
+      Some("PART") => Some(Entity::Part),
+      _ => None,
+    }
+  }
+}
+
+/// Find out what kind of entity a line of a GES selects. Returns `None` if the
+/// line does not fit the format of a GES line.
+fn selection(b: &[u8]) -> Option<Selection> {
+  use self::Selection::*;
+  use byteorder::{BigEndian, ReadBytesExt};
+
+  let len = b.len();
+
+  // b"        " as a u64 in BigEndian is 2314885530818453536
+  if len < 12
+    || (&b[0..8]).read_u64::<BigEndian>().ok() != Some(2314885530818453536)
+  {
+    return None;
+  }
+
+  let num = (&b[8..12]).read_u32::<BigEndian>().ok()?;
+  let found = |b: bool, sel: Selection| if b { Some(sel) } else { None };
+
+  match num {
+    // b"ELE "
+    1162626336 => Some(Element),
+    // b"NOD "
+    1313817632 => Some(Node),
+    // b"EDG "
+    1162102560 => Some(Edge),
+    // b"SEG "
+    1397049120 => Some(Segment),
+    // b"GRP " | b"MOD "
+    1196576800 | 1297040416 => Some(Group),
+    // b"OGRP"
+    1330074192 => found(len >= 13 && b[12] == b' ', Group),
+    // b"DELN"
+    1145392206 => {
+      // b"OD " as u24 in BigEndian is 5194784
+      found(
+        len >= 15 && (&b[12..15]).read_u24::<BigEndian>().ok() == Some(5194784),
+        Node,
+      )
+    }
+    // b"DELE"
+    1145392197 => {
+      if len < 15 {
+        None
+      } else {
+        match (&b[12..15]).read_u24::<BigEndian>().ok() {
+          // b"LE " as u24 in BigEndian is 4998432
+          Some(4998432) => Some(Element),
+          // b"LE>" as u24 in BigEndian is 4998462
+          Some(4998462) => found(
+            len >= 19
+              // b"NOD " as u32 in BigEndian is 1313817632
+              && (&b[15..19]).read_u32::<BigEndian>().ok() == Some(1313817632),
+            Node,
+          ),
+          _ => None,
+        }
+      }
+    }
+    // b"DELG"
+    1145392199 => {
+      if len < 15 {
+        None
+      } else {
+        match (&b[12..15]).read_u24::<BigEndian>().ok() {
+          // b"RP " as u24 in BigEndian is 5394464
+          Some(5394464) => Some(Group),
+          // b"RP>" as u24 in BigEndian is 5394494
+          Some(5394494) => found(
+            len >= 19
+              // b"NOD " as u32 in BigEndian is 1313817632
+              && (&b[15..19]).read_u32::<BigEndian>().ok() == Some(1313817632),
+            Node,
+          ),
+          _ => None,
+        }
+      }
+    }
+    // b"END_"
+    1162757215 => {
+      // b"MOD" as u24 in BigEndian is 5066564
+      found(
+        len >= 15 && (&b[12..15]).read_u24::<BigEndian>().ok() == Some(5066564),
+        Group,
+      )
+    }
+    // b"ELE>" | b"GRP>"
+    1162626366 | 1196576830 => {
+      // b"NOD " as u32 in BigEndian is 1313817632
+      found(
+        len >= 16
+          && (&b[12..16]).read_u32::<BigEndian>().ok() == Some(1313817632),
+        Node,
+      )
+    }
+    // b"DELP"
+    1145392208 => {
+      // b"ART " as u32 in BigEndian is 1095914528
+      // b"ART>NOD " as u64 in BigEndian is 4706917187134112800
+      if len >= 16
+        && (&b[12..16]).read_u32::<BigEndian>().ok() == Some(1095914528)
+      {
+        Some(Part)
+      } else {
+        found(
+          len >= 20
+            && (&b[12..20]).read_u64::<BigEndian>().ok()
+              == Some(4706917187134112800),
+          Node,
+        )
+      }
+    }
+    // b"PART"
+    1346458196 => {
+      if len >= 13 && b[12] == b' ' {
+        Some(Part)
+      } else {
+        found(
+          len >= 17
+            && b[16] == b' '
+            // b">NOD" as u32 in BigEndian is 1045319492
+            && (&b[12..16]).read_u32::<BigEndian>().ok() == Some(1045319492),
+          Node,
+        )
+      }
+    }
+    _ => None,
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::card::ges::GesType::GesNode;
+  use crate::card::ges::GesType::{self, *};
 
   const LINES: [&'static str; 10] = [
     "ab ll",
@@ -155,6 +318,70 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_ges_types() {
+    let lines: [&[u8]; 6] = [
+      b"        NOD 1",
+      b"        ELE 12",
+      b"        EDG 3",
+      b"        SEG 4",
+      b"        PART 5",
+      b"        DELPART>NOD 6",
+    ];
+    let contained = |ges: GesType| {
+      lines.iter().map(|l| ges.contains(l)).collect::<Vec<bool>>()
+    };
+
+    assert_eq!(contained(GesNode), vec![true; 6]);
+    assert_eq!(
+      contained(GesEle),
+      vec![false, true, false, false, true, false]
+    );
+    assert_eq!(
+      contained(GesEdge),
+      vec![true, true, true, false, true, true]
+    );
+    assert_eq!(
+      contained(GesFace),
+      vec![true, true, false, true, true, true]
+    );
+    assert_eq!(
+      contained(GesPart),
+      vec![false, false, false, false, true, false]
+    );
+  }
+
+  #[test]
+  fn parse_ges_lines() {
+    use crate::card::{
+      ges::{GesItem::*, GesLine},
+      xref::Entity,
+    };
+
+    let nodes =
+      GesLine::parse(b"        NOD        1:9      118      300").unwrap();
+    assert_eq!(nodes.selector, "NOD");
+    assert_eq!(nodes.items, vec![Range(1, 9), Id(118), Id(300)]);
+    assert_eq!(nodes.entity(), Some(Entity::Node));
+    assert!(!nodes.deselects());
+
+    let parts = GesLine::parse(b"        DELPART>NOD        2").unwrap();
+    assert_eq!(parts.items, vec![Id(2)]);
+    assert_eq!(parts.entity(), Some(Entity::Part));
+    assert!(parts.deselects());
+
+    let group = GesLine::parse(b"        OGRP 'haus bau' 'x'").unwrap();
+    assert_eq!(
+      group.items,
+      vec![Name("haus bau".to_string()), Name("x".to_string())]
+    );
+    assert_eq!(group.entity(), None);
+    assert_eq!(group.items[0].to_string(), "'haus bau'");
+
+    assert_eq!(GesLine::parse(b"        END"), None);
+    assert_eq!(GesLine::parse(b"NODE  /        1"), None);
+  }
+
   #[test]
   fn test_ends_ges() {
     let v = vec![
@@ -1,83 +1,314 @@
-#![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]
 //! This module provides the [`Keyword`](crate::card::keyword::Keyword) enum to
 //! classify lines according to what card type they belong to. The terms
 //! "Keyword" and "Card" are lingo from the FEM solver Pamcrash, but generally
 //! used among FEM solvers.
+//!
+//! The builtin keywords are declared by the `keywords!` invocation below,
+//! which generates the enum variant, the parsing of its 8 characters and the lookup
+//! of its [`Card`](crate::card::Card) from a single line per card. To add a
+//! card, define its static in the [`carddata`](crate::carddata) module and add
+//! a line there.
+use byteorder::{BigEndian, ReadBytesExt};
 
-/// An enum to denote the several types of cards a line might belong to.
-#[derive(Copy, Clone, PartialEq, Debug)]
-pub enum Keyword {
-  // Node
-  Node,
-  Cnode,
-  Mass,
-  Nsmas,
-  Nsmas2,
-  // Element
-  Solid,
-  Hexa20,
-  Pent15,
-  Penta6,
-  Tetr10,
-  Tetr4,
-  Bshel,
-  Tshel,
-  Shell,
-  Shel6,
-  Shel8,
-  Membr,
-  Beam,
-  Sprgbm,
-  Bar,
-  Spring,
-  Joint,
-  Kjoin,
-  Mtojnt,
-  Sphel,
-  Sphelo,
-  Gap,
-  Impma,
-  // Link
-  Elink,
-  Llink,
-  Slink,
-  Plink,
-  Tied,
-  // Part 3D
-  PartSolid,
-  PartBshel,
-  PartTetra,
-  PartSphel,
-  PartCos3d,
-  // Part 2D
-  PartTshel,
-  PartShell,
-  PartMembr,
-  // Part 1D
-  PartBar,
-  PartBeam,
-  PartSpring,
-  PartSprgbm,
-  PartMbspr,
-  PartJoint,
-  PartKjoin,
-  PartMbkjn,
-  PartMtojnt,
-  PartTied,
-  PartSlink,
-  PartElink,
-  PartLlink,
-  PartPlink,
-  PartGap,
-  // Constraint
-  Mtoco,
-  Otmco,
-  Rbody0,
-  Rbody1,
-  Rbody2,
-  Rbody3,
-  // Auxiliaries
-  Group,
+use crate::card::{
+  registry::{self, registry},
+  section::CONTROL_STARTS,
+  Card,
+};
+
+/// Declare the builtin keywords. `plain` keywords are given by the first 8
+/// characters of their line, e.g. `Node => NODE: b"NODE  / "` for the
+/// keyword `Node` with the card `NODE`. The `subtyped` keywords share their
+/// first 8 characters, named like `PART: b"PART  / "`, and are told apart by
+/// the word in the 8 columns starting at the given one, which may be padded
+/// by spaces on either side. Lines with those 8 characters but an unknown
+/// subtype are no keyword lines.
+///
+/// The first 8 characters of each line are read as a big endian number, and
+/// matched against the ones of the keywords, declared as constants in the
+/// module `num`. The compiler turns that into a binary search, instead of
+/// comparing the line to one keyword after the other.
+///
+/// Generates the [`Keyword`](crate::card::keyword::Keyword) enum, the list of
+/// [`BUILTIN`](crate::card::keyword::Keyword::BUILTIN) keywords, the parsing
+/// of the builtin keywords, [`index`](crate::card::keyword::Keyword::index),
+/// [`sample`](crate::card::keyword::Keyword::sample) and
+/// [`builtin_card`](crate::card::keyword::Keyword::builtin_card).
+macro_rules! keywords {
+  (
+    plain {
+      $($kw: ident => $card: ident: $name: literal,)*
+    }
+    subtyped {
+      $($pname: ident: $parent: literal [$col: literal] {
+        $($skw: ident => $scard: ident: $sub: literal,)*
+      })*
+    }
+  ) => {
+    /// The first 8 characters of the builtin keywords as big endian numbers
+    mod num {
+      $(pub const $card: u64 = u64::from_be_bytes(*$name);)*
+      $(pub const $pname: u64 = u64::from_be_bytes(*$parent);)*
+    }
+
+    /// The builtin keywords in the order of
+    /// [`BUILTIN`](crate::card::keyword::Keyword::BUILTIN), to number them
+    enum Builtin {
+      $($kw,)*
+      $($($skw,)*)*
+      Encrypted,
+      Unknown,
+    }
+
+    /// An enum to denote the several types of cards a line might belong to.
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    pub enum Keyword {
+      $($kw,)*
+      $($($skw,)*)*
+      /// An encrypted block, starting with `BEGIN_ENCRYPTED`
+      Encrypted,
+      /// A card nvimpam does not know, starting with an uppercase word padded
+      /// to 6 columns and followed by `/ `, e.g. `SENSOR/ `
+      Unknown,
+      /// A card defined at runtime, see [`card::custom`](crate::card::custom).
+      /// The number is the index among the new keywords defined.
+      Custom(u8),
+    }
+
+    impl Keyword {
+      /// All builtin keywords, i.e. all but the
+      /// [`Custom`](crate::card::keyword::Keyword::Custom) ones
+      pub const BUILTIN: &'static [Keyword] = &[
+        $(Keyword::$kw,)*
+        $($(Keyword::$skw,)*)*
+        Keyword::Encrypted,
+        Keyword::Unknown,
+      ];
+
+      /// Find the builtin keyword of the line `s`, whose first 8 characters
+      /// form the big endian number `num`. Does not know about
+      /// [`Encrypted`](crate::card::keyword::Keyword::Encrypted) blocks and
+      /// [`Unknown`](crate::card::keyword::Keyword::Unknown) cards.
+      /// Returns `Err(())` if `num` is the one of a subtyped keyword, but the
+      /// subtype is unknown.
+      #[inline]
+      fn parse_builtin(num: u64, s: &[u8]) -> Result<Option<Self>, ()> {
+        match num {
+          $(num::$card => Ok(Some(Keyword::$kw)),)*
+          $(
+            num::$pname => match subtype(s, $col).ok_or(())? {
+              $($sub => Ok(Some(Keyword::$skw)),)*
+              _ => Err(()),
+            },
+          )*
+          _ => Ok(None),
+        }
+      }
+
+      /// The position of the keyword in
+      /// [`BUILTIN`](crate::card::keyword::Keyword::BUILTIN). The
+      /// [`Custom`](crate::card::keyword::Keyword::Custom) keywords follow
+      /// after the builtin ones in the order of their numbers.
+      #[inline]
+      pub fn index(self) -> usize {
+        match self {
+          $(Keyword::$kw => Builtin::$kw as usize,)*
+          $($(Keyword::$skw => Builtin::$skw as usize,)*)*
+          Keyword::Encrypted => Builtin::Encrypted as usize,
+          Keyword::Unknown => Builtin::Unknown as usize,
+          Keyword::Custom(n) => Keyword::BUILTIN.len() + n as usize,
+        }
+      }
+
+      /// A minimal keyword line of a builtin keyword. As long as the
+      /// keywords are unique, [`parse`](crate::card::keyword::Keyword::parse)
+      /// turns it back into this keyword. `None` for
+      /// [`Unknown`](crate::card::keyword::Keyword::Unknown) and
+      /// [`Custom`](crate::card::keyword::Keyword::Custom) ones.
+      pub fn sample(self) -> Option<Vec<u8>> {
+        match self {
+          $(Keyword::$kw => Some($name.to_vec()),)*
+          $($(
+            Keyword::$skw => Some(subtyped_sample($parent, $col, $sub)),
+          )*)*
+          Keyword::Encrypted => Some(b"BEGIN_ENCRYPTED".to_vec()),
+          Keyword::Unknown | Keyword::Custom(_) => None,
+        }
+      }
+
+      /// The [`Card`](crate::card::Card) of a builtin keyword, `None` for
+      /// [`Custom`](crate::card::keyword::Keyword::Custom) ones
+      pub fn builtin_card(self) -> Option<&'static Card> {
+        use crate::carddata::*;
+
+        match self {
+          $(Keyword::$kw => Some(&$card),)*
+          $($(Keyword::$skw => Some(&$scard),)*)*
+          Keyword::Encrypted => Some(&ENCRYPTED),
+          Keyword::Unknown => Some(&UNKNOWN),
+          Keyword::Custom(_) => None,
+        }
+      }
+    }
+  };
+}
+
+keywords! {
+  plain {
+    // Node
+    Node => NODE: b"NODE  / ",
+    Cnode => CNODE: b"CNODE / ",
+    Mass => MASS: b"MASS  / ",
+    Nsmas => NSMAS: b"NSMAS / ",
+    Nsmas2 => NSMAS2: b"NSMAS2/ ",
+    // Element
+    Solid => SOLID: b"SOLID / ",
+    Hexa20 => HEXA20: b"HEXA20/ ",
+    Pent15 => PENT15: b"PENT15/ ",
+    Penta6 => PENTA6: b"PENTA6/ ",
+    Tetr10 => TETR10: b"TETR10/ ",
+    Tetr4 => TETR4: b"TETR4 / ",
+    Bshel => BSHEL: b"BSHEL / ",
+    Tshel => TSHEL: b"TSHEL / ",
+    Shell => SHELL: b"SHELL / ",
+    Shel6 => SHEL6: b"SHEL6 / ",
+    Shel8 => SHEL8: b"SHEL8 / ",
+    Membr => MEMBR: b"MEMBR / ",
+    Beam => BEAM: b"BEAM  / ",
+    Sprgbm => SPRGBM: b"SPRGBM/ ",
+    Bar => BAR: b"BAR   / ",
+    Spring => SPRING: b"SPRING/ ",
+    Joint => JOINT: b"JOINT / ",
+    Kjoin => KJOIN: b"KJOIN / ",
+    Mtojnt => MTOJNT: b"MTOJNT/ ",
+    Sphel => SPHEL: b"SPHEL / ",
+    Sphelo => SPHELO: b"SPHELO/ ",
+    Gap => GAP: b"GAP   / ",
+    Impma => IMPMA: b"IMPMA / ",
+    Musc1 => MUSC1: b"MUSC1 / ",
+    // Link
+    Elink => ELINK: b"ELINK / ",
+    Llink => LLINK: b"LLINK / ",
+    Slink => SLINK: b"SLINK / ",
+    Plink => PLINK: b"PLINK / ",
+    Tied => TIED: b"TIED  / ",
+    // Constraint
+    Mtoco => MTOCO: b"MTOCO / ",
+    Otmco => OTMCO: b"OTMCO / ",
+    Bounc => BOUNC: b"BOUNC / ",
+    Dis3d => DIS3D: b"DIS3D / ",
+    Vel3d => VEL3D: b"VEL3D / ",
+    Acfld => ACFLD: b"ACFLD / ",
+    // Thermal
+    Thmat => THMAT: b"THMAT / ",
+    Hflux => HFLUX: b"HFLUX / ",
+    Intem => INTEM: b"INTEM / ",
+    Tembc => TEMBC: b"TEMBC / ",
+    Htsurf => HTSURF: b"HTSURF/ ",
+    // Load case
+    Lcase => LCASE: b"LCASE / ",
+    EndLcase => END_LCASE: b"END_LCAS",
+    // Auxiliaries
+    Group => GROUP: b"GROUP / ",
+    Funct => FUNCT: b"FUNCT / ",
+    Looku => LOOKU: b"LOOKU / ",
+    Frame => FRAME: b"FRAME / ",
+    Trsfm => TRSFM: b"TRSFM / ",
+    // Material
+    Ply => PLY: b"PLY   / ",
+    Layer => LAYER: b"LAYER / ",
+    Rupmo => RUPMO: b"RUPMO / ",
+    Thvar => THVAR: b"THVAR / ",
+    // Output
+    Thnod => THNOD: b"THNOD / ",
+    Thele => THELE: b"THELE / ",
+    Thloc => THLOC: b"THLOC / ",
+  }
+  subtyped {
+    // The type of the part in columns 17-24
+    PART: b"PART  / " [16] {
+      // Part 3D
+      PartSolid => PARTSOLID: b"SOLID",
+      PartBshel => PARTBSHEL: b"BSHEL",
+      PartTetra => PARTTETRA: b"TETRA",
+      PartSphel => PARTSPHEL: b"SPHEL",
+      PartCos3d => PARTCOS3D: b"COS3D",
+      // Part 2D
+      PartTshel => PARTTSHEL: b"TSHEL",
+      PartShell => PARTSHELL: b"SHELL",
+      PartMembr => PARTMEMBR: b"MEMBR",
+      // Part 1D
+      PartBar => PARTBAR: b"BAR",
+      PartBeam => PARTBEAM: b"BEAM",
+      PartSpring => PARTSPRING: b"SPRING",
+      PartSprgbm => PARTSPRGBM: b"SPRGBM",
+      PartMbspr => PARTMBSPR: b"MBSPR",
+      PartJoint => PARTJOINT: b"JOINT",
+      PartKjoin => PARTKJOIN: b"KJOIN",
+      PartMbkjn => PARTMBKJN: b"MBKJN",
+      PartMtojnt => PARTMTOJNT: b"MTOJNT",
+      PartTied => PARTTIED: b"TIED",
+      PartSlink => PARTSLINK: b"SLINK",
+      PartElink => PARTELINK: b"ELINK",
+      PartLlink => PARTLLINK: b"LLINK",
+      PartPlink => PARTPLINK: b"PLINK",
+      PartGap => PARTGAP: b"GAP",
+    }
+    // The type of the rigid body in columns 25-32
+    RBODY: b"RBODY / " [24] {
+      Rbody0 => RBODY0: b"0",
+      Rbody1 => RBODY1: b"1",
+      Rbody2 => RBODY2: b"2",
+      Rbody3 => RBODY3: b"3",
+    }
+    // The material type in columns 17-24
+    MATER: b"MATER / " [16] {
+      Mater130 => MATER130: b"130",
+      Mater131 => MATER131: b"131",
+      Mater132 => MATER132: b"132",
+    }
+  }
+}
+
+/// The word in the 8 columns of `s` starting at `col`, if there's exactly one
+#[inline]
+fn subtype(s: &[u8], col: usize) -> Option<&[u8]> {
+  let mut words = s.get(col..col + 8)?.split(|&b| b == b' ');
+  let word = words.find(|w| !w.is_empty())?;
+
+  if words.all(<[u8]>::is_empty) {
+    Some(word)
+  } else {
+    None
+  }
+}
+
+/// A line starting with `parent`, with `sub` ending in the 8 columns starting
+/// at `col`
+fn subtyped_sample(parent: &[u8], col: usize, sub: &[u8]) -> Vec<u8> {
+  let mut line = parent.to_vec();
+  line.resize(col + 8 - sub.len(), b' ');
+  line.extend_from_slice(sub);
+  line
+}
+
+/// If `s` starts with the keyword of an
+/// [`Unknown`](crate::card::keyword::Keyword::Unknown) card. The control
+/// cards and `INCLU` lines look like that as well, e.g. `TITLE / `, but they
+/// are no cards. Needs at least 8 characters.
+#[inline]
+fn is_unknown(s: &[u8]) -> bool {
+  let (word, rest) = s.split_at(6);
+  let len = word.iter().position(|&b| b == b' ').unwrap_or(6);
+
+  rest.starts_with(b"/ ")
+    && word[0].is_ascii_uppercase()
+    && word[..len]
+      .iter()
+      .all(|&b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+    && word[len..].iter().all(|&b| b == b' ')
+    && !s.starts_with(b"INCLU ")
+    && !CONTROL_STARTS.iter().any(|c| s.starts_with(c))
 }
 
 impl Keyword {
@@ -96,229 +327,117 @@ impl Keyword {
   /// Parse a string to determine if it starts with the keyword of a card.
   #[inline]
   pub fn parse(s: &[u8]) -> Option<Self> {
-    use self::Keyword::*;
-    use byteorder::{BigEndian, ReadBytesExt};
-
     let len = s.len();
 
     if len == 0 || s[0] == b'#' || s[0] == b'$' || len < 8 {
-      None
-    } else {
-      let mut start = &s[0..8];
-      let num = match start.read_u64::<BigEndian>() {
-        Ok(n) => n,
-        Err(_) => return None,
+      return None;
+    }
+
+    let mut start = &s[0..8];
+    let num = start.read_u64::<BigEndian>().ok()?;
+
+    // b"BEGIN_EN", needs to be followed by b"CRYPTED"
+    if num == u64::from_be_bytes(*b"BEGIN_EN") {
+      return if s[8..].starts_with(b"CRYPTED") {
+        Some(Keyword::Encrypted)
+      } else {
+        None
       };
+    }
 
-      match num {
-        // Node
-        // NODE
-        5642803921800933152 => Some(Node),
-        // b"CNODE / "
-        4849901003360710432 => Some(Cnode),
-        // b"MASS  / "
-        5566822230893014816 => Some(Mass),
-        // b"NSMAS / "
-        5643939700988194592 => Some(Nsmas),
-        // b"NSMAS2/ "
-        5643939700989374240 => Some(Nsmas2),
-        // b"SOLID / "
-        6003100705867444000 => Some(Solid),
-        // b"HEXA20/ "
-        5207665581161983776 => Some(Hexa20),
-        // b"PENT15/ "
-        5784115419937058592 => Some(Pent15),
-        // b"PENTA6/ "
-        5784115420205559584 => Some(Penta6),
-        // b"TETR10/ "
-        6072352384568274720 => Some(Tetr10),
-        // b"TETR4 / "
-        6072352384617557792 => Some(Tetr4),
-        // b"BSHEL / "
-        4779243092037349152 => Some(Bshel),
-        // b"TSHEL / "
-        6076279784720052000 => Some(Tshel),
-        // b"SHELL / "
-        6001122697468194592 => Some(Shell),
-        // b"SHEL6 / "
-        6001122697099095840 => Some(Shel6),
-        // b"SHEL8 / "
-        6001122697132650272 => Some(Shel8),
-        // b"MEMBR / "
-        5567941461554507552 => Some(Membr),
-        // b"BEAM  / "
-        4775294779403546400 => Some(Beam),
-        // b"SPRGBM/ "
-        6003388769293381408 => Some(Sprgbm),
-        // b"BAR   / "
-        4774187377920847648 => Some(Bar),
-        // b"SPRING/ "
-        6003388778084249376 => Some(Spring),
-        // b"JOINT / "
-        5354579082734481184 => Some(Joint),
-        // b"KJOIN / "
-        5425235877383122720 => Some(Kjoin),
-        // b"MTOJNT/ "
-        5572165819524460320 => Some(Mtojnt),
-        // b"SPHEL / "
-        6003377765751992096 => Some(Sphel),
-        // b"SPHELO/ "
-        6003377765755072288 => Some(Sphelo),
-        // b"GAP   / "
-        5134473149087231776 => Some(Gap),
-        // b"IMPMA / "
-        5281966230710791968 => Some(Impma),
-        // b"ELINK / "
-        4993446687463714592 => Some(Elink),
-        // b"LLINK / "
-        5497849845729210144 => Some(Llink),
-        // b"SLINK / "
-        6002253003994705696 => Some(Slink),
-        // b"PLINK / "
-        5786080221880921888 => Some(Plink),
-        // b"TIED  / "
-        6073461731384897312 => Some(Tied),
-        // b"PART  / "
-        5782993917790138144 => {
-          if len < 24 {
-            None
-          } else {
-            let mut p = &s[16..24];
+    match Self::parse_builtin(num, s) {
+      Ok(Some(kw)) => Some(kw),
+      Ok(None) => {
+        let custom = if registry::has_custom() {
+          registry().keyword(num)
+        } else {
+          None
+        };
 
-            let num2 = match p.read_u64::<BigEndian>() {
-              Ok(n) => n,
-              Err(_) => return None,
-            };
-            match num2 {
-              // "   SOLID", "  SOLID ", " SOLID  ", "SOLID   "
-              2314885750653208900 | 2314941808515826720
-              | 2329292621345988640 | 6003100705867440160 => Some(PartSolid),
-              // "BSHEL   ", " BSHEL  ", "  BSHEL ", "   BSHEL"
-              4779243092037345312 | 2324511927541964832
-              | 2314923133930654752 | 2314885677705610572 => Some(PartBshel),
-              // "TETRA   ", " TETRA  ", "  TETRA ", "   TETRA"
-              6072352384835657760 | 2329563135716958240
-              | 2314942865212588320 | 2314885754780930625 => Some(PartTetra),
-              // "SPHEL   ", " SPHEL  ", "  SPHEL ", "   SPHEL"
-              6003377765751988256 | 2329293703611162656
-              | 2314941812743425056 | 2314885750669722956 => Some(PartSphel),
-              // "COS3D   ", " COS3D  ", "  COS3D ", "   COS3D"
-              4850186803352707104 | 2324789051414290464
-              | 2314924216445781024 | 2314885681934185284 => Some(PartCos3d),
-              // "TSHEL   ", " TSHEL  ", "  TSHEL ", "   TSHEL"
-              6076279784720048160 | 2329578477122756640
-              | 2314942925139954720 | 2314885755015021900 => Some(PartTshel),
-              // "SHELL   ", " SHELL  ", "  SHELL ", "   SHELL"
-              6001122697468190752 | 2329284894750679072
-              | 2314941778333813792 | 2314885750535310412 => Some(PartShell),
-              // "MEMBR   ", " MEMBR  ", "  MEMBR ", "   MEMBR"
-              5567941461554503712 | 2327592780547891232
-              | 2314935168512709152 | 2314885724715696722 => Some(PartMembr),
-              // "BAR     ", " BAR    ", "  BAR   ", "   BAR  ", "    BAR ",
-              // "     BAR"
-              4774187377920843808 | 2324492178658697248
-              | 2314923056786579488 | 2314885677404266528
-              | 2314885531391054368 | 2314885530820690258 => Some(PartBar),
-              // "BEAM    ", " BEAM   ", "  BEAM  ", "   BEAM ", "    BEAM"
-              4775294779403542560 | 2324496504445739040
-              | 2314923073684185120 | 2314885677470272800
-              | 2314885531391312205 => Some(PartBeam),
-              // "SPRING  ", " SPRING ", "  SPRING"
-              6003388778084245536 | 2329293746628085536
-              | 2314941812911459911 => Some(PartSpring),
-              // "SPRGBM  ", " SPRGBM ", "  SPRGBM"
-              6003388769293377568 | 2329293746593746208
-              | 2314941812911325773 => Some(PartSprgbm),
-              // "MBSPR   ", " MBSPR  ", "  MBSPR ", "   MBSPR"
-              5567103693823680544 | 2327589508017692704
-              | 2314935155729388064 | 2314885724665761874 => Some(PartMbspr),
-              // "JOINT   ", " JOINT  ", "  JOINT ", "   JOINT"
-              5354579082734477344 | 2326759333755625504
-              | 2314931912861176864 | 2314885711998307924 => Some(PartJoint),
-              // "KJOIN   ", " KJOIN  ", "  KJOIN ", "   KJOIN"
-              5425235877383118880 | 2327035336859721760
-              | 2314932990998302240 | 2314885716209781070 => Some(PartKjoin),
-              // "MTOJNT  ", " MTOJNT ", "  MTOJNT"
-              5572165819524456480 | 2327609281946211360
-              | 2314935232971296340 => Some(PartMtojnt),
-              // "MBKJN   ", " MBKJN  ", "  MBKJN ", "   MBKJN"
-              5567094871893745696 | 2327589473557028896
-              | 2314935155594776096 | 2314885724665236046 => Some(PartMbkjn),
-              // "TIED    ", " TIED   ", "  TIED  ", "   TIED ", "    TIED"
-              6073461731384893472 | 2329567469101916192
-              | 2314942882139873312 | 2314885754847052832
-              | 2314885531693565252 => Some(PartTied),
-              // "SLINK   ", " SLINK  ", "  SLINK ", "   SLINK"
-              6002253003994701856 | 2329289310010548256
-              | 2314941795580922656 | 2314885750602681931 => Some(PartSlink),
-              // "ELINK   ", " ELINK  ", "  ELINK ", "   ELINK"
-              4993446687463710752 | 2325348660336599072
-              | 2314926402418133792 | 2314885690473139787 => Some(PartElink),
-              // "LLINK   ", " LLINK  ", "  LLINK ", "   LLINK"
-              5497849845729206304 | 2327318985173573664
-              | 2314934098999528224 | 2314885720537910859 => Some(PartLlink),
-              // "PLINK   ", " PLINK  ", "  PLINK ", "   PLINK"
-              5786080221880918048 | 2328444885080416288
-              | 2314938497046039328 | 2314885737717780043 => Some(PartPlink),
-              // "GAP     ", " GAP    ", "  GAP   ", "   GAP  ", "    GAP ",
-              // "     GAP"
-              5134473149087227936 | 2325899544952315936
-              | 2314928554311163936 | 2314885698878971936
-              | 2314885531474939936 | 2314885530821017936 => Some(PartGap),
-              _ => None,
-            }
-          }
-        }
-        // Constraint
-        // b"MTOCO / "
-        5572165789473058592 => Some(Mtoco),
-        // b"OTMCO / "
-        5716278778525658912 => Some(Otmco),
-        // b"RBODY / "
-        5927387214544645920 => {
-          if len < 32 {
-            None
+        custom.or_else(|| {
+          if is_unknown(s) {
+            Some(Keyword::Unknown)
           } else {
-            let mut p = &s[24..32];
-            let num3 = match p.read_u64::<BigEndian>() {
-              Ok(n) => n,
-              Err(_) => return None,
-            };
-
-            match num3 {
-              // "0       ", " 0      ", "  0     ", "   0    ", "    0   ",
-              // "     0  ", "      0 ", "       0"
-              3467807035425300512 | 2319389130445824032
-              | 2314903123004497952 | 2314885599537930272
-              | 2314885531086888992 | 2314885530819502112
-              | 2314885530818457632 | 2314885530818453552 => Some(Rbody0),
-              // "1       ", " 1      ", "  1     ", "   1    ", "    1   ",
-              // "     1  ", "      1 ", "       1"
-              3539864629463228448 | 2319670605422534688
-              | 2314904222516125728 | 2314885603832897568
-              | 2314885531103666208 | 2314885530819567648
-              | 2314885530818457888 | 2314885530818453553 => Some(Rbody1),
-              // "2       ", " 2      ", "  2     ", "   2    ", "    2   ",
-              // "     2  ", "      2 ", "       2"
-              3611922223501156384 | 2319952080399245344
-              | 2314905322027753504 | 2314885608127864864
-              | 2314885531120443424 | 2314885530819633184
-              | 2314885530818458144 | 2314885530818453554 => Some(Rbody2),
-              // "3       ", " 3      ", "  3     ", "   3    ", "    3   ",
-              // "     3  ", "      3 ", "       3"
-              3683979817539084320 | 2320233555375956000
-              | 2314906421539381280 | 2314885612422832160
-              | 2314885531137220640 | 2314885530819698720
-              | 2314885530818458400 | 2314885530818453555 => Some(Rbody3),
-              _ => None,
-            }
+            None
           }
-        }
-        // Auxiliaries
-        // b"GROUP / "
-        5139257352618258208 => Some(Group),
-        _ => None,
+        })
       }
+      Err(()) => None,
+    }
+  }
+
+  /// The keyword of the cards starting with `name`, e.g. `"SHELL"`, matched
+  /// case insensitively. Returns `None` for names that aren't known.
+  pub fn from_name(name: &str) -> Option<Self> {
+    let padded = format!("{:<6}/ ", name.trim().to_uppercase());
+    Self::parse(padded.as_ref()).filter(|&k| k != Keyword::Unknown)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword;
+
+  #[test]
+  fn builtin_cards_match_keywords() {
+    for &kw in Keyword::BUILTIN {
+      let card = kw.builtin_card().unwrap();
+      assert_eq!(card.keyword(), kw);
+    }
+    assert!(Keyword::Custom(0).builtin_card().is_none());
+  }
+
+  #[test]
+  fn index_keywords() {
+    for (i, &kw) in Keyword::BUILTIN.iter().enumerate() {
+      assert_eq!(kw.index(), i);
     }
+    assert_eq!(Keyword::Custom(2).index(), Keyword::BUILTIN.len() + 2);
+  }
+
+  #[test]
+  fn parse_subtypes() {
+    assert_eq!(
+      Keyword::parse(b"PART  /        1   SHELL"),
+      Some(Keyword::PartShell)
+    );
+    assert_eq!(
+      Keyword::parse(b"PART  /        1SHELL   "),
+      Some(Keyword::PartShell)
+    );
+    assert_eq!(
+      Keyword::parse(b"PART  /        1     BAR"),
+      Some(Keyword::PartBar)
+    );
+    assert_eq!(Keyword::parse(b"PART  /        1   SHELX"), None);
+    assert_eq!(Keyword::parse(b"PART  /        1 SH ELL "), None);
+    assert_eq!(Keyword::parse(b"PART  /        1"), None);
+    assert_eq!(
+      Keyword::parse(b"RBODY /        1       0       2"),
+      Some(Keyword::Rbody2)
+    );
+    assert_eq!(
+      Keyword::parse(b"MATER /        1  131   "),
+      Some(Keyword::Mater131)
+    );
+    assert_eq!(Keyword::parse(b"BEGIN_ENCRYPTED"), Some(Keyword::Encrypted));
+    assert_eq!(Keyword::parse(b"BEGIN_EN"), None);
+  }
+
+  #[test]
+  fn parse_unknown() {
+    assert_eq!(Keyword::parse(b"SENSOR/ 1"), Some(Keyword::Unknown));
+    assert_eq!(Keyword::parse(b"XYZ   / "), Some(Keyword::Unknown));
+    assert_eq!(Keyword::parse(b"RUPMO2/        1"), Some(Keyword::Unknown));
+    // Control cards and lines not looking like a keyword
+    assert_eq!(Keyword::parse(b"TITLE / My deck"), None);
+    assert_eq!(Keyword::parse(b"OCTRL / "), None);
+    assert_eq!(Keyword::parse(b"INCLU / nodes.inc"), None);
+    assert_eq!(Keyword::parse(b"Xyz   / "), None);
+    assert_eq!(Keyword::parse(b"X Y   / "), None);
+    assert_eq!(Keyword::parse(b"XYZ   /1"), None);
+    assert_eq!(Keyword::parse(b"        XYZ"), None);
+    // Subtyped keywords with an unknown subtype are no keywords at all
+    assert_eq!(Keyword::parse(b"PART  /        1   SHELX"), None);
   }
 }
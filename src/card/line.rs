@@ -5,7 +5,12 @@ use std::{cmp, ops::Range};
 
 use atoi::atoi;
 
-use crate::card::{cell::Cell, ges::GesType, keyword::Keyword};
+use crate::card::{
+  cell::{Cell, FixedStr},
+  ges::GesType,
+  keyword::Keyword,
+  tabs,
+};
 
 /// A line (actually, zero or more lines) inside a card in a Pamcrash input
 /// file.
@@ -36,6 +41,27 @@ pub enum Line {
   /// A block that's entirely optional, starting with a line of a given string
   /// and ending in a line with another given string
   OptionalBlock(&'static [u8], &'static [u8]),
+  /// A block of lines nvimpam can't make sense of, e.g. encrypted data, ended
+  /// by a line starting with the given string. Lines inside the block are
+  /// neither highlighted nor checked for keywords.
+  Opaque(&'static [u8]),
+  /// The lines up to the next keyword line, whose layout nvimpam does not
+  /// know. They are not highlighted. This needs to be the last line of the
+  /// card.
+  Rest,
+  /// Alternative layouts of the whole card, e.g. a fixed and a long format.
+  /// The conditional is evaluated on the keyword line. If it's true, the
+  /// lines of the second slice are used, otherwise the ones of the first.
+  /// This needs to be the only line of the card, and both layouts need to
+  /// start with the line containing the keyword. See
+  /// [`Card::layout`](crate::card::Card::layout).
+  Layouts(&'static [Line], &'static [Line], Conditional),
+  /// The cards up to the one with the given keyword are nested inside of this
+  /// card, e.g. the boundary conditions of a load case. This needs to be the
+  /// last line of the card. The card gets a level 1 fold of its own lines,
+  /// and a level 2 fold containing the folds of the nested cards, see
+  /// [`Card::closed_by`](crate::card::Card::closed_by).
+  Contains(Keyword),
 }
 
 impl Line {
@@ -47,6 +73,7 @@ impl Line {
 
     match *self {
       Cells(s) | Provides(s, _) => s[0].keyword(),
+      Layouts(l, _, _) => l.first().and_then(Line::keyword),
       _ => None,
     }
   }
@@ -59,7 +86,33 @@ impl Line {
 
     match *self {
       Cells(s) | Provides(s, _) | Optional(s, _) | Repeat(s, _) => Some(s),
-      Ges(_) | Block(_, _) | OptionalBlock(_, _) => None,
+      Layouts(l, _, _) => l.first().and_then(Line::cells),
+      Ges(_)
+      | Block(_, _)
+      | OptionalBlock(_, _)
+      | Opaque(_)
+      | Rest
+      | Contains(_) => None,
+    }
+  }
+
+  /// Check if the line is a `NAME` line, i.e. starts with
+  /// [`FixedStr::Name`](crate::card::cell::FixedStr::Name).
+  #[inline]
+  pub fn is_name(&self) -> bool {
+    match *self {
+      Line::Cells(s) => s.first() == Some(&Cell::Fixed(FixedStr::Name)),
+      _ => false,
+    }
+  }
+
+  /// Check if the line is an [`Opaque`](crate::card::line::Line::Opaque)
+  /// block.
+  #[inline]
+  pub fn is_opaque(&self) -> bool {
+    match *self {
+      Line::Opaque(_) => true,
+      _ => false,
     }
   }
 
@@ -76,6 +129,50 @@ impl Line {
   */
 }
 
+/// Check if a `NAME` line is continued on the next line. That's the case if
+/// it ends in a continuation marker `->` followed by a number, e.g.
+/// `NAME first part of the title ->1`. The next line then needs to be another
+/// `NAME` line.
+pub fn name_continues(text: &[u8]) -> bool {
+  if !text.starts_with(b"NAME") {
+    return false;
+  }
+
+  let trimmed = match text.iter().rposition(|b| *b != b' ') {
+    Some(i) => &text[..=i],
+    None => return false,
+  };
+  let digits = trimmed
+    .iter()
+    .rev()
+    .take_while(|b| (**b as char).is_ascii_digit())
+    .count();
+
+  digits > 0 && trimmed[..trimmed.len() - digits].ends_with(b"->")
+}
+
+/// The number of bytes of a `NAME` line taken for its title
+const MAX_TITLE: usize = 256;
+
+/// The title given by the `NAME` line `text`, without the keyword and the
+/// continuation marker, see [`name_continues`](crate::card::line::name_continues).
+/// Only the first 256 bytes of the line are taken.
+pub fn name_title(text: &[u8]) -> String {
+  let end = cmp::min(text.len(), MAX_TITLE);
+  let mut title = String::from_utf8_lossy(text.get(4..end).unwrap_or(&[]))
+    .trim()
+    .to_string();
+
+  // The marker is cut off along with the rest of a long line
+  if end == text.len() && name_continues(text) {
+    if let Some(i) = title.rfind("->") {
+      title.truncate(i);
+    }
+  }
+
+  title.trim_end().to_string()
+}
+
 /// An enum to represent different conditionals on lines
 #[derive(Debug, PartialEq)]
 pub enum Conditional {
@@ -86,6 +183,8 @@ pub enum Conditional {
   Int(Range<u8>, u8),
   // Read a number from a given cell
   Number(Range<u8>),
+  // The integer at the cell given by the range is one of the given numbers
+  IntIn(Range<u8>, &'static [u8]),
 }
 
 /// An enum to represent the different results of conditionals
@@ -100,6 +199,9 @@ impl Conditional {
   pub fn evaluate(&self, line: &[u8]) -> CondResult {
     use self::CondResult::*;
 
+    let line = tabs::expanded(line);
+    let line = line.as_ref();
+
     match *self {
       Conditional::RelChar(idx, c) => Bool(line.get(idx as usize) == Some(&c)),
       Conditional::Int(ref r, b) => {
@@ -136,13 +238,55 @@ impl Conditional {
 
         Number(cell.get(firstdigit..).and_then(|s| atoi::<usize>(s)))
       }
+      Conditional::IntIn(ref r, bs) => {
+        let range = r.start as usize..cmp::min(line.len(), r.end as usize);
+
+        let cell = match line.get(range) {
+          Some(c) => c,
+          None => return Bool(false),
+        };
+
+        let firstdigit = cell
+          .iter()
+          .position(|b| *b >= b'0' && *b <= b'9')
+          .unwrap_or(0_usize);
+
+        Bool(
+          cell
+            .get(firstdigit..)
+            .and_then(|s| atoi::<usize>(s))
+            .map_or(false, |n| bs.iter().any(|b| *b as usize == n)),
+        )
+      }
     }
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::card::line::{CondResult::*, Conditional};
+  use crate::card::line::{
+    name_continues, name_title, CondResult::*, Conditional,
+  };
+
+  #[test]
+  fn name_continuation() {
+    assert!(name_continues(b"NAME MASS  / ->1     "));
+    assert!(name_continues(b"NAME a long title ->12"));
+    assert!(!name_continues(b"NAME a long title"));
+    assert!(!name_continues(b"NAME a long title ->"));
+    assert!(!name_continues(b"   a long title ->1"));
+  }
+
+  #[test]
+  fn name_titles() {
+    assert_eq!(name_title(b"NAME bumper_outer   "), "bumper_outer");
+    assert_eq!(name_title(b"NAME a long title ->12"), "a long title");
+    assert_eq!(name_title(b"NAME a -> b"), "a -> b");
+    assert_eq!(name_title(b"NAME"), "");
+
+    let long = format!("NAME {} ->1", "x".repeat(100_000));
+    assert_eq!(name_title(long.as_bytes()), "x".repeat(251));
+  }
 
   #[test]
   fn relchar_can_be_evaluated() {
@@ -162,4 +306,22 @@ mod tests {
     assert_eq!(Bool(false), cond1.evaluate(line.as_ref()));
   }
 
+  #[test]
+  fn intin_can_be_evaluated() {
+    let cond = Conditional::IntIn(16..24, &[6, 8]);
+
+    assert_eq!(
+      Bool(true),
+      cond.evaluate(b"PLY   /        1       6              0.")
+    );
+    assert_eq!(
+      Bool(true),
+      cond.evaluate(b"PLY   /        1       8              0.")
+    );
+    assert_eq!(
+      Bool(false),
+      cond.evaluate(b"PLY   /        1       0              0.")
+    );
+    assert_eq!(Bool(false), cond.evaluate(b"PLY   /        1"));
+  }
 }
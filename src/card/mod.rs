@@ -9,13 +9,26 @@
 //!
 //! The definitions of the global static values can be found in the
 //! [`carddata`](crate::carddata) module.
+pub mod align;
 pub mod cell;
+pub mod conversion;
+pub mod custom;
 pub mod ges;
 pub mod keyword;
 pub mod line;
+pub mod number;
+pub mod parse;
+pub mod registry;
+pub mod section;
+pub mod selfcheck;
+pub mod tabs;
+pub mod value;
+pub mod version;
+pub mod xref;
 
-use self::{keyword::Keyword, line::Line};
-use crate::carddata::*;
+use self::{
+  cell::cell_ranges, keyword::Keyword, line::Line, registry::registry,
+};
 
 /// A card consists of severals [`Line`](crate::card::line::Line). If `ownfold`
 /// is true, than each card of this type will get an own fold. Otherwise, all
@@ -38,82 +51,111 @@ impl Card {
       ))
     })
   }
+
+  /// Return the lines of the card whose keyword line is `text`. If the card
+  /// has [`Layouts`](crate::card::line::Line::Layouts), the conditional
+  /// decides which ones to use. Otherwise, these are simply the
+  /// [`lines`](crate::card::Card::lines).
+  pub fn layout(&self, text: &[u8]) -> &'static [Line] {
+    use self::line::CondResult;
+
+    match self.lines.first() {
+      Some(Line::Layouts(fixed, long, ref cond)) => {
+        if cond.evaluate(text) == CondResult::Bool(true) {
+          long
+        } else {
+          fixed
+        }
+      }
+      _ => self.lines,
+    }
+  }
+
+  /// If the card is a container, the keyword of the card closing it, see
+  /// [`Contains`](crate::card::line::Line::Contains)
+  pub fn closed_by(&self) -> Option<Keyword> {
+    match self.lines.last() {
+      Some(Line::Contains(kw)) => Some(*kw),
+      _ => None,
+    }
+  }
+
+  /// Return the (end-exclusive) column ranges of the cells on the line
+  /// `offset` lines after the keyword line `text` of the card. This is only
+  /// known if the line and all lines before it always occur exactly once, i.e.
+  /// are [`Cells`](crate::card::line::Line::Cells) or
+  /// [`Provides`](crate::card::line::Line::Provides) lines. Otherwise, `None`
+  /// is returned.
+  pub fn cell_boundaries(
+    &self,
+    text: &[u8],
+    offset: usize,
+  ) -> Option<Vec<(u8, u8)>> {
+    let lines = self.layout(text);
+    let fixed = lines
+      .get(..=offset)?
+      .iter()
+      .all(|l| matches!(*l, Line::Cells(_) | Line::Provides(_, _)));
+
+    if !fixed {
+      return None;
+    }
+
+    lines[offset].cells().map(cell_ranges)
+  }
 }
 
 impl<'a> From<&'a Keyword> for &'static Card {
   fn from(kw: &'a Keyword) -> &'static Card {
-    match *kw {
-      // Node
-      Keyword::Node => &NODE,
-      Keyword::Cnode => &CNODE,
-      Keyword::Mass => &MASS,
-      Keyword::Nsmas => &NSMAS,
-      Keyword::Nsmas2 => &NSMAS2,
-      // Element
-      Keyword::Solid => &SOLID,
-      Keyword::Hexa20 => &HEXA20,
-      Keyword::Pent15 => &PENT15,
-      Keyword::Penta6 => &PENTA6,
-      Keyword::Tetr10 => &TETR10,
-      Keyword::Tetr4 => &TETR4,
-      Keyword::Bshel => &BSHEL,
-      Keyword::Tshel => &TSHEL,
-      Keyword::Shell => &SHELL,
-      Keyword::Shel6 => &SHEL6,
-      Keyword::Shel8 => &SHEL8,
-      Keyword::Membr => &MEMBR,
-      Keyword::Beam => &BEAM,
-      Keyword::Sprgbm => &SPRGBM,
-      Keyword::Bar => &BAR,
-      Keyword::Spring => &SPRING,
-      Keyword::Joint => &JOINT,
-      Keyword::Kjoin => &KJOIN,
-      Keyword::Mtojnt => &MTOJNT,
-      Keyword::Sphel => &SPHEL,
-      Keyword::Sphelo => &SPHELO,
-      Keyword::Gap => &GAP,
-      Keyword::Impma => &IMPMA,
-      // Link
-      Keyword::Elink => &ELINK,
-      Keyword::Llink => &LLINK,
-      Keyword::Slink => &SLINK,
-      Keyword::Plink => &PLINK,
-      Keyword::Tied => &TIED,
-      // Part 3D
-      Keyword::PartSolid => &PARTSOLID,
-      Keyword::PartBshel => &PARTBSHEL,
-      Keyword::PartTetra => &PARTTETRA,
-      Keyword::PartSphel => &PARTSPHEL,
-      Keyword::PartCos3d => &PARTCOS3D,
-      // Part 2D
-      Keyword::PartTshel => &PARTTSHEL,
-      Keyword::PartShell => &PARTSHELL,
-      Keyword::PartMembr => &PARTMEMBR,
-      // Part 1D
-      Keyword::PartBar => &PARTBAR,
-      Keyword::PartBeam => &PARTBEAM,
-      Keyword::PartSpring => &PARTSPRING,
-      Keyword::PartSprgbm => &PARTSPRGBM,
-      Keyword::PartMbspr => &PARTMBSPR,
-      Keyword::PartJoint => &PARTJOINT,
-      Keyword::PartKjoin => &PARTKJOIN,
-      Keyword::PartMbkjn => &PARTMBKJN,
-      Keyword::PartMtojnt => &PARTMTOJNT,
-      Keyword::PartTied => &PARTTIED,
-      Keyword::PartSlink => &PARTSLINK,
-      Keyword::PartElink => &PARTELINK,
-      Keyword::PartLlink => &PARTLLINK,
-      Keyword::PartPlink => &PARTPLINK,
-      Keyword::PartGap => &PARTGAP,
-      // Constraint
-      Keyword::Mtoco => &MTOCO,
-      Keyword::Otmco => &OTMCO,
-      Keyword::Rbody0 => &RBODY0,
-      Keyword::Rbody1 => &RBODY1,
-      Keyword::Rbody2 => &RBODY2,
-      Keyword::Rbody3 => &RBODY3,
-      // Auxiliaries
-      Keyword::Group => &GROUP,
+    match kw.builtin_card() {
+      Some(card) if !registry::has_custom() => card,
+      _ => registry().card(*kw),
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::carddata::{MASS, NODE, SHELL};
+
+  const LONG_NODE: &str = "NODE  /                1                      0.\
+                           \x20                    0.5        &";
+
+  #[test]
+  fn cell_boundaries() {
+    assert_eq!(
+      NODE.cell_boundaries(b"NODE  /", 0),
+      Some(vec![(0, 8), (8, 16), (16, 32), (32, 48), (48, 64)])
+    );
+    assert_eq!(NODE.cell_boundaries(b"NODE  /", 1), None);
+    assert_eq!(
+      MASS.cell_boundaries(b"MASS  /", 2),
+      Some(vec![(0, 16), (16, 32), (32, 48)])
+    );
+    // Follows an optional line
+    assert_eq!(MASS.cell_boundaries(b"MASS  /", 5), None);
+  }
+
+  #[test]
+  fn long_format() {
+    assert_eq!(LONG_NODE.len(), 81);
+    assert_eq!(NODE.layout(LONG_NODE.as_ref()).len(), 2);
+    assert_eq!(
+      NODE.cell_boundaries(LONG_NODE.as_ref(), 0),
+      Some(vec![
+        (0, 8),
+        (8, 24),
+        (24, 48),
+        (48, 72),
+        (72, 80),
+        (80, 81)
+      ])
+    );
+    assert_eq!(
+      NODE.cell_boundaries(LONG_NODE.as_ref(), 1),
+      Some(vec![(0, 24), (24, 48)])
+    );
+    assert_eq!(NODE.layout(b"NODE  /        1").len(), 1);
+    assert_eq!(SHELL.layout(b"SHELL /     3129").len(), 1);
+  }
+}
@@ -0,0 +1,238 @@
+//! Reading the numbers of cells the way the solver does, and showing them in
+//! a readable way. Preprocessors often write floats in a packed E-format to
+//! fit them into their cell, e.g. `1.5-3` for `1.5E-3`, or use a Fortran `D`
+//! exponent. Those are shown exponent-normalized (`1.5E-03`) and in
+//! engineering notation (`1.5E-03`, but `12.345E+03` for `12345.`).
+//!
+//! The values can be scaled to another unit system by the
+//! [`Units`](crate::card::number::Units) given for the names of the cells.
+use neovim_lib::Value;
+
+use crate::args::FromValue;
+
+/// The number of significant digits shown
+const DIGITS: usize = 10;
+
+/// Parse the content `s` of a numeric cell. Besides the usual formats, this
+/// accepts a Fortran `D` exponent and the packed E-format, where the `E` is
+/// left out in front of the sign of the exponent.
+pub fn parse(s: &str) -> Option<f64> {
+  let s = s.trim();
+
+  if s.is_empty() {
+    return None;
+  }
+  if let Ok(f) = s.parse() {
+    return Some(f);
+  }
+
+  let fortran = s.replace(&['D', 'd'][..], "E");
+  if let Ok(f) = fortran.parse() {
+    return Some(f);
+  }
+
+  // The sign of the exponent follows a digit or the decimal point
+  let bytes = s.as_bytes();
+  let sign = (1..bytes.len()).rev().find(|&i| {
+    (bytes[i] == b'+' || bytes[i] == b'-')
+      && (bytes[i - 1].is_ascii_digit() || bytes[i - 1] == b'.')
+  })?;
+
+  format!("{}E{}", &s[..sign], &s[sign..]).parse().ok()
+}
+
+/// The significant digits of `f` without the decimal point, and its decimal
+/// exponent, e.g. `("15", -3)` for `0.0015`
+fn digits(f: f64) -> (String, i32) {
+  let formatted = format!("{:.*E}", DIGITS - 1, f.abs());
+  let (mantissa, exp) = formatted.split_at(formatted.find('E').unwrap_or(0));
+  let digits = mantissa.replace('.', "");
+  let digits = digits.trim_end_matches('0');
+
+  (
+    if digits.is_empty() { "0" } else { digits }.to_string(),
+    exp[1..].parse().unwrap_or(0),
+  )
+}
+
+/// Format `f` with `int` digits before the decimal point, followed by the
+/// exponent `exp`
+fn format_digits(f: f64, int: usize, exp: i32) -> String {
+  let (mut digits, _) = digits(f);
+  while digits.len() <= int {
+    digits.push('0');
+  }
+  let sign = if f.is_sign_negative() && f != 0. {
+    "-"
+  } else {
+    ""
+  };
+
+  format!(
+    "{}{}.{}E{}{:02}",
+    sign,
+    &digits[..int],
+    &digits[int..],
+    if exp < 0 { '-' } else { '+' },
+    exp.abs()
+  )
+}
+
+/// Format `f` with a single digit before the decimal point, e.g. `1.5E-03`
+pub fn normalized(f: f64) -> String {
+  if f == 0. || !f.is_finite() {
+    return if f.is_finite() {
+      "0.0E+00".to_string()
+    } else {
+      f.to_string()
+    };
+  }
+
+  let (_, exp) = digits(f);
+  format_digits(f, 1, exp)
+}
+
+/// Format `f` with an exponent divisible by 3 and 1 to 3 digits before the
+/// decimal point, e.g. `12.345E+03`
+pub fn engineering(f: f64) -> String {
+  if f == 0. || !f.is_finite() {
+    return normalized(f);
+  }
+
+  let (_, exp) = digits(f);
+  let eng = exp - exp.rem_euclid(3);
+  format_digits(f, (exp - eng) as usize + 1, eng)
+}
+
+/// A unit to show the values of a cell in. The values in the deck are
+/// multiplied by `factor` to get the value in this unit.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Unit {
+  pub factor: f64,
+  pub name: String,
+}
+
+/// The units of the cells, keyed by the name of the cell in its `$#` header.
+/// The unit named `*` applies to all other cells.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Units(pub Vec<(String, Unit)>);
+
+impl Units {
+  /// The unit of the cell named `name`, compared case insensitively
+  pub fn get(&self, name: Option<&str>) -> Option<&Unit> {
+    let find = |n: &str| {
+      self
+        .0
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(n))
+        .map(|(_, u)| u)
+    };
+
+    name.and_then(find).or_else(|| find("*"))
+  }
+}
+
+impl FromValue for Units {
+  fn name() -> String {
+    "a map of [factor, unit] lists".to_string()
+  }
+
+  fn from_value(value: Value) -> Result<Self, String> {
+    let unit = |v: Value| match v {
+      Value::Array(ref a) if a.len() == 2 => {
+        let factor = a[0].as_f64().or_else(|| a[0].as_i64().map(|i| i as f64));
+        match (factor, a[1].as_str()) {
+          (Some(factor), Some(name)) => Ok(Unit {
+            factor,
+            name: name.to_string(),
+          }),
+          _ => Err(v.to_string()),
+        }
+      }
+      v => Err(v.to_string()),
+    };
+
+    match value {
+      Value::Map(m) => m
+        .into_iter()
+        .map(|(k, v)| Ok((String::from_value(k)?, unit(v)?)))
+        .collect::<Result<_, _>>()
+        .map(Units),
+      // An empty lua table is sent as an array
+      Value::Array(ref v) if v.is_empty() => Ok(Units::default()),
+      v => Err(v.to_string()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::Value;
+
+  use crate::{
+    args::FromValue,
+    card::number::{engineering, normalized, parse, Unit, Units},
+  };
+
+  #[test]
+  fn parse_numbers() {
+    assert_eq!(parse(" 0.5 "), Some(0.5));
+    assert_eq!(parse("1.5E-3"), Some(1.5e-3));
+    assert_eq!(parse("1.5D-3"), Some(1.5e-3));
+    assert_eq!(parse("1.5-3"), Some(1.5e-3));
+    assert_eq!(parse("-2.+5"), Some(-2e5));
+    assert_eq!(parse("7"), Some(7.));
+    assert_eq!(parse("-"), None);
+    assert_eq!(parse("x.5"), None);
+    assert_eq!(parse("<var>"), None);
+    assert_eq!(parse(""), None);
+  }
+
+  #[test]
+  fn format_numbers() {
+    assert_eq!(normalized(0.0015), "1.5E-03");
+    assert_eq!(engineering(0.0015), "1.5E-03");
+    assert_eq!(normalized(12345.), "1.2345E+04");
+    assert_eq!(engineering(12345.), "12.345E+03");
+    assert_eq!(engineering(-210000.), "-210.0E+03");
+    assert_eq!(engineering(7.85e-9), "7.85E-09");
+    assert_eq!(normalized(0.), "0.0E+00");
+    assert_eq!(engineering(0.), "0.0E+00");
+    assert_eq!(normalized(1.), "1.0E+00");
+    // Rounded to 10 significant digits
+    assert_eq!(normalized(0.1 + 0.2), "3.0E-01");
+  }
+
+  #[test]
+  fn units_by_name() {
+    let value = Value::from(vec![
+      (
+        Value::from("X"),
+        Value::from(vec![Value::from(1000), Value::from("mm")]),
+      ),
+      (
+        Value::from("*"),
+        Value::from(vec![Value::from(1.), Value::from("m")]),
+      ),
+    ]);
+    let units = Units::from_value(value).unwrap();
+
+    let mm = Unit {
+      factor: 1000.,
+      name: "mm".to_string(),
+    };
+    assert_eq!(units.get(Some("x")), Some(&mm));
+    assert_eq!(units.get(Some("Y")).map(|u| u.name.as_str()), Some("m"));
+    assert_eq!(units.get(None).map(|u| u.name.as_str()), Some("m"));
+
+    assert_eq!(
+      Units::from_value(Value::Array(vec![])),
+      Ok(Units::default())
+    );
+    assert!(Units::from_value(Value::from(vec![(
+      Value::from("X"),
+      Value::from(1000)
+    )]))
+    .is_err());
+  }
+}
@@ -0,0 +1,233 @@
+//! Typed access to the cells of a line of a card. A
+//! [`CellLine`](crate::card::parse::CellLine) pairs the text of a line
+//! with the [`Cells`](crate::card::cell::Cell) of its
+//! [`CardLine`](crate::card::line::Line), so the content of a cell can be
+//! extracted as an ID, coordinate or string by its index. Each cell is
+//! parsed on its own, so an invalid cell does not affect the others.
+use std::{borrow::Cow, cmp, fmt};
+
+use failure::Fail;
+
+use crate::card::{
+  cell::{cell_ranges, Cell},
+  line::Line as CardLine,
+  tabs,
+};
+
+/// The ways extracting the content of a cell can fail. Indices start at 0.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CellError {
+  /// The line has no cell with the index
+  Missing { index: usize },
+  /// The cell is blank, or beyond the end of the line
+  Blank { index: usize },
+  /// The cell does not have the type asked for
+  Type {
+    index: usize,
+    expected: &'static str,
+    got: &'static str,
+  },
+  /// The cell has the right type, but its content can't be parsed
+  Invalid { index: usize, content: String },
+}
+
+impl fmt::Display for CellError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CellError::Missing { index } => write!(f, "no cell {}", index),
+      CellError::Blank { index } => write!(f, "cell {} is blank", index),
+      CellError::Type {
+        index,
+        expected,
+        got,
+      } => write!(f, "cell {} should be {}, got {}", index, expected, got),
+      CellError::Invalid { index, content } => {
+        write!(f, "cell {} has the invalid content '{}'", index, content)
+      }
+    }
+  }
+}
+
+impl Fail for CellError {}
+
+/// The name of the type of a cell, e.g. `"integer"`
+pub fn kind(cell: &Cell) -> &'static str {
+  use self::Cell::*;
+
+  match *cell {
+    Kw(_) => "keyword",
+    Fixed(_) => "fixed",
+    Integer(_) | IntegerorBlank(_) => "integer",
+    Float(_) => "float",
+    Blank(_) => "blank",
+    Cont => "continuation",
+    Str(_) => "string",
+    Binary(_) => "binary",
+  }
+}
+
+/// A line of a card along with the cells of its layout. The text is
+/// [`expanded`](crate::card::tabs::expanded) according to the tab policy.
+#[derive(Debug)]
+pub struct CellLine<'a> {
+  text: Cow<'a, [u8]>,
+  cells: &'static [Cell],
+  ranges: Vec<(u8, u8)>,
+}
+
+impl<'a> CellLine<'a> {
+  /// Pair the line `text` with the cells of `cardline`. Returns `None` if the
+  /// layout of the line is not made of cells, e.g. for a
+  /// [`GES`](crate::card::line::Line::Ges).
+  pub fn new(cardline: &CardLine, text: &'a [u8]) -> Option<Self> {
+    cardline.cells().map(|c| Self::from_cells(c, text))
+  }
+
+  /// Pair the line `text` with the cells `cells`
+  pub fn from_cells(cells: &'static [Cell], text: &'a [u8]) -> Self {
+    CellLine {
+      text: tabs::expanded(text),
+      cells,
+      ranges: cell_ranges(cells),
+    }
+  }
+
+  /// The cells of the line
+  pub fn cells(&self) -> &'static [Cell] {
+    self.cells
+  }
+
+  /// The (end-exclusive) column ranges of the cells
+  pub fn ranges(&self) -> &[(u8, u8)] {
+    &self.ranges
+  }
+
+  /// The (end-exclusive) column range of the cell `index`
+  pub fn range(&self, index: usize) -> Result<(u8, u8), CellError> {
+    self
+      .ranges
+      .get(index)
+      .cloned()
+      .ok_or(CellError::Missing { index })
+  }
+
+  /// The text of the cell `index`. Cells beyond the end of the line are
+  /// empty, and the last one might be shorter than its width.
+  pub fn raw(&self, index: usize) -> Result<&[u8], CellError> {
+    let (start, end) = self.range(index)?;
+    let start = cmp::min(start as usize, self.text.len());
+    let end = cmp::min(end as usize, self.text.len());
+
+    Ok(&self.text[start..end])
+  }
+
+  /// The text of the cell `index` without surrounding whitespace. This is
+  /// empty for blank cells.
+  pub fn str(&self, index: usize) -> Result<String, CellError> {
+    let raw = self.raw(index)?;
+
+    Ok(String::from_utf8_lossy(raw).trim().to_string())
+  }
+
+  /// The trimmed content of the cell `index`, if it is not blank and its type
+  /// is named `expected` by [`kind`](crate::card::parse::kind)
+  fn content(
+    &self,
+    index: usize,
+    expected: &'static str,
+  ) -> Result<String, CellError> {
+    let content = self.str(index)?;
+    let got = kind(&self.cells[index]);
+
+    if got != expected {
+      return Err(CellError::Type {
+        index,
+        expected,
+        got,
+      });
+    }
+
+    if content.is_empty() {
+      return Err(CellError::Blank { index });
+    }
+
+    Ok(content)
+  }
+
+  /// The content of the integer cell `index`, e.g. an ID
+  pub fn int(&self, index: usize) -> Result<i64, CellError> {
+    let content = self.content(index, "integer")?;
+
+    content
+      .parse()
+      .map_err(|_| CellError::Invalid { index, content })
+  }
+
+  /// The content of the float cell `index`, e.g. a coordinate. Pyvars like
+  /// `<var>` don't have a value yet, so they're invalid here.
+  pub fn float(&self, index: usize) -> Result<f64, CellError> {
+    let content = self.content(index, "float")?;
+
+    content
+      .parse()
+      .map_err(|_| CellError::Invalid { index, content })
+  }
+
+  /// The index of the first cell of the type named `name`, see
+  /// [`kind`](crate::card::parse::kind)
+  pub fn position(&self, name: &str) -> Option<usize> {
+    self.cells.iter().position(|c| kind(c) == name)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    card::{
+      line::Line as CardLine,
+      parse::{CellError, CellLine},
+    },
+    carddata::{NODE, SHELL},
+  };
+
+  #[test]
+  fn node_cells() {
+    let text =
+      b"NODE  /        1              0.            -0.5              x.";
+    let line = CellLine::new(&NODE.lines[0], text).unwrap();
+
+    assert_eq!(line.position("integer"), Some(1));
+    assert_eq!(line.int(1), Ok(1));
+    assert_eq!(line.float(2), Ok(0.));
+    assert_eq!(line.float(3), Ok(-0.5));
+    assert_eq!(line.str(0), Ok("NODE  /".to_string()));
+    assert_eq!(line.range(2), Ok((16, 32)));
+    assert_eq!(
+      line.float(4),
+      Err(CellError::Invalid {
+        index: 4,
+        content: "x.".to_string()
+      })
+    );
+    assert_eq!(
+      line.int(2),
+      Err(CellError::Type {
+        index: 2,
+        expected: "integer",
+        got: "float"
+      })
+    );
+    assert_eq!(line.int(9), Err(CellError::Missing { index: 9 }));
+  }
+
+  #[test]
+  fn short_lines() {
+    let text = b"SHELL /     3129       1";
+    let line = CellLine::new(&SHELL.lines[0], text).unwrap();
+
+    assert_eq!(line.int(2), Ok(1));
+    assert_eq!(line.int(3), Err(CellError::Blank { index: 3 }));
+    assert_eq!(line.raw(4), Ok(&b""[..]));
+    assert!(CellLine::new(&CardLine::Opaque(b"END"), text).is_none());
+  }
+}
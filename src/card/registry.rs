@@ -0,0 +1,159 @@
+//! The registry of the cards of all keywords, so the
+//! [`Card`](crate::card::Card) of a keyword is found by indexing a table,
+//! no matter if it's a builtin card or a custom one loaded at runtime, see
+//! [`card::custom`](crate::card::custom).
+//!
+//! The builtin cards are statics, so they don't need to be constructed at
+//! startup. The registry only holds references to them, and is built on the
+//! first lookup. Registering the custom cards builds it anew.
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+  },
+};
+
+use lazy_static::lazy_static;
+
+use crate::card::{
+  custom::{custom_cards, CustomCard},
+  keyword::Keyword,
+  Card,
+};
+
+/// The cards of all keywords, by their
+/// [`index`](crate::card::keyword::Keyword::index)
+#[derive(Debug)]
+pub struct Registry {
+  cards: Vec<&'static Card>,
+  custom_cards: Arc<Vec<CustomCard>>,
+  custom: Vec<Option<usize>>,
+  keywords: HashMap<u64, Keyword>,
+}
+
+lazy_static! {
+  /// The current registry, see
+  /// [`registry`](crate::card::registry::registry)
+  static ref REGISTRY: RwLock<Arc<Registry>> =
+    RwLock::new(Arc::new(Registry::build()));
+}
+
+/// If custom cards were registered, see
+/// [`has_custom`](crate::card::registry::has_custom)
+static HAS_CUSTOM: AtomicBool = AtomicBool::new(false);
+
+impl Registry {
+  /// Build the registry of the builtin cards and the custom cards
+  /// registered so far. Custom cards for builtin keywords replace the
+  /// builtin ones.
+  fn build() -> Self {
+    let custom = custom_cards();
+    let new = custom
+      .iter()
+      .filter(|c| matches!(c.keyword, Keyword::Custom(_)))
+      .count();
+    let len = Keyword::BUILTIN.len() + new;
+
+    let mut cards: Vec<&'static Card> = Keyword::BUILTIN
+      .iter()
+      .filter_map(|kw| kw.builtin_card())
+      .collect();
+    cards.extend(custom.iter().filter_map(|c| match c.keyword {
+      Keyword::Custom(_) => Some(c.card),
+      _ => None,
+    }));
+
+    let mut registry = Registry {
+      cards,
+      custom_cards: Arc::clone(&custom),
+      custom: vec![None; len],
+      keywords: HashMap::with_capacity(new),
+    };
+
+    for (i, c) in custom.iter().enumerate() {
+      let index = c.keyword.index();
+      registry.cards[index] = c.card;
+      registry.custom[index] = Some(i);
+      if let Keyword::Custom(_) = c.keyword {
+        let _ = registry.keywords.insert(c.hash, c.keyword);
+      }
+    }
+
+    registry
+  }
+
+  /// The card of `kw`
+  #[inline]
+  pub fn card(&self, kw: Keyword) -> &'static Card {
+    // Custom keywords only exist once their card is registered
+    self.cards[kw.index()]
+  }
+
+  /// The custom card for `kw`, if there is one
+  #[inline]
+  pub fn custom(&self, kw: Keyword) -> Option<&CustomCard> {
+    self
+      .custom
+      .get(kw.index())
+      .and_then(|i| i.map(|i| &self.custom_cards[i]))
+  }
+
+  /// The new keyword of the custom card whose first 8 characters form
+  /// `hash`. Custom cards of builtin keywords are found by their builtin
+  /// keyword.
+  #[inline]
+  pub fn keyword(&self, hash: u64) -> Option<Keyword> {
+    self.keywords.get(&hash).cloned()
+  }
+
+  /// The number of keywords in the registry
+  pub fn len(&self) -> usize {
+    self.cards.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.cards.is_empty()
+  }
+}
+
+/// The registry of all cards, built on the first call
+#[inline]
+pub fn registry() -> Arc<Registry> {
+  Arc::clone(&REGISTRY.read().unwrap())
+}
+
+/// Build the registry anew after the custom cards were registered. Callers
+/// still holding the old one keep it until they drop it.
+pub(crate) fn rebuild() {
+  let new = Arc::new(Registry::build());
+  let custom = !new.custom_cards.is_empty();
+  *REGISTRY.write().unwrap() = new;
+  HAS_CUSTOM.store(custom, Ordering::Release);
+}
+
+/// If any custom cards were registered. Without them, the builtin cards are
+/// all there is, and lookups can skip the registry.
+#[inline]
+pub fn has_custom() -> bool {
+  HAS_CUSTOM.load(Ordering::Acquire)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::card::{keyword::Keyword, registry::registry};
+
+  #[test]
+  fn registry_holds_builtin_cards() {
+    let registry = registry();
+
+    assert!(registry.len() >= Keyword::BUILTIN.len());
+    for &kw in Keyword::BUILTIN {
+      let card = registry.card(kw);
+      if registry.custom(kw).is_none() {
+        assert!(std::ptr::eq(card, kw.builtin_card().unwrap()));
+      }
+    }
+    assert_eq!(registry.keyword(u64::from_be_bytes(*b"NODE  / ")), None);
+  }
+}
@@ -0,0 +1,271 @@
+//! The sections of a Pamcrash input file, and rules governing their order.
+//! Some solver versions are picky about the order, e.g. they require the
+//! control cards to come before all entities, and `ENDDATA` to be the very
+//! last line.
+use crate::{
+  card::{
+    custom,
+    keyword::Keyword::{self, *},
+  },
+  diagnostics::{Diagnostic, Severity},
+  linenr::LineNr,
+};
+
+/// The lines that start a control card, which don't have a
+/// [`Keyword`](crate::card::keyword::Keyword) of their own
+pub(crate) const CONTROL_STARTS: [&[u8]; 9] = [
+  b"INPUTVERSION",
+  b"ANALYSIS",
+  b"SOLVER",
+  b"UNIT",
+  b"TITLE",
+  b"OCTRL",
+  b"CTRL",
+  b"RUNEND",
+  b"SIGNAL",
+];
+
+/// A section of a Pamcrash input file. Apart from
+/// [`Control`](crate::card::section::Section::Control) and
+/// [`EndData`](crate::card::section::Section::EndData), these correspond to
+/// the modules in [`carddata`](crate::carddata).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Section {
+  Control,
+  Node,
+  Element,
+  Link,
+  Part,
+  Constraint,
+  Auxiliaries,
+  Material,
+  EndData,
+}
+
+impl Section {
+  /// Determine the section a line belongs to, if it starts one.
+  pub fn parse(text: &[u8], keyword: Option<Keyword>) -> Option<Self> {
+    if keyword == Some(Encrypted) {
+      // We can't know what's inside
+      None
+    } else if let Some(kw) = keyword.filter(|&k| k != Unknown) {
+      Some(kw.into())
+    } else if text.starts_with(b"ENDDATA") {
+      Some(Section::EndData)
+    } else if CONTROL_STARTS.iter().any(|s| text.starts_with(s)) {
+      Some(Section::Control)
+    } else {
+      None
+    }
+  }
+
+  /// Parse the name of a section as used in the rule configuration, e.g.
+  /// `"control"` or `"enddata"`.
+  pub fn from_name(name: &str) -> Option<Self> {
+    use self::Section::*;
+
+    match name.to_lowercase().as_str() {
+      "control" => Some(Control),
+      "node" => Some(Node),
+      "element" => Some(Element),
+      "link" => Some(Link),
+      "part" => Some(Part),
+      "constraint" => Some(Constraint),
+      "auxiliaries" => Some(Auxiliaries),
+      "material" => Some(Material),
+      "enddata" => Some(EndData),
+      _ => None,
+    }
+  }
+}
+
+impl From<Keyword> for Section {
+  fn from(kw: Keyword) -> Self {
+    match kw {
+      Node | Cnode | Mass | Nsmas | Nsmas2 => Section::Node,
+      Solid | Hexa20 | Pent15 | Penta6 | Tetr10 | Tetr4 | Bshel | Tshel
+      | Shell | Shel6 | Shel8 | Membr | Beam | Sprgbm | Bar | Spring
+      | Joint | Kjoin | Mtojnt | Sphel | Sphelo | Gap | Impma | Musc1 => {
+        Section::Element
+      }
+      Elink | Llink | Slink | Plink | Tied => Section::Link,
+      PartSolid | PartBshel | PartTetra | PartSphel | PartCos3d | PartTshel
+      | PartShell | PartMembr | PartBar | PartBeam | PartSpring
+      | PartSprgbm | PartMbspr | PartJoint | PartKjoin | PartMbkjn
+      | PartMtojnt | PartTied | PartSlink | PartElink | PartLlink
+      | PartPlink | PartGap => Section::Part,
+      Mtoco | Otmco | Rbody0 | Rbody1 | Rbody2 | Rbody3 | Bounc | Dis3d
+      | Vel3d | Acfld | Hflux | Intem | Tembc | Htsurf | Lcase | EndLcase => {
+        Section::Constraint
+      }
+      Group | Funct | Looku | Frame | Trsfm | Thnod | Thele | Thloc => {
+        Section::Auxiliaries
+      }
+      Ply | Layer | Mater130 | Mater131 | Mater132 | Thmat | Rupmo | Thvar => {
+        Section::Material
+      }
+      // Not reported by `Section::parse`
+      Encrypted | Unknown => Section::Auxiliaries,
+      Custom(_) => custom::section(kw).unwrap_or(Section::Auxiliaries),
+    }
+  }
+}
+
+/// A rule about the order of the sections of a file
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SectionRule {
+  /// All occurences of the first section need to come before any occurence of
+  /// the second section
+  Before(Section, Section),
+  /// No other section may come after the given one
+  Last(Section),
+  /// The section may only occur once
+  Unique(Section),
+}
+
+/// The rules used if none are configured: Control cards before all entities,
+/// and a single `ENDDATA` at the end.
+pub static DEFAULT_SECTION_RULES: [SectionRule; 9] = [
+  SectionRule::Before(Section::Control, Section::Node),
+  SectionRule::Before(Section::Control, Section::Element),
+  SectionRule::Before(Section::Control, Section::Link),
+  SectionRule::Before(Section::Control, Section::Part),
+  SectionRule::Before(Section::Control, Section::Constraint),
+  SectionRule::Before(Section::Control, Section::Auxiliaries),
+  SectionRule::Before(Section::Control, Section::Material),
+  SectionRule::Last(Section::EndData),
+  SectionRule::Unique(Section::EndData),
+];
+
+impl SectionRule {
+  /// Parse a rule from its configuration, e.g. `["before", "control",
+  /// "node"]`, `["last", "enddata"]` or `["unique", "enddata"]`.
+  pub fn from_strs<S: AsRef<str>>(strs: &[S]) -> Option<Self> {
+    let section =
+      |i: usize| strs.get(i).and_then(|s| Section::from_name(s.as_ref()));
+
+    match strs.first()?.as_ref().to_lowercase().as_str() {
+      "before" => Some(SectionRule::Before(section(1)?, section(2)?)),
+      "last" => Some(SectionRule::Last(section(1)?)),
+      "unique" => Some(SectionRule::Unique(section(1)?)),
+      _ => None,
+    }
+  }
+
+  /// Check the rule against a sequence of sections, given as the section and
+  /// the line it starts at. Adjacent entries should belong to different
+  /// sections. The diagnostics have their
+  /// [`target`](crate::diagnostics::Diagnostic::target) set to the line the
+  /// conflict arises with.
+  pub fn check(&self, sections: &[(Section, LineNr)]) -> Vec<Diagnostic> {
+    let first = |s: Section| sections.iter().find(|(t, _)| *t == s);
+    let mut diagnostics = vec![];
+
+    match *self {
+      SectionRule::Before(a, b) => {
+        if let Some((_, bline)) = first(b) {
+          let late = sections.iter().filter(|(s, l)| *s == a && l > bline);
+
+          for (_, aline) in late {
+            diagnostics.push(
+              Diagnostic::new(
+                *aline,
+                0,
+                8,
+                Severity::Error,
+                format!("{:?} section needs to come before {:?}", a, b),
+              )
+              .with_target(*bline),
+            );
+          }
+        }
+      }
+      SectionRule::Last(a) => {
+        if let Some((_, aline)) = first(a) {
+          let after = sections.iter().filter(|(s, l)| *s != a && l > aline);
+
+          for (s, line) in after {
+            diagnostics.push(
+              Diagnostic::new(
+                *line,
+                0,
+                8,
+                Severity::Error,
+                format!("{:?} section after {:?} section", s, a),
+              )
+              .with_target(*aline),
+            );
+          }
+        }
+      }
+      SectionRule::Unique(a) => {
+        if let Some((_, aline)) = first(a) {
+          let dups = sections.iter().filter(|(s, l)| *s == a && l > aline);
+
+          for (_, line) in dups {
+            diagnostics.push(
+              Diagnostic::new(
+                *line,
+                0,
+                8,
+                Severity::Error,
+                format!("Duplicate {:?} section", a),
+              )
+              .with_target(*aline),
+            );
+          }
+        }
+      }
+    }
+
+    diagnostics
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::card::section::{Section::*, SectionRule};
+
+  #[test]
+  fn parse_rules() {
+    assert_eq!(
+      SectionRule::from_strs(&["before", "control", "node"]),
+      Some(SectionRule::Before(Control, Node))
+    );
+    assert_eq!(
+      SectionRule::from_strs(&["LAST", "EndData"]),
+      Some(SectionRule::Last(EndData))
+    );
+    assert_eq!(SectionRule::from_strs(&["unique"]), None);
+    assert_eq!(SectionRule::from_strs(&["after", "node", "part"]), None);
+  }
+
+  #[test]
+  fn check_rules() {
+    let sections = vec![
+      (Node, 0.into()),
+      (Control, 3.into()),
+      (EndData, 5.into()),
+      (Element, 6.into()),
+      (EndData, 8.into()),
+    ];
+
+    let before = SectionRule::Before(Control, Node).check(&sections);
+    assert_eq!(before.len(), 1);
+    assert_eq!(before[0].line, 3.into());
+    assert_eq!(before[0].target, Some(0.into()));
+
+    let last = SectionRule::Last(EndData).check(&sections);
+    assert_eq!(last.len(), 1);
+    assert_eq!(last[0].line, 6.into());
+    assert_eq!(last[0].target, Some(5.into()));
+
+    let unique = SectionRule::Unique(EndData).check(&sections);
+    assert_eq!(unique.len(), 1);
+    assert_eq!(unique[0].line, 8.into());
+
+    assert!(SectionRule::Before(Node, Element)
+      .check(&sections)
+      .is_empty());
+  }
+}
@@ -0,0 +1,287 @@
+//! A check of the card definitions for internal consistency, printed by
+//! `nvimpam selfcheck`. A malformed definition in the
+//! [`carddata`](crate::carddata) or a custom one otherwise only shows when a
+//! card of its keyword is found in a file, e.g. as an index out of bounds
+//! when walking its lines.
+use std::{cmp, fmt};
+
+use crate::card::{
+  cell::Cell,
+  custom,
+  keyword::Keyword,
+  line::{Conditional, Line},
+  Card,
+};
+
+/// The number of columns of a line of a card. A line may hold one more, the
+/// [`Cont`](crate::card::cell::Cell::Cont) in column 81.
+const LINE_WIDTH: u16 = 80;
+
+/// A card definition to check: Its keyword, its name as reported, the card
+/// and a keyword line for it, if one is known
+type Definition = (Keyword, String, &'static Card, Option<Vec<u8>>);
+
+/// A problem with the definition of a card
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+  /// The name of the keyword, e.g. `Node`, or the keyword itself for a custom
+  /// card, e.g. `MYLOAD/`
+  pub card: String,
+  pub message: String,
+}
+
+impl fmt::Display for Problem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.card, self.message)
+  }
+}
+
+/// The result of checking all card definitions, see
+/// [`run`](crate::card::selfcheck::Selfcheck::run)
+#[derive(Debug)]
+pub struct Selfcheck {
+  /// The number of cards checked
+  pub cards: usize,
+  pub problems: Vec<Problem>,
+}
+
+impl Selfcheck {
+  /// Check the builtin cards and the registered
+  /// [custom](crate::card::custom) ones. Besides the lines of each card, this
+  /// checks that the keywords are unique, i.e. that a keyword line of each
+  /// card is parsed as its keyword.
+  pub fn run() -> Self {
+    let mut cards = 0;
+    let mut problems = vec![];
+
+    let builtin = Keyword::BUILTIN.iter().filter_map(|&kw| {
+      let name = format!("{:?}", kw);
+      kw.builtin_card().map(|c| (kw, name, c, kw.sample()))
+    });
+    let custom_cards = custom::custom_cards();
+    let custom = custom_cards.iter().map(|c| {
+      let sample = c.hash.to_be_bytes().to_vec();
+      let name = String::from_utf8_lossy(&sample).trim_end().to_string();
+      (c.keyword, name, c.card, Some(sample))
+    });
+
+    let definitions: Vec<Definition> = builtin.chain(custom).collect();
+
+    for (kw, name, card, sample) in definitions {
+      cards += 1;
+
+      if let Some(parsed) = sample.map(|s| Keyword::parse(&s)) {
+        if parsed != Some(kw) {
+          problems.push(Problem {
+            card: name.clone(),
+            message: format!("the keyword line is parsed as {:?}", parsed),
+          });
+        }
+      }
+
+      problems.extend(check_card(kw, card).into_iter().map(|message| {
+        Problem {
+          card: name.clone(),
+          message,
+        }
+      }));
+    }
+
+    Selfcheck { cards, problems }
+  }
+}
+
+impl fmt::Display for Selfcheck {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for problem in &self.problems {
+      writeln!(f, "{}", problem)?;
+    }
+
+    match self.problems.len() {
+      0 => writeln!(f, "Checked {} cards, found no problems", self.cards),
+      n => writeln!(f, "Checked {} cards, found {} problems", self.cards, n),
+    }
+  }
+}
+
+/// The problems with the definition `card` of the keyword `kw`
+pub fn check_card(kw: Keyword, card: &Card) -> Vec<String> {
+  let mut problems = vec![];
+
+  match card.lines {
+    [Line::Layouts(fixed, long, cond)] => {
+      let width = cmp::max(
+        fixed.first().map_or(0, width),
+        long.first().map_or(0, width),
+      );
+      if let Some(p) = check_conditional(cond, width) {
+        problems.push(format!("the conditional of the layouts {}", p));
+      }
+      check_lines(kw, fixed, "fixed layout line", &mut problems);
+      check_lines(kw, long, "long layout line", &mut problems);
+    }
+    lines => check_lines(kw, lines, "line", &mut problems),
+  }
+
+  problems
+}
+
+/// The number of columns of the cells of `line`
+fn width(line: &Line) -> u16 {
+  line
+    .cells()
+    .map_or(0, |cells| cells.iter().map(|c| u16::from(c.len())).sum())
+}
+
+/// What's wrong with `cond` on a line of `width` columns, if anything. It
+/// needs to start inside of the cells, but may read up to column 81, since
+/// it's cut off at the end of the line anyways.
+fn check_conditional(cond: &Conditional, width: u16) -> Option<String> {
+  let (start, end) = match *cond {
+    Conditional::RelChar(idx, _) => (idx, idx.saturating_add(1)),
+    Conditional::Int(ref r, _)
+    | Conditional::Number(ref r)
+    | Conditional::IntIn(ref r, _) => (r.start, r.end),
+  };
+
+  if start >= end {
+    Some(format!("reads the empty columns {}..{}", start, end))
+  } else if u16::from(start) >= width || u16::from(end) > LINE_WIDTH + 1 {
+    Some(format!("reads columns {}..{} of {}", start, end, width))
+  } else {
+    None
+  }
+}
+
+/// Push the problems with `lines` of the card of `kw` to `problems`. The
+/// messages start with `what` and the number of the line.
+fn check_lines(
+  kw: Keyword,
+  lines: &[Line],
+  what: &str,
+  problems: &mut Vec<String>,
+) {
+  // The conditionals provided so far, see `Line::Optional`
+  let mut conds = vec![];
+
+  match lines.first().map(Line::keyword) {
+    None => problems.push(format!("{}s are missing", what)),
+    Some(Some(k)) if k == kw => {}
+    Some(Some(k)) => problems.push(format!("{} 1 starts with {:?}", what, k)),
+    Some(None) => problems.push(format!("{} 1 has no keyword", what)),
+  }
+
+  for (i, line) in lines.iter().enumerate() {
+    let nr = i + 1;
+
+    if let Some(cells) = line.cells() {
+      let width = width(line);
+      let limit = match cells.last() {
+        Some(Cell::Cont) => LINE_WIDTH + 1,
+        _ => LINE_WIDTH,
+      };
+      if cells.is_empty() {
+        problems.push(format!("{} {} has no cells", what, nr));
+      } else if width > limit {
+        problems.push(format!("{} {} is {} columns wide", what, nr, width));
+      }
+      if i > 0 && cells.iter().any(|c| c.keyword().is_some()) {
+        problems.push(format!("{} {} holds a keyword", what, nr));
+      }
+    }
+
+    match *line {
+      Line::Provides(_, ref cond) => {
+        if let Some(p) = check_conditional(cond, width(line)) {
+          problems.push(format!("the conditional of {} {} {}", what, nr, p));
+        }
+        conds.push(cond);
+      }
+      Line::Optional(_, idx) => match conds.get(usize::from(idx)) {
+        None => problems.push(format!(
+          "{} {} uses conditional {} of {}",
+          what,
+          nr,
+          idx,
+          conds.len()
+        )),
+        Some(Conditional::Number(_)) => problems.push(format!(
+          "{} {} is optional by the number of conditional {}",
+          what, nr, idx
+        )),
+        Some(_) => {}
+      },
+      Line::Repeat(_, idx) => match conds.get(usize::from(idx)) {
+        None => problems.push(format!(
+          "{} {} uses conditional {} of {}",
+          what,
+          nr,
+          idx,
+          conds.len()
+        )),
+        Some(Conditional::Number(_)) => {}
+        Some(_) => problems.push(format!(
+          "{} {} is repeated by the boolean conditional {}",
+          what, nr, idx
+        )),
+      },
+      Line::Rest | Line::Contains(_) if nr < lines.len() => {
+        problems.push(format!("{} {} needs to be the last line", what, nr))
+      }
+      Line::Layouts(..) => {
+        problems.push(format!("{} {} needs to be the only line", what, nr))
+      }
+      _ => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::card::{
+    cell::Cell,
+    keyword::Keyword,
+    line::{Conditional, Line},
+    selfcheck::{check_card, Selfcheck},
+    Card,
+  };
+
+  const BROKEN: Card = Card {
+    lines: &[
+      Line::Provides(
+        &[Cell::Kw(Keyword::Node), Cell::Integer(8)],
+        Conditional::Number(16..24),
+      ),
+      Line::Cells(&[Cell::Float(40), Cell::Float(40), Cell::Blank(8)]),
+      Line::Optional(&[Cell::Integer(8)], 0),
+      Line::Repeat(&[Cell::Integer(8)], 1),
+      Line::Rest,
+      Line::Cells(&[Cell::Kw(Keyword::Node)]),
+    ],
+    ownfold: false,
+  };
+
+  #[test]
+  fn builtin_cards_pass() {
+    let check = Selfcheck::run();
+    assert!(check.cards >= Keyword::BUILTIN.len());
+    assert_eq!(check.problems, vec![]);
+    assert!(check.to_string().ends_with("found no problems\n"));
+  }
+
+  #[test]
+  fn broken_card() {
+    assert_eq!(
+      check_card(Keyword::Shell, &BROKEN),
+      vec![
+        "line 1 starts with Node",
+        "the conditional of line 1 reads columns 16..24 of 16",
+        "line 2 is 88 columns wide",
+        "line 3 is optional by the number of conditional 0",
+        "line 4 uses conditional 1 of 1",
+        "line 5 needs to be the last line",
+        "line 6 holds a keyword",
+      ]
+    );
+  }
+}
@@ -0,0 +1,221 @@
+//! Tabs in the lines of a card. Pamcrash reads its cards by fixed columns, so
+//! a tab shifts the contents of all following cells. What nvimpam does about
+//! them is set for the whole program by a
+//! [`TabPolicy`](crate::card::tabs::TabPolicy), see
+//! [`set_policy`](crate::card::tabs::set_policy).
+//!
+//! With [`Expand`](crate::card::tabs::TabPolicy::Expand), tabs are expanded
+//! to the next tab stop for all the column math, i.e. the highlights, the
+//! [`Conditional`](crate::card::line::Conditional)s and the extraction of
+//! the cells, so the cells line up with what's shown in neovim. The columns
+//! sent to neovim are still byte-indexed, see
+//! [`byte_column`](crate::card::tabs::byte_column). Otherwise, a tab counts
+//! as a single column, and each tab is reported by a warning
+//! [`Diagnostic`](crate::diagnostics::Diagnostic).
+use std::{
+  borrow::Cow,
+  sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{
+  diagnostics::{Diagnostic, Severity},
+  linenr::LineNr,
+};
+
+/// The distance between two tab stops
+pub const TABSTOP: usize = 8;
+
+/// What to do about tabs in the lines of a card
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TabPolicy {
+  /// Expand the tabs for the column math, and don't report them
+  Expand,
+  /// Report the tabs. The default.
+  Flag,
+  /// Report the tabs along with the spaces to replace them by
+  Replace,
+}
+
+impl TabPolicy {
+  /// The policy with the name `name`, e.g. `"expand"`
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "expand" => Some(TabPolicy::Expand),
+      "flag" => Some(TabPolicy::Flag),
+      "replace" => Some(TabPolicy::Replace),
+      _ => None,
+    }
+  }
+}
+
+/// The policy in use, as its index in `POLICIES`
+static POLICY: AtomicU8 = AtomicU8::new(1);
+
+const POLICIES: [TabPolicy; 3] =
+  [TabPolicy::Expand, TabPolicy::Flag, TabPolicy::Replace];
+
+/// Set the policy for the rest of the runtime of the program
+pub fn set_policy(policy: TabPolicy) {
+  #[allow(clippy::cast_possible_truncation)]
+  let idx = POLICIES.iter().position(|&p| p == policy).unwrap_or(1) as u8;
+  POLICY.store(idx, Ordering::Relaxed);
+}
+
+/// The policy in use
+pub fn policy() -> TabPolicy {
+  POLICIES[usize::from(POLICY.load(Ordering::Relaxed))]
+}
+
+/// The line `text` as used for the column math: With tabs expanded to the
+/// next tab stop if the policy is
+/// [`Expand`](crate::card::tabs::TabPolicy::Expand), otherwise unchanged.
+/// Only allocates for lines holding a tab.
+#[inline]
+pub fn expanded(text: &[u8]) -> Cow<'_, [u8]> {
+  if policy() != TabPolicy::Expand || !text.contains(&b'\t') {
+    return Cow::Borrowed(text);
+  }
+
+  let mut line = Vec::with_capacity(text.len() + TABSTOP);
+  for &b in text {
+    if b == b'\t' {
+      let width = TABSTOP - line.len() % TABSTOP;
+      line.extend((0..width).map(|_| b' '));
+    } else {
+      line.push(b);
+    }
+  }
+
+  Cow::Owned(line)
+}
+
+/// The byte index in `text` of the column `col` of the
+/// [`expanded`](crate::card::tabs::expanded) line, i.e. the number of
+/// characters starting before that column. A column inside of a tab belongs
+/// to the tab. Columns after the end of the line map to the bytes after it.
+pub fn byte_column(text: &[u8], col: u8) -> u8 {
+  #![allow(clippy::cast_possible_truncation)]
+  let col = usize::from(col);
+  let mut virt = 0;
+
+  for (i, &b) in text.iter().enumerate() {
+    if virt >= col {
+      return i as u8;
+    }
+    virt += if b == b'\t' {
+      TABSTOP - virt % TABSTOP
+    } else {
+      1
+    };
+  }
+
+  (text.len() + col.saturating_sub(virt)).min(usize::from(u8::MAX)) as u8
+}
+
+/// Convert the columns of `diagnostic` in the line `text` from the
+/// [`expanded`](crate::card::tabs::expanded) line to byte indices. Does
+/// nothing if the tabs aren't expanded.
+pub fn to_bytes(diagnostic: &mut Diagnostic, text: &[u8]) {
+  if policy() == TabPolicy::Expand && text.contains(&b'\t') {
+    diagnostic.start = byte_column(text, diagnostic.start);
+    diagnostic.end = byte_column(text, diagnostic.end);
+  }
+}
+
+/// A warning for each tab in the line `text` with the number `line`, unless
+/// the policy is [`Expand`](crate::card::tabs::TabPolicy::Expand). With
+/// [`Replace`](crate::card::tabs::TabPolicy::Replace), the message names the
+/// number of spaces the tab is shown as.
+pub fn diagnostics(line: LineNr, text: &[u8]) -> Vec<Diagnostic> {
+  #![allow(clippy::cast_possible_truncation)]
+  let policy = policy();
+  let mut diagnostics = vec![];
+
+  if policy == TabPolicy::Expand || !text.contains(&b'\t') {
+    return diagnostics;
+  }
+
+  let mut virt = 0;
+  for (i, &b) in text.iter().enumerate().take(usize::from(u8::MAX)) {
+    if b != b'\t' {
+      virt += 1;
+      continue;
+    }
+
+    let width = TABSTOP - virt % TABSTOP;
+    let message = match policy {
+      TabPolicy::Replace => {
+        format!("Tab in a fixed-format line, replace it by {} spaces", width)
+      }
+      _ => "Tab in a fixed-format line shifts the following cells".to_string(),
+    };
+    diagnostics.push(Diagnostic::new(
+      line,
+      i as u8,
+      i as u8 + 1,
+      Severity::Warning,
+      message,
+    ));
+    virt += width;
+  }
+
+  diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::highlights::Highlights,
+    card::{
+      line::{CondResult, Conditional},
+      parse::CellLine,
+      tabs::{byte_column, diagnostics, expanded, set_policy, TabPolicy},
+    },
+    carddata::NODE,
+    linenr::LineNr,
+  };
+
+  #[test]
+  fn tab_policies() {
+    let text = b"NODE  /\t       1\t0.";
+
+    set_policy(TabPolicy::Flag);
+    assert_eq!(expanded(text).as_ref(), &text[..]);
+    let diags = diagnostics(LineNr::from_usize(3), text);
+    assert_eq!(diags.len(), 2);
+    assert_eq!((diags[1].start, diags[1].end), (16, 17));
+
+    set_policy(TabPolicy::Replace);
+    let diags = diagnostics(LineNr::from_usize(3), text);
+    assert!(diags[0].message.ends_with("replace it by 1 spaces"));
+    assert!(diags[1].message.ends_with("replace it by 8 spaces"));
+
+    set_policy(TabPolicy::Expand);
+    assert!(diagnostics(LineNr::from_usize(3), text).is_empty());
+    let spaced = format!("NODE  / {}1{}0.", " ".repeat(7), " ".repeat(8));
+    assert_eq!(expanded(text).as_ref(), spaced.as_bytes());
+    assert_eq!(byte_column(text, 8), 8);
+    assert_eq!(byte_column(text, 16), 16);
+    assert_eq!(byte_column(text, 20), 17);
+    assert_eq!(byte_column(text, 24), 17);
+    assert_eq!(byte_column(text, 26), 19);
+    assert_eq!(byte_column(text, 30), 23);
+
+    // The cells line up with the expanded line, the highlights are sent in
+    // bytes
+    let line = CellLine::new(&NODE.lines[0], text).unwrap();
+    assert_eq!(line.int(1), Ok(1));
+    assert_eq!(line.float(2), Ok(0.));
+    assert_eq!(
+      Conditional::Number(8..16).evaluate(text),
+      CondResult::Number(Some(1))
+    );
+    let mut highlights = Highlights::new();
+    highlights.add_line_highlights(LineNr::from_usize(3), text, &NODE.lines[0]);
+    let columns: Vec<_> =
+      highlights.iter().map(|((_, s, e), _)| (s, e)).collect();
+    assert_eq!(columns, vec![(0, 8), (8, 16), (16, 19)]);
+
+    set_policy(TabPolicy::Flag);
+  }
+}
@@ -0,0 +1,476 @@
+//! Extracting the contents of the cells of a card, e.g. to show them outside
+//! of the buffer. The [`Card`](crate::card::Card) definitions only know the
+//! type and width of the cells, so their names are taken from the `$#`
+//! comment line before a line if there is one. Those are written by most
+//! preprocessors, with each name aligned to the end of its cell.
+use std::cmp;
+
+use neovim_lib::Value;
+
+use crate::{
+  card::{
+    cell::{Cell, FixedStr},
+    keyword::Keyword,
+    line::{self, CondResult, Line},
+    parse::{kind, CellLine},
+    tabs, Card,
+  },
+  diagnostics::{Diagnostic, Severity},
+  linenr::LineNr,
+};
+
+/// The content of a cell
+#[derive(Debug, PartialEq, Clone)]
+pub enum CellValue {
+  Keyword(Keyword),
+  Integer(i64),
+  Float(f64),
+  Str(String),
+  Blank,
+  /// The content does not fit the type of the cell
+  Invalid(String),
+}
+
+/// A cell of a line, with its (end-exclusive) column range. The `name` is
+/// only known if the line has a header.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CellData {
+  pub name: Option<String>,
+  pub kind: &'static str,
+  pub start: u8,
+  pub end: u8,
+  pub value: CellValue,
+}
+
+/// The cells of the line `line` of a card. They're empty if the layout of the
+/// line is not known, e.g. for the lines of a
+/// [`GES`](crate::card::line::Line::Ges).
+#[derive(Debug, PartialEq)]
+pub struct LineValues {
+  pub line: LineNr,
+  pub cells: Vec<CellData>,
+}
+
+impl<'a> From<&'a CellValue> for Value {
+  fn from(v: &'a CellValue) -> Self {
+    match *v {
+      CellValue::Keyword(k) => Value::from(format!("{:?}", k)),
+      CellValue::Integer(i) => Value::from(i),
+      CellValue::Float(f) => Value::from(f),
+      CellValue::Str(ref s) | CellValue::Invalid(ref s) => {
+        Value::from(s.as_str())
+      }
+      CellValue::Blank => Value::Nil,
+    }
+  }
+}
+
+impl<'a> From<&'a CellData> for Value {
+  fn from(c: &'a CellData) -> Self {
+    let mut map = vec![
+      (Value::from("kind"), Value::from(c.kind)),
+      (Value::from("start"), Value::from(u64::from(c.start))),
+      (Value::from("end"), Value::from(u64::from(c.end))),
+      (Value::from("value"), Value::from(&c.value)),
+    ];
+
+    if let Some(ref name) = c.name {
+      map.push((Value::from("name"), Value::from(name.as_str())));
+    }
+
+    Value::from(map)
+  }
+}
+
+impl<'a> From<&'a LineValues> for Value {
+  fn from(l: &'a LineValues) -> Self {
+    Value::from(vec![
+      (Value::from("line"), Value::from(l.line)),
+      (
+        Value::from("cells"),
+        Value::from(l.cells.iter().map(Value::from).collect::<Vec<_>>()),
+      ),
+    ])
+  }
+}
+
+/// Parse the content `s` of a cell
+fn value(cell: &Cell, s: &[u8]) -> CellValue {
+  use self::Cell::*;
+
+  let trimmed = String::from_utf8_lossy(s).trim().to_string();
+  let blank = trimmed.is_empty();
+
+  match *cell {
+    Kw(k) => CellValue::Keyword(k),
+    Fixed(f) => CellValue::Str(<&'static str>::from(f).to_string()),
+    _ if blank => CellValue::Blank,
+    Integer(_) | IntegerorBlank(_) => trimmed
+      .parse()
+      .map(CellValue::Integer)
+      .unwrap_or(CellValue::Invalid(trimmed)),
+    Float(_) => match trimmed.parse() {
+      Ok(f) => CellValue::Float(f),
+      // Pyvars like `<var>` are valid, but have no value yet
+      Err(_) if cell.verify(s) => CellValue::Str(trimmed),
+      Err(_) => CellValue::Invalid(trimmed),
+    },
+    Cont if trimmed == "&" => CellValue::Str(trimmed),
+    Binary(_) if trimmed.bytes().all(|b| b == b'0' || b == b'1') => {
+      CellValue::Str(trimmed)
+    }
+    Str(_) => CellValue::Str(trimmed),
+    Blank(_) | Cont | Binary(_) => CellValue::Invalid(trimmed),
+  }
+}
+
+/// The names in the `$#` comment line `header` of the cells with the column
+/// ranges `ranges`. A name belongs to the cell containing its last character.
+fn names(header: &[u8], ranges: &[(u8, u8)]) -> Vec<Option<String>> {
+  let mut names: Vec<Vec<&[u8]>> = vec![vec![]; ranges.len()];
+  // Names after the last cell belong to no cell, so the rest of a long
+  // header isn't looked at
+  let end = ranges.last().map_or(0, |r| usize::from(r.1));
+  let header = &header[..cmp::min(header.len(), end + 1)];
+
+  let mut start = None;
+  for i in 2..=header.len() {
+    match (header.get(i).filter(|b| **b != b' '), start) {
+      (Some(_), None) => start = Some(i),
+      (None, Some(s)) => {
+        let last = i - 1;
+        if let Some(idx) = ranges
+          .iter()
+          .position(|r| r.0 as usize <= last && last < r.1 as usize)
+        {
+          names[idx].push(&header[s..i]);
+        }
+        start = None;
+      }
+      _ => {}
+    }
+  }
+
+  names
+    .into_iter()
+    .map(|n| {
+      if n.is_empty() {
+        None
+      } else {
+        Some(String::from_utf8_lossy(&n.join(&b' ')).into_owned())
+      }
+    })
+    .collect()
+}
+
+/// Extract the values of `cells` from the line `text`, naming them by
+/// `header` if given
+pub fn line_values(
+  cells: &'static [Cell],
+  text: &[u8],
+  header: Option<&[u8]>,
+) -> Vec<CellData> {
+  let line = CellLine::from_cells(cells, text);
+  let names =
+    header.map_or_else(|| vec![None; cells.len()], |h| names(h, line.ranges()));
+
+  cells
+    .iter()
+    .enumerate()
+    .zip(line.ranges())
+    .zip(names)
+    .map(|(((i, cell), &(start, end)), name)| CellData {
+      name,
+      kind: kind(cell),
+      start,
+      end,
+      value: value(cell, line.raw(i).unwrap_or(&[])),
+    })
+    .collect()
+}
+
+/// Extract the values of the lines of a card of type `card`. The `lines` are
+/// numbered lines of the buffer, starting with the keyword line or the `$#`
+/// header before it. Other comments are skipped.
+///
+/// The lines of the card are followed as long as their layout is known, i.e.
+/// up to the first line that's not a line of cells. The following lines are
+/// returned without any cells.
+pub fn card_values(card: &Card, lines: &[(LineNr, &[u8])]) -> Vec<LineValues> {
+  let is_comment =
+    |t: &[u8]| t.first() == Some(&b'$') || t.first() == Some(&b'#');
+  let kwtext = lines
+    .iter()
+    .map(|l| l.1)
+    .find(|t| !is_comment(t))
+    .unwrap_or(&[]);
+
+  let mut cardlines = card.layout(kwtext).iter();
+  let mut conds: Vec<CondResult> = vec![];
+  let mut known = true;
+  // The cells of the next lines, e.g. for repeated lines, and how many lines
+  // still use them
+  let mut pending: Option<(&'static [Cell], usize)> = None;
+  let mut header = None;
+  let mut values = vec![];
+
+  for (number, text) in lines {
+    if text.starts_with(b"$#") {
+      header = Some(*text);
+      continue;
+    } else if is_comment(text) {
+      continue;
+    }
+
+    let cells = match pending {
+      Some((cells, n)) if n > 0 => {
+        pending = Some((cells, n - 1));
+        Some(cells)
+      }
+      _ => loop {
+        if !known {
+          break None;
+        }
+
+        match cardlines.next() {
+          Some(Line::Cells(s)) => break Some(*s),
+          Some(Line::Provides(s, ref c)) => {
+            conds.push(c.evaluate(text));
+            break Some(*s);
+          }
+          Some(Line::Optional(s, i)) => {
+            if conds.get(*i as usize) == Some(&CondResult::Bool(true)) {
+              break Some(*s);
+            }
+          }
+          Some(Line::Repeat(s, i)) => match conds.get(*i as usize) {
+            Some(CondResult::Number(Some(n))) if *n > 0 => {
+              pending = Some((s, n - 1));
+              break Some(*s);
+            }
+            _ => {}
+          },
+          _ => known = false,
+        }
+      },
+    };
+
+    if let Some(c) = cells {
+      if c.first() == Some(&Cell::Fixed(FixedStr::Name))
+        && line::name_continues(text)
+      {
+        pending = Some((c, 1));
+      }
+    }
+
+    values.push(LineValues {
+      line: *number,
+      cells: cells.map_or_else(Vec::new, |c| line_values(c, text, header)),
+    });
+    header = None;
+  }
+
+  values
+}
+
+/// The ID of a card of type `card` with the keyword line `text`, i.e. the
+/// value of the first integer cell, e.g. the node number of a `NODE`. `None`
+/// if there's no such cell, or it's blank.
+pub fn card_id(card: &Card, text: &[u8]) -> Option<i64> {
+  let line = CellLine::new(card.layout(text).first()?, text)?;
+
+  line.int(line.position("integer")?).ok()
+}
+
+/// An error [`Diagnostic`](crate::diagnostics::Diagnostic) for each cell of
+/// `values` with an invalid content. Content in blank cells is ignored by the
+/// solver, so it only gets a warning.
+pub fn invalid_cells(values: &[LineValues]) -> Vec<Diagnostic> {
+  values
+    .iter()
+    .flat_map(|l| {
+      l.cells.iter().filter_map(move |c| match c.value {
+        CellValue::Invalid(ref s) if c.kind == "blank" => {
+          Some(Diagnostic::new(
+            l.line,
+            c.start,
+            c.end,
+            Severity::Warning,
+            format!("Content '{}' of a blank cell is ignored", s),
+          ))
+        }
+        CellValue::Invalid(ref s) => Some(Diagnostic::new(
+          l.line,
+          c.start,
+          c.end,
+          Severity::Error,
+          format!("Invalid {} cell '{}'", c.kind, s),
+        )),
+        _ => None,
+      })
+    })
+    .collect()
+}
+
+/// The columns of the content of `text` from the column `from` on, without
+/// the blanks around it. `None` if there's only blanks. The end is capped at
+/// the largest column a highlight can have.
+pub fn ignored_range(text: &[u8], from: u8) -> Option<(u8, u8)> {
+  let rest = text.get(from as usize..)?;
+  let first = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+  let last = rest.iter().rposition(|b| !b.is_ascii_whitespace())?;
+
+  #[allow(clippy::cast_possible_truncation)]
+  let col = |i: usize| cmp::min(from as usize + i, u8::MAX as usize) as u8;
+
+  Some((col(first), col(last + 1)))
+}
+
+/// A warning [`Diagnostic`](crate::diagnostics::Diagnostic) for each line of
+/// `values` with content after its last cell, which the solver ignores. The
+/// `lines` are the ones the `values` were extracted from, see
+/// [`card_values`](crate::card::value::card_values). Lines of unknown layout
+/// are skipped.
+pub fn overflows(
+  values: &[LineValues],
+  lines: &[(LineNr, &[u8])],
+) -> Vec<Diagnostic> {
+  values
+    .iter()
+    .filter_map(|l| {
+      let end = l.cells.last()?.end;
+      let (_, text) = lines.iter().find(|(n, _)| *n == l.line)?;
+      let (start, stop) = ignored_range(&tabs::expanded(text), end)?;
+
+      Some(Diagnostic::new(
+        l.line,
+        start,
+        stop,
+        Severity::Warning,
+        format!("Content after column {} is ignored", end),
+      ))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    card::{
+      keyword::Keyword,
+      value::{
+        card_id, card_values, ignored_range, invalid_cells, overflows,
+        CellValue::*,
+      },
+    },
+    carddata::{MASS, NODE, SHELL},
+    diagnostics::Severity,
+    linenr::LineNr,
+  };
+
+  fn numbered<'a>(lines: &[&'a str]) -> Vec<(LineNr, &'a [u8])> {
+    lines
+      .iter()
+      .enumerate()
+      .map(|(i, l)| (LineNr::from_usize(i), l.as_bytes()))
+      .collect()
+  }
+
+  #[test]
+  fn card_ids() {
+    assert_eq!(
+      card_id(
+        &NODE,
+        b"NODE  /        7              0.             0.5              0."
+      ),
+      Some(7)
+    );
+    assert_eq!(
+      card_id(&SHELL, b"SHELL /     3129       1       1    2967    2971"),
+      Some(3129)
+    );
+    assert_eq!(card_id(&NODE, b"NODE  /"), None);
+  }
+
+  #[test]
+  fn node_values() {
+    let lines = numbered(&[
+      "$#         IDNOD               X               Y               Z",
+      "NODE  /        1              0.             0.5              x.",
+    ]);
+    let values = card_values(&NODE, &lines);
+
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0].line, LineNr::from_usize(1));
+
+    let cells = &values[0].cells;
+    assert_eq!(cells[0].value, Keyword(Keyword::Node));
+    assert_eq!(cells[1].name, Some("IDNOD".to_string()));
+    assert_eq!(cells[1].value, Integer(1));
+    assert_eq!((cells[2].start, cells[2].end), (16, 32));
+    assert_eq!(cells[2].value, Float(0.));
+    assert_eq!(cells[3].name, Some("Y".to_string()));
+    assert_eq!(cells[4].value, Invalid("x.".to_string()));
+
+    let diagnostics = invalid_cells(&values);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].start, diagnostics[0].end), (48, 64));
+  }
+
+  #[test]
+  fn ignored_content() {
+    let lines = numbered(&[
+      "NODE  /        1              0.              0.              0.  12  ",
+    ]);
+    let values = card_values(&NODE, &lines);
+    assert!(invalid_cells(&values).is_empty());
+
+    let diagnostics = overflows(&values, &lines);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].start, diagnostics[0].end), (66, 68));
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+
+    let lines = numbered(&[
+      "NODE  /                1                      0.                      \
+       0.  x     &",
+      "     y                                        0.",
+    ]);
+    let values = card_values(&NODE, &lines);
+    assert!(overflows(&values, &lines).is_empty());
+
+    let diagnostics = invalid_cells(&values);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!((diagnostics[0].start, diagnostics[0].end), (72, 80));
+    assert_eq!(diagnostics[1].line, LineNr::from_usize(1));
+    assert_eq!(diagnostics[1].severity, Severity::Warning);
+
+    assert_eq!(ignored_range(b"NODE  /    ", 8), None);
+    assert_eq!(ignored_range(b"NODE", 8), None);
+  }
+
+  #[test]
+  fn mass_values() {
+    let lines = numbered(&[
+      "MASS  /        0       0                                ",
+      "NAME MASS  / ->1",
+      "NAME continued",
+      "$ a comment",
+      "              0.              0.              0.",
+      "                      0.              0.              0.",
+      "            1    END",
+    ]);
+    let values = card_values(&MASS, &lines);
+
+    assert_eq!(values.len(), 6);
+    assert_eq!(values[0].cells[1].value, Integer(0));
+    assert_eq!(values[0].cells[3].value, Blank);
+    assert_eq!(values[0].cells[1].name, None);
+    assert_eq!(values[1].cells[1].value, Str("MASS  / ->1".to_string()));
+    assert_eq!(values[2].cells[1].value, Str("continued".to_string()));
+    assert_eq!(values[3].line, LineNr::from_usize(4));
+    assert_eq!(values[3].cells[0].value, Float(0.));
+    assert_eq!(values[4].cells.len(), 6);
+    // The GES is not made of cells
+    assert!(values[5].cells.is_empty());
+  }
+}
@@ -0,0 +1,107 @@
+//! The input version a deck declares in its `INPUTVERSION` line, and the
+//! revisions of cards whose layout changed between versions. The table of
+//! revisions can be found in
+//! [`carddata::revision`](crate::carddata::revision).
+use std::fmt;
+
+use crate::{
+  card::{custom, keyword::Keyword, Card},
+  carddata::REVISIONS,
+};
+
+/// The version of the input format, e.g. `2018.0`. Ordered by year first,
+/// then by the minor version.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct InputVersion {
+  pub year: u16,
+  pub minor: u16,
+}
+
+impl InputVersion {
+  pub fn new(year: u16, minor: u16) -> Self {
+    InputVersion { year, minor }
+  }
+
+  /// Parse a version like `2018` or `2018.0`. Only the first two components
+  /// are considered.
+  pub fn parse(s: &str) -> Option<Self> {
+    let mut parts = s.trim().splitn(3, '.');
+    let year = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+      Some(m) if !m.is_empty() => m.parse().ok()?,
+      _ => 0,
+    };
+
+    Some(InputVersion { year, minor })
+  }
+}
+
+impl fmt::Display for InputVersion {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}.{}", self.year, self.minor)
+  }
+}
+
+/// An older layout `card` of cards of type `keyword`, used by decks with an
+/// input version before `before`.
+#[derive(Debug)]
+pub struct CardRevision {
+  pub keyword: Keyword,
+  pub before: InputVersion,
+  pub card: &'static Card,
+}
+
+/// The layout of cards of type `kw` in a deck of the given input version. If
+/// the version is not known, the current layout is used. Custom card
+/// definitions take precedence over all of them.
+pub fn card_for(kw: Keyword, version: Option<InputVersion>) -> &'static Card {
+  if let Some(card) = custom::card(kw) {
+    return card;
+  }
+
+  version
+    .and_then(|v| {
+      REVISIONS
+        .iter()
+        .filter(|r| r.keyword == kw && v < r.before)
+        .min_by_key(|r| r.before)
+    })
+    .map(|r| r.card)
+    .unwrap_or_else(|| (&kw).into())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    card::{
+      keyword::Keyword::*,
+      version::{card_for, InputVersion},
+    },
+    carddata::{NSMAS, NSMAS_PRE2010},
+  };
+
+  #[test]
+  fn parse_versions() {
+    assert_eq!(
+      InputVersion::parse("2018"),
+      Some(InputVersion::new(2018, 0))
+    );
+    assert_eq!(
+      InputVersion::parse(" 2016.5 "),
+      Some(InputVersion::new(2016, 5))
+    );
+    assert_eq!(InputVersion::parse("v2018"), None);
+    assert!(InputVersion::new(2009, 5) < InputVersion::new(2010, 0));
+    assert_eq!(InputVersion::new(2016, 5).to_string(), "2016.5");
+  }
+
+  #[test]
+  fn select_revision() {
+    let old = Some(InputVersion::new(2008, 0));
+    let new = Some(InputVersion::new(2010, 0));
+
+    assert!(std::ptr::eq(card_for(Nsmas, old), &NSMAS_PRE2010));
+    assert!(std::ptr::eq(card_for(Nsmas, new), &NSMAS));
+    assert!(std::ptr::eq(card_for(Nsmas, None), &NSMAS));
+  }
+}
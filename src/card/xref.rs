@@ -0,0 +1,805 @@
+//! Cross references between cards, i.e. which cells hold the ID of an entity
+//! and which cells refer to it. The table itself can be found in
+//! [`carddata::xref`](crate::carddata::xref). Currently, only cells on the
+//! first line of a card are considered.
+//!
+//! The IDs of a buffer can be kept in an [`XrefIndex`], which is updated
+//! with the changed lines only, so they don't need to be looked for on all
+//! cards after each change.
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+  card::{keyword::Keyword, parse::CellLine, Card},
+  carddata::XREFS,
+  diagnostics::{Diagnostic, Severity},
+  linenr::LineNr,
+};
+
+/// The lines changed by renaming or renumbering IDs, along with a
+/// [`Diagnostic`](crate::diagnostics::Diagnostic) for each problem found
+pub type Edits = (Vec<(LineNr, String)>, Vec<Diagnostic>);
+
+/// The entities that can be referenced by their ID
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Entity {
+  Node,
+  Element,
+  Part,
+}
+
+impl Entity {
+  /// Parse the name of an entity, e.g. `"node"`
+  pub fn from_name(name: &str) -> Option<Self> {
+    use self::Entity::*;
+
+    match name.to_lowercase().as_str() {
+      "node" => Some(Node),
+      "element" => Some(Element),
+      "part" => Some(Part),
+      _ => None,
+    }
+  }
+}
+
+/// The cells of the first line of the cards in `keywords` with the given
+/// indices contain an ID of `entity`. If `defines` is true, that's the ID of
+/// the card itself, otherwise it's a reference to another card.
+#[derive(Debug)]
+pub struct CellRef {
+  pub keywords: &'static [Keyword],
+  pub cells: &'static [u8],
+  pub entity: Entity,
+  pub defines: bool,
+}
+
+/// An ID in a cell of a keyword line
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct XrefId {
+  pub entity: Entity,
+  pub id: i64,
+  /// The column range of the cell
+  pub range: (u8, u8),
+  /// If the card defines the ID, otherwise it refers to another card
+  pub defines: bool,
+}
+
+/// The IDs of all entities on the first line `text` of a card of type `kw`.
+/// Blank or invalid cells are skipped.
+fn all_ids(kw: Keyword, text: &[u8]) -> Vec<XrefId> {
+  if !XREFS.iter().any(|x| x.keywords.contains(&kw)) {
+    return vec![];
+  }
+
+  let card: &Card = (&kw).into();
+  let line = match CellLine::new(&card.layout(text)[0], text) {
+    Some(l) => l,
+    None => return vec![],
+  };
+
+  XREFS
+    .iter()
+    .filter(|x| x.keywords.contains(&kw))
+    .flat_map(|x| x.cells.iter().map(move |c| (*c as usize, x)))
+    .filter_map(|(c, x)| {
+      Some(XrefId {
+        entity: x.entity,
+        id: line.int(c).ok()?,
+        range: line.range(c).ok()?,
+        defines: x.defines,
+      })
+    })
+    .collect()
+}
+
+/// The IDs of `entity` on the first line `text` of a card of type `kw`, along
+/// with the column ranges of their cells and the information if the card
+/// defines them. Blank or invalid cells are skipped.
+fn ids(kw: Keyword, text: &[u8], entity: Entity) -> Vec<(i64, (u8, u8), bool)> {
+  all_ids(kw, text)
+    .into_iter()
+    .filter(|i| i.entity == entity)
+    .map(|i| (i.id, i.range, i.defines))
+    .collect()
+}
+
+/// The IDs on the keyword lines of a buffer, by line. Once built, it's kept
+/// up to date by [`splice`](crate::card::xref::XrefIndex::splice), which
+/// only looks at the changed lines.
+#[derive(Debug, Default, PartialEq)]
+pub struct XrefIndex(BTreeMap<LineNr, Vec<XrefId>>);
+
+impl XrefIndex {
+  /// Index the IDs on the keyword lines `lines`
+  pub fn new(lines: &[(LineNr, &[u8], Keyword)]) -> Self {
+    let mut index = XrefIndex::default();
+    index.insert(lines);
+    index
+  }
+
+  fn insert(&mut self, lines: &[(LineNr, &[u8], Keyword)]) {
+    for (number, text, kw) in lines {
+      let ids = all_ids(*kw, text);
+      if !ids.is_empty() {
+        let _ = self.0.insert(*number, ids);
+      }
+    }
+  }
+
+  /// Replace the lines `firstline..lastline` by `added` more lines. The
+  /// following lines are shifted, and the new lines are indexed, of which
+  /// `lines` are the keyword lines.
+  pub fn splice(
+    &mut self,
+    firstline: LineNr,
+    lastline: LineNr,
+    added: isize,
+    lines: &[(LineNr, &[u8], Keyword)],
+  ) {
+    let mut removed = self.0.split_off(&firstline);
+    let after = removed.split_off(&lastline);
+
+    self
+      .0
+      .extend(after.into_iter().map(|(l, ids)| (l + added, ids)));
+    self.insert(lines);
+  }
+
+  /// The IDs of `entity` along with their lines, in the order of the lines
+  fn ids(&self, entity: Entity) -> impl Iterator<Item = (LineNr, &XrefId)> {
+    self
+      .0
+      .iter()
+      .flat_map(|(l, ids)| ids.iter().map(move |i| (*l, i)))
+      .filter(move |(_, i)| i.entity == entity)
+  }
+
+  /// The IDs of `entity` defined, along with the line of their first
+  /// definition
+  pub fn definitions(&self, entity: Entity) -> HashMap<i64, LineNr> {
+    let mut defined = HashMap::new();
+
+    for (number, i) in self.ids(entity).filter(|(_, i)| i.defines) {
+      let _ = defined.entry(i.id).or_insert(number);
+    }
+
+    defined
+  }
+
+  /// Find the IDs of `entity` that are defined more than once. Returns a
+  /// [`Diagnostic`](crate::diagnostics::Diagnostic) for each later
+  /// definition, targeting the first one.
+  pub fn duplicates(&self, entity: Entity) -> Vec<Diagnostic> {
+    let mut defined = HashMap::new();
+    let mut diagnostics = vec![];
+
+    for (number, i) in self.ids(entity).filter(|(_, i)| i.defines) {
+      match defined.get(&i.id) {
+        Some(first) => diagnostics.push(
+          Diagnostic::new(
+            number,
+            i.range.0,
+            i.range.1,
+            Severity::Warning,
+            format!("{:?} ID {} is defined more than once", entity, i.id),
+          )
+          .with_target(*first),
+        ),
+        None => {
+          let _ = defined.insert(i.id, number);
+        }
+      }
+    }
+
+    diagnostics
+  }
+
+  /// Find the references to IDs of `entity` that are not defined. IDs for
+  /// which `known` returns true are defined elsewhere, e.g. in an include
+  /// file. Returns a [`Diagnostic`](crate::diagnostics::Diagnostic) for each
+  /// reference.
+  pub fn undefined<F>(&self, entity: Entity, known: F) -> Vec<Diagnostic>
+  where
+    F: Fn(i64) -> bool,
+  {
+    let defined = self.definitions(entity);
+
+    self
+      .ids(entity)
+      .filter(|(_, i)| !i.defines)
+      .filter(|(_, i)| !defined.contains_key(&i.id) && !known(i.id))
+      .map(|(number, i)| {
+        Diagnostic::new(
+          number,
+          i.range.0,
+          i.range.1,
+          Severity::Warning,
+          format!("{:?} {} is not defined", entity, i.id),
+        )
+      })
+      .collect()
+  }
+}
+
+/// Renumber the entities of type `entity` defined on the keyword lines in
+/// `first..last` to `start`, `start + step`, ... in the order of their
+/// occurence, and update all references to them. `lines` are the number, text
+/// and keyword of all keyword lines of the buffer.
+///
+/// Returns the changed lines, and a
+/// [`Diagnostic`](crate::diagnostics::Diagnostic) for each new ID that's
+/// already in use outside the range or does not fit into its cell. In the
+/// latter case, the line is left unchanged.
+pub fn renumber(
+  lines: &[(LineNr, &[u8], Keyword)],
+  entity: Entity,
+  start: i64,
+  step: i64,
+  first: LineNr,
+  last: LineNr,
+) -> Edits {
+  let mut mapping = HashMap::new();
+  let mut next = start;
+  let mut diagnostics = vec![];
+
+  for (number, text, kw) in lines.iter().filter(|l| first <= l.0 && l.0 < last)
+  {
+    for (id, _, _) in ids(*kw, text, entity).into_iter().filter(|i| i.2) {
+      let _ = mapping.insert(id, (next, *number));
+      next += step;
+    }
+  }
+
+  for (number, text, kw) in lines.iter().filter(|l| l.0 < first || last <= l.0)
+  {
+    for (id, range, _) in ids(*kw, text, entity).into_iter().filter(|i| i.2) {
+      if let Some((_, line)) = mapping.values().find(|(new, _)| *new == id) {
+        diagnostics.push(
+          Diagnostic::new(
+            *line,
+            range.0,
+            range.1,
+            Severity::Warning,
+            format!("{:?} ID {} is already in use", entity, id),
+          )
+          .with_target(*number),
+        );
+      }
+    }
+  }
+
+  let edits = replace_ids(
+    lines,
+    |e, id| {
+      if e == entity {
+        mapping.get(&id).map(|n| n.0)
+      } else {
+        None
+      }
+    },
+    &mut diagnostics,
+  );
+
+  (edits, diagnostics)
+}
+
+/// Replace the IDs on the keyword lines `lines` by the ones `new_id` returns
+/// for their entity and ID, leaving those alone for which it returns `None`.
+/// The new IDs are right-aligned in their cells. If one does not fit, an
+/// error is pushed to `diagnostics` and its line is left unchanged. Returns
+/// the changed lines.
+fn replace_ids<F>(
+  lines: &[(LineNr, &[u8], Keyword)],
+  new_id: F,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<(LineNr, String)>
+where
+  F: Fn(Entity, i64) -> Option<i64>,
+{
+  let mut edits = vec![];
+
+  'lines: for (number, text, kw) in lines {
+    let mut newline = text.to_vec();
+    let mut changed = false;
+
+    for XrefId {
+      entity, id, range, ..
+    } in all_ids(*kw, text)
+    {
+      let new = match new_id(entity, id) {
+        Some(n) => n,
+        None => continue,
+      };
+
+      let width = (range.1 - range.0) as usize;
+      let newid = format!("{:>width$}", new, width = width);
+
+      if newid.len() > width {
+        diagnostics.push(Diagnostic::new(
+          *number,
+          range.0,
+          range.1,
+          Severity::Error,
+          format!("{:?} ID {} does not fit into the cell", entity, new),
+        ));
+        continue 'lines;
+      }
+
+      if newline.len() < range.1 as usize {
+        newline.resize(range.1 as usize, b' ');
+      }
+      newline[range.0 as usize..range.1 as usize]
+        .copy_from_slice(newid.as_bytes());
+      changed = true;
+    }
+
+    if changed {
+      edits.push((*number, String::from_utf8_lossy(&newline).into_owned()));
+    }
+  }
+
+  edits
+}
+
+/// Rename the entity of type `entity` with the ID `old` to `new` on the
+/// keyword lines `lines`, i.e. change its definition and all references to
+/// it. IDs for which `known` returns true are defined elsewhere, e.g. in an
+/// include file.
+///
+/// Returns an error if `old` is not defined on the lines. If `new` is
+/// already defined, nothing is changed and a
+/// [`Diagnostic`](crate::diagnostics::Diagnostic) on the definition of `old`
+/// is returned. Otherwise, returns the changed lines, and a diagnostic for
+/// each line where `new` does not fit into the cell.
+pub fn rename<F>(
+  lines: &[(LineNr, &[u8], Keyword)],
+  entity: Entity,
+  old: i64,
+  new: i64,
+  known: F,
+) -> Result<Edits, String>
+where
+  F: Fn(i64) -> bool,
+{
+  let (line, range) = lines
+    .iter()
+    .find_map(|(number, text, kw)| {
+      ids(*kw, text, entity)
+        .into_iter()
+        .find(|i| i.2 && i.0 == old)
+        .map(|i| (*number, i.1))
+    })
+    .ok_or_else(|| format!("{:?} {} is not defined", entity, old))?;
+
+  if old == new {
+    return Ok((vec![], vec![]));
+  }
+
+  let exists = |place: &str| {
+    Diagnostic::new(
+      line,
+      range.0,
+      range.1,
+      Severity::Error,
+      format!("{:?} ID {} already exists{}", entity, new, place),
+    )
+  };
+
+  if let Some(target) = definitions(lines, entity).get(&new) {
+    return Ok((vec![], vec![exists("").with_target(*target)]));
+  }
+  if known(new) {
+    return Ok((vec![], vec![exists(" in an include file")]));
+  }
+
+  let mut diagnostics = vec![];
+  let edits = replace_ids(
+    lines,
+    |e, id| {
+      if e == entity && id == old {
+        Some(new)
+      } else {
+        None
+      }
+    },
+    &mut diagnostics,
+  );
+
+  Ok((edits, diagnostics))
+}
+
+/// Add `offset` to the IDs of the `entities` defined on the keyword lines of
+/// the first of `files`, e.g. an include file, and update the references to
+/// them in all of `files`. `files` are the number, text and keyword of the
+/// keyword lines of each file.
+///
+/// Returns the changed lines of each file in the order of `files`, along
+/// with a [`Diagnostic`](crate::diagnostics::Diagnostic) for each new ID
+/// that's already defined in one of the other files, and for each one that
+/// does not fit into its cell. In the latter case, the line is left
+/// unchanged.
+pub fn offset(
+  files: &[Vec<(LineNr, &[u8], Keyword)>],
+  entities: &[Entity],
+  offset: i64,
+) -> Vec<Edits> {
+  let region = match files.first() {
+    Some(r) => r,
+    None => return vec![],
+  };
+
+  let defined_in = |lines: &[(LineNr, &[u8], Keyword)]| {
+    lines
+      .iter()
+      .flat_map(|(number, text, kw)| {
+        all_ids(*kw, text).into_iter().map(move |i| (*number, i))
+      })
+      .filter(|(_, i)| i.defines && entities.contains(&i.entity))
+      .collect::<Vec<_>>()
+  };
+
+  let moved: HashSet<(Entity, i64)> = defined_in(region)
+    .into_iter()
+    .map(|(_, i)| (i.entity, i.id))
+    .collect();
+  let others: HashSet<(Entity, i64)> = files[1..]
+    .iter()
+    .flat_map(|lines| defined_in(lines))
+    .map(|(_, i)| (i.entity, i.id))
+    .collect();
+
+  let mut results: Vec<_> = files
+    .iter()
+    .map(|lines| {
+      let mut diagnostics = vec![];
+      let edits = replace_ids(
+        lines,
+        |e, id| {
+          if moved.contains(&(e, id)) {
+            Some(id + offset)
+          } else {
+            None
+          }
+        },
+        &mut diagnostics,
+      );
+      (edits, diagnostics)
+    })
+    .collect();
+
+  for (number, i) in defined_in(region) {
+    if others.contains(&(i.entity, i.id + offset)) {
+      results[0].1.push(Diagnostic::new(
+        number,
+        i.range.0,
+        i.range.1,
+        Severity::Warning,
+        format!(
+          "{:?} ID {} is already defined in another file",
+          i.entity,
+          i.id + offset
+        ),
+      ));
+    }
+  }
+
+  results
+}
+
+/// The IDs of `entity` defined on the keyword lines `lines`, along with the
+/// line of their first definition
+pub fn definitions(
+  lines: &[(LineNr, &[u8], Keyword)],
+  entity: Entity,
+) -> HashMap<i64, LineNr> {
+  XrefIndex::new(lines).definitions(entity)
+}
+
+/// Find the IDs of `entity` that are defined more than once on the keyword
+/// lines `lines`, see
+/// [`XrefIndex::duplicates`](crate::card::xref::XrefIndex::duplicates)
+pub fn duplicates(
+  lines: &[(LineNr, &[u8], Keyword)],
+  entity: Entity,
+) -> Vec<Diagnostic> {
+  XrefIndex::new(lines).duplicates(entity)
+}
+
+/// Find the references to IDs of `entity` on the keyword lines `lines` that
+/// are not defined there, see
+/// [`XrefIndex::undefined`](crate::card::xref::XrefIndex::undefined)
+pub fn undefined<F>(
+  lines: &[(LineNr, &[u8], Keyword)],
+  entity: Entity,
+  known: F,
+) -> Vec<Diagnostic>
+where
+  F: Fn(i64) -> bool,
+{
+  XrefIndex::new(lines).undefined(entity, known)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    card::{
+      keyword::Keyword::{self, *},
+      xref::{
+        duplicates, offset, rename, renumber, undefined, Entity, XrefIndex,
+      },
+    },
+    linenr::LineNr,
+  };
+
+  const LINES: [(&str, Keyword); 5] = [
+    (
+      "NODE  /       10              0.             0.5              0.",
+      Node,
+    ),
+    (
+      "NODE  /       11              0.             0.5              0.",
+      Node,
+    ),
+    (
+      "NODE  /       12              0.             0.5              0.",
+      Node,
+    ),
+    (
+      "SHELL /     3129       1      10      11      12      10",
+      Shell,
+    ),
+    (
+      "PART  /        1   SHELL       2       0       0       0",
+      PartShell,
+    ),
+  ];
+
+  fn lines() -> Vec<(LineNr, &'static [u8], Keyword)> {
+    LINES
+      .iter()
+      .enumerate()
+      .map(|(i, (t, k))| (LineNr::from_usize(i), t.as_ref(), *k))
+      .collect()
+  }
+
+  #[test]
+  fn renumber_nodes() {
+    let (edits, diags) = renumber(
+      &lines(),
+      Entity::Node,
+      100,
+      2,
+      LineNr::from_usize(0),
+      LineNr::from_usize(3),
+    );
+
+    assert!(diags.is_empty());
+    assert_eq!(edits.len(), 4);
+    assert_eq!(&edits[1].1[..16], "NODE  /      102");
+    assert_eq!(
+      edits[3].1,
+      "SHELL /     3129       1     100     102     104     100"
+    );
+  }
+
+  #[test]
+  fn renumber_reports_conflicts() {
+    let (edits, diags) = renumber(
+      &lines(),
+      Entity::Node,
+      11,
+      1,
+      LineNr::from_usize(0),
+      LineNr::from_usize(1),
+    );
+
+    assert_eq!(edits.len(), 2);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].target, Some(LineNr::from_usize(1)));
+
+    let (edits, diags) = renumber(
+      &lines(),
+      Entity::Part,
+      123_456_789,
+      1,
+      LineNr::from_usize(4),
+      LineNr::from_usize(5),
+    );
+
+    assert!(edits.is_empty());
+    assert_eq!(diags.len(), 2);
+  }
+
+  #[test]
+  fn duplicate_ids() {
+    let mut lines = lines();
+    lines[2].1 =
+      b"NODE  /       10              0.             0.5              0.";
+    lines.push((
+      LineNr::from_usize(5),
+      b"PART  /        2   SHELL       2       0       0       0",
+      PartShell,
+    ));
+    lines.push((
+      LineNr::from_usize(6),
+      b"PART  /        1   SHELL       2       0       0       0",
+      PartShell,
+    ));
+
+    let diags = duplicates(&lines, Entity::Node);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(2));
+    assert_eq!(diags[0].target, Some(LineNr::from_usize(0)));
+    assert_eq!((diags[0].start, diags[0].end), (8, 16));
+
+    let diags = duplicates(&lines, Entity::Part);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(6));
+    assert_eq!(diags[0].target, Some(LineNr::from_usize(4)));
+
+    assert!(duplicates(&lines, Entity::Element).is_empty());
+  }
+
+  #[test]
+  fn undefined_references() {
+    let mut lines = lines();
+    let _ = lines.remove(1);
+
+    let diags = undefined(&lines, Entity::Node, |_| false);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(3));
+    assert_eq!((diags[0].start, diags[0].end), (32, 40));
+    assert_eq!(diags[0].message, "Node 11 is not defined");
+    assert!(undefined(&lines, Entity::Node, |id| id == 11).is_empty());
+
+    assert!(undefined(&lines, Entity::Part, |_| false).is_empty());
+    let _ = lines.pop();
+    let diags = undefined(&lines, Entity::Part, |_| false);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].message, "Part 1 is not defined");
+  }
+
+  #[test]
+  fn rename_node() {
+    let (edits, diags) =
+      rename(&lines(), Entity::Node, 10, 1_234, |_| false).unwrap();
+
+    assert!(diags.is_empty());
+    assert_eq!(edits.len(), 2);
+    assert_eq!(edits[0].0, LineNr::from_usize(0));
+    assert_eq!(&edits[0].1[..16], "NODE  /     1234");
+    assert_eq!(
+      edits[1].1,
+      "SHELL /     3129       1    1234      11      12    1234"
+    );
+
+    let (edits, diags) =
+      rename(&lines(), Entity::Part, 1, 5, |_| false).unwrap();
+    assert!(diags.is_empty());
+    assert_eq!(edits.len(), 2);
+    assert_eq!(edits[0].0, LineNr::from_usize(3));
+    assert_eq!(edits[1].0, LineNr::from_usize(4));
+  }
+
+  #[test]
+  fn rename_refuses_existing_ids() {
+    let (edits, diags) =
+      rename(&lines(), Entity::Node, 10, 12, |_| false).unwrap();
+    assert!(edits.is_empty());
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(0));
+    assert_eq!(diags[0].target, Some(LineNr::from_usize(2)));
+    assert_eq!(diags[0].message, "Node ID 12 already exists");
+
+    let (edits, diags) =
+      rename(&lines(), Entity::Node, 10, 20, |id| id == 20).unwrap();
+    assert!(edits.is_empty());
+    assert_eq!(
+      diags[0].message,
+      "Node ID 20 already exists in an include file"
+    );
+
+    assert_eq!(
+      rename(&lines(), Entity::Node, 13, 20, |_| false),
+      Err("Node 13 is not defined".to_string())
+    );
+  }
+
+  #[test]
+  fn offset_include() {
+    let region = lines()[..3].to_vec();
+    let deck = vec![
+      lines()[3],
+      (
+        LineNr::from_usize(1),
+        b"NODE  /     1011              0.             0.5              0.",
+        Node,
+      ),
+    ];
+
+    let results = offset(&[region, deck], &[Entity::Node], 1_000);
+    assert_eq!(results.len(), 2);
+
+    let (edits, diags) = &results[0];
+    assert_eq!(edits.len(), 3);
+    assert_eq!(&edits[2].1[..16], "NODE  /     1012");
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(1));
+    assert_eq!(
+      diags[0].message,
+      "Node ID 1011 is already defined in another file"
+    );
+
+    let (edits, diags) = &results[1];
+    assert!(diags.is_empty());
+    assert_eq!(
+      edits,
+      &vec![(
+        LineNr::from_usize(3),
+        "SHELL /     3129       1    1010    1011    1012    1010".to_string()
+      )]
+    );
+
+    let results = offset(&[lines()], &[Entity::Element, Entity::Part], 5);
+    let (edits, _) = &results[0];
+    assert_eq!(
+      edits,
+      &vec![
+        (
+          LineNr::from_usize(3),
+          "SHELL /     3134       6      10      11      12      10"
+            .to_string()
+        ),
+        (
+          LineNr::from_usize(4),
+          "PART  /        6   SHELL       2       0       0       0"
+            .to_string()
+        ),
+      ]
+    );
+    assert!(offset(&[], &[Entity::Node], 1).is_empty());
+  }
+
+  #[test]
+  fn index_splices() {
+    let lines = lines();
+    let mut index = XrefIndex::new(&lines);
+
+    // Remove the second node, and insert a new one before the shell
+    let new: &[u8] =
+      b"NODE  /       13              0.             0.5              0.";
+    index.splice(
+      LineNr::from_usize(1),
+      LineNr::from_usize(2),
+      1,
+      &[
+        (LineNr::from_usize(1), new, Node),
+        (LineNr::from_usize(2), new, Node),
+      ],
+    );
+
+    let mut expected = lines.clone();
+    expected[1].1 = new;
+    expected.insert(2, (LineNr::from_usize(2), new, Node));
+    for (i, l) in expected.iter_mut().enumerate() {
+      l.0 = LineNr::from_usize(i);
+    }
+    assert_eq!(index, XrefIndex::new(&expected));
+
+    let diags = index.undefined(Entity::Node, |_| false);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(4));
+    assert_eq!(diags[0].message, "Node 11 is not defined");
+    let diags = index.duplicates(Entity::Node);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].line, LineNr::from_usize(2));
+    assert_eq!(diags[0].target, Some(LineNr::from_usize(1)));
+
+    // Remove all but the part
+    index.splice(LineNr::from_usize(0), LineNr::from_usize(5), -5, &[]);
+    assert!(index.definitions(Entity::Node).is_empty());
+    assert_eq!(
+      index.definitions(Entity::Part).get(&1),
+      Some(&LineNr::from_usize(0))
+    );
+  }
+}
@@ -1,7 +1,12 @@
 //! This modules holds the the global static auxiliary
 //! [`Card`](crate::card::Card) instances.
 use crate::card::{
-  cell::Cell::*, ges::GesType::*, keyword::Keyword::*, line::Line::*, Card,
+  cell::{Cell::*, FixedStr},
+  ges::GesType::*,
+  keyword::Keyword::{self, *},
+  line::{CondResult, Conditional::*, Line::*},
+  parse::CellLine,
+  Card,
 };
 
 pub static GROUP: Card = Card {
@@ -13,6 +18,77 @@ pub static GROUP: Card = Card {
   ownfold: true,
 };
 
+/// The number of points NPTS controls the number of `X Y` lines. Tables with
+/// NPTS = 0 are ended by an `END` line instead, which is not supported.
+pub static FUNCT: Card = Card {
+  lines: &[
+    Provides(
+      &[
+        Kw(Funct),
+        Integer(8),
+        Integer(8),
+        Float(8),
+        Float(8),
+        Float(8),
+        Float(8),
+        Integer(8),
+        Integer(8),
+      ],
+      Number(16..24),
+    ),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Repeat(&[Blank(16), Float(16), Float(16)], 0),
+  ],
+  ownfold: true,
+};
+
+/// The `ARGUMENT` blocks don't have a count, so they are skipped up to the
+/// `FUNCTION` block, which is skipped up to its `END`.
+pub static LOOKU: Card = Card {
+  lines: &[
+    Cells(&[Kw(Looku), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Opaque(b"        FUNCTION"),
+    Opaque(b"        END"),
+  ],
+  ownfold: true,
+};
+
+/// The foldtext of a fold over a function table of `lines` lines, whose first
+/// line is `text`. It shows the ID of the table, and the number of points of
+/// a `FUNCT`. Returns `None` for other cards.
+pub fn table_foldtext(
+  kw: Keyword,
+  text: &[u8],
+  lines: isize,
+) -> Option<String> {
+  let card: &Card = (&kw).into();
+  let id = CellLine::new(&card.layout(text)[0], text)
+    .and_then(|l| l.str(1).ok())
+    .unwrap_or_default();
+
+  match kw {
+    Funct => match Number(16..24).evaluate(text) {
+      CondResult::Number(Some(n)) => {
+        Some(format!(" FUNCT {}: {} points ", id, n))
+      }
+      _ => Some(format!(" {} lines: FUNCT {} ", lines, id)),
+    },
+    Looku => Some(format!(" {} lines: LOOKU {} ", lines, id)),
+    _ => None,
+  }
+}
+
+pub static ENCRYPTED: Card = Card {
+  lines: &[Cells(&[Kw(Encrypted), Str(72)]), Opaque(b"END_ENCRYPTED")],
+  ownfold: true,
+};
+
+pub static UNKNOWN: Card = Card {
+  lines: &[Cells(&[Kw(Unknown), Str(72)]), Rest],
+  ownfold: true,
+};
+
 #[cfg(test)]
 mod tests {
   use crate::card::keyword::Keyword::*;
@@ -50,4 +126,199 @@ mod tests {
     vec![(0, 10, Group)]
   );
 
+  const CARD_GROUP3: [&'static str; 10] = [
+    "GROUP / TitleOfTheGroup",
+    "        ELE ",
+    "        DELNOD ",
+    "        PART 14 ",
+    "        END",
+    "GROUP / TitleOfTheGroup2",
+    "        ELE ",
+    "        END",
+    "GROUP / TitleOfTheGroup3",
+    "        ELE ",
+  ];
+
+  #[test]
+  fn fold_ges() {
+    use crate::{bufdata::BufData, linenr::LineNr};
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&CARD_GROUP3).unwrap();
+
+    let ges_folds = |b: &BufData| match b.fold_calls() {
+      Value::Array(v) => v[2].clone(),
+      v => panic!("Not an array: {:?}", v),
+    };
+    let fold = |s: u64, e: u64, t: &str| {
+      Value::from(vec![Value::from(s), Value::from(e), Value::from(t)])
+    };
+
+    // The last GES only has a single line
+    assert_eq!(
+      ges_folds(&bufdata),
+      Value::from(vec![
+        fold(2, 5, " GES (3 entries) "),
+        fold(7, 8, " GES (1 entries) "),
+      ])
+    );
+
+    let _ = bufdata
+      .update(
+        LineNr::from_usize(2),
+        LineNr::from_usize(2),
+        vec!["        ELE ".to_string()],
+      )
+      .unwrap();
+    assert_eq!(
+      ges_folds(&bufdata),
+      Value::from(vec![
+        fold(2, 6, " GES (4 entries) "),
+        fold(8, 9, " GES (1 entries) "),
+      ])
+    );
+  }
+
+  const CARD_FUNCT: [&'static str; 11] = [
+    "$FUNCT Card with Sinus Function",
+    "FUNCT /        1       3      1.      1.      0.      0.       0       0",
+    "NAME Sinusfunktion      ",
+    "                              0.              0.",
+    "$ A comment",
+    "                              2.     0.000986001",
+    "                              4.        0.003939",
+    "FUNCT /        2       1      1.      1.      0.      0.       0       0",
+    "NAME Constant",
+    "                              0.              1.",
+    "NODE  /        1              0.             0.5              0.",
+  ];
+
+  cardtest!(
+    fold_funct,
+    CARD_FUNCT,
+    vec![(1, 6, Funct), (7, 9, Funct), (10, 10, Node)],
+    vec![(1, 9, Funct)]
+  );
+
+  const CARD_LOOKU: [&'static str; 11] = [
+    "LOOKU /        7",
+    "NAME Look Up->1",
+    "$#                  IARG    NAMA    IINT   ILEXT   IUEXT            ARGD",
+    "        ARGUMENT       3",
+    "        END",
+    "        ARGUMENT       4",
+    "        END",
+    "        FUNCTION",
+    "                  1.",
+    "        END",
+    "NODE  /        1              0.             0.5              0.",
+  ];
+
+  cardtest!(fold_looku, CARD_LOOKU, vec![(0, 9, Looku), (10, 10, Node)]);
+
+  #[test]
+  fn funct_foldtexts() {
+    use crate::bufdata::BufData;
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&CARD_FUNCT).unwrap();
+
+    let texts: Vec<_> = match bufdata.fold_calls() {
+      Value::Array(v) => match v[0] {
+        Value::Array(ref folds) => folds
+          .iter()
+          .map(|f| f.as_array().unwrap()[2].as_str().unwrap().to_string())
+          .collect(),
+        ref f => panic!("Not an array: {:?}", f),
+      },
+      v => panic!("Not an array: {:?}", v),
+    };
+    assert_eq!(
+      texts,
+      vec![
+        " FUNCT 1: 3 points ",
+        " FUNCT 2: 1 points ",
+        " 1 lines: Node "
+      ]
+    );
+
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&CARD_LOOKU).unwrap();
+    match bufdata.fold_calls() {
+      Value::Array(v) => assert_eq!(
+        v[0].as_array().unwrap()[0],
+        Value::from(vec![
+          Value::from(1),
+          Value::from(10),
+          Value::from(" 10 lines: LOOKU 7 ")
+        ])
+      ),
+      v => panic!("Not an array: {:?}", v),
+    }
+  }
+
+  #[test]
+  fn funct_points_highlighted() {
+    use crate::{
+      bufdata::{highlights::HighlightGroup::*, BufData},
+      linenr::LineNr,
+    };
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&CARD_FUNCT).unwrap();
+
+    let points: Vec<_> = bufdata
+      .highlights
+      .iter()
+      .filter(|((l, _, _), _)| *l == LineNr::from_usize(5))
+      .map(|((_, s, e), h)| (s, e, h))
+      .collect();
+    assert_eq!(
+      points,
+      vec![(0, 16, CellOdd), (16, 32, CellEven), (32, 48, CellOdd)]
+    );
+
+    let comment =
+      bufdata.hl_linerange(LineNr::from_usize(4), LineNr::from_usize(5));
+    assert!(comment.start == comment.end);
+  }
+
+  const CARD_ENCRYPTED: [&'static str; 7] = [
+    "BEGIN_ENCRYPTED",
+    "NODE  / 8dK3nq0ZmVb1xWcQe7sPaAuLr2tYhGjE",
+    "        Qk9ERSAgLyAgICAgICAgMSAgICAgICAgICAgIDAu",
+    "END_ENCRYPTED",
+    "NODE  /        1              0.             0.5              0.",
+    "BEGIN_ENCRYPTED",
+    "    bWFzcyBwYXJ0aWNsZXM=",
+  ];
+
+  cardtest!(
+    fold_encrypted,
+    CARD_ENCRYPTED,
+    vec![(0, 3, Encrypted), (4, 4, Node), (5, 6, Encrypted)]
+  );
+
+  #[test]
+  fn encrypted_not_highlighted() {
+    use crate::{bufdata::BufData, linenr::LineNr};
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&CARD_ENCRYPTED).unwrap();
+
+    let inner =
+      bufdata.hl_linerange(LineNr::from_usize(1), LineNr::from_usize(4));
+    assert!(inner.start == inner.end);
+    let node =
+      bufdata.hl_linerange(LineNr::from_usize(4), LineNr::from_usize(5));
+    assert!(node.start < node.end);
+  }
 }
@@ -184,6 +184,102 @@ pub static RBODY3: Card = Card {
   ownfold: true,
 };
 
+pub static BOUNC: Card = Card {
+  lines: &[
+    Cells(&[
+      Kw(Bounc),
+      Integer(8),
+      Blank(2),
+      Binary(6),
+      Integer(8),
+      Integer(8),
+    ]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+/// A `&` in column 81 of the first line means the direction of the
+/// displacement is given by a vector on the line after the `NAME`.
+pub static DIS3D: Card = Card {
+  lines: &[
+    Provides(
+      &[
+        Kw(Dis3d),
+        Integer(8),
+        Integer(8),
+        Integer(8),
+        Integer(8),
+        Float(8),
+        Float(8),
+        Float(8),
+        Integer(8),
+        Integer(8),
+        Cont,
+      ],
+      RelChar(80, b'&'),
+    ),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Optional(&[Blank(8), Float(16), Float(16), Float(16)], 0),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+/// Same layout as [`DIS3D`](crate::carddata::constraint::DIS3D)
+pub static VEL3D: Card = Card {
+  lines: &[
+    Provides(
+      &[
+        Kw(Vel3d),
+        Integer(8),
+        Integer(8),
+        Integer(8),
+        Integer(8),
+        Float(8),
+        Float(8),
+        Float(8),
+        Integer(8),
+        Integer(8),
+        Cont,
+      ],
+      RelChar(80, b'&'),
+    ),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Optional(&[Blank(8), Float(16), Float(16), Float(16)], 0),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+/// A `&` in column 81 of the first line means the direction of the field is
+/// given by a vector on the line after the `NAME`.
+pub static ACFLD: Card = Card {
+  lines: &[
+    Provides(
+      &[
+        Kw(Acfld),
+        Integer(8),
+        Integer(8),
+        Float(8),
+        Integer(8),
+        Float(8),
+        Integer(8),
+        Float(8),
+        Integer(8),
+        Integer(8),
+        Cont,
+      ],
+      RelChar(80, b'&'),
+    ),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Optional(&[Blank(8), Float(16), Float(16), Float(16)], 0),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
 #[cfg(test)]
 mod tests {
   use crate::card::keyword::Keyword::*;
@@ -292,4 +388,55 @@ mod tests {
 
   cardtest!(fold_rbody3, CARD_RBODY3, vec![(2, 7, Rbody3)]);
 
+  const CARD_BOUNC: [&'static str; 7] = [
+    "$#         IDNOD  XYZUVW    IFRA   ISENS",
+    "BOUNC /        1  111000       0       0",
+    "$#                                                                         TITLE",
+    "NAME BOUNC / ->1                                                                ",
+    "        NOD 1 2 3",
+    "        PART 12",
+    "        END",
+  ];
+
+  cardtest!(fold_bounc, CARD_BOUNC, vec![(1, 6, Bounc)]);
+
+  const CARD_DIS3D: [&'static str; 11] = [
+    "$#         IDNOD   IFUN1   IFUN2   IFUN3   SFAC1   SFAC2   SFAC3    IFRA   ISEAND",
+    "DIS3D /        0       1       0       0      1.      1.      1.       0       0 ",
+    "NAME DIS3D / ->1                                                                ",
+    "        NOD 1",
+    "        END",
+    "DIS3D /        0       1       0       0      1.      1.      1.       0       0&",
+    "NAME DIS3D / ->1                                                                ",
+    "$#                 DX              DY              DZ",
+    "                0.7071          0.7071              0.",
+    "        NOD 2",
+    "        END",
+  ];
+
+  cardtest!(fold_dis3d, CARD_DIS3D, vec![(1, 4, Dis3d), (5, 10, Dis3d)]);
+
+  const CARD_VEL3D: [&'static str; 6] = [
+    "$#         IDNOD   IFUN1   IFUN2   IFUN3   SFAC1   SFAC2   SFAC3    IFRA   ISEAND",
+    "VEL3D /        0       1       0       0    -10.      0.      0.       0       0&",
+    "NAME VEL3D / ->1                                                                ",
+    "                    1.              0.              0.",
+    "        PART 1",
+    "        END",
+  ];
+
+  cardtest!(fold_vel3d, CARD_VEL3D, vec![(1, 5, Vel3d)]);
+
+  const CARD_ACFLD: [&'static str; 7] = [
+    "$#        IDAFLD   IFUN1   SCAF1   IFUN2   SCAF2   IFUN3   SCAF3    IFRA   ISENS",
+    "ACFLD /        1       0      1.       0      1.       2 9.81E-3       0        ",
+    "$#                                                                         TITLE",
+    "NAME Gravity                                                                    ",
+    "        PART 1",
+    "        PART 2",
+    "        END",
+  ];
+
+  cardtest!(fold_acfld, CARD_ACFLD, vec![(1, 6, Acfld)]);
+
 }
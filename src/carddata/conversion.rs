@@ -0,0 +1,158 @@
+//! This modules holds the global static table of
+//! [`Conversion`](crate::card::conversion::Conversion)s between cards.
+use crate::card::{
+  conversion::{CellSource::*, Conversion},
+  keyword::Keyword::*,
+};
+
+pub static CONVERSIONS: [Conversion; 7] = [
+  Conversion {
+    from: Shell,
+    to: Membr,
+    lines: &[&[
+      Text(b"MEMBR / "),
+      Source(1),
+      Source(2),
+      Source(3),
+      Source(4),
+      Source(5),
+      Source(6),
+      Empty,
+      Source(8),
+      Empty,
+    ]],
+  },
+  Conversion {
+    from: Membr,
+    to: Shell,
+    lines: &[&[
+      Text(b"SHELL / "),
+      Source(1),
+      Source(2),
+      Source(3),
+      Source(4),
+      Source(5),
+      Source(6),
+      Empty,
+      Source(8),
+    ]],
+  },
+  Conversion {
+    from: Mtoco,
+    to: Otmco,
+    lines: &[
+      &[
+        Text(b"OTMCO / "),
+        Source(1),
+        Source(2),
+        Source(3),
+        Empty,
+        Empty,
+        Empty,
+        Empty,
+        Source(7),
+      ],
+      &[Source(0), Source(1)],
+    ],
+  },
+  Conversion {
+    from: Otmco,
+    to: Mtoco,
+    lines: &[
+      &[
+        Text(b"MTOCO / "),
+        Source(1),
+        Source(2),
+        Source(3),
+        Empty,
+        Empty,
+        Empty,
+        Source(8),
+      ],
+      &[Source(0), Source(1)],
+    ],
+  },
+  Conversion {
+    from: Tetr4,
+    to: Tetr10,
+    lines: &[
+      &[Text(b"TETR10/ "), Source(1), Source(2)],
+      &[
+        Empty,
+        Cell(0, 3),
+        Cell(0, 4),
+        Cell(0, 5),
+        Cell(0, 6),
+        Manual("node 5"),
+        Manual("node 6"),
+        Manual("node 7"),
+        Manual("node 8"),
+      ],
+      &[Empty, Manual("node 9"), Manual("node 10")],
+    ],
+  },
+  Conversion {
+    from: Penta6,
+    to: Pent15,
+    lines: &[
+      &[Text(b"PENT15/ "), Source(1), Source(2)],
+      &[
+        Empty,
+        Cell(0, 3),
+        Cell(0, 4),
+        Cell(0, 5),
+        Cell(0, 6),
+        Cell(0, 7),
+        Cell(0, 8),
+        Manual("node 7"),
+        Manual("node 8"),
+      ],
+      &[
+        Empty,
+        Manual("node 9"),
+        Manual("node 10"),
+        Manual("node 11"),
+        Manual("node 12"),
+        Manual("node 13"),
+        Manual("node 14"),
+        Manual("node 15"),
+      ],
+    ],
+  },
+  Conversion {
+    from: Solid,
+    to: Hexa20,
+    lines: &[
+      &[Text(b"HEXA20/ "), Source(1), Source(2)],
+      &[
+        Empty,
+        Source(1),
+        Source(2),
+        Source(3),
+        Source(4),
+        Source(5),
+        Source(6),
+        Source(7),
+        Source(8),
+      ],
+      &[
+        Empty,
+        Manual("node 9"),
+        Manual("node 10"),
+        Manual("node 11"),
+        Manual("node 12"),
+        Manual("node 13"),
+        Manual("node 14"),
+        Manual("node 15"),
+        Manual("node 16"),
+      ],
+      &[
+        Empty,
+        Manual("node 17"),
+        Manual("node 18"),
+        Manual("node 19"),
+        Manual("node 20"),
+      ],
+    ],
+  },
+];
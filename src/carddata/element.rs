@@ -3,7 +3,7 @@
 use crate::card::{
   cell::{Cell::*, FixedStr},
   keyword::Keyword::*,
-  line::Line::*,
+  line::{Conditional::*, Line::*},
   Card,
 };
 
@@ -161,18 +161,35 @@ pub static TSHEL: Card = Card {
   ownfold: false,
 };
 
+/// A `&` in column 81 of the keyword line selects the long format, which
+/// spans 2 lines
 pub static SHELL: Card = Card {
-  lines: &[Cells(&[
-    Kw(Shell),
-    Integer(8),
-    Integer(8),
-    Integer(8),
-    Integer(8),
-    Integer(8),
-    Integer(8),
-    Blank(8),
-    Float(8),
-  ])],
+  lines: &[Layouts(
+    &[Cells(&[
+      Kw(Shell),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Blank(8),
+      Float(8),
+    ])],
+    &[
+      Cells(&[
+        Kw(Shell),
+        Integer(16),
+        Integer(16),
+        Integer(16),
+        Integer(16),
+        Blank(8),
+        Cont,
+      ]),
+      Cells(&[Blank(8), Integer(16), Integer(16), Blank(16), Float(16)]),
+    ],
+    RelChar(80, b'&'),
+  )],
   ownfold: false,
 };
 
@@ -373,11 +390,51 @@ pub static IMPMA: Card = Card {
   lines: &[
     Cells(&[Kw(Impma), Integer(8), Str(8), Str(8), Str(8), Integer(8)]),
     Cells(&[Fixed(FixedStr::Name), Str(76)]),
-    Cells(&[Blank(8), Str(76)]),
+    Cells(&[Blank(8), Str(72)]),
   ],
   ownfold: false,
 };
 
+pub static MUSC1: Card = Card {
+  lines: &[
+    Cells(&[Kw(Musc1), Integer(8), Float(8), Float(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Cells(&[Blank(8), Integer(8), Float(8), Float(8), Float(8)]),
+    Cells(&[
+      Blank(8),
+      Integer(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+    ]),
+    Cells(&[
+      Blank(8),
+      Integer(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+    ]),
+    Cells(&[
+      Blank(8),
+      Integer(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+      Float(8),
+    ]),
+    Cells(&[Blank(8), Integer(8), Integer(8), Float(8), Integer(8)]),
+    Cells(&[Blank(8), Fixed(FixedStr::End)]),
+  ],
+  ownfold: true,
+};
+
 #[cfg(test)]
 mod tests {
   use crate::card::keyword::Keyword::*;
@@ -704,4 +761,39 @@ mod tests {
 
   cardtest!(fold_impma, CARD_IMPMA, vec![(2, 13, Impma)]);
 
+  const CARD_MUSC1: [&'static str; 26] = [
+    "$MUSC1 Muscle Element Definition",
+    "$#         IDMUS    Fmax     ALF",
+    "MUSC1 /        1      0.        ",
+    "$#                                                                         TITLE",
+    "NAME MUSC1 / ->1                                                                ",
+    "$# BLANK    NLENNLEN_FAC   ALopt     CSH",
+    "               0                        ",
+    "$# BLANK    NVELNVEL_FAC   AVmax  CSHORT   CLENG    CMVL",
+    "               0                                        ",
+    "$# BLANK    NACTNACT_FAC    Tact  Tdeact   Texci    TREF   Ainit   Einit",
+    "               0                                                        ",
+    "$# BLANK    NPASNPAS_FAC   L0fib   PEmax     CPE   CDAMP",
+    "               0                                        ",
+    "$# BLANK   ITRIG  ELTRIG    WMAS    NMCY",
+    "                                        ",
+    "        END",
+    "MUSC1 /        2      0.        ",
+    "NAME MUSC1 / ->2                                                                ",
+    "               0                        ",
+    "               0                                        ",
+    "               0                                                        ",
+    "               0                                        ",
+    "                                        ",
+    "        END",
+    "$",
+    "NODE  /        1              0.             0.5              0.",
+  ];
+
+  cardtest!(
+    fold_musc1,
+    CARD_MUSC1,
+    vec![(2, 15, Musc1), (16, 23, Musc1), (25, 25, Node)]
+  );
+
 }
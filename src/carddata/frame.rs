@@ -0,0 +1,62 @@
+//! This modules holds the the global static [`Card`](crate::card::Card)
+//! instances of coordinate frames and transformations.
+use crate::card::{
+  cell::{Cell::*, FixedStr},
+  keyword::Keyword::*,
+  line::Line::*,
+  Card,
+};
+
+/// A local coordinate frame, given by 3 nodes
+pub static FRAME: Card = Card {
+  lines: &[
+    Cells(&[Kw(Frame), Integer(8), Integer(8), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Cells(&[Integer(8), Integer(8), Integer(8)]),
+  ],
+  ownfold: true,
+};
+
+/// The steps of the transformation, e.g. `TRANS` or `ROTAT`, have varying
+/// layouts, so they're skipped as a whole.
+pub static TRSFM: Card = Card {
+  lines: &[
+    Cells(&[Kw(Trsfm), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Opaque(b"END_TRSFM"),
+  ],
+  ownfold: true,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_FRAME: [&'static str; 8] = [
+    "$#         IDFRA IFRATYP   IAXIS",
+    "FRAME /        1       0       1",
+    "$#                                                                         TITLE",
+    "NAME FRAME / ->1                                                                ",
+    "$#    NOD1    NOD2    NOD3",
+    "       101     102     103",
+    "FRAME /        2       0       1",
+    "NAME FRAME / ->2                                                                ",
+  ];
+
+  cardtest!(fold_frame, CARD_FRAME, vec![(1, 5, Frame), (6, 7, Frame)]);
+
+  const CARD_TRSFM: [&'static str; 10] = [
+    "$#         IDTRS",
+    "TRSFM /        1",
+    "NAME TRSFM / ->1                                                                ",
+    "$#               DX        DY        DZ",
+    "TRANS         100.        0.        0.",
+    "$#             NOD1    NOD2     ANGLE",
+    "ROTAT             1       2       90.",
+    "END_TRSFM",
+    "TRSFM /        2",
+    "END_TRSFM",
+  ];
+
+  cardtest!(fold_trsfm, CARD_TRSFM, vec![(1, 7, Trsfm), (8, 9, Trsfm)]);
+}
@@ -11,7 +11,7 @@ use crate::card::{
 pub static ELINK: Card = Card {
   lines: &[
     Cells(&[Kw(Elink), Integer(8), Integer(8), Integer(8), Integer(8)]),
-    Ges(GesFace),
+    Ges(GesEle),
   ],
   ownfold: false,
 };
@@ -19,7 +19,7 @@ pub static ELINK: Card = Card {
 pub static LLINK: Card = Card {
   lines: &[
     Cells(&[Kw(Llink), Integer(8), Integer(8), Integer(8), Integer(8)]),
-    Ges(GesFace),
+    Ges(GesEdge),
   ],
   ownfold: false,
 };
@@ -50,7 +50,7 @@ pub static PLINK: Card = Card {
       Integer(8),
       Integer(8),
     ]),
-    Ges(GesFace),
+    Ges(GesNode),
   ],
   ownfold: false,
 };
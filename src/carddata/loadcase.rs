@@ -0,0 +1,73 @@
+//! This modules holds the the global static load case
+//! [`Card`](crate::card::Card) instances. A load case is a container, the
+//! cards up to its `END_LCASE` line belong to it.
+use crate::card::{
+  cell::{Cell::*, FixedStr},
+  keyword::Keyword::*,
+  line::Line::*,
+  Card,
+};
+
+pub static LCASE: Card = Card {
+  lines: &[
+    Cells(&[Kw(Lcase), Integer(8), Float(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Contains(EndLcase),
+  ],
+  ownfold: true,
+};
+
+/// The keyword is `END_LCAS`, the rest of the line is ignored
+pub static END_LCASE: Card = Card {
+  lines: &[Cells(&[Kw(EndLcase), Str(72)])],
+  ownfold: true,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_LCASE: [&'static str; 12] = [
+    "$#          IDLC   SCALE",
+    "LCASE /        1      1.",
+    "NAME LCASE / ->1                                                                ",
+    "BOUNC /        1  111000       0       0",
+    "NAME BOUNC / ->1                                                                ",
+    "        NOD 1",
+    "        END",
+    "BOUNC /        2  111000       0       0",
+    "NAME BOUNC / ->2                                                                ",
+    "END_LCASE",
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        2              0.             0.5              0.",
+  ];
+
+  cardtest!(
+    fold_lcase,
+    CARD_LCASE,
+    vec![
+      (1, 2, Lcase),
+      (3, 6, Bounc),
+      (7, 8, Bounc),
+      (9, 9, EndLcase),
+      (10, 11, Node)
+    ],
+    vec![(1, 9, Lcase)]
+  );
+
+  const CARD_LCASE_UNCLOSED: [&'static str; 6] = [
+    "LCASE /        1      1.",
+    "NAME LCASE / ->1                                                                ",
+    "BOUNC /        1  111000       0       0",
+    "NAME BOUNC / ->1                                                                ",
+    "BOUNC /        2  111000       0       0",
+    "NAME BOUNC / ->2                                                                ",
+  ];
+
+  cardtest!(
+    fold_lcase_unclosed,
+    CARD_LCASE_UNCLOSED,
+    vec![(0, 1, Lcase), (2, 3, Bounc), (4, 5, Bounc)],
+    vec![(2, 5, Bounc)]
+  );
+}
@@ -0,0 +1,313 @@
+//! This modules holds the the global static material
+//! [`Card`](crate::card::Card) instances for composites, and the rupture
+//! models and time-history variables used along with the materials.
+use crate::card::{
+  cell::{Cell::*, FixedStr},
+  keyword::Keyword::*,
+  line::{Conditional::*, Line, Line::*},
+  Card,
+};
+
+// The ply reference lines of the MATER 13x cards, 8 pairs of IDPLY and IDAUX
+const PLY_IDAUX: Line = Cells(&[
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+  Integer(5),
+]);
+
+// Multilayered composite materials, the number of plies NOPER controls the
+// number of `IDPLY THKPL ANGPL` lines
+macro_rules! mater13x {
+  ($k: expr) => {
+    Card {
+      lines: &[
+        Cells(&[
+          Kw($k),
+          Integer(8),
+          Integer(8),
+          Float(16),
+          Integer(8),
+          Integer(8),
+          Integer(8),
+          Integer(8),
+        ]),
+        Cells(&[Blank(48), Float(16), Integer(16)]),
+        Cells(&[Fixed(FixedStr::Name), Str(76)]),
+        Provides(
+          &[
+            Float(10),
+            Float(10),
+            Integer(5),
+            Integer(5),
+            Blank(10),
+            Float(10),
+            Float(10),
+            Float(10),
+            Float(10),
+          ],
+          Number(20..25),
+        ),
+        Repeat(&[Integer(10), Float(10), Float(10)], 0),
+        Cells(&[
+          Blank(10),
+          Integer(5),
+          Blank(5),
+          Float(10),
+          Float(10),
+          Integer(5),
+          Blank(5),
+          Float(10),
+        ]),
+        PLY_IDAUX,
+        PLY_IDAUX,
+        PLY_IDAUX,
+        PLY_IDAUX,
+        PLY_IDAUX,
+        PLY_IDAUX,
+      ],
+      ownfold: true,
+    }
+  };
+}
+
+pub static MATER130: Card = mater13x!(Mater130);
+pub static MATER131: Card = mater13x!(Mater131);
+pub static MATER132: Card = mater13x!(Mater132);
+
+// The data lines of a PLY depend on the ply type ITYP, but all of them consist
+// of fields of width 10.
+const PLY_DATA: Line = Cells(&[
+  Float(10),
+  Float(10),
+  Float(10),
+  Float(10),
+  Float(10),
+  Float(10),
+  Float(10),
+  Float(10),
+]);
+
+// Types 6 and 8 have 2 more data lines than the others.
+const PLY_DATA_OPT: Line = Optional(
+  &[
+    Float(10),
+    Float(10),
+    Float(10),
+    Float(10),
+    Float(10),
+    Float(10),
+    Float(10),
+    Float(10),
+  ],
+  0,
+);
+
+pub static PLY: Card = Card {
+  lines: &[
+    Provides(
+      &[
+        Kw(Ply),
+        Integer(8),
+        Integer(8),
+        Float(16),
+        Integer(8),
+        Integer(8),
+        Float(16),
+      ],
+      IntIn(16..24, &[6, 8]),
+    ),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA,
+    PLY_DATA_OPT,
+    PLY_DATA_OPT,
+  ],
+  ownfold: true,
+};
+
+// The number of plies NPLY controls the number of `IDPLY THKPL ANGPL` lines
+pub static LAYER: Card = Card {
+  lines: &[
+    Provides(&[Kw(Layer), Integer(8), Integer(8)], Number(16..24)),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Repeat(&[Integer(10), Float(10), Float(10)], 0),
+  ],
+  ownfold: true,
+};
+
+// The data lines of a rupture model depend on its type IRUPT
+pub static RUPMO: Card = Card {
+  lines: &[
+    Cells(&[Kw(Rupmo), Integer(8), Integer(8), Integer(8), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Rest,
+  ],
+  ownfold: true,
+};
+
+// The variables of a time-history variable definition depend on the material
+// it is used with
+pub static THVAR: Card = Card {
+  lines: &[
+    Cells(&[Kw(Thvar), Integer(8), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Rest,
+  ],
+  ownfold: true,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_LAYER: [&'static str; 12] = [
+    "$LAYER Material Card",
+    "$#       IDLAYER    NPLY",
+    "LAYER /        1       3",
+    "$#                                                                         TITLE",
+    "NAME Layer->1                                                                   ",
+    "$#   IDPLY     THKPL     ANGPL",
+    "         1        1.        0.",
+    "         2        1.       45.",
+    "         3        1.       90.",
+    "LAYER /        2       1",
+    "NAME Layer->2                                                                   ",
+    "         1        2.        0.",
+  ];
+
+  cardtest!(fold_layer, CARD_LAYER, vec![(2, 8, Layer), (9, 11, Layer)]);
+
+  const CARD_MATER131: [&'static str; 26] = [
+    "$Material Type 131",
+    "$#         IDMAT   MATYP             RHO   ISINT    ISHG  ISTRAT   IFROZ",
+    "MATER /        1     131              0.       0       0                ",
+    "$# BLANK                                                     QVM           IDMPD",
+    "                                                              1.               0",
+    "$#                                                                         TITLE",
+    "NAME 1=>mat131                                                                  ",
+    "$#     KSI        FoNOPER ILAY                 HGM       HGW       HGQ        As",
+    "        0.        0.    2    0                                                  ",
+    "$#   IDPLY     THKPL     ANGPL",
+    "         1        0.        0.",
+    "         2        0.       90.",
+    "$#   BLANK NMINBLANK   GRUC_KW  GRUC_VALIFAILBLANK    ERATIO               BLANK",
+    "              0                             0                                   ",
+    "$#PLIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUX8",
+    "                                                                                ",
+    "$#PLIDAUIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUX16",
+    "                                                                                ",
+    "$#PIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUX24",
+    "                                                                                ",
+    "$#PIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUX32",
+    "                                                                                ",
+    "$#PIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUX40",
+    "                                                                                ",
+    "$#PIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUXIDPLYIDAUX48",
+    "                                                                                ",
+  ];
+
+  cardtest!(fold_mater131, CARD_MATER131, vec![(2, 25, Mater131)]);
+
+  const CARD_PLY: [&'static str; 33] = [
+    "$PLY Type 5",
+    "PLY   /        1       5              0.       0                        ",
+    "NAME PLY   / ->1                                                                ",
+    "$#       A         B     BLANK",
+    "                              ",
+    "$#     LTC",
+    "         0",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "$PLY Type 6",
+    "PLY   /        2       6              0.       0                        ",
+    "NAME PLY   / ->2                                                                ",
+    "        0.        0.        0.",
+    "        0.        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.        0.        0.          ",
+    "",
+    "        0.        0.        0.",
+    "        0.        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.        0.        0.",
+    "        0.        0.        0.        0.        0.        0.        0.",
+    "NODE  /        1              0.             0.5              0.",
+  ];
+
+  const CARD_RUPMO: [&'static str; 22] = [
+    "LAYER /        1       1",
+    "NAME Layer->1                                                                   ",
+    "         1        1.        0.",
+    "$RUPMO Type 5",
+    "$#        IDRUPT   IRUPT   IFMON  FILTER",
+    "RUPMO /        1       5                ",
+    "NAME RUPMO / ->1                                                                ",
+    "              0.      0.      0.      0.      0.      0.       0                ",
+    "                                        ",
+    "RUPMO /        2       6        ",
+    "NAME RUPMO / ->2                                                                ",
+    "                                                                ",
+    "THVAR /        1       2",
+    "NAME THVAR / ->1                                                                ",
+    "        0.        0.",
+    "LAYER /        2       1",
+    "NAME Layer->2                                                                   ",
+    "         1        2.        0.",
+    "LAYER /        3       2",
+    "NAME Layer->3                                                                   ",
+    "         1        2.        0.",
+    "         2        2.       90.",
+  ];
+
+  cardtest!(
+    fold_rupmo_thvar,
+    CARD_RUPMO,
+    vec![
+      (0, 2, Layer),
+      (5, 8, Rupmo),
+      (9, 11, Rupmo),
+      (12, 14, Thvar),
+      (15, 17, Layer),
+      (18, 21, Layer)
+    ],
+    vec![(5, 11, Rupmo), (15, 21, Layer)]
+  );
+
+  cardtest!(
+    fold_ply,
+    CARD_PLY,
+    vec![(1, 15, Ply), (17, 31, Ply), (32, 32, Node)]
+  );
+}
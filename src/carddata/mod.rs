@@ -35,14 +35,25 @@ macro_rules! cardtest {
 
 pub mod auxiliaries;
 pub mod constraint;
+pub mod conversion;
 pub mod element;
+pub mod frame;
 pub mod link;
+pub mod loadcase;
+pub mod material;
 pub mod node;
+pub mod output;
 pub mod part;
+pub mod revision;
+pub mod thermal;
+pub mod xref;
 
 /// All static declarations can be imported via
 /// ```rust, compile_fail
 /// use carddata::*;
 /// ```
 pub use self::element::*;
-pub use self::{auxiliaries::*, constraint::*, link::*, node::*, part::*};
+pub use self::{
+  auxiliaries::*, constraint::*, conversion::*, frame::*, link::*, loadcase::*,
+  material::*, node::*, output::*, part::*, revision::*, thermal::*, xref::*,
+};
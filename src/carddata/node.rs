@@ -8,14 +8,23 @@ use crate::card::{
   Card,
 };
 
+/// A `&` in column 81 of the keyword line selects the long format, which
+/// spans 2 lines
 pub static NODE: Card = Card {
-  lines: &[Cells(&[
-    Kw(Node),
-    Integer(8),
-    Float(16),
-    Float(16),
-    Float(16),
-  ])],
+  lines: &[Layouts(
+    &[Cells(&[
+      Kw(Node),
+      Integer(8),
+      Float(16),
+      Float(16),
+      Float(16),
+    ])],
+    &[
+      Cells(&[Kw(Node), Integer(16), Float(24), Float(24), Blank(8), Cont]),
+      Cells(&[Blank(24), Float(24)]),
+    ],
+    RelChar(80, b'&'),
+  )],
   ownfold: false,
 };
 
@@ -52,33 +61,48 @@ pub static MASS: Card = Card {
   ownfold: true,
 };
 
+/// A `&` in column 81 of the keyword line announces an additional line with
+/// the scale factors of the mass per length, area and volume. The mass is
+/// distributed over the elements selected by the GES.
 pub static NSMAS: Card = Card {
   lines: &[
-    Cells(&[
-      Kw(Nsmas),
-      Integer(8),
-      Float(16),
-      Float(16),
-      Float(16),
-      Float(16),
-    ]),
+    Provides(
+      &[
+        Kw(Nsmas),
+        Integer(8),
+        Float(16),
+        Float(16),
+        Float(16),
+        Float(16),
+        Cont,
+      ],
+      RelChar(80, b'&'),
+    ),
     Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Optional(&[Blank(8), Float(16), Float(16), Float(16)], 0),
     Ges(GesEle),
   ],
   ownfold: true,
 };
 
+/// Like [`NSMAS`](crate::carddata::node::NSMAS), including the additional
+/// line
 pub static NSMAS2: Card = Card {
   lines: &[
-    Cells(&[
-      Kw(Nsmas2),
-      Integer(8),
-      Float(16),
-      Float(16),
-      Float(16),
-      Float(16),
-    ]),
+    Provides(
+      &[
+        Kw(Nsmas2),
+        Integer(8),
+        Float(16),
+        Float(16),
+        Float(16),
+        Float(16),
+        Cont,
+      ],
+      RelChar(80, b'&'),
+    ),
     Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Optional(&[Blank(8), Float(16), Float(16), Float(16)], 0),
     Ges(GesEle),
   ],
   ownfold: true,
@@ -112,6 +136,59 @@ mod tests {
 
   cardtest!(fold_nsmas2, CARD_NSMAS2, vec![(2, 6, Nsmas)]);
 
+  const CARD_NSMAS_TITLE: [&'static str; 8] = [
+    "NSMAS /        1              0.                                                ",
+    "$#                                                                         TITLE",
+    "NAME A title that is too long for a ->1",
+    "NAME single line, so it is continued ->2",
+    "NAME on the next lines",
+    "        ELE 123",
+    "        END",
+    "#Comment",
+  ];
+
+  cardtest!(fold_nsmas_title, CARD_NSMAS_TITLE, vec![(0, 6, Nsmas)]);
+
+  const CARD_NSMAS_CRLF: [&'static str; 5] = [
+    "NSMAS /        1              0.\r",
+    "NAME A title that is continued ->1\r",
+    "NAME on the next line\r",
+    "        ELE 123\r",
+    "        END\r",
+  ];
+
+  cardtest!(fold_nsmas_crlf, CARD_NSMAS_CRLF, vec![(0, 4, Nsmas)]);
+
+  const CARD_NSMAS_OPT: [&'static str; 7] = [
+    "NSMAS /        1              0.                                                &",
+    "NAME NSMAS / ->1                                                                ",
+    "$# BLANK           SCALL           SCALA           SCALV",
+    "                      1.              1.              1.",
+    "        ELE 123",
+    "        END",
+    "#Comment",
+  ];
+
+  cardtest!(fold_nsmas_opt, CARD_NSMAS_OPT, vec![(0, 5, Nsmas)]);
+
+  const CARD_NSMAS2_CARDS: [&'static str; 9] = [
+    "NSMAS2/        1              0.                                                &",
+    "NAME NSMAS2/ ->1                                                                ",
+    "                      1.              1.              1.",
+    "        ELE 123",
+    "        END",
+    "NSMAS2/        2              0.                                                ",
+    "NAME NSMAS2/ ->2                                                                ",
+    "        PART 1",
+    "        END",
+  ];
+
+  cardtest!(
+    fold_nsmas2_cards,
+    CARD_NSMAS2_CARDS,
+    vec![(0, 4, Nsmas2), (5, 8, Nsmas2)]
+  );
+
   const CARD_MASS: [&'static str; 10] = [
     "$ MASS Card",
     "$#         IDNOD    IFRA   Blank            DISr            DISs            DISt",
@@ -0,0 +1,85 @@
+//! This modules holds the the global static [`Card`](crate::card::Card)
+//! instances of output requests.
+use crate::card::{
+  cell::{Cell::*, FixedStr},
+  ges::GesType::*,
+  keyword::Keyword::*,
+  line::Line::*,
+  Card,
+};
+
+/// Time history output of nodes
+pub static THNOD: Card = Card {
+  lines: &[
+    Cells(&[Kw(Thnod), Integer(8), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+/// Time history output of elements
+pub static THELE: Card = Card {
+  lines: &[
+    Cells(&[Kw(Thele), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesEle),
+  ],
+  ownfold: true,
+};
+
+/// Time history output of nodes in a local frame, given by the frame IFRA or
+/// the nodes IDNOD1 and IDNOD2
+pub static THLOC: Card = Card {
+  lines: &[
+    Cells(&[
+      Kw(Thloc),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Integer(8),
+      Blank(8),
+      Integer(8),
+    ]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_OUTPUT: [&'static str; 16] = [
+    "$THNOD Output",
+    "$#         IDNOD  ISTMOD",
+    "THNOD /        0        ",
+    "NAME THNOD / ->1                                                                ",
+    "        NOD 1 2 3",
+    "        END",
+    "THNOD /        0        ",
+    "NAME THNOD / ->2                                                                ",
+    "        NOD 4",
+    "        END",
+    "THELE /        0",
+    "NAME THELE / ->1                                                                ",
+    "        ELE 12",
+    "        END",
+    "THLOC /        0       0       0       0       0                ",
+    "NAME THLOC / ->1                                                                ",
+  ];
+
+  cardtest!(
+    fold_output,
+    CARD_OUTPUT,
+    vec![
+      (2, 5, Thnod),
+      (6, 9, Thnod),
+      (10, 13, Thele),
+      (14, 15, Thloc)
+    ],
+    vec![(2, 9, Thnod)]
+  );
+}
@@ -22,6 +22,7 @@ macro_rules! part {
           ], Int(25..33, 0)),
         Optional(&[Fixed(FixedStr::Rmat), Str(76)], 0),
         Cells(&[Fixed(FixedStr::Name), Str(76)]),
+        OptionalBlock(b"META", b"END_META"),
         Cells(&[Float(10), Float(10)]),
         Cells(&[Float(10), Float(10), Float(10)]),
         $( $e ),+ ,
@@ -676,4 +677,45 @@ mod tests {
 
   cardtest!(fold_partgap, CARD_PARTGAP, vec![(2, 12, PartGap)]);
 
+  const CARD_PARTSHELL_META: [&'static str; 13] = [
+    "PART  /        1   SHELL       2       0       0       0",
+    "NAME PART_1                                                                     ",
+    "META",
+    "  origin = mesher",
+    "END_META",
+    "                              ",
+    "                              ",
+    "              5               ",
+    "    0                                   ",
+    "END_PART",
+    "PART  /        2   SHELL       2       0       0       0",
+    "NAME PART_2                                                                     ",
+    "END_PART",
+  ];
+
+  cardtest!(
+    fold_partshell_meta,
+    CARD_PARTSHELL_META,
+    vec![(0, 9, PartShell), (10, 12, PartShell)]
+  );
+
+  #[test]
+  fn highlight_parttied_options() {
+    use crate::bufdata::BufData;
+    use neovim_lib::{neovim_api::Buffer, Value};
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&CARD_PARTTIED2).unwrap();
+
+    let lines: Vec<usize> = bufdata
+      .highlights
+      .iter()
+      .map(|((l, _, _), _)| usize::from(l))
+      .collect();
+
+    // The line providing the conditional, and the optional line
+    assert!(lines.contains(&8));
+    assert!(lines.contains(&9));
+  }
 }
@@ -0,0 +1,30 @@
+//! This modules holds the global static table of
+//! [`CardRevision`](crate::card::version::CardRevision)s, along with the
+//! [`Card`](crate::card::Card)s describing the older layouts.
+use crate::card::{
+  cell::{Cell::*, FixedStr},
+  ges::GesType::*,
+  keyword::Keyword::*,
+  line::Line::*,
+  version::{CardRevision, InputVersion},
+  Card,
+};
+
+/// Before version 2010, `NSMAS` only had 3 float cells on its first line
+pub static NSMAS_PRE2010: Card = Card {
+  lines: &[
+    Cells(&[Kw(Nsmas), Integer(8), Float(16), Float(16), Float(16)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesEle),
+  ],
+  ownfold: true,
+};
+
+pub static REVISIONS: [CardRevision; 1] = [CardRevision {
+  keyword: Nsmas,
+  before: InputVersion {
+    year: 2010,
+    minor: 0,
+  },
+  card: &NSMAS_PRE2010,
+}];
@@ -0,0 +1,208 @@
+//! This modules holds the the global static thermal
+//! [`Card`](crate::card::Card) instances, i.e. the cards of a coupled
+//! mechanical/thermal analysis.
+use crate::card::{
+  cell::{Cell::*, FixedStr},
+  ges::GesType::*,
+  keyword::Keyword::*,
+  line::{Conditional::*, Line::*},
+  Card,
+};
+
+/// The material properties are given by lines of varying layout, started by
+/// the name of the property, e.g. `MASS_DENSITY`, so they're skipped as a
+/// whole.
+pub static THMAT: Card = Card {
+  lines: &[
+    Cells(&[Kw(Thmat), Integer(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Opaque(b"END_THMAT"),
+  ],
+  ownfold: true,
+};
+
+pub static HFLUX: Card = Card {
+  lines: &[
+    Cells(&[
+      Kw(Hflux),
+      Integer(8),
+      Float(8),
+      Integer(8),
+      Integer(8),
+      Float(8),
+    ]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+pub static INTEM: Card = Card {
+  lines: &[
+    Cells(&[Kw(Intem), Integer(8), Float(8)]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+pub static TEMBC: Card = Card {
+  lines: &[
+    Cells(&[
+      Kw(Tembc),
+      Integer(8),
+      Float(8),
+      Integer(8),
+      Float(8),
+      Str(8),
+      Integer(8),
+      Integer(8),
+    ]),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Ges(GesNode),
+  ],
+  ownfold: true,
+};
+
+/// An `IOB` of 1 means the exchange surface is given by a thickness on the
+/// line after the convection parameters.
+pub static HTSURF: Card = Card {
+  lines: &[
+    Provides(
+      &[
+        Kw(Htsurf),
+        Float(8),
+        Integer(8),
+        Integer(8),
+        Blank(8),
+        Integer(8),
+      ],
+      Int(40..48, 1),
+    ),
+    Cells(&[Fixed(FixedStr::Name), Str(76)]),
+    Cells(&[Float(8), Integer(8), Integer(8), Integer(8)]),
+    Optional(&[Float(8)], 0),
+    Ges(GesEle),
+  ],
+  ownfold: true,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_THMAT: [&'static str; 24] = [
+    "$THMAT Thermal Material",
+    "$#         IDMAT",
+    "THMAT /        1",
+    "$#                                                                         TITLE",
+    "NAME THMAT->1                                                                   ",
+    "$#           QUALIFIER",
+    "MASS_DENSITY  CONSTANT ",
+    "$#     RHO",
+    "        0.",
+    "$#           QUALIFIER1 QUALIFIER2",
+    "CONDUCTIVITY  ISOTROPIC   CONSTANT ",
+    "$#      Kp",
+    "        0.",
+    "$#            QUALIFY_S",
+    "SPECIFIC_HEAT  CONSTANT ",
+    "$#      CP      Href",
+    "        0.        0.",
+    "$#          Qlat Tmin Tmax",
+    "LATENT_HEAT   0.   0.   0. ",
+    "$#       IFUNHT",
+    "ENTHALPY      0 ",
+    "END_THMAT  ",
+    "THMAT /        2",
+    "END_THMAT  ",
+  ];
+
+  cardtest!(
+    fold_thmat,
+    CARD_THMAT,
+    vec![(2, 21, Thmat), (22, 23, Thmat)]
+  );
+
+  const CARD_HFLUX: [&'static str; 8] = [
+    "$#          NODE  HEATIN    LCUR   LPYTH    SCAF",
+    "HFLUX /        0               0       0        ",
+    "$#                                                                         TITLE",
+    "NAME HFLUX / ->1                                                                ",
+    "        NOD 1",
+    "        END",
+    "HFLUX /        0               0       0        ",
+    "NAME HFLUX / ->2                                                                ",
+  ];
+
+  cardtest!(fold_hflux, CARD_HFLUX, vec![(1, 5, Hflux), (6, 7, Hflux)]);
+
+  const CARD_INTEM: [&'static str; 6] = [
+    "$INTEM Initial Temperature",
+    "$#          NODE  TEMINI",
+    "INTEM /        0    293.",
+    "$#                                                                         TITLE",
+    "NAME INTEM / ->1                                                                ",
+    "        END",
+  ];
+
+  cardtest!(fold_intem, CARD_INTEM, vec![(2, 5, Intem)]);
+
+  const CARD_TEMBC: [&'static str; 7] = [
+    "$#          NODE  TEMIMP   TFIMP   SLFAC    FILE   ISENS    TDOF",
+    "TEMBC /        0               0                       0        ",
+    "$#                                                                         TITLE",
+    "NAME TEMBC / ->1                                                                ",
+    "        PART 1",
+    "        PART 2",
+    "        END",
+  ];
+
+  cardtest!(fold_tembc, CARD_TEMBC, vec![(1, 6, Tembc)]);
+
+  const CARD_HTSURF: [&'static str; 17] = [
+    "$HTSURF Heat Exchange Surface",
+    "$#          TAMB   LTAMB  LPTAMB             IOB",
+    "HTSURF/                0       0                ",
+    "$#                                                                         TITLE",
+    "NAME HTSURF/ ->1                                                                ",
+    "$# HCONV LTHCON1 LTHCON2  LPHCON",
+    "               0       0       0",
+    "        ELE 1",
+    "        END",
+    "HTSURF/                0       0               1",
+    "NAME HTSURF/ ->2                                                                ",
+    "$# HCONV LTHCON1 LTHCON2  LPHCON",
+    "               0       0       0",
+    "$#  Sp_e",
+    "       1.",
+    "        PART 1",
+    "        END",
+  ];
+
+  cardtest!(
+    fold_htsurf,
+    CARD_HTSURF,
+    vec![(2, 8, Htsurf), (9, 16, Htsurf)]
+  );
+
+  const CARD_COUPLED: [&'static str; 11] = [
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        2              0.             0.5              0.",
+    "INTEM /        0    293.",
+    "NAME INTEM / ->1                                                                ",
+    "        END",
+    "TEMBC /        0               0                       0        ",
+    "NAME TEMBC / ->1                                                                ",
+    "        NOD 1",
+    "        END",
+    "BOUNC /        1  111000       0       0",
+    "NAME BOUNC / ->1                                                                ",
+  ];
+
+  cardtest!(
+    fold_coupled,
+    CARD_COUPLED,
+    vec![(0, 1, Node), (2, 4, Intem), (5, 8, Tembc), (9, 10, Bounc)]
+  );
+}
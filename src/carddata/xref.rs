@@ -0,0 +1,44 @@
+//! This modules holds the global static table of
+//! [`CellRef`](crate::card::xref::CellRef)s describing which cells hold IDs.
+use crate::card::{
+  keyword::Keyword::*,
+  xref::{CellRef, Entity},
+};
+
+pub static XREFS: [CellRef; 5] = [
+  CellRef {
+    keywords: &[Node, Cnode],
+    cells: &[1],
+    entity: Entity::Node,
+    defines: true,
+  },
+  CellRef {
+    keywords: &[Shell, Membr],
+    cells: &[1],
+    entity: Entity::Element,
+    defines: true,
+  },
+  CellRef {
+    keywords: &[Shell, Membr],
+    cells: &[2],
+    entity: Entity::Part,
+    defines: false,
+  },
+  CellRef {
+    keywords: &[Shell, Membr],
+    cells: &[3, 4, 5, 6],
+    entity: Entity::Node,
+    defines: false,
+  },
+  CellRef {
+    keywords: &[
+      PartSolid, PartBshel, PartTetra, PartSphel, PartCos3d, PartTshel,
+      PartShell, PartMembr, PartBar, PartBeam, PartSpring, PartSprgbm,
+      PartMbspr, PartJoint, PartKjoin, PartMbkjn, PartMtojnt, PartTied,
+      PartSlink, PartElink, PartLlink, PartPlink, PartGap,
+    ],
+    cells: &[1],
+    entity: Entity::Part,
+    defines: true,
+  },
+];
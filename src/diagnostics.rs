@@ -0,0 +1,160 @@
+//! Diagnostics nvimpam can report back to neovim about the contents of a
+//! buffer, e.g. cells that could not be handled by some operation.
+use std::fmt;
+
+use neovim_lib::Value;
+
+use crate::linenr::LineNr;
+
+/// The severity of a [`Diagnostic`](crate::diagnostics::Diagnostic)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+  Error,
+  Warning,
+  Info,
+}
+
+impl From<Severity> for &'static str {
+  fn from(s: Severity) -> &'static str {
+    use self::Severity::*;
+
+    match s {
+      Error => "error",
+      Warning => "warning",
+      Info => "info",
+    }
+  }
+}
+
+/// A message concerning the column range `start..end` of the line `line`.
+/// Columns are byte-indexed just like the highlights. If the message concerns
+/// another line as well, e.g. the first occurence of a duplicate, it's given
+/// as the `target` to jump to.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+  pub line: LineNr,
+  pub start: u8,
+  pub end: u8,
+  pub severity: Severity,
+  pub message: String,
+  pub target: Option<LineNr>,
+}
+
+impl Diagnostic {
+  pub fn new(
+    line: LineNr,
+    start: u8,
+    end: u8,
+    severity: Severity,
+    message: String,
+  ) -> Self {
+    Diagnostic {
+      line,
+      start,
+      end,
+      severity,
+      message,
+      target: None,
+    }
+  }
+
+  /// Set the [`target`](crate::diagnostics::Diagnostic::target) of the
+  /// diagnostic
+  pub fn with_target(mut self, target: LineNr) -> Self {
+    self.target = Some(target);
+    self
+  }
+
+  /// The diagnostic as an entry of the quickfix list for the file
+  /// `filename`, see `:h setqflist()`. Lines and columns are one-indexed
+  /// there, and the type is the first letter of the severity.
+  pub fn to_quickfix(&self, filename: &str) -> Value {
+    let kind = match self.severity {
+      Severity::Error => "E",
+      Severity::Warning => "W",
+      Severity::Info => "I",
+    };
+    let text = match self.target {
+      Some(t) => format!("{} (see line {})", self.message, t + 1),
+      None => self.message.clone(),
+    };
+
+    Value::from(vec![
+      (Value::from("filename"), Value::from(filename)),
+      (Value::from("lnum"), Value::from(self.line + 1)),
+      (Value::from("col"), Value::from(u64::from(self.start) + 1)),
+      (Value::from("end_col"), Value::from(u64::from(self.end))),
+      (Value::from("text"), Value::from(text)),
+      (Value::from("type"), Value::from(kind)),
+    ])
+  }
+}
+
+impl<'a> From<&'a Diagnostic> for Value {
+  fn from(d: &'a Diagnostic) -> Self {
+    let severity: &'static str = d.severity.into();
+
+    let mut map = vec![
+      (Value::from("line"), Value::from(d.line)),
+      (Value::from("start"), Value::from(u64::from(d.start))),
+      (Value::from("end"), Value::from(u64::from(d.end))),
+      (Value::from("severity"), Value::from(severity)),
+      (Value::from("message"), Value::from(d.message.as_str())),
+    ];
+
+    if let Some(target) = d.target {
+      map.push((Value::from("target"), Value::from(target)));
+    }
+
+    Value::from(map)
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let severity: &'static str = self.severity.into();
+
+    write!(
+      f,
+      "{}:{}-{} {}: {}",
+      self.line, self.start, self.end, severity, self.message
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::Value;
+
+  use crate::{
+    diagnostics::{Diagnostic, Severity},
+    linenr::LineNr,
+  };
+
+  #[test]
+  fn quickfix_entry() {
+    let diagnostic = Diagnostic::new(
+      LineNr::from_usize(4),
+      8,
+      16,
+      Severity::Warning,
+      "Node ID 1 is defined more than once".to_string(),
+    )
+    .with_target(LineNr::from_usize(0));
+
+    assert_eq!(
+      diagnostic.to_quickfix("deck.pc"),
+      Value::from(vec![
+        (Value::from("filename"), Value::from("deck.pc")),
+        (Value::from("lnum"), Value::from(5)),
+        (Value::from("col"), Value::from(9)),
+        (Value::from("end_col"), Value::from(16)),
+        (
+          Value::from("text"),
+          Value::from("Node ID 1 is defined more than once (see line 1)")
+        ),
+        (Value::from("type"), Value::from("W")),
+      ])
+    );
+  }
+}
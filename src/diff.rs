@@ -0,0 +1,204 @@
+//! Comparing the cards of a deck with the ones of a reference deck, e.g. an
+//! earlier version of the model. Cards are matched by their type and ID
+//! instead of their position, so moving a card around is not a change.
+//!
+//! The ID of a card is given by
+//! [`card_id`](crate::card::value::card_id). Cards without one, and cards
+//! sharing an ID, are matched in the order they occur in the decks.
+use neovim_lib::Value;
+
+use crate::{card::keyword::Keyword, linenr::LineNr};
+
+/// A card of a deck, as far as the comparison is concerned
+#[derive(Debug, PartialEq)]
+pub struct DeckCard {
+  pub keyword: Keyword,
+  pub id: Option<i64>,
+  /// The keyword line of the card
+  pub line: LineNr,
+  /// The lines of the card, without comments and trailing whitespace
+  pub lines: Vec<String>,
+}
+
+/// How a card differs from the reference deck
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Change {
+  /// The card is not in the reference deck
+  Added,
+  /// The card is only in the reference deck
+  Removed,
+  /// The card is in both decks, but its lines differ
+  Changed,
+}
+
+impl From<Change> for &'static str {
+  fn from(c: Change) -> Self {
+    match c {
+      Change::Added => "added",
+      Change::Removed => "removed",
+      Change::Changed => "changed",
+    }
+  }
+}
+
+/// A card that differs between the decks. `line` is its keyword line in
+/// the deck, `refline` the one in the reference deck. Either one is `None` if
+/// the card was added or removed.
+#[derive(Debug, PartialEq)]
+pub struct CardDiff {
+  pub change: Change,
+  pub keyword: Keyword,
+  pub id: Option<i64>,
+  pub line: Option<LineNr>,
+  pub refline: Option<LineNr>,
+}
+
+impl<'a> From<&'a CardDiff> for Value {
+  fn from(d: &'a CardDiff) -> Self {
+    let mut map = vec![
+      (Value::from("change"), Value::from(<&str>::from(d.change))),
+      (
+        Value::from("keyword"),
+        Value::from(format!("{:?}", d.keyword)),
+      ),
+    ];
+
+    if let Some(id) = d.id {
+      map.push((Value::from("id"), Value::from(id)));
+    }
+    if let Some(line) = d.line {
+      map.push((Value::from("line"), Value::from(line)));
+    }
+    if let Some(refline) = d.refline {
+      map.push((Value::from("refline"), Value::from(refline)));
+    }
+
+    Value::from(map)
+  }
+}
+
+/// The number of cards before `cards[idx]` with the same type and ID
+fn occurrence(cards: &[DeckCard], idx: usize) -> usize {
+  let card = &cards[idx];
+
+  cards[..idx]
+    .iter()
+    .filter(|c| c.keyword == card.keyword && c.id == card.id)
+    .count()
+}
+
+/// Compare the cards of a deck with the ones of the reference deck
+/// `reference`. The added and changed cards come first, ordered like
+/// `cards`, followed by the removed cards in the order of `reference`.
+pub fn diff_cards(cards: &[DeckCard], reference: &[DeckCard]) -> Vec<CardDiff> {
+  let mut matched = vec![false; reference.len()];
+  let mut diffs = vec![];
+
+  for (i, card) in cards.iter().enumerate() {
+    let n = occurrence(cards, i);
+    let found = reference
+      .iter()
+      .enumerate()
+      .filter(|(_, r)| r.keyword == card.keyword && r.id == card.id)
+      .nth(n);
+
+    match found {
+      Some((j, r)) => {
+        matched[j] = true;
+        if r.lines != card.lines {
+          diffs.push(CardDiff {
+            change: Change::Changed,
+            keyword: card.keyword,
+            id: card.id,
+            line: Some(card.line),
+            refline: Some(r.line),
+          });
+        }
+      }
+      None => diffs.push(CardDiff {
+        change: Change::Added,
+        keyword: card.keyword,
+        id: card.id,
+        line: Some(card.line),
+        refline: None,
+      }),
+    }
+  }
+
+  diffs.extend(reference.iter().zip(matched).filter(|(_, m)| !m).map(
+    |(r, _)| CardDiff {
+      change: Change::Removed,
+      keyword: r.keyword,
+      id: r.id,
+      line: None,
+      refline: Some(r.line),
+    },
+  ));
+
+  diffs
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::{
+    bufdata::BufData,
+    card::keyword::Keyword::*,
+    diff::{diff_cards, CardDiff, Change::*},
+    linenr::LineNr,
+  };
+
+  #[test]
+  fn diff_decks() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let line = |n| Some(LineNr::from_usize(n));
+
+    let reflines = [
+      "NODE  /        1              0.             0.5              0.",
+      "NODE  /        2              0.             0.5              0.",
+      "NODE  /        3              0.             0.5              0.",
+      "SHELL /     3129       1       1    2967    2971    2970",
+    ];
+    let mut reference = BufData::new(&buf);
+    reference.parse_strs(&reflines).unwrap();
+
+    let lines = [
+      "$ Moved to the front, trailing whitespace does not matter",
+      "SHELL /     3129       1       1    2967    2971    2970   ",
+      "NODE  /        3              0.             0.5              0.",
+      "NODE  /        1              1.             0.5              0.",
+      "NODE  /        4              0.             0.5              0.",
+    ];
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&lines).unwrap();
+
+    let diffs = diff_cards(&bufdata.deck_cards(), &reference.deck_cards());
+    assert_eq!(
+      diffs,
+      vec![
+        CardDiff {
+          change: Changed,
+          keyword: Node,
+          id: Some(1),
+          line: line(3),
+          refline: line(0),
+        },
+        CardDiff {
+          change: Added,
+          keyword: Node,
+          id: Some(4),
+          line: line(4),
+          refline: None,
+        },
+        CardDiff {
+          change: Removed,
+          keyword: Node,
+          id: Some(2),
+          line: None,
+          refline: line(1),
+        },
+      ]
+    );
+  }
+}
@@ -1,12 +1,73 @@
 //! The events that nvimpam needs to accept and deal with. They're sent by the
 //! [`NeovimHandler`](crate::handler::NeovimHandler) to the main loop.
-use std::{ffi::OsString, fmt, fs, sync::mpsc};
+use std::{
+  cmp,
+  collections::VecDeque,
+  env,
+  ffi::OsString,
+  fmt,
+  fs::{self, OpenOptions},
+  io::Write,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, RecvTimeoutError, TryRecvError},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
 
 use failure::{self, Error, ResultExt};
 use log::{info, warn};
 use neovim_lib::{neovim::Neovim, neovim_api::Buffer, NeovimApi, Value};
 
-use crate::{bufdata::BufData, linenr::LineNr};
+use crate::{
+  background::{self, Requests},
+  bufdata::{
+    banner::Pattern,
+    comment,
+    degraded::Degraded,
+    foldgroup::FoldGroups,
+    folds::ExprLevel,
+    foldstate::FoldState,
+    foldtext::FoldTexts,
+    highlights::{HighlightGroup, HlConfig, TOKEN_MODIFIERS, TOKEN_TYPES},
+    refresh::Refresh,
+    stats::{EventTiming, TimingLog},
+    viewed::ViewedRegions,
+    BufData, ParsedRegion, PendingUpdate,
+  },
+  card::{
+    custom,
+    keyword::Keyword,
+    number::Units,
+    section::{SectionRule, DEFAULT_SECTION_RULES},
+    xref::Entity,
+  },
+  index::{self, Index},
+  linenr::LineNr,
+  session::Session,
+  worker::Worker,
+};
+
+#[cfg(feature = "watch")]
+use crate::watch;
+
+/// The arguments nvimpam was started with, see the
+/// [`event_loop`](crate::event::Event::event_loop)
+#[derive(Debug, Default)]
+pub struct Startup {
+  /// The file to read the buffer from, instead of requesting it from neovim
+  pub file: Option<OsString>,
+  /// If the buffer is only shown, without enabling buffer events
+  pub preview: bool,
+  /// The size in bytes above which the buffer is degraded, and the mode
+  pub max_size: Option<(usize, Degraded)>,
+  /// The patterns of the comment banners, the defaults if `None`
+  pub banners: Option<Vec<Pattern>>,
+  /// If the data is checked for consistency after each update
+  pub strict: bool,
+}
 
 /// The event list the main loop reacts to
 pub enum Event {
@@ -15,10 +76,11 @@ pub enum Event {
   /// 0`). The range from firstline to lastline is end-exclusive. `more`
   /// indicates if we need to expect another event of this type with more
   /// lines, in case Neovim decided to split up the buffer (not yet
-  /// implemented).
+  /// implemented). Neovim does not send a `changedtick` for some changes, e.g.
+  /// when undoing to an unmodified state.
   LinesEvent {
     buf: Buffer,
-    changedtick: u64,
+    changedtick: Option<u64>,
     firstline: i64,
     lastline: i64,
     linedata: Vec<String>,
@@ -37,11 +99,371 @@ pub enum Event {
   /// Highlight lines in the buffer containing at least the given line range
   // TODO: maybe accept buffer as an argument?
   HighlightRegion { firstline: i64, lastline: i64 },
+  /// Highlight the visible lines `topline..=botline` and some lines around
+  /// them right away, and the rest of the buffer when idle. Zero-indexed.
+  HighlightViewport { topline: i64, botline: i64 },
+  /// The window showing the buffer now shows the lines `topline..botline`,
+  /// like the `win_viewport` UI event of neovim. Zero-indexed, `botline` is
+  /// exclusive. Highlights like a
+  /// [`HighlightViewport`](crate::event::Event::HighlightViewport) event, but
+  /// the lines the view is scrolling towards are highlighted first.
+  ViewportEvent { topline: i64, botline: i64 },
+  /// Set the time in milliseconds after which the highlights of lines that
+  /// have not been viewed in the meantime are cleared in neovim, see
+  /// [`ViewedRegions`](crate::bufdata::viewed::ViewedRegions). They are sent
+  /// again when the lines are viewed. While set, the rest of the buffer is
+  /// not highlighted when idle. A value of 0 or less keeps all highlights.
+  HighlightAge { millis: i64 },
+  /// Convert the card containing `line` to the card type named `target`
+  ConvertCard { line: i64, target: String },
+  /// Renumber the entities of type `entity` defined in the lines
+  /// `firstline..lastline` to `start`, `start + step`, ... and update the
+  /// references to them
+  Renumber {
+    entity: String,
+    start: i64,
+    step: i64,
+    firstline: i64,
+    lastline: i64,
+  },
+  /// Rename the entity of type `entity` with the ID `old` to `new` and
+  /// update the references to it
+  RenameId { entity: String, old: i64, new: i64 },
+  /// Add `offset` to the IDs of the `entities` defined in the include file
+  /// of the `INCLU` line `line`, and update the references to them in the
+  /// buffer and its indexed include files, see
+  /// [`BufData::offset_include`](crate::bufdata::BufData::offset_include).
+  /// `path` is the file of the buffer, the include is resolved relative to
+  /// its directory.
+  OffsetInclude {
+    path: String,
+    line: i64,
+    offset: i64,
+    entities: Vec<String>,
+  },
+  /// Re-align the cells of the cards in the lines `firstline..lastline`. If
+  /// `modified` is set, only the cards changed since the buffer was last
+  /// [`Written`](crate::event::Event::Written) are re-aligned.
+  AlignCells {
+    firstline: i64,
+    lastline: i64,
+    modified: bool,
+  },
+  /// The buffer was written to its file
+  Written,
+  /// An `'inccommand'` preview started or ended. Neovim undoes the changes
+  /// of the preview before running the command, so the
+  /// [`LinesEvent`](crate::event::Event::LinesEvent)s sent while it's
+  /// `active` are only highlighted, but don't change the
+  /// [`BufData`](crate::bufdata::BufData).
+  Inccommand { active: bool },
+  /// Check if inserting a character at column `column` of line `line` would
+  /// shift the cells of the line
+  CellHint { line: i64, column: i64 },
+  /// Set the namespace and highlight groups used for highlighting, also for
+  /// the keywords of single cards, the column the highlights end at, and the
+  /// encoding of the columns sent by
+  /// [`HighlightsForRange`](crate::event::Event::HighlightsForRange)
+  HighlightConfig { config: HlConfig },
+  /// Set the time window in milliseconds in which consecutive
+  /// [`LinesEvent`](crate::event::Event::LinesEvent)s are merged before
+  /// updating the buffer
+  CoalesceWindow { millis: i64 },
+  /// Set the number of folds sent to neovim at once when nvimpam sends the
+  /// folds on its own, e.g. after a [`Restart`](crate::event::Event::Restart)
+  FoldChunkSize { size: i64 },
+  /// Enable or disable the check for IDs defined more than once, and send the
+  /// duplicates found. While enabled, they are sent again after each update
+  CheckDuplicates { enable: bool },
+  /// Enable or disable the highlights. While disabled, no highlights are
+  /// generated or sent, but the folds are kept up to date as usual.
+  EnableHighlights { enable: bool },
+  /// Set the templates for the foldtexts, and resend the folds
+  FoldTexts { texts: FoldTexts },
+  /// Set the rules grouping the level 1 folds, and resend the folds
+  FoldGroups { groups: FoldGroups },
+  /// Send the metadata declared at the top of the buffer
+  Metadata,
+  /// Send statistics about the data held for the buffer, see
+  /// [`Stats`](crate::bufdata::stats::Stats)
+  Stats,
+  /// Send the number of cards and lines of each card type in the buffer, see
+  /// [`KeywordCount`](crate::bufdata::stats::KeywordCount)
+  KeywordSummary,
+  /// Send a report for `:checkhealth`: The version of nvimpam and the API
+  /// level of neovim, if the buffer is attached, the card definitions loaded,
+  /// the statistics of the buffer and the status of the index of the
+  /// include files
+  Health,
+  /// Check the order of the sections of the buffer. Uses the
+  /// [`default rules`](crate::card::section::DEFAULT_SECTION_RULES) if
+  /// `rules` is empty.
+  LintSections { rules: Vec<SectionRule> },
+  /// Write the fold state of the buffer to the file `path`
+  ExportFolds { path: String },
+  /// Read the fold state of the buffer from the file `path`, and resend the
+  /// folds
+  ImportFolds { path: String },
+  /// Write the [`Session`](crate::session::Session) of the buffer, i.e. its
+  /// folds and the index of its include files, to the file `path`
+  SaveState { path: String },
+  /// Read the [`Session`](crate::session::Session) of the buffer from the
+  /// file `path`, restore the custom folds and the index of the include
+  /// files, and resend the folds
+  LoadState { path: String },
+  /// Set the foldtext of the fold starting at `start_line` to `label`, and
+  /// resend the folds
+  RenameFold { start_line: i64, label: String },
+  /// Write the cards in the lines `firstline..lastline` to the new file
+  /// `path`, and send the `INCLU` line replacing them
+  ExtractInclude {
+    firstline: i64,
+    lastline: i64,
+    path: String,
+  },
+  /// Comment out the cards in the lines `firstline..lastline`, or uncomment
+  /// them if all of those lines are comments. The range is extended to whole
+  /// cards, and the new lines are sent back along with it.
+  ToggleComment { firstline: i64, lastline: i64 },
+  /// Index the entities defined in the include files of the buffer in the
+  /// background, see [`index`](crate::index). `path` is the file of the
+  /// buffer, the includes are resolved relative to its directory.
+  IndexIncludes { path: String },
+  /// Find the definition of the ID `id` of `entity`, e.g. "node", in the
+  /// buffer or its indexed include files. Sends back the file and the line,
+  /// the file is empty if the definition is in the buffer itself.
+  FindDefinition { entity: String, id: i64 },
+  /// Send all diagnostics of the buffer as entries of the quickfix list, see
+  /// [`BufData::diagnostics`](crate::bufdata::BufData::diagnostics). `path`
+  /// is the file name used for the entries.
+  Quickfix { path: String },
+  /// Send the timings of the last `count` events handled, see
+  /// [`EventTiming`](crate::bufdata::stats::EventTiming)
+  EventTimings { count: i64 },
+  /// Send the type, line range, cell values and diagnostics of the card
+  /// containing the line `line`, see
+  /// [`BufData::card_info`](crate::bufdata::BufData::card_info)
+  CardInfo { line: i64 },
+  /// Send the selectors of the GES containing the line `line`, along with
+  /// the number of defined entities each one matches, see
+  /// [`BufData::ges_contents`](crate::bufdata::BufData::ges_contents)
+  GesContents { line: i64 },
+  /// Send the value of `foldexpr` for the line `line`, so neovim can compute
+  /// the folds itself, see
+  /// [`BufData::fold_level`](crate::bufdata::BufData::fold_level). Lines
+  /// outside of the buffer are no part of a fold.
+  FoldLevel { line: i64 },
+  /// Send the folds intersecting the lines `firstline..lastline`, packed up
+  /// like all folds are sent, see
+  /// [`BufData::folds_in_range`](crate::bufdata::BufData::folds_in_range)
+  FoldsForRange { firstline: i64, lastline: i64 },
+  /// Send the highlights of the lines `firstline..lastline`, with the columns
+  /// in the configured encoding, see
+  /// [`BufData::highlights_in_range`](crate::bufdata::BufData::highlights_in_range)
+  HighlightsForRange { firstline: i64, lastline: i64 },
+  /// Send the highlights of the lines `firstline..lastline` as LSP semantic
+  /// tokens along with their legend, see
+  /// [`BufData::semantic_tokens`](crate::bufdata::BufData::semantic_tokens)
+  SemanticTokens { firstline: i64, lastline: i64 },
+  /// Replace the highlights another tool pushed to the lines
+  /// `firstline..lastline` by `spans`, and send the highlights of the lines,
+  /// see
+  /// [`BufData::set_external_highlights`](crate::bufdata::BufData::set_external_highlights)
+  ExternalHighlights {
+    firstline: i64,
+    lastline: i64,
+    spans: Vec<((LineNr, u8, u8), HighlightGroup)>,
+  },
+  /// Send the column range of the cell `field` of the line `line`, and the
+  /// lines of the run of identical cards around it, see
+  /// [`BufData::cell_range`](crate::bufdata::BufData::cell_range). Used to
+  /// select a cell of all the cards in visual block mode.
+  CellRange { line: i64, field: String },
+  /// Send the first and last line of "a card" (`inner` false) or the "inner
+  /// card" (`inner` true) at the line `line`, see
+  /// [`BufData::card_object`](crate::bufdata::BufData::card_object). Used
+  /// for the text objects selecting cards.
+  CardObject { line: i64, inner: bool },
+  /// Send the number in the cell at the column `column` of the line `line`
+  /// in readable notations, scaled by the unit of the cell in `units`, see
+  /// [`BufData::cell_number`](crate::bufdata::BufData::cell_number)
+  CellNumber {
+    line: i64,
+    column: i64,
+    units: Units,
+  },
+  /// Compare the cards of the buffer with the ones of the file `path`, see
+  /// [`diff`](crate::diff). Sends back the cards that were added, removed or
+  /// changed.
+  DiffDeck { path: String },
+  /// Hand over the state of the buffer to a new nvimpam process, e.g. after
+  /// upgrading the binary. Sends back the serialized
+  /// [`FoldState`](crate::bufdata::foldstate::FoldState) as `state` along
+  /// with the buffer's `changedtick`, and quits. The Lua side passes both to
+  /// the new process by a
+  /// [`RestoreState`](crate::event::Event::RestoreState) notification, i.e.
+  /// through the pipe of its RPC channel. If the changedtick can't be
+  /// requested, this sends back an error and keeps running.
+  Restart,
+  /// Restore the `state` handed over by a
+  /// [`Restart`](crate::event::Event::Restart)ing nvimpam. It's only
+  /// applied if the buffer's changedtick is still `changedtick` and its lines
+  /// match the hash of the state, otherwise the custom folds are lost. If the
+  /// buffer has not been parsed yet, it's applied right after that.
+  RestoreState { state: String, changedtick: u64 },
   /// This plugin should quit. Currently only sent by the user directly.
   Quit,
+  /// The result of parsing the lines of the update `id` by the
+  /// [`Worker`](crate::worker::Worker). It's `None` if the parse got
+  /// cancelled.
+  Parsed {
+    id: u64,
+    result: Result<Option<ParsedRegion>, Error>,
+  },
+  /// One of the include files indexed by
+  /// [`IndexIncludes`](crate::event::Event::IndexIncludes) changed on disk.
+  /// Only sent by the [`IncludeWatcher`](crate::watch::IncludeWatcher).
+  IncludeChanged { path: PathBuf },
+  /// A thread of nvimpam panicked, so the event loop stops with an error. Sent
+  /// by the panic hook of the binary, which reports the panic to neovim.
+  Panicked,
+  /// Answer `request` in the background, see [`background`](crate::background).
+  /// The answer is passed to neovim along with `id`.
+  Background { id: u64, request: Box<Event> },
+  /// Cancel the background request `id`
+  Cancel { id: u64 },
+  /// The answer to the background request `id`. It's `None` if the request
+  /// got cancelled. Only sent by the
+  /// [`Requests`](crate::background::Requests) thread.
+  Finished { id: u64, result: Option<Value> },
 }
 
 impl Event {
+  /// The name of the event, used for its
+  /// [`EventTiming`](crate::bufdata::stats::EventTiming)
+  pub fn name(&self) -> &'static str {
+    use self::Event::*;
+
+    match *self {
+      LinesEvent { .. } => "LinesEvent",
+      ChangedTickEvent { .. } => "ChangedTickEvent",
+      DetachEvent { .. } => "DetachEvent",
+      RefreshFolds => "RefreshFolds",
+      HighlightRegion { .. } => "HighlightRegion",
+      HighlightViewport { .. } => "HighlightViewport",
+      ViewportEvent { .. } => "ViewportEvent",
+      HighlightAge { .. } => "HighlightAge",
+      ConvertCard { .. } => "ConvertCard",
+      Renumber { .. } => "Renumber",
+      RenameId { .. } => "RenameId",
+      OffsetInclude { .. } => "OffsetInclude",
+      AlignCells { .. } => "AlignCells",
+      Written => "Written",
+      Inccommand { .. } => "Inccommand",
+      CellHint { .. } => "CellHint",
+      HighlightConfig { .. } => "HighlightConfig",
+      CoalesceWindow { .. } => "CoalesceWindow",
+      FoldChunkSize { .. } => "FoldChunkSize",
+      CheckDuplicates { .. } => "CheckDuplicates",
+      EnableHighlights { .. } => "EnableHighlights",
+      FoldTexts { .. } => "FoldTexts",
+      FoldGroups { .. } => "FoldGroups",
+      Metadata => "Metadata",
+      Stats => "Stats",
+      Health => "Health",
+      KeywordSummary => "KeywordSummary",
+      LintSections { .. } => "LintSections",
+      ExportFolds { .. } => "ExportFolds",
+      ImportFolds { .. } => "ImportFolds",
+      SaveState { .. } => "SaveState",
+      LoadState { .. } => "LoadState",
+      RenameFold { .. } => "RenameFold",
+      ExtractInclude { .. } => "ExtractInclude",
+      ToggleComment { .. } => "ToggleComment",
+      IndexIncludes { .. } => "IndexIncludes",
+      FindDefinition { .. } => "FindDefinition",
+      Quickfix { .. } => "Quickfix",
+      EventTimings { .. } => "EventTimings",
+      CardInfo { .. } => "CardInfo",
+      GesContents { .. } => "GesContents",
+      FoldLevel { .. } => "FoldLevel",
+      FoldsForRange { .. } => "FoldsForRange",
+      HighlightsForRange { .. } => "HighlightsForRange",
+      SemanticTokens { .. } => "SemanticTokens",
+      ExternalHighlights { .. } => "ExternalHighlights",
+      CellRange { .. } => "CellRange",
+      CardObject { .. } => "CardObject",
+      CellNumber { .. } => "CellNumber",
+      DiffDeck { .. } => "DiffDeck",
+      Restart => "Restart",
+      RestoreState { .. } => "RestoreState",
+      Quit => "Quit",
+      Parsed { .. } => "Parsed",
+      IncludeChanged { .. } => "IncludeChanged",
+      Panicked => "Panicked",
+      Background { .. } => "Background",
+      Cancel { .. } => "Cancel",
+      Finished { .. } => "Finished",
+    }
+  }
+
+  /// The number of lines of the buffer the event changes or highlights
+  pub fn lines_touched(&self) -> usize {
+    use self::Event::*;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    match *self {
+      LinesEvent {
+        firstline,
+        lastline,
+        ref linedata,
+        ..
+      } => cmp::max((lastline - firstline).max(0) as usize, linedata.len()),
+      HighlightRegion {
+        firstline,
+        lastline,
+      } => (lastline - firstline + 1).max(0) as usize,
+      HighlightViewport { topline, botline } => {
+        (botline - topline + 1).max(0) as usize
+      }
+      ViewportEvent { topline, botline } => (botline - topline).max(0) as usize,
+      _ => 0,
+    }
+  }
+
+  /// If the event is an update of the buffer. Those can be handled while
+  /// earlier updates are still being parsed.
+  pub fn is_update(&self) -> bool {
+    use self::Event::*;
+
+    matches!(*self, LinesEvent { .. } | ChangedTickEvent { .. })
+  }
+
+  /// If the event is a request that can be answered in the background, see
+  /// [`background`](crate::background)
+  pub fn runs_in_background(&self) -> bool {
+    use self::Event::*;
+
+    matches!(*self, KeywordSummary | DiffDeck { .. } | Quickfix { .. })
+  }
+
+  /// If the event changes the contents of the buffer. Those are refused in
+  /// preview mode, since nvimpam would not learn about the changes.
+  pub fn edits_buffer(&self) -> bool {
+    use self::Event::*;
+
+    matches!(
+      *self,
+      ConvertCard { .. }
+        | Renumber { .. }
+        | RenameId { .. }
+        | OffsetInclude { .. }
+        | AlignCells { .. }
+        | ExtractInclude { .. }
+        | ToggleComment { .. }
+    )
+  }
+
   /// Run the event loop. The receiver receives the events from the
   /// [handler](crate::handler::NeovimHandler).
   ///
@@ -54,26 +476,109 @@ impl Event {
   /// [buffer events](https://neovim.io/doc/user/api.html#nvim_buf_attach())
   /// and requests the buffer's contents from it instead.
   ///
+  /// In `preview` mode, the file is parsed and its folds and highlights are
+  /// sent to neovim right away, but buffer events are not enabled. This saves
+  /// the overhead of the updates when viewing huge decks. Nvimpam keeps
+  /// answering requests about the file, apart from the ones
+  /// [editing the buffer](crate::event::Event::edits_buffer).
+  ///
+  /// If the state of a [`Restart`](crate::event::Event::Restart)ing nvimpam
+  /// is handed over by a
+  /// [`RestoreState`](crate::event::Event::RestoreState) notification, its
+  /// custom folds are restored once the buffer has been parsed, and the folds
+  /// are sent to neovim.
+  ///
+  /// [`LinesEvent`](crate::event::Event::LinesEvent)s arriving in quick
+  /// succession, e.g. while typing, are merged before updating the buffer.
+  /// The changed cards are parsed by a [`Worker`](crate::worker::Worker),
+  /// which sends the result back to `to_self`. In the meantime, further
+  /// updates are accepted, and cancel the parse if they change the same
+  /// cards. All other events wait until the updates are done.
+  /// The time window can be set by a
+  /// [`CoalesceWindow`](crate::event::Event::CoalesceWindow) event, by default
+  /// only events that are already waiting get merged.
+  ///
+  /// Folds pushed to neovim without a request are sent in chunks, see
+  /// [`FoldChunkSize`](crate::event::Event::FoldChunkSize).
+  ///
+  /// After a
+  /// [`HighlightViewport`](crate::event::Event::HighlightViewport) event, the
+  /// rest of the buffer is highlighted chunk by chunk while no other events
+  /// arrive. After a [`ViewportEvent`](crate::event::Event::ViewportEvent),
+  /// the lines in the direction of the scroll come first. If a
+  /// [`HighlightAge`](crate::event::Event::HighlightAge) is set,
+  /// only the viewed lines are highlighted, and their highlights are cleared
+  /// when they get too old.
+  ///
+  /// If a `max_size` is given, a buffer larger than that many bytes is
+  /// degraded to the given mode when it's parsed, see
+  /// [`set_max_size`](crate::bufdata::BufData::set_max_size). The comment
+  /// banners are folded if they match the `banners` patterns, see
+  /// [`banner`](crate::bufdata::banner). In `strict` mode, an inconsistency
+  /// of the data found after an update is reported to the user, see
+  /// [`set_strict`](crate::bufdata::BufData::set_strict).
+  ///
+  /// The time it takes to handle each event is recorded, see
+  /// [`EventTimings`](crate::event::Event::EventTimings), and logged.
+  ///
   /// Sending the [`Quit`](crate::event::Event::Quit) event will
   /// exit the loop and return from the function.
   pub fn event_loop(
     from_handler: &mpsc::Receiver<Self>,
     to_handler: &mpsc::Sender<Value>,
+    to_self: &mpsc::Sender<Self>,
     nvim: &mut Neovim,
-    file: Option<OsString>,
+    startup: Startup,
   ) -> Result<(), Error> {
     use self::Event::*;
 
+    let Startup {
+      file,
+      preview,
+      max_size,
+      banners,
+      strict,
+    } = startup;
     let curbuf = nvim.get_current_buf()?;
     let origlines;
     let mut bufdata = BufData::new(&curbuf);
+    if let Some((size, mode)) = max_size {
+      bufdata.set_max_size(size, mode);
+    }
+    if let Some(patterns) = banners {
+      bufdata.set_banner_patterns(patterns);
+    }
+    bufdata.set_strict(strict);
+    let mut fold_chunk = DEFAULT_FOLD_CHUNK;
+
+    // The state handed over by a restarting nvimpam, until the buffer has
+    // been parsed
+    let mut restore: Option<(FoldState, u64)> = None;
+    let mut parsed = false;
 
     let connected = match file {
+      None if preview => {
+        return Err(failure::err_msg("The preview mode needs a file!"));
+      }
       None => curbuf.attach(nvim, true, vec![])?,
       Some(f) => {
         origlines = fs::read(f)?;
         bufdata.parse_slice(&origlines)?;
-        curbuf.attach(nvim, false, vec![])?
+        parsed = true;
+        if bufdata.take_no_cards() {
+          report_no_cards(nvim)?;
+        }
+        if preview {
+          send_folds(&bufdata, nvim, fold_chunk)?;
+        }
+        if preview {
+          if let Some(calls) = bufdata.highlight_all_calls() {
+            nvim.call_atomic(calls).context("call_atomic failed")?;
+          }
+          true
+        } else {
+          curbuf.attach(nvim, false, vec![])?
+        }
       }
     };
 
@@ -81,8 +586,110 @@ impl Event {
       return Err(failure::err_msg("Could not enable buffer updates!"));
     }
 
+    let mut window = Duration::from_millis(0);
+    let mut pending = None;
+    let mut viewport = None;
+    let mut deferred: VecDeque<[LineNr; 2]> = VecDeque::new();
+    let mut viewed = ViewedRegions::default();
+    let mut index: Option<Arc<Index>> = None;
+    let mut indexing = None;
+    // Only held to keep the include files watched
+    #[cfg(feature = "watch")]
+    let mut _watcher = None;
+    let mut timings = TimingLog::default();
+    let worker = Worker::spawn(to_self.clone());
+    let mut requests = Requests::spawn(to_self.clone());
+    let mut next_id = 0;
+    // The updates being parsed by the worker, in order
+    let mut in_flight: VecDeque<(u64, PendingUpdate, Arc<AtomicBool>)> =
+      VecDeque::new();
+    // The events waiting for the updates in flight
+    let mut waiting: VecDeque<Self> = VecDeque::new();
+    // If an 'inccommand' preview is shown, and the lines highlighted for it
+    let mut inccommand = false;
+    let mut previewed: Option<[LineNr; 2]> = None;
+
     loop {
-      match from_handler.recv() {
+      if in_flight.is_empty() {
+        expire_highlights(&bufdata, nvim, &mut viewed)?;
+      }
+
+      // Wake up to highlight the deferred lines, or to clear the highlights
+      // of old regions
+      let timeout = if deferred.is_empty() {
+        viewed.next_expiry(Instant::now())
+      } else {
+        Some(IDLE_TIMEOUT)
+      };
+
+      let event = match pending.take() {
+        Some(e) => Ok(e),
+        None if in_flight.is_empty() && !waiting.is_empty() => {
+          Ok(waiting.pop_front().unwrap_or_else(|| unreachable!()))
+        }
+        None if timeout.is_none() || !in_flight.is_empty() => {
+          from_handler.recv()
+        }
+        None => match from_handler
+          .recv_timeout(timeout.unwrap_or_else(|| unreachable!()))
+        {
+          Ok(e) => Ok(e),
+          Err(RecvTimeoutError::Timeout) => {
+            if let Some([first, last]) = deferred.pop_front() {
+              let start = Instant::now();
+              highlight_region(&mut bufdata, nvim, &mut viewed, first, last)?;
+              log_timing(
+                &mut timings,
+                EventTiming {
+                  event: "IdleHighlight",
+                  duration: start.elapsed(),
+                  lines: first.lines_to(last) + 1,
+                  folds: 0,
+                },
+              );
+            }
+            continue;
+          }
+          Err(RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
+        },
+      };
+      let event = match event {
+        Ok(e) if !in_flight.is_empty() && !e.is_update() => match e {
+          // A panicked worker won't send the results waited for
+          Parsed { .. } | Panicked => Ok(e),
+          _ => {
+            waiting.push_back(e);
+            continue;
+          }
+        },
+        // Updates must not overtake the events waiting before them
+        Ok(e) if !waiting.is_empty() && !in_flight.is_empty() => {
+          waiting.push_back(e);
+          continue;
+        }
+        Ok(e @ LinesEvent { .. }) if waiting.is_empty() => {
+          Ok(coalesce_lines(e, from_handler, window, &mut pending))
+        }
+        e => e,
+      };
+
+      let (name, lines) = match event {
+        Ok(ref e) => (e.name(), e.lines_touched()),
+        Err(_) => ("RecvError", 0),
+      };
+      let start = Instant::now();
+      let mut folds = 0;
+
+      if preview && event.as_ref().map_or(false, Event::edits_buffer) {
+        to_handler.send(Value::from(format!(
+          "{} is not available in preview mode, use :NvimPamRestart to enable \
+           buffer updates",
+          name
+        )))?;
+        continue;
+      }
+
+      match event {
         Ok(LinesEvent {
           firstline,
           lastline,
@@ -90,63 +697,745 @@ impl Event {
           changedtick,
           ..
         }) => {
-          if changedtick == 0 {
+          if changedtick == Some(0) {
             continue;
           }
           if lastline == -1 {
-            bufdata.parse_vec(linedata)?;
+            // The results of the updates in flight are outdated now
+            for (_, _, cancel) in in_flight.drain(..) {
+              cancel.store(true, Ordering::Relaxed);
+            }
+            if let Refresh::Updated {
+              range,
+              firstline,
+              lastline,
+              added,
+            } = bufdata.refresh(linedata)?
+            {
+              shift_deferred(&mut deferred, lastline, added);
+              viewed.shift(firstline, lastline, added);
+
+              let newlast = lastline.saturating_add(added);
+              let calls =
+                bufdata.highlight_region_calls(range, firstline, newlast);
+              if let Some(calls) = calls {
+                viewed.view(firstline, newlast, Instant::now());
+                nvim.call_atomic(calls).context("call_atomic failed")?;
+              }
+              update_foldtexts(&bufdata, nvim, firstline, newlast, added)?;
+            }
+            parsed = true;
+            if let Some((state, tick)) = restore.take() {
+              let current = match changedtick {
+                Some(c) => c,
+                None => buffer_changedtick(&bufdata, nvim)?,
+              };
+              restore_folds(
+                &mut bufdata,
+                nvim,
+                state,
+                tick == current,
+                fold_chunk,
+              )?;
+            }
+            if bufdata.checks_duplicates() {
+              send_duplicates(&bufdata, nvim)?;
+            }
+          } else if inccommand {
+            let [firstline, lastline] = LineNr::try_range(firstline, lastline)?;
+            let (calls, [first, last]) =
+              bufdata.preview_calls(firstline, lastline, linedata)?;
+
+            previewed = Some(match previewed {
+              Some([f, l]) => [f.min(first), l.max(last)],
+              None => [first, last],
+            });
+            if let Some(calls) = calls {
+              nvim.call_atomic(calls).context("call_atomic failed")?;
+            }
           } else {
-            debug_assert!(
-              lastline >= 0 && firstline >= 0 && lastline >= firstline
-            );
-            let lastline = LineNr::from_i64(lastline);
-            let firstline = LineNr::from_i64(firstline);
+            let [firstline, lastline] = LineNr::try_range(firstline, lastline)?;
+
+            let update = bufdata.prepare_update(firstline, lastline, linedata);
+            shift_deferred(&mut deferred, lastline, update.added());
+            viewed.shift(firstline, lastline, update.added());
+
+            if let Some((_, previous, cancel)) = in_flight.back() {
+              if update.covers(previous) {
+                cancel.store(true, Ordering::Relaxed);
+              }
+            }
+
+            next_id += 1;
+            let cancel = worker.submit(
+              next_id,
+              bufdata.pending_lines(&update),
+              bufdata.metadata().version,
+              bufdata.highlights_enabled(),
+              bufdata.highlights.columns(),
+            )?;
+            in_flight.push_back((next_id, update, cancel));
+          }
+        }
+        Ok(Parsed { id, result }) => {
+          // Results of updates dropped by a full parse are ignored
+          let update = match in_flight.front() {
+            Some((i, _, _)) if *i == id => in_flight.pop_front(),
+            _ => None,
+          };
 
-            let (newrange, added) = bufdata.update(firstline, lastline, linedata)?;
-            if let Some(calls) =
-              bufdata.highlight_region_calls(newrange, firstline, lastline +
-                                             added)
+          if let Some((_, update, _)) = update {
+            let parsed = result?.unwrap_or_default();
+            let version_changed = update.version_changed();
+            let [first, last] = update.changed_lines();
+            let (newrange, added) = bufdata.finish_update(update, parsed)?;
+            folds = bufdata.timings().update_folds;
+            let clamped = viewport
+              .and_then(|(top, bot)| clamp_to_viewport(first, last, top, bot));
+            let calls = if let (false, Some(([f, l], rest))) =
+              (version_changed, clamped)
             {
+              highlight_region(&mut bufdata, nvim, &mut viewed, f, l)?;
+              // Lines that aren't viewed don't get highlighted in these modes
+              if bufdata.degraded() != Some(Degraded::Viewport)
+                && !viewed.enabled()
+              {
+                deferred.extend(rest);
+              }
+              None
+            } else if !version_changed {
+              let calls = bufdata.highlight_region_calls(newrange, first, last);
+              if calls.is_some() {
+                viewed.view(first, last, Instant::now());
+              }
+              calls
+            } else {
+              // Cards might have a different layout now
+              bufdata.regenerate()?;
+              match viewport {
+                Some((top, bot)) => {
+                  deferred = highlight_viewport(
+                    &mut bufdata,
+                    nvim,
+                    &mut viewed,
+                    top,
+                    bot,
+                    0,
+                  )?;
+                  None
+                }
+                None if viewed.enabled() => viewed_calls(&mut bufdata, &viewed),
+                None => bufdata.highlight_all_calls(),
+              }
+            };
+            if let Some(calls) = calls {
               nvim.call_atomic(calls).context("call_atomic failed")?;
             }
+            update_foldtexts(&bufdata, nvim, first, last, added)?;
+            if bufdata.checks_duplicates() {
+              send_duplicates(&bufdata, nvim)?;
+            }
           }
         }
         Ok(RefreshFolds) => to_handler.send(bufdata.fold_calls())?,
-        Ok(HighlightRegion {
+        Ok(ConvertCard { line, target }) => {
+          to_handler.send(match LineNr::try_from_i64(line) {
+            Ok(line) => bufdata.convert_card(line, &target),
+            Err(e) => Value::from(format!("Could not convert card: {}", e)),
+          })?
+        }
+        Ok(Renumber {
+          entity,
+          start,
+          step,
           firstline,
           lastline,
         }) => {
-          debug_assert!(
-            lastline >= 0 && firstline >= 0 && lastline >= firstline
-          );
-          let lastline = LineNr::from_i64(lastline);
-          let firstline = LineNr::from_i64(firstline);
+          to_handler.send(match LineNr::try_range(firstline, lastline) {
+            Ok([first, last]) => {
+              bufdata.renumber(&entity, start, step, first, last)
+            }
+            Err(e) => Value::from(format!("Could not renumber: {}", e)),
+          })?
+        }
+        Ok(RenameId { entity, old, new }) => {
+          receive_index(&mut indexing, &mut index);
+          to_handler.send(bufdata.rename_id(
+            &entity,
+            old,
+            new,
+            index.as_deref(),
+          ))?
+        }
+        Ok(OffsetInclude {
+          path,
+          line,
+          offset,
+          entities,
+        }) => {
+          receive_index(&mut indexing, &mut index);
+          let dir = Path::new(&path)
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+          let res = if indexing.is_some() {
+            Err(failure::err_msg("The include files are not indexed yet!"))
+          } else {
+            LineNr::try_from_i64(line).and_then(|line| {
+              bufdata.offset_include(
+                line,
+                &dir,
+                offset,
+                &entities,
+                index.as_deref(),
+              )
+            })
+          };
+
+          to_handler.send(match res {
+            Ok(v) => v,
+            Err(e) => Value::from(format!("Could not offset the IDs: {}", e)),
+          })?
+        }
+        Ok(AlignCells {
+          firstline,
+          lastline,
+          modified,
+        }) => {
+          to_handler.send(match LineNr::try_range(firstline, lastline) {
+            Ok([first, last]) => bufdata.align_cells(first, last, modified),
+            Err(e) => Value::from(format!("Could not align cells: {}", e)),
+          })?
+        }
+        Ok(Written) => bufdata.written(),
+        Ok(Inccommand { active }) => {
+          inccommand = active;
+          // The preview got undone, so the highlights of the unchanged lines
+          // are sent again
+          if let Some([first, last]) = previewed.filter(|_| !active) {
+            previewed = None;
+            let last = last.min(bufdata.end());
+            let range = bufdata.hl_linerange(first, last);
+            let calls = bufdata.highlight_region_calls(range, first, last);
+            if let Some(calls) = calls {
+              viewed.view(first, last, Instant::now());
+              nvim.call_atomic(calls).context("call_atomic failed")?;
+            }
+          }
+        }
+        Ok(CellHint { line, column }) => {
+          // Any column past the end of the line gives the same hint
+          #[allow(clippy::cast_possible_truncation)]
+          let column = cmp::min(column.max(0), 255) as u8;
+          to_handler.send(match LineNr::try_from_i64(line) {
+            Ok(line) => bufdata.cell_hint(line, column),
+            Err(e) => Value::from(format!("Could not get cell hint: {}", e)),
+          })?
+        }
+        Ok(FoldLevel { line }) => to_handler.send(Value::from(
+          LineNr::try_from_i64(line)
+            .map_or(ExprLevel::Outside, |l| bufdata.fold_level(l)),
+        ))?,
+        Ok(FoldsForRange {
+          firstline,
+          lastline,
+        }) => {
+          let res = LineNr::try_range(firstline, lastline);
+
+          to_handler.send(match res {
+            Ok([first, last]) => {
+              let levels = if first < last {
+                bufdata.folds_in_range(first, last.prev())
+              } else {
+                vec![vec![]; 3]
+              };
+              Value::from(
+                levels.into_iter().map(Value::from).collect::<Vec<_>>(),
+              )
+            }
+            Err(e) => Value::from(format!("Could not get the folds: {}", e)),
+          })?
+        }
+        Ok(HighlightsForRange {
+          firstline,
+          lastline,
+        }) => {
+          let res = LineNr::try_range(firstline, lastline);
+
+          to_handler.send(match res {
+            Ok([first, last]) => {
+              Value::from(bufdata.highlights_in_range(first, last))
+            }
+            Err(e) => {
+              Value::from(format!("Could not get the highlights: {}", e))
+            }
+          })?
+        }
+        Ok(SemanticTokens {
+          firstline,
+          lastline,
+        }) => {
+          let res = LineNr::try_range(firstline, lastline);
+
+          to_handler.send(match res {
+            Ok([first, last]) => {
+              semantic_tokens(bufdata.semantic_tokens(first, last))
+            }
+            Err(e) => {
+              Value::from(format!("Could not get the semantic tokens: {}", e))
+            }
+          })?
+        }
+        Ok(ExternalHighlights {
+          firstline,
+          lastline,
+          spans,
+        }) => {
+          let res = LineNr::try_range(firstline, lastline);
+
+          to_handler.send(match res {
+            Ok([first, last]) => {
+              if let Some(calls) =
+                bufdata.set_external_highlights(spans, first, last)
+              {
+                nvim.call_atomic(calls).context("call_atomic failed")?;
+              }
+              Value::from(true)
+            }
+            Err(e) => {
+              Value::from(format!("Could not set the highlights: {}", e))
+            }
+          })?
+        }
+        Ok(CardInfo { line }) => {
+          let res = LineNr::try_from_i64(line)
+            .and_then(|line| card_info(&bufdata, nvim, line));
+
+          to_handler.send(match res {
+            Ok(v) => v,
+            Err(e) => Value::from(format!("Could not get card info: {}", e)),
+          })?
+        }
+        Ok(GesContents { line }) => {
+          receive_index(&mut indexing, &mut index);
+          // The IDs of all selectors are looked up
+          bufdata.index_xrefs();
+          let res = LineNr::try_from_i64(line).and_then(|line| {
+            ges_contents(&bufdata, nvim, line, index.as_deref())
+          });
+
+          to_handler.send(match res {
+            Ok(v) => v,
+            Err(e) => Value::from(format!("Could not list the GES: {}", e)),
+          })?
+        }
+        Ok(CellRange { line, field }) => {
+          let res = LineNr::try_from_i64(line)
+            .and_then(|line| cell_range(&bufdata, nvim, line, &field));
+
+          to_handler.send(match res {
+            Ok(v) => v,
+            Err(e) => Value::from(format!("Could not get cell range: {}", e)),
+          })?
+        }
+        Ok(CardObject { line, inner }) => {
+          let res = LineNr::try_from_i64(line).and_then(|line| {
+            bufdata.card_object(line, inner).ok_or_else(|| {
+              failure::err_msg(format!(
+                "Line {} is not part of a card!",
+                line + 1
+              ))
+            })
+          });
 
-          let fl = bufdata.first_before(firstline);
-          let mut ll = bufdata.first_after(lastline);
+          to_handler.send(match res {
+            Ok(range) => Value::from(vec![
+              Value::from(i64::from(range[0])),
+              Value::from(i64::from(range[1])),
+            ]),
+            Err(e) => Value::from(format!("Could not select the card: {}", e)),
+          })?
+        }
+        Ok(CellNumber {
+          line,
+          column,
+          ref units,
+        }) => {
+          let res = LineNr::try_from_i64(line)
+            .and_then(|line| cell_number(&bufdata, nvim, line, column, units));
 
-          // highlight_region is end_exclusive, so we need to make sure
-          // we include the last line requested even if it is a keyword line
-          if ll.1 == lastline {
-            ll.0 += 1;
-            ll.1 += 1;
+          to_handler.send(match res {
+            Ok(v) => v,
+            Err(e) => Value::from(format!("Could not inspect cell: {}", e)),
+          })?
+        }
+        Ok(e @ DiffDeck { .. }) | Ok(e @ KeywordSummary) => {
+          to_handler.send(background::answer(&e, &bufdata, None))?
+        }
+        Ok(Metadata) => to_handler.send(Value::from(bufdata.metadata()))?,
+        Ok(Stats) => {
+          to_handler.send(bufdata.stats().to_value(bufdata.timings()))?
+        }
+        Ok(Health) => {
+          receive_index(&mut indexing, &mut index);
+          to_handler.send(health(
+            &bufdata,
+            nvim,
+            preview,
+            index.as_deref(),
+            indexing.is_some(),
+          ))?
+        }
+        Ok(CoalesceWindow { millis }) => {
+          window = Duration::from_millis(millis.max(0) as u64);
+          to_handler.send(Value::from(true))?
+        }
+        Ok(FoldChunkSize { size }) => {
+          fold_chunk = cmp::max(size, 1) as usize;
+          to_handler.send(Value::from(true))?
+        }
+        Ok(CheckDuplicates { enable }) => {
+          bufdata.set_check_duplicates(enable);
+          to_handler.send(Value::from(
+            bufdata
+              .duplicates()
+              .iter()
+              .map(Value::from)
+              .collect::<Vec<_>>(),
+          ))?
+        }
+        Ok(FoldTexts { texts }) => {
+          bufdata.set_foldtexts(texts);
+          to_handler.send(bufdata.fold_calls())?
+        }
+        Ok(FoldGroups { groups }) => {
+          to_handler.send(match bufdata.set_fold_groups(groups) {
+            Ok(()) => bufdata.fold_calls(),
+            Err(e) => Value::from(format!("Could not group folds: {}", e)),
+          })?
+        }
+        Ok(EnableHighlights { enable }) => {
+          if !enable {
+            deferred.clear();
+            viewed.clear();
           }
-          let newrange = bufdata.hl_linerange(fl.1, ll.1);
+          let calls = bufdata.set_highlights_enabled(enable)?;
+          if let Some(calls) = calls {
+            nvim.call_atomic(calls).context("call_atomic failed")?;
+          }
+          to_handler.send(Value::from(true))?
+        }
+        Ok(HighlightAge { millis }) => {
+          #[allow(clippy::cast_sign_loss)]
+          let age = Some(millis)
+            .filter(|&m| m > 0)
+            .map(|m| Duration::from_millis(m as u64));
+          let was_enabled = viewed.enabled();
+          viewed.set_max_age(age);
 
-          if let Some(calls) =
-            bufdata.highlight_region_calls(newrange, fl.1, ll.1)
-          {
+          if viewed.enabled() && !was_enabled {
+            // Only the viewed lines are highlighted from now on
+            deferred.clear();
+            if bufdata.highlights_enabled() {
+              nvim
+                .call_atomic(bufdata.clear_calls())
+                .context("call_atomic failed")?;
+            }
+          }
+          if viewed.enabled() != was_enabled {
+            if let Some((top, bot)) = viewport {
+              deferred = highlight_viewport(
+                &mut bufdata,
+                nvim,
+                &mut viewed,
+                top,
+                bot,
+                0,
+              )?;
+            }
+          }
+          to_handler.send(Value::from(true))?
+        }
+        Ok(HighlightConfig { config }) => {
+          if let Some(calls) = bufdata.set_highlight_config(config)? {
             nvim.call_atomic(calls).context("call_atomic failed")?;
           }
+          to_handler.send(Value::from(true))?
+        }
+        Ok(LintSections { rules }) => {
+          let rules = if rules.is_empty() {
+            &DEFAULT_SECTION_RULES[..]
+          } else {
+            &rules[..]
+          };
+          let diagnostics = bufdata.lint_sections(rules);
+
+          to_handler.send(Value::from(
+            diagnostics.iter().map(Value::from).collect::<Vec<_>>(),
+          ))?
+        }
+        Ok(ExportFolds { path }) => {
+          let res = fs::write(&path, bufdata.fold_state().to_string());
+
+          to_handler.send(match res {
+            Ok(()) => Value::from(true),
+            Err(e) => Value::from(format!("Could not write '{}': {}", path, e)),
+          })?
+        }
+        Ok(ImportFolds { path }) => {
+          let res = fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|s| FoldState::parse(&s))
+            .and_then(|state| bufdata.set_fold_state(state));
+
+          to_handler.send(match res {
+            Ok(()) => bufdata.fold_calls(),
+            Err(e) => {
+              Value::from(format!("Could not import '{}': {}", path, e))
+            }
+          })?
+        }
+        Ok(SaveState { path }) => {
+          receive_index(&mut indexing, &mut index);
+          let session = Session {
+            folds: bufdata.fold_state(),
+            index: index.clone(),
+          };
+          let res = fs::write(&path, session.to_string());
+
+          to_handler.send(match res {
+            Ok(()) => Value::from(true),
+            Err(e) => Value::from(format!("Could not write '{}': {}", path, e)),
+          })?
+        }
+        Ok(LoadState { path }) => {
+          receive_index(&mut indexing, &mut index);
+          let res = fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|s| Session::parse(&s))
+            .and_then(|session| {
+              bufdata.set_fold_state(session.folds)?;
+              Ok(session.index)
+            });
+
+          to_handler.send(match res {
+            Ok(i) => {
+              // An index being built is newer than the saved one
+              if indexing.is_none() && i.is_some() {
+                index = i;
+              }
+              bufdata.fold_calls()
+            }
+            Err(e) => Value::from(format!("Could not load '{}': {}", path, e)),
+          })?
+        }
+        Ok(RenameFold { start_line, label }) => {
+          let res = LineNr::try_from_i64(start_line)
+            .and_then(|line| bufdata.rename_fold(line, label));
+
+          to_handler.send(match res {
+            Ok(()) => bufdata.fold_calls(),
+            Err(e) => Value::from(format!("Could not rename fold: {}", e)),
+          })?
+        }
+        Ok(ExtractInclude {
+          firstline,
+          lastline,
+          path,
+        }) => {
+          let res =
+            LineNr::try_range(firstline, lastline).and_then(|[first, last]| {
+              extract_include(&bufdata, nvim, first, last, &path)
+            });
+
+          to_handler.send(match res {
+            Ok(line) => {
+              Value::from(vec![(Value::from("line"), Value::from(line))])
+            }
+            Err(e) => Value::from(format!("Could not extract include: {}", e)),
+          })?
+        }
+        Ok(ToggleComment {
+          firstline,
+          lastline,
+        }) => {
+          let res =
+            LineNr::try_range(firstline, lastline).and_then(|[first, last]| {
+              toggle_comment(&bufdata, nvim, first, last)
+            });
+
+          to_handler.send(match res {
+            Ok((first, last, lines)) => Value::from(vec![
+              (Value::from("first"), Value::from(first)),
+              (Value::from("last"), Value::from(last)),
+              (
+                Value::from("lines"),
+                Value::from(
+                  lines.into_iter().map(Value::from).collect::<Vec<_>>(),
+                ),
+              ),
+            ]),
+            Err(e) => Value::from(format!("Could not toggle comment: {}", e)),
+          })?
+        }
+        Ok(IndexIncludes { path }) => {
+          let dir = Path::new(&path)
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+          let receiver = index::spawn(dir, bufdata.includes());
+          // Replacing the watcher stops the old one
+          #[cfg(feature = "watch")]
+          let receiver = {
+            let (receiver, w) = watch::spawn(receiver, to_self.clone());
+            _watcher = Some(w);
+            receiver
+          };
+          indexing = Some(receiver);
+          to_handler.send(Value::from(true))?
+        }
+        Ok(IncludeChanged { path }) => {
+          nvim
+            .execute_lua(
+              "require('nvimpam').include_changed(...)",
+              vec![
+                bufdata.buf.get_value().clone(),
+                Value::from(path.to_string_lossy().into_owned()),
+              ],
+            )
+            .context("Could not notify about the include file")?;
+        }
+        Ok(FindDefinition { entity, id }) => {
+          receive_index(&mut indexing, &mut index);
+          // Definitions are likely looked up again, keep the IDs up to date
+          bufdata.index_xrefs();
+          let res = find_definition(
+            &bufdata,
+            index.as_deref(),
+            indexing.is_some(),
+            &entity,
+            id,
+          );
+
+          to_handler.send(match res {
+            Ok(v) => v,
+            Err(e) => Value::from(format!("Could not find definition: {}", e)),
+          })?
+        }
+        Ok(e @ Quickfix { .. }) => {
+          receive_index(&mut indexing, &mut index);
+          to_handler.send(background::answer(&e, &bufdata, index.as_deref()))?
+        }
+        Ok(Background { id, request }) => {
+          receive_index(&mut indexing, &mut index);
+          requests.submit(id, *request, &bufdata, index.clone())?
+        }
+        Ok(Cancel { id }) => {
+          if !requests.cancel(id) {
+            info!("Background request {} already finished", id);
+          }
+        }
+        Ok(Finished { id, result }) => match result {
+          Some(value) if requests.finish(id) => {
+            nvim
+              .execute_lua(
+                "require('nvimpam').finish_request(...)",
+                vec![Value::from(id), value],
+              )
+              .context("Could not send the answer of a background request")?;
+          }
+          _ => {
+            let _ = requests.finish(id);
+          }
+        },
+        Ok(HighlightRegion {
+          firstline,
+          lastline,
+        }) => match LineNr::try_range(firstline, lastline) {
+          Ok([first, last]) => {
+            highlight_region(&mut bufdata, nvim, &mut viewed, first, last)?
+          }
+          Err(e) => warn!("Ignoring HighlightRegion: {}", e),
+        },
+        Ok(HighlightViewport { topline, botline }) => {
+          match LineNr::try_range(topline, botline) {
+            Ok([top, bot]) => {
+              viewport = Some((top, bot));
+              deferred = highlight_viewport(
+                &mut bufdata,
+                nvim,
+                &mut viewed,
+                top,
+                bot,
+                0,
+              )?;
+            }
+            Err(e) => warn!("Ignoring HighlightViewport: {}", e),
+          }
+        }
+        Ok(ViewportEvent { topline, botline }) => {
+          match LineNr::try_range(topline, cmp::max(botline - 1, topline)) {
+            Ok([top, bot]) => {
+              let delta = viewport.map_or(0, |(t, _)| top - t);
+              viewport = Some((top, bot));
+              deferred = highlight_viewport(
+                &mut bufdata,
+                nvim,
+                &mut viewed,
+                top,
+                bot,
+                delta,
+              )?;
+            }
+            Err(e) => warn!("Ignoring ViewportEvent: {}", e),
+          }
+        }
+        Ok(Restart) => match buffer_changedtick(&bufdata, nvim) {
+          Ok(changedtick) => {
+            to_handler.send(Value::from(vec![
+              (
+                Value::from("state"),
+                Value::from(bufdata.fold_state().to_string()),
+              ),
+              (Value::from("changedtick"), Value::from(changedtick)),
+            ]))?;
+            break;
+          }
+          Err(e) => to_handler.send(Value::from(format!(
+            "Could not hand over the state: {}",
+            e
+          )))?,
+        },
+        Ok(RestoreState { state, changedtick }) => {
+          match FoldState::parse(&state) {
+            Ok(state) if parsed => {
+              let current = buffer_changedtick(&bufdata, nvim)?;
+              restore_folds(
+                &mut bufdata,
+                nvim,
+                state,
+                changedtick == current,
+                fold_chunk,
+              )?;
+            }
+            Ok(state) => restore = Some((state, changedtick)),
+            Err(e) => warn!("Could not restore folds: {}", e),
+          }
         }
         Ok(Quit) => {
+          // Neovim might be exiting already
+          if let Err(e) = cleanup(&bufdata, nvim) {
+            warn!("Could not clean up before quitting: {}", e);
+          }
           break;
         }
+        Ok(Panicked) => {
+          if let Err(e) = cleanup(&bufdata, nvim) {
+            warn!("Could not clean up after a panic: {}", e);
+          }
+          return Err(failure::err_msg("A thread of nvimpam panicked!"));
+        }
         Ok(DetachEvent { buf }) => {
           if *bufdata.buf == buf {
-            buf
-              .clear_namespace(nvim, 5, 0, -1)
-              .context("could not clear namespace 5")?;
+            clear_highlights(&bufdata, nvim)?;
             break;
           } else {
             warn!(
@@ -156,6 +1445,11 @@ impl Event {
             );
           }
         }
+        Ok(EventTimings { count }) => {
+          #[allow(clippy::cast_sign_loss)]
+          let count = cmp::max(count, 0) as usize;
+          to_handler.send(timings.to_value(count))?
+        }
         Ok(o) => {
           warn!("receiver recieved {:?}", o);
         }
@@ -163,6 +1457,23 @@ impl Event {
           warn!("receiver received error: {:?}", e);
         }
       }
+
+      if let Some(msg) = bufdata.take_inconsistency() {
+        report_inconsistency(nvim, &msg)?;
+      }
+      if bufdata.take_no_cards() {
+        report_no_cards(nvim)?;
+      }
+
+      log_timing(
+        &mut timings,
+        EventTiming {
+          event: name,
+          duration: start.elapsed(),
+          lines,
+          folds,
+        },
+      );
     }
     info!("quitting");
     Ok(())
@@ -182,7 +1493,7 @@ impl fmt::Debug for Event {
         ..
       } => write!(
         f,
-        "Update{{ changedtick: {}, firstline: {}, \
+        "Update{{ changedtick: {:?}, firstline: {}, \
          lastline: {}, #linedata: {} }}",
         changedtick,
         firstline,
@@ -200,9 +1511,1465 @@ impl fmt::Debug for Event {
         "HighlightRegion{{ firstline: {}, lastline: {} }}",
         firstline, lastline
       ),
-      DetachEvent { .. } => write!(f, "DetachEvent"),
-      RefreshFolds => write!(f, "RefreshFolds"),
-      Quit => write!(f, "Quit"),
-    }
+      HighlightViewport { topline, botline } => write!(
+        f,
+        "HighlightViewport{{ topline: {}, botline: {} }}",
+        topline, botline
+      ),
+      ViewportEvent { topline, botline } => write!(
+        f,
+        "ViewportEvent{{ topline: {}, botline: {} }}",
+        topline, botline
+      ),
+      HighlightAge { millis } => {
+        write!(f, "HighlightAge{{ millis: {} }}", millis)
+      }
+      ConvertCard { line, ref target } => {
+        write!(f, "ConvertCard{{ line: {}, target: {} }}", line, target)
+      }
+      Renumber {
+        ref entity,
+        start,
+        step,
+        firstline,
+        lastline,
+      } => write!(
+        f,
+        "Renumber{{ entity: {}, start: {}, step: {}, firstline: {}, \
+         lastline: {} }}",
+        entity, start, step, firstline, lastline
+      ),
+      RenameId {
+        ref entity,
+        old,
+        new,
+      } => write!(
+        f,
+        "RenameId{{ entity: {}, old: {}, new: {} }}",
+        entity, old, new
+      ),
+      OffsetInclude {
+        ref path,
+        line,
+        offset,
+        ref entities,
+      } => write!(
+        f,
+        "OffsetInclude{{ path: {}, line: {}, offset: {}, entities: {:?} }}",
+        path, line, offset, entities
+      ),
+      AlignCells {
+        firstline,
+        lastline,
+        modified,
+      } => write!(
+        f,
+        "AlignCells{{ firstline: {}, lastline: {}, modified: {} }}",
+        firstline, lastline, modified
+      ),
+      CellHint { line, column } => {
+        write!(f, "CellHint{{ line: {}, column: {} }}", line, column)
+      }
+      HighlightConfig { ref config } => {
+        write!(f, "HighlightConfig{{ config: {:?} }}", config)
+      }
+      CoalesceWindow { millis } => {
+        write!(f, "CoalesceWindow{{ millis: {} }}", millis)
+      }
+      FoldChunkSize { size } => {
+        write!(f, "FoldChunkSize{{ size: {} }}", size)
+      }
+      CheckDuplicates { enable } => {
+        write!(f, "CheckDuplicates{{ enable: {} }}", enable)
+      }
+      EnableHighlights { enable } => {
+        write!(f, "EnableHighlights{{ enable: {} }}", enable)
+      }
+      FoldTexts { ref texts } => {
+        write!(f, "FoldTexts{{ texts: {:?} }}", texts)
+      }
+      FoldGroups { ref groups } => {
+        write!(f, "FoldGroups{{ groups: {:?} }}", groups)
+      }
+      Metadata => write!(f, "Metadata"),
+      Stats => write!(f, "Stats"),
+      Health => write!(f, "Health"),
+      KeywordSummary => write!(f, "KeywordSummary"),
+      LintSections { ref rules } => {
+        write!(f, "LintSections{{ #rules: {} }}", rules.len())
+      }
+      ExportFolds { ref path } => write!(f, "ExportFolds{{ path: {} }}", path),
+      ImportFolds { ref path } => write!(f, "ImportFolds{{ path: {} }}", path),
+      SaveState { ref path } => write!(f, "SaveState{{ path: {} }}", path),
+      LoadState { ref path } => write!(f, "LoadState{{ path: {} }}", path),
+      RenameFold {
+        start_line,
+        ref label,
+      } => write!(
+        f,
+        "RenameFold{{ start_line: {}, label: {} }}",
+        start_line, label
+      ),
+      ExtractInclude {
+        firstline,
+        lastline,
+        ref path,
+      } => write!(
+        f,
+        "ExtractInclude{{ firstline: {}, lastline: {}, path: {} }}",
+        firstline, lastline, path
+      ),
+      ToggleComment {
+        firstline,
+        lastline,
+      } => write!(
+        f,
+        "ToggleComment{{ firstline: {}, lastline: {} }}",
+        firstline, lastline
+      ),
+      IndexIncludes { ref path } => {
+        write!(f, "IndexIncludes{{ path: {} }}", path)
+      }
+      FindDefinition { ref entity, id } => {
+        write!(f, "FindDefinition{{ entity: {}, id: {} }}", entity, id)
+      }
+      Quickfix { ref path } => write!(f, "Quickfix{{ path: {} }}", path),
+      EventTimings { count } => write!(f, "EventTimings{{ count: {} }}", count),
+      CardInfo { line } => write!(f, "CardInfo{{ line: {} }}", line),
+      GesContents { line } => write!(f, "GesContents{{ line: {} }}", line),
+      FoldLevel { line } => write!(f, "FoldLevel{{ line: {} }}", line),
+      FoldsForRange {
+        firstline,
+        lastline,
+      } => write!(
+        f,
+        "FoldsForRange{{ firstline: {}, lastline: {} }}",
+        firstline, lastline
+      ),
+      HighlightsForRange {
+        firstline,
+        lastline,
+      } => write!(
+        f,
+        "HighlightsForRange{{ firstline: {}, lastline: {} }}",
+        firstline, lastline
+      ),
+      SemanticTokens {
+        firstline,
+        lastline,
+      } => write!(
+        f,
+        "SemanticTokens{{ firstline: {}, lastline: {} }}",
+        firstline, lastline
+      ),
+      ExternalHighlights {
+        firstline,
+        lastline,
+        ref spans,
+      } => write!(
+        f,
+        "ExternalHighlights{{ firstline: {}, lastline: {}, spans: {} }}",
+        firstline,
+        lastline,
+        spans.len()
+      ),
+      CellRange { line, ref field } => {
+        write!(f, "CellRange{{ line: {}, field: {} }}", line, field)
+      }
+      CardObject { line, inner } => {
+        write!(f, "CardObject{{ line: {}, inner: {} }}", line, inner)
+      }
+      CellNumber { line, column, .. } => {
+        write!(f, "CellNumber{{ line: {}, column: {} }}", line, column)
+      }
+      DiffDeck { ref path } => write!(f, "DiffDeck{{ path: {} }}", path),
+      Parsed { id, ref result } => write!(
+        f,
+        "Parsed{{ id: {}, cancelled: {} }}",
+        id,
+        result.as_ref().map_or(false, Option::is_none)
+      ),
+      IncludeChanged { ref path } => {
+        write!(f, "IncludeChanged{{ path: {} }}", path.display())
+      }
+      Panicked => write!(f, "Panicked"),
+      Background { id, ref request } => {
+        write!(f, "Background{{ id: {}, request: {:?} }}", id, request)
+      }
+      Cancel { id } => write!(f, "Cancel{{ id: {} }}", id),
+      Finished { id, ref result } => {
+        write!(f, "Finished{{ id: {}, result: {:?} }}", id, result)
+      }
+      DetachEvent { .. } => write!(f, "DetachEvent"),
+      RefreshFolds => write!(f, "RefreshFolds"),
+      Written => write!(f, "Written"),
+      Inccommand { active } => {
+        write!(f, "Inccommand{{ active: {} }}", active)
+      }
+      Restart => write!(f, "Restart"),
+      RestoreState { changedtick, .. } => {
+        write!(f, "RestoreState{{ changedtick: {} }}", changedtick)
+      }
+      Quit => write!(f, "Quit"),
+    }
+  }
+}
+
+/// Log `timing` and add it to `timings`
+/// Show the inconsistency `msg` found in strict mode to the user
+fn report_inconsistency(nvim: &mut Neovim, msg: &str) -> Result<(), Error> {
+  nvim
+    .execute_lua(
+      "require('nvimpam').nvimpam_err(...)",
+      vec![Value::from(format!("Nvimpam (strict mode): {}", msg))],
+    )
+    .context("execute_lua nvimpam_err failed")?;
+  Ok(())
+}
+
+/// Tell the user that the buffer was not parsed since no cards were found,
+/// see [`prescan`](crate::bufdata::prescan)
+fn report_no_cards(nvim: &mut Neovim) -> Result<(), Error> {
+  nvim
+    .out_write(
+      "Nvimpam: No cards found, the buffer is not folded or highlighted\n",
+    )
+    .context("out_write failed")?;
+  Ok(())
+}
+
+fn log_timing(timings: &mut TimingLog, timing: EventTiming) {
+  info!("{}", timing);
+  timings.push(timing);
+}
+
+/// Merge the [`LinesEvent`](crate::event::Event::LinesEvent) `next` into the
+/// `LinesEvent` `prev`, if both are changes to a part of the buffer and `next`
+/// only touches lines `prev` replaced or inserted. Returns `next` if they can't
+/// be merged.
+fn merge_lines(prev: &mut Event, next: Event) -> Option<Event> {
+  let (buf, f2, l2, d2, c2) = match next {
+    Event::LinesEvent {
+      buf,
+      firstline,
+      lastline,
+      linedata,
+      changedtick,
+      more: false,
+    } if lastline >= 0 && changedtick != Some(0) => {
+      (buf, firstline, lastline, linedata, changedtick)
+    }
+    _ => return Some(next),
+  };
+
+  if let Event::LinesEvent {
+    firstline,
+    lastline,
+    ref mut linedata,
+    ref mut changedtick,
+    more: false,
+    ..
+  } = *prev
+  {
+    let end = firstline + linedata.len() as i64;
+
+    if lastline >= 0
+      && *changedtick != Some(0)
+      && firstline <= f2
+      && f2 <= l2
+      && l2 <= end
+    {
+      let range = (f2 - firstline) as usize..(l2 - firstline) as usize;
+      let _ = linedata.splice(range, d2);
+      *changedtick = c2.or(*changedtick);
+      return None;
+    }
+  }
+
+  Some(Event::LinesEvent {
+    buf,
+    changedtick: c2,
+    firstline: f2,
+    lastline: l2,
+    linedata: d2,
+    more: false,
+  })
+}
+
+/// Merge the [`LinesEvent`](crate::event::Event::LinesEvent)s arriving within
+/// `window` after `event` into it, as long as possible. The first event that
+/// can't be merged is put into `pending`, to be handled next.
+fn coalesce_lines(
+  mut event: Event,
+  from_handler: &mpsc::Receiver<Event>,
+  window: Duration,
+  pending: &mut Option<Event>,
+) -> Event {
+  let deadline = Instant::now() + window;
+
+  loop {
+    let now = Instant::now();
+    let timeout = if now < deadline {
+      deadline - now
+    } else {
+      Duration::from_millis(0)
+    };
+
+    match from_handler.recv_timeout(timeout) {
+      Ok(next) => {
+        if let Some(next) = merge_lines(&mut event, next) {
+          *pending = Some(next);
+          return event;
+        }
+      }
+      Err(_) => return event,
+    }
+  }
+}
+
+/// The number of folds sent to neovim at once by
+/// [`send_folds`](crate::event::send_folds), unless set by a
+/// [`FoldChunkSize`](crate::event::Event::FoldChunkSize) event
+const DEFAULT_FOLD_CHUNK: usize = 5000;
+
+/// Restore the custom folds from a
+/// [`FoldState`](crate::bufdata::foldstate::FoldState) handed over by a
+/// restarting nvimpam, and send the folds to neovim. If the buffer changed in
+/// between, i.e. `unchanged` is false because the changedtick differs or the
+/// lines don't match the hash of the state, the custom folds are lost but the
+/// generated ones are still sent.
+fn restore_folds<N: NeovimApi>(
+  bufdata: &mut BufData,
+  nvim: &mut N,
+  state: FoldState,
+  unchanged: bool,
+  chunk: usize,
+) -> Result<(), Error> {
+  if !unchanged {
+    warn!("Could not restore folds: The buffer changed during the restart");
+  } else if let Err(e) = bufdata.set_fold_state(state) {
+    warn!("Could not restore folds: {}", e);
+  }
+
+  send_folds(bufdata, nvim, chunk)
+}
+
+/// The current changedtick of the buffer
+fn buffer_changedtick(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+) -> Result<u64, Error> {
+  let changedtick = bufdata
+    .buf
+    .get_changedtick(nvim)
+    .context("Could not get the changedtick")?;
+  Ok(changedtick as u64)
+}
+
+/// Send all folds of the buffer to neovim, at most `chunk` of them per call,
+/// so huge decks don't block neovim while it decodes a single message. The
+/// folds are only applied by the final call to `commit_folds`.
+fn send_folds<N: NeovimApi>(
+  bufdata: &BufData,
+  nvim: &mut N,
+  chunk: usize,
+) -> Result<(), Error> {
+  let luafn = "require('nvimpam').add_folds(...)";
+
+  for (i, level) in bufdata.fold_levels().iter().enumerate() {
+    for folds in level.chunks(chunk) {
+      nvim
+        .execute_lua(
+          luafn,
+          vec![Value::from(i + 1), Value::from(folds.to_vec())],
+        )
+        .context("Could not send folds")?;
+    }
+  }
+
+  nvim
+    .execute_lua("require('nvimpam').commit_folds()", vec![])
+    .context("Could not send folds")?;
+
+  Ok(())
+}
+
+/// Remove the highlights and folds nvimpam added to the buffer, along with the
+/// highlighted duplicates
+/// Remove the highlights of the buffer in the configured namespace, see
+/// [`HlConfig`](crate::bufdata::highlights::HlConfig)
+fn clear_highlights<N: NeovimApi>(
+  bufdata: &BufData,
+  nvim: &mut N,
+) -> Result<(), Error> {
+  nvim
+    .call_atomic(bufdata.clear_calls())
+    .context("Could not clear the highlights")?;
+  Ok(())
+}
+
+fn cleanup<N: NeovimApi>(bufdata: &BufData, nvim: &mut N) -> Result<(), Error> {
+  let buf = bufdata.buf.get_value().clone();
+
+  clear_highlights(bufdata, nvim)?;
+  nvim
+    .execute_lua("require('nvimpam').clear_folds(...)", vec![buf.clone()])
+    .context("Could not clear the folds")?;
+
+  if bufdata.checks_duplicates() {
+    nvim
+      .execute_lua(
+        "require('nvimpam').update_duplicates(...)",
+        vec![buf, Value::from(Vec::<Value>::new())],
+      )
+      .context("Could not clear the duplicates")?;
+  }
+
+  Ok(())
+}
+
+/// Write the lines `firstline..lastline` of the buffer to the new file `path`
+/// and return the `INCLU` line to replace them with. The lines are fetched
+/// from neovim, since comments are not kept in the
+/// [`BufData`](crate::bufdata::BufData). Existing files are not overwritten.
+fn extract_include(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  firstline: LineNr,
+  lastline: LineNr,
+  path: &str,
+) -> Result<String, Error> {
+  let _ = bufdata.whole_cards(firstline, lastline)?;
+
+  let lines = bufdata.buf.get_lines(
+    nvim,
+    i64::from(firstline),
+    i64::from(lastline),
+    true,
+  )?;
+  let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+
+  for line in lines {
+    writeln!(file, "{}", line)?;
+  }
+
+  Ok(format!("INCLU / {}", path))
+}
+
+/// The number of lines fetched at once when looking for the start and end of
+/// a block of comments
+const COMMENT_CHUNK: i64 = 100;
+
+/// Comment out or uncomment the cards in the lines `firstline..lastline`, see
+/// [`ToggleComment`](crate::event::Event::ToggleComment). Returns the range
+/// of lines to replace (end-exclusive) along with the new lines. The lines are
+/// fetched from neovim, since comments are not kept in the
+/// [`BufData`](crate::bufdata::BufData).
+fn toggle_comment(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  firstline: LineNr,
+  lastline: LineNr,
+) -> Result<(i64, i64, Vec<String>), Error> {
+  let buf = bufdata.buf;
+
+  if firstline == lastline {
+    return Err(failure::err_msg("No lines in the range!"));
+  }
+
+  let lines =
+    buf.get_lines(nvim, i64::from(firstline), i64::from(lastline), true)?;
+
+  if !lines.iter().all(|l| l.starts_with('$')) {
+    let first = bufdata.card_range(firstline).map_or(firstline, |r| r[0]);
+    let last = bufdata
+      .card_range(lastline.prev())
+      .map_or(lastline, |r| r[1] + 1);
+    let (first, last) = (i64::from(first), i64::from(last));
+    let lines = buf.get_lines(nvim, first, last, true)?;
+
+    return Ok((first, last, comment::comment(&lines)));
+  }
+
+  let (firstline, lastline) = (i64::from(firstline), i64::from(lastline));
+
+  // The cards might extend beyond the range, so find the whole block of
+  // comments around it
+  let mut start = firstline;
+  while start > 0 {
+    let from = cmp::max(0, start - COMMENT_CHUNK);
+    let chunk = buf.get_lines(nvim, from, start, true)?;
+    let n = chunk
+      .iter()
+      .rev()
+      .take_while(|l| l.starts_with('$'))
+      .count();
+
+    start -= n as i64;
+    if n < chunk.len() {
+      break;
+    }
+  }
+
+  let mut end = lastline;
+  loop {
+    let chunk = buf.get_lines(nvim, end, end + COMMENT_CHUNK, false)?;
+    let n = chunk.iter().take_while(|l| l.starts_with('$')).count();
+
+    end += n as i64;
+    if n < COMMENT_CHUNK as usize {
+      break;
+    }
+  }
+
+  let block = buf.get_lines(nvim, start, end, true)?;
+  let ([first, last], lines) = comment::uncomment(
+    buf,
+    &block,
+    (firstline - start) as usize,
+    (lastline - 1 - start) as usize,
+  )?;
+
+  Ok((start + first as i64, start + last as i64 + 1, lines))
+}
+
+/// Take the [`Index`](crate::index::Index) from the receiver `indexing` if
+/// it's done building
+fn receive_index(
+  indexing: &mut Option<mpsc::Receiver<Index>>,
+  index: &mut Option<Arc<Index>>,
+) {
+  if let Some(ref receiver) = indexing {
+    match receiver.try_recv() {
+      Ok(i) => {
+        *index = Some(Arc::new(i));
+        *indexing = None;
+      }
+      Err(TryRecvError::Disconnected) => *indexing = None,
+      Err(TryRecvError::Empty) => {}
+    }
+  }
+}
+
+/// Find the definition of the ID `id` of `entity`, see
+/// [`FindDefinition`](crate::event::Event::FindDefinition). The buffer is
+/// searched first, then the `index` of the include files. If `indexing` is
+/// true, a new index is still being built.
+fn find_definition(
+  bufdata: &BufData,
+  index: Option<&Index>,
+  indexing: bool,
+  entity: &str,
+  id: i64,
+) -> Result<Value, Error> {
+  let entity = Entity::from_name(entity)
+    .ok_or_else(|| failure::err_msg(format!("Unknown entity '{}'!", entity)))?;
+
+  let (file, line) = match bufdata.find_definition(entity, id) {
+    Some(line) => (String::new(), line),
+    None => match index.and_then(|i| i.find(entity, id)) {
+      Some(l) => (l.file.to_string_lossy().into_owned(), l.line),
+      None if indexing => {
+        return Err(failure::err_msg("The include files are not indexed yet!"))
+      }
+      None => {
+        return Err(failure::err_msg(format!(
+          "{:?} {} is not defined!",
+          entity, id
+        )))
+      }
+    },
+  };
+
+  Ok(Value::from(vec![
+    (Value::from("file"), Value::from(file)),
+    (Value::from("line"), Value::from(line)),
+  ]))
+}
+
+/// Describe the card containing `line`, see
+/// [`BufData::card_info`](crate::bufdata::BufData::card_info). The line
+/// before the card is fetched as well, since it might be the header of the
+/// keyword line.
+fn card_info(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  line: LineNr,
+) -> Result<Value, Error> {
+  let range = bufdata.card_range(line).ok_or_else(|| {
+    failure::err_msg(format!("Line {} is not part of a card!", line + 1))
+  })?;
+  let start = range[0].saturating_add(-1);
+  let lines = bufdata.buf.get_lines(
+    nvim,
+    i64::from(start),
+    i64::from(range[1]) + 1,
+    true,
+  )?;
+
+  bufdata.card_info(range, start, &lines)
+}
+
+/// List the GES containing `line`, see
+/// [`BufData::ges_contents`](crate::bufdata::BufData::ges_contents). The
+/// lines of the card containing it are fetched, since a GES is always part
+/// of a card.
+fn ges_contents(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  line: LineNr,
+  index: Option<&Index>,
+) -> Result<Value, Error> {
+  let range = bufdata.card_range(line).ok_or_else(|| {
+    failure::err_msg(format!("Line {} is not part of a GES!", line + 1))
+  })?;
+  let lines = bufdata.buf.get_lines(
+    nvim,
+    i64::from(range[0]),
+    i64::from(range[1]) + 1,
+    true,
+  )?;
+
+  bufdata.ges_contents(range[0], &lines, line, index)
+}
+
+/// The [`cell_run`](crate::bufdata::BufData::cell_run) of the line `line`,
+/// and the lines of the buffer around it starting at the returned line. The
+/// line before the run of cards is fetched as well, since it might be the
+/// header naming the cells.
+fn run_lines(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  line: LineNr,
+) -> Result<([LineNr; 2], LineNr, Vec<String>), Error> {
+  let run = bufdata.cell_run(line).ok_or_else(|| {
+    failure::err_msg(format!("Line {} is not part of a card!", line + 1))
+  })?;
+  let range = bufdata.card_range(line).unwrap_or(run);
+  let start = cmp::min(run[0], range[0]).saturating_add(-1);
+  let end = cmp::max(run[1], range[1]);
+  let lines =
+    bufdata
+      .buf
+      .get_lines(nvim, i64::from(start), i64::from(end) + 1, true)?;
+
+  Ok((run, start, lines))
+}
+
+/// Send the column range of the cell `field` of the line `line`, see
+/// [`BufData::cell_range`](crate::bufdata::BufData::cell_range)
+fn cell_range(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  line: LineNr,
+  field: &str,
+) -> Result<Value, Error> {
+  let (run, start, lines) = run_lines(bufdata, nvim, line)?;
+  bufdata.cell_range(run, start, &lines, line, field)
+}
+
+/// Send the number in the cell at the column `column` of the line `line`, see
+/// [`BufData::cell_number`](crate::bufdata::BufData::cell_number)
+fn cell_number(
+  bufdata: &BufData,
+  nvim: &mut Neovim,
+  line: LineNr,
+  column: i64,
+  units: &Units,
+) -> Result<Value, Error> {
+  let (run, start, lines) = run_lines(bufdata, nvim, line)?;
+  let column = cmp::max(column, 0) as usize;
+  bufdata.cell_number(run, start, &lines, line, column, units)
+}
+
+/// The LSP semantic tokens `data` along with their legend, i.e. a map with
+/// the keys `legend` and `data`, where the legend holds the `tokenTypes` and
+/// `tokenModifiers` like in the capabilities of an LSP server
+fn semantic_tokens(data: Vec<u64>) -> Value {
+  let strs = |s: &[&str]| {
+    Value::from(s.iter().map(|&t| Value::from(t)).collect::<Vec<_>>())
+  };
+
+  Value::from(vec![
+    (
+      Value::from("legend"),
+      Value::from(vec![
+        (Value::from("tokenTypes"), strs(TOKEN_TYPES)),
+        (Value::from("tokenModifiers"), strs(TOKEN_MODIFIERS)),
+      ]),
+    ),
+    (
+      Value::from("data"),
+      Value::from(data.into_iter().map(Value::from).collect::<Vec<_>>()),
+    ),
+  ])
+}
+
+/// The report for `:checkhealth`: The version of nvimpam, the API level of
+/// neovim (`nil` if unknown), if the buffer is attached (i.e. not in preview
+/// mode), the number of builtin and custom card definitions, the
+/// [`Stats`](crate::bufdata::stats::Stats) of the buffer including the
+/// timings, and the status of the index of the include files, one of `none`,
+/// `indexing` and `ready`.
+fn health<N: NeovimApi>(
+  bufdata: &BufData,
+  nvim: &mut N,
+  preview: bool,
+  index: Option<&Index>,
+  indexing: bool,
+) -> Value {
+  let api_level = nvim
+    .get_api_info()
+    .ok()
+    .and_then(|info| info.into_iter().nth(1))
+    .and_then(|info| map_get(info, "version"))
+    .and_then(|version| map_get(version, "api_level"))
+    .unwrap_or(Value::Nil);
+
+  let index_status = match index {
+    _ if indexing => "indexing",
+    Some(_) => "ready",
+    None => "none",
+  };
+  let index_errors = index.map_or(vec![], |i| {
+    i.errors().iter().map(|e| Value::from(e.as_str())).collect()
+  });
+
+  Value::from(vec![
+    (
+      Value::from("version"),
+      Value::from(env!("CARGO_PKG_VERSION")),
+    ),
+    (Value::from("api_level"), api_level),
+    (Value::from("attached"), Value::from(!preview)),
+    (
+      Value::from("builtin_cards"),
+      Value::from(Keyword::BUILTIN.len()),
+    ),
+    (
+      Value::from("custom_cards"),
+      Value::from(custom::custom_cards().len()),
+    ),
+    (
+      Value::from("highlights"),
+      Value::from(bufdata.highlights_enabled()),
+    ),
+    (
+      Value::from("degraded"),
+      bufdata.degraded().map_or(Value::Nil, |d| Value::from(d.name())),
+    ),
+    (
+      Value::from("stats"),
+      bufdata.stats().to_value(bufdata.timings()),
+    ),
+    (Value::from("index"), Value::from(index_status)),
+    (
+      Value::from("index_files"),
+      Value::from(index.map_or(0, |i| i.files().len())),
+    ),
+    (Value::from("index_errors"), Value::from(index_errors)),
+  ])
+}
+
+/// The value of the entry `key` of the map `map`, if it is one
+fn map_get(map: Value, key: &str) -> Option<Value> {
+  match map {
+    Value::Map(m) => m
+      .into_iter()
+      .find(|(k, _)| k.as_str() == Some(key))
+      .map(|(_, v)| v),
+    _ => None,
+  }
+}
+
+/// Update the foldtexts in neovim after an update changed the lines
+/// `firstline..lastline`, adding `added` lines. Neovim moves and resizes its
+/// folds along with the lines, so the foldtexts are moved as well, and the
+/// ones of the folds whose text changed are replaced. The folds themselves
+/// are not recreated. The line numbers are the ones after the update.
+fn update_foldtexts<N: NeovimApi>(
+  bufdata: &BufData,
+  nvim: &mut N,
+  firstline: LineNr,
+  lastline: LineNr,
+  added: isize,
+) -> Result<(), Error> {
+  let texts = bufdata.resized_fold_calls();
+
+  if added == 0 && texts.is_empty() {
+    return Ok(());
+  }
+
+  nvim
+    .execute_lua(
+      "require('nvimpam').update_foldtexts(...)",
+      vec![
+        Value::from(firstline),
+        Value::from(lastline.saturating_add(-added)),
+        Value::from(added as i64),
+        Value::from(texts),
+      ],
+    )
+    .context("Could not update the foldtexts")?;
+
+  Ok(())
+}
+
+/// Send the duplicate IDs of the buffer to neovim, which shows them as
+/// warnings
+fn send_duplicates<N: NeovimApi>(
+  bufdata: &BufData,
+  nvim: &mut N,
+) -> Result<(), Error> {
+  let duplicates: Vec<Value> =
+    bufdata.duplicates().iter().map(Value::from).collect();
+
+  let luafn = "require('nvimpam').update_duplicates(...)";
+  nvim
+    .execute_lua(
+      luafn,
+      vec![bufdata.buf.get_value().clone(), Value::from(duplicates)],
+    )
+    .context("Could not send duplicates")?;
+
+  Ok(())
+}
+
+/// The number of lines above and below the visible window that a
+/// [`HighlightViewport`](crate::event::Event::HighlightViewport) event
+/// highlights right away
+const VIEWPORT_MARGIN: usize = 100;
+/// The number of lines highlighted at once when idle
+const IDLE_CHUNK: usize = 1000;
+/// The number of changed lines above which an update only gets the lines
+/// around the visible ones highlighted right away, see
+/// [`clamp_to_viewport`](crate::event::clamp_to_viewport)
+const CLAMP_LINES: usize = 2 * IDLE_CHUNK;
+/// How long the event loop waits for an event before highlighting the next
+/// chunk of deferred lines
+const IDLE_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Split the lines `0..len` into the region around the visible lines
+/// `top..=bot` that should be highlighted right away, and chunks of the
+/// remaining lines to highlight when idle. If the view moved by `delta` lines,
+/// the chunks in that direction come first. Otherwise, chunks closer to the
+/// visible lines come first. All ranges are end-inclusive.
+fn viewport_ranges(
+  top: usize,
+  bot: usize,
+  len: usize,
+  delta: isize,
+) -> ([usize; 2], Vec<[usize; 2]>) {
+  let end = len.saturating_sub(1);
+  let last = cmp::min(bot + VIEWPORT_MARGIN, end);
+  let first = cmp::min(top.saturating_sub(VIEWPORT_MARGIN), last);
+
+  let after = (last + 1..len)
+    .step_by(IDLE_CHUNK)
+    .map(|s| [s, cmp::min(s + IDLE_CHUNK - 1, end)]);
+  let before = (0..first)
+    .rev()
+    .step_by(IDLE_CHUNK)
+    .map(|e| [e.saturating_sub(IDLE_CHUNK - 1), e]);
+
+  let mut chunks = vec![];
+  let mut after = after.fuse();
+  let mut before = before.fuse();
+
+  loop {
+    match (after.next(), before.next()) {
+      (None, None) => break,
+      (a, b) => chunks.extend(a.into_iter().chain(b)),
+    }
+  }
+
+  // The sort is stable, so closer chunks still come first
+  match delta.cmp(&0) {
+    cmp::Ordering::Greater => chunks.sort_by_key(|c| c[0] < first),
+    cmp::Ordering::Less => chunks.sort_by_key(|c| c[0] > last),
+    cmp::Ordering::Equal => {}
+  }
+
+  ([first, last], chunks)
+}
+
+/// Split the lines `first..=last` changed by an update into the lines around
+/// the visible lines `top..=bot` to highlight right away, and the chunks of
+/// the others to highlight when idle, like
+/// [`viewport_ranges`](crate::event::viewport_ranges) does for the whole
+/// buffer. If the visible lines are outside of the changed ones, the changed
+/// lines closest to them are highlighted right away. Returns `None` for
+/// updates of less than `CLAMP_LINES` lines, which are highlighted at once.
+fn clamp_to_viewport(
+  first: LineNr,
+  last: LineNr,
+  top: LineNr,
+  bot: LineNr,
+) -> Option<([LineNr; 2], Vec<[LineNr; 2]>)> {
+  let len = first.lines_to(last) + 1;
+  if len < CLAMP_LINES {
+    return None;
+  }
+
+  let offset = usize::from(first);
+  let line = |l: usize| LineNr::from_usize(l + offset);
+  let ([f, l], chunks) = viewport_ranges(
+    usize::from(top).saturating_sub(offset),
+    usize::from(bot).saturating_sub(offset),
+    len,
+    0,
+  );
+
+  Some((
+    [line(f), line(l)],
+    chunks.into_iter().map(|[f, l]| [line(f), line(l)]).collect(),
+  ))
+}
+
+/// Highlight the visible lines `top..=bot` and the lines around them. Returns
+/// the chunks of the lines to highlight when idle, see
+/// [`viewport_ranges`](crate::event::viewport_ranges). If the `viewed`
+/// regions are tracked, only the chunk the view is scrolling towards is
+/// returned, since the highlights of the others would be cleared anyways. In
+/// the [`Viewport`](crate::bufdata::degraded::Degraded) mode, nothing is
+/// highlighted when idle.
+fn highlight_viewport<N: NeovimApi>(
+  bufdata: &mut BufData,
+  nvim: &mut N,
+  viewed: &mut ViewedRegions,
+  top: LineNr,
+  bot: LineNr,
+  delta: isize,
+) -> Result<VecDeque<[LineNr; 2]>, Error> {
+  if !bufdata.highlights_enabled() {
+    return Ok(VecDeque::new());
+  }
+
+  let len = bufdata.end().into();
+  let ([first, last], mut chunks) =
+    viewport_ranges(top.into(), bot.into(), len, delta);
+
+  highlight_region(
+    bufdata,
+    nvim,
+    viewed,
+    LineNr::from_usize(first),
+    LineNr::from_usize(last),
+  )?;
+
+  if bufdata.degraded() == Some(Degraded::Viewport) {
+    chunks.clear();
+  } else if viewed.enabled() {
+    let ahead = |c: &[usize; 2]| {
+      (delta > 0 && c[0] > last) || (delta < 0 && c[1] < first)
+    };
+    chunks.truncate(1);
+    chunks.retain(ahead);
+  }
+
+  Ok(
+    chunks
+      .into_iter()
+      .map(|[f, l]| [LineNr::from_usize(f), LineNr::from_usize(l)])
+      .collect(),
+  )
+}
+
+/// Move the deferred chunks after `lastline` by `added` lines after an update
+/// of the buffer
+fn shift_deferred(
+  deferred: &mut VecDeque<[LineNr; 2]>,
+  lastline: LineNr,
+  added: isize,
+) {
+  for chunk in deferred.iter_mut().filter(|c| c[0] >= lastline) {
+    chunk[0] += added;
+    chunk[1] += added;
+  }
+}
+
+/// Highlight the cards containing the lines `firstline..=lastline`, and record
+/// them as `viewed`.
+fn highlight_region<N: NeovimApi>(
+  bufdata: &mut BufData,
+  nvim: &mut N,
+  viewed: &mut ViewedRegions,
+  firstline: LineNr,
+  lastline: LineNr,
+) -> Result<(), Error> {
+  let fl = bufdata.first_before(firstline);
+  let mut ll = bufdata.first_after(lastline);
+
+  // highlight_region is end_exclusive, so we need to make sure
+  // we include the last line requested even if it is a keyword line
+  if ll.1 == lastline {
+    ll.0 += 1;
+    ll.1 += 1;
+  }
+  let newrange = bufdata.hl_linerange(fl.1, ll.1);
+
+  if let Some(calls) = bufdata.highlight_region_calls(newrange, fl.1, ll.1) {
+    nvim.call_atomic(calls).context("call_atomic failed")?;
+    viewed.view(fl.1, ll.1, Instant::now());
+  }
+
+  Ok(())
+}
+
+/// The calls to clear all highlights of the buffer, and highlight the
+/// `viewed` regions again, e.g. after the layout of the cards changed
+fn viewed_calls(
+  bufdata: &mut BufData,
+  viewed: &ViewedRegions,
+) -> Option<Vec<Value>> {
+  if !bufdata.highlights_enabled() {
+    return None;
+  }
+
+  let mut calls = bufdata.clear_calls();
+  for [first, last] in viewed.regions() {
+    let range = bufdata.hl_linerange(first, last);
+    calls.extend(
+      bufdata
+        .highlight_region_calls(range, first, last)
+        .unwrap_or_default(),
+    );
+  }
+
+  Some(calls)
+}
+
+/// Clear the highlights of the `viewed` regions that have not been viewed
+/// for too long, see [`HighlightAge`](crate::event::Event::HighlightAge)
+fn expire_highlights<N: NeovimApi>(
+  bufdata: &BufData,
+  nvim: &mut N,
+  viewed: &mut ViewedRegions,
+) -> Result<(), Error> {
+  let expired = viewed.expire(Instant::now());
+
+  if let Some(calls) = bufdata.clear_regions_calls(&expired) {
+    nvim.call_atomic(calls).context("call_atomic failed")?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use std::{sync::mpsc, thread, time::Duration};
+
+  use crate::{
+    bufdata::{highlights::HlConfig, viewed::ViewedRegions, BufData},
+    event::{
+      clamp_to_viewport, clear_highlights, coalesce_lines, expire_highlights,
+      health, highlight_region, highlight_viewport, merge_lines, restore_folds,
+      send_folds, shift_deferred, viewport_ranges, Event,
+    },
+    linenr::LineNr,
+    testing::MockNeovim,
+  };
+
+  fn lines_event(
+    firstline: i64,
+    lastline: i64,
+    linedata: &[&str],
+    changedtick: u64,
+  ) -> Event {
+    Event::LinesEvent {
+      buf: Buffer::new(Value::from(0_usize)),
+      changedtick: Some(changedtick),
+      firstline,
+      lastline,
+      linedata: linedata.iter().map(|s| s.to_string()).collect(),
+      more: false,
+    }
+  }
+
+  fn parts(e: &Event) -> (i64, i64, Vec<String>, Option<u64>) {
+    match *e {
+      Event::LinesEvent {
+        firstline,
+        lastline,
+        ref linedata,
+        changedtick,
+        ..
+      } => (firstline, lastline, linedata.clone(), changedtick),
+      _ => panic!("Not a LinesEvent: {:?}", e),
+    }
+  }
+
+  #[test]
+  fn merge_lines_events() {
+    // Typing on the same line, then opening a new line below it
+    let mut prev = lines_event(3, 4, &["NODE"], 2);
+    assert!(merge_lines(&mut prev, lines_event(3, 4, &["NODE "], 3)).is_none());
+    assert!(merge_lines(&mut prev, lines_event(4, 4, &[""], 4)).is_none());
+    assert_eq!(
+      parts(&prev),
+      (3, 4, vec!["NODE ".to_string(), String::new()], Some(4))
+    );
+
+    // Changes outside the range replaced before, or a whole new buffer
+    let mut prev = lines_event(3, 4, &["NODE"], 2);
+    assert!(merge_lines(&mut prev, lines_event(2, 3, &["SHELL"], 3)).is_some());
+    assert!(merge_lines(&mut prev, lines_event(3, 5, &["SHELL"], 3)).is_some());
+    assert!(merge_lines(&mut prev, lines_event(0, -1, &["SHELL"], 3)).is_some());
+    assert!(merge_lines(&mut prev, Event::RefreshFolds).is_some());
+    assert_eq!(parts(&prev), (3, 4, vec!["NODE".to_string()], Some(2)));
+  }
+
+  #[test]
+  fn coalesce_waiting_events() {
+    let (tx, rx) = mpsc::channel();
+    tx.send(lines_event(0, 1, &["NO"], 3)).unwrap();
+    tx.send(lines_event(0, 1, &["NOD"], 4)).unwrap();
+    tx.send(Event::RefreshFolds).unwrap();
+    tx.send(lines_event(0, 1, &["NODE"], 5)).unwrap();
+
+    let mut pending = None;
+    let first = lines_event(0, 1, &["N"], 2);
+    let merged =
+      coalesce_lines(first, &rx, Duration::from_millis(0), &mut pending);
+
+    assert_eq!(parts(&merged), (0, 1, vec!["NOD".to_string()], Some(4)));
+    assert!(matches!(pending, Some(Event::RefreshFolds)));
+    assert!(matches!(rx.try_recv(), Ok(Event::LinesEvent { .. })));
+  }
+
+  const LINES: [&str; 4] = [
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        2              0.             0.5              0.",
+    "SHELL /     3129       1       1    2967    2971    2970",
+    "SHELL /     3130       1       1    2967    2971    2970",
+  ];
+
+  #[test]
+  fn highlight_region_sends_calls() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let mut nvim = MockNeovim::new();
+
+    highlight_region(
+      &mut bufdata,
+      &mut nvim,
+      &mut ViewedRegions::default(),
+      LineNr::from_usize(2),
+      LineNr::from_usize(2),
+    )
+    .unwrap();
+
+    let calls = nvim.calls_to("nvim_call_atomic");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(
+      calls[0][0],
+      Value::from(vec![
+        Value::from("nvim_buf_clear_highlight"),
+        Value::from(vec![
+          Value::from(0_usize),
+          Value::from(5),
+          Value::from(2),
+          Value::from(3),
+        ]),
+      ])
+    );
+    // The keyword and 6 cells of the SHELL line
+    assert_eq!(calls[0].len(), 1 + 7);
+  }
+
+  #[test]
+  fn split_viewport() {
+    let (visible, chunks) = viewport_ranges(1500, 1550, 4000, 0);
+    assert_eq!(visible, [1400, 1650]);
+    assert_eq!(
+      chunks,
+      vec![
+        [1651, 2650],
+        [400, 1399],
+        [2651, 3650],
+        [0, 399],
+        [3651, 3999]
+      ]
+    );
+
+    let (visible, chunks) = viewport_ranges(0, 50, 120, 0);
+    assert_eq!(visible, [0, 119]);
+    assert!(chunks.is_empty());
+
+    let (visible, chunks) = viewport_ranges(10, 20, 0, 0);
+    assert_eq!(visible, [0, 0]);
+    assert!(chunks.is_empty());
+  }
+
+  #[test]
+  fn clamp_updates_to_viewport() {
+    let line = LineNr::from_usize;
+    assert_eq!(clamp_to_viewport(line(10), line(500), line(0), line(50)), None);
+
+    let (visible, chunks) =
+      clamp_to_viewport(line(1000), line(4999), line(1500), line(1550))
+        .unwrap();
+    assert_eq!(visible, [line(1400), line(1650)]);
+    assert_eq!(
+      chunks,
+      vec![
+        [line(1651), line(2650)],
+        [line(1000), line(1399)],
+        [line(2651), line(3650)],
+        [line(3651), line(4650)],
+        [line(4651), line(4999)]
+      ]
+    );
+
+    // The view is above the changed lines
+    let (visible, chunks) =
+      clamp_to_viewport(line(1000), line(2999), line(10), line(60)).unwrap();
+    assert_eq!(visible, [line(1000), line(1100)]);
+    assert_eq!(chunks[0], [line(1101), line(2100)]);
+  }
+
+  #[test]
+  fn split_viewport_scrolling() {
+    let (visible, chunks) = viewport_ranges(1500, 1550, 4000, 30);
+    assert_eq!(visible, [1400, 1650]);
+    assert_eq!(
+      chunks,
+      vec![
+        [1651, 2650],
+        [2651, 3650],
+        [3651, 3999],
+        [400, 1399],
+        [0, 399]
+      ]
+    );
+
+    let (_, chunks) = viewport_ranges(1500, 1550, 4000, -30);
+    assert_eq!(
+      chunks,
+      vec![
+        [400, 1399],
+        [0, 399],
+        [1651, 2650],
+        [2651, 3650],
+        [3651, 3999]
+      ]
+    );
+  }
+
+  #[test]
+  fn highlight_viewport_defers() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let mut nvim = MockNeovim::new();
+
+    let mut deferred = highlight_viewport(
+      &mut bufdata,
+      &mut nvim,
+      &mut ViewedRegions::default(),
+      LineNr::from_usize(0),
+      LineNr::from_usize(1),
+      0,
+    )
+    .unwrap();
+
+    // The buffer fits into the margin
+    assert!(deferred.is_empty());
+    assert_eq!(
+      nvim.calls_to("nvim_call_atomic")[0].len(),
+      1 + bufdata.highlights.len()
+    );
+
+    deferred.push_back([LineNr::from_usize(2), LineNr::from_usize(3)]);
+    deferred.push_back([LineNr::from_usize(5), LineNr::from_usize(8)]);
+    shift_deferred(&mut deferred, LineNr::from_usize(4), -1);
+    assert_eq!(deferred[0], [LineNr::from_usize(2), LineNr::from_usize(3)]);
+    assert_eq!(deferred[1], [LineNr::from_usize(4), LineNr::from_usize(7)]);
+  }
+
+  #[test]
+  fn expire_viewed_highlights() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let mut nvim = MockNeovim::new();
+    let mut viewed = ViewedRegions::default();
+    viewed.set_max_age(Some(Duration::from_millis(1)));
+
+    let deferred = highlight_viewport(
+      &mut bufdata,
+      &mut nvim,
+      &mut viewed,
+      LineNr::from_usize(2),
+      LineNr::from_usize(3),
+      0,
+    )
+    .unwrap();
+    assert!(deferred.is_empty());
+    assert_eq!(
+      viewed.regions().collect::<Vec<_>>(),
+      vec![[LineNr::from_usize(0), LineNr::from_usize(4)]]
+    );
+
+    thread::sleep(Duration::from_millis(5));
+    expire_highlights(&bufdata, &mut nvim, &mut viewed).unwrap();
+
+    let calls = nvim.calls_to("nvim_call_atomic");
+    assert_eq!(calls.len(), 2);
+    assert_eq!(
+      calls[1][0],
+      Value::from(vec![
+        Value::from("nvim_buf_clear_highlight"),
+        Value::from(vec![
+          Value::from(0_usize),
+          Value::from(5),
+          Value::from(0),
+          Value::from(4),
+        ]),
+      ])
+    );
+    assert_eq!(viewed.regions().count(), 0);
+  }
+
+  #[test]
+  fn restore_folds_sends_folds() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let generated = bufdata.fold_state();
+    let state = |bufdata: &BufData| {
+      let mut state = bufdata.fold_state();
+      state.folds[1].1 = "Shells".to_string();
+      state
+    };
+    let mut nvim = MockNeovim::new();
+
+    // The buffer changed during the restart
+    let changed = state(&bufdata);
+    restore_folds(&mut bufdata, &mut nvim, changed, false, 5000).unwrap();
+    assert_eq!(bufdata.fold_state(), generated);
+    assert_eq!(nvim.calls_to("nvim_execute_lua").len(), 2);
+
+    let mut nvim = MockNeovim::new();
+    let state = state(&bufdata);
+    restore_folds(&mut bufdata, &mut nvim, state, true, 5000).unwrap();
+
+    let calls = nvim.calls_to("nvim_execute_lua");
+    assert_eq!(calls.len(), 2);
+    assert_eq!(
+      calls[0][1],
+      Value::from(vec![Value::from(1), bufdata.fold_calls()[0].clone()])
+    );
+    assert_eq!(
+      bufdata.fold_calls(),
+      Value::from(vec![
+        Value::from(vec![
+          Value::from(vec![
+            Value::from(1),
+            Value::from(2),
+            Value::from(" 2 lines: Node "),
+          ]),
+          Value::from(vec![
+            Value::from(3),
+            Value::from(4),
+            Value::from("Shells"),
+          ]),
+        ]),
+        Value::from(Vec::<Value>::new()),
+        Value::from(Vec::<Value>::new()),
+      ])
+    );
+  }
+
+  #[test]
+  fn health_report() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let mut nvim = MockNeovim::new();
+
+    let report = match health(&bufdata, &mut nvim, true, None, true) {
+      Value::Map(m) => m,
+      v => panic!("Not a map: {:?}", v),
+    };
+    let get = |key: &str| {
+      report
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v.clone())
+        .unwrap()
+    };
+
+    assert_eq!(get("version"), Value::from(env!("CARGO_PKG_VERSION")));
+    // The mock does not know about the API
+    assert_eq!(get("api_level"), Value::Nil);
+    assert_eq!(get("attached"), Value::from(false));
+    assert_eq!(get("index"), Value::from("indexing"));
+    assert!(get("builtin_cards").as_u64().unwrap() > 0);
+    assert!(get("stats").is_map());
+    assert_eq!(get("degraded"), Value::Nil);
+  }
+
+  #[test]
+  fn send_folds_in_chunks() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let mut nvim = MockNeovim::new();
+
+    send_folds(&bufdata, &mut nvim, 1).unwrap();
+
+    let calls = nvim.calls_to("nvim_execute_lua");
+    assert_eq!(calls.len(), 3);
+    assert_eq!(
+      calls[0][0],
+      Value::from("require('nvimpam').add_folds(...)")
+    );
+    assert_eq!(
+      calls[1][1],
+      Value::from(vec![
+        Value::from(1),
+        Value::from(vec![bufdata.fold_calls()[0][1].clone()])
+      ])
+    );
+    assert_eq!(
+      calls[2][0],
+      Value::from("require('nvimpam').commit_folds()")
+    );
+  }
+
+  #[test]
+  fn detach_clears_configured_namespace() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+    let config = HlConfig {
+      namespace: 12,
+      ..HlConfig::default()
+    };
+    let _ = bufdata.set_highlight_config(config).unwrap();
+    let mut nvim = MockNeovim::new();
+
+    clear_highlights(&bufdata, &mut nvim).unwrap();
+
+    let calls = nvim.calls_to("nvim_call_atomic");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0], bufdata.clear_calls().as_slice());
+    let call = calls[0][0].as_array().unwrap();
+    assert_eq!(call[1].as_array().unwrap()[1], Value::from(12));
+  }
+
+  #[test]
+  fn preview_refuses_edits() {
+    let edit = Event::ToggleComment {
+      firstline: 0,
+      lastline: 1,
+    };
+    assert!(edit.edits_buffer());
+    assert!(!Event::RefreshFolds.edits_buffer());
+    assert!(!lines_event(0, 1, &[], 1).edits_buffer());
   }
 }
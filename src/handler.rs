@@ -1,14 +1,28 @@
 //! The handler for the rpc events sent by `neovim_lib`. Note that this is
 //! excuted in another thread, so we use a
 //! [`Sender<Event>`](std::sync::mpsc::Sender) to send the parsed event data to
-//! the main thread.
+//! the main thread. The arguments of the events are parsed by
+//! [`FromValues`](crate::args::FromValues).
+//!
+//! Requests block neovim until the main thread sends back the answer, while
+//! notifications are only passed on. Long-running requests can be sent as a
+//! `Background` notification instead, see [`background`](crate::background).
 use std::sync::mpsc;
 
-use failure::{self, Error};
 use log::{error, info};
-use neovim_lib::{neovim_api::Buffer, Handler, RequestHandler, Value};
+use neovim_lib::{Handler, RequestHandler, Value};
 
-use crate::event::Event;
+use crate::{
+  args::{parse_arg, ArgError, FromValues, StringMap},
+  bufdata::{
+    foldgroup::{FoldGroup, FoldGroups},
+    foldtext::{FoldKind, FoldTexts},
+    highlights::{Encoding, HighlightGroup, HlConfig},
+  },
+  card::{keyword::Keyword, section::SectionRule},
+  event::Event,
+  linenr::LineNr,
+};
 
 /// The handler containing the sending end of a channel. The receiving end is
 /// the main [`event loop`](crate::event::Event::event_loop).
@@ -19,19 +33,37 @@ pub struct NeovimHandler {
 
 impl NeovimHandler {
   /// Parse a nvim_buf_lines_event notification into a
-  /// [`LinesEvent`](::event::Event::LinesEvent) event
-  fn parse_lines_event(
-    &mut self,
-    mut args: Vec<Value>,
-  ) -> Result<Event, Error> {
-    let nea = "Not enough arguments in nvim_buf_lines_event!";
+  /// [`LinesEvent`](::event::Event::LinesEvent) event. The arguments are
+  /// taken from the front, so fields added by newer versions of neovim are
+  /// ignored. The changedtick may be `nil`, and a missing `more` is taken to
+  /// be `false`.
+  fn parse_lines_event(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    if args.len() < 5 {
+      return Err(ArgError::Count {
+        expected: 5,
+        got: args.len(),
+      });
+    }
+
+    let mut args = args.into_iter();
 
-    let more = parse_bool(&last_arg(&mut args, nea)?)?;
-    let linedata = parse_vecstr(last_arg(&mut args, nea)?)?;
-    let lastline = parse_i64(&last_arg(&mut args, nea)?)?;
-    let firstline = parse_i64(&last_arg(&mut args, nea)?)?;
-    let changedtick = parse_u64(&last_arg(&mut args, nea)?)?;
-    let buf = parse_buf(last_arg(&mut args, nea)?);
+    let buf = parse_arg(args.next().unwrap_or(Value::Nil), 0)?;
+    let changedtick = parse_arg(args.next().unwrap_or(Value::Nil), 1)?;
+    let firstline = parse_arg(args.next().unwrap_or(Value::Nil), 2)?;
+    let lastline = parse_arg(args.next().unwrap_or(Value::Nil), 3)?;
+    let linedata = parse_arg(args.next().unwrap_or(Value::Nil), 4)?;
+    let more = match args.next() {
+      Some(m) => parse_arg(m, 5)?,
+      None => false,
+    };
+
+    let unknown = args.count();
+    if unknown > 0 {
+      info!(
+        "Ignoring {} unknown fields of nvim_buf_lines_event",
+        unknown
+      );
+    }
 
     Ok(Event::LinesEvent {
       buf,
@@ -48,12 +80,9 @@ impl NeovimHandler {
   /// event
   fn parse_changedtick_event(
     &mut self,
-    mut args: Vec<Value>,
-  ) -> Result<Event, Error> {
-    let nea = "Not enough arguments in nvim_buf_changedtick_event!";
-
-    let changedtick = parse_u64(&last_arg(&mut args, nea)?)?;
-    let buf = parse_buf(last_arg(&mut args, nea)?);
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (buf, changedtick) = FromValues::from_values(args)?;
     Ok(Event::ChangedTickEvent { buf, changedtick })
   }
 
@@ -61,28 +90,677 @@ impl NeovimHandler {
   /// [`HighlightRegion`](::event::Event::HighlightRegion) event
   fn parse_highlight_region(
     &mut self,
-    mut args: Vec<Value>,
-  ) -> Result<Event, Error> {
-    let nea = "Not enough arguments in HighlightRegion notification!";
-
-    let lastline = parse_i64(&last_arg(&mut args, nea)?)?;
-    let firstline = parse_i64(&last_arg(&mut args, nea)?)?;
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (firstline, lastline) = FromValues::from_values(args)?;
     Ok(Event::HighlightRegion {
       firstline,
       lastline,
     })
   }
 
+  /// Parse a HighlightViewport notification into a
+  /// [`HighlightViewport`](::event::Event::HighlightViewport) event
+  fn parse_highlight_viewport(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (topline, botline) = FromValues::from_values(args)?;
+    Ok(Event::HighlightViewport { topline, botline })
+  }
+
+  /// Parse a Viewport notification into a
+  /// [`ViewportEvent`](::event::Event::ViewportEvent)
+  fn parse_viewport(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (topline, botline) = FromValues::from_values(args)?;
+    Ok(Event::ViewportEvent { topline, botline })
+  }
+
+  /// Parse the `win_viewport` events of a redraw notification, as sent to
+  /// attached UIs, into a [`ViewportEvent`](::event::Event::ViewportEvent).
+  /// Only the last one is used, and all other UI events are ignored. The
+  /// arguments are `[grid, win, topline, botline, curline, curcol]`.
+  fn parse_redraw(&mut self, args: Vec<Value>) -> Option<Event> {
+    let mut event = None;
+
+    for batch in args {
+      let mut batch = match batch {
+        Value::Array(b) => b.into_iter(),
+        _ => continue,
+      };
+      if batch.next().as_ref().and_then(Value::as_str) != Some("win_viewport") {
+        continue;
+      }
+
+      for update in batch {
+        let line = |i: usize| match update {
+          Value::Array(ref a) => a.get(i).and_then(Value::as_i64),
+          _ => None,
+        };
+        if let (Some(topline), Some(botline)) = (line(2), line(3)) {
+          event = Some(Event::ViewportEvent { topline, botline });
+        }
+      }
+    }
+
+    event
+  }
+
+  /// Parse a HighlightAge request into a
+  /// [`HighlightAge`](::event::Event::HighlightAge) event
+  fn parse_highlight_age(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (millis,) = FromValues::from_values(args)?;
+    Ok(Event::HighlightAge { millis })
+  }
+
+  /// Parse a ConvertCard request into a
+  /// [`ConvertCard`](::event::Event::ConvertCard) event
+  fn parse_convert_card(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (line, target) = FromValues::from_values(args)?;
+    Ok(Event::ConvertCard { line, target })
+  }
+
+  /// Parse a Renumber request into a
+  /// [`Renumber`](::event::Event::Renumber) event
+  fn parse_renumber(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (entity, start, step, firstline, lastline) =
+      FromValues::from_values(args)?;
+    Ok(Event::Renumber {
+      entity,
+      start,
+      step,
+      firstline,
+      lastline,
+    })
+  }
+
+  /// Parse a RenameId request into a
+  /// [`RenameId`](::event::Event::RenameId) event
+  fn parse_rename_id(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (entity, old, new) = FromValues::from_values(args)?;
+    Ok(Event::RenameId { entity, old, new })
+  }
+
+  /// Parse an OffsetInclude request into an
+  /// [`OffsetInclude`](::event::Event::OffsetInclude) event
+  fn parse_offset_include(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (path, line, offset, entities) = FromValues::from_values(args)?;
+    Ok(Event::OffsetInclude {
+      path,
+      line,
+      offset,
+      entities,
+    })
+  }
+
+  /// Parse an AlignCells request into an
+  /// [`AlignCells`](::event::Event::AlignCells) event
+  fn parse_align_cells(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (firstline, lastline, modified) = FromValues::from_values(args)?;
+    Ok(Event::AlignCells {
+      firstline,
+      lastline,
+      modified,
+    })
+  }
+
+  /// Parse a CellHint request into a
+  /// [`CellHint`](::event::Event::CellHint) event
+  fn parse_cell_hint(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (line, column) = FromValues::from_values(args)?;
+    Ok(Event::CellHint { line, column })
+  }
+
+  /// Parse a CoalesceWindow request into a
+  /// [`CoalesceWindow`](::event::Event::CoalesceWindow) event
+  fn parse_coalesce_window(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (millis,) = FromValues::from_values(args)?;
+    Ok(Event::CoalesceWindow { millis })
+  }
+
+  /// Parse a FoldChunkSize request into a
+  /// [`FoldChunkSize`](::event::Event::FoldChunkSize) event
+  fn parse_fold_chunk_size(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (size,) = FromValues::from_values(args)?;
+    Ok(Event::FoldChunkSize { size })
+  }
+
+  /// Parse a LintSections request into a
+  /// [`LintSections`](::event::Event::LintSections) event
+  fn parse_lint_sections(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (rules,): (Vec<Vec<String>>,) = FromValues::from_values(args)?;
+    let rules = rules
+      .into_iter()
+      .map(|strs| {
+        SectionRule::from_strs(&strs).ok_or_else(|| ArgError::Invalid {
+          index: 0,
+          message: format!("invalid section rule {:?}", strs),
+        })
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(Event::LintSections { rules })
+  }
+
+  /// Parse a HighlightConfig request into a
+  /// [`HighlightConfig`](::event::Event::HighlightConfig) event. The namespace,
+  /// the column the highlights end at and the encoding of the columns may be
+  /// `nil` to use the default ones. The overrides map the keywords of cards,
+  /// e.g. `NODE`, to the group for their keyword highlights.
+  fn parse_highlight_config(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (namespace, groups, columns, encoding, overrides): (
+      Option<i64>,
+      StringMap,
+      Option<i64>,
+      Option<String>,
+      StringMap,
+    ) = FromValues::from_values(args)?;
+    let mut config = HlConfig::default();
+
+    if let Some(c) = columns {
+      if !(1..=i64::from(u8::MAX)).contains(&c) {
+        return Err(ArgError::Invalid {
+          index: 2,
+          message: format!("columns need to be between 1 and 255, got {}", c),
+        });
+      }
+      #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+      {
+        config.columns = c as u8;
+      }
+    }
+
+    for (kind, group) in groups.0 {
+      let hl =
+        HighlightGroup::from_name(&kind).ok_or_else(|| ArgError::Invalid {
+          index: 1,
+          message: format!("invalid highlight kind '{}'", kind),
+        })?;
+      let _ = config.groups.insert(hl, group);
+    }
+
+    for (name, group) in overrides.0 {
+      let kw = Keyword::from_name(&name).ok_or_else(|| ArgError::Invalid {
+        index: 4,
+        message: format!("invalid keyword '{}'", name),
+      })?;
+      config.keywords.push((kw, group));
+    }
+
+    if let Some(ns) = namespace {
+      config.namespace = ns;
+    }
+
+    if let Some(e) = encoding {
+      config.encoding =
+        Encoding::from_name(&e).ok_or_else(|| ArgError::Invalid {
+          index: 3,
+          message: format!("invalid encoding '{}'", e),
+        })?;
+    }
+
+    Ok(Event::HighlightConfig { config })
+  }
+
+  /// Parse a CheckDuplicates request into a
+  /// [`CheckDuplicates`](::event::Event::CheckDuplicates) event
+  fn parse_check_duplicates(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (enable,) = FromValues::from_values(args)?;
+    Ok(Event::CheckDuplicates { enable })
+  }
+
+  /// Parse an EnableHighlights request into an
+  /// [`EnableHighlights`](::event::Event::EnableHighlights) event
+  fn parse_enable_highlights(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (enable,) = FromValues::from_values(args)?;
+    Ok(Event::EnableHighlights { enable })
+  }
+
+  /// Parse a FoldTexts request into a
+  /// [`FoldTexts`](::event::Event::FoldTexts) event
+  fn parse_foldtexts(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (templates,): (StringMap,) = FromValues::from_values(args)?;
+    let mut texts = FoldTexts::default();
+
+    for (name, template) in templates.0 {
+      let kind =
+        FoldKind::from_name(&name).ok_or_else(|| ArgError::Invalid {
+          index: 0,
+          message: format!("invalid fold kind '{}'", name),
+        })?;
+      let _ = texts.templates.insert(kind, template);
+    }
+
+    Ok(Event::FoldTexts { texts })
+  }
+
+  /// Parse a FoldGroups request into a
+  /// [`FoldGroups`](::event::Event::FoldGroups) event
+  fn parse_fold_groups(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (groups,): (Vec<Vec<String>>,) = FromValues::from_values(args)?;
+    let groups = groups
+      .into_iter()
+      .map(|strs| {
+        FoldGroup::from_strs(&strs).ok_or_else(|| ArgError::Invalid {
+          index: 0,
+          message: format!("invalid fold group {:?}", strs),
+        })
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(Event::FoldGroups {
+      groups: FoldGroups { groups },
+    })
+  }
+
+  /// Parse an ExportFolds, ImportFolds, SaveState or LoadState request into
+  /// the corresponding [`ExportFolds`](::event::Event::ExportFolds),
+  /// [`ImportFolds`](::event::Event::ImportFolds),
+  /// [`SaveState`](::event::Event::SaveState) or
+  /// [`LoadState`](::event::Event::LoadState) event
+  fn parse_fold_state(
+    &mut self,
+    name: &str,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (path,) = FromValues::from_values(args)?;
+    match name {
+      "ExportFolds" => Ok(Event::ExportFolds { path }),
+      "ImportFolds" => Ok(Event::ImportFolds { path }),
+      "SaveState" => Ok(Event::SaveState { path }),
+      _ => Ok(Event::LoadState { path }),
+    }
+  }
+
+  /// Parse a RenameFold request into a
+  /// [`RenameFold`](::event::Event::RenameFold) event
+  fn parse_rename_fold(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (start_line, label) = FromValues::from_values(args)?;
+    Ok(Event::RenameFold { start_line, label })
+  }
+
+  /// Parse an ExtractInclude request into an
+  /// [`ExtractInclude`](::event::Event::ExtractInclude) event
+  fn parse_extract_include(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (firstline, lastline, path) = FromValues::from_values(args)?;
+    Ok(Event::ExtractInclude {
+      firstline,
+      lastline,
+      path,
+    })
+  }
+
+  /// Parse a ToggleComment request into a
+  /// [`ToggleComment`](::event::Event::ToggleComment) event
+  fn parse_toggle_comment(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (firstline, lastline) = FromValues::from_values(args)?;
+    Ok(Event::ToggleComment {
+      firstline,
+      lastline,
+    })
+  }
+
+  /// Parse an IndexIncludes request into an
+  /// [`IndexIncludes`](::event::Event::IndexIncludes) event
+  fn parse_index_includes(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (path,) = FromValues::from_values(args)?;
+    Ok(Event::IndexIncludes { path })
+  }
+
+  /// Parse a FindDefinition request into a
+  /// [`FindDefinition`](::event::Event::FindDefinition) event
+  fn parse_find_definition(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (entity, id) = FromValues::from_values(args)?;
+    Ok(Event::FindDefinition { entity, id })
+  }
+
+  /// Parse an EventTimings request into an
+  /// [`EventTimings`](::event::Event::EventTimings) event
+  fn parse_event_timings(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (count,) = FromValues::from_values(args)?;
+    Ok(Event::EventTimings { count })
+  }
+
+  /// Parse a CardInfo request into a
+  /// [`CardInfo`](::event::Event::CardInfo) event
+  fn parse_card_info(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (line,) = FromValues::from_values(args)?;
+    Ok(Event::CardInfo { line })
+  }
+
+  /// Parse a GesContents request into a
+  /// [`GesContents`](::event::Event::GesContents) event
+  fn parse_ges_contents(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (line,) = FromValues::from_values(args)?;
+    Ok(Event::GesContents { line })
+  }
+
+  /// Parse a FoldLevel request into a
+  /// [`FoldLevel`](::event::Event::FoldLevel) event
+  fn parse_fold_level(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (line,) = FromValues::from_values(args)?;
+    Ok(Event::FoldLevel { line })
+  }
+
+  /// Parse a FoldsForRange request into a
+  /// [`FoldsForRange`](::event::Event::FoldsForRange) event
+  fn parse_folds_for_range(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (firstline, lastline) = FromValues::from_values(args)?;
+    Ok(Event::FoldsForRange {
+      firstline,
+      lastline,
+    })
+  }
+
+  /// Parse a HighlightsForRange request into a
+  /// [`HighlightsForRange`](::event::Event::HighlightsForRange) event
+  fn parse_highlights_for_range(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (firstline, lastline) = FromValues::from_values(args)?;
+    Ok(Event::HighlightsForRange {
+      firstline,
+      lastline,
+    })
+  }
+
+  /// Parse a SemanticTokens request into a
+  /// [`SemanticTokens`](::event::Event::SemanticTokens) event
+  fn parse_semantic_tokens(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (firstline, lastline) = FromValues::from_values(args)?;
+    Ok(Event::SemanticTokens {
+      firstline,
+      lastline,
+    })
+  }
+
+  /// Parse an ExternalHighlights request into an
+  /// [`ExternalHighlights`](::event::Event::ExternalHighlights) event. The
+  /// spans are lists `[line, start, end, group]` with byte columns, like
+  /// the arguments of `nvim_buf_add_highlight`. An end of -1 highlights up to
+  /// the end of the line, and columns after the 255th are cut off.
+  fn parse_external_highlights(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    #![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let (firstline, lastline, spans): (i64, i64, Vec<Vec<Value>>) =
+      FromValues::from_values(args)?;
+    let max = i64::from(u8::MAX);
+
+    let spans = spans
+      .into_iter()
+      .enumerate()
+      .map(|(i, span)| {
+        let invalid = |message: String| ArgError::Invalid {
+          index: 2,
+          message: format!("invalid span {}: {}", i, message),
+        };
+        let (line, start, end, group): (i64, i64, i64, String) =
+          FromValues::from_values(span).map_err(|e| invalid(e.to_string()))?;
+        let line =
+          LineNr::try_from_i64(line).map_err(|e| invalid(e.to_string()))?;
+        let end = if end == -1 { max } else { end.min(max) };
+
+        if start < 0 || start >= end {
+          return Err(invalid(format!("empty columns {}..{}", start, end)));
+        }
+
+        Ok((
+          (line, start as u8, end as u8),
+          HighlightGroup::external(&group),
+        ))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Event::ExternalHighlights {
+      firstline,
+      lastline,
+      spans,
+    })
+  }
+
+  /// Parse a CellRange request into a
+  /// [`CellRange`](::event::Event::CellRange) event
+  fn parse_cell_range(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (line, field) = FromValues::from_values(args)?;
+    Ok(Event::CellRange { line, field })
+  }
+
+  /// Parse a CardObject request into a
+  /// [`CardObject`](::event::Event::CardObject) event
+  fn parse_card_object(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (line, inner) = FromValues::from_values(args)?;
+    Ok(Event::CardObject { line, inner })
+  }
+
+  /// Parse a CellNumber request into a
+  /// [`CellNumber`](::event::Event::CellNumber) event
+  fn parse_cell_number(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (line, column, units) = FromValues::from_values(args)?;
+    Ok(Event::CellNumber {
+      line,
+      column,
+      units,
+    })
+  }
+
+  /// Parse a Quickfix request into a
+  /// [`Quickfix`](::event::Event::Quickfix) event
+  fn parse_quickfix(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (path,) = FromValues::from_values(args)?;
+    Ok(Event::Quickfix { path })
+  }
+
+  /// Parse a DiffDeck request into a
+  /// [`DiffDeck`](::event::Event::DiffDeck) event
+  fn parse_diff_deck(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (path,) = FromValues::from_values(args)?;
+    Ok(Event::DiffDeck { path })
+  }
+
   /// Parse a nvim_buf_detach_event notification into a
   /// [`DetachEvent`](::event::Event::DetachEvent) event
   fn parse_detach_event(
     &mut self,
-    mut args: Vec<Value>,
-  ) -> Result<Event, Error> {
-    let nea = "Not enough arguments in nvim_buf_detach_event!";
-    let buf = parse_buf(last_arg(&mut args, nea)?);
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (buf,) = FromValues::from_values(args)?;
     Ok(Event::DetachEvent { buf })
   }
+
+  /// Parse a request without arguments into `event`
+  fn parse_no_args(
+    &mut self,
+    event: Event,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let () = FromValues::from_values(args)?;
+    Ok(event)
+  }
+
+  /// Parse the arguments of the request `name` into its event. Returns `None`
+  /// if the request is unknown. Those are the events neovim waits for, and
+  /// the ones that can be answered in the background, see
+  /// [`parse_background`](crate::handler::NeovimHandler::parse_background).
+  fn parse_request(
+    &mut self,
+    name: &str,
+    args: Vec<Value>,
+  ) -> Option<Result<Event, ArgError>> {
+    let event = match name {
+      "RefreshFolds" => self.parse_no_args(Event::RefreshFolds, args),
+      "Restart" => self.parse_no_args(Event::Restart, args),
+      "Stats" => self.parse_no_args(Event::Stats, args),
+      "Health" => self.parse_no_args(Event::Health, args),
+      "KeywordSummary" => self.parse_no_args(Event::KeywordSummary, args),
+      "Metadata" => self.parse_no_args(Event::Metadata, args),
+      "ConvertCard" => self.parse_convert_card(args),
+      "Renumber" => self.parse_renumber(args),
+      "RenameId" => self.parse_rename_id(args),
+      "OffsetInclude" => self.parse_offset_include(args),
+      "AlignCells" => self.parse_align_cells(args),
+      "CellHint" => self.parse_cell_hint(args),
+      "HighlightConfig" => self.parse_highlight_config(args),
+      "HighlightAge" => self.parse_highlight_age(args),
+      "CoalesceWindow" => self.parse_coalesce_window(args),
+      "FoldChunkSize" => self.parse_fold_chunk_size(args),
+      "CheckDuplicates" => self.parse_check_duplicates(args),
+      "EnableHighlights" => self.parse_enable_highlights(args),
+      "FoldTexts" => self.parse_foldtexts(args),
+      "FoldGroups" => self.parse_fold_groups(args),
+      "LintSections" => self.parse_lint_sections(args),
+      "ExportFolds" | "ImportFolds" | "SaveState" | "LoadState" => {
+        self.parse_fold_state(name, args)
+      }
+      "RenameFold" => self.parse_rename_fold(args),
+      "ExtractInclude" => self.parse_extract_include(args),
+      "ToggleComment" => self.parse_toggle_comment(args),
+      "IndexIncludes" => self.parse_index_includes(args),
+      "FindDefinition" => self.parse_find_definition(args),
+      "EventTimings" => self.parse_event_timings(args),
+      "CardInfo" => self.parse_card_info(args),
+      "GesContents" => self.parse_ges_contents(args),
+      "FoldLevel" => self.parse_fold_level(args),
+      "FoldsForRange" => self.parse_folds_for_range(args),
+      "HighlightsForRange" => self.parse_highlights_for_range(args),
+      "SemanticTokens" => self.parse_semantic_tokens(args),
+      "ExternalHighlights" => self.parse_external_highlights(args),
+      "CellRange" => self.parse_cell_range(args),
+      "CardObject" => self.parse_card_object(args),
+      "CellNumber" => self.parse_cell_number(args),
+      "DiffDeck" => self.parse_diff_deck(args),
+      "Quickfix" => self.parse_quickfix(args),
+      _ => return None,
+    };
+
+    Some(event)
+  }
+
+  /// Parse a Background notification into a
+  /// [`Background`](::event::Event::Background) event. The arguments are the
+  /// ID to send the answer with, the name of the request, and the list of its
+  /// arguments.
+  fn parse_background(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (id, name, args): (u64, String, Vec<Value>) =
+      FromValues::from_values(args)?;
+    let invalid = |message: String| ArgError::Invalid { index: 1, message };
+
+    let request = match self.parse_request(&name, args) {
+      Some(r) => r.map_err(|e| invalid(format!("{}: {}", name, e)))?,
+      None => return Err(invalid(format!("unknown request '{}'", name))),
+    };
+
+    if !request.runs_in_background() {
+      return Err(invalid(format!("{} can not run in the background", name)));
+    }
+
+    Ok(Event::Background {
+      id,
+      request: Box::new(request),
+    })
+  }
+
+  /// Parse a Cancel notification into a
+  /// [`Cancel`](::event::Event::Cancel) event
+  fn parse_cancel(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (id,) = FromValues::from_values(args)?;
+    Ok(Event::Cancel { id })
+  }
+
+  /// Parse an Inccommand notification into an
+  /// [`Inccommand`](::event::Event::Inccommand) event
+  fn parse_inccommand(&mut self, args: Vec<Value>) -> Result<Event, ArgError> {
+    let (active,) = FromValues::from_values(args)?;
+    Ok(Event::Inccommand { active })
+  }
+
+  /// Parse a RestoreState notification into a
+  /// [`RestoreState`](::event::Event::RestoreState) event
+  fn parse_restore_state(
+    &mut self,
+    args: Vec<Value>,
+  ) -> Result<Event, ArgError> {
+    let (state, changedtick) = FromValues::from_values(args)?;
+    Ok(Event::RestoreState { state, changedtick })
+  }
+
+  /// Send the `event` parsed from the arguments of the request `name` to the
+  /// main thread and return its reply. If the arguments were invalid, the
+  /// error is sent back to neovim.
+  fn request(
+    &mut self,
+    name: &str,
+    event: Result<Event, ArgError>,
+  ) -> Result<Value, Value> {
+    let event = event.map_err(|e| {
+      error!("Could not parse args of {}: '{}'", name, e);
+      e.to_value(name)
+    })?;
+    info!("{:?}", event);
+    self.to_main.send(event).map_err(|e| {
+      Value::from(format!(
+        "Could not send '{}' to main thread: {:?}!",
+        name, e
+      ))
+    })?;
+    self.from_main.recv().map_err(|e| {
+      Value::from(format!(
+        "Error receiving value for request '{}' from main thread: {:?}!",
+        name, e
+      ))
+    })
+  }
 }
 
 impl Handler for NeovimHandler {
@@ -92,7 +770,7 @@ impl Handler for NeovimHandler {
         let event = match self.parse_lines_event(args) {
           Ok(ev) => ev,
           Err(e) => {
-            return error!("Could not parse args of {}: '{:?}'", name, e);
+            return error!("Could not parse args of {}: '{}'", name, e);
           }
         };
         info!("{:?}", event);
@@ -104,7 +782,7 @@ impl Handler for NeovimHandler {
         let event = match self.parse_changedtick_event(args) {
           Ok(ev) => ev,
           Err(e) => {
-            return error!("Could not parse args of {}: '{:?}'", name, e);
+            return error!("Could not parse args of {}: '{}'", name, e);
           }
         };
         info!("{:?}", event);
@@ -119,7 +797,7 @@ impl Handler for NeovimHandler {
         let event = match self.parse_detach_event(args) {
           Ok(ev) => ev,
           Err(e) => {
-            return error!("Could not parse args of {}: '{:?}'", name, e);
+            return error!("Could not parse args of {}: '{}'", name, e);
           }
         };
         info!("{:?}", event);
@@ -131,7 +809,7 @@ impl Handler for NeovimHandler {
         let event = match self.parse_highlight_region(args) {
           Ok(ev) => ev,
           Err(e) => {
-            return error!("Could not parse args of {}: '{:?}'", name, e);
+            return error!("Could not parse args of {}: '{}'", name, e);
           }
         };
         info!("{:?}", event);
@@ -139,6 +817,60 @@ impl Handler for NeovimHandler {
           error!("Could not send 'HighlightRegion' to main thread: '{:?}'", e)
         });
       }
+      "HighlightViewport" => {
+        let event = match self.parse_highlight_viewport(args) {
+          Ok(ev) => ev,
+          Err(e) => {
+            return error!("Could not parse args of {}: '{}'", name, e);
+          }
+        };
+        info!("{:?}", event);
+        self.to_main.send(event).unwrap_or_else(|e| {
+          error!(
+            "Could not send 'HighlightViewport' to main thread: '{:?}'",
+            e
+          )
+        });
+      }
+      "Viewport" => {
+        let event = match self.parse_viewport(args) {
+          Ok(ev) => ev,
+          Err(e) => {
+            return error!("Could not parse args of {}: '{}'", name, e);
+          }
+        };
+        info!("{:?}", event);
+        self.to_main.send(event).unwrap_or_else(|e| {
+          error!("Could not send 'ViewportEvent' to main thread: '{:?}'", e)
+        });
+      }
+      "redraw" => {
+        if let Some(event) = self.parse_redraw(args) {
+          info!("{:?}", event);
+          self.to_main.send(event).unwrap_or_else(|e| {
+            error!("Could not send 'ViewportEvent' to main thread: '{:?}'", e)
+          });
+        }
+      }
+      "Background" | "Cancel" | "Inccommand" | "RestoreState" | "Written" => {
+        let event = match name {
+          "Background" => self.parse_background(args),
+          "Cancel" => self.parse_cancel(args),
+          "Inccommand" => self.parse_inccommand(args),
+          "RestoreState" => self.parse_restore_state(args),
+          _ => self.parse_no_args(Event::Written, args),
+        };
+        let event = match event {
+          Ok(ev) => ev,
+          Err(e) => {
+            return error!("Could not parse args of {}: '{}'", name, e);
+          }
+        };
+        info!("{:?}", event);
+        self.to_main.send(event).unwrap_or_else(|e| {
+          error!("Could not send '{}' to main thread: '{:?}'", name, e)
+        });
+      }
       "quit" => {
         info!("{:?}", Event::Quit);
         self.to_main.send(Event::Quit).unwrap_or_else(|e| {
@@ -156,87 +888,288 @@ impl RequestHandler for NeovimHandler {
   fn handle_request(
     &mut self,
     name: String,
-    _args: Vec<Value>,
+    args: Vec<Value>,
   ) -> Result<Value, Value> {
-    match name.as_str() {
-      "RefreshFolds" => {
-        self.to_main.send(Event::RefreshFolds).map_err(|e| {
-          Value::from(format!(
-            "Could not send 'RefreshFolds' to main thread: {:?}!",
-            e
-          ))
-        })?;
-        self.from_main.recv().map_err(|e| {
-          Value::from(format!(
-            "Error receiving value for request '{}' from main thread: {:?}!",
-            name, e
-          ))
-        })
+    let event = match self.parse_request(&name, args) {
+      Some(e) => e,
+      None => return Err(Value::from(format!("Unknown Request: '{}'!", name))),
+    };
+
+    self.request(&name, event)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc;
+
+  use neovim_lib::{RequestHandler, Value};
+
+  use crate::{args::ArgError, event::Event, handler::NeovimHandler};
+
+  fn handler() -> NeovimHandler {
+    let (to_main, _) = mpsc::channel();
+    let (_, from_main) = mpsc::channel();
+
+    NeovimHandler { to_main, from_main }
+  }
+
+  fn lines_args(changedtick: Value) -> Vec<Value> {
+    vec![
+      Value::from(1_usize),
+      changedtick,
+      Value::from(2),
+      Value::from(3),
+      Value::from(vec![Value::from("NODE  /        1")]),
+      Value::from(false),
+    ]
+  }
+
+  #[test]
+  fn parse_lines_events() {
+    let mut h = handler();
+
+    let mut args = lines_args(Value::from(5));
+    args.push(Value::from(vec![(Value::from("bytes"), Value::from(16))]));
+    match h.parse_lines_event(args).unwrap() {
+      Event::LinesEvent {
+        changedtick,
+        firstline,
+        lastline,
+        linedata,
+        more,
+        ..
+      } => {
+        assert_eq!(changedtick, Some(5));
+        assert_eq!((firstline, lastline, more), (2, 3, false));
+        assert_eq!(linedata, vec!["NODE  /        1".to_string()]);
       }
-      _ => Err(Value::from(format!("Unknown Request: '{}'!", name))),
+      e => panic!("Not a LinesEvent: {:?}", e),
     }
+
+    let mut args = lines_args(Value::Nil);
+    let _ = args.pop();
+    match h.parse_lines_event(args).unwrap() {
+      Event::LinesEvent {
+        changedtick, more, ..
+      } => assert_eq!((changedtick, more), (None, false)),
+      e => panic!("Not a LinesEvent: {:?}", e),
+    }
+
+    let mut args = lines_args(Value::from(5));
+    args.truncate(4);
+    assert!(h.parse_lines_event(args).is_err());
   }
-}
 
-/// Helper function to get the last argument of a `Vec<Value>` or return an
-/// error message
-fn last_arg(v: &mut Vec<Value>, errmsg: &'static str) -> Result<Value, Error> {
-  v.pop().ok_or_else(|| failure::err_msg(errmsg))
-}
+  #[test]
+  fn parse_restore_states() {
+    let mut h = handler();
 
-/// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a u64
-fn parse_u64(value: &Value) -> Result<u64, Error> {
-  value.as_u64().ok_or_else(|| {
-    failure::err_msg(format!("Cannot parse '{:?}' as u64", value))
-  })
-}
-///
-/// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a i64
-fn parse_i64(value: &Value) -> Result<i64, Error> {
-  value.as_i64().ok_or_else(|| {
-    failure::err_msg(format!("Cannot parse '{:?}' as i64", value))
-  })
-}
+    let args = vec![Value::from("nvimpam foldstate 1\n"), Value::from(12)];
+    match h.parse_restore_state(args).unwrap() {
+      Event::RestoreState { state, changedtick } => {
+        assert_eq!(state, "nvimpam foldstate 1\n");
+        assert_eq!(changedtick, 12);
+      }
+      e => panic!("Not a RestoreState: {:?}", e),
+    }
 
-/// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a bool
-fn parse_bool(value: &Value) -> Result<bool, Error> {
-  value.as_bool().ok_or_else(|| {
-    failure::err_msg(format!("Cannot parse '{:?}' as bool", value))
-  })
-}
+    assert!(h.parse_restore_state(vec![Value::from("state")]).is_err());
+  }
 
-/// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a `Vec<String>`. Note
-/// that this method takes ownership of the value so it does not need to copy
-/// out the contained strings
-fn parse_vecstr(value: Value) -> Result<Vec<String>, Error> {
-  let mut res: Vec<String>;
-  if let Value::Array(v) = value {
-    res = Vec::with_capacity(v.len());
-
-    for val in v {
-      if let Value::String(s) = val {
-        match s.into_str() {
-          Some(string) => res.push(string),
-          None => return Err(failure::err_msg("Non-utf8 values in array")),
-        }
-      } else {
-        return Err(failure::err_msg("Non-String value in array"));
+  #[test]
+  fn parse_redraw_viewport() {
+    let mut h = handler();
+    let viewport = |top: i64, bot: i64| {
+      Value::from(vec![
+        Value::from(2),
+        Value::from(1000),
+        Value::from(top),
+        Value::from(bot),
+        Value::from(top),
+        Value::from(0),
+      ])
+    };
+
+    let args = vec![
+      Value::from(vec![Value::from("win_viewport"), viewport(0, 40)]),
+      Value::from(vec![
+        Value::from("grid_scroll"),
+        Value::from(vec![Value::from(2)]),
+      ]),
+      Value::from(vec![
+        Value::from("win_viewport"),
+        viewport(10, 50),
+        viewport(20, 60),
+      ]),
+    ];
+    match h.parse_redraw(args) {
+      Some(Event::ViewportEvent { topline, botline }) => {
+        assert_eq!((topline, botline), (20, 60))
       }
+      e => panic!("Not a ViewportEvent: {:?}", e),
     }
-  } else {
-    return Err(failure::err_msg(format!(
-      "Cannot parse '{:?}' as array",
-      value
-    )));
+
+    let args = vec![Value::from(vec![Value::from("flush")])];
+    assert!(h.parse_redraw(args).is_none());
   }
 
-  Ok(res)
-}
+  #[test]
+  fn parse_external_highlights() {
+    let mut h = handler();
+    let span = |line: i64, start: i64, end: i64| {
+      Value::from(vec![
+        Value::from(line),
+        Value::from(start),
+        Value::from(end),
+        Value::from("LogError"),
+      ])
+    };
+
+    let args = vec![
+      Value::from(0),
+      Value::from(10),
+      Value::from(vec![span(2, 8, -1), span(3, 0, 300)]),
+    ];
+    match h.parse_external_highlights(args).unwrap() {
+      Event::ExternalHighlights { spans, .. } => {
+        let spans: Vec<_> = spans
+          .into_iter()
+          .map(|((l, s, e), hl)| (usize::from(l), s, e, <&str>::from(hl)))
+          .collect();
+        assert_eq!(
+          spans,
+          vec![(2, 8, 255, "LogError"), (3, 0, 255, "LogError")]
+        );
+      }
+      e => panic!("Not an ExternalHighlights event: {:?}", e),
+    }
+
+    let args = vec![
+      Value::from(0),
+      Value::from(10),
+      Value::from(vec![span(2, 8, 8)]),
+    ];
+    match h.parse_external_highlights(args).unwrap_err() {
+      ArgError::Invalid { index, .. } => assert_eq!(index, 2),
+      e => panic!("Not an invalid argument: {:?}", e),
+    }
+  }
+
+  #[test]
+  fn reject_invalid_requests() {
+    let mut h = handler();
+
+    let err = h
+      .handle_request("CellHint".to_string(), vec![Value::from(1)])
+      .unwrap_err();
+    assert_eq!(
+      err,
+      Value::from(vec![
+        Value::from("count"),
+        Value::from(
+          "Invalid arguments for CellHint: expected 2 arguments, got 1"
+        )
+      ])
+    );
+
+    let args = vec![Value::from(1), Value::from("3")];
+    match h.handle_request("CellHint".to_string(), args).unwrap_err() {
+      Value::Array(v) => assert_eq!(v[0], Value::from("type")),
+      v => panic!("Not an array: {:?}", v),
+    }
+
+    let args =
+      vec![Value::from(vec![(Value::from("bogus"), Value::from("x"))])];
+    match h.handle_request("FoldTexts".to_string(), args).unwrap_err() {
+      Value::Array(v) => assert_eq!(v[0], Value::from("invalid")),
+      v => panic!("Not an array: {:?}", v),
+    }
 
-/// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a
-/// [`neovim_lib::Buffer`](neovim_lib::neovim_api::Buffer). This cannot fail,
-/// but if the Value was not obtained from the rpc api, this will probably not
-/// be a valid buffer to send commands to.
-fn parse_buf(value: Value) -> Buffer {
-  Buffer::new(value)
+    let args = vec![Value::from(vec![Value::from(vec![
+      Value::from("Shells"),
+      Value::from("SHELLS"),
+    ])])];
+    let err = h.handle_request("FoldGroups".to_string(), args);
+    match err.unwrap_err() {
+      Value::Array(v) => assert_eq!(v[0], Value::from("invalid")),
+      v => panic!("Not an array: {:?}", v),
+    }
+
+    let args = vec![
+      Value::Nil,
+      Value::Array(vec![]),
+      Value::from(300),
+      Value::Nil,
+      Value::Array(vec![]),
+    ];
+    let err = h.handle_request("HighlightConfig".to_string(), args);
+    match err.unwrap_err() {
+      Value::Array(v) => assert_eq!(v[0], Value::from("invalid")),
+      v => panic!("Not an array: {:?}", v),
+    }
+
+    let args = vec![
+      Value::Nil,
+      Value::Array(vec![]),
+      Value::Nil,
+      Value::from("latin1"),
+      Value::Array(vec![]),
+    ];
+    let err = h.handle_request("HighlightConfig".to_string(), args);
+    match err.unwrap_err() {
+      Value::Array(v) => assert_eq!(v[0], Value::from("invalid")),
+      v => panic!("Not an array: {:?}", v),
+    }
+
+    let args = vec![
+      Value::Nil,
+      Value::Array(vec![]),
+      Value::Nil,
+      Value::Nil,
+      Value::from(vec![(Value::from("NODEX"), Value::from("MyNodeHl"))]),
+    ];
+    let err = h.handle_request("HighlightConfig".to_string(), args);
+    match err.unwrap_err() {
+      Value::Array(v) => assert_eq!(v[0], Value::from("invalid")),
+      v => panic!("Not an array: {:?}", v),
+    }
+
+    assert!(h
+      .handle_request("Stats".to_string(), vec![Value::Nil])
+      .is_err());
+  }
+
+  #[test]
+  fn parse_background_requests() {
+    let mut h = handler();
+    let args = |name: &str, args: Vec<Value>| {
+      vec![Value::from(3), Value::from(name), Value::from(args)]
+    };
+
+    match h.parse_background(args("DiffDeck", vec![Value::from("a.pc")])) {
+      Ok(Event::Background { id, request }) => {
+        assert_eq!(id, 3);
+        assert_eq!(request.name(), "DiffDeck");
+      }
+      e => panic!("Not a Background event: {:?}", e),
+    }
+
+    let err = h.parse_background(args("Stats", vec![])).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "argument 1: Stats can not run in the background"
+    );
+    let err = h.parse_background(args("DiffDeck", vec![])).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "argument 1: DiffDeck: expected 1 arguments, got 0"
+    );
+    assert!(h.parse_background(args("Bogus", vec![])).is_err());
+
+    match h.parse_cancel(vec![Value::from(3)]) {
+      Ok(Event::Cancel { id }) => assert_eq!(id, 3),
+      e => panic!("Not a Cancel event: {:?}", e),
+    }
+  }
 }
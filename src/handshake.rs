@@ -0,0 +1,213 @@
+//! The handshake between nvimpam and its Lua glue, so a binary and Lua
+//! runtime files that are out of sync are reported right after starting
+//! instead of failing on the first request or callback that changed.
+//!
+//! On connecting, nvimpam calls `require('nvimpam').handshake` with its
+//! [`hello`](crate::handshake::hello): its version, its
+//! [`API_LEVEL`](crate::handshake::API_LEVEL), the
+//! [`LUA_API_LEVEL`](crate::handshake::LUA_API_LEVEL) it needs and the RPC
+//! requests it handles. The Lua side checks the API level of the binary and
+//! returns its own level along with the
+//! [`LUA_CALLBACKS`](crate::handshake::LUA_CALLBACKS) it provides, see
+//! [`Features`](crate::handshake::Features).
+use failure::{self, Error, ResultExt};
+use neovim_lib::{NeovimApi, Value};
+
+/// The level of the RPC API of nvimpam, i.e. its requests and their
+/// arguments. Increased whenever a request is added or changed.
+pub const API_LEVEL: u64 = 7;
+
+/// The lowest level of the Lua glue that provides everything nvimpam calls
+pub const LUA_API_LEVEL: u64 = 1;
+
+/// The functions of the Lua module `nvimpam` that nvimpam calls
+pub const LUA_CALLBACKS: &[&str] = &[
+  "add_folds",
+  "clear_folds",
+  "commit_folds",
+  "finish_request",
+  "include_changed",
+  "nvimpam_err",
+  "on_panic",
+  "update_duplicates",
+  "update_foldtexts",
+];
+
+/// What the Lua glue reported in its reply to the handshake
+#[derive(Debug, PartialEq)]
+pub struct Features {
+  /// The level of the Lua glue
+  pub lua_api_level: u64,
+  /// The functions of [`LUA_CALLBACKS`](crate::handshake::LUA_CALLBACKS)
+  /// the Lua glue provides
+  pub callbacks: Vec<String>,
+}
+
+impl Features {
+  /// Read the reply of the Lua glue, a map with the keys `lua_api_level`
+  /// and `callbacks`
+  pub fn from_reply(reply: &Value) -> Result<Self, Error> {
+    let map = match reply {
+      Value::Map(ref m) => m,
+      Value::Nil => {
+        return Err(failure::err_msg(
+          "The Lua runtime files did not reply to the handshake!",
+        ))
+      }
+      v => {
+        return Err(failure::err_msg(format!(
+          "Invalid reply to the handshake: {}",
+          v
+        )))
+      }
+    };
+    let get = |key: &str| {
+      map
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v)
+    };
+
+    let lua_api_level = get("lua_api_level")
+      .and_then(Value::as_u64)
+      .ok_or_else(|| failure::err_msg("The handshake misses lua_api_level!"))?;
+    let callbacks =
+      get("callbacks")
+        .and_then(Value::as_array)
+        .map_or(vec![], |c| {
+          c.iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+        });
+
+    Ok(Features {
+      lua_api_level,
+      callbacks,
+    })
+  }
+
+  /// Check that the Lua glue provides everything nvimpam needs
+  pub fn check(&self) -> Result<(), Error> {
+    if self.lua_api_level < LUA_API_LEVEL {
+      return Err(failure::err_msg(format!(
+        "The Lua runtime files have API level {}, but the nvimpam binary \
+         needs {}, please update them!",
+        self.lua_api_level, LUA_API_LEVEL
+      )));
+    }
+
+    let missing: Vec<_> = LUA_CALLBACKS
+      .iter()
+      .filter(|c| !self.callbacks.iter().any(|f| f == *c))
+      .cloned()
+      .collect();
+    if !missing.is_empty() {
+      return Err(failure::err_msg(format!(
+        "The Lua runtime files miss the functions {}, please update them!",
+        missing.join(", ")
+      )));
+    }
+
+    Ok(())
+  }
+}
+
+/// The message sent to the Lua glue on connecting, a map of the `version`
+/// of nvimpam, its `api_level`, the `lua_api_level` it needs, the names of
+/// the `requests` it handles and the `callbacks` it calls.
+pub fn hello(requests: &[String]) -> Value {
+  let requests: Vec<_> =
+    requests.iter().map(|r| Value::from(r.as_str())).collect();
+  let callbacks: Vec<_> =
+    LUA_CALLBACKS.iter().map(|&c| Value::from(c)).collect();
+
+  Value::from(vec![
+    (
+      Value::from("version"),
+      Value::from(env!("CARGO_PKG_VERSION")),
+    ),
+    (Value::from("api_level"), Value::from(API_LEVEL)),
+    (Value::from("lua_api_level"), Value::from(LUA_API_LEVEL)),
+    (Value::from("requests"), Value::from(requests)),
+    (Value::from("callbacks"), Value::from(callbacks)),
+  ])
+}
+
+/// Send the [`hello`](crate::handshake::hello) to the Lua glue, and check
+/// its reply
+pub fn handshake<N: NeovimApi>(
+  nvim: &mut N,
+  requests: &[String],
+) -> Result<Features, Error> {
+  let reply = nvim
+    .execute_lua(
+      "return require('nvimpam').handshake(...)",
+      vec![hello(requests)],
+    )
+    .context("The Lua runtime files don't know the handshake")?;
+
+  let features = Features::from_reply(&reply)?;
+  features.check()?;
+
+  Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::Value;
+
+  use crate::{
+    handshake::{handshake, hello, Features, LUA_API_LEVEL, LUA_CALLBACKS},
+    testing::MockNeovim,
+  };
+
+  fn reply(level: u64, callbacks: &[&str]) -> Value {
+    Value::from(vec![
+      (Value::from("lua_api_level"), Value::from(level)),
+      (
+        Value::from("callbacks"),
+        Value::from(
+          callbacks
+            .iter()
+            .map(|&c| Value::from(c))
+            .collect::<Vec<_>>(),
+        ),
+      ),
+    ])
+  }
+
+  #[test]
+  fn handshake_replies() {
+    let features = Features::from_reply(&reply(1, LUA_CALLBACKS)).unwrap();
+    assert_eq!(features.lua_api_level, 1);
+    assert!(features.check().is_ok());
+
+    let old = Features::from_reply(&reply(LUA_API_LEVEL - 1, LUA_CALLBACKS));
+    assert!(old.unwrap().check().is_err());
+
+    let missing = Features::from_reply(&reply(1, &LUA_CALLBACKS[1..]))
+      .unwrap()
+      .check()
+      .unwrap_err();
+    assert!(missing
+      .to_string()
+      .contains("miss the functions add_folds,"));
+
+    assert!(Features::from_reply(&Value::Nil).is_err());
+    assert!(Features::from_reply(&Value::from(1)).is_err());
+  }
+
+  #[test]
+  fn handshake_sends_hello() {
+    let mut nvim = MockNeovim::new();
+    let requests = vec!["CardInfo".to_string()];
+
+    // The mock doesn't reply
+    assert!(handshake(&mut nvim, &requests).is_err());
+
+    let calls = nvim.calls_to("nvim_execute_lua");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0][1], Value::from(vec![hello(&requests)]));
+  }
+}
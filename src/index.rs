@@ -0,0 +1,252 @@
+//! An index of the entities defined in the include files of a deck, so their
+//! definitions can be found even if the files are not opened. The include
+//! files are given by `INCLU` lines, and resolved relative to the directory of
+//! the file including them. Includes of included files are followed as well.
+//!
+//! Building the index reads all the files, so it's done in a background
+//! thread by [`spawn`](crate::index::spawn).
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  path::{Path, PathBuf},
+  sync::mpsc,
+  thread,
+};
+
+use log::warn;
+
+use crate::{
+  card::xref::{self, Entity},
+  linenr::LineNr,
+  lines::Lines,
+};
+
+/// The place where an entity is defined
+#[derive(Debug, PartialEq, Clone)]
+pub struct Location {
+  pub file: PathBuf,
+  pub line: LineNr,
+}
+
+/// The entities defined in the include files of a deck. If an ID is defined
+/// more than once, the first definition is kept.
+#[derive(Debug, Default)]
+pub struct Index {
+  definitions: HashMap<(Entity, i64), Location>,
+  files: Vec<PathBuf>,
+  errors: Vec<String>,
+}
+
+/// Returns the file name of an `INCLU` line, or `None` if `text` is not one
+pub fn include_path(text: &[u8]) -> Option<String> {
+  if !text.starts_with(b"INCLU") || text.get(6) != Some(&b'/') {
+    return None;
+  }
+
+  let path = String::from_utf8_lossy(&text[7..]).trim().to_string();
+
+  if path.is_empty() {
+    None
+  } else {
+    Some(path)
+  }
+}
+
+impl Index {
+  /// Build the index from the files `includes`, which are included by a deck
+  /// in the directory `dir`
+  pub fn build(dir: &Path, includes: &[String]) -> Self {
+    let mut index = Index::default();
+    let mut seen = HashSet::new();
+
+    for include in includes {
+      index.add_file(&dir.join(include), &mut seen);
+    }
+
+    index
+  }
+
+  /// Add the definitions of the file at `path`, followed by the ones of the
+  /// files it includes. Files in `seen` are skipped, so an include cycle
+  /// does not recurse endlessly.
+  fn add_file(&mut self, path: &Path, seen: &mut HashSet<PathBuf>) {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    if !seen.insert(canonical) {
+      return;
+    }
+
+    let content = match fs::read(path) {
+      Ok(c) => c,
+      Err(e) => {
+        self
+          .errors
+          .push(format!("Could not read '{}': {}", path.display(), e));
+        return;
+      }
+    };
+
+    let mut lines = Lines::new();
+    lines.parse_slice(&content);
+
+    let kwlines: Vec<_> = lines
+      .iter()
+      .filter_map(|l| l.keyword.map(|k| (l.number, l.text.as_ref(), k)))
+      .collect();
+
+    for entity in &[Entity::Node, Entity::Element, Entity::Part] {
+      for (id, line) in xref::definitions(&kwlines, *entity) {
+        let _ = self.definitions.entry((*entity, id)).or_insert(Location {
+          file: path.to_owned(),
+          line,
+        });
+      }
+    }
+
+    self.files.push(path.to_owned());
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let includes: Vec<String> = lines
+      .iter()
+      .filter_map(|l| include_path(l.text.as_ref()))
+      .collect();
+
+    for include in includes {
+      self.add_file(&dir.join(include), seen);
+    }
+  }
+
+  /// Create an index from its parts, e.g. when it's restored from a
+  /// [`Session`](crate::session::Session)
+  pub fn from_parts(
+    definitions: Vec<(Entity, i64, Location)>,
+    files: Vec<PathBuf>,
+    errors: Vec<String>,
+  ) -> Self {
+    Index {
+      definitions: definitions
+        .into_iter()
+        .map(|(e, id, l)| ((e, id), l))
+        .collect(),
+      files,
+      errors,
+    }
+  }
+
+  /// All definitions of the index, sorted by entity and ID
+  pub fn definitions(&self) -> Vec<(Entity, i64, &Location)> {
+    let mut definitions: Vec<_> = self
+      .definitions
+      .iter()
+      .map(|((e, id), l)| (*e, *id, l))
+      .collect();
+    definitions.sort_by_key(|(e, id, _)| (*e, *id));
+
+    definitions
+  }
+
+  /// The location of the definition of the ID `id` of `entity`
+  pub fn find(&self, entity: Entity, id: i64) -> Option<&Location> {
+    self.definitions.get(&(entity, id))
+  }
+
+  /// The IDs of `entity` defined in the index, in no particular order
+  pub fn ids(&self, entity: Entity) -> impl Iterator<Item = i64> + '_ {
+    self
+      .definitions
+      .keys()
+      .filter(move |(e, _)| *e == entity)
+      .map(|(_, id)| *id)
+  }
+
+  /// The files that were indexed, in the order they were read
+  pub fn files(&self) -> &[PathBuf] {
+    &self.files
+  }
+
+  /// The errors that occured while reading the files
+  pub fn errors(&self) -> &[String] {
+    &self.errors
+  }
+}
+
+/// Build the [`Index`](crate::index::Index) of the files `includes` in the
+/// directory `dir` in a background thread. The returned receiver gets it
+/// when it's done.
+pub fn spawn(dir: PathBuf, includes: Vec<String>) -> mpsc::Receiver<Index> {
+  let (sender, receiver) = mpsc::channel();
+
+  let _ = thread::spawn(move || {
+    let index = Index::build(&dir, &includes);
+
+    for e in index.errors() {
+      warn!("Indexing include files: {}", e);
+    }
+
+    // The receiver is gone if the index was rebuilt in the meantime
+    let _ = sender.send(index);
+  });
+
+  receiver
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs, process};
+
+  use crate::{
+    card::xref::Entity,
+    index::{include_path, Index},
+    linenr::LineNr,
+  };
+
+  #[test]
+  fn parse_include_lines() {
+    assert_eq!(
+      include_path(b"INCLU / nodes.inc  "),
+      Some("nodes.inc".to_string())
+    );
+    assert_eq!(include_path(b"INCLU /   "), None);
+    assert_eq!(include_path(b"NODE  /        1"), None);
+  }
+
+  #[test]
+  fn index_includes() {
+    let dir = env::temp_dir().join(format!("nvimpam-index-{}", process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+
+    fs::write(
+      dir.join("main.inc"),
+      "$ Nodes\n\
+       NODE  /        1              0.             0.5              0.\n\
+       INCLU / sub/shells.inc\n\
+       INCLU / missing.inc\n",
+    )
+    .unwrap();
+    fs::write(
+      dir.join("sub/shells.inc"),
+      "NODE  /        1              1.             0.5              0.\n\
+       NODE  /        2              0.             0.5              0.\n\
+       SHELL /     3129       1       1       2       2       1\n\
+       INCLU / ../main.inc\n",
+    )
+    .unwrap();
+
+    let index = Index::build(&dir, &["main.inc".to_string()]);
+    let _ = fs::remove_dir_all(&dir);
+
+    assert_eq!(index.files().len(), 2);
+    assert_eq!(index.errors().len(), 1);
+
+    let node1 = index.find(Entity::Node, 1).unwrap();
+    assert_eq!(node1.file, dir.join("main.inc"));
+    assert_eq!(node1.line, LineNr::from_usize(1));
+
+    let node2 = index.find(Entity::Node, 2).unwrap();
+    assert_eq!(node2.file, dir.join("sub/shells.inc"));
+    assert_eq!(node2.line, LineNr::from_usize(1));
+
+    let shell = index.find(Entity::Element, 3129).unwrap();
+    assert_eq!(shell.line, LineNr::from_usize(2));
+    assert_eq!(index.find(Entity::Part, 1), None);
+  }
+}
@@ -4,11 +4,25 @@
 
 #[macro_use]
 pub mod carddata;
+pub mod args;
+pub mod background;
 pub mod bufdata;
 pub mod card;
+pub mod diagnostics;
+pub mod diff;
 pub mod event;
 pub mod handler;
+pub mod handshake;
+pub mod index;
 pub mod linenr;
 pub mod lines;
 pub mod linesiter;
+pub mod report;
+pub mod session;
 pub mod skipresult;
+#[cfg(test)]
+pub mod testing;
+pub mod toml;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod worker;
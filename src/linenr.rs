@@ -1,10 +1,17 @@
-//! The struct for linenumbers.
+//! The struct for linenumbers. Neovim sends line numbers as `i64`, which can
+//! be converted by [`try_from_i64`](crate::linenr::LineNr::try_from_i64) or
+//! [`try_range`](crate::linenr::LineNr::try_range). Adding an `isize` to a
+//! `LineNr` is checked in debug mode, use
+//! [`checked_add`](crate::linenr::LineNr::checked_add) or
+//! [`saturating_add`](crate::linenr::LineNr::saturating_add) if the result
+//! might be out of range. The difference of two `LineNr`s is an `isize`.
 use std::{
-  fmt, i32, isize,
+  cmp, fmt, i32, isize,
   ops::{Add, AddAssign, Sub},
   u32,
 };
 
+use failure::{self, Error};
 use neovim_lib::Value;
 
 /// Wraps a `u32`, but we actually make sure on construction to stay in
@@ -15,6 +22,9 @@ use neovim_lib::Value;
 pub struct LineNr(u32);
 
 impl LineNr {
+  /// The largest `LineNr`
+  pub const MAX: LineNr = LineNr(i32::MAX as u32);
+
   pub fn from_i64(num: i64) -> Self {
     debug_assert!(num >= 0);
     debug_assert!(num <= i64::from(i32::MAX));
@@ -33,12 +43,84 @@ impl LineNr {
     LineNr(num as u32)
   }
 
+  /// Convert a line number sent by neovim. Returns an error if it is
+  /// negative, which neovim uses e.g. to denote the end of the buffer, or
+  /// larger than [`LineNr::MAX`](crate::linenr::LineNr::MAX).
+  pub fn try_from_i64(num: i64) -> Result<Self, Error> {
+    if num < 0 {
+      Err(failure::err_msg(format!("Negative line number {}!", num)))
+    } else if num > i64::from(i32::MAX) {
+      Err(failure::err_msg(format!("Line number {} too large!", num)))
+    } else {
+      Ok(Self::from_i64(num))
+    }
+  }
+
+  /// Convert the range `firstline..lastline` sent by neovim, see
+  /// [`try_from_i64`](crate::linenr::LineNr::try_from_i64). Also returns an
+  /// error if `lastline` is before `firstline`.
+  pub fn try_range(firstline: i64, lastline: i64) -> Result<[Self; 2], Error> {
+    let first = Self::try_from_i64(firstline)?;
+    let last = Self::try_from_i64(lastline)?;
+
+    if last < first {
+      return Err(failure::err_msg(format!(
+        "Invalid line range {}..{}!",
+        firstline, lastline
+      )));
+    }
+
+    Ok([first, last])
+  }
+
+  /// Add `other` to the `LineNr`, returning `None` if the result is negative
+  /// or larger than [`LineNr::MAX`](crate::linenr::LineNr::MAX)
+  pub fn checked_add(self, other: isize) -> Option<Self> {
+    // Cast is lossless, see the comment for [`LineNr`](crate::linenr::LineNr)
+    let res = (self.0 as isize).checked_add(other)?;
+
+    if res >= 0 && res <= i32::MAX as isize {
+      Some(Self(res as u32))
+    } else {
+      None
+    }
+  }
+
+  /// Add `other` to the `LineNr`, clamping the result to
+  /// `0..=LineNr::MAX`
+  pub fn saturating_add(self, other: isize) -> Self {
+    // Cast is lossless, see the comment for [`LineNr`](crate::linenr::LineNr)
+    let res = (self.0 as isize).saturating_add(other);
+    let res = cmp::min(cmp::max(res, 0), i32::MAX as isize);
+
+    Self(res as u32)
+  }
+
+  /// The number of lines in the end-exclusive range `self..end`, which is 0
+  /// if `end` is not after `self`
+  pub fn lines_to(self, end: LineNr) -> usize {
+    end.0.saturating_sub(self.0) as usize
+  }
+
   pub fn prev(self) -> Self {
     debug_assert!(self.0 >= 1, "LineNr 0 has no previous LineNr!");
     LineNr(self.0 - 1)
   }
 }
 
+impl From<LineNr> for i64 {
+  fn from(l: LineNr) -> Self {
+    i64::from(l.0)
+  }
+}
+
+impl From<LineNr> for isize {
+  fn from(l: LineNr) -> Self {
+    // Cast is lossless, see the comment for [`LineNr`](crate::linenr::LineNr)
+    l.0 as isize
+  }
+}
+
 impl From<LineNr> for usize {
   fn from(l: LineNr) -> Self {
     l.0 as usize
@@ -65,10 +147,9 @@ impl Add<isize> for LineNr {
   type Output = LineNr;
 
   fn add(self, other: isize) -> Self::Output {
-    // Cast is lossless, see the comment for [`LineNr`](::linenr::LineNr)
-    let res = self.0 as isize + other;
-    debug_assert!(res >= 0 && res <= i32::MAX as isize);
-    Self(res as u32)
+    let res = self.checked_add(other);
+    debug_assert!(res.is_some(), "LineNr {} + {} out of range!", self, other);
+    res.unwrap_or_else(|| self.saturating_add(other))
   }
 }
 
@@ -76,17 +157,13 @@ impl Sub for LineNr {
   type Output = isize;
 
   fn sub(self, other: Self) -> Self::Output {
-    // Casts are lossless, see the comment for [`LineNr`](::linenr::LineNr)
-    self.0 as isize - other.0 as isize
+    isize::from(self) - isize::from(other)
   }
 }
 
 impl AddAssign<isize> for LineNr {
   fn add_assign(&mut self, other: isize) {
-    let res = self.0 as isize + other;
-    // Casts are lossless, see the comment for [`LineNr`](::linenr::LineNr)
-    debug_assert!(res >= 0 && res <= i32::MAX as isize);
-    self.0 = res as u32;
+    *self = *self + other;
   }
 }
 
@@ -108,3 +185,36 @@ impl From<LineNr> for Value {
     Value::Integer(From::from(v.0))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::linenr::LineNr;
+
+  #[test]
+  fn convert_neovim_linenrs() {
+    assert_eq!(LineNr::try_from_i64(3).unwrap(), LineNr::from_usize(3));
+    assert!(LineNr::try_from_i64(-1).is_err());
+    assert!(LineNr::try_from_i64(i64::from(i32::max_value()) + 1).is_err());
+    assert_eq!(LineNr::try_from_i64(0x7fff_ffff).unwrap(), LineNr::MAX);
+
+    let [first, last] = LineNr::try_range(2, 5).unwrap();
+    assert_eq!((i64::from(first), i64::from(last)), (2, 5));
+    assert!(LineNr::try_range(5, 2).is_err());
+    assert!(LineNr::try_range(0, -1).is_err());
+  }
+
+  #[test]
+  fn linenr_arithmetic() {
+    let l = LineNr::from_usize(5);
+
+    assert_eq!(l + 3, LineNr::from_usize(8));
+    assert_eq!(l + -5, LineNr::from_usize(0));
+    assert_eq!(l - LineNr::from_usize(7), -2);
+    assert_eq!(l.checked_add(-6), None);
+    assert_eq!(LineNr::MAX.checked_add(1), None);
+    assert_eq!(l.saturating_add(-6), LineNr::from_usize(0));
+    assert_eq!(LineNr::MAX.saturating_add(1), LineNr::MAX);
+    assert_eq!(l.lines_to(LineNr::from_usize(7)), 2);
+    assert_eq!(l.lines_to(LineNr::from_usize(2)), 0);
+  }
+}
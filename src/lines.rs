@@ -1,13 +1,24 @@
 //! This module holds the datastructure for the Lines of the buffer.
 //!
-//! Future ideas, if performance isn't enough: Skip list, gap buffer (adapted to
-//! lines instead of strings), rope (adapted to lines instead of strings)
-use std::{convert::AsRef, fmt, ops::Deref, slice};
+//! The lines are stored in chunks of at most `CHUNK_LINES` lines, and their
+//! numbers are stored relative to the first line of their chunk. An update
+//! only rebuilds the chunks it touches, and moves the first lines of the
+//! chunks after them. That's still linear in the number of chunks, but with
+//! a million lines, those are only about a thousand, see `benches/lines.rs`.
+//! Lines are accessed by their index, and handed out as
+//! [`LineRef`](crate::lines::LineRef)s with their line number.
+use std::{convert::AsRef, fmt, mem, ops::Range, panic, thread};
 
 use crate::{card::keyword::Keyword, linenr::LineNr, linesiter::LinesIter};
 
 use memchr;
 
+/// The maximum number of lines of a chunk
+const CHUNK_LINES: usize = 1024;
+/// The number of lines above which the keywords of new lines are parsed on
+/// several threads, e.g. for a paste of a large block
+const PARALLEL_LINES: usize = 16 * CHUNK_LINES;
+
 /// An enum representing a line of a file, either as a byte slice (which we
 /// obtain from reading a file into a `Vec<u8>` and splitting on newlines) or an
 /// owned `String` (which we get from neovim's buffer update API via a
@@ -19,7 +30,9 @@ pub enum RawLine<'a> {
 }
 
 /// A struct to hold the data of a [`RawLine`](crate::lines::RawLine) that has
-/// been [`parse`](crate::card::keyword::Keyword::parse)d before.
+/// been [`parse`](crate::card::keyword::Keyword::parse)d before. This owns the
+/// line, e.g. to send it to another thread or to build
+/// [`Lines`](crate::lines::Lines) from it.
 #[derive(PartialEq, Debug)]
 pub struct ParsedLine<'a> {
   pub number: LineNr,
@@ -27,6 +40,15 @@ pub struct ParsedLine<'a> {
   pub keyword: Option<Keyword>,
 }
 
+/// A line of [`Lines`](crate::lines::Lines), referencing its
+/// [`RawLine`](crate::lines::RawLine).
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct LineRef<'a> {
+  pub number: LineNr,
+  pub text: &'a RawLine<'a>,
+  pub keyword: Option<Keyword>,
+}
+
 /// A struct to hold (a reference to) a [`RawLine`](crate::lines::RawLine) of a
 /// file that has been [`parse`](crate::card::keyword::Keyword::parse)d and
 /// starts with a [`Keyword`](crate::card::keyword::Keyword).
@@ -37,9 +59,41 @@ pub struct KeywordLine<'a> {
   pub keyword: Keyword,
 }
 
+/// A line as stored in a [`Chunk`](crate::lines::Chunk), numbered relative to
+/// the first line of the chunk
+#[derive(Debug)]
+struct StoredLine<'a> {
+  offset: u32,
+  text: RawLine<'a>,
+  keyword: Option<Keyword>,
+}
+
+/// Consecutive lines, never empty. `first` is the number of the first line.
+#[derive(Debug)]
+struct Chunk<'a> {
+  first: LineNr,
+  lines: Vec<StoredLine<'a>>,
+}
+
 /// The struct to hold the lines.
-#[derive(Debug, Default, PartialEq)]
-pub struct Lines<'a>(Vec<ParsedLine<'a>>);
+#[derive(Debug, Default)]
+pub struct Lines<'a> {
+  chunks: Vec<Chunk<'a>>,
+  /// The index of the first line of each chunk
+  starts: Vec<usize>,
+  len: usize,
+}
+
+/// An iterator over a range of [`Lines`](crate::lines::Lines). `front` and
+/// `back` are the positions of the next line from either end, as the index of
+/// the chunk and the (for `back` end-exclusive) index in the chunk.
+#[derive(Clone)]
+pub struct Iter<'a> {
+  chunks: &'a [Chunk<'a>],
+  front: (usize, usize),
+  back: (usize, usize),
+  len: usize,
+}
 
 impl<'a> AsRef<[u8]> for RawLine<'a> {
   fn as_ref(&self) -> &[u8] {
@@ -51,88 +105,274 @@ impl<'a> AsRef<[u8]> for RawLine<'a> {
   }
 }
 
+/// Strip the `\r` of a CRLF line ending, so it does not end up in the last
+/// cell of the line
+#[inline]
+fn trim_cr(l: &[u8]) -> &[u8] {
+  match l.split_last() {
+    Some((b'\r', rest)) => rest,
+    _ => l,
+  }
+}
+
+/// The keywords of `lines`. Above `PARALLEL_LINES` lines, they're split into
+/// a chunk for each of the `threads`.
+fn parse_keywords(lines: &[String], threads: usize) -> Vec<Option<Keyword>> {
+  let parse = |lines: &[String]| -> Vec<Option<Keyword>> {
+    lines.iter().map(|s| Keyword::parse(s.as_ref())).collect()
+  };
+
+  if lines.len() <= PARALLEL_LINES || threads < 2 {
+    return parse(lines);
+  }
+
+  let size = lines.len().div_ceil(threads);
+  thread::scope(|s| {
+    let handles: Vec<_> = lines
+      .chunks(size)
+      .map(|c| s.spawn(move || parse(c)))
+      .collect();
+
+    handles
+      .into_iter()
+      .flat_map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+      .collect()
+  })
+}
+
+/// The lines of `v` that are no comments, numbered starting at `first`. A
+/// trailing `\r` is removed from each line.
+fn owned_lines(
+  mut v: Vec<String>,
+  first: LineNr,
+) -> impl Iterator<Item = ParsedLine<'static>> {
+  for s in v.iter_mut().filter(|s| s.ends_with('\r')) {
+    let _ = s.pop();
+  }
+  let threads = thread::available_parallelism().map_or(1, |n| n.get());
+  let keywords = parse_keywords(&v, threads);
+
+  v.into_iter()
+    .zip(keywords)
+    .enumerate()
+    .filter(|(_, (s, _))| {
+      let first = s.as_bytes().get(0);
+      first != Some(&b'$') && first != Some(&b'#')
+    })
+    .map(move |(i, (s, keyword))| ParsedLine {
+      number: first + LineNr::from_usize(i),
+      keyword,
+      text: RawLine::ChangedLine(s),
+    })
+}
+
+/// Append `line` to the last of the `chunks`, or to a new one if it has
+/// `size` lines already
+fn push_line<'a>(
+  chunks: &mut Vec<Chunk<'a>>,
+  line: ParsedLine<'a>,
+  size: usize,
+) {
+  match chunks.last_mut() {
+    Some(chunk) if chunk.lines.len() < size => chunk.lines.push(StoredLine {
+      offset: chunk.first.lines_to(line.number) as u32,
+      text: line.text,
+      keyword: line.keyword,
+    }),
+    _ => chunks.push(Chunk {
+      first: line.number,
+      lines: vec![StoredLine {
+        offset: 0,
+        text: line.text,
+        keyword: line.keyword,
+      }],
+    }),
+  }
+}
+
 impl<'a> ParsedLine<'a> {
-  fn shift(&mut self, added: isize) {
-    self.number += added;
+  /// A reference to the line, like the ones handed out by
+  /// [`Lines`](crate::lines::Lines)
+  pub fn as_line_ref(&self) -> LineRef<'_> {
+    LineRef {
+      number: self.number,
+      text: &self.text,
+      keyword: self.keyword,
+    }
   }
+}
 
-  /// Try to convert the [`ParsedLine`](crate::lines::ParsedLine) into a
+impl<'a> LineRef<'a> {
+  /// A copy of the line that owns its text, e.g. to send it to another
+  /// thread. Invalid UTF-8 is replaced, since owned lines are `String`s.
+  pub fn to_owned_line(&self) -> ParsedLine<'static> {
+    ParsedLine {
+      number: self.number,
+      text: RawLine::ChangedLine(
+        String::from_utf8_lossy(self.text.as_ref()).into_owned(),
+      ),
+      keyword: self.keyword,
+    }
+  }
+
+  /// Try to convert the [`LineRef`](crate::lines::LineRef) into a
   /// [`KeywordLine`](crate::lines::KeywordLine). This is of course possible if
-  /// and only if the [`keyword`](crate::lines::ParsedLine::keyword) is
+  /// and only if the [`keyword`](crate::lines::LineRef::keyword) is
   /// `Some(kw)`.
-  pub fn try_into_keywordline(&'a self) -> Option<KeywordLine<'a>> {
-    if let Some(kw) = self.keyword {
-      return Some(KeywordLine {
-        number: self.number,
-        text: self.text.as_ref(),
-        keyword: kw,
-      });
-    } else {
-      return None;
-    }
+  pub fn try_into_keywordline(self) -> Option<KeywordLine<'a>> {
+    self.keyword.map(|kw| KeywordLine {
+      number: self.number,
+      text: self.text.as_ref(),
+      keyword: kw,
+    })
+  }
+}
+
+impl<'a> Chunk<'a> {
+  fn get(&self, i: usize) -> Option<LineRef<'_>> {
+    self.lines.get(i).map(|l| LineRef {
+      number: self.first + LineNr::from_usize(l.offset as usize),
+      text: &l.text,
+      keyword: l.keyword,
+    })
+  }
+
+  /// Move the lines out of the chunk
+  fn into_lines(self) -> impl Iterator<Item = ParsedLine<'a>> {
+    let first = self.first;
+    self.lines.into_iter().map(move |l| ParsedLine {
+      number: first + LineNr::from_usize(l.offset as usize),
+      text: l.text,
+      keyword: l.keyword,
+    })
   }
 }
 
 impl<'a> Lines<'a> {
   pub fn new() -> Self {
-    Lines(vec![])
+    Lines::default()
   }
 
   pub fn is_empty(&self) -> bool {
-    self.0.is_empty()
+    self.len == 0
   }
 
   pub fn clear(&mut self) {
-    self.0.clear()
+    self.chunks.clear();
+    self.starts.clear();
+    self.len = 0;
   }
+
   pub fn len(&self) -> usize {
-    self.0.len()
+    self.len
+  }
+
+  /// The number of bytes of the text of all lines
+  pub fn text_bytes(&self) -> usize {
+    self.iter().map(|l| l.text.as_ref().len()).sum()
   }
 
-  /// Extend a [`Lines`](crate::lines::Lines) struct from a `Vec<String>`
+  /// The number of bytes allocated for the lines. Lines borrowed from the
+  /// original file are only accounted for by their entry in their chunk.
+  pub fn heap_bytes(&self) -> usize {
+    let chunks: usize = self
+      .chunks
+      .iter()
+      .map(|c| {
+        let changed: usize = c
+          .lines
+          .iter()
+          .map(|l| match l.text {
+            RawLine::ChangedLine(ref s) => s.capacity(),
+            RawLine::OriginalLine(_) => 0,
+          })
+          .sum();
+        c.lines.capacity() * mem::size_of::<StoredLine>() + changed
+      })
+      .sum();
+
+    self.chunks.capacity() * mem::size_of::<Chunk>()
+      + self.starts.capacity() * mem::size_of::<usize>()
+      + chunks
+  }
+
+  /// Append a line, which needs to be after all others
+  fn push(&mut self, line: ParsedLine<'a>) {
+    let chunks = self.chunks.len();
+    push_line(&mut self.chunks, line, CHUNK_LINES);
+    if self.chunks.len() > chunks {
+      self.starts.push(self.len);
+    }
+    self.len += 1;
+  }
+
+  /// Recompute the index of the first line of the chunks from the one with
+  /// index `from` on. The ones before need to be unchanged.
+  fn reindex_from(&mut self, from: usize) {
+    self.starts.truncate(from);
+    let mut start = self
+      .starts
+      .last()
+      .map_or(0, |&s| s + self.chunks[from - 1].lines.len());
+    for chunk in &self.chunks[from..] {
+      self.starts.push(start);
+      start += chunk.lines.len();
+    }
+    self.len = start;
+  }
+
+  /// The index of the chunk containing the line with index `idx`. It's the
+  /// last chunk if `idx` is after the last line.
+  fn chunk_of(&self, idx: usize) -> usize {
+    match self.starts.binary_search(&idx) {
+      Ok(c) => c,
+      Err(c) => c.saturating_sub(1),
+    }
+  }
+
+  /// Extend a [`Lines`](crate::lines::Lines) struct from a `Vec<String>`. A
+  /// trailing `\r` is removed from each line, which happens if a file with
+  /// mixed line endings is edited with `fileformat=unix`.
   pub fn parse_vec(&mut self, v: Vec<String>) {
-    self.0.extend(
-      v.into_iter()
-        .enumerate()
-        .filter(|(_, s)| {
-          let first = s.as_bytes().get(0);
-          first != Some(&b'$') && first != Some(&b'#')
-        })
-        .map(|(i, s)| ParsedLine {
-          number: i.into(),
-          keyword: Keyword::parse(s.as_ref()),
-          text: RawLine::ChangedLine(s),
-        }),
-    );
+    for line in owned_lines(v, LineNr::from_usize(0)) {
+      self.push(line);
+    }
   }
 
   /// Extend a [`Lines`](crate::lines::Lines) struct from a slice of `&'str`s
   pub fn parse_strs<'c: 'a>(&mut self, v: &'c [&'a str]) {
-    self.0.extend(
-      v.iter()
-        .enumerate()
-        .filter(|(_, s)| {
-          let first = s.as_bytes().get(0_usize);
-          first != Some(&b'$') && first != Some(&b'#')
-        })
-        .map(|(i, l)| ParsedLine {
+    let lines = v
+      .iter()
+      .enumerate()
+      .filter(|(_, s)| {
+        let first = s.as_bytes().get(0_usize);
+        first != Some(&b'$') && first != Some(&b'#')
+      })
+      .map(|(i, l)| {
+        let l = trim_cr(l.as_ref());
+        ParsedLine {
           number: i.into(),
-          text: RawLine::OriginalLine(l.as_ref()),
-          keyword: Keyword::parse(l.as_ref()),
-        }),
-    );
+          text: RawLine::OriginalLine(l),
+          keyword: Keyword::parse(l),
+        }
+      });
+
+    for line in lines {
+      self.push(line);
+    }
   }
 
   /// Extend a [`Lines`](crate::lines::Lines) struct from a byte slice by
-  /// splitting on newlines.
+  /// splitting on newlines. Both LF and CRLF line endings are recognized, even
+  /// mixed in the same slice.
   pub fn parse_slice<'c: 'a>(&mut self, mut v: &'c [u8]) {
     let mut lineidx = 0usize;
 
     while let Some(nl) = memchr::memchr(b'\n', v) {
       let first = v.get(0_usize).expect("Memchr found slice nonempty");
       if first != &b'$' && first != &b'#' {
-        let l = &v[..nl];
-        self.0.push(ParsedLine {
+        let l = trim_cr(&v[..nl]);
+        self.push(ParsedLine {
           number: lineidx.into(),
           text: RawLine::OriginalLine(l),
           keyword: Keyword::parse(l),
@@ -145,10 +385,11 @@ impl<'a> Lines<'a> {
     let first = v.get(0_usize);
 
     if first.is_some() && first != Some(&b'$') && first != Some(&b'#') {
-      self.0.push(ParsedLine {
+      let l = trim_cr(v);
+      self.push(ParsedLine {
         number: lineidx.into(),
-        text: RawLine::OriginalLine(v),
-        keyword: Keyword::parse(v),
+        text: RawLine::OriginalLine(l),
+        keyword: Keyword::parse(l),
       });
     }
   }
@@ -159,6 +400,12 @@ impl<'a> Lines<'a> {
   /// This are the exact conditions to use the range `first..last` together with
   /// `splice` on a `Vec`.
   /// Returns the change in length after removing comments
+  ///
+  /// Only the chunks containing the replaced lines are rebuilt, along with
+  /// the next one if they'd end up small. The chunks after them are moved by
+  /// `added` lines, and their indices are recomputed. So an update takes time
+  /// linear in the number of replaced lines plus the number of chunks after
+  /// them, i.e. the lines after them divided by `CHUNK_LINES`.
   pub fn update(
     &mut self,
     linedata: Vec<String>,
@@ -168,45 +415,162 @@ impl<'a> Lines<'a> {
   ) -> isize {
     let startidx = self.linenr_to_index(first);
     let endidx = self.linenr_to_index(last);
+    let newlines: Vec<_> = owned_lines(linedata, first).collect();
 
-    let indexrange = startidx..endidx;
+    let new_nocomments = LineNr::from_usize(newlines.len())
+      - LineNr::from_usize(endidx - startidx);
 
-    if added != 0 {
-      for line in self.0[indexrange.end..].iter_mut() {
-        line.shift(added);
-      }
+    let mut touched = if self.chunks.is_empty() {
+      0..0
+    } else {
+      let lastidx = if endidx > startidx {
+        endidx - 1
+      } else {
+        startidx
+      };
+      self.chunk_of(startidx)..self.chunk_of(lastidx) + 1
+    };
+    let kept = self.starts.get(touched.end).map_or(self.len, |&s| s)
+      - self.starts.get(touched.start).map_or(0, |&s| s)
+      - (endidx - startidx);
+    if kept + newlines.len() < CHUNK_LINES / 2
+      && touched.end < self.chunks.len()
+    {
+      touched.end += 1;
     }
 
-    let mut newlines = Lines::new();
-    newlines.parse_vec(linedata);
+    let first_idx = self.starts.get(touched.start).map_or(0, |&s| s);
+    let mut before = vec![];
+    let mut after = vec![];
+    let drained = self
+      .chunks
+      .drain(touched.clone())
+      .flat_map(Chunk::into_lines);
+    for (idx, line) in (first_idx..).zip(drained) {
+      if idx < startidx {
+        before.push(line);
+      } else if idx >= endidx {
+        after.push(ParsedLine {
+          number: line.number + added,
+          ..line
+        });
+      }
+    }
 
-    // TODO(KillTheMule): What to do about these casts?
-    let new_nocomments = newlines.len() as isize - indexrange.len() as isize;
+    // Spread the lines evenly, so the chunks don't get split into tiny ones
+    // by repeated insertions
+    let count = before.len() + newlines.len() + after.len();
+    let size = match count.div_ceil(CHUNK_LINES) {
+      0 => CHUNK_LINES,
+      nchunks => count.div_ceil(nchunks),
+    };
+    let mut rebuilt = vec![];
+    for line in before.into_iter().chain(newlines).chain(after) {
+      push_line(&mut rebuilt, line, size);
+    }
 
-    let _ = self.0.splice(
-      indexrange,
-      newlines.0.into_iter().map(|mut p| {
-        p.number += first;
-        p
-      }),
-    );
+    let moved = touched.start + rebuilt.len();
+    let _ = self.chunks.splice(touched.start..touched.start, rebuilt);
+    if added != 0 {
+      for chunk in &mut self.chunks[moved..] {
+        chunk.first += added;
+      }
+    }
+    self.reindex_from(touched.start);
 
     new_nocomments
   }
 
+  /// Copies of the lines with indices in `window`, with the lines from
+  /// `first` to `last` replaced by `linedata` just like
+  /// [`update`](crate::lines::Lines::update) would do it. The lines
+  /// themselves are not changed.
+  pub fn replaced(
+    &self,
+    window: Range<usize>,
+    linedata: Vec<String>,
+    first: LineNr,
+    last: LineNr,
+  ) -> Vec<ParsedLine<'static>> {
+    let startidx = self.linenr_to_index(first).max(window.start);
+    let endidx = self.linenr_to_index(last).min(window.end).max(startidx);
+    let added = (first + LineNr::from_usize(linedata.len())) - last;
+
+    self
+      .range(window.start..startidx)
+      .map(|l| l.to_owned_line())
+      .chain(owned_lines(linedata, first))
+      .chain(self.range(endidx..window.end).map(|l| ParsedLine {
+        number: l.number + added,
+        ..l.to_owned_line()
+      }))
+      .collect()
+  }
+
   /// Return an Iterator over the lines of a file.
-  pub fn iter<'b>(&'a self) -> LinesIter<'b, slice::Iter<'b, ParsedLine<'b>>>
-  where
-    'a: 'b,
-  {
-    LinesIter::new(self.0.iter())
+  pub fn iter(&self) -> LinesIter<'_, Iter<'_>> {
+    LinesIter::new(self.range(0..self.len))
+  }
+
+  /// Return an Iterator over the lines with the indices in `range`
+  pub fn range(&self, range: Range<usize>) -> Iter<'_> {
+    let end = range.end.min(self.len);
+
+    if range.start >= end {
+      return Iter {
+        chunks: &[],
+        front: (0, 0),
+        back: (0, 0),
+        len: 0,
+      };
+    }
+
+    let first = self.chunk_of(range.start);
+    let last = self.chunk_of(end - 1);
+
+    Iter {
+      chunks: &self.chunks,
+      front: (first, range.start - self.starts[first]),
+      back: (last, end - self.starts[last]),
+      len: end - range.start,
+    }
+  }
+
+  /// The line with the index `idx`
+  pub fn get(&self, idx: usize) -> Option<LineRef<'_>> {
+    if idx >= self.len {
+      return None;
+    }
+    let c = self.chunk_of(idx);
+    self.chunks[c].get(idx - self.starts[c])
+  }
+
+  /// The last line
+  pub fn last(&self) -> Option<LineRef<'_>> {
+    self.len.checked_sub(1).and_then(|i| self.get(i))
+  }
+
+  /// Search the index of the line with number `line`, like
+  /// [`binary_search`](slice::binary_search) on a slice: If there's no such
+  /// line, the error holds the index where it would be inserted.
+  pub fn search(&self, line: LineNr) -> Result<usize, usize> {
+    let c = match self.chunks.binary_search_by_key(&line, |c| c.first) {
+      Ok(c) => return Ok(self.starts[c]),
+      Err(0) => return Err(0),
+      Err(c) => c - 1,
+    };
+    let chunk = &self.chunks[c];
+    let offset = chunk.first.lines_to(line) as u32;
+
+    chunk
+      .lines
+      .binary_search_by_key(&offset, |l| l.offset)
+      .map(|i| self.starts[c] + i)
+      .map_err(|i| self.starts[c] + i)
   }
 
   fn linenr_to_index(&self, line: LineNr) -> usize {
-    self
-      .0
-      .binary_search_by_key(&line, |l| l.number)
-      .unwrap_or_else(|e| e)
+    self.search(line).unwrap_or_else(|e| e)
   }
 
   // TODO(KillTheMule): Efficient? This is called a lot ...
@@ -222,9 +586,7 @@ impl<'a> Lines<'a> {
       line_index += 1;
     }
     self
-      .get(0..line_index)
-      .unwrap_or(&[])
-      .iter()
+      .range(0..line_index)
       .enumerate()
       .rfind(|(_, l)| l.keyword.is_some())
       .map(|(i, l)| (i, l.number))
@@ -240,28 +602,94 @@ impl<'a> Lines<'a> {
   /// itself starts with a non-comment keyword, its index is returned.
   pub fn first_after(&self, line: LineNr) -> (usize, LineNr) {
     let to_skip = self.linenr_to_index(line);
-    if self.is_empty() {
-      (0_usize, 0_usize.into())
-    } else {
-      self
-        .iter()
+    match self.last() {
+      None => (0_usize, 0_usize.into()),
+      Some(last) => self
+        .range(to_skip..self.len)
         .enumerate()
-        .skip(to_skip)
         .find(|(_, l)| l.keyword.is_some())
-        .map(|(i, l)| (i, l.number))
-        .unwrap_or_else(|| {
-          let len = self.len();
-          (len, self[len - 1].number + 1)
-        })
+        .map(|(i, l)| (to_skip + i, l.number))
+        .unwrap_or_else(|| (self.len, last.number + 1)),
+    }
+  }
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = LineRef<'a>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    let (mut c, mut i) = self.front;
+    while i >= self.chunks[c].lines.len() {
+      c += 1;
+      i = 0;
+    }
+    self.front = (c, i + 1);
+    self.len -= 1;
+
+    self.chunks[c].get(i)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+
+  /// Skip whole chunks at once, e.g. for `skip`
+  fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+    if n >= self.len {
+      self.len = 0;
+      return None;
+    }
+    self.len -= n;
+
+    let (mut c, mut i) = self.front;
+    while i + n >= self.chunks[c].lines.len() {
+      n -= self.chunks[c].lines.len() - i;
+      c += 1;
+      i = 0;
+    }
+    self.front = (c, i + n);
+
+    self.next()
+  }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    let (mut c, mut i) = self.back;
+    while i == 0 {
+      c -= 1;
+      i = self.chunks[c].lines.len();
     }
+    self.back = (c, i - 1);
+    self.len -= 1;
+
+    self.chunks[c].get(i - 1)
   }
 }
 
-impl<'a> Deref for Lines<'a> {
-  type Target = [ParsedLine<'a>];
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl<'a> From<Vec<ParsedLine<'a>>> for Lines<'a> {
+  fn from(v: Vec<ParsedLine<'a>>) -> Self {
+    let mut lines = Lines::new();
+    for line in v {
+      lines.push(line);
+    }
+    lines
+  }
+}
 
-  fn deref(&self) -> &[ParsedLine<'a>] {
-    &self.0
+impl<'a> PartialEq for Lines<'a> {
+  fn eq(&self, other: &Self) -> bool {
+    self.len == other.len && self.iter().eq(other.iter())
   }
 }
 
@@ -278,6 +706,12 @@ impl<'a> fmt::Display for RawLine<'a> {
 }
 
 impl<'a> fmt::Display for ParsedLine<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_line_ref())
+  }
+}
+
+impl<'a> fmt::Display for LineRef<'a> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "{{{}, {}, {:?}}}", self.number, self.text, self.keyword)
   }
@@ -299,7 +733,7 @@ impl<'a> fmt::Display for Lines<'a> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let mut s = String::new();
     s.push_str("Lines {{\n");
-    for line in &self.0 {
+    for line in self.iter() {
       s.push_str(&format!(" {}\n", line));
     }
     s.push_str("}}\n");
@@ -309,9 +743,33 @@ impl<'a> fmt::Display for Lines<'a> {
 
 #[cfg(test)]
 mod tests {
-  use crate::{linenr::LineNr, lines::Lines};
+  use crate::{
+    card::keyword::Keyword,
+    linenr::LineNr,
+    lines::{parse_keywords, Lines, CHUNK_LINES, PARALLEL_LINES},
+  };
   use std::fs;
 
+  #[test]
+  fn parallel_keywords() {
+    let lines: Vec<String> = (0..PARALLEL_LINES + 7)
+      .map(|i| match i % 3 {
+        0 => "NODE  /        1              0.".to_string(),
+        1 => "SHELL /        1       1       1       2       3       4".into(),
+        _ => "        END".into(),
+      })
+      .collect();
+
+    let sequential = parse_keywords(&lines, 1);
+    assert_eq!(sequential.len(), lines.len());
+    assert_eq!(
+      sequential[..3],
+      [Some(Keyword::Node), Some(Keyword::Shell), None]
+    );
+    assert_eq!(parse_keywords(&lines, 3), sequential);
+    assert_eq!(parse_keywords(&lines, 8), sequential);
+  }
+
   const LINES: &str = "This\nis \nan \nexample \nof \nsome \nlines \n.";
 
   const LINES_DEL: &str = "This\n.";
@@ -347,12 +805,13 @@ mod tests {
 
     l.update(newlines, 2.into(), 2.into(), 3);
 
-    for i in 0..11 {
+    for (a, b) in l.iter().zip(ln.iter()) {
       assert_eq!(
-        (l[i].number, l[i].text.as_ref(), l[i].keyword),
-        (ln[i].number, ln[i].text.as_ref(), ln[i].keyword)
+        (a.number, a.text.as_ref(), a.keyword),
+        (b.number, b.text.as_ref(), b.keyword)
       );
     }
+    assert_eq!(l.len(), 11);
   }
 
   const LINES_UPD: &str = "This\nhaaargl\nwaaarglll\nblaaargl\n.";
@@ -372,12 +831,13 @@ mod tests {
 
     l.update(newlines, 1.into(), 7.into(), -3);
 
-    for i in 0..5 {
+    for (i, (a, b)) in l.iter().zip(ln.iter()).enumerate() {
       assert_eq!(
-        (i, l[i].number, l[i].text.as_ref(), l[i].keyword),
-        (i, ln[i].number, ln[i].text.as_ref(), ln[i].keyword)
+        (i, a.number, a.text.as_ref(), a.keyword),
+        (i, b.number, b.text.as_ref(), b.keyword)
       );
     }
+    assert_eq!(l.len(), 5);
   }
 
   #[test]
@@ -389,7 +849,84 @@ mod tests {
              Lines of the buffer. }"
       .to_string();
 
-    assert_eq!(f, format!("{}", l.0[0].text));
+    assert_eq!(f, format!("{}", l.get(0).unwrap().text));
+  }
+
+  const LINES_CRLF: &str =
+    "This\r\nis \r\nan \nexample \r\nof \nsome \r\nlines \r\n.\r";
+
+  #[test]
+  fn lines_strip_crlf() {
+    let mut l = Lines::new();
+    l.parse_slice(LINES_CRLF.as_ref());
+
+    let mut ln = Lines::new();
+    ln.parse_slice(LINES.as_ref());
+    assert_eq!(l, ln);
+
+    let mut lv = Lines::new();
+    lv.parse_vec(vec!["NODE  / \r".to_string(), "\r".to_string()]);
+    assert_eq!(lv.get(0).unwrap().text.as_ref(), b"NODE  / ");
+    assert_eq!(lv.get(1).unwrap().text.as_ref(), b"");
+  }
+
+  /// A buffer of `n` lines spanning several chunks, with some keywords and
+  /// comments
+  fn big_buffer(n: usize) -> Vec<String> {
+    (0..n)
+      .map(|i| match i % 7 {
+        0 => format!("NODE  / {:8}", i),
+        3 => format!("$ comment {}", i),
+        _ => format!("line {}", i),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn updates_across_chunks() {
+    let mut buffer = big_buffer(5000);
+    let mut l = Lines::new();
+    l.parse_vec(buffer.clone());
+
+    // (first, last, number of new lines)
+    let updates = [
+      (0, 0, 3),
+      (1020, 1030, 0),
+      (1500, 1500, 2500),
+      (10, 3000, 1),
+      (4000, 4504, 20),
+      (2000, 2001, 1),
+      (0, 3000, 0),
+    ];
+
+    for &(first, last, new) in updates.iter() {
+      let newlines = big_buffer(new);
+      let added = new as isize - (last - first) as isize;
+      let _ = buffer.splice(first..last, newlines.iter().cloned());
+      l.update(newlines, first.into(), last.into(), added);
+
+      let mut fresh = Lines::new();
+      fresh.parse_vec(buffer.clone());
+      assert_eq!(l, fresh);
+      assert!(l
+        .chunks
+        .iter()
+        .all(|c| !c.lines.is_empty() && c.lines.len() <= CHUNK_LINES));
+
+      let numbers: Vec<_> = l.iter().map(|l| l.number).collect();
+      let mut reversed: Vec<_> =
+        l.range(0..l.len()).rev().map(|l| l.number).collect();
+      reversed.reverse();
+      assert_eq!(numbers, reversed);
+      for (i, &n) in numbers.iter().enumerate().step_by(97) {
+        assert_eq!(l.search(n), Ok(i));
+        assert_eq!(l.get(i).map(|l| l.number), Some(n));
+        assert_eq!(
+          l.range(i..l.len()).nth(50).map(|l| l.number),
+          numbers.get(i + 50).cloned()
+        );
+      }
+    }
   }
 
   macro_rules! test_before {
@@ -4,30 +4,37 @@
 //! It returns enumerated Lines, but skips Comments (lines starting with `$` or
 //! `#`). All skip functions, used by
 //! [`parse_from_iter`](crate::bufdata::BufData::parse_from_iter), work on a
-//! [`LinesIter`](crate::linesiter::LinesIter).
+//! [`LinesIter`](crate::linesiter::LinesIter). They can be used to write other
+//! parsers, too: Start with
+//! [`skip_to_next_keyword`](crate::linesiter::LinesIter::skip_to_next_keyword),
+//! skip the card it returns, and continue with the line after it as given by
+//! the returned [`Skipped`](crate::skipresult::Skipped).
+//!
+//! All skip functions expect that the line passed to them was the last one
+//! returned by the iterator.
 use crate::{
   bufdata::highlights::Highlights,
   card::{
     ges::GesType,
-    line::{CondResult, Line as CardLine},
+    keyword::Keyword,
+    line::{self, CondResult, Line as CardLine},
+    value,
+    version::{self, InputVersion},
     Card,
   },
   linenr::LineNr,
-  lines::{KeywordLine, ParsedLine},
-  skipresult::SkipResult,
+  lines::{KeywordLine, LineRef},
+  skipresult::Skipped,
 };
 
-// Used in skip functions. Returns the next `ParsedLine` from the iterator. If
-// theres no next line, return a `SkipResult` containing the line number of
-// `prevline` and nothing else.
+// Used in skip functions. Returns the next `LineRef` from the iterator. If
+// theres no next line, return a `Skipped::Eof` containing the line number of
+// `prevline`.
 macro_rules! next_or_return_previdx {
   ($self:ident, $previdx:expr) => {
     match $self.next() {
       None => {
-        return SkipResult {
-          skip_end: $previdx,
-          nextline: None,
-        };
+        return Skipped::Eof { skip_end: $previdx };
       }
       Some(t) => t,
     };
@@ -35,15 +42,12 @@ macro_rules! next_or_return_previdx {
 }
 
 // Used in skip_ges to get the next line. If it's None, we're at the end of
-// the file and only return what we found before. Also used in `advance_some!`
+// the file and only return what we found before.
 macro_rules! next_or_return_some_previdx {
   ($self:ident, $previdx:expr) => {
     match $self.next() {
       None => {
-        return Some(SkipResult {
-          skip_end: $previdx,
-          nextline: None,
-        });
+        return Some(Skipped::Eof { skip_end: $previdx });
       }
       Some(t) => t,
     };
@@ -62,7 +66,7 @@ macro_rules! next_or_return_none {
 }
 
 // A common pattern for nocommentiter: Save Some(nextline) in prevline,
-// and advance the iterator. Save in nextline, or return a SkipResult built
+// and advance the iterator. Save in nextline, or return a `Skipped::Eof` built
 // from prevline's line number
 macro_rules! advance {
   ($self:ident, $previdx:ident, $nextline:ident) => {
@@ -71,28 +75,56 @@ macro_rules! advance {
   };
 }
 
-// Same as advance above, just that the `SkipResult` is wrapped in `Some`. Used
-// in skip_ges.
-macro_rules! advance_some {
-  ($self:ident, $previdx:ident, $nextline:ident) => {
-    $previdx = $nextline.number;
-    $nextline = next_or_return_some_previdx!($self, $previdx);
-  };
+/// The title of a card of type `card` with the keyword line `text` and the
+/// parts `name` of its `NAME` lines, see
+/// [`take_title`](LinesIter::take_title)
+fn title(card: &Card, text: &[u8], name: &[String]) -> Option<String> {
+  let parts: Vec<&str> = name
+    .iter()
+    .map(String::as_str)
+    .filter(|n| !n.is_empty())
+    .collect();
+
+  if parts.is_empty() {
+    return None;
+  }
+  let name = parts.join(" ");
+
+  Some(match value::card_id(card, text) {
+    Some(id) => format!("{}: {}", id, name),
+    None => name,
+  })
 }
+
 /// The struct simply holds a type instance. Skipping comments is done in the
 /// Iterator implementation.
+///
+/// The GES regions skipped by [`skip_ges`](LinesIter::skip_ges) are recorded
+/// along with the number of their entries, so they can be folded. Fetch them
+/// via [`take_ges_regions`](LinesIter::take_ges_regions). Likewise, the title
+/// of the last fold skipped is available via
+/// [`take_title`](LinesIter::take_title).
+///
+/// Cards are skipped according to their layout in the input version set via
+/// [`with_version`](LinesIter::with_version), or the current one if unset.
+/// The highlights of the skipped lines are only added if not disabled via
+/// [`with_highlights`](LinesIter::with_highlights).
 pub struct LinesIter<'a, I>
 where
-  I: Iterator<Item = &'a ParsedLine<'a>>,
+  I: Iterator<Item = LineRef<'a>>,
 {
   it: I,
+  ges_regions: Vec<([LineNr; 2], usize)>,
+  title: Option<String>,
+  version: Option<InputVersion>,
+  highlight: bool,
 }
 
 impl<'a, I> Iterator for LinesIter<'a, I>
 where
-  I: Iterator<Item = &'a ParsedLine<'a>>,
+  I: Iterator<Item = LineRef<'a>>,
 {
-  type Item = &'a ParsedLine<'a>;
+  type Item = LineRef<'a>;
 
   fn next(&mut self) -> Option<Self::Item> {
     self.it.next()
@@ -101,10 +133,41 @@ where
 
 impl<'a, I> LinesIter<'a, I>
 where
-  I: Iterator<Item = &'a ParsedLine<'a>>,
+  I: Iterator<Item = LineRef<'a>>,
 {
   pub fn new(it: I) -> Self {
-    Self { it }
+    Self {
+      it,
+      ges_regions: vec![],
+      title: None,
+      version: None,
+      highlight: true,
+    }
+  }
+
+  /// Skip cards according to their layout in the given input version
+  pub fn with_version(mut self, version: Option<InputVersion>) -> Self {
+    self.version = version;
+    self
+  }
+
+  /// Add the highlights of the skipped lines only if `enable` is true
+  pub fn with_highlights(mut self, enable: bool) -> Self {
+    self.highlight = enable;
+    self
+  }
+
+  /// Return the GES regions of more than one line recorded since the last
+  /// call, as the end-inclusive range of lines and the number of entries.
+  pub fn take_ges_regions(&mut self) -> Vec<([LineNr; 2], usize)> {
+    std::mem::take(&mut self.ges_regions)
+  }
+
+  /// Return the title of the last fold skipped, if it consists of a single
+  /// card with a `NAME` line. It's the ID of the card, if it has one, followed
+  /// by the name, e.g. `37: bumper_outer`.
+  pub fn take_title(&mut self) -> Option<String> {
+    self.title.take()
   }
 
   /// Advance the iterator until meeting the first line with a keyword. If the
@@ -124,52 +187,69 @@ where
   ///
   /// Returns `None` if skipline neither ends the GES, nor is
   /// contained in it. We do not try to advance the iterator in this case.
+  ///
+  /// GES regions of more than one line are recorded, see
+  /// [`take_ges_regions`](LinesIter::take_ges_regions).
   pub fn skip_ges<'b>(
     &'b mut self,
     ges: GesType,
-    skipline: &ParsedLine<'a>,
-  ) -> Option<SkipResult<'a>> {
+    skipline: LineRef<'a>,
+  ) -> Option<Skipped<'a>> {
     let mut previdx: LineNr = skipline.number;
-    let mut nextline: &'a ParsedLine<'a>;
 
     let contained = ges.contains(skipline.text.as_ref());
     let ends = ges.ended_by(skipline.text.as_ref());
 
     if ends {
-      nextline = next_or_return_some_previdx!(self, previdx);
-      Some(SkipResult {
-        nextline: Some(nextline),
+      let nextline = next_or_return_some_previdx!(self, previdx);
+      Some(Skipped::ToKeyword {
         skip_end: previdx,
+        nextline,
       })
     } else if !ends && !contained {
       None
     } else {
-      nextline = next_or_return_some_previdx!(self, skipline.number);
+      // Not using the macros here, since the region needs to be recorded even
+      // if the file ends inside of it
+      let mut entries = 1;
+      let mut next = self.next();
+
+      while let Some(n) = next.filter(|n| ges.contains(n.text.as_ref())) {
+        entries += 1;
+        previdx = n.number;
+        next = self.next();
+      }
 
-      while ges.contains(nextline.text.as_ref()) {
-        advance_some!(self, previdx, nextline);
+      if let Some(n) = next.filter(|n| ges.ended_by(n.text.as_ref())) {
+        previdx = n.number;
+        next = self.next();
       }
 
-      if ges.ended_by(nextline.text.as_ref()) {
-        advance_some!(self, previdx, nextline);
+      if skipline.number < previdx {
+        self.ges_regions.push(([skipline.number, previdx], entries));
       }
 
-      Some(SkipResult {
-        nextline: Some(nextline),
-        skip_end: previdx,
+      Some(match next {
+        Some(nextline) => Skipped::ToKeyword {
+          skip_end: previdx,
+          nextline,
+        },
+        None => Skipped::Eof { skip_end: previdx },
       })
     }
   }
 
   /// A wrapper around [`skip_card`](LinesIter::skip_card) and
   /// [`skip_card_gather`](LinesIter::skip_card_gather), dispatching by value of
-  /// [`Card.ownfold`](crate::card::Card::ownfold)
+  /// [`Card.ownfold`](crate::card::Card::ownfold). The card is looked up by
+  /// the keyword of `skipline`, taking the input version into account. The
+  /// skipped lines are the ones of a level 1 fold.
   pub fn skip_fold<'b>(
     &'b mut self,
     skipline: &KeywordLine<'a>,
     highlights: &mut Highlights,
-  ) -> SkipResult<'a> {
-    let card: &Card = (&skipline.keyword).into();
+  ) -> Skipped<'a> {
+    let card = version::card_for(skipline.keyword, self.version);
 
     if card.ownfold {
       self.skip_card(&skipline, card, highlights)
@@ -178,64 +258,111 @@ where
     }
   }
 
-  /// Let [`NoCommentIter`](NoCommentIter) skip the given
-  /// [`Card`](::card::Card), but only skip this 1 card. This only really makes
-  /// sense when the last line the iterator returned is the line with the
-  /// keyword starting that card, which is passed as `skipline`.
+  /// Let the [`LinesIter`](LinesIter) skip the given
+  /// [`Card`](crate::card::Card), but only skip this 1 card. This only really
+  /// makes sense when the last line the iterator returned is the line with the
+  /// keyword starting that card, which is passed as `skipline`. The highlights
+  /// of the skipped lines are added to `highlights`.
   ///
   /// If you want to skip all cards of a given type, use
-  /// [`skip_card_gather`](NoCommentIter::skip_card_gather)
-  fn skip_card<'b>(
+  /// [`skip_card_gather`](LinesIter::skip_card_gather)
+  pub fn skip_card<'b>(
     &'b mut self,
     skipline: &KeywordLine<'a>,
     card: &Card,
     highlights: &mut Highlights,
-  ) -> SkipResult<'a> {
+  ) -> Skipped<'a> {
     let mut conds: Vec<CondResult> = vec![]; // the vec to hold the conditionals
-    let mut cardlines = card.lines.iter();
+    let mut cardlines = card.layout(skipline.text).iter();
+    let mut name: Vec<String> = vec![];
+    self.title = None;
+
+    // Unknown cards are told apart by their keyword
+    if skipline.keyword == Keyword::Unknown {
+      let kw = String::from_utf8_lossy(&skipline.text[..6]);
+      self.title = Some(kw.trim_end().to_string());
+    }
     let cardline = cardlines.next().unwrap_or_else(|| unreachable!());
 
     if let CardLine::Provides(_s, ref c) = cardline {
       conds.push(c.evaluate(skipline.text));
     }
 
-    highlights.add_line_highlights(skipline.number, skipline.text, cardline);
+    if self.highlight {
+      highlights.add_line_highlights(skipline.number, skipline.text, cardline);
+    }
 
     let mut previdx: LineNr = skipline.number;
     let mut nextline = next_or_return_previdx!(self, previdx);
 
     for cardline in cardlines {
-      if nextline.keyword.is_some() {
+      if nextline.keyword.is_some() && !cardline.is_opaque() {
         break;
       }
 
       match *cardline {
         CardLine::Provides(_s, ref c) => {
           conds.push(c.evaluate(nextline.text.as_ref()));
+          if self.highlight {
+            highlights.add_line_highlights(
+              nextline.number,
+              nextline.text.as_ref(),
+              cardline,
+            );
+          }
           advance!(self, previdx, nextline);
         }
-        CardLine::Ges(ref g) => {
-          if let Some(sr) = self.skip_ges(*g, nextline) {
-            match sr.nextline {
-              None => return sr,
-              Some(pl) => {
-                previdx = sr.skip_end;
-                nextline = pl;
-              }
-            };
+        CardLine::Ges(ref g) => match self.skip_ges(*g, nextline) {
+          Some(sr @ Skipped::Eof { .. }) => return sr,
+          Some(Skipped::ToKeyword {
+            skip_end,
+            nextline: pl,
+          }) => {
+            previdx = skip_end;
+            nextline = pl;
           }
-        }
+          None => {}
+        },
+        CardLine::Cells(_s) if cardline.is_name() => loop {
+          if self.highlight {
+            highlights
+              .add_title_highlights(nextline.number, nextline.text.as_ref());
+          }
+
+          // Set right away, the file might end after this line
+          name.push(line::name_title(nextline.text.as_ref()));
+          self.title = title(card, skipline.text, &name);
+
+          let continues = line::name_continues(nextline.text.as_ref());
+          advance!(self, previdx, nextline);
+
+          if !continues
+            || nextline.keyword.is_some()
+            || !nextline.text.as_ref().starts_with(b"NAME")
+          {
+            break;
+          }
+        },
         CardLine::Cells(_s) => {
-          highlights.add_line_highlights(
-            nextline.number,
-            nextline.text.as_ref(),
-            cardline,
-          );
+          if self.highlight {
+            highlights.add_line_highlights(
+              nextline.number,
+              nextline.text.as_ref(),
+              cardline,
+            );
+          }
 
           advance!(self, previdx, nextline);
         }
         CardLine::Optional(_s, i) => {
           if conds.get(i as usize) == Some(&CondResult::Bool(true)) {
+            if self.highlight {
+              highlights.add_line_highlights(
+                nextline.number,
+                nextline.text.as_ref(),
+                cardline,
+              );
+            }
             advance!(self, previdx, nextline);
           } else {
             continue;
@@ -251,6 +378,13 @@ where
           // We need one more loop than *num because we need to get the next
           // line for the next outer iteration
           for _ in 0..*num {
+            if self.highlight {
+              highlights.add_line_highlights(
+                nextline.number,
+                nextline.text.as_ref(),
+                cardline,
+              );
+            }
             advance!(self, previdx, nextline);
 
             if nextline.keyword.is_some() {
@@ -268,6 +402,25 @@ where
           }
           advance!(self, previdx, nextline);
         },
+        CardLine::Opaque(s) => {
+          while !nextline.text.as_ref().starts_with(s) {
+            advance!(self, previdx, nextline);
+          }
+          advance!(self, previdx, nextline);
+        }
+        // Only the first line of a card can hold layouts, and `layout` has
+        // already picked one of them
+        CardLine::Layouts(..) => {}
+        // The nested cards get folds of their own
+        CardLine::Contains(_) => break,
+        // The loop stops at the next keyword line
+        CardLine::Rest => loop {
+          advance!(self, previdx, nextline);
+
+          if nextline.keyword.is_some() {
+            break;
+          }
+        },
         CardLine::OptionalBlock(s1, s2) => {
           if !nextline.text.as_ref().starts_with(s1) {
             continue;
@@ -279,36 +432,38 @@ where
               break;
             }
           }
+          // The line ending the block belongs to it
+          if nextline.keyword.is_none() {
+            advance!(self, previdx, nextline);
+          }
         }
       }
     }
-    SkipResult {
-      nextline: Some(nextline),
+    Skipped::ToKeyword {
       skip_end: previdx,
+      nextline,
     }
   }
 
-  /// Let [`NoCommentIter`](NoCommentIter) skip all given
-  /// [`Card`](::card::Card)s, until the next different card starts. The basic
-  /// assumption is that the last line the iterator returned is a the first line
-  /// of a card of the given type, which is passed as `skipline`.
-  fn skip_card_gather<'b>(
+  /// Let the [`LinesIter`](LinesIter) skip all given
+  /// [`Card`](crate::card::Card)s, until the next different card starts. The
+  /// basic assumption is that the last line the iterator returned is a the
+  /// first line of a card of the given type, which is passed as `skipline`.
+  pub fn skip_card_gather<'b>(
     &'b mut self,
     skipline: &KeywordLine<'a>,
     card: &Card,
     highlights: &mut Highlights,
-  ) -> SkipResult<'a> {
+  ) -> Skipped<'a> {
     let mut r = self.skip_card(&skipline, card, highlights);
 
-    while let Some(p) = r.nextline {
-      if let Some(kl) = p.try_into_keywordline() {
-        if kl.keyword == card.keyword() {
-          r = self.skip_card(&kl, card, highlights);
-        } else {
-          break;
-        }
+    // Invalid lines end the cards, too
+    while let Some(kl) = r.next_keywordline() {
+      if kl.keyword == card.keyword() {
+        r = self.skip_card(&kl, card, highlights);
+        // A fold of several cards has no single title
+        self.title = None;
       } else {
-        // Happens on invalid lines
         break;
       }
     }
@@ -354,8 +509,8 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(COMMENTS.as_ref());
     let mut l = lines.iter();
-    assert_eq!(l.next(), Some(&pline!(4.into(), "of", None)));
-    assert_eq!(l.next(), Some(&pline!(5.into(), "some", None)));
+    assert_eq!(l.next(), Some(pline!(4.into(), "of", None).as_line_ref()));
+    assert_eq!(l.next(), Some(pline!(5.into(), "some", None).as_line_ref()));
   }
 
   const NOKEYWORD_LINES: &'static str = "\nsome\nlines\n.";
@@ -400,12 +555,12 @@ mod tests {
     let mut l = lines.iter();
 
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let tmp = l.skip_ges(GesNode, nextline).unwrap();
     assert_eq!(
-      tmp.nextline.unwrap(),
-      &pline!(4.into(), b"NODE  / ", Some(Node))
+      tmp.nextline().unwrap(),
+      pline!(4.into(), b"NODE  / ", Some(Node)).as_line_ref()
     );
-    assert_eq!(tmp.skip_end, 3.into());
+    assert_eq!(tmp.skip_end(), 3.into());
     assert_eq!(l.next(), None);
   }
 
@@ -428,14 +583,17 @@ mod tests {
     let mut l = lines.iter();
 
     let mut nextline = l.next().unwrap();
-    let mut tmp = l.skip_ges(GesNode, &nextline).unwrap();
-    assert_eq!(tmp.nextline.unwrap(), &pline!(3.into(), GES2_NEXT, None));
-    assert_eq!(tmp.skip_end, 2.into());
+    let mut tmp = l.skip_ges(GesNode, nextline).unwrap();
+    assert_eq!(
+      tmp.nextline().unwrap(),
+      pline!(3.into(), GES2_NEXT, None).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 2.into());
 
     nextline = l.next().unwrap();
-    tmp = l.skip_ges(GesNode, &nextline).unwrap();
-    assert_eq!(tmp.nextline, None);
-    assert_eq!(tmp.skip_end, 8.into());
+    tmp = l.skip_ges(GesNode, nextline).unwrap();
+    assert!(tmp.is_eof());
+    assert_eq!(tmp.skip_end(), 8.into());
     assert_eq!(l.next(), None);
   }
 
@@ -459,18 +617,24 @@ mod tests {
     lines.parse_slice(GES3.as_ref());
     let mut l = lines.iter();
     let mut nextline = l.next().unwrap();
-    let mut tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let mut tmp = l.skip_ges(GesNode, nextline).unwrap();
     assert_eq!(
-      tmp.nextline.unwrap(),
-      &pline!(2.into(), GES3_FIRST, Some(Node))
+      tmp.nextline().unwrap(),
+      pline!(2.into(), GES3_FIRST, Some(Node)).as_line_ref()
     );
-    assert_eq!(tmp.skip_end, 1.into());
+    assert_eq!(tmp.skip_end(), 1.into());
 
     nextline = l.next().unwrap();
-    tmp = l.skip_ges(GesNode, &nextline).unwrap();
-    assert_eq!(tmp.nextline.unwrap(), &pline!(7.into(), GES3_SECOND, None));
-    assert_eq!(tmp.skip_end, 6.into());
-    assert_eq!(l.next(), Some(&pline!(8.into(), GES3_LAST, None)));
+    tmp = l.skip_ges(GesNode, nextline).unwrap();
+    assert_eq!(
+      tmp.nextline().unwrap(),
+      pline!(7.into(), GES3_SECOND, None).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 6.into());
+    assert_eq!(
+      l.next(),
+      Some(pline!(8.into(), GES3_LAST, None).as_line_ref())
+    );
   }
 
   const GES4: &'static str = "wupdiwup\nNODE  / ";
@@ -482,9 +646,12 @@ mod tests {
     lines.parse_slice(GES4.as_ref());
     let mut l = lines.iter();
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline);
+    let tmp = l.skip_ges(GesNode, nextline);
     assert!(tmp.is_none());
-    assert_eq!(l.next().unwrap(), &pline!(1.into(), GES4_LAST, Some(Node)));
+    assert_eq!(
+      l.next().unwrap(),
+      pline!(1.into(), GES4_LAST, Some(Node)).as_line_ref()
+    );
   }
 
   const GES5: &'static str = "        PART 1234\
@@ -503,12 +670,12 @@ mod tests {
     lines.parse_slice(GES5.as_ref());
     let mut l = lines.iter();
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let tmp = l.skip_ges(GesNode, nextline).unwrap();
     assert_eq!(
-      tmp.nextline.unwrap(),
-      &pline!(6.into(), GES5_NEXTL, Some(Node))
+      tmp.nextline().unwrap(),
+      pline!(6.into(), GES5_NEXTL, Some(Node)).as_line_ref()
     );
-    assert_eq!(tmp.skip_end, 4.into());
+    assert_eq!(tmp.skip_end(), 4.into());
     assert_eq!(l.next(), None);
   }
 
@@ -523,9 +690,9 @@ mod tests {
     lines.parse_slice(GES6.as_ref());
     let mut l = lines.iter();
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline).unwrap();
-    assert_eq!(tmp.nextline, None);
-    assert_eq!(tmp.skip_end, 0.into());
+    let tmp = l.skip_ges(GesNode, nextline).unwrap();
+    assert!(tmp.is_eof());
+    assert_eq!(tmp.skip_end(), 0.into());
     assert_eq!(l.next(), None);
   }
 
@@ -550,10 +717,101 @@ mod tests {
     let tmp =
       l.skip_card(&firstline.try_into_keywordline().unwrap(), &MASS, &mut hls);
     assert_eq!(
-      tmp.nextline.unwrap(),
-      &pline!(7.into(), &"NODE  /      ", Some(Node))
+      tmp.nextline().unwrap(),
+      pline!(7.into(), &"NODE  /      ", Some(Node)).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 4.into());
+  }
+
+  const CARD_NSMAS_INCOMPLETE: &'static str =
+    "NSMAS /        1              0.                                                &\
+    \nNAME NSMAS / ->1                                                                \
+    \n$# BLANK           SCALL           SCALA           SCALV\
+    \nNODE  /      \
+    \n        ELE 123";
+
+  const CARD_NSMAS2_INCOMPLETE: &'static str =
+    "NSMAS2/        1              0.                                                \
+    \nNAME NSMAS2/ ->1                                                                \
+    \n        ELE 123\
+    \n        ELE 124\
+    \nNODE  /      \
+    \n        END";
+
+  #[test]
+  fn skip_incomplete_nsmas() {
+    let mut lines = Lines::new();
+    lines.parse_slice(CARD_NSMAS_INCOMPLETE.as_ref());
+    let mut l = lines.iter();
+    let mut hls = Highlights::new();
+    let firstline = l.next().unwrap();
+    let tmp =
+      l.skip_card(&firstline.try_into_keywordline().unwrap(), &NSMAS, &mut hls);
+    assert_eq!(
+      tmp.nextline().unwrap(),
+      pline!(3.into(), &"NODE  /      ", Some(Node)).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 1.into());
+
+    let mut lines = Lines::new();
+    lines.parse_slice(CARD_NSMAS2_INCOMPLETE.as_ref());
+    let mut l = lines.iter();
+    let firstline = l.next().unwrap();
+    let tmp = l.skip_card(
+      &firstline.try_into_keywordline().unwrap(),
+      &NSMAS2,
+      &mut hls,
+    );
+    assert_eq!(
+      tmp.nextline().unwrap(),
+      pline!(4.into(), &"NODE  /      ", Some(Node)).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 3.into());
+  }
+
+  const CARD_MASS_TITLE: &'static str =
+    "MASS  /        0       0                                                        \
+    \nNAME MASS  / ->1\
+    \n$#                                                          TITLE continued\
+    \nNAME and some more ->2\
+    \nNAME end of title\
+    \n                                                        \
+    \nNODE  /      ";
+
+  #[test]
+  fn skip_name_continuations() {
+    use crate::bufdata::highlights::HighlightGroup::{Keyword, Title};
+
+    let mut lines = Lines::new();
+    lines.parse_slice(CARD_MASS_TITLE.as_ref());
+    let mut l = lines.iter();
+    let mut hls = Highlights::new();
+    let firstline = l.next().unwrap();
+    let tmp =
+      l.skip_card(&firstline.try_into_keywordline().unwrap(), &MASS, &mut hls);
+    assert_eq!(
+      tmp.nextline().unwrap(),
+      pline!(6.into(), &"NODE  /      ", Some(Node)).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 5.into());
+
+    let titles: Vec<_> = hls
+      .iter()
+      .filter(|(_, h)| *h == Keyword || *h == Title)
+      .skip(1)
+      .map(|((l, s, e), h)| (l, s, e, h))
+      .collect();
+    assert_eq!(
+      titles,
+      vec![
+        (1.into(), 0, 4, Keyword),
+        (1.into(), 4, 16, Title),
+        (3.into(), 0, 4, Keyword),
+        (3.into(), 4, 22, Title),
+        (4.into(), 0, 4, Keyword),
+        (4.into(), 4, 17, Title),
+      ]
     );
-    assert_eq!(tmp.skip_end, 4.into());
   }
 
   const LINES_GATHER: [&'static str; 20] = [
@@ -610,30 +868,125 @@ mod tests {
 
     let mut tmp =
       li.skip_fold(&(firstline.try_into_keywordline()).unwrap(), &mut hls);
-    let mut tmp_nextline = tmp.nextline.unwrap();
+    let mut tmp_nextline = tmp.nextline().unwrap();
     assert_eq!(
       tmp_nextline,
-      &pline!(5.into(), &LINES_GATHER[5], Some(Shell))
+      pline!(5.into(), &LINES_GATHER[5], Some(Shell)).as_line_ref()
     );
-    assert_eq!(tmp.skip_end, 3.into());
+    assert_eq!(tmp.skip_end(), 3.into());
 
     tmp = li.skip_fold(&tmp_nextline.try_into_keywordline().unwrap(), &mut hls);
-    tmp_nextline = tmp.nextline.unwrap();
-    assert_eq!(tmp_nextline, &pline!(6.into(), &LINES_GATHER[6], None));
-    assert_eq!(tmp.skip_end, 5.into());
+    tmp_nextline = tmp.nextline().unwrap();
+    assert_eq!(
+      tmp_nextline,
+      pline!(6.into(), &LINES_GATHER[6], None).as_line_ref()
+    );
+    assert_eq!(tmp.skip_end(), 5.into());
 
     let skipped = li.skip_to_next_keyword().unwrap();
     tmp = li.skip_fold(&skipped.into(), &mut hls);
-    tmp_nextline = tmp.nextline.unwrap();
+    tmp_nextline = tmp.nextline().unwrap();
     assert_eq!(
       tmp_nextline,
-      &pline!(18.into(), &LINES_GATHER[18], Some(Node))
+      pline!(18.into(), &LINES_GATHER[18], Some(Node)).as_line_ref()
     );
-    assert_eq!(tmp.skip_end, 15.into());
+    assert_eq!(tmp.skip_end(), 15.into());
 
     tmp = li.skip_fold(&tmp_nextline.try_into_keywordline().unwrap(), &mut hls);
-    assert_eq!(tmp.nextline, None);
-    assert_eq!(tmp.skip_end, 19.into());
+    assert!(tmp.is_eof());
+    assert_eq!(tmp.skip_end(), 19.into());
+  }
+
+  const LINES_LONG: [&'static str; 5] = [
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /                2                      0.\
+     \x20                    0.5        &",
+    "                                              1.",
+    "SHELL /             3129               1               1            2967\
+     \x20       &",
+    "                    2971            2970                             0.5",
+  ];
+
+  #[test]
+  fn skips_long_format() {
+    use crate::bufdata::highlights::HighlightGroup::{CellEven, CellOdd};
+
+    let mut lines = Lines::new();
+    let mut hls = Highlights::new();
+    lines.parse_strs(&LINES_LONG);
+    let mut li = lines.iter();
+
+    let firstline = li.next().unwrap();
+    let tmp =
+      li.skip_fold(&(firstline.try_into_keywordline()).unwrap(), &mut hls);
+    assert_eq!(tmp.skip_end(), 2.into());
+
+    let kl = tmp.next_keywordline().unwrap();
+    assert_eq!(kl.keyword, Shell);
+    let tmp = li.skip_fold(&kl, &mut hls);
+    assert!(tmp.is_eof());
+    assert_eq!(tmp.skip_end(), 4.into());
+
+    let cells: Vec<_> = hls
+      .iter()
+      .filter(|((l, _, _), _)| *l == 2.into() || *l == 4.into())
+      .map(|((l, s, e), h)| (l, s, e, h))
+      .collect();
+    assert_eq!(
+      cells,
+      vec![
+        (2.into(), 0, 24, CellOdd),
+        (2.into(), 24, 48, CellEven),
+        (4.into(), 0, 8, CellOdd),
+        (4.into(), 8, 24, CellEven),
+        (4.into(), 24, 40, CellOdd),
+        (4.into(), 40, 56, CellEven),
+        (4.into(), 56, 72, CellOdd),
+      ]
+    );
   }
 
+  const LINES_UNKNOWN: [&'static str; 6] = [
+    "SENSOR/        1       0",
+    "        some data",
+    "$ a comment",
+    "        more data",
+    "XYZ   /        2",
+    "NODE  /        1              0.              0.              0.",
+  ];
+
+  #[test]
+  fn skips_unknown_cards() {
+    use crate::bufdata::highlights::HighlightGroup::{CellEven, Keyword};
+
+    let mut lines = Lines::new();
+    let mut hls = Highlights::new();
+    lines.parse_strs(&LINES_UNKNOWN);
+    let mut li = lines.iter();
+
+    let firstline = li.next().unwrap();
+    let kl = firstline.try_into_keywordline().unwrap();
+    assert_eq!(kl.keyword, Unknown);
+    let tmp = li.skip_fold(&kl, &mut hls);
+    assert_eq!(tmp.skip_end(), 3.into());
+    assert_eq!(li.take_title(), Some("SENSOR".to_string()));
+
+    let kl = tmp.next_keywordline().unwrap();
+    assert_eq!(kl.keyword, Unknown);
+    let tmp = li.skip_fold(&kl, &mut hls);
+    assert_eq!(tmp.skip_end(), 4.into());
+    assert_eq!(li.take_title(), Some("XYZ".to_string()));
+    assert_eq!(tmp.next_keywordline().unwrap().keyword, Node);
+
+    // Only the keyword and the cell after it get highlighted
+    let sensor: Vec<_> = hls
+      .iter()
+      .filter(|((l, _, _), _)| *l <= 3.into())
+      .map(|((l, s, e), h)| (l, s, e, h))
+      .collect();
+    assert_eq!(
+      sensor,
+      vec![(0.into(), 0, 8, Keyword), (0.into(), 8, 24, CellEven)]
+    );
+  }
 }
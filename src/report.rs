@@ -0,0 +1,427 @@
+//! A report about a deck, printed by `nvimpam stats <file>` without running
+//! neovim. It lists the cards of each keyword, the tree of files included by
+//! the deck, the ranges of the IDs it defines and the warnings nvimpam finds
+//! in it, either as a table or as JSON.
+use std::{
+  collections::HashSet,
+  fmt::{self, Write},
+  fs,
+  path::{Path, PathBuf},
+};
+
+use failure::{Error, ResultExt};
+use neovim_lib::{neovim_api::Buffer, Value};
+
+use crate::{
+  bufdata::{stats::KeywordCount, BufData},
+  card::xref::Entity,
+  diagnostics::Diagnostic,
+  index::{self, Index},
+  lines::Lines,
+};
+
+/// A file included by the deck, along with the files it includes itself
+#[derive(Debug, PartialEq)]
+pub struct Include {
+  /// The file name given in the `INCLU` line
+  pub name: String,
+  /// Why the file was not read. Files are only read when they're included
+  /// for the first time.
+  pub error: Option<String>,
+  pub includes: Vec<Include>,
+}
+
+/// The number of IDs of `entity` defined by the deck, and the lowest and
+/// highest of them
+#[derive(Debug, PartialEq)]
+pub struct IdRange {
+  pub entity: Entity,
+  pub count: usize,
+  pub min: i64,
+  pub max: i64,
+}
+
+/// The report about the deck `file`
+#[derive(Debug)]
+pub struct Report {
+  pub file: String,
+  pub lines: usize,
+  pub keywords: Vec<KeywordCount>,
+  pub includes: Vec<Include>,
+  pub ids: Vec<IdRange>,
+  /// The diagnostics of the deck, where IDs defined in the included files
+  /// are not reported as undefined
+  pub warnings: Vec<Diagnostic>,
+}
+
+/// The tree of the files `names` included by a file in the directory `dir`.
+/// Files in `seen` are not read again, so an include cycle does not recurse
+/// endlessly.
+fn include_tree(
+  dir: &Path,
+  names: Vec<String>,
+  seen: &mut HashSet<PathBuf>,
+) -> Vec<Include> {
+  let mut includes = vec![];
+
+  for name in names {
+    let path = dir.join(&name);
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+
+    if !seen.insert(canonical) {
+      includes.push(Include {
+        name,
+        error: Some("included before".to_string()),
+        includes: vec![],
+      });
+      continue;
+    }
+
+    let content = match fs::read(&path) {
+      Ok(c) => c,
+      Err(e) => {
+        includes.push(Include {
+          name,
+          error: Some(e.to_string()),
+          includes: vec![],
+        });
+        continue;
+      }
+    };
+
+    let mut lines = Lines::new();
+    lines.parse_slice(&content);
+    let names = lines
+      .iter()
+      .filter_map(|l| index::include_path(l.text.as_ref()))
+      .collect();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    includes.push(Include {
+      name,
+      error: None,
+      includes: include_tree(dir, names, seen),
+    });
+  }
+
+  includes
+}
+
+impl Report {
+  /// Read the deck at `path` and create its report
+  pub fn read(path: &Path) -> Result<Self, Error> {
+    let content =
+      fs::read(path).context(format!("Could not read '{}'", path.display()))?;
+
+    Report::new(path, &content)
+  }
+
+  /// Create the report about the deck at `path` with the contents `content`.
+  /// The files it includes are read from the disk.
+  pub fn new(path: &Path, content: &[u8]) -> Result<Self, Error> {
+    // The buffer is never used, since nothing is sent to neovim
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    let _ = bufdata.set_highlights_enabled(false)?;
+    bufdata.parse_slice(content)?;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let names = bufdata.includes();
+    let index = Index::build(dir, &names);
+    let mut seen = HashSet::new();
+    let _ = seen.insert(path.canonicalize().unwrap_or_else(|_| path.into()));
+
+    let ids = [Entity::Node, Entity::Element, Entity::Part]
+      .iter()
+      .filter_map(|e| {
+        let defined = bufdata.definitions(*e);
+
+        Some(IdRange {
+          entity: *e,
+          count: defined.len(),
+          min: *defined.keys().min()?,
+          max: *defined.keys().max()?,
+        })
+      })
+      .collect();
+
+    let lines = match content.last() {
+      None => 0,
+      Some(b'\n') => memchr::memchr_iter(b'\n', content).count(),
+      Some(_) => memchr::memchr_iter(b'\n', content).count() + 1,
+    };
+
+    Ok(Report {
+      file: path.display().to_string(),
+      lines,
+      keywords: bufdata.keyword_summary(),
+      includes: include_tree(dir, names, &mut seen),
+      ids,
+      warnings: bufdata.diagnostics(Some(&index)),
+    })
+  }
+
+  /// Pack up the report into a `Value`, e.g. to write it as
+  /// [`to_json`](crate::report::Report::to_json)
+  pub fn to_value(&self) -> Value {
+    fn include(i: &Include) -> Value {
+      let mut map = vec![(Value::from("name"), Value::from(i.name.as_str()))];
+      if let Some(ref e) = i.error {
+        map.push((Value::from("error"), Value::from(e.as_str())));
+      }
+      map.push((
+        Value::from("includes"),
+        Value::from(i.includes.iter().map(include).collect::<Vec<_>>()),
+      ));
+
+      Value::from(map)
+    }
+
+    let ids = self.ids.iter().map(|r| {
+      Value::from(vec![
+        (
+          Value::from("entity"),
+          Value::from(format!("{:?}", r.entity)),
+        ),
+        (Value::from("count"), Value::from(r.count)),
+        (Value::from("min"), Value::from(r.min)),
+        (Value::from("max"), Value::from(r.max)),
+      ])
+    });
+
+    Value::from(vec![
+      (Value::from("file"), Value::from(self.file.as_str())),
+      (Value::from("lines"), Value::from(self.lines)),
+      (
+        Value::from("keywords"),
+        Value::from(self.keywords.iter().map(Value::from).collect::<Vec<_>>()),
+      ),
+      (
+        Value::from("includes"),
+        Value::from(self.includes.iter().map(include).collect::<Vec<_>>()),
+      ),
+      (Value::from("ids"), Value::from(ids.collect::<Vec<_>>())),
+      (
+        Value::from("warnings"),
+        Value::from(self.warnings.iter().map(Value::from).collect::<Vec<_>>()),
+      ),
+    ])
+  }
+
+  /// The report as a JSON object, with the keys of
+  /// [`to_value`](crate::report::Report::to_value)
+  pub fn to_json(&self) -> String {
+    let mut json = String::new();
+    write_json(&mut json, &self.to_value());
+    json
+  }
+}
+
+/// Write `s` as a JSON string
+fn write_json_str(out: &mut String, s: &str) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => {
+        let _ = write!(out, "\\u{:04x}", c as u32);
+      }
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+/// Write `value` as JSON. Binary data is written as a string, extension types
+/// and floats that JSON can't hold as `null`. Keys of maps that are no
+/// strings are written as JSON, and then as a string.
+fn write_json(out: &mut String, value: &Value) {
+  match *value {
+    Value::Nil | Value::Ext(..) => out.push_str("null"),
+    Value::Boolean(b) => out.push_str(if b { "true" } else { "false" }),
+    Value::Integer(ref i) => {
+      let _ = write!(out, "{}", i);
+    }
+    Value::F32(f) if f.is_finite() => {
+      let _ = write!(out, "{:?}", f);
+    }
+    Value::F64(f) if f.is_finite() => {
+      let _ = write!(out, "{:?}", f);
+    }
+    Value::F32(_) | Value::F64(_) => out.push_str("null"),
+    Value::String(ref s) => {
+      write_json_str(out, &String::from_utf8_lossy(s.as_bytes()))
+    }
+    Value::Binary(ref b) => write_json_str(out, &String::from_utf8_lossy(b)),
+    Value::Array(ref a) => {
+      out.push('[');
+      for (i, v) in a.iter().enumerate() {
+        if i > 0 {
+          out.push(',');
+        }
+        write_json(out, v);
+      }
+      out.push(']');
+    }
+    Value::Map(ref m) => {
+      out.push('{');
+      for (i, (k, v)) in m.iter().enumerate() {
+        if i > 0 {
+          out.push(',');
+        }
+        match *k {
+          Value::String(ref s) => {
+            write_json_str(out, &String::from_utf8_lossy(s.as_bytes()))
+          }
+          ref k => {
+            let mut key = String::new();
+            write_json(&mut key, k);
+            write_json_str(out, &key);
+          }
+        }
+        out.push(':');
+        write_json(out, v);
+      }
+      out.push('}');
+    }
+  }
+}
+
+/// Write the tree of `includes` with the given `depth` of indentation
+fn fmt_includes(
+  f: &mut fmt::Formatter,
+  includes: &[Include],
+  depth: usize,
+) -> fmt::Result {
+  for i in includes {
+    write!(f, "{:1$}{2}", "", 2 * depth, i.name)?;
+    match i.error {
+      Some(ref e) => writeln!(f, " ({})", e)?,
+      None => writeln!(f)?,
+    }
+    fmt_includes(f, &i.includes, depth + 1)?;
+  }
+
+  Ok(())
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}: {} lines", self.file, self.lines)?;
+
+    writeln!(f, "\n{:<16}{:>10}{:>10}", "Keyword", "Cards", "Lines")?;
+    for c in &self.keywords {
+      let keyword = format!("{:?}", c.keyword);
+      writeln!(f, "{:<16}{:>10}{:>10}", keyword, c.cards, c.lines)?;
+    }
+
+    writeln!(f, "\nIncludes")?;
+    if self.includes.is_empty() {
+      writeln!(f, "none")?;
+    }
+    fmt_includes(f, &self.includes, 0)?;
+
+    writeln!(
+      f,
+      "\n{:<16}{:>10}{:>12}{:>12}",
+      "IDs", "Count", "Min", "Max"
+    )?;
+    for r in &self.ids {
+      let entity = format!("{:?}", r.entity);
+      writeln!(f, "{:<16}{:>10}{:>12}{:>12}", entity, r.count, r.min, r.max)?;
+    }
+
+    writeln!(f, "\nWarnings")?;
+    if self.warnings.is_empty() {
+      writeln!(f, "none")?;
+    }
+    for w in &self.warnings {
+      writeln!(f, "{}", w)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use neovim_lib::Value;
+
+  use crate::{
+    card::{keyword::Keyword, xref::Entity},
+    report::{write_json, IdRange, Include, Report},
+  };
+
+  const DECK: &str = "NODE  /        1              0.             0.5\n\
+                      NODE  /        7              0.             0.5\n\
+                      INCLU / missing.inc\n\
+                      SHELL /     3129       1       1       2       7\n";
+
+  #[test]
+  fn deck_report() {
+    let report =
+      Report::new(Path::new("/nonexistent/deck.pc"), DECK.as_bytes()).unwrap();
+
+    assert_eq!(report.lines, 4);
+    assert_eq!(report.keywords[0].keyword, Keyword::Node);
+    assert_eq!(report.keywords[0].cards, 2);
+    assert_eq!(report.includes.len(), 1);
+    assert_eq!(report.includes[0].name, "missing.inc");
+    assert!(report.includes[0].error.is_some());
+    assert_eq!(
+      report.ids[0],
+      IdRange {
+        entity: Entity::Node,
+        count: 2,
+        min: 1,
+        max: 7
+      }
+    );
+    let warnings: Vec<_> =
+      report.warnings.iter().map(|w| w.message.as_str()).collect();
+    assert_eq!(warnings, ["Node 2 is not defined", "Part 1 is not defined"]);
+
+    let table = report.to_string();
+    assert!(table.starts_with("/nonexistent/deck.pc: 4 lines\n"));
+    assert!(table.contains("missing.inc ("));
+  }
+
+  #[test]
+  fn report_json() {
+    let report = Report {
+      file: "deck \"1\".pc".to_string(),
+      lines: 0,
+      keywords: vec![],
+      includes: vec![Include {
+        name: "a.inc".to_string(),
+        error: None,
+        includes: vec![],
+      }],
+      ids: vec![],
+      warnings: vec![],
+    };
+
+    assert_eq!(
+      report.to_json(),
+      "{\"file\":\"deck \\\"1\\\".pc\",\"lines\":0,\"keywords\":[],\
+       \"includes\":[{\"name\":\"a.inc\",\"includes\":[]}],\"ids\":[],\
+       \"warnings\":[]}"
+    );
+
+    let mut json = String::new();
+    write_json(
+      &mut json,
+      &Value::from(vec![
+        (Value::from(1), Value::from(-1.5)),
+        (Value::from("x"), Value::from(f64::NAN)),
+      ]),
+    );
+    assert_eq!(json, "{\"1\":-1.5,\"x\":null}");
+  }
+}
@@ -0,0 +1,216 @@
+//! The state of nvimpam for a buffer, saved along with a neovim session (see
+//! `:mksession`). When the session is restored, the custom folds and the
+//! index of the include files are brought back without indexing the files
+//! again. The lines of the buffer aren't saved, but the hash of them, see
+//! [`hash_lines`](crate::bufdata::foldstate::hash_lines). A state is only
+//! loaded into a buffer with the same lines.
+//!
+//! The format is line-based like the one of a
+//! [`FoldState`](crate::bufdata::foldstate::FoldState). After a header line
+//! and the hash, there's a line for each fold. If the include files were
+//! indexed, the line `index` follows, and then a line for each definition
+//! (the entity, the ID, the 1-indexed line and the file), file and error of
+//! the [`Index`](crate::index::Index):
+//!
+//! ```text
+//! nvimpam session 1
+//! hash 8c1ef6a4b8942589
+//! fold 1 4  4 lines: Node
+//! index
+//! def node 12 5 includes/nodes.inc
+//! file includes/nodes.inc
+//! error Could not read 'missing.inc': No such file or directory
+//! ```
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use failure::{self, Error};
+
+use crate::{
+  bufdata::foldstate::{self, FoldState},
+  card::xref::Entity,
+  index::{Index, Location},
+  linenr::LineNr,
+};
+
+/// The first line of a session file
+const HEADER: &str = "nvimpam session 1";
+
+/// The saved state of a buffer
+#[derive(Debug)]
+pub struct Session {
+  /// All folds of the buffer, and the hash of its lines
+  pub folds: FoldState,
+  /// The index of the include files, if they were indexed
+  pub index: Option<Arc<Index>>,
+}
+
+/// Parse the `def` line `line` of an index
+fn parse_definition(line: &str) -> Option<(Entity, i64, Location)> {
+  let mut parts = line.splitn(4, ' ');
+  let entity = parts.next().and_then(Entity::from_name)?;
+  let id = parts.next()?.parse().ok()?;
+  let line = parts.next()?.parse::<usize>().ok().filter(|&l| l > 0)?;
+  let file = PathBuf::from(parts.next()?);
+
+  Some((
+    entity,
+    id,
+    Location {
+      file,
+      line: LineNr::from_usize(line - 1),
+    },
+  ))
+}
+
+impl Session {
+  /// Parse the contents of a session file
+  pub fn parse(s: &str) -> Result<Self, Error> {
+    let mut lines = s.lines();
+
+    if lines.next() != Some(HEADER) {
+      return Err(failure::err_msg("Not a nvimpam session file!"));
+    }
+
+    let hash = lines
+      .next()
+      .filter(|l| l.starts_with("hash "))
+      .and_then(|l| u64::from_str_radix(&l[5..], 16).ok())
+      .ok_or_else(|| failure::err_msg("Missing hash in session file!"))?;
+
+    let mut folds = vec![];
+    let mut indexed = false;
+    let mut definitions = vec![];
+    let mut files = vec![];
+    let mut errors = vec![];
+
+    for line in lines.filter(|l| !l.is_empty()) {
+      let (kind, rest) = match line.find(' ') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, ""),
+      };
+
+      match kind {
+        "fold" if !indexed => folds.push(foldstate::parse_fold(rest)?),
+        "index" if !indexed => indexed = true,
+        "def" if indexed => {
+          definitions.push(parse_definition(rest).ok_or_else(|| {
+            failure::err_msg(format!(
+              "Invalid definition '{}' in session file!",
+              rest
+            ))
+          })?)
+        }
+        "file" if indexed => files.push(PathBuf::from(rest)),
+        "error" if indexed => errors.push(rest.to_string()),
+        _ => {
+          return Err(failure::err_msg(format!(
+            "Invalid line '{}' in session file!",
+            line
+          )))
+        }
+      }
+    }
+
+    Ok(Session {
+      folds: FoldState { hash, folds },
+      index: if indexed {
+        Some(Arc::new(Index::from_parts(definitions, files, errors)))
+      } else {
+        None
+      },
+    })
+  }
+}
+
+impl fmt::Display for Session {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}", HEADER)?;
+    writeln!(f, "hash {:016x}", self.folds.hash)?;
+
+    for (range, text) in &self.folds.folds {
+      write!(f, "fold ")?;
+      foldstate::write_fold(f, *range, text)?;
+    }
+
+    if let Some(ref index) = self.index {
+      writeln!(f, "index")?;
+
+      for (entity, id, location) in index.definitions() {
+        writeln!(
+          f,
+          "def {} {} {} {}",
+          format!("{:?}", entity).to_lowercase(),
+          id,
+          location.line + 1,
+          location.file.display()
+        )?;
+      }
+      for file in index.files() {
+        writeln!(f, "file {}", file.display())?;
+      }
+      for error in index.errors() {
+        writeln!(f, "error {}", error.replace('\n', " "))?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use crate::{card::xref::Entity, linenr::LineNr, session::Session};
+
+  const SESSION: &str = "nvimpam session 1\nhash 00000000000012ab\nfold 1 4  \
+                         4 lines: Node \nfold 6 13 Boundary \
+                         Conditions\nindex\ndef node 12 5 \
+                         includes/my nodes.inc\ndef element 3 1 \
+                         shells.inc\nfile includes/my nodes.inc\nfile \
+                         shells.inc\nerror Could not read 'missing.inc'\n";
+
+  #[test]
+  fn session_roundtrip() {
+    let session = Session::parse(SESSION).unwrap();
+
+    assert_eq!(session.folds.hash, 0x12ab);
+    assert_eq!(session.folds.folds.len(), 2);
+    assert_eq!(session.folds.folds[1].1, "Boundary Conditions");
+
+    let index = session.index.as_ref().unwrap();
+    let node = index.find(Entity::Node, 12).unwrap();
+    assert_eq!(node.file, PathBuf::from("includes/my nodes.inc"));
+    assert_eq!(node.line, LineNr::from_usize(4));
+    assert_eq!(index.files().len(), 2);
+    assert_eq!(index.errors(), ["Could not read 'missing.inc'"]);
+
+    assert_eq!(session.to_string(), SESSION);
+  }
+
+  #[test]
+  fn session_without_index() {
+    let session =
+      Session::parse("nvimpam session 1\nhash 1\nfold 1 2 Nodes\n").unwrap();
+    assert!(session.index.is_none());
+    assert_eq!(
+      session.to_string(),
+      "nvimpam session 1\nhash 0000000000000001\nfold 1 2 Nodes\n"
+    );
+  }
+
+  #[test]
+  fn session_invalid() {
+    assert!(Session::parse("nvimpam foldstate 1\nhash 1\n").is_err());
+    assert!(Session::parse("nvimpam session 1\nfold 1 2 x\n").is_err());
+    assert!(Session::parse("nvimpam session 1\nhash 1\n1 2 x\n").is_err());
+    assert!(
+      Session::parse("nvimpam session 1\nhash 1\ndef node 1 1 a.inc\n")
+        .is_err()
+    );
+    assert!(Session::parse(
+      "nvimpam session 1\nhash 1\nindex\ndef shell 1 1 a.inc\n"
+    )
+    .is_err());
+  }
+}
@@ -2,35 +2,97 @@
 //! [`LinesIter`](crate::linesiter::LinesIter)
 use std::fmt;
 
-use crate::{linenr::LineNr, lines::ParsedLine};
-
-/// A data structure returned by several skip methods on
-/// [`LinesIter`](crate::linesiter::LinesIter)
-///
-/// `nextline` will be `None` in those cases where the iterator returned `None`
-/// before such a line could be found, i.e. the file ended.
-///
-/// `skip_end` is the index of the last line we skipped.
-#[derive(Debug)]
-pub struct SkipResult<'a> {
-  pub nextline: Option<&'a ParsedLine<'a>>,
-  pub skip_end: LineNr,
+use crate::{
+  linenr::LineNr,
+  lines::{KeywordLine, LineRef},
+};
+
+/// The result of the skip methods on
+/// [`LinesIter`](crate::linesiter::LinesIter). `skip_end` is always the index
+/// of the last line that was skipped.
+#[derive(Debug, PartialEq)]
+pub enum Skipped<'a> {
+  /// Skipping stopped at `nextline`, which was taken from the iterator but is
+  /// not part of the skipped lines. Usually, it starts the next card, but it
+  /// can also be a line that does not fit the card that was skipped.
+  ToKeyword {
+    skip_end: LineNr,
+    nextline: LineRef<'a>,
+  },
+  /// The iterator ended after `skip_end`
+  Eof { skip_end: LineNr },
+}
+
+impl<'a> Skipped<'a> {
+  /// The index of the last skipped line
+  pub fn skip_end(&self) -> LineNr {
+    match *self {
+      Skipped::ToKeyword { skip_end, .. } | Skipped::Eof { skip_end } => {
+        skip_end
+      }
+    }
+  }
+
+  /// The line after the skipped lines, if the iterator did not end
+  pub fn nextline(&self) -> Option<LineRef<'a>> {
+    match *self {
+      Skipped::ToKeyword { nextline, .. } => Some(nextline),
+      Skipped::Eof { .. } => None,
+    }
+  }
+
+  /// The line after the skipped lines, if it starts a card
+  pub fn next_keywordline(&self) -> Option<KeywordLine<'a>> {
+    self.nextline().and_then(LineRef::try_into_keywordline)
+  }
+
+  /// If the iterator ended while skipping
+  pub fn is_eof(&self) -> bool {
+    match *self {
+      Skipped::ToKeyword { .. } => false,
+      Skipped::Eof { .. } => true,
+    }
+  }
 }
 
-impl<'a> fmt::Display for SkipResult<'a> {
+impl<'a> fmt::Display for Skipped<'a> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    if let Some(ref pl) = self.nextline {
-      write!(
+    match *self {
+      Skipped::ToKeyword { skip_end, nextline } => write!(
         f,
-        "SkipResult {{ nextline: {}, skip_end: {:?} }}",
-        pl, self.skip_end
-      )
-    } else {
-      write!(
-        f,
-        "SkipResult {{ nextline: None, skip_end: {:?} }}",
-        self.skip_end
-      )
+        "Skipped::ToKeyword {{ skip_end: {:?}, nextline: {} }}",
+        skip_end, nextline
+      ),
+      Skipped::Eof { skip_end } => {
+        write!(f, "Skipped::Eof {{ skip_end: {:?} }}", skip_end)
+      }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::{card::keyword::Keyword::*, lines::Lines, skipresult::Skipped};
+
+  #[test]
+  fn skipped_accessors() {
+    let strs = [
+      "NODE  /        1              0.             0.5              0.",
+      "SHELL /     3129       1       1    2967    2971    2970",
+    ];
+    let mut lines = Lines::new();
+    lines.parse_strs(&strs);
+    let mut li = lines.iter();
+    let kl = li.skip_to_next_keyword().unwrap();
+
+    let skipped = li.skip_fold(&kl, &mut Default::default());
+    assert_eq!(skipped.skip_end(), 0.into());
+    assert!(!skipped.is_eof());
+    assert_eq!(skipped.next_keywordline().unwrap().keyword, Shell);
+
+    let kl = skipped.next_keywordline().unwrap();
+    let skipped = li.skip_fold(&kl, &mut Default::default());
+    assert_eq!(skipped, Skipped::Eof { skip_end: 1.into() });
+    assert_eq!(skipped.nextline(), None);
+  }
+}
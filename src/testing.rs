@@ -0,0 +1,344 @@
+//! A mock [`NeovimApi`](neovim_lib::NeovimApi) for unit tests. It records the
+//! calls that nvimpam uses to send highlights and folds, so those can be
+//! asserted without spawning neovim. All other calls return an error.
+use neovim_lib::{
+  neovim_api::{Buffer, Tabpage, Window},
+  CallError, NeovimApi, Value,
+};
+
+/// A fake neovim instance. The recorded calls are saved by the name of their
+/// RPC method, along with their arguments.
+#[derive(Debug, Default)]
+pub struct MockNeovim {
+  pub calls: Vec<(String, Vec<Value>)>,
+}
+
+impl MockNeovim {
+  pub fn new() -> Self {
+    MockNeovim { calls: vec![] }
+  }
+
+  /// The arguments of all recorded calls to the RPC method `name`
+  pub fn calls_to(&self, name: &str) -> Vec<&[Value]> {
+    self
+      .calls
+      .iter()
+      .filter(|(n, _)| n == name)
+      .map(|(_, a)| a.as_slice())
+      .collect()
+  }
+}
+
+fn not_mocked(name: &str) -> CallError {
+  CallError::GenericError(format!("{} is not mocked", name))
+}
+
+impl NeovimApi for MockNeovim {
+  fn ui_detach(&mut self) -> Result<(), CallError> {
+    Err(not_mocked("nvim_ui_detach"))
+  }
+
+  fn ui_try_resize(
+    &mut self,
+    _width: i64,
+    _height: i64,
+  ) -> Result<(), CallError> {
+    Err(not_mocked("nvim_ui_try_resize"))
+  }
+
+  fn ui_set_option(
+    &mut self,
+    _name: &str,
+    _value: Value,
+  ) -> Result<(), CallError> {
+    Err(not_mocked("nvim_ui_set_option"))
+  }
+
+  fn command(&mut self, command: &str) -> Result<(), CallError> {
+    self
+      .calls
+      .push(("nvim_command".to_owned(), vec![Value::from(command)]));
+    Ok(())
+  }
+
+  fn get_hl_by_name(
+    &mut self,
+    _name: &str,
+    _rgb: bool,
+  ) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_hl_by_name"))
+  }
+
+  fn get_hl_by_id(
+    &mut self,
+    _hl_id: i64,
+    _rgb: bool,
+  ) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_hl_by_id"))
+  }
+
+  fn feedkeys(
+    &mut self,
+    _keys: &str,
+    _mode: &str,
+    _escape_csi: bool,
+  ) -> Result<(), CallError> {
+    Err(not_mocked("nvim_feedkeys"))
+  }
+
+  fn input(&mut self, _keys: &str) -> Result<i64, CallError> {
+    Err(not_mocked("nvim_input"))
+  }
+
+  fn replace_termcodes(
+    &mut self,
+    _str: &str,
+    _from_part: bool,
+    _do_lt: bool,
+    _special: bool,
+  ) -> Result<String, CallError> {
+    Err(not_mocked("nvim_replace_termcodes"))
+  }
+
+  fn command_output(&mut self, _command: &str) -> Result<String, CallError> {
+    Err(not_mocked("nvim_command_output"))
+  }
+
+  fn eval(&mut self, _expr: &str) -> Result<Value, CallError> {
+    Err(not_mocked("nvim_eval"))
+  }
+
+  fn execute_lua(
+    &mut self,
+    code: &str,
+    args: Vec<Value>,
+  ) -> Result<Value, CallError> {
+    self.calls.push((
+      "nvim_execute_lua".to_owned(),
+      vec![Value::from(code), Value::from(args)],
+    ));
+    Ok(Value::Nil)
+  }
+
+  fn call_function(
+    &mut self,
+    fname: &str,
+    args: Vec<Value>,
+  ) -> Result<Value, CallError> {
+    self.calls.push((
+      "nvim_call_function".to_owned(),
+      vec![Value::from(fname), Value::from(args)],
+    ));
+    Ok(Value::Nil)
+  }
+
+  fn call_dict_function(
+    &mut self,
+    _dict: Value,
+    _fname: &str,
+    _args: Vec<Value>,
+  ) -> Result<Value, CallError> {
+    Err(not_mocked("nvim_call_dict_function"))
+  }
+
+  fn strwidth(&mut self, _text: &str) -> Result<i64, CallError> {
+    Err(not_mocked("nvim_strwidth"))
+  }
+
+  fn list_runtime_paths(&mut self) -> Result<Vec<String>, CallError> {
+    Err(not_mocked("nvim_list_runtime_paths"))
+  }
+
+  fn set_current_dir(&mut self, _dir: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_current_dir"))
+  }
+
+  fn get_current_line(&mut self) -> Result<String, CallError> {
+    Err(not_mocked("nvim_get_current_line"))
+  }
+
+  fn set_current_line(&mut self, _line: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_current_line"))
+  }
+
+  fn del_current_line(&mut self) -> Result<(), CallError> {
+    Err(not_mocked("nvim_del_current_line"))
+  }
+
+  fn get_var(&mut self, _name: &str) -> Result<Value, CallError> {
+    Err(not_mocked("nvim_get_var"))
+  }
+
+  fn set_var(&mut self, _name: &str, _value: Value) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_var"))
+  }
+
+  fn del_var(&mut self, _name: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_del_var"))
+  }
+
+  fn get_vvar(&mut self, _name: &str) -> Result<Value, CallError> {
+    Err(not_mocked("nvim_get_vvar"))
+  }
+
+  fn get_option(&mut self, _name: &str) -> Result<Value, CallError> {
+    Err(not_mocked("nvim_get_option"))
+  }
+
+  fn set_option(
+    &mut self,
+    _name: &str,
+    _value: Value,
+  ) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_option"))
+  }
+
+  fn out_write(&mut self, _str: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_out_write"))
+  }
+
+  fn err_write(&mut self, _str: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_err_write"))
+  }
+
+  fn err_writeln(&mut self, _str: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_err_writeln"))
+  }
+
+  fn list_bufs(&mut self) -> Result<Vec<Buffer>, CallError> {
+    Err(not_mocked("nvim_list_bufs"))
+  }
+
+  fn get_current_buf(&mut self) -> Result<Buffer, CallError> {
+    Ok(Buffer::new(Value::from(0_usize)))
+  }
+
+  fn set_current_buf(&mut self, _buffer: &Buffer) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_current_buf"))
+  }
+
+  fn list_wins(&mut self) -> Result<Vec<Window>, CallError> {
+    Err(not_mocked("nvim_list_wins"))
+  }
+
+  fn get_current_win(&mut self) -> Result<Window, CallError> {
+    Err(not_mocked("nvim_get_current_win"))
+  }
+
+  fn set_current_win(&mut self, _window: &Window) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_current_win"))
+  }
+
+  fn list_tabpages(&mut self) -> Result<Vec<Tabpage>, CallError> {
+    Err(not_mocked("nvim_list_tabpages"))
+  }
+
+  fn get_current_tabpage(&mut self) -> Result<Tabpage, CallError> {
+    Err(not_mocked("nvim_get_current_tabpage"))
+  }
+
+  fn set_current_tabpage(
+    &mut self,
+    _tabpage: &Tabpage,
+  ) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_current_tabpage"))
+  }
+
+  fn create_namespace(&mut self, _name: &str) -> Result<i64, CallError> {
+    Err(not_mocked("nvim_create_namespace"))
+  }
+
+  fn get_namespaces(&mut self) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_namespaces"))
+  }
+
+  fn subscribe(&mut self, _event: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_subscribe"))
+  }
+
+  fn unsubscribe(&mut self, _event: &str) -> Result<(), CallError> {
+    Err(not_mocked("nvim_unsubscribe"))
+  }
+
+  fn get_color_by_name(&mut self, _name: &str) -> Result<i64, CallError> {
+    Err(not_mocked("nvim_get_color_by_name"))
+  }
+
+  fn get_color_map(&mut self) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_color_map"))
+  }
+
+  fn get_mode(&mut self) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_mode"))
+  }
+
+  fn get_keymap(
+    &mut self,
+    _mode: &str,
+  ) -> Result<Vec<Vec<(Value, Value)>>, CallError> {
+    Err(not_mocked("nvim_get_keymap"))
+  }
+
+  fn get_commands(
+    &mut self,
+    _opts: Vec<(Value, Value)>,
+  ) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_commands"))
+  }
+
+  fn get_api_info(&mut self) -> Result<Vec<Value>, CallError> {
+    Err(not_mocked("nvim_get_api_info"))
+  }
+
+  fn set_client_info(
+    &mut self,
+    _name: &str,
+    _version: Vec<(Value, Value)>,
+    _typ: &str,
+    _methods: Vec<(Value, Value)>,
+    _attributes: Vec<(Value, Value)>,
+  ) -> Result<(), CallError> {
+    Err(not_mocked("nvim_set_client_info"))
+  }
+
+  fn get_chan_info(
+    &mut self,
+    _chan: i64,
+  ) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_get_chan_info"))
+  }
+
+  fn list_chans(&mut self) -> Result<Vec<Value>, CallError> {
+    Err(not_mocked("nvim_list_chans"))
+  }
+
+  fn call_atomic(
+    &mut self,
+    calls: Vec<Value>,
+  ) -> Result<Vec<Value>, CallError> {
+    let results: Vec<Value> = calls.iter().map(|_| Value::Nil).collect();
+    self.calls.push(("nvim_call_atomic".to_owned(), calls));
+    Ok(vec![Value::from(results), Value::Nil])
+  }
+
+  fn parse_expression(
+    &mut self,
+    _expr: &str,
+    _flags: &str,
+    _highlight: bool,
+  ) -> Result<Vec<(Value, Value)>, CallError> {
+    Err(not_mocked("nvim_parse_expression"))
+  }
+
+  fn list_uis(&mut self) -> Result<Vec<Value>, CallError> {
+    Err(not_mocked("nvim_list_uis"))
+  }
+
+  fn get_proc_children(&mut self, _pid: i64) -> Result<Vec<Value>, CallError> {
+    Err(not_mocked("nvim_get_proc_children"))
+  }
+
+  fn get_proc(&mut self, _pid: i64) -> Result<Value, CallError> {
+    Err(not_mocked("nvim_get_proc"))
+  }
+}
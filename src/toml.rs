@@ -0,0 +1,286 @@
+//! A reader for the small subset of TOML needed for the card definition files,
+//! see [`card::custom`](crate::card::custom). Supported are arrays of tables
+//! like `[[card]]`, and keys with strings, integers, booleans and arrays of
+//! those as values. Comments start with `#`.
+use failure::{self, Error};
+
+/// The value of a key
+#[derive(Debug, PartialEq, Clone)]
+pub enum TomlValue {
+  Str(String),
+  Int(i64),
+  Bool(bool),
+  Array(Vec<TomlValue>),
+}
+
+/// A table, i.e. its keys with their values in order of occurence
+pub type Table = Vec<(String, TomlValue)>;
+
+/// The tables of a file, along with the name of the array they belong to,
+/// e.g. `card` for `[[card]]`
+pub type Tables = Vec<(String, Table)>;
+
+struct Reader<'a> {
+  chars: &'a [char],
+  pos: usize,
+  line: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).cloned()
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    let c = self.peek();
+
+    if let Some(ch) = c {
+      self.pos += 1;
+      if ch == '\n' {
+        self.line += 1;
+      }
+    }
+
+    c
+  }
+
+  fn error(&self, msg: &str) -> Error {
+    failure::err_msg(format!("Line {}: {}!", self.line, msg))
+  }
+
+  /// Skip blanks and a trailing comment, but not the end of the line
+  fn skip_blanks(&mut self) {
+    while let Some(c) = self.peek() {
+      match c {
+        ' ' | '\t' | '\r' => {}
+        '#' => {
+          while let Some(c) = self.peek() {
+            if c == '\n' {
+              return;
+            }
+            let _ = self.bump();
+          }
+          return;
+        }
+        _ => return,
+      }
+      let _ = self.bump();
+    }
+  }
+
+  /// Skip blanks, comments and line endings
+  fn skip_whitespace(&mut self) {
+    loop {
+      self.skip_blanks();
+      if self.peek() == Some('\n') {
+        let _ = self.bump();
+      } else {
+        return;
+      }
+    }
+  }
+
+  fn expect(&mut self, s: &str) -> Result<(), Error> {
+    for c in s.chars() {
+      if self.bump() != Some(c) {
+        return Err(self.error(&format!("Expected '{}'", s)));
+      }
+    }
+
+    Ok(())
+  }
+
+  /// After a key/value pair or a table header, only a comment may follow on
+  /// the line
+  fn expect_line_end(&mut self) -> Result<(), Error> {
+    self.skip_blanks();
+
+    match self.bump() {
+      None | Some('\n') => Ok(()),
+      Some(_) => Err(self.error("Expected the end of the line")),
+    }
+  }
+
+  fn key(&mut self) -> Result<String, Error> {
+    let mut key = String::new();
+
+    while let Some(c) = self.peek() {
+      if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+        key.push(c);
+        let _ = self.bump();
+      } else {
+        break;
+      }
+    }
+
+    if key.is_empty() {
+      Err(self.error("Expected a key"))
+    } else {
+      Ok(key)
+    }
+  }
+
+  fn string(&mut self) -> Result<String, Error> {
+    self.expect("\"")?;
+    let mut s = String::new();
+
+    loop {
+      match self.bump() {
+        Some('"') => return Ok(s),
+        Some('\\') => match self.bump() {
+          Some('"') => s.push('"'),
+          Some('\\') => s.push('\\'),
+          Some('t') => s.push('\t'),
+          Some('n') => s.push('\n'),
+          _ => return Err(self.error("Unsupported escape sequence")),
+        },
+        Some('\n') | None => return Err(self.error("Unterminated string")),
+        Some(c) => s.push(c),
+      }
+    }
+  }
+
+  fn array(&mut self) -> Result<Vec<TomlValue>, Error> {
+    self.expect("[")?;
+    let mut values = vec![];
+
+    loop {
+      self.skip_whitespace();
+      if self.peek() == Some(']') {
+        let _ = self.bump();
+        return Ok(values);
+      }
+
+      values.push(self.value()?);
+      self.skip_whitespace();
+
+      match self.bump() {
+        Some(',') => {}
+        Some(']') => return Ok(values),
+        _ => return Err(self.error("Expected ',' or ']' in array")),
+      }
+    }
+  }
+
+  fn value(&mut self) -> Result<TomlValue, Error> {
+    match self.peek() {
+      Some('"') => self.string().map(TomlValue::Str),
+      Some('[') => self.array().map(TomlValue::Array),
+      Some(_) => {
+        let word = self.key()?;
+
+        match word.as_str() {
+          "true" => Ok(TomlValue::Bool(true)),
+          "false" => Ok(TomlValue::Bool(false)),
+          w => w
+            .replace('_', "")
+            .parse()
+            .map(TomlValue::Int)
+            .map_err(|_| self.error(&format!("Invalid value '{}'", w))),
+        }
+      }
+      None => Err(self.error("Expected a value")),
+    }
+  }
+}
+
+/// Parse the tables of `text`. Keys before the first table header are an
+/// error, as are plain tables like `[card]`.
+pub fn parse(text: &str) -> Result<Tables, Error> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut reader = Reader {
+    chars: &chars,
+    pos: 0,
+    line: 1,
+  };
+  let mut tables: Tables = vec![];
+
+  loop {
+    reader.skip_whitespace();
+
+    match reader.peek() {
+      None => return Ok(tables),
+      Some('[') => {
+        reader.expect("[[")?;
+        let name = reader.key()?;
+        reader.expect("]]")?;
+        reader.expect_line_end()?;
+        tables.push((name, vec![]));
+      }
+      Some(_) => {
+        let key = reader.key()?;
+        reader.skip_blanks();
+        reader.expect("=")?;
+        reader.skip_blanks();
+        let value = reader.value()?;
+        reader.expect_line_end()?;
+
+        match tables.last_mut() {
+          Some((_, table)) if table.iter().any(|(k, _)| *k == key) => {
+            return Err(reader.error(&format!("Duplicate key '{}'", key)))
+          }
+          Some((_, table)) => table.push((key, value)),
+          None => {
+            return Err(reader.error("Keys need to be inside a table"));
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::toml::{parse, TomlValue::*};
+
+  const TEXT: &str = r#"
+# Site specific cards
+[[card]]
+keyword = "MYCARD/ " # a comment
+ownfold = false
+lines = [
+  "Kw Integer(8)",  # the first line
+  "Fixed(Name) Str(76)",
+]
+
+[[card]]
+keyword = "OTHER / "
+width = 1_000
+"#;
+
+  #[test]
+  fn parse_tables() {
+    let tables = parse(TEXT).unwrap();
+
+    assert_eq!(tables.len(), 2);
+    assert_eq!(tables[0].0, "card");
+    assert_eq!(
+      tables[0].1,
+      vec![
+        ("keyword".to_string(), Str("MYCARD/ ".to_string())),
+        ("ownfold".to_string(), Bool(false)),
+        (
+          "lines".to_string(),
+          Array(vec![
+            Str("Kw Integer(8)".to_string()),
+            Str("Fixed(Name) Str(76)".to_string())
+          ])
+        ),
+      ]
+    );
+    assert_eq!(tables[1].1[1], ("width".to_string(), Int(1000)));
+  }
+
+  #[test]
+  fn report_invalid_toml() {
+    let err = parse("keyword = \"A\"").unwrap_err();
+    assert_eq!(err.to_string(), "Line 1: Keys need to be inside a table!");
+
+    let err = parse("[[card]]\nlines = [\"a\" \"b\"]").unwrap_err();
+    assert_eq!(err.to_string(), "Line 2: Expected ',' or ']' in array!");
+
+    assert!(parse("[card]").is_err());
+    assert!(parse("[[card]]\nkeyword = \"A").is_err());
+    assert!(parse("[[card]]\na = 1\na = 2").is_err());
+  }
+}
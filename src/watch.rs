@@ -0,0 +1,168 @@
+//! Watching the include files of a deck, so the plugin can rebuild the
+//! [`Index`](crate::index::Index) when they change on disk. Only available
+//! with the `watch` feature.
+//!
+//! The watcher starts once the index is built, and watches the files it read.
+//! Changes are sent to the event loop as
+//! [`IncludeChanged`](crate::event::Event::IncludeChanged) events. The
+//! directories of the files are watched instead of the files themselves,
+//! since editors often save a file by replacing it.
+use std::{
+  collections::HashSet,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, RecvTimeoutError},
+    Arc,
+  },
+  thread,
+  time::Duration,
+};
+
+use log::warn;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{event::Event, index::Index};
+
+/// How long the changes of a file are collected before sending them, so
+/// writing a file in several steps only gives one event
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How often the watching thread checks if it should stop
+const POLL: Duration = Duration::from_millis(200);
+
+/// A handle to the watching thread. The thread stops when this is dropped.
+pub struct IncludeWatcher {
+  stop: Arc<AtomicBool>,
+}
+
+impl Drop for IncludeWatcher {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+/// Pass the [`Index`](crate::index::Index) received from `indexing`, see
+/// [`index::spawn`](crate::index::spawn), on to the returned receiver. The
+/// files it was built from are watched from then on, and their changes sent
+/// to `to_main`.
+pub fn spawn(
+  indexing: mpsc::Receiver<Index>,
+  to_main: mpsc::Sender<Event>,
+) -> (mpsc::Receiver<Index>, IncludeWatcher) {
+  let (sender, receiver) = mpsc::channel();
+  let stop = Arc::new(AtomicBool::new(false));
+  let watcher = IncludeWatcher {
+    stop: Arc::clone(&stop),
+  };
+
+  let _ = thread::spawn(move || {
+    let index = match indexing.recv() {
+      Ok(i) => i,
+      Err(_) => return,
+    };
+    let files = index.files().to_vec();
+    let watching = watch(&files);
+
+    // The receiver is gone if the index was rebuilt in the meantime
+    if sender.send(index).is_err() {
+      return;
+    }
+
+    match watching {
+      Ok((_watcher, changes)) => {
+        send_changes(&files, &changes, &to_main, &stop)
+      }
+      Err(e) => warn!("Watching include files: {}", e),
+    }
+  });
+
+  (receiver, watcher)
+}
+
+/// Start watching the directories of `files`. The returned receiver gets the
+/// changes as long as the watcher is alive.
+fn watch(
+  files: &[PathBuf],
+) -> Result<(RecommendedWatcher, mpsc::Receiver<DebouncedEvent>), notify::Error>
+{
+  let (sender, receiver) = mpsc::channel();
+  let mut watcher = notify::watcher(sender, DEBOUNCE)?;
+
+  let dirs: HashSet<&Path> = files
+    .iter()
+    .map(|f| f.parent().unwrap_or_else(|| Path::new(".")))
+    .collect();
+  for dir in dirs {
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+  }
+
+  Ok((watcher, receiver))
+}
+
+/// Send the changes of `files` from `changes` to `to_main`, until `stop` is
+/// set or the event loop is gone
+fn send_changes(
+  files: &[PathBuf],
+  changes: &mpsc::Receiver<DebouncedEvent>,
+  to_main: &mpsc::Sender<Event>,
+  stop: &AtomicBool,
+) {
+  while !stop.load(Ordering::Relaxed) {
+    let path = match changes.recv_timeout(POLL) {
+      Ok(DebouncedEvent::Create(p))
+      | Ok(DebouncedEvent::Write(p))
+      | Ok(DebouncedEvent::Remove(p))
+      | Ok(DebouncedEvent::Rename(_, p)) => p,
+      Ok(DebouncedEvent::Error(e, _)) => {
+        warn!("Watching include files: {}", e);
+        continue;
+      }
+      Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+      Err(RecvTimeoutError::Disconnected) => break,
+    };
+
+    if !files.contains(&path) {
+      continue;
+    }
+
+    if to_main.send(Event::IncludeChanged { path }).is_err() {
+      break;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs, process, sync::mpsc, time::Duration};
+
+  use crate::{event::Event, index, watch};
+
+  #[test]
+  fn watch_includes() {
+    let dir = env::temp_dir().join(format!("nvimpam-watch-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let include = dir.join("nodes.inc");
+    fs::write(&include, "$ Nodes\n").unwrap();
+    fs::write(dir.join("other.inc"), "").unwrap();
+
+    let (to_main, from_watcher) = mpsc::channel();
+    let indexing = index::spawn(dir.clone(), vec!["nodes.inc".to_string()]);
+    let (indexing, watcher) = watch::spawn(indexing, to_main);
+    let index = indexing.recv().unwrap();
+    assert_eq!(index.files(), &[include.clone()]);
+
+    // Files not included are not reported
+    fs::write(dir.join("other.inc"), "$ Changed\n").unwrap();
+    fs::write(&include, "$ More nodes\n").unwrap();
+
+    let event = from_watcher.recv_timeout(Duration::from_secs(10));
+    drop(watcher);
+    let _ = fs::remove_dir_all(&dir);
+
+    match event.unwrap() {
+      Event::IncludeChanged { path } => assert_eq!(path, include),
+      e => panic!("Not an IncludeChanged event: {:?}", e),
+    }
+  }
+}
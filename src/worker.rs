@@ -0,0 +1,230 @@
+//! Parsing the cards changed by an update in a background thread, so the
+//! event loop stays responsive while big changes are parsed, e.g. pasting
+//! thousands of lines.
+//!
+//! The [`Worker`](crate::worker::Worker) gets a copy of the lines it needs to
+//! parse, and sends the results back as a
+//! [`Parsed`](crate::event::Event::Parsed) event, where they're spliced into
+//! the [`BufData`](crate::bufdata::BufData). A parse can be cancelled if a
+//! later update replaces its results anyways.
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+  },
+  thread,
+};
+
+use failure::Error;
+use log::warn;
+
+use crate::{
+  bufdata::BufData, card::version::InputVersion, event::Event,
+  lines::ParsedLine,
+};
+
+/// The lines of an update to parse
+struct Job {
+  id: u64,
+  lines: Vec<ParsedLine<'static>>,
+  version: Option<InputVersion>,
+  highlight: bool,
+  columns: u8,
+  cancel: Arc<AtomicBool>,
+}
+
+/// A handle to the worker thread. The thread exits when this is dropped.
+pub struct Worker {
+  jobs: mpsc::Sender<Job>,
+}
+
+impl Worker {
+  /// Spawn the worker thread. The results are sent to `to_main`, in the
+  /// order the jobs were submitted.
+  pub fn spawn(to_main: mpsc::Sender<Event>) -> Self {
+    let (jobs, from_main) = mpsc::channel::<Job>();
+
+    let _ = thread::spawn(move || {
+      for job in from_main {
+        let result = if job.cancel.load(Ordering::Relaxed) {
+          Ok(None)
+        } else {
+          BufData::parse_region(
+            &job.lines,
+            job.version,
+            job.highlight,
+            job.columns,
+            &job.cancel,
+          )
+        };
+
+        if to_main.send(Event::Parsed { id: job.id, result }).is_err() {
+          warn!("Event loop gone, stopping the worker");
+          break;
+        }
+      }
+    });
+
+    Worker { jobs }
+  }
+
+  /// Let the worker parse `lines` for the input version `version`, along
+  /// with their highlights up to the column `columns` if `highlight` is
+  /// true. The result is sent with the given `id`. Setting the returned flag
+  /// cancels the parse.
+  pub fn submit(
+    &self,
+    id: u64,
+    lines: Vec<ParsedLine<'static>>,
+    version: Option<InputVersion>,
+    highlight: bool,
+    columns: u8,
+  ) -> Result<Arc<AtomicBool>, Error> {
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    self
+      .jobs
+      .send(Job {
+        id,
+        lines,
+        version,
+        highlight,
+        columns,
+        cancel: Arc::clone(&cancel),
+      })
+      .map_err(|_| failure::err_msg("The worker thread stopped!"))?;
+
+    Ok(cancel)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc,
+  };
+
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::{
+    bufdata::{highlights::DEFAULT_COLUMNS, BufData, ParsedRegion},
+    event::Event,
+    linenr::LineNr,
+    worker::Worker,
+  };
+
+  const LINES: [&str; 4] = [
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        2              0.             0.5              0.",
+    "SHELL /     3129       1       1    2967    2971    2970",
+    "SHELL /     3130       1       1    2967    2971    2970",
+  ];
+
+  fn parsed(rx: &mpsc::Receiver<Event>) -> (u64, Option<ParsedRegion>) {
+    match rx.recv().unwrap() {
+      Event::Parsed { id, result } => (id, result.unwrap()),
+      e => panic!("Not a parse result: {:?}", e),
+    }
+  }
+
+  #[test]
+  fn parse_in_worker() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let worker = Worker::spawn(tx);
+    let version = bufdata.metadata().version;
+
+    let first = bufdata.prepare_update(
+      LineNr::from_usize(1),
+      LineNr::from_usize(2),
+      vec!["SHELL /     3128       1       1    2967    2971    2970".into()],
+    );
+    let cancel = worker
+      .submit(
+        1,
+        bufdata.pending_lines(&first),
+        version,
+        true,
+        DEFAULT_COLUMNS,
+      )
+      .unwrap();
+
+    // Changing the same card again makes the first parse superfluous
+    let second = bufdata.prepare_update(
+      LineNr::from_usize(1),
+      LineNr::from_usize(2),
+      vec!["SHELL /     3127       1       1    2967    2971    2970".into()],
+    );
+    assert!(second.covers(&first));
+    cancel.store(true, Ordering::Relaxed);
+    let _ = worker
+      .submit(
+        2,
+        bufdata.pending_lines(&second),
+        version,
+        true,
+        DEFAULT_COLUMNS,
+      )
+      .unwrap();
+
+    // The first parse might have been done before the cancellation
+    let (id, result) = parsed(&rx);
+    assert_eq!(id, 1);
+    let _ = bufdata
+      .finish_update(first, result.unwrap_or_default())
+      .unwrap();
+
+    let (id, result) = parsed(&rx);
+    assert_eq!(id, 2);
+    let _ = bufdata.finish_update(second, result.unwrap()).unwrap();
+
+    let mut lines = LINES;
+    lines[1] = "SHELL /     3127       1       1    2967    2971    2970";
+    let mut expected = BufData::new(&buf);
+    expected.parse_strs(&lines).unwrap();
+    assert_eq!(bufdata.folds_to_vec(), expected.folds_to_vec());
+    assert_eq!(
+      bufdata.folds_level2_to_vec(),
+      expected.folds_level2_to_vec()
+    );
+  }
+
+  #[test]
+  fn cancelled_parse() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_strs(&LINES).unwrap();
+
+    let pending = bufdata.prepare_update(
+      LineNr::from_usize(0),
+      LineNr::from_usize(1),
+      vec![LINES[0].to_string()],
+    );
+    let lines = bufdata.pending_lines(&pending);
+
+    let cancel = AtomicBool::new(false);
+    assert!(BufData::parse_region(
+      &lines,
+      None,
+      true,
+      DEFAULT_COLUMNS,
+      &cancel
+    )
+    .unwrap()
+    .is_some());
+    cancel.store(true, Ordering::Relaxed);
+    assert!(BufData::parse_region(
+      &lines,
+      None,
+      true,
+      DEFAULT_COLUMNS,
+      &cancel
+    )
+    .unwrap()
+    .is_none());
+  }
+}